@@ -1,40 +1,141 @@
 //! Defines a backtest, which determines what data is sent and the
 //! conditions that trigger it to be sent.
 
-use std::sync::mpsc;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, mpsc};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Instant;
 use uuid::Uuid;
 
 use {BacktestType, DataSource, DataDest};
 use simbroker::SimBrokerSettings;
-use tickgrinder_util::transport::tickstream::TickstreamCommand;
+use broker::ManagedBroker;
+use tickgrinder_util::transport::tickstream::{TickSink, TickstreamCommand};
+use tickgrinder_util::trading::tick::Tick;
+use tickgrinder_util::trading::sma::Sma;
+
+/// How many of the most recent ticks of each backtest are kept for inspection
+pub const TICK_HISTORY_LEN: usize = 100000;
+/// How many ticks can be waiting for a backtest's data destination unless its definition says otherwise
+pub const DEFAULT_DATA_DEST_BUFFER: usize = 1000;
+/// Periods in milliseconds of the SMAs that `InspectTick` reports for backtests with a SimBroker attached
+pub const INSPECTED_SMA_PERIODS: [u64; 3] = [1000, 60 * 1000, 5 * 60 * 1000];
 
 /// Contains controls for pausing, resuming, and stopping a backtest as well as
 /// some data about it.
 pub struct BacktestHandle {
-    pub symbol: String,
-    pub backtest_type: BacktestType,
-    pub data_source: DataSource,
-    pub endpoint: DataDest,
+    /// The definition the backtest was started with
+    pub definition: BacktestDefinition,
     pub handle: mpsc::SyncSender<TickstreamCommand>,
+    /// Number of ticks that have been sent to the backtest's endpoint so far
+    pub tick_count: Arc<AtomicUsize>,
+    /// False while the backtest is paused
+    pub running: Arc<AtomicBool>,
+    pub started: Instant,
+    /// The most recent ticks that have been sent to the backtest's endpoint
+    pub history: Arc<Mutex<TickHistory>>,
+    /// The endpoint the backtest's ticks are sent to, which can be replaced while the backtest is running
+    pub sink: Arc<Mutex<Box<TickSink + Send>>>,
+}
+
+/// A bounded buffer of the ticks a backtest has processed, indexed by their position in the backtest so that the
+/// tick behind an unexpected strategy decision can be looked up.  Once it's full, the oldest ticks are dropped.
+#[derive(Debug, Clone)]
+pub struct TickHistory {
+    ticks: VecDeque<Tick>,
+    /// Index of the oldest tick in the buffer
+    first_index: u64,
+    capacity: usize,
+}
+
+impl TickHistory {
+    pub fn new(capacity: usize) -> TickHistory {
+        TickHistory::starting_at(capacity, 0)
+    }
+
+    /// Creates a history whose first tick has the index `first_index`, for backtests that were resumed partway.
+    pub fn starting_at(capacity: usize, first_index: u64) -> TickHistory {
+        TickHistory {
+            ticks: VecDeque::new(),
+            first_index: first_index,
+            capacity: capacity,
+        }
+    }
+
+    pub fn push(&mut self, tick: Tick) {
+        if self.capacity == 0 {
+            self.first_index += 1;
+            return;
+        }
+        if self.ticks.len() == self.capacity {
+            self.ticks.pop_front();
+            self.first_index += 1;
+        }
+        self.ticks.push_back(tick);
+    }
+
+    /// Returns the tick at position `index` of the backtest, counting from 0, if it's still in the buffer.
+    pub fn get(&self, index: u64) -> Option<&Tick> {
+        if index < self.first_index {
+            return None;
+        }
+        self.ticks.get((index - self.first_index) as usize)
+    }
+
+    /// Returns the range of indexes of the ticks in the buffer, end exclusive.
+    pub fn index_range(&self) -> (u64, u64) {
+        (self.first_index, self.first_index + self.ticks.len() as u64)
+    }
+
+    /// Calculates the time-weighted SMA of the mid price for each of `periods` as of the tick at position `index`,
+    /// keyed by period label.  Only buffered ticks are averaged, so an SMA whose period reaches back past the oldest
+    /// of them covers less time than its period.
+    pub fn sma_values(&self, index: u64, periods: &[u64]) -> Option<HashMap<String, f64>> {
+        let end = match self.get(index) {
+            Some(_) => (index - self.first_index) as usize,
+            None => return None,
+        };
+        let values = periods.iter().filter_map(|&period| {
+            let mut sma = Sma::new(period);
+            let start_time = self.ticks[end].timestamp.saturating_sub(period);
+            // the tick before the start of the period sets the price that the period starts at
+            let start = (0..end + 1).rev().find(|&i| self.ticks[i].timestamp < start_time).unwrap_or(0);
+            let value = self.ticks.iter().skip(start).take(end + 1 - start)
+                .filter_map(|t| sma.push_f64(*t).ok())
+                .last();
+            value.and_then(|value| value).map(|value| (sma.period_label.clone(), value))
+        }).collect();
+        Some(values)
+    }
+}
+
+/// A tick from a backtest's history, returned by `InspectTick`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InspectedTick {
+    pub tick_index: u64,
+    pub timestamp: u64,
+    pub bid: usize,
+    pub ask: usize,
+    /// The SMAs of the `INSPECTED_SMA_PERIODS` as of the tick, keyed by period label, if the backtest's ticks are
+    /// sent to a SimBroker
+    pub sma_values: Option<HashMap<String, f64>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SerializableBacktestHandle {
     pub uuid: Uuid,
-    pub symbol: String,
-    pub backtest_type: BacktestType,
-    pub data_source: DataSource,
-    pub endpoint: DataDest,
+    pub definition: BacktestDefinition,
+    pub tick_count: usize,
+    pub running: bool,
 }
 
 impl SerializableBacktestHandle {
     pub fn from_handle(handle: &BacktestHandle, uuid: Uuid) -> SerializableBacktestHandle {
         SerializableBacktestHandle {
             uuid: uuid,
-            symbol: handle.symbol.clone(),
-            backtest_type: handle.backtest_type.clone(),
-            data_source: handle.data_source.clone(),
-            endpoint: handle.endpoint.clone(),
+            definition: handle.definition.clone(),
+            tick_count: handle.tick_count.load(Ordering::Relaxed),
+            running: handle.running.load(Ordering::Relaxed),
         }
     }
 }
@@ -52,6 +153,44 @@ pub struct BacktestDefinition {
     pub data_source: DataSource,
     pub data_dest: DataDest,
     pub broker_settings: SimBrokerSettings,
+    /// The amount of capital the backtest starts with, in units of the SimBroker's account currency.  If set, the
+    /// SimBroker the backtest's ticks are sent to is funded with it when the backtest starts or the SimBroker is
+    /// attached; otherwise the SimBroker keeps its configured starting balance.  Percentage returns are relative
+    /// to the SimBroker's starting equity.
+    #[serde(default)]
+    pub starting_capital: Option<f64>,
+    /// Caps the number of positions the strategy can have open at once, overriding the SimBroker's
+    /// `max_open_positions` setting.
+    #[serde(default)]
+    pub max_open_positions: Option<usize>,
+    /// Number of ticks the backtest had already processed before it was restarted.  That many ticks are skipped
+    /// from the start of the data source and the backtest's tick count picks up from there.
+    #[serde(default)]
+    pub resume_from_tick: Option<u64>,
+    /// How many ticks can be waiting for the backtest's data destination before the tickstream has to wait for it
+    #[serde(default = "default_data_dest_buffer")]
+    pub data_dest_buffer: usize,
+    /// Positions that the backtest starts out holding, as signed sizes keyed by symbol.  They're opened on the
+    /// backtest's SimBroker at the mid price of the first tick it receives; see `SimBroker::preload_position`.
+    #[serde(default)]
+    pub initial_portfolio: HashMap<String, f64>,
+}
+
+fn default_data_dest_buffer() -> usize { DEFAULT_DATA_DEST_BUFFER }
+
+impl BacktestDefinition {
+    /// Funds the SimBroker that the backtest's ticks are sent to with the backtest's starting capital and applies
+    /// its position limit, if the definition sets them.
+    pub fn configure_simbroker(&self, simbroker: &mut ManagedBroker) {
+        if let Some(starting_capital) = self.starting_capital {
+            let decimals = simbroker.settings().account_currency_decimals;
+            let starting_balance = starting_capital * 10f64.powi(decimals as i32);
+            simbroker.set_starting_balance(starting_balance.round() as usize);
+        }
+        if let Some(max_open_positions) = self.max_open_positions {
+            simbroker.set_max_open_positions(max_open_positions);
+        }
+    }
 }
 
 /// Ticks sent to the SimBroker should be re-broadcast to the client.
@@ -81,3 +220,77 @@ fn tick_retransmission() {
         .collect();
     assert_eq!(res.len(), 10);
 }
+
+/// The SimBroker's account should start out with the backtest's starting capital.
+#[test]
+fn starting_capital_balance() {
+    use futures::Future;
+
+    use simbroker::*;
+    use BacktestType;
+
+    let definition_str = r#"{"start_time":null,"max_timestamp":null,"max_tick_n":null,"symbol":"TEST",
+        "backtest_type":{"Fast":{"delay_ms":0}},"data_source":"Random","data_dest":"Null","broker_settings":"#;
+    let settings_str = ::serde_json::to_string(&SimBrokerSettings::default()).unwrap();
+    let mut definition: BacktestDefinition = ::serde_json::from_str(&format!("{}{}}}", definition_str, settings_str)).unwrap();
+    // older definitions without a starting capital still deserialize
+    assert_eq!(definition.starting_capital, None);
+    assert_eq!(definition.max_open_positions, None);
+    assert_eq!(definition.resume_from_tick, None);
+    assert_eq!(definition.data_dest_buffer, DEFAULT_DATA_DEST_BUFFER);
+    match definition.backtest_type {
+        BacktestType::Fast{delay_ms} => assert_eq!(delay_ms, 0),
+        _ => unreachable!(),
+    }
+
+    let mut sim = SimBrokerClient::init(HashMap::new()).wait().unwrap().unwrap();
+    definition.configure_simbroker(&mut sim);
+    assert_eq!(sim.settings().max_open_positions, SimBrokerSettings::default().max_open_positions);
+
+    // the starting capital is in dollars and balances are in cents
+    definition.starting_capital = Some(1000.);
+    definition.max_open_positions = Some(2);
+    definition.configure_simbroker(&mut sim);
+    assert_eq!(sim.equity().0, 100000);
+    assert_eq!(sim.starting_equity(), 100000.);
+    assert_eq!(sim.settings().starting_balance, 100000);
+    assert_eq!(sim.settings().max_open_positions, 2);
+}
+
+/// A definition without a starting capital leaves the SimBroker's configured starting balance alone.
+#[test]
+fn starting_capital_unset() {
+    use futures::Future;
+
+    use simbroker::*;
+
+    let definition_str = r#"{"start_time":null,"max_timestamp":null,"max_tick_n":null,"symbol":"TEST",
+        "backtest_type":{"Fast":{"delay_ms":0}},"data_source":"Random","data_dest":"Null","broker_settings":"#;
+    let settings_str = ::serde_json::to_string(&SimBrokerSettings::default()).unwrap();
+    let definition: BacktestDefinition = ::serde_json::from_str(&format!("{}{}}}", definition_str, settings_str)).unwrap();
+    assert_eq!(definition.starting_capital, None);
+
+    let mut sim = SimBrokerClient::init(HashMap::new()).wait().unwrap().unwrap();
+    let starting_balance = sim.settings().starting_balance;
+    let (balance, equity) = (sim.equity().0, sim.starting_equity());
+    definition.configure_simbroker(&mut sim);
+    assert_eq!(sim.settings().starting_balance, starting_balance);
+    assert_eq!(sim.equity().0, balance);
+    assert_eq!(sim.starting_equity(), equity);
+    assert_eq!(sim.starting_equity(), SimBrokerSettings::default().starting_balance as f64);
+}
+
+/// Ticks keep their backtest indexes after older ones are evicted.
+#[test]
+fn tick_history_eviction() {
+    let mut history = TickHistory::new(3);
+    for i in 0..5 {
+        history.push(Tick {timestamp: i * 10, bid: 100 + i as usize, ask: 102 + i as usize});
+    }
+
+    assert_eq!(history.index_range(), (2, 5));
+    assert!(history.get(1).is_none());
+    assert_eq!(history.get(2).map(|t| t.timestamp), Some(20));
+    assert_eq!(history.get(4).map(|t| t.bid), Some(104));
+    assert!(history.get(5).is_none());
+}
@@ -0,0 +1,195 @@
+//! The interface through which the Backtester drives and inspects the brokers it manages.  Strategies trade on
+//! them through the `Broker` trait; everything on top of that is what the Backtester needs to feed them backtest
+//! ticks, fund them, report on them, and reuse them between backtests.
+
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use tickgrinder_util::trading::tick::Tick;
+use simbroker::*;
+
+/// A broker that's managed by the Backtester.  Implemented by `SimBrokerClient`.
+pub trait ManagedBroker: Broker {
+    fn uuid(&self) -> Uuid;
+
+    /// Subscribes the broker to its topics on the bus shared by all managed brokers.
+    fn attach_bus(&mut self, bus: Arc<MessageBus>) -> Result<(), BrokerError>;
+
+    /// Publishes a message to the bus, returning how many subscribers received it.
+    fn publish_message(&self, topic: &str, msg: String) -> Result<usize, BrokerError>;
+
+    /// Returns the `(topic, message)` pairs received from the bus since the last call.
+    fn take_messages(&mut self) -> Vec<(String, String)>;
+
+    fn settings(&self) -> &SimBrokerSettings;
+
+    /// Changes the starting balance of the broker's accounts, in the lowest units of the account currency.
+    fn set_starting_balance(&mut self, starting_balance: usize);
+
+    fn set_max_open_positions(&mut self, max_open_positions: usize);
+
+    /// Tags the trades the broker logs with the UUID of the backtest driving it.
+    fn set_backtest_uuid(&mut self, backtest_uuid: Uuid);
+
+    /// Sets the price of a symbol, creating it if it doesn't exist, without processing a tick.
+    fn oneshot_price_set(
+        &mut self, name: String, price: (usize, usize), is_fx: bool, decimal_precision: usize,
+    ) -> BrokerResult;
+
+    /// Processes a tick of a symbol, creating the symbol if it doesn't exist.
+    fn push_tick(&mut self, name: String, tick: Tick, is_fx: bool, decimal_precision: usize) -> BrokerResult;
+
+    fn get_price(&self, symbol: &String) -> Option<(usize, usize)>;
+
+    /// Opens a position that the account held before the backtest started.
+    fn preload_position(&mut self, symbol: &str, qty: f64, entry_price: usize) -> BrokerResult;
+
+    fn pending_orders(&self) -> Vec<(Uuid, Position)>;
+
+    fn position_blotter(&self) -> Vec<BlotterEntry>;
+
+    fn order_blotter(&self) -> Vec<BlotterEntry>;
+
+    /// Modifies a pending order of whichever account it belongs to.
+    fn modify_pending_order(
+        &mut self, order_uuid: Uuid, new_price: Option<usize>, new_size: Option<usize>,
+        new_sl_tp: Option<(Option<usize>, Option<usize>)>,
+    ) -> OrderUpdateResult;
+
+    /// Cancels a pending order of whichever account it belongs to.
+    fn cancel_pending_order(&mut self, order_uuid: Uuid) -> OrderUpdateResult;
+
+    /// Cancels every pending order, returning how many were cancelled.
+    fn cancel_all_orders(&mut self) -> usize;
+
+    /// Returns the combined balance and equity of the broker's accounts.
+    fn equity(&self) -> (usize, f64);
+
+    /// Returns the combined equity of the broker's accounts at the start of the simulation.
+    fn starting_equity(&self) -> f64;
+
+    fn equity_curve(&self) -> &[EquitySample];
+
+    fn stats(&self) -> SimBrokerStats;
+
+    fn trade_log(&self) -> &[TradeLogEntry];
+
+    fn flush_trade_log(&mut self) -> Result<(), String>;
+
+    /// Returns the broker to its initial state so that it can be used for another backtest.
+    fn reset(&mut self, settings: Option<SimBrokerSettings>) -> BrokerResult;
+
+    fn bench(&mut self, n_ticks: usize, orders_per_tick: usize) -> Result<BenchResults, BrokerError>;
+}
+
+impl ManagedBroker for SimBrokerClient {
+    fn uuid(&self) -> Uuid {
+        SimBrokerClient::uuid(self)
+    }
+
+    fn attach_bus(&mut self, bus: Arc<MessageBus>) -> Result<(), BrokerError> {
+        SimBrokerClient::attach_bus(self, bus)
+    }
+
+    fn publish_message(&self, topic: &str, msg: String) -> Result<usize, BrokerError> {
+        SimBrokerClient::publish_message(self, topic, msg)
+    }
+
+    fn take_messages(&mut self) -> Vec<(String, String)> {
+        SimBrokerClient::take_messages(self)
+    }
+
+    fn settings(&self) -> &SimBrokerSettings {
+        SimBrokerClient::settings(self)
+    }
+
+    fn set_starting_balance(&mut self, starting_balance: usize) {
+        SimBrokerClient::set_starting_balance(self, starting_balance)
+    }
+
+    fn set_max_open_positions(&mut self, max_open_positions: usize) {
+        SimBrokerClient::set_max_open_positions(self, max_open_positions)
+    }
+
+    fn set_backtest_uuid(&mut self, backtest_uuid: Uuid) {
+        SimBrokerClient::set_backtest_uuid(self, backtest_uuid)
+    }
+
+    fn oneshot_price_set(
+        &mut self, name: String, price: (usize, usize), is_fx: bool, decimal_precision: usize,
+    ) -> BrokerResult {
+        SimBrokerClient::oneshot_price_set(self, name, price, is_fx, decimal_precision)
+    }
+
+    fn push_tick(&mut self, name: String, tick: Tick, is_fx: bool, decimal_precision: usize) -> BrokerResult {
+        SimBrokerClient::push_tick(self, name, tick, is_fx, decimal_precision)
+    }
+
+    fn get_price(&self, symbol: &String) -> Option<(usize, usize)> {
+        SimBrokerClient::get_price(self, symbol)
+    }
+
+    fn preload_position(&mut self, symbol: &str, qty: f64, entry_price: usize) -> BrokerResult {
+        SimBrokerClient::preload_position(self, symbol, qty, entry_price)
+    }
+
+    fn pending_orders(&self) -> Vec<(Uuid, Position)> {
+        SimBrokerClient::pending_orders(self)
+    }
+
+    fn position_blotter(&self) -> Vec<BlotterEntry> {
+        SimBrokerClient::position_blotter(self)
+    }
+
+    fn order_blotter(&self) -> Vec<BlotterEntry> {
+        SimBrokerClient::order_blotter(self)
+    }
+
+    fn modify_pending_order(
+        &mut self, order_uuid: Uuid, new_price: Option<usize>, new_size: Option<usize>,
+        new_sl_tp: Option<(Option<usize>, Option<usize>)>,
+    ) -> OrderUpdateResult {
+        SimBrokerClient::modify_order(self, order_uuid, new_price, new_size, new_sl_tp)
+    }
+
+    fn cancel_pending_order(&mut self, order_uuid: Uuid) -> OrderUpdateResult {
+        SimBrokerClient::cancel_order(self, order_uuid)
+    }
+
+    fn cancel_all_orders(&mut self) -> usize {
+        SimBrokerClient::cancel_all_orders(self)
+    }
+
+    fn equity(&self) -> (usize, f64) {
+        SimBrokerClient::equity(self)
+    }
+
+    fn starting_equity(&self) -> f64 {
+        SimBrokerClient::starting_equity(self)
+    }
+
+    fn equity_curve(&self) -> &[EquitySample] {
+        SimBrokerClient::equity_curve(self)
+    }
+
+    fn stats(&self) -> SimBrokerStats {
+        SimBrokerClient::stats(self)
+    }
+
+    fn trade_log(&self) -> &[TradeLogEntry] {
+        SimBrokerClient::trade_log(self)
+    }
+
+    fn flush_trade_log(&mut self) -> Result<(), String> {
+        SimBrokerClient::flush_trade_log(self)
+    }
+
+    fn reset(&mut self, settings: Option<SimBrokerSettings>) -> BrokerResult {
+        SimBrokerClient::reset(self, settings)
+    }
+
+    fn bench(&mut self, n_ticks: usize, orders_per_tick: usize) -> Result<BenchResults, BrokerError> {
+        SimBrokerClient::bench(self, n_ticks, orders_per_tick)
+    }
+}
@@ -0,0 +1,562 @@
+//! Wrappers around `TickGenerator`s that change which of their ticks make it into a backtest or their timestamps,
+//! the layout of flatfile archives holding the ticks of many symbols, the reader of IEX Cloud's historical prices,
+//! and the WebSocket server that streams backtests' ticks to browsers.
+
+use std::path::PathBuf;
+use std::net::{Ipv4Addr, TcpListener, TcpStream, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{Datelike, NaiveDate, NaiveTime, TimeZone, Weekday};
+use chrono_tz::America::New_York;
+use futures::stream::{Stream, BoxStream};
+use futures::sync::mpsc::channel;
+use reqwest;
+use tungstenite::{Message, WebSocket};
+use tungstenite::server::accept;
+
+use tickgrinder_util::transport::tickstream::{
+    TickGenerator, TickMap, TickSink, CommandStream, TickstreamCommand, FlatfileReader, check_mail,
+    spawn_listener_thread, send_tick
+};
+use tickgrinder_util::trading::tick::{Tick, TimestampNormalizer};
+use SIMBROKER_DECIMAL_PRECISION;
+
+/// Where IEX Cloud's API is served
+pub const IEX_CLOUD_URL: &'static str = "https://cloud.iexapis.com/stable";
+/// The most requests per second that IEX Cloud allows on its free tier
+const IEX_REQUESTS_PER_SECOND: u32 = 5;
+
+lazy_static!{
+    /// Shared by every `IEXCloudReader` since the limit applies to all requests made with an API key
+    static ref IEX_RATE_LIMITER: RateLimiter = RateLimiter::new(IEX_REQUESTS_PER_SECOND);
+}
+
+/// A directory holding a CSV file of ticks for each symbol at `{root_dir}/{symbol}/ticks.csv`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatfileStore {
+    pub root_dir: String,
+}
+
+impl FlatfileStore {
+    pub fn new(root_dir: String) -> FlatfileStore {
+        FlatfileStore {
+            root_dir: root_dir,
+        }
+    }
+
+    /// Returns the path of the file holding the ticks of `symbol`.
+    pub fn symbol_to_path(&self, symbol: &str) -> PathBuf {
+        let mut path = PathBuf::from(&self.root_dir);
+        path.push(symbol);
+        path.push("ticks.csv");
+        path
+    }
+
+    /// Returns a `FlatfileReader` of the ticks of `symbol` starting at `start_time`.
+    pub fn reader(&self, symbol: String, start_time: Option<u64>) -> FlatfileReader {
+        FlatfileReader {
+            path: Some(self.symbol_to_path(&symbol)),
+            symbol: symbol,
+            start_time: start_time,
+        }
+    }
+}
+
+/// Drops the first `skip_n` ticks of the wrapped generator, for data sets that start with bad data or auction noise.
+/// Skipped ticks never reach the backtest's map, so they don't cause any delay and aren't counted as processed.
+pub struct SkippingTickGenerator {
+    pub inner: Box<TickGenerator + Send>,
+    pub skip_n: usize,
+}
+
+impl SkippingTickGenerator {
+    pub fn new(inner: Box<TickGenerator + Send>, skip_n: usize) -> SkippingTickGenerator {
+        SkippingTickGenerator {
+            inner: inner,
+            skip_n: skip_n,
+        }
+    }
+}
+
+impl TickGenerator for SkippingTickGenerator {
+    fn get(
+        &mut self, map: Box<TickMap + Send>, cmd_handle: CommandStream
+    ) -> Result<BoxStream<Tick, ()>, String> {
+        let skip_map = SkipMap {
+            remaining: self.skip_n,
+            inner: map,
+        };
+        self.inner.get(Box::new(skip_map), cmd_handle)
+    }
+
+    fn get_raw(&mut self) -> Result<BoxStream<Tick, ()>, String> {
+        self.inner.get_raw().map(|stream| stream.skip(self.skip_n as u64).boxed())
+    }
+}
+
+/// Drops ticks until `remaining` have been dropped, then hands the rest to the inner map.
+struct SkipMap {
+    remaining: usize,
+    inner: Box<TickMap + Send>,
+}
+
+impl TickMap for SkipMap {
+    fn map(&mut self, t: Tick) -> Option<Tick> {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            return None;
+        }
+        self.inner.map(t)
+    }
+}
+
+/// Converts the timestamps of the wrapped generator's ticks to milliseconds before they reach the backtest's map, so
+/// that delays and `max_timestamp` are worked out in milliseconds whatever the source's unit.  Ticks whose timestamps
+/// are in a different unit than the ones before them are dropped.
+pub struct NormalizingTickGenerator {
+    pub inner: Box<TickGenerator + Send>,
+    pub normalizer: TimestampNormalizer,
+}
+
+impl NormalizingTickGenerator {
+    pub fn new(inner: Box<TickGenerator + Send>, normalizer: TimestampNormalizer) -> NormalizingTickGenerator {
+        NormalizingTickGenerator {
+            inner: inner,
+            normalizer: normalizer,
+        }
+    }
+}
+
+impl TickGenerator for NormalizingTickGenerator {
+    fn get(
+        &mut self, map: Box<TickMap + Send>, cmd_handle: CommandStream
+    ) -> Result<BoxStream<Tick, ()>, String> {
+        let normalize_map = NormalizeMap {
+            normalizer: self.normalizer.clone(),
+            inner: map,
+        };
+        self.inner.get(Box::new(normalize_map), cmd_handle)
+    }
+
+    fn get_raw(&mut self) -> Result<BoxStream<Tick, ()>, String> {
+        let mut normalizer = self.normalizer.clone();
+        self.inner.get_raw().map(|stream| stream.filter_map(move |t| normalize(&mut normalizer, t)).boxed())
+    }
+}
+
+/// Returns the tick with its timestamp in milliseconds, or logs and drops it if its unit doesn't match the stream's.
+fn normalize(normalizer: &mut TimestampNormalizer, t: Tick) -> Option<Tick> {
+    match normalizer.normalize(t) {
+        Ok(t) => Some(t),
+        Err(err) => {
+            println!("Dropping tick {:?}: {}", t, err);
+            None
+        },
+    }
+}
+
+/// Converts the timestamps of ticks to milliseconds, then hands them to the inner map.
+struct NormalizeMap {
+    normalizer: TimestampNormalizer,
+    inner: Box<TickMap + Send>,
+}
+
+impl TickMap for NormalizeMap {
+    fn map(&mut self, t: Tick) -> Option<Tick> {
+        normalize(&mut self.normalizer, t).and_then(|t| self.inner.map(t))
+    }
+}
+
+/// Spaces out calls to `wait()` so that no more than the given number happen in any second.
+pub struct RateLimiter {
+    interval: Duration,
+    last: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: u32) -> RateLimiter {
+        RateLimiter {
+            interval: Duration::from_secs(1) / per_second,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Blocks until a full interval has passed since the last call returned.  Callers are let through one at a
+    /// time since the lock is held while sleeping.
+    pub fn wait(&self) {
+        let mut last = self.last.lock().unwrap();
+        if let Some(last) = *last {
+            let elapsed = last.elapsed();
+            if elapsed < self.interval {
+                thread::sleep(self.interval - elapsed);
+            }
+        }
+        *last = Some(Instant::now());
+    }
+}
+
+/// A minute of IEX Cloud's intraday prices.  Minutes without any trades have no prices.
+#[derive(Deserialize)]
+struct IEXMinuteBar {
+    /// Start of the minute as `HH:MM`, New York time
+    minute: String,
+    close: Option<f64>,
+}
+
+/// Reads a symbol's historical prices from IEX Cloud one day at a time, from `start` through `end`, which are
+/// dates formatted as `YYYYMMDD`.  IEX only has minute bars for past days rather than individual trades, so the
+/// close of each minute with trades becomes a tick with that price as both its bid and ask, timestamped at the
+/// end of the minute.  Prices are converted to the decimal precision of the backtests' SimBroker symbols.
+pub struct IEXCloudReader {
+    pub symbol: String,
+    pub api_key: String,
+    pub start: String,
+    pub end: String,
+    /// Only ticks with timestamps at or after this are read
+    pub start_time: Option<u64>,
+    /// Only ticks with timestamps at or before this are read
+    pub end_time: Option<u64>,
+    /// Where the API is served; changed to test against a local server
+    pub base_url: String,
+    /// Set when the reader is dropped so that the worker threads stop making requests
+    closed: Arc<AtomicBool>,
+}
+
+impl IEXCloudReader {
+    pub fn new(
+        symbol: String, api_key: String, start: String, end: String, start_time: Option<u64>, end_time: Option<u64>
+    ) -> IEXCloudReader {
+        IEXCloudReader {
+            symbol: symbol,
+            api_key: api_key,
+            start: start,
+            end: end,
+            start_time: start_time,
+            end_time: end_time,
+            base_url: String::from(IEX_CLOUD_URL),
+            closed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Parses the dates and returns a copy of the settings needed by a worker thread to read the ticks.
+    fn query(&self) -> Result<IEXCloudQuery, String> {
+        let parse_date = |date: &str| NaiveDate::parse_from_str(date, "%Y%m%d")
+            .map_err(|_| format!("Unable to parse date \"{}\"; expected YYYYMMDD.", date));
+        Ok(IEXCloudQuery {
+            base_url: self.base_url.clone(),
+            symbol: self.symbol.clone(),
+            api_key: self.api_key.clone(),
+            start: parse_date(&self.start)?,
+            end: parse_date(&self.end)?,
+            start_time: self.start_time,
+            end_time: self.end_time,
+            closed: self.closed.clone(),
+        })
+    }
+}
+
+impl Drop for IEXCloudReader {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+struct IEXCloudQuery {
+    base_url: String,
+    symbol: String,
+    api_key: String,
+    start: NaiveDate,
+    end: NaiveDate,
+    start_time: Option<u64>,
+    end_time: Option<u64>,
+    closed: Arc<AtomicBool>,
+}
+
+impl IEXCloudQuery {
+    /// Hands the ticks of each day to `handle_batch` in order until it returns false.  Weekends are skipped
+    /// without making a request since the market is closed.
+    fn run<F>(&self, mut handle_batch: F) -> Result<(), String> where F: FnMut(&[Tick]) -> bool {
+        let mut date = self.start;
+        while date <= self.end {
+            if self.closed.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            if date.weekday() != Weekday::Sat && date.weekday() != Weekday::Sun {
+                let ticks: Vec<Tick> = self.fetch_day(date)?
+                    .into_iter()
+                    .filter(|t| {
+                        self.start_time.map(|start| t.timestamp >= start).unwrap_or(true) &&
+                            self.end_time.map(|end| t.timestamp <= end).unwrap_or(true)
+                    }).collect();
+                if !ticks.is_empty() && !handle_batch(&ticks) {
+                    return Ok(());
+                }
+            }
+            date = date.succ();
+        }
+        Ok(())
+    }
+
+    /// Requests the minute bars of one day and converts them to ticks.  The URL isn't included in errors since it
+    /// holds the API key.
+    fn fetch_day(&self, date: NaiveDate) -> Result<Vec<Tick>, String> {
+        let date_str = date.format("%Y%m%d").to_string();
+        let url = format!(
+            "{}/stock/{}/chart/date/{}?token={}", self.base_url, self.symbol, date_str, self.api_key
+        );
+        IEX_RATE_LIMITER.wait();
+        let mut res = reqwest::get(url.as_str())
+            .map_err(|err| format!("Error requesting {} prices for {}: {}", self.symbol, date_str, err))?;
+        if !res.status().is_success() {
+            return Err(format!("IEX Cloud returned {} for {} prices on {}", res.status(), self.symbol, date_str));
+        }
+        let bars: Vec<IEXMinuteBar> = res.json()
+            .map_err(|err| format!("Unable to parse {} prices for {}: {}", self.symbol, date_str, err))?;
+
+        bars.iter().filter_map(|bar| bar.close.map(|close| (bar, close))).map(|(bar, close)| {
+            let minute = NaiveTime::parse_from_str(&bar.minute, "%H:%M")
+                .map_err(|_| format!("Unable to parse minute \"{}\" of {}", bar.minute, date_str))?;
+            let start = New_York.from_local_datetime(&date.and_time(minute)).single()
+                .ok_or_else(|| format!("Minute {} of {} doesn't exist in New York time", bar.minute, date_str))?;
+            let price = (close * 10f64.powi(SIMBROKER_DECIMAL_PRECISION as i32)).round() as usize;
+            Ok(Tick {timestamp: start.timestamp() as u64 * 1000 + 60 * 1000, bid: price, ask: price})
+        }).collect()
+    }
+}
+
+impl TickGenerator for IEXCloudReader {
+    fn get(
+        &mut self, mut map: Box<TickMap + Send>, cmd_handle: CommandStream
+    ) -> Result<BoxStream<Tick, ()>, String> {
+        let query = self.query()?;
+        let internal_message: Arc<Mutex<TickstreamCommand>> = Arc::new(Mutex::new(TickstreamCommand::Stop));
+        let got_mail = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel::<Tick>(1);
+
+        let _got_mail = got_mail.clone();
+        let _internal_message = internal_message.clone();
+        let reader_handle = thread::spawn(move || {
+            let mut tx = Some(tx);
+            let res = query.run(|ticks| {
+                for tick in ticks {
+                    if check_mail(&*got_mail, &*_internal_message) {
+                        println!("Stop command received; killing reader");
+                        return false;
+                    }
+
+                    if let Some(t_mod) = map.map(*tick) {
+                        if !send_tick(&mut tx, t_mod) {
+                            return false;
+                        }
+                    }
+                }
+                true
+            });
+            if let Err(err) = res {
+                println!("Error while reading ticks from IEX Cloud: {}", err);
+            }
+        }).thread().clone();
+
+        spawn_listener_thread(_got_mail, cmd_handle, internal_message, reader_handle);
+
+        Ok(rx.boxed())
+    }
+
+    fn get_raw(&mut self) -> Result<BoxStream<Tick, ()>, String> {
+        let query = self.query()?;
+        let (tx, rx) = channel(1);
+
+        thread::spawn(move || {
+            let mut tx = Some(tx);
+            let res = query.run(|ticks| ticks.iter().all(|tick| send_tick(&mut tx, *tick)));
+            if let Err(err) = res {
+                println!("Error while reading ticks from IEX Cloud: {}", err);
+            }
+        });
+
+        Ok(rx.boxed())
+    }
+}
+
+/// Accepts WebSocket connections and sends each tick to every connected client as a JSON-encoded `SymbolTick`.
+/// Clients that have disconnected are only noticed, and removed, when sending them the next tick fails.
+pub struct WebSocketSink {
+    pub symbol: String,
+    /// The address that the server is listening on
+    pub local_addr: SocketAddr,
+    clients: Arc<Mutex<Vec<WebSocket<TcpStream>>>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl WebSocketSink {
+    /// Binds to `bind_addr` and starts accepting connections in the background until the sink is dropped.
+    pub fn bind(symbol: String, bind_addr: &str) -> Result<WebSocketSink, String> {
+        let listener = TcpListener::bind(bind_addr)
+            .map_err(|err| format!("Unable to bind WebSocket server to {}: {}", bind_addr, err))?;
+        let local_addr = listener.local_addr().map_err(|err| format!("{}", err))?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let (clients_clone, closed_clone) = (clients.clone(), closed.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if closed_clone.load(Ordering::Relaxed) {
+                    return;
+                }
+                if let Ok(stream) = stream {
+                    // a client that never finishes its handshake shouldn't keep the others from connecting
+                    let clients = clients_clone.clone();
+                    thread::spawn(move || {
+                        if let Ok(ws) = accept(stream) {
+                            clients.lock().unwrap().push(ws);
+                        }
+                    });
+                }
+            }
+        });
+
+        Ok(WebSocketSink {
+            symbol: symbol,
+            local_addr: local_addr,
+            clients: clients,
+            closed: closed,
+        })
+    }
+}
+
+impl TickSink for WebSocketSink {
+    fn tick(&mut self, t: Tick) {
+        let msg = t.to_json_string(self.symbol.clone());
+        let mut clients = self.clients.lock().unwrap();
+        let connected = clients.drain(..)
+            .filter_map(|mut ws| ws.write_message(Message::Text(msg.clone())).ok().map(|_| ws))
+            .collect();
+        *clients = connected;
+    }
+}
+
+impl Drop for WebSocketSink {
+    fn drop(&mut self) {
+        // wake the listener up so that it sees that it's closed and frees the address
+        self.closed.store(true, Ordering::Relaxed);
+        let mut addr = self.local_addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(Ipv4Addr::new(127, 0, 0, 1).into());
+        }
+        let _ = TcpStream::connect(addr);
+    }
+}
+
+#[test]
+fn flatfile_store_paths() {
+    let store = FlatfileStore::new(String::from("/data/ticks"));
+    assert_eq!(store.symbol_to_path("EURUSD"), PathBuf::from("/data/ticks/EURUSD/ticks.csv"));
+    assert_eq!(store.reader(String::from("USDJPY"), None).path(), PathBuf::from("/data/ticks/USDJPY/ticks.csv"));
+}
+
+#[test]
+fn skip_map_drops_first_ticks() {
+    use tickgrinder_util::transport::tickstream::NullMap;
+
+    let mut map = SkipMap {remaining: 5, inner: Box::new(NullMap {})};
+    let passed: Vec<u64> = (0..10)
+        .filter_map(|timestamp| map.map(Tick {timestamp: timestamp, bid: 1, ask: 1}))
+        .map(|t| t.timestamp)
+        .collect();
+    assert_eq!(passed, vec![5, 6, 7, 8, 9]);
+}
+
+/// Serves each body to requests for its path with `?token=test` appended, and a 404 to anything else, on a local
+/// port for the rest of the test run.  Returns the server's URL along with the paths of the requests it received.
+#[cfg(test)]
+fn serve_http(bodies: Vec<(String, String)>) -> (String, Arc<Mutex<Vec<String>>>) {
+    use std::io::{Read, Write};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requested = Arc::new(Mutex::new(Vec::new()));
+    let requested_clone = requested.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut req = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !String::from_utf8_lossy(&req).contains("\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                if n == 0 { break; }
+                req.extend_from_slice(&buf[..n]);
+            }
+            let req = String::from_utf8_lossy(&req).into_owned();
+            let path = req.split_whitespace().nth(1).unwrap_or("").to_string();
+            let body = bodies.iter().find(|&&(ref p, _)| format!("{}?token=test", p) == path).map(|&(_, ref b)| b);
+            let res = match body {
+                Some(body) => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                    Connection: close\r\n\r\n{}", body.len(), body
+                ),
+                None => String::from("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"),
+            };
+            stream.write_all(res.as_bytes()).unwrap();
+            requested_clone.lock().unwrap().push(path);
+        }
+    });
+
+    (url, requested)
+}
+
+/// Each trading day between the dates is requested once, at no more than the rate limit, and the minutes with
+/// trades are read back as ticks at their closes in the SimBrokers' precision.
+#[test]
+fn iex_cloud_reader() {
+    let friday = r#"[
+        {"date": "2017-12-15", "minute": "09:30", "label": "09:30 AM", "high": 174.9, "low": 174.5,
+            "average": 174.7, "volume": 3000, "notional": 524100, "numberOfTrades": 20, "open": 174.5,
+            "close": 174.88},
+        {"date": "2017-12-15", "minute": "09:31", "label": "09:31 AM", "high": null, "low": null, "average": null,
+            "volume": 0, "notional": 0, "numberOfTrades": 0, "open": null, "close": null}
+    ]"#;
+    let monday = r#"[
+        {"date": "2017-12-18", "minute": "09:30", "label": "09:30 AM", "high": 175.5, "low": 175.1,
+            "average": 175.3, "volume": 1500, "notional": 262950, "numberOfTrades": 12, "open": 175.1,
+            "close": 175.2},
+        {"date": "2017-12-18", "minute": "09:31", "label": "09:31 AM", "high": 175.4, "low": 175.2,
+            "average": 175.3, "volume": 700, "notional": 122710, "numberOfTrades": 5, "open": 175.2,
+            "close": 175.31}
+    ]"#;
+    let (url, requested) = serve_http(vec![
+        (String::from("/stock/AAPL/chart/date/20171215"), String::from(friday)),
+        (String::from("/stock/AAPL/chart/date/20171218"), String::from(monday)),
+    ]);
+
+    let mut reader = IEXCloudReader::new(
+        String::from("AAPL"), String::from("test"), String::from("20171215"), String::from("20171218"), None, None
+    );
+    reader.base_url = url.clone();
+    let start = Instant::now();
+    let ticks: Vec<Tick> = reader.get_raw().unwrap().wait().map(|t| t.unwrap()).collect();
+    assert!(start.elapsed() >= Duration::from_secs(1) / IEX_REQUESTS_PER_SECOND);
+    assert_eq!(ticks, vec![
+        // 09:30 in New York is 14:30 UTC in December, and the tick comes at the end of the minute
+        Tick {timestamp: 1513348260000, bid: 1748800, ask: 1748800},
+        Tick {timestamp: 1513607460000, bid: 1752000, ask: 1752000},
+        Tick {timestamp: 1513607520000, bid: 1753100, ask: 1753100},
+    ]);
+    assert_eq!(*requested.lock().unwrap(), vec![
+        String::from("/stock/AAPL/chart/date/20171215?token=test"),
+        String::from("/stock/AAPL/chart/date/20171218?token=test"),
+    ]);
+
+    // the time range filters the ticks, and an invalid date is refused before any request is made
+    let mut reader = IEXCloudReader::new(
+        String::from("AAPL"), String::from("test"), String::from("20171218"), String::from("20171218"),
+        Some(1513607460000), Some(1513607460000)
+    );
+    reader.base_url = url;
+    let ticks: Vec<Tick> = reader.get_raw().unwrap().wait().map(|t| t.unwrap()).collect();
+    assert_eq!(ticks, vec![Tick {timestamp: 1513607460000, bid: 1752000, ask: 1752000}]);
+    reader.end = String::from("2017-12-18");
+    assert!(reader.get_raw().is_err());
+}
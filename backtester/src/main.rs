@@ -22,12 +22,29 @@ extern crate test;
 #[macro_use]
 extern crate from_hashmap;
 extern crate simbroker;
+extern crate prometheus;
+extern crate tungstenite;
+extern crate reqwest;
+extern crate chrono;
+extern crate chrono_tz;
+#[cfg(test)]
+extern crate url;
 
 mod backtest;
+mod stats;
+mod metrics;
+mod data;
+mod strategy_bus;
+mod broker;
 
 use std::sync::{Arc, Mutex, mpsc};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use std::thread;
 use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
 use std::collections::HashMap;
 use std::str::FromStr;
 
@@ -36,18 +53,30 @@ use futures::Future;
 use futures::stream::{Stream, BoxStream};
 use serde_json::to_string;
 
-use tickgrinder_util::transport::command_server::CommandServer;
+use tickgrinder_util::transport::command_server::{CommandServer, set_log_level_response};
 use tickgrinder_util::transport::redis::{sub_multiple, get_client};
 use tickgrinder_util::transport::commands::*;
 use tickgrinder_util::transport::tickstream::*;
-use tickgrinder_util::trading::tick::Tick;
+use tickgrinder_util::trading::tick::{Tick, TimestampUnit, TimestampNormalizer};
+use tickgrinder_util::transport::tracing;
 use tickgrinder_util::instance::PlatformInstance;
 use tickgrinder_util::conf::CONF;
 use backtest::*;
+use stats::*;
+use metrics::*;
+use data::*;
+use strategy_bus::StrategyBus;
+use broker::ManagedBroker;
 use simbroker::*;
 
+/// How long the tick counts of paused backtests have to stay the same before they're considered settled
+const PAUSE_SETTLE_MS: u64 = 50;
+/// Decimal precision of the symbols that backtests create on the SimBrokers they're sending their ticks to
+const SIMBROKER_DECIMAL_PRECISION: usize = 4;
+
 lazy_static!{
     static ref NO_BACKTEST: String = String::from("No backtest with that UUID!");
+    static ref NO_SIMBROKER: String = String::from("No SimBroker with that UUID!");
 }
 
 /// Starts the backtester module, initializing its interface to the rest of the platform
@@ -63,8 +92,22 @@ fn main() {
         _ => panic!("Wrong number of arguments provided!  Usage: ./tick_processor [uuid] [symbol]"),
     }
 
-    let backtester = Backtester::new(uuid);
+    tracing::init_from_conf("Backtester");
+    let mut backtester = Backtester::new(uuid);
     let mut csc = backtester.cs.clone();
+    // pick up the backtests that the previous Backtester saved before it was restarted
+    if Path::new(CONF.restart_state_file).exists() {
+        match backtester.load_state(CONF.restart_state_file) {
+            Ok(uuids) => csc.notice(None, &format!("Resumed {} backtests from before the restart.", uuids.len())),
+            Err(err) => csc.error(None, &err),
+        }
+    }
+    if CONF.backtester_metrics_port != 0 {
+        let addr = format!("0.0.0.0:{}", CONF.backtester_metrics_port);
+        if let Err(err) = serve_metrics(&addr, backtester.metrics.clone(), backtester.running_backtests.clone()) {
+            csc.error(None, &format!("Unable to start metrics endpoint on {}: {:?}", addr, err));
+        }
+    }
     let uuid = backtester.uuid;
     backtester.listen(uuid, &mut csc);
 }
@@ -74,15 +117,40 @@ fn main() {
 pub enum BacktestType {
     Fast{delay_ms: usize},
     Live,
+    /// Outputs ticks at a constant rate regardless of their timestamps
+    TickCount{ticks_per_second: f64},
 }
 
 /// Where to get the data to drive the backtest
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum DataSource {
     Flatfile,
+    /// Reads the backtest's symbol out of a directory with a file for each symbol.  See `FlatfileStore`.
+    FlatfileStore{root: String},
     RedisChannel{host: String, channel: String},
     Postgres,
     Random,
+    /// A TimescaleDB hypertable holding the ticks of many symbols, of which `symbol` is read.  See `TimescaleReader`.
+    TimescaleDB{connection_str: String, hypertable: String, symbol: String},
+    /// Reads from `inner` but ignores its first `skip_n` ticks entirely; they never reach the map or the endpoint.
+    Skipped{inner: Box<DataSource>, skip_n: usize},
+    /// Historical prices of `symbol` from IEX Cloud for the dates from `start` through `end`, formatted as
+    /// `YYYYMMDD`.  See `IEXCloudReader`.
+    IEXCloud{symbol: String, api_key: String, start: String, end: String},
+    /// Reads from `inner` with its timestamps taken to be in `unit` instead of the unit set in the config or detected
+    /// from the timestamps themselves.
+    WithTimestampUnit{inner: Box<DataSource>, unit: TimestampUnit},
+}
+
+impl DataSource {
+    /// Returns the unit that the source's timestamps were said to be in, if they were.
+    pub fn timestamp_unit(&self) -> Option<TimestampUnit> {
+        match *self {
+            DataSource::WithTimestampUnit{unit, ..} => Some(unit),
+            DataSource::Skipped{ref inner, ..} => inner.timestamp_unit(),
+            _ => None,
+        }
+    }
 }
 
 /// Where to send the backtest's generated data
@@ -92,6 +160,8 @@ pub enum DataDest {
     Console,
     Null,
     SimBroker{uuid: Uuid}, // Requires that a SimBroker is running on the Backtester in order to work
+    /// Serves the ticks over WebSocket to every client connected to `bind_addr`.  See `WebSocketSink`.
+    WebSocket{bind_addr: String},
 }
 
 #[derive(Clone)]
@@ -99,14 +169,26 @@ struct Backtester {
     pub uuid: Uuid,
     pub cs: CommandServer,
     pub running_backtests: Arc<Mutex<HashMap<Uuid, BacktestHandle>>>,
-    pub simbrokers: Arc<Mutex<HashMap<Uuid, SimBrokerClient>>>,
+    pub simbrokers: Arc<Mutex<HashMap<Uuid, Box<ManagedBroker + Send>>>>,
+    pub metrics: BacktestMetrics,
+    /// Shared by all managed SimBrokers so that the strategies trading on them can message each other
+    pub bus: Arc<StrategyBus>,
 }
 
 impl PlatformInstance for Backtester {
     fn handle_command(&mut self, cmd: Command) -> Option<Response> {
         match cmd {
-            Command::Ping => Some(Response::Pong{ args: vec![self.uuid.hyphenated().to_string()] }),
+            Command::Ping => {
+                self.cs.debug(None, "Received Ping");
+                Some(Response::Pong{ args: vec![self.uuid.hyphenated().to_string()] })
+            },
             Command::Type => Some(Response::Info{ info: String::from("Backtester") }),
+            Command::SetLogLevel{uuid, level} => {
+                if uuid != self.uuid {
+                    return None;
+                }
+                Some(set_log_level_response(&level))
+            },
             Command::StartBacktest{definition: definition_str} => {
                 let definition = serde_json::from_str(&definition_str);
                 if definition.is_err() {
@@ -144,6 +226,21 @@ impl PlatformInstance for Backtester {
                     Err(()) => Response::Error{status: NO_BACKTEST.clone()},
                 })
             },
+            Command::PrepareForRestart => {
+                Some(match self.save_state(CONF.restart_state_file) {
+                    Ok(n) => {
+                        thread::spawn(|| {
+                            thread::sleep(Duration::from_secs(3));
+                            std::process::exit(0);
+                        });
+
+                        Response::Info{info: format!("Saved {} backtests; Backtester will exit in 3 seconds.", n)}
+                    },
+                    Err(err) => Response::Error{status: err},
+                })
+            },
+            Command::PauseAllBacktests => Some(self.send_all_backtests_cmd(TickstreamCommand::Pause, "pause")),
+            Command::ResumeAllBacktests => Some(self.send_all_backtests_cmd(TickstreamCommand::Resume, "resume")),
             Command::StopBacktest{uuid} => {
                 Some(match self.send_backtest_cmd(&uuid, TickstreamCommand::Stop) {
                     Ok(()) => {
@@ -172,6 +269,14 @@ impl PlatformInstance for Backtester {
                 let uuid = self.init_simbroker(settings);
                 Some(Response::Info{info: uuid.hyphenated().to_string()})
             },
+            Command::DiffBacktests{uuid_a, uuid_b} => Some(self.diff_backtests(&uuid_a, &uuid_b)),
+            Command::InspectTick{backtest_uuid, tick_index} => Some(self.inspect_tick(&backtest_uuid, tick_index)),
+            Command::AttachSimbroker{backtest_uuid, simbroker_uuid} => {
+                Some(match self.attach_simbroker(&backtest_uuid, simbroker_uuid) {
+                    Ok(()) => Response::Ok,
+                    Err(err) => Response::Error{status: err},
+                })
+            },
             Command::ListSimbrokers => {
                 let simbrokers = self.simbrokers.lock().unwrap();
                 let mut uuids = Vec::new();
@@ -181,6 +286,36 @@ impl PlatformInstance for Backtester {
                 let message = serde_json::to_string(&uuids).unwrap();
                 Some(Response::Info{info: message})
             },
+            Command::ModifySimbrokerOrder{uuid, order_uuid, new_price, new_size, new_sl_tp} => {
+                Some(self.simbroker_cmd(&uuid, |sim| {
+                    to_string(&sim.modify_pending_order(order_uuid, new_price, new_size, new_sl_tp))
+                }))
+            },
+            Command::CancelSimbrokerOrder{uuid, order_uuid} => {
+                Some(self.simbroker_cmd(&uuid, |sim| to_string(&sim.cancel_pending_order(order_uuid))))
+            },
+            Command::CancelAllOrders{simbroker_uuid} => {
+                Some(self.simbroker_cmd(&simbroker_uuid, |sim| to_string(&sim.cancel_all_orders())))
+            },
+            Command::ListSimbrokerOrders{uuid} => {
+                Some(self.simbroker_cmd(&uuid, |sim| to_string(&sim.pending_orders())))
+            },
+            Command::SimbrokerPositions{uuid} => {
+                Some(self.simbroker_cmd(&uuid, |sim| to_string(&sim.position_blotter())))
+            },
+            Command::SimbrokerOrders{uuid} => {
+                Some(self.simbroker_cmd(&uuid, |sim| to_string(&sim.order_blotter())))
+            },
+            Command::EquityCurve{uuid} => {
+                Some(self.simbroker_cmd(&uuid, |sim| to_string(sim.equity_curve())))
+            },
+            Command::GetSimbrokerStats{uuid} => {
+                Some(self.simbroker_cmd(&uuid, |sim| to_string(&sim.stats())))
+            },
+            Command::ResetSimbroker{uuid, settings} => Some(self.reset_simbroker(&uuid, settings)),
+            Command::BenchSimbroker{uuid, n_ticks, orders_per_tick} => {
+                Some(self.bench_simbroker(&uuid, n_ticks, orders_per_tick))
+            },
             _ => Some(Response::Error{ status: String::from("Backtester doesn't recognize that command.") })
         }
     }
@@ -193,29 +328,46 @@ impl Backtester {
             cs: CommandServer::new(uuid, "Backtester"),
             running_backtests: Arc::new(Mutex::new(HashMap::new())),
             simbrokers: Arc::new(Mutex::new(HashMap::new())),
+            metrics: BacktestMetrics::new(),
+            bus: Arc::new(StrategyBus::new()),
         }
     }
 
-    /// Creates a SimBroker that's managed by the Backtester.  Returns its UUID.
+    /// Creates a SimBroker that's managed by the Backtester and attaches it to the strategy bus.  Returns its UUID.
     pub fn init_simbroker(&mut self, settings: HashMap<String, String>) -> Uuid {
         let mut simbrokers = self.simbrokers.lock().unwrap();
         // TODO: Use new updated SimbrokerSettings
         let simbroker = SimBrokerClient::init(settings).wait().unwrap().unwrap();
-        let uuid = Uuid::new_v4();
+        let mut simbroker: Box<ManagedBroker + Send> = Box::new(simbroker);
+        if let Err(err) = simbroker.attach_bus(self.bus.clone()) {
+            self.cs.error(None, &format!("Unable to subscribe the SimBroker to its bus topics: {:?}", err));
+        }
+        let uuid = simbroker.uuid();
         simbrokers.insert(uuid, simbroker);
         uuid
     }
 
     /// Initiates a new backtest and adds it to the internal list of monitored backtests.
-    fn start_backtest(
-        &mut self, definition: BacktestDefinition) -> Result<Uuid, String>
+    fn start_backtest(&mut self, definition: BacktestDefinition) -> Result<Uuid, String> {
+        self.start_backtest_with_uuid(definition, Uuid::new_v4())
+    }
+
+    /// Same as `start_backtest` but registers the backtest under the given UUID.  Used to keep the UUIDs of
+    /// backtests that are resumed after a restart.
+    fn start_backtest_with_uuid(
+        &mut self, definition: BacktestDefinition, uuid: Uuid) -> Result<Uuid, String>
     {
         let msg = format!("Starting backtest with definition: {:?}", definition);
         self.cs.notice(None, &msg);
         // Create the TickGenerator that provides the backtester with data
-        let mut src: Box<TickGenerator> = resolve_data_source(
-            &definition.data_source, definition.symbol.clone(), definition.start_time
+        let mut src: Box<TickGenerator + Send> = resolve_data_source(
+            &definition.data_source, definition.symbol.clone(), definition.start_time, definition.max_timestamp
         );
+        // a resumed backtest skips the ticks it processed before it was restarted
+        let resume_from = definition.resume_from_tick.unwrap_or(0);
+        if resume_from > 0 {
+            src = Box::new(SkippingTickGenerator::new(src, resume_from as usize));
+        }
 
         // create channel for communicating messages to the running backtest sent externally
         let (external_handle_tx, handle_rx) = mpsc::sync_channel::<TickstreamCommand>(5);
@@ -229,75 +381,87 @@ impl Backtester {
                 Box::new(FastMap{delay_ms: delay_ms}), handle_rx
             ),
             BacktestType::Live => src.get(Box::new(LiveMap::new()), handle_rx),
+            BacktestType::TickCount{ticks_per_second} => {
+                // also rejects NaN
+                if !(ticks_per_second > 0.) {
+                    return Err(format!("Ticks per second must be positive, but {} was supplied.", ticks_per_second));
+                }
+                src.get(Box::new(TickRateMap::new(ticks_per_second)), handle_rx)
+            },
         };
 
         if tickstream.is_err() {
             return Err( format!("Error creating tickstream: {}", tickstream.err().unwrap()) )
         }
 
-        // create a TickSink that receives the output of the backtest
-        let dst_opt: Result<Box<TickSink + Send>, Uuid> = match definition.data_dest {
+        // create a TickSink that receives the output of the backtest.  It's shared with the backtest's handle so
+        // that it can be replaced while the backtest is running.
+        let dst: Box<TickSink + Send> = match definition.data_dest {
             DataDest::RedisChannel{ref host, ref channel} => {
-                Ok(Box::new(RedisSink::new(definition.symbol.clone(), channel.clone(), host.as_str())))
+                Box::new(RedisSink::new(definition.symbol.clone(), channel.clone(), host.as_str()))
             },
-            DataDest::Console => Ok(Box::new(ConsoleSink{})),
-            DataDest::Null => Ok(Box::new(NullSink{})),
-            DataDest::SimBroker{uuid} => Err(uuid),
+            DataDest::Console => Box::new(ConsoleSink{}),
+            DataDest::Null => Box::new(NullSink{}),
+            DataDest::SimBroker{uuid: simbroker_uuid} => {
+                Box::new(self.simbroker_sink(simbroker_uuid, uuid, &definition)?)
+            },
+            DataDest::WebSocket{ref bind_addr} => Box::new(WebSocketSink::bind(definition.symbol.clone(), bind_addr)?),
         };
+        let sink = Arc::new(Mutex::new(dst));
 
         let _definition = definition.clone();
-        let mut i = 0;
-        let uuid = Uuid::new_v4();
+        let mut i = resume_from as usize;
+        let tick_count = Arc::new(AtomicUsize::new(i));
+        let tick_count_clone = tick_count.clone();
+        let history = Arc::new(Mutex::new(TickHistory::starting_at(TICK_HISTORY_LEN, resume_from)));
+        let history_clone = history.clone();
 
         // initiate tick flow
         let mut csc = self.cs.clone();
-        if dst_opt.is_ok() {
-            let mut dst = dst_opt.unwrap();
-            thread::spawn(move || {
-                for t_res in tickstream.unwrap().wait() {
-                    match t_res {
-                        Ok(t) => {
-                            i += 1;
-
-                            // send the tick to the sink
-                            dst.tick(t);
-
-                            if check_early_exit(&t, &_definition, i) {
-                                let msg = "Backtest early exit condition true; exiting backtest.";
-                                csc.notice(None, msg);
-                                return Err(())
-                            }
-                        },
-                        Err(_) => {
-                            csc.notice(None, "Stopping backtest because tickstream has ended");
-                            internal_handle_tx.send(TickstreamCommand::Stop)
-                                .expect("Sending through the internal handle failed; tickstream dropped?");
+        // a slow sink fills the buffer rather than holding up the tickstream until it's full
+        let sink_tx = spawn_sink_thread(sink.clone(), definition.data_dest_buffer);
+        thread::spawn(move || {
+            // readers such as `PostgresReader` stop once they're dropped, so keep the source alive until
+            // the backtest is over
+            let _src = src;
+            for t_res in tickstream.unwrap().wait() {
+                match t_res {
+                    Ok(t) => {
+                        i += 1;
+                        tick_count_clone.store(i, Ordering::Relaxed);
+                        history_clone.lock().unwrap().push(t);
+
+                        // send the tick to the sink
+                        if sink_tx.send(t).is_err() {
+                            csc.error(None, "The backtest's sink thread has stopped; exiting backtest.");
+                            return Err(())
                         }
-                    };
-                }
-                Ok(())
-            });
-        } else {
-            let mut simbrokers = self.simbrokers.lock().unwrap();
-            let simbroker_opt = simbrokers.get_mut(&dst_opt.err().unwrap());
-            if simbroker_opt.is_none() {
-                return Err("No SimBroker running with that Uuid!".to_string())
-            }
 
-            let simbroker = simbroker_opt.unwrap();
-            // plug the tickstream into the matching SimBroker
-            // TODO TODO TODO: Implement proper values here
-            // BIG TODO; a large part of the backtester logic will have to be re-thought
-            // simbroker.register_tickstream(definition.symbol.clone(), tickstream.unwrap(), true, 6).unwrap();
-            // simbroker.init_sim_loop().expect("Unable to start SimBroker sim loop");
-        }
+                        if check_early_exit(&t, &_definition, i) {
+                            let msg = "Backtest early exit condition true; exiting backtest.";
+                            csc.notice(None, msg);
+                            return Err(())
+                        }
+                    },
+                    Err(_) => {
+                        csc.notice(None, "Stopping backtest because tickstream has ended");
+                        internal_handle_tx.send(TickstreamCommand::Stop)
+                            .expect("Sending through the internal handle failed; tickstream dropped?");
+                    }
+                };
+            }
+            Ok(())
+        });
 
         let handle = BacktestHandle {
-            symbol: definition.symbol,
-            backtest_type: definition.backtest_type,
-            data_source: definition.data_source,
-            endpoint: definition.data_dest,
-            handle: external_handle_tx
+            definition: definition,
+            handle: external_handle_tx,
+            tick_count: tick_count,
+            // backtests start out paused
+            running: Arc::new(AtomicBool::new(false)),
+            started: Instant::now(),
+            history: history,
+            sink: sink,
         };
 
         // register the backtest's existence
@@ -307,10 +471,294 @@ impl Backtester {
         Ok(uuid)
     }
 
-    /// Removes a stopped backtest from the internal running backtest list
+    /// Creates a sink that sends the ticks of a backtest to the managed SimBroker with the given UUID, funds the
+    /// SimBroker with the backtest's starting capital, and opens the backtest's initial portfolio on it.
+    fn simbroker_sink(
+        &mut self, simbroker_uuid: Uuid, backtest_uuid: Uuid, definition: &BacktestDefinition
+    ) -> Result<SimBrokerSink, String> {
+        let mut simbrokers = self.simbrokers.lock().unwrap();
+        match simbrokers.get_mut(&simbroker_uuid) {
+            Some(simbroker) => {
+                simbroker.set_backtest_uuid(backtest_uuid);
+                definition.configure_simbroker(&mut **simbroker);
+            },
+            None => return Err(NO_SIMBROKER.clone()),
+        }
+
+        Ok(SimBrokerSink {
+            simbrokers: self.simbrokers.clone(),
+            uuid: simbroker_uuid,
+            symbol: definition.symbol.clone(),
+            initial_portfolio: Some(definition.initial_portfolio.clone()),
+            cs: self.cs.clone(),
+        })
+    }
+
+    /// Replaces the data destination of a running backtest with a managed SimBroker.  Ticks that were waiting for
+    /// the old destination are sent to the SimBroker as well.
+    pub fn attach_simbroker(&mut self, backtest_uuid: &Uuid, simbroker_uuid: Uuid) -> Result<(), String> {
+        let definition = match self.running_backtests.lock().unwrap().get(backtest_uuid) {
+            Some(handle) => handle.definition.clone(),
+            None => return Err(NO_BACKTEST.clone()),
+        };
+        let sink = self.simbroker_sink(simbroker_uuid, *backtest_uuid, &definition)?;
+
+        let mut handles = self.running_backtests.lock().unwrap();
+        let handle = handles.get_mut(backtest_uuid).ok_or_else(|| NO_BACKTEST.clone())?;
+        *handle.sink.lock().unwrap() = Box::new(sink);
+        handle.definition.data_dest = DataDest::SimBroker{uuid: simbroker_uuid};
+        Ok(())
+    }
+
+    /// Pauses every backtest and writes their definitions and positions to `path` so that `load_state` can resume
+    /// them after the Backtester is restarted.  Returns the number of backtests that were saved.
+    pub fn save_state(&mut self, path: &str) -> Result<usize, String> {
+        let was_running: HashMap<Uuid, bool> = self.running_backtests.lock().unwrap().iter()
+            .map(|(uuid, handle)| (*uuid, handle.running.load(Ordering::Relaxed)))
+            .collect();
+        if let Response::Error{status} = self.send_all_backtests_cmd(TickstreamCommand::Pause, "pause") {
+            return Err(status);
+        }
+        self.await_settled_tick_counts();
+
+        let states: Vec<SerializableBacktestHandle> = self.running_backtests.lock().unwrap().iter()
+            .map(|(uuid, handle)| {
+                let mut state = SerializableBacktestHandle::from_handle(handle, *uuid);
+                state.running = was_running.get(uuid).cloned().unwrap_or(false);
+                state.definition.resume_from_tick = Some(state.tick_count as u64);
+                state
+            })
+            .collect();
+
+        let ser = to_string(&states).map_err(|err| format!("Unable to serialize backtests: {:?}", err))?;
+        File::create(path)
+            .and_then(|mut file| file.write_all(ser.as_bytes()))
+            .map_err(|err| format!("Unable to write restart state file {}: {}", path, err))?;
+        Ok(states.len())
+    }
+
+    /// Waits until the tick counts of all backtests stop changing.  Ticks that had already left the data source
+    /// when a backtest was paused still reach its sink, so its position isn't final as soon as it's paused.
+    fn await_settled_tick_counts(&self) {
+        let tick_counts = || -> HashMap<Uuid, usize> {
+            self.running_backtests.lock().unwrap().iter()
+                .map(|(uuid, handle)| (*uuid, handle.tick_count.load(Ordering::Relaxed)))
+                .collect()
+        };
+
+        let mut last_counts = tick_counts();
+        loop {
+            thread::sleep(Duration::from_millis(PAUSE_SETTLE_MS));
+            let counts = tick_counts();
+            if counts == last_counts {
+                return;
+            }
+            last_counts = counts;
+        }
+    }
+
+    /// Restarts the backtests saved by `save_state` under their old UUIDs, skipping the ticks they had already
+    /// processed, and deletes the state file.  Backtests that were running when they were saved are resumed right
+    /// away.  Returns the UUIDs of the backtests that were restored.
+    pub fn load_state(&mut self, path: &str) -> Result<Vec<Uuid>, String> {
+        let mut ser = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut ser))
+            .map_err(|err| format!("Unable to read restart state file {}: {}", path, err))?;
+        let states: Vec<SerializableBacktestHandle> = serde_json::from_str(&ser)
+            .map_err(|err| format!("Unable to parse restart state file {}: {}", path, err))?;
+        // the saved positions are only valid for a single restart
+        fs::remove_file(path).map_err(|err| format!("Unable to remove restart state file {}: {}", path, err))?;
+
+        let mut uuids = Vec::new();
+        for state in states {
+            match self.start_backtest_with_uuid(state.definition, state.uuid) {
+                Ok(uuid) => {
+                    if state.running {
+                        let _ = self.send_backtest_cmd(&uuid, TickstreamCommand::Resume);
+                    }
+                    uuids.push(uuid);
+                },
+                Err(err) => {
+                    let msg = format!("Unable to resume backtest {}: {}", state.uuid.hyphenated(), err);
+                    self.cs.error(None, &msg);
+                },
+            }
+        }
+        Ok(uuids)
+    }
+
+    /// Removes a stopped backtest from the internal running backtest list and flushes the trade log of
+    /// the SimBroker it was driving, if any.
     pub fn remove_backtest(&mut self, uuid: &Uuid) {
         let mut handles = self.running_backtests.lock().unwrap();
-        handles.remove(uuid);
+        self.metrics.remove(uuid);
+        let dest = handles.remove(uuid).map(|handle| handle.definition.data_dest);
+        if let Some(DataDest::SimBroker{uuid: simbroker_uuid}) = dest {
+            if let Some(simbroker) = self.simbrokers.lock().unwrap().get_mut(&simbroker_uuid) {
+                if let Err(err) = simbroker.flush_trade_log() {
+                    self.cs.error(None, &err);
+                }
+            }
+        }
+    }
+
+    /// Computes the statistics of a managed backtest and returns them along with the number of ticks it has
+    /// processed.  Equity is taken from the backtest's SimBroker and converted from the lowest units of its account
+    /// currency.  Backtests that aren't sending their ticks to a SimBroker never trade, so their equity is flat at
+    /// their starting capital or, if they don't set one, the starting balance of their broker settings.
+    fn backtest_stats(&self, uuid: &Uuid) -> Option<(BacktestStats, usize)> {
+        let handles = self.running_backtests.lock().unwrap();
+        let handle = match handles.get(uuid) {
+            Some(handle) => handle,
+            None => return None,
+        };
+        let starting_capital = handle.definition.starting_capital.unwrap_or_else(|| {
+            let settings = &handle.definition.broker_settings;
+            settings.starting_balance as f64 / 10f64.powi(settings.account_currency_decimals as i32)
+        });
+
+        let simbroker_stats = match handle.definition.data_dest {
+            DataDest::SimBroker{uuid: simbroker_uuid} => {
+                let simbrokers = self.simbrokers.lock().unwrap();
+                simbrokers.get(&simbroker_uuid).map(|sim| {
+                    let units = 10f64.powi(sim.settings().account_currency_decimals as i32);
+                    let mut equity_curve: Vec<f64> = sim.equity_curve().iter()
+                        .map(|sample| sample.equity / units)
+                        .collect();
+                    // samples are only taken every so often, so the curve is finished off with the current equity
+                    equity_curve.push(sim.equity().1 / units);
+                    let sim_stats = sim.stats();
+                    BacktestStats::from_equity_curve(sim.starting_equity() / units, &equity_curve)
+                        .with_tick_anomalies(sim_stats.tick_anomalies)
+                        .with_requotes(sim_stats.requotes)
+                        .with_win_rate(sim_stats.winning_trades, sim_stats.closed_trades)
+                })
+            },
+            _ => None,
+        };
+        let stats = simbroker_stats.unwrap_or_else(|| BacktestStats::from_equity_curve(starting_capital, &[]));
+
+        Some((stats, handle.tick_count.load(Ordering::Relaxed)))
+    }
+
+    /// Compares the results of two managed backtests, returning a `BacktestDiff` of B relative to A as JSON.
+    fn diff_backtests(&self, uuid_a: &Uuid, uuid_b: &Uuid) -> Response {
+        let (stats_a, ticks_a) = match self.backtest_stats(uuid_a) {
+            Some(res) => res,
+            None => return Response::Error{status: NO_BACKTEST.clone()},
+        };
+        let (stats_b, ticks_b) = match self.backtest_stats(uuid_b) {
+            Some(res) => res,
+            None => return Response::Error{status: NO_BACKTEST.clone()},
+        };
+
+        match to_string(&BacktestDiff::new(&stats_a, ticks_a, &stats_b, ticks_b)) {
+            Ok(info) => Response::Info{info: info},
+            Err(err) => Response::Error{status: format!("Unable to serialize backtest comparison: {:?}", err)},
+        }
+    }
+
+    /// Looks up a tick in the history of a managed backtest by its index and returns it as JSON.
+    fn inspect_tick(&self, uuid: &Uuid, tick_index: u64) -> Response {
+        let handles = self.running_backtests.lock().unwrap();
+        let (history, has_simbroker) = match handles.get(uuid) {
+            Some(handle) => {
+                let has_simbroker = match handle.definition.data_dest {
+                    DataDest::SimBroker{..} => true,
+                    _ => false,
+                };
+                (handle.history.lock().unwrap(), has_simbroker)
+            },
+            None => return Response::Error{status: NO_BACKTEST.clone()},
+        };
+
+        let tick = match history.get(tick_index) {
+            Some(tick) => *tick,
+            None => {
+                let status = match history.index_range() {
+                    (start, end) if start < end => format!(
+                        "Tick {} isn't in the backtest's history, which holds ticks {} through {}.",
+                        tick_index, start, end - 1
+                    ),
+                    _ => String::from("The backtest's history doesn't hold any ticks yet."),
+                };
+                return Response::Error{status: status};
+            },
+        };
+        let inspected = InspectedTick {
+            tick_index: tick_index,
+            timestamp: tick.timestamp,
+            bid: tick.bid,
+            ask: tick.ask,
+            sma_values: if has_simbroker { history.sma_values(tick_index, &INSPECTED_SMA_PERIODS) } else { None },
+        };
+        match to_string(&inspected) {
+            Ok(info) => Response::Info{info: info},
+            Err(err) => Response::Error{status: format!("Unable to serialize the tick: {:?}", err)},
+        }
+    }
+
+    /// Runs the supplied closure on the SimBroker with the given UUID and returns its serialized result
+    /// as a `Response`.  The SimBroker map stays locked for the duration of the closure, so the operation
+    /// can't interleave with anything else using the SimBroker.
+    fn simbroker_cmd<F>(&mut self, uuid: &Uuid, f: F) -> Response
+        where F: FnOnce(&mut Box<ManagedBroker + Send>) -> serde_json::Result<String>
+    {
+        let mut simbrokers = self.simbrokers.lock().unwrap();
+        match simbrokers.get_mut(uuid) {
+            Some(sim) => match f(sim) {
+                Ok(info) => Response::Info{info: info},
+                Err(err) => Response::Error{status: format!("Unable to serialize SimBroker response: {:?}", err)},
+            },
+            None => Response::Error{status: NO_SIMBROKER.clone()},
+        }
+    }
+
+    /// Resets a managed SimBroker so that it can be used for another backtest.  Brokers that are still the
+    /// destination of a running backtest can't be reset.
+    fn reset_simbroker(&mut self, uuid: &Uuid, settings: Option<HashMap<String, String>>) -> Response {
+        if self.simbroker_attached(uuid) {
+            return Response::Error{status: String::from("That SimBroker has a backtest attached; stop it before resetting.")};
+        }
+
+        let mut simbrokers = self.simbrokers.lock().unwrap();
+        match simbrokers.get_mut(uuid) {
+            Some(sim) => match sim.reset(settings.map(SimBrokerSettings::from_hashmap)) {
+                Ok(_) => Response::Ok,
+                Err(err) => Response::Error{status: format!("Unable to reset the SimBroker: {:?}", err)},
+            },
+            None => Response::Error{status: NO_SIMBROKER.clone()},
+        }
+    }
+
+    /// Benchmarks a managed SimBroker and returns the results as JSON.  Since benchmarking resets the broker, it
+    /// can't be done while a backtest is attached to it.
+    fn bench_simbroker(&mut self, uuid: &Uuid, n_ticks: usize, orders_per_tick: usize) -> Response {
+        if self.simbroker_attached(uuid) {
+            return Response::Error{status: String::from("That SimBroker has a backtest attached; stop it before benchmarking.")};
+        }
+
+        let mut simbrokers = self.simbrokers.lock().unwrap();
+        match simbrokers.get_mut(uuid) {
+            Some(sim) => match sim.bench(n_ticks, orders_per_tick) {
+                Ok(results) => match to_string(&results) {
+                    Ok(info) => Response::Info{info: info},
+                    Err(err) => Response::Error{status: format!("Unable to serialize benchmark results: {:?}", err)},
+                },
+                Err(err) => Response::Error{status: format!("Unable to benchmark the SimBroker: {:?}", err)},
+            },
+            None => Response::Error{status: NO_SIMBROKER.clone()},
+        }
+    }
+
+    /// Returns `true` if a running backtest is sending its ticks to the SimBroker with the given UUID.
+    fn simbroker_attached(&self, uuid: &Uuid) -> bool {
+        let handles = self.running_backtests.lock().unwrap();
+        handles.values().any(|handle| match handle.definition.data_dest {
+            DataDest::SimBroker{uuid: simbroker_uuid} => simbroker_uuid == *uuid,
+            _ => false,
+        })
     }
 
     /// Sends a command to a managed backtest
@@ -321,34 +769,151 @@ impl Backtester {
         if handle.is_none() {
             return Err(());
         }
-        let sender: &mpsc::SyncSender<TickstreamCommand> = &handle.unwrap().handle;
+        let handle = handle.unwrap();
+        match cmd {
+            TickstreamCommand::Pause | TickstreamCommand::Stop => handle.running.store(false, Ordering::Relaxed),
+            TickstreamCommand::Resume => handle.running.store(true, Ordering::Relaxed),
+        }
+        let sender: &mpsc::SyncSender<TickstreamCommand> = &handle.handle;
         sender.send(cmd)
             .expect("The receiver corresponding to the sender in the backtest handle seems to have been dropped.");
 
         Ok(())
     }
+
+    /// Sends a command to every managed backtest, returning an error listing how many of them it couldn't be
+    /// delivered to.  `verb` describes the command in that error.
+    pub fn send_all_backtests_cmd(&mut self, cmd: TickstreamCommand, verb: &str) -> Response {
+        // collect the UUIDs first since `send_backtest_cmd` needs the lock
+        let uuids: Vec<Uuid> = self.running_backtests.lock().unwrap().keys().cloned().collect();
+        let failed = uuids.iter()
+            .filter(|uuid| self.send_backtest_cmd(uuid, cmd.clone()).is_err())
+            .count();
+
+        if failed == 0 {
+            Response::Ok
+        } else {
+            Response::Error{status: format!("{} backtests failed to {}", failed, verb)}
+        }
+    }
+}
+
+/// Creates a `TickGenerator` from a `DataSource` and symbol String with the ticks' timestamps converted to
+/// milliseconds.  Sources that support it only read ticks with timestamps up to `end_time`.
+pub fn resolve_data_source(
+    data_source: &DataSource, symbol: String, start_time: Option<u64>, end_time: Option<u64>
+) -> Box<TickGenerator + Send> {
+    let mut normalizer = TimestampNormalizer::from_conf();
+    if let Some(unit) = data_source.timestamp_unit() {
+        normalizer.explicit = Some(unit);
+    }
+    let reader = open_data_source(data_source, symbol, start_time, end_time);
+    Box::new(NormalizingTickGenerator::new(reader, normalizer))
 }
 
-/// Creates a `TickGenerator` from a `DataSource` and symbol String
-pub fn resolve_data_source(data_source: &DataSource, symbol: String, start_time: Option<u64>) -> Box<TickGenerator> {
+/// Returns a generator of the ticks of the given data source with their timestamps in the source's own unit.
+fn open_data_source(
+    data_source: &DataSource, symbol: String, start_time: Option<u64>, end_time: Option<u64>
+) -> Box<TickGenerator + Send> {
     match *data_source {
         DataSource::Flatfile => {
             Box::new(FlatfileReader{
                 symbol: symbol.clone(),
                 start_time: start_time,
-            }) as Box<TickGenerator>
+                path: None,
+            }) as Box<TickGenerator + Send>
+        },
+        DataSource::FlatfileStore{ref root} => {
+            Box::new(FlatfileStore::new(root.clone()).reader(symbol, start_time))
         },
         DataSource::RedisChannel{ref host, ref channel} => {
             Box::new(
                 RedisReader::new(symbol.clone(), host.clone(), channel.clone())
-            ) as Box<TickGenerator>
+            ) as Box<TickGenerator + Send>
         },
         DataSource::Random => {
-            Box::new(RandomReader {}) as Box<TickGenerator>
+            Box::new(RandomReader {}) as Box<TickGenerator + Send>
         },
         DataSource::Postgres => {
-            Box::new(PostgresReader {symbol: symbol, start_time: start_time} )
+            Box::new(PostgresReader::new(symbol, start_time))
+        },
+        DataSource::TimescaleDB{ref connection_str, ref hypertable, ref symbol} => {
+            Box::new(TimescaleReader::new(
+                connection_str.clone(), hypertable.clone(), symbol.clone(), start_time, end_time
+            ))
+        },
+        DataSource::Skipped{ref inner, skip_n} => {
+            Box::new(SkippingTickGenerator::new(open_data_source(inner, symbol, start_time, end_time), skip_n))
+        },
+        DataSource::IEXCloud{ref symbol, ref api_key, ref start, ref end} => {
+            Box::new(IEXCloudReader::new(
+                symbol.clone(), api_key.clone(), start.clone(), end.clone(), start_time, end_time
+            ))
         },
+        DataSource::WithTimestampUnit{ref inner, ..} => open_data_source(inner, symbol, start_time, end_time),
+    }
+}
+
+/// Starts a thread that sends ticks to `dst` as they're received through the returned `SyncSender`, which holds up
+/// to `capacity` ticks that the sink hasn't gotten to yet.  The thread exits once the `SyncSender` is dropped.  The
+/// sink is locked for each tick, so replacing it takes effect starting with the next one.
+fn spawn_sink_thread(dst: Arc<Mutex<Box<TickSink + Send>>>, capacity: usize) -> mpsc::SyncSender<Tick> {
+    let (sink_tx, sink_rx) = mpsc::sync_channel::<Tick>(capacity);
+    thread::spawn(move || {
+        for t in sink_rx.iter() {
+            dst.lock().unwrap().tick(t);
+        }
+    });
+
+    sink_tx
+}
+
+/// Feeds each tick it receives to a managed SimBroker as a tick of the backtest's symbol, so that it fills pending
+/// orders, triggers stop losses and take profits, and advances the broker's clock and equity curve.  Ticks are
+/// dropped if the SimBroker no longer exists.
+struct SimBrokerSink {
+    simbrokers: Arc<Mutex<HashMap<Uuid, Box<ManagedBroker + Send>>>>,
+    uuid: Uuid,
+    symbol: String,
+    /// The backtest's initial portfolio until it's opened along with the first tick
+    initial_portfolio: Option<HashMap<String, f64>>,
+    cs: CommandServer,
+}
+
+impl SimBrokerSink {
+    /// Opens the positions of the initial portfolio.  Those in the backtest's symbol are entered at the mid price
+    /// of its first tick and those in other symbols at the mid price that the SimBroker has for them.
+    fn preload_portfolio(
+        &mut self, simbroker: &mut Box<ManagedBroker + Send>, portfolio: HashMap<String, f64>, t: &Tick
+    ) {
+        for (symbol, qty) in portfolio {
+            let entry_price = if symbol == self.symbol {
+                Some(t.mid())
+            } else {
+                simbroker.get_price(&symbol).map(|(bid, ask)| (bid + ask) / 2)
+            };
+            let res = match entry_price {
+                Some(entry_price) => simbroker.preload_position(&symbol, qty, entry_price),
+                None => Err(BrokerError::NoSuchSymbol),
+            };
+            if let Err(err) = res {
+                self.cs.error(None, &format!("Unable to open the initial position in {}: {:?}", symbol, err));
+            }
+        }
+    }
+}
+
+impl TickSink for SimBrokerSink {
+    fn tick(&mut self, t: Tick) {
+        let simbrokers = self.simbrokers.clone();
+        if let Some(simbroker) = simbrokers.lock().unwrap().get_mut(&self.uuid) {
+            if let Err(err) = simbroker.push_tick(self.symbol.clone(), t, false, SIMBROKER_DECIMAL_PRECISION) {
+                self.cs.error(None, &format!("Unable to send tick to SimBroker {}: {:?}", self.uuid, err));
+            }
+            if let Some(portfolio) = self.initial_portfolio.take() {
+                self.preload_portfolio(simbroker, portfolio, &t);
+            }
+        }
     }
 }
 
@@ -364,23 +929,131 @@ fn check_early_exit (
     false
 }
 
+/// Definition of a backtest of random `TEST` ticks with no destination.  Tests override the fields they need with
+/// struct update syntax.
+#[cfg(test)]
+fn test_definition() -> BacktestDefinition {
+    BacktestDefinition {
+        start_time: None,
+        max_tick_n: None,
+        max_timestamp: None,
+        symbol: "TEST".to_string(),
+        backtest_type: BacktestType::Fast{delay_ms: 0},
+        data_source: DataSource::Random,
+        data_dest: DataDest::Null,
+        broker_settings: SimBrokerSettings::default(),
+        starting_capital: None,
+        max_open_positions: None,
+        resume_from_tick: None,
+        data_dest_buffer: DEFAULT_DATA_DEST_BUFFER,
+        initial_portfolio: HashMap::new(),
+    }
+}
+
+/// Returns the UUID of the first account of the SimBroker.
+#[cfg(test)]
+fn first_account(sim: &mut Box<ManagedBroker + Send>) -> Uuid {
+    match sim.execute(BrokerAction::ListAccounts).wait().unwrap() {
+        Ok(BrokerMessage::AccountListing{accounts}) => accounts[0].uuid,
+        res => panic!("Unexpected response to account listing: {:?}", res),
+    }
+}
+
+/// Runs a backtest of `ORDR` ticks with the given `(timestamp, bid)` pairs, asks 2 above the bids, to a new SimBroker
+/// funded with $1000.  Before the backtest is resumed, a long position of `size` units is ordered at 1001 with a stop
+/// loss at 901.  Returns the UUID of the backtest once its last tick has reached the SimBroker.
+#[cfg(test)]
+fn run_trading_backtest(bt: &mut Backtester, ticks: &[(u64, usize)], size: usize) -> Uuid {
+    let root = env::temp_dir().join(format!("trading_backtest_{}", Uuid::new_v4().simple()));
+    fs::create_dir_all(root.join("ORDR")).unwrap();
+    let mut file = File::create(root.join("ORDR").join("ticks.csv")).unwrap();
+    for &(timestamp, bid) in ticks {
+        writeln!(file, "{}, {}, {}", timestamp, bid, bid + 2).unwrap();
+    }
+
+    let sim_uuid = bt.init_simbroker(HashMap::new());
+    let definition = BacktestDefinition {
+        symbol: "ORDR".to_string(),
+        data_source: DataSource::FlatfileStore{root: root.to_str().unwrap().to_string()},
+        data_dest: DataDest::SimBroker{uuid: sim_uuid},
+        starting_capital: Some(1000.),
+        ..test_definition()
+    };
+    let uuid = bt.start_backtest(definition).unwrap();
+    {
+        let mut simbrokers = bt.simbrokers.lock().unwrap();
+        let sim = simbrokers.get_mut(&sim_uuid).unwrap();
+        sim.oneshot_price_set(String::from("ORDR"), (999, 1001), false, 4).unwrap();
+        let account_uuid = first_account(sim);
+        match sim.submit_order(account_uuid, String::from("ORDR"), true, size, 1001, Some(901), None).wait().unwrap() {
+            Ok(_) => (),
+            res => panic!("Unexpected response to order: {:?}", res),
+        }
+    }
+    bt.send_backtest_cmd(&uuid, TickstreamCommand::Resume).unwrap();
+
+    let last_bid = ticks[ticks.len() - 1].1;
+    let symbol = String::from("ORDR");
+    let started = Instant::now();
+    while bt.simbrokers.lock().unwrap().get(&sim_uuid).unwrap().get_price(&symbol) != Some((last_bid, last_bid + 2)) {
+        assert!(started.elapsed() < Duration::from_secs(5), "The backtest's ticks never reached the SimBroker");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+    uuid
+}
+
+/// The tickstream can get up to `data_dest_buffer` ticks ahead of a sink that isn't consuming them.
+#[test]
+fn data_dest_buffering() {
+    /// Doesn't accept any ticks until its gate is opened
+    struct GatedSink {
+        gate: mpsc::Receiver<()>,
+        received: Arc<AtomicUsize>,
+    }
+
+    impl TickSink for GatedSink {
+        fn tick(&mut self, _: Tick) {
+            if self.received.load(Ordering::SeqCst) == 0 {
+                self.gate.recv().unwrap();
+            }
+            self.received.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let (gate_tx, gate_rx) = mpsc::channel();
+    let received = Arc::new(AtomicUsize::new(0));
+    let sink = GatedSink {gate: gate_rx, received: received.clone()};
+    let sink: Box<TickSink + Send> = Box::new(sink);
+    let sink_tx = spawn_sink_thread(Arc::new(Mutex::new(sink)), 5);
+
+    for i in 0..5 {
+        sink_tx.try_send(Tick {timestamp: i, bid: 100, ask: 102}).unwrap();
+    }
+    assert_eq!(received.load(Ordering::SeqCst), 0);
+
+    gate_tx.send(()).unwrap();
+    drop(sink_tx);
+    let started = Instant::now();
+    while received.load(Ordering::SeqCst) < 5 && started.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(received.load(Ordering::SeqCst), 5);
+}
+
 #[test]
 fn backtest_n_early_exit() {
     let rx = tickgrinder_util::transport::redis::sub_channel(CONF.redis_host, "test1_ii");
 
     let mut bt = Backtester::new(Uuid::new_v4());
     let definition = BacktestDefinition {
-        start_time: None,
         max_tick_n: Some(10),
-        max_timestamp: None,
-        symbol: "TEST".to_string(),
-        backtest_type: BacktestType::Fast{delay_ms: 0},
-        data_source: DataSource::Random,
         data_dest: DataDest::RedisChannel{
             host: CONF.redis_host.to_string(),
             channel: "test1_ii".to_string()
         },
-        broker_settings: SimBrokerSettings::default(),
+        ..test_definition()
     };
 
     let uuid = bt.start_backtest(definition).unwrap();
@@ -396,17 +1069,12 @@ fn backtest_timestamp_early_exit() {
 
     let mut bt = Backtester::new(Uuid::new_v4());
     let definition = BacktestDefinition {
-        start_time: None,
-        max_tick_n: None,
         max_timestamp: Some(8),
-        symbol: "TEST".to_string(),
-        backtest_type: BacktestType::Fast{delay_ms: 0},
-        data_source: DataSource::Random,
         data_dest: DataDest::RedisChannel{
             host: CONF.redis_host.to_string(),
             channel: "test2_ii".to_string()
         },
-        broker_settings: SimBrokerSettings::default(),
+        ..test_definition()
     };
 
     let uuid = bt.start_backtest(definition)
@@ -416,3 +1084,885 @@ fn backtest_timestamp_early_exit() {
     let res = rx.wait().take(8).collect::<Vec<_>>();
     assert_eq!(res.len(), 8);
 }
+
+/// Backtests reading from a `FlatfileStore` read the file of their own symbol.
+#[test]
+fn flatfile_store_backtests() {
+    let root = env::temp_dir().join(format!("flatfile_store_{}", Uuid::new_v4().simple()));
+    for &(symbol, price) in &[("EURUSD", 100), ("USDJPY", 200)] {
+        fs::create_dir_all(root.join(symbol)).unwrap();
+        let mut file = File::create(root.join(symbol).join("ticks.csv")).unwrap();
+        for timestamp in 1..6 {
+            writeln!(file, "{}, {}, {}", timestamp, price + timestamp, price + timestamp + 2).unwrap();
+        }
+    }
+
+    let mut bt = Backtester::new(Uuid::new_v4());
+    for &(symbol, price) in &[("EURUSD", 100), ("USDJPY", 200)] {
+        let channel = format!("flatfile_store_{}", Uuid::new_v4().simple());
+        let rx = tickgrinder_util::transport::redis::sub_channel(CONF.redis_host, &channel);
+        let definition = BacktestDefinition {
+            symbol: symbol.to_string(),
+            data_source: DataSource::FlatfileStore{root: root.to_str().unwrap().to_string()},
+            data_dest: DataDest::RedisChannel{host: CONF.redis_host.to_string(), channel: channel},
+            ..test_definition()
+        };
+
+        let uuid = bt.start_backtest(definition).unwrap();
+        bt.send_backtest_cmd(&uuid, TickstreamCommand::Resume).unwrap();
+        let bids: Vec<usize> = rx.wait().take(5)
+            .map(|msg| tickgrinder_util::trading::tick::SymbolTick::from_json_string(msg.unwrap()).bid)
+            .collect();
+        assert_eq!(bids, (1..6).map(|timestamp| price + timestamp).collect::<Vec<_>>());
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+/// Timestamps in seconds are read as milliseconds, ticks in another unit than the ones before them are dropped, and
+/// a source's explicit unit is used instead of the detected one.
+#[test]
+fn data_source_timestamp_units() {
+    let root = env::temp_dir().join(format!("flatfile_units_{}", Uuid::new_v4().simple()));
+    fs::create_dir_all(root.join("EURUSD")).unwrap();
+    let mut file = File::create(root.join("EURUSD").join("ticks.csv")).unwrap();
+    for timestamp in &[1476650327u64, 1476650328, 1476650329000, 1476650330] {
+        writeln!(file, "{}, {}, {}", timestamp, 100, 102).unwrap();
+    }
+
+    let read = |source: &DataSource| -> Vec<u64> {
+        resolve_data_source(source, "EURUSD".to_string(), None, None)
+            .get_raw()
+            .unwrap()
+            .wait()
+            .map(|t| t.unwrap().timestamp)
+            .collect()
+    };
+    let store = DataSource::FlatfileStore{root: root.to_str().unwrap().to_string()};
+    assert_eq!(read(&store), vec![1476650327000, 1476650328000, 1476650330000]);
+
+    let as_micros = DataSource::WithTimestampUnit{inner: Box::new(store), unit: TimestampUnit::Microseconds};
+    assert_eq!(read(&as_micros), vec![1476650, 1476650, 1476650329, 1476650]);
+    let skipped = DataSource::Skipped{inner: Box::new(as_micros), skip_n: 2};
+    assert_eq!(read(&skipped), vec![1476650329, 1476650]);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn tick_count_backtest_rate_validation() {
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let mut definition = BacktestDefinition {
+        max_tick_n: Some(1),
+        backtest_type: BacktestType::TickCount{ticks_per_second: 0.},
+        ..test_definition()
+    };
+    assert!(bt.start_backtest(definition.clone()).is_err());
+    definition.backtest_type = BacktestType::TickCount{ticks_per_second: -10.};
+    assert!(bt.start_backtest(definition.clone()).is_err());
+    assert!(bt.running_backtests.lock().unwrap().is_empty());
+
+    definition.backtest_type = BacktestType::TickCount{ticks_per_second: 1000.};
+    assert!(bt.start_backtest(definition).is_ok());
+}
+
+/// A SimBroker attached to a running backtest receives its ticks from then on.
+#[test]
+fn simbroker_attachment() {
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let sim_uuid = bt.init_simbroker(HashMap::new());
+    let definition = BacktestDefinition {
+        symbol: "ATCH".to_string(),
+        backtest_type: BacktestType::Fast{delay_ms: 1},
+        ..test_definition()
+    };
+    let uuid = bt.start_backtest(definition).unwrap();
+    bt.send_backtest_cmd(&uuid, TickstreamCommand::Resume).unwrap();
+
+    let res = bt.handle_command(Command::AttachSimbroker{backtest_uuid: Uuid::new_v4(), simbroker_uuid: sim_uuid});
+    assert_eq!(res, Some(Response::Error{status: NO_BACKTEST.clone()}));
+    let res = bt.handle_command(Command::AttachSimbroker{backtest_uuid: uuid, simbroker_uuid: Uuid::new_v4()});
+    assert_eq!(res, Some(Response::Error{status: NO_SIMBROKER.clone()}));
+    assert!(!bt.simbroker_attached(&sim_uuid));
+
+    let res = bt.handle_command(Command::AttachSimbroker{backtest_uuid: uuid, simbroker_uuid: sim_uuid});
+    assert_eq!(res, Some(Response::Ok));
+    assert!(bt.simbroker_attached(&sim_uuid));
+
+    let symbol = String::from("ATCH");
+    let started = Instant::now();
+    let mut price = None;
+    while price.is_none() && started.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+        price = bt.simbrokers.lock().unwrap().get(&sim_uuid).unwrap().get_price(&symbol);
+    }
+    assert!(price.is_some());
+
+    let res = bt.handle_command(Command::StopBacktest{uuid: uuid});
+    assert_eq!(res, Some(Response::Ok));
+}
+
+/// The initial portfolio is opened on the backtest's SimBroker at the first tick's mid price and is marked to market
+/// by the ticks that follow.  Positions in symbols the SimBroker has no price for are left out.
+#[test]
+fn initial_portfolio() {
+    let root = env::temp_dir().join(format!("initial_portfolio_{}", Uuid::new_v4().simple()));
+    fs::create_dir_all(root.join("EURUSD")).unwrap();
+    let mut file = File::create(root.join("EURUSD").join("ticks.csv")).unwrap();
+    writeln!(file, "1, 11000, 11002").unwrap();
+    writeln!(file, "2, 10950, 10952").unwrap();
+
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let sim_uuid = bt.init_simbroker(HashMap::new());
+    let mut portfolio = HashMap::new();
+    portfolio.insert(String::from("EURUSD"), 100.);
+    portfolio.insert(String::from("NOPRICE"), -50.);
+    let definition = BacktestDefinition {
+        symbol: "EURUSD".to_string(),
+        data_source: DataSource::FlatfileStore{root: root.to_str().unwrap().to_string()},
+        data_dest: DataDest::SimBroker{uuid: sim_uuid},
+        initial_portfolio: portfolio,
+        ..test_definition()
+    };
+    let uuid = bt.start_backtest(definition).unwrap();
+    bt.send_backtest_cmd(&uuid, TickstreamCommand::Resume).unwrap();
+
+    let symbol = String::from("EURUSD");
+    let started = Instant::now();
+    let mut price = None;
+    while price != Some((10950, 10952)) && started.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+        price = bt.simbrokers.lock().unwrap().get(&sim_uuid).unwrap().get_price(&symbol);
+    }
+    assert_eq!(price, Some((10950, 10952)));
+
+    let stats = bt.simbrokers.lock().unwrap().get(&sim_uuid).unwrap().stats();
+    // entered at 11001 and marked at the bid of 10950
+    assert_eq!(stats.unrealized_pnl, -5100.);
+    assert_eq!(stats.positions.get("EURUSD"), Some(&100.));
+    assert_eq!(stats.positions.get("NOPRICE"), None);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+/// Orders resting on a backtest's SimBroker are filled by the backtest's ticks and marked to market by the ones after.
+#[test]
+fn simbroker_sink_fills_resting_orders() {
+    let root = env::temp_dir().join(format!("resting_orders_{}", Uuid::new_v4().simple()));
+    fs::create_dir_all(root.join("ORDR")).unwrap();
+    let mut file = File::create(root.join("ORDR").join("ticks.csv")).unwrap();
+    for &(timestamp, bid) in &[(1, 1000), (2, 990), (3, 980), (4, 995)] {
+        writeln!(file, "{}, {}, {}", timestamp, bid, bid + 2).unwrap();
+    }
+
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let sim_uuid = bt.init_simbroker(HashMap::new());
+    let order_uuid = {
+        let mut simbrokers = bt.simbrokers.lock().unwrap();
+        let sim = simbrokers.get_mut(&sim_uuid).unwrap();
+        sim.oneshot_price_set(String::from("ORDR"), (999, 1001), false, 4).unwrap();
+        let account_uuid = first_account(sim);
+        match sim.submit_order(account_uuid, String::from("ORDR"), true, 10, 985, None, None).wait().unwrap() {
+            Ok(BrokerMessage::OrderPlaced{order_id, ..}) => order_id,
+            res => panic!("Unexpected response to limit order: {:?}", res),
+        }
+    };
+    let definition = BacktestDefinition {
+        symbol: "ORDR".to_string(),
+        data_source: DataSource::FlatfileStore{root: root.to_str().unwrap().to_string()},
+        data_dest: DataDest::SimBroker{uuid: sim_uuid},
+        ..test_definition()
+    };
+    let uuid = bt.start_backtest(definition).unwrap();
+    bt.send_backtest_cmd(&uuid, TickstreamCommand::Resume).unwrap();
+
+    let symbol = String::from("ORDR");
+    let started = Instant::now();
+    while bt.simbrokers.lock().unwrap().get(&sim_uuid).unwrap().get_price(&symbol) != Some((995, 997)) {
+        assert!(started.elapsed() < Duration::from_secs(5), "The backtest's ticks never reached the SimBroker");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let simbrokers = bt.simbrokers.lock().unwrap();
+    let sim = simbrokers.get(&sim_uuid).unwrap();
+    assert!(sim.pending_orders().is_empty());
+    let positions = sim.position_blotter();
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].uuid, order_uuid);
+    // filled at the ask of the third tick, the first one at or below the limit price, and marked at the last bid
+    assert_eq!(positions[0].price, Some(982));
+    assert_eq!(positions[0].unrealized_pnl, Some(130.));
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+/// Returns are computed from the equity of the backtest's SimBroker relative to the starting capital it was funded
+/// with, in units of the account currency.
+#[test]
+fn backtest_percentage_return() {
+    let mut bt = Backtester::new(Uuid::new_v4());
+    // bought at 1001 and stopped out at 901, losing exactly $100
+    let uuid = run_trading_backtest(&mut bt, &[(1, 999), (2, 950), (3, 901)], 100);
+
+    let (stats, ticks_processed) = bt.backtest_stats(&uuid).unwrap();
+    assert_eq!(ticks_processed, 3);
+    assert_eq!(stats.starting_capital, 1000.);
+    assert_eq!(stats.final_equity, 900.);
+    assert!((stats.total_return_pct - -0.1).abs() < 1e-12);
+    assert!((stats.max_drawdown_pct - 0.1).abs() < 1e-12);
+    assert_eq!(stats.win_rate, 0.);
+}
+
+/// Messages published from the SimBroker of one backtest reach the SimBroker of another that's subscribed to the
+/// same topic.
+#[test]
+fn strategy_bus_between_backtests() {
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let mut settings = HashMap::new();
+    settings.insert(String::from("bus_topics"), String::from("[\"signals\"]"));
+    let sim_a = bt.init_simbroker(HashMap::new());
+    let sim_b = bt.init_simbroker(settings);
+
+    let mut backtests = Vec::new();
+    for &(symbol, sim_uuid) in &[("BUSA", sim_a), ("BUSB", sim_b)] {
+        let definition = BacktestDefinition {
+            symbol: symbol.to_string(),
+            backtest_type: BacktestType::Fast{delay_ms: 1},
+            data_dest: DataDest::SimBroker{uuid: sim_uuid},
+            ..test_definition()
+        };
+        backtests.push(bt.start_backtest(definition).unwrap());
+    }
+
+    {
+        let mut simbrokers = bt.simbrokers.lock().unwrap();
+        let res = simbrokers.get(&sim_a).unwrap().publish_message("signals", String::from("long EURUSD"));
+        assert_eq!(res, Ok(1));
+        // the broker of the first backtest isn't subscribed to anything
+        assert_eq!(simbrokers.get_mut(&sim_a).unwrap().take_messages(), Vec::new());
+        let expected = vec![(String::from("signals"), String::from("long EURUSD"))];
+        assert_eq!(simbrokers.get_mut(&sim_b).unwrap().take_messages(), expected);
+        assert_eq!(simbrokers.get_mut(&sim_b).unwrap().take_messages(), Vec::new());
+    }
+
+    for uuid in backtests {
+        assert_eq!(bt.handle_command(Command::StopBacktest{uuid: uuid}), Some(Response::Ok));
+    }
+}
+
+/// Clients connected to a backtest's WebSocket destination receive its ticks as JSON.
+#[test]
+fn websocket_data_dest() {
+    use std::net::{TcpListener, TcpStream};
+    use tungstenite::Message;
+    use tungstenite::client::client;
+    use url::Url;
+    use tickgrinder_util::trading::tick::SymbolTick;
+
+    // find a free port to serve on
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let definition = BacktestDefinition {
+        symbol: "WSKT".to_string(),
+        backtest_type: BacktestType::Fast{delay_ms: 1},
+        data_dest: DataDest::WebSocket{bind_addr: addr.to_string()},
+        ..test_definition()
+    };
+    let uuid = bt.start_backtest(definition).unwrap();
+
+    let url = Url::parse(&format!("ws://{}/", addr)).unwrap();
+    let (mut ws, _) = client(url, TcpStream::connect(addr).unwrap()).ok().unwrap();
+    bt.send_backtest_cmd(&uuid, TickstreamCommand::Resume).unwrap();
+    for _ in 0..10 {
+        let t: SymbolTick = match ws.read_message().unwrap() {
+            Message::Text(json) => serde_json::from_str(&json).unwrap(),
+            msg => panic!("Unexpected WebSocket message: {:?}", msg),
+        };
+        assert_eq!(t.symbol, "WSKT");
+    }
+
+    // the address is freed once the backtest is over
+    let res = bt.handle_command(Command::StopBacktest{uuid: uuid});
+    assert_eq!(res, Some(Response::Ok));
+    drop(ws);
+    let started = Instant::now();
+    while TcpListener::bind(addr).is_err() {
+        assert!(started.elapsed() < Duration::from_secs(5), "The WebSocket server never released its address");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn simbroker_order_commands() {
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let sim_uuid = bt.init_simbroker(HashMap::new());
+
+    let res = bt.handle_command(Command::ListSimbrokerOrders{uuid: sim_uuid});
+    assert_eq!(res, Some(Response::Info{info: String::from("[]")}));
+
+    let res = bt.handle_command(Command::CancelSimbrokerOrder{uuid: sim_uuid, order_uuid: Uuid::new_v4()});
+    let expected = to_string(&OrderUpdateResult::NotFound).unwrap();
+    assert_eq!(res, Some(Response::Info{info: expected}));
+
+    let res = bt.handle_command(Command::CancelSimbrokerOrder{uuid: Uuid::new_v4(), order_uuid: Uuid::new_v4()});
+    assert_eq!(res, Some(Response::Error{status: NO_SIMBROKER.clone()}));
+}
+
+#[test]
+fn simbroker_blotter_commands() {
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let sim_uuid = bt.init_simbroker(HashMap::new());
+
+    let (pos_uuid, order_uuid) = {
+        let mut simbrokers = bt.simbrokers.lock().unwrap();
+        let sim = simbrokers.get_mut(&sim_uuid).unwrap();
+        sim.oneshot_price_set(String::from("ORDR"), (999, 1001), false, 4).unwrap();
+        let account_uuid = first_account(sim);
+
+        let pos_uuid = match sim.open_position(account_uuid, String::from("ORDR"), true, 10, Some(990), None).wait().unwrap() {
+            Ok(BrokerMessage::PositionOpened{position_id, ..}) => position_id,
+            res => panic!("Unexpected response to market order: {:?}", res),
+        };
+        let order_uuid = match sim.submit_order(account_uuid, String::from("ORDR"), false, 5, 1010, None, Some(1000)).wait().unwrap() {
+            Ok(BrokerMessage::OrderPlaced{order_id, ..}) => order_id,
+            res => panic!("Unexpected response to limit order: {:?}", res),
+        };
+        (pos_uuid, order_uuid)
+    };
+
+    let positions: Vec<BlotterEntry> = match bt.handle_command(Command::SimbrokerPositions{uuid: sim_uuid}) {
+        Some(Response::Info{info}) => serde_json::from_str(&info).unwrap(),
+        res => panic!("Unexpected response to position query: {:?}", res),
+    };
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].uuid, pos_uuid);
+    assert_eq!(positions[0].symbol, "ORDR");
+    assert!(positions[0].long);
+    assert_eq!((positions[0].size, positions[0].price, positions[0].stop), (10, Some(1001), Some(990)));
+    // bought at 1001 and the bid is 999
+    assert_eq!(positions[0].unrealized_pnl, Some(-20.));
+
+    let orders: Vec<BlotterEntry> = match bt.handle_command(Command::SimbrokerOrders{uuid: sim_uuid}) {
+        Some(Response::Info{info}) => serde_json::from_str(&info).unwrap(),
+        res => panic!("Unexpected response to order query: {:?}", res),
+    };
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].uuid, order_uuid);
+    assert!(!orders[0].long);
+    assert_eq!((orders[0].size, orders[0].price, orders[0].take_profit), (5, Some(1010), Some(1000)));
+    assert_eq!(orders[0].unrealized_pnl, None);
+
+    let res = bt.handle_command(Command::SimbrokerPositions{uuid: Uuid::new_v4()});
+    assert_eq!(res, Some(Response::Error{status: NO_SIMBROKER.clone()}));
+}
+
+#[test]
+fn cancel_all_orders_command() {
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let sim_uuid = bt.init_simbroker(HashMap::new());
+
+    {
+        let mut simbrokers = bt.simbrokers.lock().unwrap();
+        let sim = simbrokers.get_mut(&sim_uuid).unwrap();
+        sim.oneshot_price_set(String::from("HALT"), (999, 1001), false, 4).unwrap();
+        let account_uuid = first_account(sim);
+        for entry_price in 990..995 {
+            let res = sim.submit_order(account_uuid, String::from("HALT"), true, 5, entry_price, None, None).wait();
+            match res.unwrap() {
+                Ok(BrokerMessage::OrderPlaced{..}) => (),
+                res => panic!("Unexpected response to limit order: {:?}", res),
+            }
+        }
+        assert_eq!(sim.pending_orders().len(), 5);
+    }
+
+    let res = bt.handle_command(Command::CancelAllOrders{simbroker_uuid: sim_uuid});
+    assert_eq!(res, Some(Response::Info{info: String::from("5")}));
+    assert_eq!(bt.simbrokers.lock().unwrap().get(&sim_uuid).unwrap().pending_orders().len(), 0);
+
+    let res = bt.handle_command(Command::CancelAllOrders{simbroker_uuid: Uuid::new_v4()});
+    assert_eq!(res, Some(Response::Error{status: NO_SIMBROKER.clone()}));
+}
+
+#[test]
+fn simbroker_stats_command() {
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let sim_uuid = bt.init_simbroker(HashMap::new());
+
+    {
+        let mut simbrokers = bt.simbrokers.lock().unwrap();
+        let sim = simbrokers.get_mut(&sim_uuid).unwrap();
+        sim.oneshot_price_set(String::from("ORDR"), (999, 1001), false, 4).unwrap();
+        let account_uuid = first_account(sim);
+
+        let pos_uuid = match sim.open_position(account_uuid, String::from("ORDR"), true, 10, None, None).wait().unwrap() {
+            Ok(BrokerMessage::PositionOpened{position_id, ..}) => position_id,
+            res => panic!("Unexpected response to market order: {:?}", res),
+        };
+        sim.close_position(account_uuid, pos_uuid, 4).wait().unwrap().unwrap();
+        sim.submit_order(account_uuid, String::from("ORDR"), false, 5, 1010, None, None).wait().unwrap().unwrap();
+    }
+
+    let stats: SimBrokerStats = match bt.handle_command(Command::GetSimbrokerStats{uuid: sim_uuid}) {
+        Some(Response::Info{info}) => serde_json::from_str(&info).unwrap(),
+        res => panic!("Unexpected response to stats query: {:?}", res),
+    };
+    assert_eq!(stats.total_fills, 1);
+    assert_eq!(stats.total_volume, 10.);
+    // bought at 1001 and closed 4 units at the bid of 999
+    assert_eq!(stats.realized_pnl, -8.);
+    assert_eq!(stats.unrealized_pnl, -12.);
+    assert_eq!(stats.open_orders, 1);
+    assert_eq!(stats.positions.get("ORDR"), Some(&6.));
+
+    let res = bt.handle_command(Command::GetSimbrokerStats{uuid: Uuid::new_v4()});
+    assert_eq!(res, Some(Response::Error{status: NO_SIMBROKER.clone()}));
+}
+
+#[test]
+fn simbroker_bench_command() {
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let sim_uuid = bt.init_simbroker(HashMap::new());
+
+    let cmd = Command::BenchSimbroker{uuid: sim_uuid, n_ticks: 100, orders_per_tick: 3};
+    let results: BenchResults = match bt.handle_command(cmd) {
+        Some(Response::Info{info}) => serde_json::from_str(&info).unwrap(),
+        res => panic!("Unexpected response to benchmark command: {:?}", res),
+    };
+    assert_eq!((results.ticks, results.orders_placed), (100, 300));
+    assert!(results.ticks_per_sec > 0.);
+    assert!(bt.simbrokers.lock().unwrap().get(&sim_uuid).unwrap().pending_orders().is_empty());
+
+    let res = bt.handle_command(Command::BenchSimbroker{uuid: Uuid::new_v4(), n_ticks: 100, orders_per_tick: 3});
+    assert_eq!(res, Some(Response::Error{status: NO_SIMBROKER.clone()}));
+}
+
+/// Runs two backtests against the same SimBroker, resetting it in between, and makes sure that nothing from the
+/// first run is visible in the second.
+#[test]
+fn simbroker_reset_between_backtests() {
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let sim_uuid = bt.init_simbroker(HashMap::new());
+    let starting_balance = SimBrokerSettings::default().starting_balance;
+    let definition = BacktestDefinition {
+        data_dest: DataDest::SimBroker{uuid: sim_uuid},
+        ..test_definition()
+    };
+
+    let run = |bt: &mut Backtester| -> (usize, usize) {
+        let backtest_uuid = bt.start_backtest(definition.clone()).unwrap();
+        let balance = {
+            let mut simbrokers = bt.simbrokers.lock().unwrap();
+            let sim = simbrokers.get_mut(&sim_uuid).unwrap();
+            let account_uuid = first_account(sim);
+            match sim.account_state(account_uuid).wait().unwrap() {
+                Ok(BrokerMessage::Ledger{ledger}) => {
+                    assert_eq!(ledger.buying_power, starting_balance);
+                    assert!(ledger.open_positions.is_empty() && ledger.closed_positions.is_empty());
+                },
+                res => panic!("Unexpected response to ledger query: {:?}", res),
+            }
+            assert!(sim.trade_log().is_empty());
+            assert!(sim.equity_curve().is_empty());
+            // symbols registered during the last run are gone
+            assert!(sim.open_position(account_uuid, String::from("ORDR"), true, 10, None, None).wait().unwrap().is_err());
+
+            sim.oneshot_price_set(String::from("ORDR"), (999, 1001), false, 4).unwrap();
+            let pos_uuid = match sim.open_position(account_uuid, String::from("ORDR"), true, 10, None, None).wait().unwrap() {
+                Ok(BrokerMessage::PositionOpened{position_id, ..}) => position_id,
+                res => panic!("Unexpected response to market order: {:?}", res),
+            };
+            sim.submit_order(account_uuid, String::from("ORDR"), false, 5, 1010, None, None).wait().unwrap().unwrap();
+            sim.close_position(account_uuid, pos_uuid, 10).wait().unwrap().unwrap();
+            assert_eq!(sim.trade_log().len(), 2);
+
+            match sim.account_state(account_uuid).wait().unwrap() {
+                Ok(BrokerMessage::Ledger{ledger}) => ledger.buying_power,
+                res => panic!("Unexpected response to ledger query: {:?}", res),
+            }
+        };
+
+        // the broker can't be reset out from under a running backtest
+        match bt.handle_command(Command::ResetSimbroker{uuid: sim_uuid, settings: None}) {
+            Some(Response::Error{..}) => (),
+            res => panic!("Reset of a SimBroker with an attached backtest wasn't rejected: {:?}", res),
+        }
+        assert_eq!(bt.handle_command(Command::StopBacktest{uuid: backtest_uuid}), Some(Response::Ok));
+        assert_eq!(bt.handle_command(Command::ResetSimbroker{uuid: sim_uuid, settings: None}), Some(Response::Ok));
+
+        let open_orders = bt.simbrokers.lock().unwrap().get(&sim_uuid).unwrap().pending_orders().len();
+        (balance, open_orders)
+    };
+
+    let first = run(&mut bt);
+    let second = run(&mut bt);
+    assert_eq!(first, second);
+    assert!(first.0 < starting_balance);
+    assert_eq!(first.1, 0);
+
+    let res = bt.handle_command(Command::ResetSimbroker{uuid: Uuid::new_v4(), settings: None});
+    assert_eq!(res, Some(Response::Error{status: NO_SIMBROKER.clone()}));
+}
+
+#[test]
+fn per_backtest_metrics() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let definition = BacktestDefinition {
+        max_tick_n: Some(5),
+        ..test_definition()
+    };
+    let uuid1 = bt.start_backtest(definition.clone()).unwrap();
+    let uuid2 = bt.start_backtest(definition).unwrap();
+    bt.send_backtest_cmd(&uuid1, TickstreamCommand::Resume).unwrap();
+
+    let addr = serve_metrics("127.0.0.1:0", bt.metrics.clone(), bt.running_backtests.clone()).unwrap();
+    let scrape = || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).unwrap();
+        res
+    };
+
+    let res = scrape();
+    let tick_count_lines: Vec<&str> = res.lines().filter(|l| l.starts_with("backtests_tick_count{")).collect();
+    assert_eq!(tick_count_lines.len(), 2);
+    for uuid in &[uuid1, uuid2] {
+        let label = format!("uuid=\"{}\"", uuid.hyphenated());
+        assert!(tick_count_lines.iter().any(|l| l.contains(&label)));
+    }
+    assert!(res.contains(&format!("backtests_running{{uuid=\"{}\"}} 1", uuid1.hyphenated())));
+    assert!(res.contains(&format!("backtests_running{{uuid=\"{}\"}} 0", uuid2.hyphenated())));
+
+    // label sets are cleaned up when the backtest is removed
+    bt.remove_backtest(&uuid2);
+    let res = scrape();
+    assert_eq!(res.lines().filter(|l| l.starts_with("backtests_tick_count{")).count(), 1);
+    assert!(!res.contains(&uuid2.hyphenated().to_string()));
+}
+
+#[test]
+fn pause_resume_all_backtests() {
+    use std::time::Duration;
+
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let definition = BacktestDefinition {
+        backtest_type: BacktestType::Fast{delay_ms: 1},
+        ..test_definition()
+    };
+    let uuids = vec![bt.start_backtest(definition.clone()).unwrap(), bt.start_backtest(definition).unwrap()];
+    let tick_counts = |bt: &Backtester| -> Vec<usize> {
+        let handles = bt.running_backtests.lock().unwrap();
+        uuids.iter().map(|uuid| handles.get(uuid).unwrap().tick_count.load(Ordering::Relaxed)).collect()
+    };
+    let all_running = |bt: &Backtester, running: bool| {
+        let handles = bt.running_backtests.lock().unwrap();
+        uuids.iter().all(|uuid| handles.get(uuid).unwrap().running.load(Ordering::Relaxed) == running)
+    };
+
+    assert_eq!(bt.handle_command(Command::ResumeAllBacktests), Some(Response::Ok));
+    assert!(all_running(&bt, true));
+    thread::sleep(Duration::from_millis(100));
+
+    assert_eq!(bt.handle_command(Command::PauseAllBacktests), Some(Response::Ok));
+    assert!(all_running(&bt, false));
+    // let any tick that was in flight when the pause arrived make it through
+    thread::sleep(Duration::from_millis(50));
+    let paused_counts = tick_counts(&bt);
+    assert!(paused_counts.iter().all(|&count| count > 0));
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(tick_counts(&bt), paused_counts);
+
+    assert_eq!(bt.handle_command(Command::ResumeAllBacktests), Some(Response::Ok));
+    assert!(all_running(&bt, true));
+    thread::sleep(Duration::from_millis(100));
+    let resumed_counts = tick_counts(&bt);
+    assert!(resumed_counts.iter().zip(paused_counts.iter()).all(|(resumed, paused)| resumed > paused));
+}
+
+/// Listed backtests include the full definition they were started with.
+#[test]
+fn list_backtests_definitions() {
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let definition = BacktestDefinition {
+        max_timestamp: Some(1000),
+        symbol: "EURUSD".to_string(),
+        backtest_type: BacktestType::TickCount{ticks_per_second: 50.},
+        ..test_definition()
+    };
+    let uuid = bt.start_backtest(definition).unwrap();
+
+    let handles: Vec<SerializableBacktestHandle> = match bt.handle_command(Command::ListBacktests) {
+        Some(Response::Info{info}) => serde_json::from_str(&info).unwrap(),
+        res => panic!("Unexpected response to backtest listing: {:?}", res),
+    };
+    assert_eq!(handles.len(), 1);
+    assert_eq!(handles[0].uuid, uuid);
+    let listed = &handles[0].definition;
+    assert_eq!(listed.symbol, "EURUSD");
+    assert_eq!(listed.max_timestamp, Some(1000));
+    match listed.backtest_type {
+        BacktestType::TickCount{ticks_per_second} => assert_eq!(ticks_per_second, 50.),
+        ref backtest_type => panic!("Unexpected backtest type: {:?}", backtest_type),
+    }
+    match listed.data_source {
+        DataSource::Random => (),
+        ref data_source => panic!("Unexpected data source: {:?}", data_source),
+    }
+    match listed.data_dest {
+        DataDest::Null => (),
+        ref data_dest => panic!("Unexpected data destination: {:?}", data_dest),
+    }
+
+    bt.handle_command(Command::StopBacktest{uuid: uuid});
+}
+
+#[test]
+fn diff_backtests_command() {
+    use std::time::Duration;
+
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let definition = |max_tick_n: usize| BacktestDefinition {
+        max_tick_n: Some(max_tick_n),
+        starting_capital: Some(1000.0),
+        ..test_definition()
+    };
+    // the random data source isn't seeded, so the backtests are told apart by how many ticks they process
+    let uuid_a = bt.start_backtest(definition(20)).unwrap();
+    let uuid_b = bt.start_backtest(definition(10)).unwrap();
+    assert_eq!(bt.handle_command(Command::ResumeAllBacktests), Some(Response::Ok));
+
+    // backtests stay registered after hitting their tick limit, so wait for both of them to get there
+    for _ in 0..100 {
+        let done = {
+            let handles = bt.running_backtests.lock().unwrap();
+            handles.get(&uuid_a).unwrap().tick_count.load(Ordering::Relaxed) == 20 &&
+                handles.get(&uuid_b).unwrap().tick_count.load(Ordering::Relaxed) == 10
+        };
+        if done {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let diff: BacktestDiff = match bt.handle_command(Command::DiffBacktests{uuid_a: uuid_a, uuid_b: uuid_b}) {
+        Some(Response::Info{info}) => serde_json::from_str(&info).unwrap(),
+        res => panic!("Unexpected response to backtest comparison: {:?}", res),
+    };
+    assert_eq!((diff.ticks_processed_a, diff.ticks_processed_b), (20, 10));
+    assert!(diff.ticks_processed_b < diff.ticks_processed_a);
+    // neither backtest traded, so their results are identical
+    assert_eq!(diff.return_delta, 0.);
+    assert_eq!(diff.sharpe_delta, 0.);
+    assert_eq!(diff.drawdown_delta, 0.);
+    assert_eq!(diff.win_rate_delta, 0.);
+
+    let res = bt.handle_command(Command::DiffBacktests{uuid_a: uuid_a, uuid_b: Uuid::new_v4()});
+    assert_eq!(res, Some(Response::Error{status: NO_BACKTEST.clone()}));
+    let res = bt.handle_command(Command::DiffBacktests{uuid_a: Uuid::new_v4(), uuid_b: uuid_b});
+    assert_eq!(res, Some(Response::Error{status: NO_BACKTEST.clone()}));
+}
+
+/// Backtests that trade on their own SimBrokers are compared by their returns on their starting capital.
+#[test]
+fn diff_trading_backtests() {
+    let mut bt = Backtester::new(Uuid::new_v4());
+    // both are stopped out at 901 after buying at 1001, but B's position is half as big
+    let ticks = [(1, 999), (2, 950), (3, 901)];
+    let uuid_a = run_trading_backtest(&mut bt, &ticks, 100);
+    let uuid_b = run_trading_backtest(&mut bt, &ticks, 50);
+
+    let diff: BacktestDiff = match bt.handle_command(Command::DiffBacktests{uuid_a: uuid_a, uuid_b: uuid_b}) {
+        Some(Response::Info{info}) => serde_json::from_str(&info).unwrap(),
+        res => panic!("Unexpected response to backtest comparison: {:?}", res),
+    };
+    assert_eq!((diff.ticks_processed_a, diff.ticks_processed_b), (3, 3));
+    // A lost 10% and B lost 5%
+    assert!((diff.return_delta - 0.05).abs() < 1e-12);
+    assert!((diff.drawdown_delta - -0.05).abs() < 1e-12);
+    assert_eq!(diff.win_rate_delta, 0.);
+}
+
+#[test]
+fn inspect_tick_command() {
+    use std::time::Duration;
+
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let definition = BacktestDefinition {
+        max_tick_n: Some(50),
+        ..test_definition()
+    };
+    let uuid = bt.start_backtest(definition).unwrap();
+    bt.send_backtest_cmd(&uuid, TickstreamCommand::Resume).unwrap();
+    for _ in 0..100 {
+        if bt.running_backtests.lock().unwrap().get(&uuid).unwrap().tick_count.load(Ordering::Relaxed) == 50 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let inspect = |bt: &mut Backtester, tick_index: u64| bt.handle_command(Command::InspectTick{
+        backtest_uuid: uuid, tick_index: tick_index,
+    });
+    let tick: InspectedTick = match inspect(&mut bt, 25) {
+        Some(Response::Info{info}) => serde_json::from_str(&info).unwrap(),
+        res => panic!("Unexpected response to tick inspection: {:?}", res),
+    };
+    assert_eq!(tick.tick_index, 25);
+    // the random source numbers its ticks from 1
+    assert!(tick.timestamp > 0 && tick.timestamp <= 50);
+    assert!(tick.bid > 0 && tick.ask > 0);
+    // there's no SimBroker attached
+    assert_eq!(tick.sma_values, None);
+
+    match inspect(&mut bt, 50) {
+        Some(Response::Error{status}) => assert!(status.contains("0 through 49")),
+        res => panic!("Unexpected response to inspecting a tick that hasn't happened: {:?}", res),
+    }
+    let res = bt.handle_command(Command::InspectTick{backtest_uuid: Uuid::new_v4(), tick_index: 0});
+    assert_eq!(res, Some(Response::Error{status: NO_BACKTEST.clone()}));
+
+    // backtests with a SimBroker attached report the SMAs as of the inspected tick
+    use tickgrinder_util::trading::sma::Sma;
+    let ticks = [(1, 999), (2, 950), (3, 901), (4, 990)];
+    let sim_backtest = run_trading_backtest(&mut bt, &ticks, 100);
+    let tick: InspectedTick = match bt.handle_command(Command::InspectTick{backtest_uuid: sim_backtest, tick_index: 2}) {
+        Some(Response::Info{info}) => serde_json::from_str(&info).unwrap(),
+        res => panic!("Unexpected response to tick inspection: {:?}", res),
+    };
+    assert_eq!((tick.timestamp, tick.bid, tick.ask), (3, 901, 903));
+    let sma_values = tick.sma_values.unwrap();
+    assert_eq!(sma_values.len(), INSPECTED_SMA_PERIODS.len());
+    for &period in INSPECTED_SMA_PERIODS.iter() {
+        // the SMA of the ticks up to and including the inspected one, not the later tick
+        let mut sma = Sma::new(period);
+        let expected = ticks[..3].iter()
+            .map(|&(timestamp, bid)| sma.push_f64(Tick {timestamp: timestamp, bid: bid, ask: bid + 2}).unwrap())
+            .last().unwrap().unwrap();
+        assert_eq!(sma_values[&sma.period_label], expected);
+    }
+    bt.handle_command(Command::StopBacktest{uuid: sim_backtest});
+}
+
+#[test]
+fn skipped_data_source() {
+    use std::time::Duration;
+
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let definition = BacktestDefinition {
+        // the random source numbers its ticks from 1, so this stops it after 10 ticks
+        max_timestamp: Some(10),
+        data_source: DataSource::Skipped{inner: Box::new(DataSource::Random), skip_n: 5},
+        ..test_definition()
+    };
+    let uuid = bt.start_backtest(definition).unwrap();
+    bt.send_backtest_cmd(&uuid, TickstreamCommand::Resume).unwrap();
+    thread::sleep(Duration::from_millis(250));
+
+    let backtests = bt.running_backtests.lock().unwrap();
+    let handle = backtests.get(&uuid).unwrap();
+    assert_eq!(handle.tick_count.load(Ordering::Relaxed), 5);
+    let history = handle.history.lock().unwrap();
+    assert_eq!(history.index_range(), (0, 5));
+    assert_eq!(history.get(0).unwrap().timestamp, 6);
+}
+
+/// Backtests saved before a restart continue from where they were in a new Backtester without repeating or
+/// missing any ticks.
+#[test]
+fn warm_restart() {
+    let path_buf = env::temp_dir().join(format!("backtester_restart_{}.json", Uuid::new_v4().simple()));
+    let path = path_buf.to_str().unwrap();
+
+    let mut bt = Backtester::new(Uuid::new_v4());
+    let definition = BacktestDefinition {
+        // the random source numbers its ticks from 1, so this stops it after 20 ticks
+        max_timestamp: Some(20),
+        backtest_type: BacktestType::Fast{delay_ms: 20},
+        ..test_definition()
+    };
+    let running_uuid = bt.start_backtest(definition.clone()).unwrap();
+    // never resumed, so it should stay paused after the restart
+    let paused_uuid = bt.start_backtest(definition).unwrap();
+    bt.send_backtest_cmd(&running_uuid, TickstreamCommand::Resume).unwrap();
+    thread::sleep(Duration::from_millis(150));
+
+    assert_eq!(bt.save_state(path), Ok(2));
+    let saved_count = bt.running_backtests.lock().unwrap().get(&running_uuid).unwrap()
+        .tick_count.load(Ordering::Relaxed);
+    assert!(saved_count > 0 && saved_count < 20, "{} ticks were processed before the restart", saved_count);
+    // the old Backtester doesn't process any more ticks once its state has been saved
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(
+        bt.running_backtests.lock().unwrap().get(&running_uuid).unwrap().tick_count.load(Ordering::Relaxed),
+        saved_count
+    );
+
+    let mut restarted = Backtester::new(Uuid::new_v4());
+    let uuids = restarted.load_state(path).unwrap();
+    assert_eq!(uuids.len(), 2);
+    assert!(uuids.contains(&running_uuid) && uuids.contains(&paused_uuid));
+    assert!(!Path::new(path).exists());
+    thread::sleep(Duration::from_millis(1000));
+
+    let backtests = restarted.running_backtests.lock().unwrap();
+    let handle = backtests.get(&running_uuid).unwrap();
+    assert_eq!(handle.definition.resume_from_tick, Some(saved_count as u64));
+    assert_eq!(handle.tick_count.load(Ordering::Relaxed), 20);
+    let history = handle.history.lock().unwrap();
+    assert_eq!(history.index_range(), (saved_count as u64, 20));
+    assert_eq!(history.get(saved_count as u64).unwrap().timestamp, saved_count as u64 + 1);
+
+    let paused = backtests.get(&paused_uuid).unwrap();
+    assert!(!paused.running.load(Ordering::Relaxed));
+    assert_eq!(paused.tick_count.load(Ordering::Relaxed), 0);
+}
+
+/// After a `SetLogLevel` of `ERROR`, the debug message logged for a `Ping` doesn't reach the logger until the level
+/// is lowered again.
+#[test]
+fn runtime_log_level() {
+    use std::time::Duration;
+    use tickgrinder_util::transport::command_server::initial_log_level;
+
+    let uuid = Uuid::new_v4();
+    let rx = tickgrinder_util::transport::redis::sub_channel(CONF.redis_host, CONF.redis_log_channel);
+    let (tx, logged) = mpsc::channel();
+    thread::spawn(move || {
+        for raw in rx.wait() {
+            if let Ok(WrappedCommand{cmd: Command::Log{msg}, ..}) = WrappedCommand::from_str(&raw.unwrap()) {
+                if msg.sender.uuid == uuid && tx.send((msg.level, msg.message)).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    let mut bt = Backtester::new(uuid);
+    let set_level = |bt: &mut Backtester, level: &str| bt.handle_command(Command::SetLogLevel{
+        uuid: uuid, level: String::from(level),
+    });
+    let pong = Some(Response::Pong{args: vec![uuid.hyphenated().to_string()]});
+    assert_eq!(set_level(&mut bt, "ERROR"), Some(Response::Ok));
+    assert_eq!(bt.handle_command(Command::Ping), pong);
+    bt.cs.error(None, "Logged after the Ping");
+    // messages are published in order, so the Ping's would have arrived first
+    let first = logged.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(first, (LogLevel::Error, String::from("Logged after the Ping")));
+
+    let other = bt.handle_command(Command::SetLogLevel{uuid: Uuid::new_v4(), level: String::from("DEBUG")});
+    assert_eq!(other, None);
+    match set_level(&mut bt, "loud") {
+        Some(Response::Error{..}) => (),
+        res => panic!("Expected an error for an unknown log level but got {:?}", res),
+    }
+    assert_eq!(set_level(&mut bt, "DEBUG"), Some(Response::Ok));
+    assert_eq!(bt.handle_command(Command::Ping), pong);
+    let second = logged.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(second, (LogLevel::Debug, String::from("Received Ping")));
+
+    tickgrinder_util::transport::command_server::set_log_level(initial_log_level());
+}
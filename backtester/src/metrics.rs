@@ -0,0 +1,105 @@
+//! Exposes per-backtest statistics in the Prometheus text format over HTTP so that running backtests
+//! can be monitored by an external Prometheus server.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use prometheus::{Encoder, GaugeVec, Opts, Registry, TextEncoder};
+use uuid::Uuid;
+
+use backtest::BacktestHandle;
+
+/// Holds the gauge families for all backtests running on a Backtester.  Each backtest contributes one
+/// label set keyed by its UUID.
+#[derive(Clone)]
+pub struct BacktestMetrics {
+    registry: Registry,
+    tick_count: GaugeVec,
+    running: GaugeVec,
+    elapsed_ms: GaugeVec,
+}
+
+fn gauge_vec(registry: &Registry, name: &str, help: &str) -> GaugeVec {
+    let gauge = GaugeVec::new(Opts::new(name, help), &["uuid"]).unwrap();
+    registry.register(Box::new(gauge.clone())).unwrap();
+    gauge
+}
+
+impl BacktestMetrics {
+    pub fn new() -> BacktestMetrics {
+        let registry = Registry::new();
+        BacktestMetrics {
+            tick_count: gauge_vec(&registry, "backtests_tick_count", "Number of ticks processed by the backtest"),
+            running: gauge_vec(&registry, "backtests_running", "1 if the backtest is running and 0 if it's paused"),
+            elapsed_ms: gauge_vec(&registry, "backtests_elapsed_ms", "Milliseconds since the backtest was started"),
+            registry: registry,
+        }
+    }
+
+    /// Sets the gauges of every backtest to their current values.
+    pub fn update(&self, backtests: &HashMap<Uuid, BacktestHandle>) {
+        for (uuid, handle) in backtests.iter() {
+            let uuid_string = uuid.hyphenated().to_string();
+            let labels = &[uuid_string.as_str()];
+            let elapsed = handle.started.elapsed();
+            let elapsed_ms = elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1_000_000) as u64;
+
+            self.tick_count.with_label_values(labels).set(handle.tick_count.load(Ordering::Relaxed) as f64);
+            self.running.with_label_values(labels).set(if handle.running.load(Ordering::Relaxed) { 1. } else { 0. });
+            self.elapsed_ms.with_label_values(labels).set(elapsed_ms as f64);
+        }
+    }
+
+    /// Drops the label sets belonging to a backtest that has been removed.
+    pub fn remove(&self, uuid: &Uuid) {
+        let uuid_string = uuid.hyphenated().to_string();
+        let labels = &[uuid_string.as_str()];
+        let _ = self.tick_count.remove_label_values(labels);
+        let _ = self.running.remove_label_values(labels);
+        let _ = self.elapsed_ms.remove_label_values(labels);
+    }
+
+    /// Refreshes the gauges and renders them in the Prometheus text format.
+    pub fn render(&self, backtests: &HashMap<Uuid, BacktestHandle>) -> String {
+        self.update(backtests);
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+}
+
+/// Starts a thread that serves the metrics of the supplied backtests over HTTP.  Every request
+/// receives the current metrics regardless of its path.  Returns the address that was bound.
+pub fn serve_metrics(
+    addr: &str, metrics: BacktestMetrics, backtests: Arc<Mutex<HashMap<Uuid, BacktestHandle>>>
+) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Ok(stream) = stream {
+                let body = metrics.render(&*backtests.lock().unwrap());
+                if let Err(err) = respond(stream, &body) {
+                    println!("Error while responding to metrics request: {:?}", err);
+                }
+            }
+        }
+    });
+
+    Ok(local_addr)
+}
+
+fn respond(mut stream: TcpStream, body: &str) -> io::Result<()> {
+    // we don't care about the contents of the request, but it has to be read before responding
+    let mut buf = [0u8; 1024];
+    let _ = stream.read(&mut buf)?;
+    write!(
+        stream, "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(), body
+    )
+}
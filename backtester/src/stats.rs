@@ -0,0 +1,170 @@
+//! Calculates statistics about the performance of a strategy over the course of a backtest.
+
+use simbroker::TickAnomalyCounts;
+
+/// Summary statistics for a completed or running backtest.  Returns are expressed as fractions of the
+/// backtest's starting capital so that backtests of different sizes can be compared directly.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BacktestStats {
+    pub starting_capital: f64,
+    pub final_equity: f64,
+    /// `final_equity / starting_capital - 1.0`, or 0 if there was no starting capital
+    pub total_return_pct: f64,
+    /// Mean of the per-sample percentage returns divided by their standard deviation.  Not annualized.
+    pub sharpe_ratio: f64,
+    /// Largest peak-to-trough decline in equity as a fraction of the peak.
+    pub max_drawdown_pct: f64,
+    /// Bad ticks that the SimBroker running the backtest rejected or clamped.  Not derived from the equity curve;
+    /// set with `with_tick_anomalies()`.
+    #[serde(default)]
+    pub tick_anomalies: TickAnomalyCounts,
+    /// Market orders that the SimBroker requoted instead of filling, which indicates how much the strategy churns
+    /// in fast markets.  Set with `with_requotes()`.
+    #[serde(default)]
+    pub requotes: u64,
+    /// Fraction of closed trades that were profitable, or 0 if no trades were closed.  Set with `with_win_rate()`.
+    #[serde(default)]
+    pub win_rate: f64,
+}
+
+impl BacktestStats {
+    /// Computes statistics from a series of equity samples taken over the course of the backtest.
+    /// The starting capital is treated as the first sample of the curve.
+    pub fn from_equity_curve(starting_capital: f64, equity_curve: &[f64]) -> BacktestStats {
+        let final_equity = *equity_curve.last().unwrap_or(&starting_capital);
+
+        let mut returns = Vec::with_capacity(equity_curve.len());
+        let mut last_equity = starting_capital;
+        let mut peak = starting_capital;
+        let mut max_drawdown_pct = 0.;
+        for &equity in equity_curve {
+            if last_equity != 0. {
+                returns.push(equity / last_equity - 1.);
+            }
+            last_equity = equity;
+
+            if equity > peak {
+                peak = equity;
+            } else if peak > 0. && (peak - equity) / peak > max_drawdown_pct {
+                max_drawdown_pct = (peak - equity) / peak;
+            }
+        }
+
+        BacktestStats {
+            starting_capital: starting_capital,
+            final_equity: final_equity,
+            total_return_pct: if starting_capital == 0. { 0. } else { final_equity / starting_capital - 1. },
+            sharpe_ratio: sharpe_ratio(&returns),
+            max_drawdown_pct: max_drawdown_pct,
+            tick_anomalies: TickAnomalyCounts::default(),
+            requotes: 0,
+            win_rate: 0.,
+        }
+    }
+
+    /// Adds the anomaly counts of the SimBroker that ran the backtest to the summary.
+    pub fn with_tick_anomalies(mut self, tick_anomalies: TickAnomalyCounts) -> BacktestStats {
+        self.tick_anomalies = tick_anomalies;
+        self
+    }
+
+    /// Adds the number of requotes from the SimBroker that ran the backtest to the summary.
+    pub fn with_requotes(mut self, requotes: u64) -> BacktestStats {
+        self.requotes = requotes;
+        self
+    }
+
+    /// Sets the win rate from the number of winning trades out of all closed trades.
+    pub fn with_win_rate(mut self, winning_trades: u64, closed_trades: u64) -> BacktestStats {
+        self.win_rate = if closed_trades == 0 { 0. } else { winning_trades as f64 / closed_trades as f64 };
+        self
+    }
+}
+
+/// The differences between the results of two backtests, A and B.  Each delta is B's value minus A's, so a
+/// positive `return_delta` means that B was more profitable.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BacktestDiff {
+    pub sharpe_delta: f64,
+    /// Positive if B had a deeper drawdown than A
+    pub drawdown_delta: f64,
+    pub return_delta: f64,
+    pub win_rate_delta: f64,
+    /// Number of ticks that each backtest had processed when the comparison was made
+    pub ticks_processed_a: usize,
+    pub ticks_processed_b: usize,
+}
+
+impl BacktestDiff {
+    pub fn new(
+        a: &BacktestStats, ticks_processed_a: usize, b: &BacktestStats, ticks_processed_b: usize
+    ) -> BacktestDiff {
+        BacktestDiff {
+            sharpe_delta: b.sharpe_ratio - a.sharpe_ratio,
+            drawdown_delta: b.max_drawdown_pct - a.max_drawdown_pct,
+            return_delta: b.total_return_pct - a.total_return_pct,
+            win_rate_delta: b.win_rate - a.win_rate,
+            ticks_processed_a: ticks_processed_a,
+            ticks_processed_b: ticks_processed_b,
+        }
+    }
+}
+
+/// Returns the mean of the supplied returns divided by their standard deviation or 0 if the standard
+/// deviation is 0.
+fn sharpe_ratio(returns: &[f64]) -> f64 {
+    if returns.is_empty() {
+        return 0.;
+    }
+
+    let n = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / n;
+    if variance == 0. {
+        return 0.;
+    }
+
+    mean / variance.sqrt()
+}
+
+#[test]
+fn percentage_return() {
+    // a strategy that loses exactly 100 units over two trades
+    let stats = BacktestStats::from_equity_curve(1000., &[1000., 940., 960., 900.]);
+    assert_eq!(stats.final_equity, 900.);
+    assert!((stats.total_return_pct - -0.1).abs() < 1e-12);
+    assert!((stats.max_drawdown_pct - 0.1).abs() < 1e-12);
+    assert!(stats.sharpe_ratio < 0.);
+}
+
+#[test]
+fn sharpe_uses_percentage_returns() {
+    // the same relative performance at different scales should produce the same Sharpe ratio
+    let small = BacktestStats::from_equity_curve(100., &[110., 99., 108.9]);
+    let large = BacktestStats::from_equity_curve(100000., &[110000., 99000., 108900.]);
+    assert!((small.sharpe_ratio - large.sharpe_ratio).abs() < 1e-9);
+    assert!((small.total_return_pct - large.total_return_pct).abs() < 1e-12);
+
+    let flat = BacktestStats::from_equity_curve(1., &[1., 1.]);
+    assert_eq!(flat.sharpe_ratio, 0.);
+    assert_eq!(flat.total_return_pct, 0.);
+    assert_eq!(BacktestStats::from_equity_curve(0., &[]).total_return_pct, 0.);
+}
+
+#[test]
+fn backtest_diff_signs() {
+    let a = BacktestStats::from_equity_curve(1000., &[1000., 940., 960., 900.]).with_win_rate(1, 4);
+    let b = BacktestStats::from_equity_curve(1000., &[1050., 1100.]).with_win_rate(2, 2);
+    let diff = BacktestDiff::new(&a, 4, &b, 2);
+    assert!(diff.sharpe_delta > 0.);
+    assert!((diff.drawdown_delta - -0.1).abs() < 1e-12);
+    assert!((diff.return_delta - 0.2).abs() < 1e-12);
+    assert_eq!(diff.win_rate_delta, 0.75);
+    assert_eq!((diff.ticks_processed_a, diff.ticks_processed_b), (4, 2));
+
+    // swapping the backtests flips the sign of every delta
+    let reversed = BacktestDiff::new(&b, 2, &a, 4);
+    assert_eq!(reversed.sharpe_delta, -diff.sharpe_delta);
+    assert_eq!(reversed.return_delta, -diff.return_delta);
+    assert_eq!(BacktestStats::from_equity_curve(1., &[]).with_win_rate(0, 0).win_rate, 0.);
+}
@@ -0,0 +1,78 @@
+//! Publish/subscribe messaging between the strategies of different backtests.  Each SimBroker managed by the
+//! Backtester is attached to the same `StrategyBus` and subscribed to the topics of its `bus_topics` setting,
+//! so a strategy trading on one of them can signal the strategies trading on the others.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, Sender, Receiver};
+
+use simbroker::MessageBus;
+
+/// Delivers every message published to a topic to all of that topic's subscribers.
+pub struct StrategyBus {
+    subscribers: Mutex<HashMap<String, Vec<Sender<String>>>>,
+}
+
+impl StrategyBus {
+    pub fn new() -> StrategyBus {
+        StrategyBus {
+            subscribers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns a receiver for all messages published to `topic` from now on.
+    pub fn subscribe(&self, topic: &str) -> Receiver<String> {
+        let (tx, rx) = channel();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.entry(String::from(topic)).or_insert_with(Vec::new).push(tx);
+        rx
+    }
+
+    /// Sends `msg` to every subscriber of `topic` and returns how many received it.  Subscribers whose receivers
+    /// have been dropped are removed.
+    pub fn publish(&self, topic: &str, msg: String) -> usize {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let delivered = match subscribers.get_mut(topic) {
+            Some(senders) => {
+                senders.retain(|tx| tx.send(msg.clone()).is_ok());
+                senders.len()
+            },
+            None => return 0,
+        };
+        if delivered == 0 {
+            subscribers.remove(topic);
+        }
+        delivered
+    }
+}
+
+impl MessageBus for StrategyBus {
+    fn subscribe(&self, topic: &str) -> Receiver<String> {
+        StrategyBus::subscribe(self, topic)
+    }
+
+    fn publish(&self, topic: &str, msg: String) -> usize {
+        StrategyBus::publish(self, topic, msg)
+    }
+}
+
+/// Messages only reach the subscribers of their topic, and dropped subscribers are pruned on the next publish.
+#[test]
+fn strategy_bus_topics() {
+    let bus = StrategyBus::new();
+    let signals = bus.subscribe("signals");
+    let dropped = bus.subscribe("signals");
+    let hedges = bus.subscribe("hedges");
+    drop(dropped);
+
+    assert_eq!(bus.publish("signals", String::from("buy")), 1);
+    assert_eq!(bus.publish("nobody", String::from("hello")), 0);
+    assert_eq!(signals.try_recv(), Ok(String::from("buy")));
+    assert!(signals.try_recv().is_err());
+    assert!(hedges.try_recv().is_err());
+
+    drop(hedges);
+    assert_eq!(bus.publish("hedges", String::from("sell")), 0);
+    assert!(!bus.subscribers.lock().unwrap().contains_key("hedges"));
+    assert_eq!(bus.publish("signals", String::from("sell")), 1);
+}
@@ -211,7 +211,7 @@ impl Broker for FXCMNative {
             },
             BrokerAction::TradingAction{action, account_uuid} => {
                 match action {
-                    TradingAction::MarketOrder{symbol, long, size, stop, take_profit, max_range} => {
+                    TradingAction::MarketOrder{symbol, long, size, stop, take_profit, max_range, ..} => {
                         unimplemented!(); // TODO
                     },
                     TradingAction::ModifyOrder{uuid, size, entry_price, stop, take_profit} => {
@@ -223,7 +223,7 @@ impl Broker for FXCMNative {
                     TradingAction::MarketClose{uuid, size} => {
                         unimplemented!(); // TODO
                     },
-                    TradingAction::LimitOrder{symbol, long, size, stop, take_profit, entry_price} => {
+                    TradingAction::LimitOrder{symbol, long, size, stop, take_profit, entry_price, ..} => {
                         unimplemented!(); // TODO
                     },
                     TradingAction::LimitClose{uuid, size, exit_price} => {
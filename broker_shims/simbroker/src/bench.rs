@@ -0,0 +1,137 @@
+//! Measures how quickly the SimBroker processes ticks and orders so that changes to the order book and position
+//! matching code can be checked for performance regressions.  The benchmark drives a synthetic, deterministically
+//! seeded random walk through the broker while constantly placing and cancelling limit orders.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use rand::{SeedableRng, XorShiftRng};
+use serde_json;
+
+use super::*;
+
+/// Orders are cancelled after this many ticks if they haven't been filled by then
+const ORDER_LIFETIME_TICKS: usize = 10;
+/// Distance of the stop loss and take profit of every order from its entry price
+const EXIT_DISTANCE: usize = 20;
+
+/// The results of a run of `SimBroker::bench()`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BenchResults {
+    pub ticks: usize,
+    pub orders_placed: usize,
+    pub orders_cancelled: usize,
+    pub elapsed_ms: f64,
+    pub ticks_per_sec: f64,
+    /// Orders placed per second; cancellations aren't counted
+    pub orders_per_sec: f64,
+    /// The largest number of pending orders in the book after any tick
+    pub peak_pending_orders: usize,
+}
+
+impl SimBroker {
+    /// Runs `n_ticks` synthetic ticks through the broker, placing `orders_per_tick` limit orders with a stop loss and
+    /// take profit after each one and cancelling orders that are still pending `ORDER_LIFETIME_TICKS` ticks later.
+    /// The ticks and orders are the same on every run.  Returns the throughput and prints it as JSON.
+    ///
+    /// The broker is reset before the benchmark (with the trade log, equity publishing, and event publishing turned
+    /// off so that they don't skew the results) and again afterwards with its original settings, so all positions,
+    /// orders, and history are discarded.
+    pub fn bench(&mut self, n_ticks: usize, orders_per_tick: usize) -> Result<BenchResults, BrokerError> {
+        let settings = self.settings.clone();
+        let mut bench_settings = settings.clone();
+        bench_settings.tickstreams = String::from("[]");
+        bench_settings.trade_log_table = String::new();
+        bench_settings.equity_publish = false;
+        bench_settings.event_publish = false;
+        // enough that no order is ever refused for lack of margin
+        bench_settings.starting_balance = 1 << 40;
+        self.reset(Some(bench_settings))?;
+
+        let res = self.run_bench(n_ticks, orders_per_tick);
+        self.reset(Some(settings))?;
+        let results = res?;
+
+        match serde_json::to_string(&results) {
+            Ok(ser) => println!("{}", ser),
+            Err(err) => self.cs.error(None, &format!("Unable to serialize benchmark results: {:?}", err)),
+        }
+        Ok(results)
+    }
+
+    fn run_bench(&mut self, n_ticks: usize, orders_per_tick: usize) -> Result<BenchResults, BrokerError> {
+        self.oneshot_price_set(String::from("BENCH"), (100000, 100002), false, 4);
+        let symbol_ix = self.symbols.get_index(&String::from("BENCH")).unwrap();
+        let account_uuid = *self.accounts.data.keys().next().unwrap();
+
+        let mut rng = XorShiftRng::from_seed([0x193a6754, 0xa8a7d469, 0x97830e05, 0x113ba7bb]);
+        let mut price: usize = 100000;
+        let mut pending: VecDeque<(usize, Uuid)> = VecDeque::new();
+        let mut buffer = Vec::new();
+        let mut orders_placed = 0;
+        let mut orders_cancelled = 0;
+        let mut peak_pending_orders = 0;
+
+        let start = Instant::now();
+        for i in 0..n_ticks {
+            price = cmp::max(price + rng.gen_range(0, 21) - 10, 1000);
+            self.timestamp = (i as u64 + 1) * 1000;
+            let tick = Tick {timestamp: self.timestamp, bid: price, ask: price + 2};
+            // every pending order and open position could be filled or closed by the tick
+            let max_messages = {
+                let positions = &self.accounts.positions[symbol_ix];
+                positions.pending.len() + positions.open.len()
+            };
+            if buffer.len() < max_messages + 1 {
+                buffer.resize(max_messages + 1, TickOutput::Tick(0, Tick::null()));
+            }
+            self.process_new_tick(symbol_ix, tick, 0, &mut buffer);
+
+            while pending.front().map(|&(placed_at, _)| placed_at + ORDER_LIFETIME_TICKS <= i).unwrap_or(false) {
+                let (_, order_uuid) = pending.pop_front().unwrap();
+                if let OrderUpdateResult::Ok{..} = self.cancel_pending_order(order_uuid) {
+                    orders_cancelled += 1;
+                }
+            }
+
+            for _ in 0..orders_per_tick {
+                let long: bool = rng.gen();
+                let offset = rng.gen_range(1, 50);
+                let entry_price = if long { price - offset } else { price + 2 + offset };
+                let (stop, take_profit) = if long {
+                    (entry_price - EXIT_DISTANCE, entry_price + EXIT_DISTANCE)
+                } else {
+                    (entry_price + EXIT_DISTANCE, entry_price - EXIT_DISTANCE)
+                };
+                let action = BrokerAction::TradingAction {
+                    account_uuid: account_uuid,
+                    action: TradingAction::LimitOrder {
+                        symbol: String::from("BENCH"), long: long, size: 1, stop: Some(stop),
+                        take_profit: Some(take_profit), entry_price: entry_price, time_in_force: TimeInForce::GTC,
+                        tag: None,
+                    },
+                };
+                if let BrokerMessage::OrderPlaced{order_id, ..} = self.exec_action(&action)? {
+                    pending.push_back((i, order_id));
+                }
+                orders_placed += 1;
+            }
+
+            peak_pending_orders = cmp::max(peak_pending_orders, self.accounts.positions[symbol_ix].pending.len());
+        }
+
+        let elapsed = start.elapsed();
+        let elapsed_secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.;
+        let per_sec = |count: usize| if elapsed_secs > 0. { count as f64 / elapsed_secs } else { 0. };
+        Ok(BenchResults {
+            ticks: n_ticks,
+            orders_placed: orders_placed,
+            orders_cancelled: orders_cancelled,
+            elapsed_ms: elapsed_secs * 1000.,
+            ticks_per_sec: per_sec(n_ticks),
+            orders_per_sec: per_sec(orders_placed),
+            peak_pending_orders: peak_pending_orders,
+        })
+    }
+}
@@ -0,0 +1,67 @@
+//! Flattened views of the open positions and pending orders on the SimBroker, used to render a live blotter.
+
+use super::*;
+
+/// A single row of the blotter describing either an open position or a pending order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlotterEntry {
+    pub uuid: Uuid,
+    pub account_uuid: Uuid,
+    pub symbol: String,
+    pub long: bool,
+    pub size: usize,
+    /// The execution price of positions and the limit price of orders (`None` for market orders)
+    pub price: Option<usize>,
+    pub stop: Option<usize>,
+    pub take_profit: Option<usize>,
+    /// Profit or loss at current prices; only set for open positions
+    pub unrealized_pnl: Option<f64>,
+    pub creation_time: u64,
+    pub execution_time: Option<u64>,
+    /// The tag the client submitted the order with, if any
+    pub tag: Option<String>,
+}
+
+impl SimBroker {
+    fn blotter_entry(&self, uuid: Uuid, account_uuid: Uuid, pos: &Position, open: bool) -> BlotterEntry {
+        BlotterEntry {
+            uuid: uuid,
+            account_uuid: account_uuid,
+            symbol: self.symbols[pos.symbol_id].name.clone(),
+            long: pos.long,
+            size: pos.size,
+            price: if open { pos.execution_price } else { pos.price },
+            stop: pos.stop,
+            take_profit: pos.take_profit,
+            unrealized_pnl: if open { Some(self.unrealized_pnl(pos)) } else { None },
+            creation_time: pos.creation_time,
+            execution_time: pos.execution_time,
+            tag: pos.tag.clone(),
+        }
+    }
+
+    fn blotter(&self, open: bool) -> Vec<BlotterEntry> {
+        let mut entries = Vec::new();
+        for (account_uuid, acct) in self.accounts.iter() {
+            let positions = if open { &acct.ledger.open_positions } else { &acct.ledger.pending_positions };
+            for (uuid, pos) in positions.iter() {
+                entries.push(self.blotter_entry(*uuid, *account_uuid, pos, open));
+            }
+        }
+
+        entries.sort_by(|e1, e2| {
+            e1.creation_time.cmp(&e2.creation_time).then_with(|| e1.uuid.as_bytes().cmp(e2.uuid.as_bytes()))
+        });
+        entries
+    }
+
+    /// Returns blotter rows for all open positions of all accounts, oldest first.
+    pub fn position_blotter(&self) -> Vec<BlotterEntry> {
+        self.blotter(true)
+    }
+
+    /// Returns blotter rows for all pending orders of all accounts, oldest first.
+    pub fn order_blotter(&self) -> Vec<BlotterEntry> {
+        self.blotter(false)
+    }
+}
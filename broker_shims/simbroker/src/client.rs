@@ -6,6 +6,15 @@ use futures::{Sink, Future};
 use futures::stream::BoxStream;
 use futures::sync::mpsc::Sender;
 
+/// A publish/subscribe channel shared between several SimBrokers through which the strategies trading on them can
+/// talk to each other.  Every message published to a topic is delivered to all of its subscribers.
+pub trait MessageBus : Send + Sync {
+    /// Returns a receiver for all messages published to `topic` from now on.
+    fn subscribe(&self, topic: &str) -> mpsc::Receiver<String>;
+    /// Sends `msg` to every subscriber of `topic`, returning how many subscribers it reached.
+    fn publish(&self, topic: &str, msg: String) -> usize;
+}
+
 /// The client-facing part of the SimBroker.  Implements the `Broker` trait and enables clients to communicate with
 /// the underlying `SimBroker` instance while it's blocked on the simulation loop.
 pub struct SimBrokerClient {
@@ -19,6 +28,10 @@ pub struct SimBrokerClient {
     tick_recvs: HashMap<String, (BoxStream<Tick, ()>, Arc<AtomicBool>,)>,
     /// True if the simulation loop has been started
     in_loop: bool,
+    /// The bus that messages are published to, if one has been attached
+    bus: Option<Arc<MessageBus>>,
+    /// Receivers for the topics of the `bus_topics` setting that the broker is subscribed to on its bus
+    bus_subscriptions: Vec<(String, mpsc::Receiver<String>)>,
 }
 
 impl Broker for SimBrokerClient {
@@ -53,6 +66,8 @@ impl Broker for SimBrokerClient {
             push_stream_recv: Some((push_stream_recv, Arc::new(AtomicBool::new(false)),)),
             tick_recvs: tick_hm,
             in_loop: false,
+            bus: None,
+            bus_subscriptions: Vec::new(),
         };
 
         c.complete(Ok(client));
@@ -232,6 +247,224 @@ impl SimBrokerClient {
         self.simbroker.oneshot_price_set(name, price, is_fx, decimal_precision);
         Ok(BrokerMessage::Success)
     }
+
+    /// Processes a tick on the inner `SimBroker` as if it came from one of its tickstreams.  Ticks can only be
+    /// pushed while the simulation loop isn't running.  See `SimBroker::push_tick`.
+    pub fn push_tick(&mut self, name: String, tick: Tick, is_fx: bool, decimal_precision: usize) -> BrokerResult {
+        if self.in_loop {
+            return Err(BrokerError::Message{
+                message: String::from("Ticks can't be pushed to a SimBroker while its simulation loop is running."),
+            });
+        }
+
+        self.simbroker.push_tick(name, tick, is_fx, decimal_precision);
+        Ok(BrokerMessage::Success)
+    }
+
+    /// Returns the current price of a symbol on the inner `SimBroker` or `None` if it doesn't have one.
+    pub fn get_price(&self, symbol: &String) -> Option<(usize, usize)> {
+        self.simbroker.symbols.get_index(symbol).and_then(|ix| self.simbroker.get_price(ix))
+    }
+
+    /// Modifies a pending order on the inner `SimBroker`.  See `SimBroker::modify_pending_order`.
+    pub fn modify_order(
+        &mut self, order_uuid: Uuid, new_price: Option<usize>, new_size: Option<usize>,
+        new_sl_tp: Option<(Option<usize>, Option<usize>)>,
+    ) -> OrderUpdateResult {
+        self.simbroker.modify_pending_order(order_uuid, new_price, new_size, new_sl_tp)
+    }
+
+    /// Cancels a pending order on the inner `SimBroker`.
+    pub fn cancel_order(&mut self, order_uuid: Uuid) -> OrderUpdateResult {
+        self.simbroker.cancel_pending_order(order_uuid)
+    }
+
+    /// Cancels every pending order on the inner `SimBroker`, returning how many were cancelled.
+    pub fn cancel_all_orders(&mut self) -> usize {
+        self.simbroker.cancel_all_orders()
+    }
+
+    /// Opens a position that the account already held on the inner `SimBroker`.  See `SimBroker::preload_position`.
+    pub fn preload_position(&mut self, symbol: &str, qty: f64, entry_price: usize) -> BrokerResult {
+        self.simbroker.preload_position(symbol, qty, entry_price)
+    }
+
+    /// Calls same function on inner `SimBroker`
+    pub fn set_starting_balance(&mut self, starting_balance: usize) {
+        self.simbroker.set_starting_balance(starting_balance)
+    }
+
+    /// Limits the number of positions that can be open at once on the inner `SimBroker`; see the
+    /// `max_open_positions` setting.
+    pub fn set_max_open_positions(&mut self, max_open_positions: usize) {
+        self.simbroker.settings.max_open_positions = max_open_positions;
+    }
+
+    /// Returns the settings of the inner `SimBroker`
+    pub fn settings(&self) -> &SimBrokerSettings {
+        &self.simbroker.settings
+    }
+
+    /// Calls same function on inner `SimBroker`
+    pub fn open_order_count(&self) -> usize {
+        self.simbroker.open_order_count()
+    }
+
+    /// Lists all pending orders on the inner `SimBroker`.
+    pub fn pending_orders(&self) -> Vec<(Uuid, Position)> {
+        self.simbroker.pending_orders()
+    }
+
+    /// Returns all orders and positions on the inner `SimBroker` that were submitted with the given tag.
+    pub fn find_by_tag(&self, tag: &str) -> Vec<(Uuid, Position)> {
+        self.simbroker.find_by_tag(tag)
+    }
+
+    /// Returns blotter rows for all open positions on the inner `SimBroker`.
+    pub fn position_blotter(&self) -> Vec<BlotterEntry> {
+        self.simbroker.position_blotter()
+    }
+
+    /// Returns blotter rows for all pending orders on the inner `SimBroker`.
+    pub fn order_blotter(&self) -> Vec<BlotterEntry> {
+        self.simbroker.order_blotter()
+    }
+
+    /// Calls same function on inner `SimBroker`
+    pub fn register_tick_callback(&mut self, cb: Box<Fn(&Tick) + Send>) {
+        self.simbroker.register_tick_callback(cb)
+    }
+
+    /// Returns the number of anomalous ticks the inner `SimBroker` has received.
+    pub fn tick_anomalies(&self) -> &TickAnomalyCounts {
+        self.simbroker.tick_anomalies()
+    }
+
+    /// Calls same function on inner `SimBroker`
+    pub fn tick_verdicts(&self, symbol_ix: usize) -> Option<&TickVerdictCounts> {
+        self.simbroker.tick_verdicts(symbol_ix)
+    }
+
+    /// Returns a description of the tick that aborted the inner `SimBroker`'s simulation, if one has.
+    pub fn abort_reason(&self) -> Option<&str> {
+        self.simbroker.abort_reason()
+    }
+
+    /// Returns aggregate trading statistics of the inner `SimBroker`.
+    pub fn stats(&self) -> SimBrokerStats {
+        self.simbroker.stats()
+    }
+
+    /// Returns all trades that have taken place on the inner `SimBroker`.
+    pub fn trade_log(&self) -> &[TradeLogEntry] {
+        self.simbroker.trade_log()
+    }
+
+    /// Calls same function on inner `SimBroker`
+    pub fn set_backtest_uuid(&mut self, backtest_uuid: Uuid) {
+        self.simbroker.set_backtest_uuid(backtest_uuid)
+    }
+
+    /// Calls same function on inner `SimBroker`
+    pub fn flush_trade_log(&mut self) -> Result<(), String> {
+        self.simbroker.flush_trade_log()
+    }
+
+    /// Returns the UUID of the inner `SimBroker`
+    pub fn uuid(&self) -> Uuid {
+        self.simbroker.uuid
+    }
+
+    /// Returns the equity curve samples recorded by the inner `SimBroker`
+    pub fn equity_curve(&self) -> &[EquitySample] {
+        self.simbroker.equity_curve()
+    }
+
+    /// Calls same function on inner `SimBroker`
+    pub fn equity(&self) -> (usize, f64) {
+        self.simbroker.equity()
+    }
+
+    /// Calls same function on inner `SimBroker`
+    pub fn starting_equity(&self) -> f64 {
+        self.simbroker.starting_equity()
+    }
+
+    /// Resets the inner `SimBroker` (see `SimBroker::reset`) and takes the tick receivers of its new tickstreams.
+    /// Afterwards actions are executed immediately again until `init_sim_loop` is called for the next simulation.
+    pub fn reset(&mut self, settings: Option<SimBrokerSettings>) -> BrokerResult {
+        let res = self.simbroker.reset(settings);
+        self.take_tick_receivers();
+        // the new settings may subscribe to different topics
+        if let Some(bus) = self.bus.clone() {
+            self.attach_bus(bus)?;
+        }
+        res
+    }
+
+    /// Returns a snapshot of the state of the inner `SimBroker`.
+    pub fn snapshot(&self) -> BrokerSnapshot {
+        self.simbroker.snapshot()
+    }
+
+    /// Restores the inner `SimBroker` from a snapshot; see `SimBroker::restore`.
+    pub fn restore(&mut self, snapshot: BrokerSnapshot) -> BrokerResult {
+        self.simbroker.restore(snapshot)
+    }
+
+    /// Runs `SimBroker::bench` on the inner `SimBroker`, which resets it afterwards, and takes the tick receivers
+    /// of its new tickstreams.
+    pub fn bench(&mut self, n_ticks: usize, orders_per_tick: usize) -> Result<BenchResults, BrokerError> {
+        let res = self.simbroker.bench(n_ticks, orders_per_tick);
+        self.take_tick_receivers();
+        res
+    }
+
+    /// Connects the broker to a message bus, subscribing it to the topics of its `bus_topics` setting.  Any bus
+    /// that was attached before is replaced along with its subscriptions.
+    pub fn attach_bus(&mut self, bus: Arc<MessageBus>) -> Result<(), BrokerError> {
+        let topics = parse_bus_topics(&self.simbroker.settings)?;
+        self.bus_subscriptions = topics.into_iter()
+            .map(|topic| {
+                let rx = bus.subscribe(&topic);
+                (topic, rx)
+            }).collect();
+        self.bus = Some(bus);
+        Ok(())
+    }
+
+    /// Publishes a message to the attached bus, returning how many subscribers it reached.  Brokers that are
+    /// subscribed to the topic themselves receive their own messages as well.
+    pub fn publish_message(&self, topic: &str, msg: String) -> Result<usize, BrokerError> {
+        match self.bus {
+            Some(ref bus) => Ok(bus.publish(topic, msg)),
+            None => Err(BrokerError::Message{message: String::from("No message bus is attached to this SimBroker.")}),
+        }
+    }
+
+    /// Returns all `(topic, message)` pairs that have arrived on the subscribed topics since the last call, in
+    /// the order they arrived for each topic.
+    pub fn take_messages(&mut self) -> Vec<(String, String)> {
+        let mut messages = Vec::new();
+        for &(ref topic, ref rx) in &self.bus_subscriptions {
+            while let Ok(msg) = rx.try_recv() {
+                messages.push((topic.clone(), msg));
+            }
+        }
+        messages
+    }
+
+    /// Replaces the tick receivers handed out to clients with those of the inner `SimBroker`'s current tickstreams
+    /// after it has been reset.
+    fn take_tick_receivers(&mut self) {
+        self.tick_recvs.clear();
+        for sym in self.simbroker.symbols.iter_mut() {
+            if let Some(recv) = sym.client_receiver.take() {
+                self.tick_recvs.insert(sym.name.clone(), (recv, Arc::new(AtomicBool::new(false)),));
+            }
+        }
+        self.in_loop = false;
+    }
 }
 
 #[test]
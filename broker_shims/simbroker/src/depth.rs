@@ -0,0 +1,106 @@
+//! A synthetic order book that makes the fill prices of large orders depend on their size.  When it's enabled,
+//! every tick is expanded into `depth_levels` price levels on each side of the market, spaced `depth_level_spacing`
+//! apart starting at the tick's bid and ask, each of which holds `depth_level_volume` units.  Orders walk the book
+//! from the top and are filled at the volume-weighted average price of the levels they take; whatever the book
+//! can't absorb is left pending for later ticks.  The book is rebuilt from scratch on every fill, so it only
+//! depends on the settings and the current tick.
+//!
+//! A symbol can also be given an explicit book with `set_order_book_depth`, which is used in place of the synthetic
+//! one.  Its levels at or above the ask are the ask side of the book and those at or below the bid are the bid side.
+//! Like the synthetic book, it isn't depleted by fills; it stays the same until it's replaced or cleared.
+
+use super::*;
+
+impl SimBroker {
+    /// Returns `true` if fills are simulated against a synthetic order book instead of the top of the book.
+    pub fn depth_enabled(&self) -> bool {
+        self.settings.depth_levels != 0 && self.settings.depth_level_volume != 0
+    }
+
+    /// Sets the `(price, available quantity)` levels of a symbol's order book, replacing the synthetic book for it.
+    /// Bid and ask levels are given together in any order.  Quantities are rounded down to whole units, and an
+    /// empty list of levels removes the symbol's book.
+    pub fn set_order_book_depth(&mut self, symbol: &str, levels: Vec<(usize, f64)>) -> Result<(), String> {
+        let symbol_ix = match self.symbols.get_index(&String::from(symbol)) {
+            Some(ix) => ix,
+            None => return Err(format!("No symbol named {} is registered.", symbol)),
+        };
+        if levels.iter().any(|&(_, qty)| !(qty >= 0.)) {
+            return Err(String::from("Order book quantities must be non-negative numbers."));
+        }
+
+        if levels.is_empty() {
+            self.order_books.remove(&symbol_ix);
+        } else {
+            self.order_books.insert(symbol_ix, levels);
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if orders for the symbol are filled by walking an order book instead of at the top of it.
+    fn has_depth(&self, symbol_ix: usize) -> bool {
+        self.order_books.contains_key(&symbol_ix) || self.depth_enabled()
+    }
+
+    /// Returns the `(price, volume)` levels of the side of the symbol's book that an order in the given direction
+    /// would be filled against, best price first.  `top` is the ask for long orders and the bid for short ones.
+    /// The symbol's explicit book is used if it has one and the synthetic book otherwise.
+    pub fn book_levels(&self, symbol_ix: usize, top: usize, long: bool) -> Vec<(usize, usize)> {
+        if let Some(levels) = self.order_books.get(&symbol_ix) {
+            let mut side: Vec<(usize, usize)> = levels.iter()
+                .filter(|&&(price, qty)| qty >= 1. && if long { price >= top } else { price <= top })
+                .map(|&(price, qty)| (price, qty as usize))
+                .collect();
+            side.sort_by_key(|&(price, _)| price);
+            if !long {
+                side.reverse();
+            }
+            return side;
+        }
+        if !self.depth_enabled() {
+            return Vec::new();
+        }
+
+        let spacing = self.settings.depth_level_spacing;
+        (0..self.settings.depth_levels)
+            // the bid side runs out at a price of zero
+            .take_while(|&level| long || level * spacing < top)
+            .map(|level| {
+                let price = if long { top + level * spacing } else { top - level * spacing };
+                (price, self.settings.depth_level_volume)
+            }).collect()
+    }
+
+    /// Walks the book from `top` to fill up to `size` units of an order, only taking levels at or better than
+    /// `limit` if one is supplied.  Returns the number of units that can be filled and their volume-weighted
+    /// average price, rounded to the nearest price unit.  If the symbol has neither an explicit book nor the
+    /// synthetic one, the whole size is filled at `top`.
+    pub fn walk_book(
+        &self, symbol_ix: usize, top: usize, long: bool, size: usize, limit: Option<usize>
+    ) -> (usize, usize) {
+        if !self.has_depth(symbol_ix) {
+            return (size, top);
+        }
+
+        let mut filled = 0;
+        let mut total_cost = 0;
+        for (price, volume) in self.book_levels(symbol_ix, top, long) {
+            let within_limit = match limit {
+                Some(limit) => if long { price <= limit } else { price >= limit },
+                None => true,
+            };
+            if filled == size || !within_limit {
+                break;
+            }
+            let taken = cmp::min(volume, size - filled);
+            filled += taken;
+            total_cost += price * taken;
+        }
+
+        if filled == 0 {
+            (0, top)
+        } else {
+            (filled, (total_cost + filled / 2) / filled)
+        }
+    }
+}
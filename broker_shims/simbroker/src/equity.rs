@@ -0,0 +1,103 @@
+//! Tracks the SimBroker's equity curve over the course of a simulation.  Samples are kept in memory
+//! so the whole curve can be retrieved at the end of a backtest and can optionally be published live.
+
+use super::*;
+
+/// A snapshot of the account balances and equity at a point in simulated time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EquitySample {
+    pub timestamp: u64,
+    /// Buying power of all accounts
+    pub balance: usize,
+    /// Balance plus the value and unrealized P&L of all open positions
+    pub equity: f64,
+    pub open_positions: usize,
+    /// Symbols of open positions whose prices were stale (see `max_price_age_ms`) when the sample was taken,
+    /// meaning that the unrealized P&L included in `equity` may be outdated
+    #[serde(default)]
+    pub stale_symbols: Vec<String>,
+    /// The UUIDs and tags of all positions that were open when the sample was taken
+    #[serde(default)]
+    pub positions: Vec<SampledPosition>,
+}
+
+/// Identifies a position that was open when an equity sample was taken.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SampledPosition {
+    pub uuid: Uuid,
+    pub tag: Option<String>,
+}
+
+/// Holds the in-memory equity series.  Once the series reaches `max_len`, every other sample is
+/// discarded and the sampling stride is doubled so that the retained samples stay uniformly spaced.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EquityCurve {
+    pub samples: Vec<EquitySample>,
+    max_len: usize,
+    /// Only every `stride`th sample is retained
+    stride: usize,
+    /// Number of samples offered since the last retained one
+    offered: usize,
+    /// Ticks processed since the last sample was taken
+    ticks_since_sample: usize,
+    last_sample_time: Option<u64>,
+}
+
+impl EquityCurve {
+    pub fn new(max_len: usize) -> EquityCurve {
+        EquityCurve {
+            samples: Vec::new(),
+            // at least two samples are needed to thin the series
+            max_len: if max_len < 2 { 2 } else { max_len },
+            stride: 1,
+            offered: 0,
+            ticks_since_sample: 0,
+            last_sample_time: None,
+        }
+    }
+
+    /// Registers that a tick was processed and returns `true` if a sample should be taken according
+    /// to the sampling settings.  A setting of 0 disables that trigger.
+    pub fn tick(&mut self, timestamp: u64, every_n_ticks: usize, interval_ms: u64) -> bool {
+        self.ticks_since_sample += 1;
+        let tick_trigger = every_n_ticks != 0 && self.ticks_since_sample >= every_n_ticks;
+        let time_trigger = interval_ms != 0 && match self.last_sample_time {
+            Some(last) => timestamp >= last + interval_ms,
+            None => true,
+        };
+
+        if tick_trigger || time_trigger {
+            self.ticks_since_sample = 0;
+            self.last_sample_time = Some(timestamp);
+            return true;
+        }
+
+        false
+    }
+
+    /// Adds a sample to the series, thinning it if it has grown too long.  Returns `true` if the
+    /// sample was retained.
+    pub fn push(&mut self, sample: EquitySample) -> bool {
+        self.offered += 1;
+        if self.offered < self.stride {
+            return false;
+        }
+        self.offered = 0;
+
+        if self.samples.len() >= self.max_len {
+            let thinned = self.samples.drain(..).enumerate().filter(|&(i, _)| i % 2 == 0).map(|(_, s)| s).collect();
+            self.samples = thinned;
+            self.stride *= 2;
+        }
+        self.samples.push(sample);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.stride = 1;
+        self.offered = 0;
+        self.ticks_since_sample = 0;
+        self.last_sample_time = None;
+    }
+}
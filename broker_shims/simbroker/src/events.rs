@@ -0,0 +1,104 @@
+//! Delivers the events that take place on the SimBroker to subscribers as they happen.  Each subscriber gets its
+//! own bounded buffer; if a subscriber falls behind and its buffer fills up, the oldest buffered events are
+//! dropped to make room for new ones so that a slow consumer can never stall the simulation.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use futures::{Async, Poll};
+use futures::task::{self, Task};
+
+use super::*;
+
+/// The form in which events are published to Redis
+#[derive(Serialize)]
+pub struct PublishedEvent {
+    pub timestamp: u64,
+    pub event: String,
+}
+
+/// An event affecting the broker's orders that's published to its `fills:<uuid>` channel.  Unlike the events
+/// published to `events_<uuid>`, these are always published and are serialized in full so they can be parsed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum FillEvent {
+    AllOrdersCancelled{count: usize},
+}
+
+/// The form in which fill events are published to Redis
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PublishedFillEvent {
+    pub timestamp: u64,
+    pub event: FillEvent,
+}
+
+struct EventBuffer {
+    events: VecDeque<(u64, BrokerEvent)>,
+    capacity: usize,
+    /// Number of events discarded because the buffer was full
+    dropped: usize,
+    /// The task waiting on the next event, if any
+    waiting: Option<Task>,
+}
+
+/// The broker's end of a subscriber's event buffer.
+#[derive(Clone)]
+pub struct EventSender {
+    buffer: Arc<Mutex<EventBuffer>>,
+}
+
+impl EventSender {
+    /// Adds an event to the buffer, dropping the oldest buffered event if it's full, and wakes the consumer.
+    pub fn send(&self, timestamp: u64, event: BrokerEvent) {
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.events.len() >= buffer.capacity {
+            buffer.events.pop_front();
+            buffer.dropped += 1;
+        }
+        buffer.events.push_back((timestamp, event));
+
+        if let Some(task) = buffer.waiting.take() {
+            task.notify();
+        }
+    }
+}
+
+/// A `Stream` of the events taking place on a SimBroker along with the timestamps at which they happened.
+pub struct EventStream {
+    buffer: Arc<Mutex<EventBuffer>>,
+}
+
+impl EventStream {
+    /// Returns how many events have been dropped because this stream wasn't consumed quickly enough.
+    pub fn dropped(&self) -> usize {
+        self.buffer.lock().unwrap().dropped
+    }
+}
+
+impl Stream for EventStream {
+    type Item = (u64, BrokerEvent);
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let mut buffer = self.buffer.lock().unwrap();
+        match buffer.events.pop_front() {
+            Some(event) => Ok(Async::Ready(Some(event))),
+            None => {
+                buffer.waiting = Some(task::current());
+                Ok(Async::NotReady)
+            },
+        }
+    }
+}
+
+/// Creates a linked sender and stream with a buffer that holds up to `capacity` events.
+pub fn event_channel(capacity: usize) -> (EventSender, EventStream) {
+    let buffer = Arc::new(Mutex::new(EventBuffer {
+        events: VecDeque::new(),
+        // a buffer that can't hold anything would drop every event
+        capacity: if capacity == 0 { 1 } else { capacity },
+        dropped: 0,
+        waiting: None,
+    }));
+
+    (EventSender {buffer: buffer.clone()}, EventStream {buffer: buffer})
+}
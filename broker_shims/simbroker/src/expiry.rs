@@ -0,0 +1,77 @@
+//! Keeps the simulated clock of the SimBroker and cancels pending orders once their time in force runs out.  The
+//! clock is driven by the timestamps of the ticks the broker processes; a good 'til date order expires as soon as
+//! the clock reaches its expiry, so an order that expires at exactly the timestamp of a tick is cancelled before
+//! that tick can fill it.
+
+use super::*;
+
+impl SimBroker {
+    /// Returns the current simulated time: the latest timestamp of all ticks the broker has processed.
+    pub fn sim_time(&self) -> u64 {
+        self.sim_time
+    }
+
+    /// Moves the simulated clock forward to the timestamp of a tick of the given symbol.  A tick older than the
+    /// clock is logged and leaves the clock where it is so that time never runs backwards.
+    pub fn advance_clock(&mut self, symbol_ix: usize, timestamp: u64) {
+        if timestamp < self.sim_time {
+            let errmsg = format!(
+                "Received a tick for {} with timestamp {}, which is before the simulated time of {}; not moving the clock back.",
+                self.symbols[symbol_ix].name, timestamp, self.sim_time
+            );
+            self.cs.warning(None, &errmsg);
+            return;
+        }
+
+        self.sim_time = timestamp;
+    }
+
+    /// Cancels every good 'til date order whose expiry has been reached, earliest expiry first.  Returns the number
+    /// of messages written into `buffer`.
+    pub fn expire_orders(&mut self, cur_index: usize, buffer: &mut Vec<TickOutput>) -> usize {
+        let sim_time = self.sim_time;
+        let mut due: Vec<(Uuid, u64)> = self.order_expiries.iter()
+            .filter(|&(_, &expires_at)| expires_at <= sim_time)
+            .map(|(order_uuid, expires_at)| (*order_uuid, *expires_at))
+            .collect();
+        due.sort_by(|&(ref uuid1, expires_at1), &(ref uuid2, expires_at2)| {
+            expires_at1.cmp(&expires_at2).then_with(|| uuid1.as_bytes().cmp(uuid2.as_bytes()))
+        });
+
+        let mut push_msg_count = 0;
+        for (order_uuid, _) in due {
+            self.order_expiries.remove(&order_uuid);
+            // orders that were filled or cancelled in the meantime are skipped
+            if let Some(res) = self.expire_order(order_uuid) {
+                self.push_msg(res.clone());
+                buffer[cur_index + push_msg_count] = TickOutput::Pushstream(self.timestamp, res);
+                push_msg_count += 1;
+            }
+        }
+
+        push_msg_count
+    }
+
+    /// Cancels a pending order because its time in force ran out and records the expiry in the trade log.  Returns
+    /// `None` if no pending order with the given UUID exists.
+    pub fn expire_order(&mut self, order_uuid: Uuid) -> Option<BrokerResult> {
+        let account_uuid = match self.find_order_account(order_uuid) {
+            Some(account_uuid) => account_uuid,
+            None => return None,
+        };
+        Some(match self.cancel_order(account_uuid, order_uuid) {
+            Ok(BrokerMessage::OrderCancelled{order_id, order, ..}) => {
+                self.log_trade(TradeEventType::Expired, order_id, &order);
+                Ok(BrokerMessage::OrderExpired{order_id: order_id, order: order, timestamp: self.timestamp})
+            },
+            res => res,
+        })
+    }
+
+    /// Expires an order that couldn't be filled at all when it was placed and so was never added to the books.
+    pub fn expire_unplaced_order(&mut self, order: Position) -> BrokerMessage {
+        let order_id = gen_uuid(self.prng);
+        self.log_trade(TradeEventType::Expired, order_id, &order);
+        BrokerMessage::OrderExpired{order_id: order_id, order: order, timestamp: self.timestamp}
+    }
+}
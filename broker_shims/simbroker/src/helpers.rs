@@ -7,6 +7,7 @@ use std::intrinsics::unlikely;
 use std::slice::{Iter, IterMut};
 use std::fmt::{self, Formatter, Debug};
 use std::collections::hash_map;
+use std::str::FromStr;
 
 use futures::{Future, Sink};
 
@@ -39,11 +40,97 @@ pub struct SimBrokerSettings {
     /// Base currency in which the SimBroker is funded.  Should be in the lowest division of that
     /// currency available (e.g. cents).
     pub fx_base_currency: String,
-    /// For forex, the amount of units of currency in one lot.
+    /// For forex, the amount of units of currency in one lot.  Used as the lot size of FX symbols that don't have
+    /// an entry in `symbol_specs`.
     pub fx_lot_size: usize,
     /// For forex, if true, calculates accurate position values by dynamically converting to the base
     /// currency.  If false, the rate must be set before broker initialization.
     pub fx_accurate_pricing: bool,
+    /// Name of the Postgres table to which the trade log is written.  If empty, the trade log is only
+    /// kept in memory.
+    pub trade_log_table: String,
+    /// How many trade log entries are buffered before they're written to Postgres.
+    pub trade_log_batch_size: usize,
+    /// Take an equity curve sample every this many ticks; 0 disables tick-based sampling.
+    pub equity_sample_ticks: usize,
+    /// Take an equity curve sample every this many milliseconds of simulated time; 0 disables
+    /// time-based sampling.
+    pub equity_sample_interval_ms: u64,
+    /// Maximum number of equity curve samples kept in memory before the series is thinned.
+    pub equity_curve_max_len: usize,
+    /// If true, equity curve samples are published to the `equity_<broker uuid>` Redis channel.
+    pub equity_publish: bool,
+    /// If true, orders submitted in reaction to a tick are filled at that tick's price and the stop loss
+    /// and take profit of newly filled positions are checked immediately.  If false, orders are held until
+    /// the next tick of their symbol arrives and are filled at its price.  See README.md for details.
+    pub fill_on_submission_tick: bool,
+    /// The maximum number of lots of an order that can be filled during a single tick; 0 means unlimited.
+    /// Larger orders are filled incrementally on successive ticks at each tick's price.
+    pub max_fill_per_tick: usize,
+    /// The maximum number of positions that can be open at once across all accounts; 0 means unlimited.  Orders
+    /// that would open another position once the limit is reached are rejected.
+    pub max_open_positions: usize,
+    /// Number of price levels on each side of the synthetic order book that orders are filled against; 0 fills
+    /// every order at the top of the book.  See `depth.rs`.
+    pub depth_levels: usize,
+    /// Distance in price units between adjacent levels of the synthetic order book
+    pub depth_level_spacing: usize,
+    /// Number of units available at each level of the synthetic order book
+    pub depth_level_volume: usize,
+    /// Currency in which account balances and realized P&L are denominated.  Profits of FX positions are
+    /// converted from the pair's quote currency into this currency when they're realized.
+    pub account_currency: String,
+    /// Number of decimals of the lowest division of the account currency (2 for cents).
+    pub account_currency_decimals: usize,
+    /// JSON-serialized `HashMap<String, f64>` of static exchange rates keyed by pair (e.g. `{"USDJPY": 110.25}`)
+    /// used to convert P&L into the account currency when no tickstream for the pair is registered.
+    pub conversion_rates: String,
+    /// How many events are buffered for each subscriber of the broker's event stream.  Once a subscriber's
+    /// buffer is full, the oldest events are dropped.
+    pub event_buffer_size: usize,
+    /// If true, broker events are published to the `events_<broker uuid>` Redis channel.
+    pub event_publish: bool,
+    /// Actions that could cause fills are refused if the last tick of their symbol is older than this many
+    /// milliseconds of simulated time; 0 disables the check.
+    pub max_price_age_ms: u64,
+    /// If true, actions that arrive while their symbol's price is stale are held until the symbol's next tick
+    /// instead of being rejected with `RejectionReason::StalePrice`.
+    pub stale_price_queue: bool,
+    /// Whether orders on the opposite side of an open position in the same symbol open a new position
+    /// (`Hedging`) or reduce the existing one (`Netting`).
+    pub position_mode: PositionMode,
+    /// JSON-serialized `HashMap<String, (f64, f64)>` of the swap paid on each unit of long and short positions at
+    /// every rollover, keyed by symbol (e.g. `{"EURUSD": [-0.35, 0.12]}`), in the lowest division of the account
+    /// currency.  Negative rates are charged.  Symbols without rates aren't affected by rollovers.
+    pub swap_rates: String,
+    /// Time of day at which rollover takes place, in milliseconds after midnight UTC of simulated time.
+    pub rollover_time_ms: u64,
+    /// Day of the week (0 is Sunday) on which three days of swap are applied to cover the weekend.
+    pub triple_swap_weekday: u64,
+    /// JSON-serialized `HashMap<String, SymbolSpec>` of contract specifications keyed by symbol.  Symbols without
+    /// an entry use the defaults of `SymbolSpec::default_for()`; fields left out of an entry are taken from
+    /// `SymbolSpec::default()`.
+    pub symbol_specs: String,
+    /// What to do with ticks whose bid is above their ask
+    pub crossed_tick_policy: TickPolicy,
+    /// What to do with ticks whose bid or ask is zero
+    pub zero_price_tick_policy: TickPolicy,
+    /// What to do with ticks that are older than the previous tick of their symbol by more than
+    /// `backwards_tick_tolerance_ms`
+    pub backwards_tick_policy: TickPolicy,
+    /// How many milliseconds a tick's timestamp may be before that of its symbol's previous tick without being
+    /// treated as an anomaly
+    pub backwards_tick_tolerance_ms: u64,
+    /// Probability from 0 to 1 that a market order is requoted instead of being filled; 0 disables requotes.
+    pub requote_probability: f64,
+    /// If nonzero, market orders are only eligible for requotes if their fill price is more than this many price
+    /// units away from the price the client saw when it submitted the order.
+    pub requote_threshold: usize,
+    /// Seed of the random number generator that decides which market orders are requoted
+    pub requote_seed: u32,
+    /// JSON-serialized `Vec<String>` of the topics the broker subscribes to once it's attached to a message bus
+    /// (e.g. `["signals", "hedges"]`).  See `SimBrokerClient::attach_bus`.
+    pub bus_topics: String,
 }
 
 impl Default for SimBrokerSettings {
@@ -60,10 +147,130 @@ impl Default for SimBrokerSettings {
             fx_base_currency: String::from("USD"),
             fx_lot_size: 1000,
             fx_accurate_pricing: false,
+            trade_log_table: String::new(),
+            trade_log_batch_size: 500,
+            equity_sample_ticks: 100,
+            equity_sample_interval_ms: 0,
+            equity_curve_max_len: 10000,
+            equity_publish: false,
+            fill_on_submission_tick: false,
+            max_fill_per_tick: 0,
+            max_open_positions: 0,
+            depth_levels: 0,
+            depth_level_spacing: 1,
+            depth_level_volume: 100,
+            account_currency: String::from("USD"),
+            account_currency_decimals: 2,
+            conversion_rates: String::new(),
+            event_buffer_size: 1000,
+            event_publish: false,
+            max_price_age_ms: 0,
+            stale_price_queue: false,
+            position_mode: PositionMode::Hedging,
+            swap_rates: String::new(),
+            rollover_time_ms: 22 * 60 * 60 * 1000, // 5 PM New York time
+            triple_swap_weekday: 3, // Wednesday
+            symbol_specs: String::new(),
+            crossed_tick_policy: TickPolicy::Reject,
+            zero_price_tick_policy: TickPolicy::Reject,
+            backwards_tick_policy: TickPolicy::Abort,
+            backwards_tick_tolerance_ms: 1000,
+            requote_probability: 0.,
+            requote_threshold: 0,
+            requote_seed: 0,
+            bus_topics: String::new(),
         }
     }
 }
 
+/// How orders interact with open positions on the other side of the same symbol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PositionMode {
+    /// Every order opens its own position, so longs and shorts in a symbol can be held at the same time.  Each
+    /// position ties up buying power for its full value.
+    Hedging,
+    /// Orders are first netted against the account's opposite positions in the symbol, oldest first, closing
+    /// them at market price; only the units left over open a new position.  Since reducing a position frees
+    /// the buying power it held, margin is only required for the net exposure.
+    Netting,
+}
+
+impl FromStr for PositionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PositionMode, String> {
+        match s.to_lowercase().as_str() {
+            "hedging" => Ok(PositionMode::Hedging),
+            "netting" => Ok(PositionMode::Netting),
+            _ => Err(format!("Unknown position mode: {}", s)),
+        }
+    }
+}
+
+/// The contract specification of a symbol.  Volumes are in the same units as position sizes (lots for FX).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SymbolSpec {
+    /// Size of one pip in units of the quote currency (0.0001 for most pairs, 0.01 for JPY-quoted pairs)
+    pub pip_size: f64,
+    /// Units of the base currency in one lot; 1 for symbols that aren't FX
+    pub lot_size: usize,
+    /// Smallest volume an order can have
+    pub min_volume: usize,
+    /// Order volumes must be a multiple of this
+    pub volume_step: usize,
+    /// Largest volume an order can have; 0 means unlimited
+    pub max_volume: usize,
+}
+
+impl Default for SymbolSpec {
+    fn default() -> SymbolSpec {
+        SymbolSpec {
+            pip_size: 0.0001,
+            lot_size: 1000,
+            min_volume: 1,
+            volume_step: 1,
+            max_volume: 0,
+        }
+    }
+}
+
+impl SymbolSpec {
+    /// Returns the specification used for a symbol that has no entry in the `symbol_specs` setting.  FX pairs
+    /// use standard pip sizes and the `fx_lot_size` setting; other symbols have a pip the size of their smallest
+    /// price increment and a lot size of 1.
+    pub fn default_for(name: &str, is_fx: bool, decimal_precision: usize, settings: &SimBrokerSettings) -> SymbolSpec {
+        let mut spec = SymbolSpec::default();
+        if !is_fx {
+            spec.pip_size = 10f64.powi(-(decimal_precision as i32));
+            spec.lot_size = 1;
+        } else {
+            if fx_currencies(name).map(|(_, quote)| quote == "JPY").unwrap_or(false) {
+                spec.pip_size = 0.01;
+            }
+            spec.lot_size = settings.fx_lot_size;
+        }
+
+        spec
+    }
+
+    /// Makes sure that an order of the given volume can be placed, returning a rejection with
+    /// `RejectionReason::InvalidSize` if it can't.
+    pub fn check_volume(&self, volume: usize) -> Result<(), BrokerError> {
+        let message = if volume == 0 || volume < self.min_volume {
+            format!("The order volume of {} is below the minimum volume of {}.", volume, cmp::max(self.min_volume, 1))
+        } else if self.max_volume != 0 && volume > self.max_volume {
+            format!("The order volume of {} is above the maximum volume of {}.", volume, self.max_volume)
+        } else if volume % self.volume_step != 0 {
+            format!("The order volume of {} isn't a multiple of the volume step of {}.", volume, self.volume_step)
+        } else {
+            return Ok(());
+        };
+
+        Err(BrokerError::rejected(RejectionReason::InvalidSize, &message))
+    }
+}
+
 impl SimBrokerSettings {
     /// Returns the delay in ns for executing a particular `BrokerAction`.
     pub fn get_delay(&self, action: &BrokerAction) -> u64 {
@@ -199,6 +406,22 @@ impl Debug for WorkUnit {
 
 impl Eq for WorkUnit {}
 
+impl WorkUnit {
+    /// Determines the order in which work units with identical timestamps are processed; lower values
+    /// are processed first.  New ticks always come first so that an order modification or cancellation
+    /// arriving at the same instant as a tick that fills the order resolves deterministically: the
+    /// fill takes place and the modification sees an already filled order.
+    pub fn priority(&self) -> u8 {
+        match *self {
+            WorkUnit::NewTick(_, _) => 0,
+            WorkUnit::ActionComplete(_, _) => 1,
+            WorkUnit::ClientTick(_, _) => 2,
+            WorkUnit::Response(_, _) => 3,
+            WorkUnit::Notification(_) => 4,
+        }
+    }
+}
+
 /// A timestamped unit of data for the priority queue.
 #[derive(PartialEq, Eq)]
 pub struct QueueItem {
@@ -208,17 +431,17 @@ pub struct QueueItem {
 
 impl PartialOrd for QueueItem {
     fn partial_cmp(&self, other: &QueueItem) -> Option<::std::cmp::Ordering> {
-        // Returns the OPPOSITE of the actual order because the `BinaryHeap` is a MAX-heap and
-        // we want to pop off the events with the smallest timestamps first.
-        Some(other.timestamp.cmp(&self.timestamp))
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for QueueItem {
     fn cmp(&self, other: &Self) -> ::std::cmp::Ordering {
         // Returns the OPPOSITE of the actual order because the `BinaryHeap` is a MAX-heap and
-        // we want to pop off the events with the smallest timestamps first.
+        // we want to pop off the events with the smallest timestamps first.  Ties are broken by
+        // the priority of the contained work unit.
         other.timestamp.cmp(&self.timestamp)
+            .then_with(|| other.unit.priority().cmp(&self.unit.priority()))
     }
 }
 
@@ -237,6 +460,10 @@ pub struct Symbol {
     pub price: (usize, usize),
     /// The next tick for this stream; used for ordering in SimBroker's internal queue
     pub next_tick: Option<Tick>,
+    /// Timestamp at which the broker received the symbol's last tick.  `None` for statically priced symbols.
+    pub last_tick_time: Option<u64>,
+    /// (bid, ask) of the last tick delivered to the client, which is the price the client's orders are based on
+    pub client_price: Option<(usize, usize)>,
 }
 
 impl Symbol {
@@ -253,6 +480,8 @@ impl Symbol {
             },
             price: price,
             next_tick: None,
+            last_tick_time: None,
+            client_price: None,
         }
     }
 
@@ -273,6 +502,8 @@ impl Symbol {
             },
             price: (0, 0),
             next_tick: Some(future_tick),
+            last_tick_time: None,
+            client_price: None,
         }
     }
 
@@ -474,7 +705,7 @@ impl SimulationQueue {
 }
 
 /// The units stored in the cache; contains the position and some data to easily locate it in the main HashMap.
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct CachedPosition {
     pub pos_uuid: Uuid,
     pub acct_uuid: Uuid,
@@ -663,6 +894,82 @@ pub fn convert_decimals(in_price: usize, in_decimals: usize, out_decimals: usize
     }
 }
 
+/// Splits the name of an FX pair such as `EURUSD` into its base and quote currencies, returning an error
+/// rather than panicking if it isn't made up of two three-byte currency codes.
+pub fn fx_currencies(pair: &str) -> Result<(&str, &str), BrokerError> {
+    if pair.len() != 6 || !pair.is_char_boundary(3) {
+        return Err(BrokerError::Message{
+            message: format!("{} isn't an FX pair made up of two three-letter currency codes.", pair),
+        });
+    }
+
+    Ok((&pair[0..3], &pair[3..6]))
+}
+
+/// Adds realized profit (or subtracts realized loss) to a ledger's buying power, which can't drop below 0.
+pub fn realize_pnl(ledger: &mut Ledger, pnl: isize) {
+    let new_buying_power = ledger.buying_power as isize + pnl;
+    ledger.buying_power = if new_buying_power < 0 { 0 } else { new_buying_power as usize };
+}
+
+/// Parses the long and short swap rates of each symbol out of the `swap_rates` setting.
+pub fn parse_swap_rates(settings: &SimBrokerSettings) -> Result<HashMap<String, (f64, f64)>, BrokerError> {
+    if settings.swap_rates.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    serde_json::from_str(&settings.swap_rates)
+        .map_err(|_| BrokerError::Message{message: String::from("Unable to deserialize the input swap rates into a map!")})
+}
+
+/// Parses the per-symbol contract specifications out of the `symbol_specs` setting.
+pub fn parse_symbol_specs(settings: &SimBrokerSettings) -> Result<HashMap<String, SymbolSpec>, BrokerError> {
+    if settings.symbol_specs.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let specs: HashMap<String, SymbolSpec> = serde_json::from_str(&settings.symbol_specs)
+        .map_err(|_| BrokerError::Message{message: String::from("Unable to deserialize the input symbol specs into a map!")})?;
+    for (symbol, spec) in specs.iter() {
+        if spec.pip_size <= 0. || spec.lot_size == 0 || spec.volume_step == 0 {
+            return Err(BrokerError::Message{
+                message: format!("The pip size, lot size, and volume step of {} must all be positive.", symbol),
+            });
+        }
+    }
+
+    Ok(specs)
+}
+
+/// Parses the static exchange rates out of the `conversion_rates` setting.
+pub fn parse_conversion_rates(settings: &SimBrokerSettings) -> Result<HashMap<String, f64>, BrokerError> {
+    if settings.conversion_rates.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    serde_json::from_str(&settings.conversion_rates)
+        .map_err(|_| BrokerError::Message{message: String::from("Unable to deserialize the input conversion rates into a map!")})
+}
+
+/// Parses the topics to subscribe to out of the `bus_topics` setting.
+pub fn parse_bus_topics(settings: &SimBrokerSettings) -> Result<Vec<String>, BrokerError> {
+    if settings.bus_topics.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    serde_json::from_str(&settings.bus_topics)
+        .map_err(|_| BrokerError::Message{message: String::from("Unable to deserialize the input bus topics into a list!")})
+}
+
+/// Creates a Redis client if the settings call for anything to be published.
+pub fn get_redis_client(settings: &SimBrokerSettings) -> Option<redis::Client> {
+    if settings.equity_publish || settings.event_publish {
+        Some(get_client(CONF.redis_host))
+    } else {
+        None
+    }
+}
+
 /// Creates a new deterministly random byte given a PRNG source.
 pub fn rand_byte(prng: *mut c_void) -> u8 {
     unsafe { rand_int_range(prng, 0, 255) as u8 }
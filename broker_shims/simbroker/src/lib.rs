@@ -18,7 +18,10 @@ extern crate tickgrinder_util;
 extern crate from_hashmap;
 extern crate libc;
 extern crate rand;
+extern crate postgres;
+extern crate redis;
 
+use std::cmp;
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
 use std::collections::BinaryHeap;
@@ -33,12 +36,13 @@ use futures::{Stream, oneshot, Oneshot, Complete};
 use futures::stream::BoxStream;
 use futures::sync::mpsc::{channel, Sender};
 use uuid::Uuid;
-use rand::Rng;
+use rand::{Rng, XorShiftRng};
 
 use tickgrinder_util::trading::tick::*;
 pub use tickgrinder_util::trading::broker::*;
 use tickgrinder_util::trading::trading_condition::*;
 use tickgrinder_util::transport::command_server::CommandServer;
+use tickgrinder_util::transport::redis::{get_client, publish};
 use tickgrinder_util::transport::tickstream::{TickGenerator, TickGenerators};
 use tickgrinder_util::conf::CONF;
 
@@ -49,6 +53,28 @@ mod client;
 pub use self::client::*;
 mod superlog;
 use superlog::SuperLogger;
+mod trade_log;
+pub use trade_log::*;
+mod equity;
+pub use equity::*;
+mod events;
+pub use events::*;
+mod blotter;
+pub use blotter::*;
+mod stats;
+pub use stats::*;
+mod rollover;
+mod expiry;
+mod requote;
+pub use requote::*;
+mod depth;
+pub use depth::*;
+mod validation;
+pub use validation::*;
+mod bench;
+pub use bench::*;
+mod snapshot;
+pub use snapshot::*;
 
 // link with the libboost_random wrapper
 #[link(name="rand_bindings")]
@@ -60,6 +86,9 @@ extern {
 /// A simulated broker that is used as the endpoint for trading activity in backtests.  This is the broker backend
 /// that creates/ingests streams that interact with the client.
 pub struct SimBroker {
+    /// Identifies this SimBroker to the rest of the platform.  Not generated from the deterministic PRNG since
+    /// it has no effect on the simulation.
+    pub uuid: Uuid,
     /// Contains all the accounts simulated by the SimBroker
     pub accounts: Accounts,
     /// A copy of the settings generated from the input HashMap
@@ -82,6 +111,47 @@ pub struct SimBroker {
     logger: SuperLogger,
     /// A source of deterministic PRNG to be used to generating Uuids.
     prng: *mut c_void,
+    /// Record of all fills and closures that have taken place on the broker
+    trade_log: TradeLog,
+    /// Periodic samples of the balance and equity of the broker's accounts
+    equity_curve: EquityCurve,
+    /// Redis client used to publish equity samples if enabled
+    redis_client: Option<redis::Client>,
+    /// Actions that can cause fills, keyed by symbol index, that are waiting for the next tick of
+    /// their symbol before being executed.  Only used if `fill_on_submission_tick` is false.
+    deferred_actions: HashMap<usize, Vec<(Complete<BrokerResult>, BrokerAction)>>,
+    /// Static exchange rates parsed from the `conversion_rates` setting, keyed by pair
+    conversion_rates: HashMap<String, f64>,
+    /// Senders for the buffers of every subscriber to the broker's event stream
+    event_senders: Vec<EventSender>,
+    /// Total profit or loss realized by closing positions, in the same units as account balances
+    realized_pnl: f64,
+    /// The generators of the tickstreams defined in the settings, kept alive for as long as their streams are
+    /// in use since some of them stop producing ticks once they're dropped
+    tick_generators: Vec<Box<TickGenerator + Send>>,
+    /// Long and short swap rates parsed from the `swap_rates` setting, keyed by symbol
+    swap_rates: HashMap<String, (f64, f64)>,
+    /// Number of the day since the epoch of the last rollover that was applied
+    last_rollover: Option<u64>,
+    /// Total swap credited to (or charged from, if negative) all positions at rollovers
+    total_swap: f64,
+    /// Combined equity of all accounts at the start of the simulation, including the value of preloaded positions
+    starting_equity: f64,
+    /// Functions called with every tick the broker processes, in the order they were registered
+    tick_callbacks: Vec<Box<Fn(&Tick) + Send>>,
+    /// Contract specifications parsed from the `symbol_specs` setting, keyed by symbol
+    symbol_specs: HashMap<String, SymbolSpec>,
+    /// The latest tick timestamp the broker has processed; see `sim_time()`
+    sim_time: u64,
+    /// The times at which good 'til date orders expire, keyed by order UUID.  Entries of orders that were filled
+    /// or cancelled are only removed once their expiry passes.
+    order_expiries: HashMap<Uuid, u64>,
+    /// Anomaly counts and other state used to check incoming ticks
+    tick_validator: TickValidationState,
+    /// Decides which market orders are requoted; seeded from the `requote_seed` setting
+    requote_rng: XorShiftRng,
+    /// `(price, quantity)` levels of the order books set with `set_order_book_depth`, keyed by symbol index
+    order_books: HashMap<usize, Vec<(usize, f64)>>,
 }
 
 // .-.
@@ -123,7 +193,17 @@ impl SimBroker {
         let tickstreams: Vec<(String, TickGenerators, bool, usize)> = serde_json::from_str(&settings.tickstreams)
             .map_err(|_| BrokerError::Message{message: String::from("Unable to deserialize the input tickstreams into a vector!")})?;
 
+        let conversion_rates = parse_conversion_rates(&settings)?;
+        let swap_rates = parse_swap_rates(&settings)?;
+        let symbol_specs = parse_symbol_specs(&settings)?;
+
+        let trade_log = TradeLog::new(&settings);
+        let equity_curve = EquityCurve::new(settings.equity_curve_max_len);
+        let redis_client = get_redis_client(&settings);
+        let requote_rng = requote_rng(&settings);
+        let starting_equity = settings.starting_balance as f64;
         let mut sim = SimBroker {
+            uuid: Uuid::new_v4(),
             accounts: accounts,
             settings: settings,
             symbols: Symbols::new(cs.clone()),
@@ -135,16 +215,121 @@ impl SimBroker {
             cs: cs,
             logger: logger,
             prng: rng,
+            trade_log: trade_log,
+            equity_curve: equity_curve,
+            redis_client: redis_client,
+            deferred_actions: HashMap::new(),
+            conversion_rates: conversion_rates,
+            event_senders: Vec::new(),
+            realized_pnl: 0.,
+            tick_generators: Vec::new(),
+            swap_rates: swap_rates,
+            last_rollover: None,
+            total_swap: 0.,
+            starting_equity: starting_equity,
+            tick_callbacks: Vec::new(),
+            symbol_specs: symbol_specs,
+            sim_time: 0,
+            order_expiries: HashMap::new(),
+            tick_validator: TickValidationState::default(),
+            requote_rng: requote_rng,
+            order_books: HashMap::new(),
         };
 
-        // create an actual tickstream for each of the definitions and subscribe to all of them
+        sim.register_settings_tickstreams(tickstreams)?;
+
+        Ok(sim)
+    }
+
+    /// Creates an actual tickstream for each of the definitions and subscribes to all of them.
+    fn register_settings_tickstreams(
+        &mut self, tickstreams: Vec<(String, TickGenerators, bool, usize)>
+    ) -> Result<(), BrokerError> {
         for (name, def, is_fx, decimals) in tickstreams {
-            let mut gen: Box<TickGenerator> = def.get();
+            let mut gen: Box<TickGenerator + Send> = def.get();
             let strm = gen.get_raw().map_err(|s| BrokerError::Message{message: s})?;
-            sim.register_tickstream(name, strm, is_fx, decimals)?;
+            self.register_tickstream(name, strm, is_fx, decimals)?;
+            self.tick_generators.push(gen);
         }
 
-        Ok(sim)
+        Ok(())
+    }
+
+    /// Returns the broker to the state it was in when it was created so that it can be reused for another
+    /// simulation.  All positions and orders are discarded, every account is restored to the starting balance,
+    /// the trade log and equity curve are cleared, and all registered tickstreams are dropped and replaced with
+    /// the ones defined in the settings.  If `settings` is supplied, it replaces the broker's current settings.
+    ///
+    /// Any actions that were submitted by the client but not yet processed are answered with an error.  Unflushed
+    /// trade log entries are flushed before the log is cleared.  Event subscribers and tick callbacks stay registered.
+    pub fn reset(&mut self, settings: Option<SimBrokerSettings>) -> BrokerResult {
+        let settings = settings.unwrap_or_else(|| self.settings.clone());
+        let tickstreams: Vec<(String, TickGenerators, bool, usize)> = serde_json::from_str(&settings.tickstreams)
+            .map_err(|_| BrokerError::Message{message: String::from("Unable to deserialize the input tickstreams into a vector!")})?;
+        let conversion_rates = parse_conversion_rates(&settings)?;
+        let swap_rates = parse_swap_rates(&settings)?;
+        let symbol_specs = parse_symbol_specs(&settings)?;
+
+        if let Err(err) = self.trade_log.flush() {
+            self.cs.error(None, &format!("Unable to flush the trade log before resetting: {}", err));
+        }
+
+        // answer everything still waiting to be processed so that no client blocks on it forever
+        let reset_err = || Err(BrokerError::rejected(
+            RejectionReason::BrokerShuttingDown, "The SimBroker was reset before this action was processed."
+        ));
+        if let Some(ref rx) = self.client_rx {
+            while let Ok((_, complete)) = rx.try_recv() {
+                complete.complete(reset_err());
+            }
+        }
+        while let Some(item) = self.pq.pop() {
+            if let WorkUnit::ActionComplete(complete, _) = item.unit {
+                complete.complete(reset_err());
+            }
+        }
+        for (_, actions) in self.deferred_actions.drain() {
+            for (complete, _) in actions {
+                complete.complete(reset_err());
+            }
+        }
+
+        // keep the account UUIDs so that clients can keep using the ones they already have
+        let account_uuids: Vec<Uuid> = self.accounts.data.keys().cloned().collect();
+        self.accounts = Accounts::new(self.logger.clone());
+        for uuid in account_uuids {
+            self.accounts.insert(uuid, Account {
+                uuid: uuid,
+                ledger: Ledger::new(settings.starting_balance),
+                live: false,
+            });
+        }
+
+        self.symbols = Symbols::new(self.cs.clone());
+        self.tick_generators.clear();
+        self.pq = SimulationQueue::new();
+        self.timestamp = 0;
+        self.sim_time = 0;
+        self.order_expiries.clear();
+        self.tick_validator = TickValidationState::default();
+        self.requote_rng = requote_rng(&settings);
+        self.order_books.clear();
+        self.realized_pnl = 0.;
+        self.last_rollover = None;
+        self.total_swap = 0.;
+        self.starting_equity = (settings.starting_balance * self.accounts.data.len()) as f64;
+        self.trade_log = TradeLog::new(&settings);
+        self.equity_curve = EquityCurve::new(settings.equity_curve_max_len);
+        self.redis_client = get_redis_client(&settings);
+        self.conversion_rates = conversion_rates;
+        self.swap_rates = swap_rates;
+        self.symbol_specs = symbol_specs;
+        self.settings = settings;
+
+        self.register_settings_tickstreams(tickstreams)?;
+        self.cs.notice(None, "SimBroker has been reset.");
+
+        Ok(BrokerMessage::Success)
     }
 
     /// Starts the simulation process.  Ticks are read in from the inputs and processed internally into
@@ -198,23 +383,13 @@ impl SimBroker {
         match item.unit {
             // A tick arriving at the broker.  The client doesn't get to know until after network delay.
             WorkUnit::NewTick(symbol_ix, tick) => {
-                // update the price for the popped tick's symbol
-                let price = (tick.bid, tick.ask);
-                self.symbols[symbol_ix].price = price;
-                // push the ClientTick event back into the queue + network delay
-                self.pq.push(QueueItem {
-                    timestamp: tick.timestamp as u64 + self.settings.ping_ns,
-                    unit: WorkUnit::ClientTick(symbol_ix, tick),
-                });
-                // check to see if we have any actions to take on open positions and take them if we do
-                self.logger.event_log(
-                    self.timestamp,
-                    &format!("Ticking positions in response to new tick: ({}, {:?})", symbol_ix, tick)
-                );
-                client_event_count += self.tick_positions(symbol_ix, (tick.bid, tick.ask,), client_event_count, buffer);
-                // push the next future tick into the queue
-                self.logger.event_log(self.timestamp, &format!("Pushing ClientTick into queue: ({}, {:?})", symbol_ix, tick));
-                self.pq.push_next_tick(&mut self.symbols);
+                client_event_count += self.process_new_tick(symbol_ix, tick, client_event_count, buffer);
+                // an aborted simulation doesn't read any more ticks
+                if self.abort_reason().is_none() {
+                    // push the next future tick into the queue
+                    self.logger.event_log(self.timestamp, &format!("Pushing ClientTick into queue: ({}, {:?})", symbol_ix, tick));
+                    self.pq.push_next_tick(&mut self.symbols);
+                }
             },
             // A tick arriving at the client.  We now send it down the Client's channels and block
             // until it is consumed.
@@ -224,6 +399,7 @@ impl SimBroker {
                 self.logger.event_log(self.timestamp, &format!("Sending tick to client: ({}, {:?})", symbol_ix, tick));
                 // send the tick through the client stream, blocking until it is consumed by the client.
                 inner_symbol.send_client(tick);
+                inner_symbol.client_price = Some((tick.bid, tick.ask));
                 // put the message into the result buffer and increment its length
                 buffer[client_event_count] = TickOutput::Tick(symbol_ix, tick);
                 client_event_count += 1;
@@ -231,17 +407,8 @@ impl SimBroker {
             // The moment the broker finishes processing an action and the action takes place.
             // Begins the network delay for the trip back to the client.
             WorkUnit::ActionComplete(future, action) => {
-                // process the message and re-insert the response into the queue
                 assert_eq!(self.timestamp, item.timestamp);
-                let res = self.exec_action(&action);
-                // calculate when the response would be recieved by the client
-                // then re-insert the response into the queue
-                let res_time = item.timestamp + self.settings.ping_ns;
-                let item = QueueItem {
-                    timestamp: res_time,
-                    unit: WorkUnit::Response(future, res),
-                };
-                self.pq.push(item);
+                client_event_count += self.process_action(future, action, client_event_count, buffer);
             },
             // The moment a response reaches the client.
             WorkUnit::Response(future, res) => {
@@ -279,19 +446,241 @@ impl SimBroker {
         client_event_count
     }
 
+    /// Handles a tick arriving at the broker.  Operations happen in this order:
+    ///
+    ///  1. The symbol's price and the simulated clock are updated.
+    ///  2. Good 'til date orders whose expiry has been reached are cancelled.
+    ///  3. If `fill_on_submission_tick` is false, actions submitted since the last tick are executed at the new price.
+    ///  4. Pending orders are checked and filled if the new price satisfies them.
+    ///  5. Stop losses and take profits of open positions are checked.  If `fill_on_submission_tick` is false,
+    ///     positions that were filled during this tick are skipped until the next one.
+    ///  6. The tick is scheduled for delivery to the client after network delay.
+    ///
+    /// Before any of that, the tick is checked for anomalies by `validate_tick()`; ticks that are discarded as a
+    /// result have no effect.
+    ///
+    /// Returns the number of messages written into `buffer`.
+    fn process_new_tick(&mut self, symbol_ix: usize, tick: Tick, cur_index: usize, buffer: &mut Vec<TickOutput>) -> usize {
+        let tick = match self.validate_tick(symbol_ix, tick) {
+            Some(tick) => tick,
+            None => return 0,
+        };
+        self.apply_rollovers();
+
+        let price = (tick.bid, tick.ask);
+        self.symbols[symbol_ix].price = price;
+        self.symbols[symbol_ix].last_tick_time = Some(self.timestamp);
+        self.advance_clock(symbol_ix, tick.timestamp);
+        let expired_count = self.expire_orders(cur_index, buffer);
+
+        if let Some(deferred) = self.deferred_actions.remove(&symbol_ix) {
+            for (future, action) in deferred {
+                self.logger.event_log(self.timestamp, &format!("Executing deferred action: {:?}", action));
+                let res = self.exec_action(&action);
+                self.push_response(future, res);
+            }
+        }
+
+        // check to see if we have any actions to take on open positions and take them if we do
+        self.logger.event_log(
+            self.timestamp,
+            &format!("Ticking positions in response to new tick: ({}, {:?})", symbol_ix, tick)
+        );
+        let event_count = expired_count + self.tick_positions(symbol_ix, price, cur_index + expired_count, buffer);
+        for cb in self.tick_callbacks.iter() {
+            cb(&tick);
+        }
+        self.sample_equity_if_due();
+
+        // push the ClientTick event back into the queue + network delay
+        self.pq.push(QueueItem {
+            timestamp: tick.timestamp as u64 + self.settings.ping_ns,
+            unit: WorkUnit::ClientTick(symbol_ix, tick),
+        });
+
+        event_count
+    }
+
+    /// Handles the moment that the broker finishes processing an action.  Actions that can cause fills are
+    /// either executed at the current price and followed by an immediate check of the symbol's orders and
+    /// positions or deferred until the symbol's next tick depending on `fill_on_submission_tick`.  If the
+    /// symbol's price is older than `max_price_age_ms`, the action is rejected or deferred until the next tick
+    /// depending on `stale_price_queue`.  Returns the number of messages written into `buffer`.
+    fn process_action(
+        &mut self, future: Complete<BrokerResult>, action: BrokerAction, cur_index: usize, buffer: &mut Vec<TickOutput>
+    ) -> usize {
+        let symbol_ix = match self.fill_symbol(&action) {
+            Some(ix) => ix,
+            None => {
+                let res = self.exec_action(&action);
+                self.push_response(future, res);
+                return 0;
+            },
+        };
+
+        let stale = match self.stale_price_age(symbol_ix) {
+            Some(age_ms) if !self.settings.stale_price_queue => {
+                let res = Err(BrokerError::rejected(RejectionReason::StalePrice, &format!(
+                    "The last price of {} is {}ms old.", self.symbols[symbol_ix].name, age_ms
+                )));
+                self.record_rejection(&action, &res);
+                self.push_response(future, res);
+                return 0;
+            },
+            Some(_) => true,
+            None => false,
+        };
+
+        if stale || !self.settings.fill_on_submission_tick {
+            self.logger.event_log(self.timestamp, &format!("Deferring action until next tick: {:?}", action));
+            self.deferred_actions.entry(symbol_ix).or_insert_with(Vec::new).push((future, action));
+            return 0;
+        }
+
+        let res = self.exec_action(&action);
+        self.push_response(future, res);
+        let price = self.symbols[symbol_ix].price;
+        self.tick_positions(symbol_ix, price, cur_index, buffer)
+    }
+
+    /// Returns how old the symbol's price is if it's older than `max_price_age_ms` and `None` if it's fresh
+    /// enough to trade against.  Statically priced symbols never go stale.
+    fn stale_price_age(&self, symbol_ix: usize) -> Option<u64> {
+        if self.settings.max_price_age_ms == 0 {
+            return None;
+        }
+
+        match self.symbols[symbol_ix].last_tick_time {
+            Some(last_tick_time) if self.timestamp > last_tick_time + self.settings.max_price_age_ms => {
+                Some(self.timestamp - last_tick_time)
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the index of the symbol whose price the action could be filled at or `None` if the action
+    /// can't cause any fills or refers to something that doesn't exist.
+    fn fill_symbol(&self, action: &BrokerAction) -> Option<usize> {
+        match action {
+            &BrokerAction::TradingAction{account_uuid, ref action} => match action {
+                &TradingAction::MarketOrder{ref symbol, ..} | &TradingAction::LimitOrder{ref symbol, ..} => {
+                    self.symbols.get_index(symbol)
+                },
+                &TradingAction::MarketClose{uuid, ..} | &TradingAction::ModifyOrder{uuid, ..} => {
+                    let ledger = match self.accounts.data.get(&account_uuid) {
+                        Some(acct) => &acct.ledger,
+                        None => return None,
+                    };
+                    ledger.open_positions.get(&uuid)
+                        .or_else(|| ledger.pending_positions.get(&uuid))
+                        .map(|pos| pos.symbol_id)
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Schedules the response to an action to be delivered to the client after network delay.
+    fn push_response(&mut self, future: Complete<BrokerResult>, res: BrokerResult) {
+        let res_time = self.timestamp + self.settings.ping_ns;
+        self.pq.push(QueueItem {
+            timestamp: res_time,
+            unit: WorkUnit::Response(future, res),
+        });
+    }
+
     /// Immediately sends a message over the broker's push channel.  Should only be called from within
     /// the SimBroker's internal event handling loop since it immediately sends the message.
-    fn push_msg(&mut self, _: BrokerResult) {
+    fn push_msg(&mut self, msg: BrokerResult) {
+        self.emit_event(BrokerEvent::from_result(msg));
         // self.logger.event_log(self.timestamp, &format!("`push_msg()` sending message to client: {:?}", msg));
         // let sender = mem::replace(&mut self.push_stream_handle, None).unwrap();
         // let new_sender = sender.send((self.timestamp, msg)).wait().expect("Unable to push_msg");
         // mem::replace(&mut self.push_stream_handle, Some(new_sender));
     }
 
+    /// Registers a function to be called with every valid tick the broker processes, after orders and positions
+    /// have been matched against it.  Callbacks are called in the order they were registered.
+    pub fn register_tick_callback(&mut self, cb: Box<Fn(&Tick) + Send>) {
+        self.tick_callbacks.push(cb);
+    }
+
+    /// Returns a stream of all events that take place on the broker from now on.  Each call creates a new
+    /// subscription with its own buffer of `event_buffer_size` events; see `events.rs` for the policy used
+    /// when a subscriber falls behind.
+    pub fn events(&mut self) -> EventStream {
+        let (tx, rx) = event_channel(self.settings.event_buffer_size);
+        self.event_senders.push(tx);
+        rx
+    }
+
+    /// Delivers an event to all event stream subscribers and publishes it to Redis if enabled.
+    fn emit_event(&mut self, event: BrokerEvent) {
+        if self.settings.event_publish {
+            if let Some(ref client) = self.redis_client {
+                // events aren't serializable, so they're published in their debug representation for display
+                let published = PublishedEvent {timestamp: self.timestamp, event: format!("{:?}", event)};
+                match serde_json::to_string(&published) {
+                    Ok(ser) => publish(client, &format!("events_{}", self.uuid.hyphenated()), &ser),
+                    Err(err) => self.cs.error(None, &format!("Unable to serialize broker event: {:?}", err)),
+                }
+            }
+        }
+
+        for sender in self.event_senders.iter() {
+            sender.send(self.timestamp, event.clone());
+        }
+    }
+
+    /// Executes the supplied action, adding it to the trade log if it's an order that was rejected.
+    fn exec_action(&mut self, cmd: &BrokerAction) -> BrokerResult {
+        let res = self.carry_out_action(cmd);
+        self.record_rejection(cmd, &res);
+        res
+    }
+
+    /// Adds an entry to the trade log if the result is the rejection of a new order.  Since no position was
+    /// created, the entry has a nil position UUID and the order's limit price (0 for market orders).
+    fn record_rejection(&mut self, cmd: &BrokerAction, res: &BrokerResult) {
+        let reason = match res {
+            &Err(ref err) => match err.rejection_reason() {
+                Some(reason) => reason,
+                None => return,
+            },
+            &Ok(_) => return,
+        };
+        let (symbol, long, size, price, tag) = match cmd {
+            &BrokerAction::TradingAction{action: TradingAction::MarketOrder{ref symbol, long, size, ref tag, ..}, ..} => {
+                (symbol.clone(), long, size, 0, tag.clone())
+            },
+            &BrokerAction::TradingAction{action: TradingAction::LimitOrder{ref symbol, long, size, entry_price, ref tag, ..}, ..} => {
+                (symbol.clone(), long, size, entry_price, tag.clone())
+            },
+            _ => return,
+        };
+
+        let entry = TradeLogEntry {
+            timestamp: self.timestamp,
+            event: TradeEventType::Rejected,
+            position_uuid: Uuid::nil(),
+            symbol: symbol,
+            long: long,
+            size: size,
+            price: price,
+            rejection_reason: Some(reason),
+            swap: None,
+            tag: tag,
+        };
+        if let Err(err) = self.trade_log.record(entry) {
+            self.cs.error(None, &err);
+        }
+    }
+
     /// Actually carries out the action of the supplied BrokerAction (simulates it being received and processed)
     /// by a remote broker) and returns the result of the action.  The provided timestamp is that of
     /// when it was received by the broker (after delays and simulated lag).
-    fn exec_action(&mut self, cmd: &BrokerAction) -> BrokerResult {
+    fn carry_out_action(&mut self, cmd: &BrokerAction) -> BrokerResult {
         self.logger.event_log(self.timestamp, &format!("`exec_action()`: {:?}", cmd));
         match cmd {
             &BrokerAction::Ping => {
@@ -299,19 +688,21 @@ impl SimBroker {
             },
             &BrokerAction::TradingAction{account_uuid, ref action} => {
                 match action {
-                    &TradingAction::MarketOrder{ref symbol, long, size, stop, take_profit, max_range} => {
+                    &TradingAction::MarketOrder{ref symbol, long, size, stop, take_profit, max_range, ref tag} => {
                         match self.symbols.get_index(symbol) {
-                            Some(ix) => self.market_open(account_uuid, ix, long, size, stop, take_profit, max_range),
-                            None => Err(BrokerError::NoSuchSymbol),
+                            Some(ix) => self.market_open(account_uuid, ix, long, size, stop, take_profit, max_range, tag.clone()),
+                            None => Err(BrokerError::rejected(RejectionReason::UnknownSymbol, &format!("No symbol named {}.", symbol))),
                         }
                     },
                     &TradingAction::MarketClose{uuid, size} => {
                         self.market_close(account_uuid, uuid, size)
                     },
-                    &TradingAction::LimitOrder{ref symbol, long, size, stop, take_profit, entry_price} => {
+                    &TradingAction::LimitOrder{ref symbol, long, size, stop, take_profit, entry_price, time_in_force, ref tag} => {
                         match self.symbols.get_index(symbol) {
-                            Some(ix) => self.place_order(account_uuid, ix, entry_price, long, size, stop, take_profit),
-                            None => Err(BrokerError::NoSuchSymbol),
+                            Some(ix) => {
+                                self.place_order(account_uuid, ix, entry_price, long, size, stop, take_profit, time_in_force, tag.clone())
+                            },
+                            None => Err(BrokerError::rejected(RejectionReason::UnknownSymbol, &format!("No symbol named {}.", symbol))),
                         }
                     },
                     // no support for partial closes at this time
@@ -349,26 +740,103 @@ impl SimBroker {
 
     /// Called when the balance of a ledger has been changed.  Automatically takes into account ping.
     fn buying_power_changed(&mut self, account_uuid: Uuid, new_buying_power: usize) {
+        self.push_notification(Ok(BrokerMessage::LedgerBalanceChange{
+            account_uuid: account_uuid,
+            new_buying_power: new_buying_power,
+        }));
+    }
+
+    /// Schedules a message that isn't the response to an action to be delivered to the client after network delay.
+    fn push_notification(&mut self, msg: BrokerResult) {
         self.pq.push(QueueItem{
             timestamp: self.timestamp + self.settings.ping_ns,
-            unit: WorkUnit::Notification(Ok(BrokerMessage::LedgerBalanceChange{
-                account_uuid: account_uuid,
-                new_buying_power: new_buying_power,
-            })),
+            unit: WorkUnit::Notification(msg),
+        });
+    }
+
+    /// Returns the positions that an order for `size` units on the `long` side of the symbol is netted against in
+    /// netting mode as `(position_uuid, units, buying power released)`, oldest first.  Empty in hedging mode.
+    fn netting_plan(
+        &self, account_uuid: Uuid, symbol_ix: usize, long: bool, size: usize
+    ) -> Result<Vec<(Uuid, usize, usize)>, BrokerError> {
+        if self.settings.position_mode != PositionMode::Netting {
+            return Ok(Vec::new());
+        }
+        let ledger = match self.accounts.data.get(&account_uuid) {
+            Some(acct) => &acct.ledger,
+            None => return Err(BrokerError::NoSuchAccount),
+        };
+
+        let mut opposite: Vec<(&Uuid, &Position)> = ledger.open_positions.iter()
+            .filter(|&(_, pos)| pos.symbol_id == symbol_ix && pos.long != long)
+            .collect();
+        opposite.sort_by(|&(uuid1, pos1), &(uuid2, pos2)| {
+            pos1.execution_time.cmp(&pos2.execution_time).then_with(|| uuid1.as_bytes().cmp(uuid2.as_bytes()))
         });
+
+        let mut plan = Vec::new();
+        let mut unnetted = size;
+        for (pos_uuid, pos) in opposite {
+            if unnetted == 0 {
+                break;
+            }
+            let units = cmp::min(unnetted, pos.size);
+            let released = (self.get_position_value(pos)? / pos.size) * units;
+            plan.push((*pos_uuid, units, released));
+            unnetted -= units;
+        }
+
+        Ok(plan)
+    }
+
+    /// Closes the units of each position in a netting plan at market price.  Returns the message for the last
+    /// position reduced; those of the others are sent to the client as notifications.
+    fn execute_netting_plan(&mut self, account_uuid: Uuid, plan: Vec<(Uuid, usize, usize)>) -> BrokerResult {
+        let mut last_msg = None;
+        for (pos_uuid, units, _) in plan {
+            let msg = self.market_close(account_uuid, pos_uuid, units)?;
+            if let Some(prev_msg) = mem::replace(&mut last_msg, Some(msg)) {
+                self.push_notification(Ok(prev_msg));
+            }
+        }
+
+        Ok(last_msg.expect("Executed an empty netting plan"))
     }
 
-    /// Creates a new pending position on the `SimBroker`.
+    /// Nets `units` of a pending order that's being filled against the account's opposite positions and returns
+    /// the buying power that was reserved for the netted units when the order was placed.  Returns how many units
+    /// were netted and the message for the last position reduced, or `None` if nothing was netted.
+    fn net_order_fill(
+        &mut self, account_uuid: Uuid, order: &Position, units: usize
+    ) -> Result<Option<(usize, BrokerMessage)>, BrokerError> {
+        let plan = self.netting_plan(account_uuid, order.symbol_id, order.long, units)?;
+        if plan.is_empty() {
+            return Ok(None);
+        }
+        let netted: usize = plan.iter().map(|&(_, units, _)| units).sum();
+        let reserved = (self.get_position_value(order)? / order.size) * netted;
+
+        let msg = self.execute_netting_plan(account_uuid, plan)?;
+        let new_buying_power = {
+            let ledger = &mut self.accounts.get_mut(&account_uuid).unwrap().ledger;
+            ledger.buying_power += reserved;
+            ledger.buying_power
+        };
+        self.buying_power_changed(account_uuid, new_buying_power);
+
+        Ok(Some((netted, msg)))
+    }
+
+    /// Creates a new pending position on the `SimBroker`.  Immediate or cancel orders, as well as good 'til date
+    /// orders whose expiry has already been reached, never become pending; whatever can't be filled right away
+    /// expires.
     fn place_order(
         &mut self, account_uuid: Uuid, symbol_ix: usize, limit_price: usize, long: bool, size: usize,
-        stop: Option<usize>, take_profit: Option<usize>,
-
+        stop: Option<usize>, take_profit: Option<usize>, time_in_force: TimeInForce, tag: Option<String>,
     ) -> BrokerResult {
-        let opt = self.get_price(symbol_ix);
-        if opt.is_none() {
-            return Err(BrokerError::NoSuchSymbol)
-        }
-        let (bid, ask) = opt.unwrap();
+        let (bid, ask) = self.get_quote(symbol_ix)?;
+        self.symbol_spec(symbol_ix).check_volume(size)?;
+        self.check_conversion_available(symbol_ix)?;
 
         let order = Position {
             creation_time: self.timestamp,
@@ -382,23 +850,46 @@ impl SimBroker {
             execution_price: None,
             exit_price: None,
             exit_time: None,
+            tag: tag,
         };
 
         // make sure the supplied parameters are sane
         let _ = order.check_sanity()?;
 
+        let expires_at = match time_in_force {
+            TimeInForce::GTC => None,
+            TimeInForce::GTD{expires_at} if expires_at > self.sim_time => Some(expires_at),
+            TimeInForce::GTD{..} | TimeInForce::IOC => Some(self.sim_time),
+        };
+        let immediate = expires_at == Some(self.sim_time);
+
         // check if we're able to open this position right away at market price
         match order.is_open_satisfied(bid, ask) {
             // if this order is fillable right now, open it.
             Some(entry_price) => {
-                let res = self.market_open(account_uuid, symbol_ix, long, size, stop, take_profit, Some(0));
-                // this should always succeed
-                if res.is_err() {
-                    self.logger.error_log(&format!("Error while trying to place order: {:?}, {:?}", &order, res));
+                let res = self.open_at_market(
+                    account_uuid, symbol_ix, long, size, stop, take_profit, Some(limit_price), order.tag.clone()
+                );
+                // rejections (such as hitting the position limit) are expected; anything else shouldn't happen
+                match res {
+                    Ok(_) | Err(BrokerError::Rejected{..}) => (),
+                    Err(_) => self.logger.error_log(&format!("Error while trying to place order: {:?}, {:?}", &order, res)),
                 }
                 // assert!(res.is_ok());
+
+                // the part of the order that couldn't be filled during this tick is left pending
+                if let Ok(BrokerMessage::PositionOpened{position_id, ..}) = res {
+                    if immediate {
+                        if let Some(expiry) = self.expire_order(position_id) {
+                            self.push_notification(expiry);
+                        }
+                    } else if let Some(expires_at) = expires_at {
+                        self.order_expiries.insert(position_id, expires_at);
+                    }
+                }
                 return res
             },
+            None if immediate => return Ok(self.expire_unplaced_order(order)),
             None => (),
         }
 
@@ -422,6 +913,9 @@ impl SimBroker {
                 match msg {
                     &BrokerMessage::OrderPlaced{order_id, order: _, timestamp: _} => {
                         self.accounts.order_placed(&order, order_id, account_uuid);
+                        if let Some(expires_at) = expires_at {
+                            self.order_expiries.insert(order_id, expires_at);
+                        }
                         let new_buying_power = self.accounts.get(&account_uuid).unwrap().ledger.buying_power;
                         self.buying_power_changed(account_uuid, new_buying_power);
                     },
@@ -434,18 +928,48 @@ impl SimBroker {
         res
     }
 
+    /// Returns the number of positions that are currently open across all accounts.
+    pub fn open_position_count(&self) -> usize {
+        self.accounts.data.values().map(|acct| acct.ledger.open_positions.len()).sum()
+    }
+
+    /// Rejects an order that would open a new position if `max_open_positions` positions are already open.
+    fn check_position_limit(&self) -> Result<(), BrokerError> {
+        let max_open_positions = self.settings.max_open_positions;
+        if max_open_positions != 0 && self.open_position_count() >= max_open_positions {
+            return Err(BrokerError::rejected(RejectionReason::MaxPositionsReached, &format!(
+                "{} positions are already open, which is the most allowed at once.", max_open_positions
+            )));
+        }
+        Ok(())
+    }
+
     /// Attempts to open a position at the current market price with options for settings stop loss, or take profit.
     /// Right now, this assumes that the order is filled as soon as it is placed (after the processing delay is taken
-    /// into account) and that it is filled fully.
+    /// into account) and that it is filled fully unless it's requoted (see `requote_price()`).
     fn market_open(
         &mut self, account_uuid: Uuid, symbol_ix: usize, long: bool, size: usize, stop: Option<usize>,
-        take_profit: Option<usize>, max_range: Option<usize>
+        take_profit: Option<usize>, max_range: Option<usize>, tag: Option<String>,
     ) -> BrokerResult {
-        let opt = self.get_price(symbol_ix);
-        if opt.is_none() {
-            return Err(BrokerError::NoSuchSymbol)
+        if let Some(new_price) = self.requote_price(symbol_ix, long) {
+            return Err(BrokerError::rejected(RejectionReason::Requote{new_price: new_price}, &format!(
+                "The price of {} moved; the order can be filled at {}.", self.symbols[symbol_ix].name, new_price
+            )));
         }
-        let (bid, ask) = opt.unwrap();
+        self.open_at_market(account_uuid, symbol_ix, long, size, stop, take_profit, None, tag)
+    }
+
+    /// Opens a position at the current market price.  If the size is larger than `max_fill_per_tick` or the depth
+    /// of the synthetic order book allows, only that much is filled now and the rest is left as a pending order
+    /// with the same UUID and a price of `remainder_price` (`None` for a market order) that fills on subsequent
+    /// ticks.  The value of the entire order is reserved from the account's buying power up front.
+    fn open_at_market(
+        &mut self, account_uuid: Uuid, symbol_ix: usize, long: bool, size: usize, stop: Option<usize>,
+        take_profit: Option<usize>, remainder_price: Option<usize>, tag: Option<String>,
+    ) -> BrokerResult {
+        let (bid, ask) = self.get_quote(symbol_ix)?;
+        self.symbol_spec(symbol_ix).check_volume(size)?;
+        self.check_conversion_available(symbol_ix)?;
 
         let cur_price = if long { ask } else { bid };
 
@@ -461,14 +985,58 @@ impl SimBroker {
             execution_price: Some(cur_price),
             exit_price: None,
             exit_time: None,
+            tag: tag,
         };
 
         // make sure the supplied parameters are sane
         let _ = pos.check_sanity()?;
 
+        // in netting mode, the order reduces opposite positions before anything is opened.  Closures aren't
+        // limited by `max_fill_per_tick`, so all netted units are filled right away.
+        let mut pos = pos;
+        let plan = self.netting_plan(account_uuid, symbol_ix, long, size)?;
+        if plan.is_empty() {
+            self.check_position_limit()?;
+        } else {
+            // netted orders close a position before opening one, so they can't exceed the position limit
+            let netted: usize = plan.iter().map(|&(_, units, _)| units).sum();
+            let released: usize = plan.iter().map(|&(_, _, released)| released).sum();
+            pos.size = size - netted;
+            // check the margin for the flipped part up front so that a rejected order doesn't reduce anything
+            let flipped_value = if pos.size == 0 { 0 } else { self.get_position_value(&pos)? };
+            if flipped_value > self.accounts.data[&account_uuid].ledger.buying_power + released {
+                return Err(BrokerError::rejected(
+                    RejectionReason::InsufficientMargin, "Not enough buying power to open the rest of the order."
+                ));
+            }
+
+            let msg = self.execute_netting_plan(account_uuid, plan)?;
+            if pos.size == 0 {
+                return Ok(msg);
+            }
+            self.push_notification(Ok(msg));
+        }
+        let size = pos.size;
+
         let pos_value = self.get_position_value(&pos)?;
         let pos_uuid = gen_uuid(self.prng);
 
+        // split off the part of the order that can't be filled during this tick, walking the book for its price
+        let (fill_size, fill_price) = self.walk_book(symbol_ix, cur_price, long, self.fill_size(size), remainder_price);
+        pos.execution_price = Some(fill_price);
+        let remainder = if fill_size < size {
+            pos.size = fill_size;
+            Some(Position {
+                size: size - fill_size,
+                price: remainder_price,
+                execution_time: None,
+                execution_price: None,
+                ..pos.clone()
+            })
+        } else {
+            None
+        };
+
         let new_buying_power;
         let res = {
             let acct_entry = self.accounts.entry(account_uuid);
@@ -477,12 +1045,17 @@ impl SimBroker {
                     let mut account = occ.get_mut();
                     // manually subtract the cost of the position from the account balance
                     if account.ledger.buying_power < pos_value {
-                        return Err(BrokerError::InsufficientBuyingPower);
+                        return Err(BrokerError::rejected(
+                            RejectionReason::InsufficientMargin, "Not enough buying power to open the position."
+                        ));
                     } else {
                         account.ledger.buying_power -= pos_value;
                         new_buying_power = account.ledger.buying_power;
                     }
 
+                    if let Some(ref remainder) = remainder {
+                        account.ledger.pending_positions.insert(pos_uuid, remainder.clone());
+                    }
                     // create the position in the `Ledger`
                     account.ledger.open_position(pos_uuid, pos.clone())
                 },
@@ -496,6 +1069,10 @@ impl SimBroker {
         assert!(res.is_ok());
         // add the position to the cache for checking when to close it
         self.accounts.position_opened_immediate(&pos, pos_uuid, account_uuid);
+        if let Some(ref remainder) = remainder {
+            self.accounts.order_placed(remainder, pos_uuid, account_uuid);
+        }
+        self.log_trade(TradeEventType::Fill, pos_uuid, &pos);
         // send notification about the change in ledger buying power
         self.buying_power_changed(account_uuid, new_buying_power);
 
@@ -531,22 +1108,45 @@ impl SimBroker {
         };
 
         let pos_value = self.get_position_value(&pos)?;
+        let exit_price = match self.get_price(pos.symbol_id) {
+            Some((bid, ask)) => if pos.long { bid } else { ask },
+            None => return Err(BrokerError::NoSuchSymbol),
+        };
+        let pnl = self.pnl(&pos, exit_price, size)?.round() as isize;
 
         let new_buying_power;
         let res = {
             let account = self.accounts.get_mut(&account_id).unwrap();
             let modification_cost = (pos_value / pos.size) * size;
             let res = account.ledger.resize_position(position_uuid, (-1 * size as isize), modification_cost, self.timestamp);
+            if res.is_ok() {
+                realize_pnl(&mut account.ledger, pnl);
+                self.realized_pnl += pnl as f64;
+            }
             new_buying_power = account.ledger.buying_power;
             res
         };
 
-        // if the position was fully closed, remove it from the cache and send notification of ledger buying power change
+        // if the position was fully closed, remove it from the cache; if it was reduced, update the cache.  Either
+        // way, log the closed units and send notification of ledger buying power change
         match res {
             Ok(ref message) => match message {
                 &BrokerMessage::PositionClosed{position: ref pos, position_id: pos_uuid, reason: _, timestamp: _} => {
                     self.accounts.position_closed(pos, pos_uuid);
                     self.buying_power_changed(account_id, new_buying_power);
+                    let mut closed_pos = pos.clone();
+                    closed_pos.exit_price = Some(exit_price);
+                    closed_pos.exit_time = Some(self.timestamp);
+                    self.log_trade(TradeEventType::Close, pos_uuid, &closed_pos);
+                },
+                &BrokerMessage::PositionModified{position: ref pos, position_id: pos_uuid, timestamp: _} => {
+                    self.accounts.position_modified(pos, pos_uuid);
+                    self.buying_power_changed(account_id, new_buying_power);
+                    let mut closed_part = pos.clone();
+                    closed_part.size = size;
+                    closed_part.exit_price = Some(exit_price);
+                    closed_part.exit_time = Some(self.timestamp);
+                    self.log_trade(TradeEventType::Close, pos_uuid, &closed_part);
                 },
                 _ => (),
             },
@@ -585,22 +1185,13 @@ impl SimBroker {
             match order.is_open_satisfied(bid, ask) {
                 // if the new entry price makes the order marketable, go ahead and open the position.
                 Some(entry_price) => {
-                    let res = {
-                        let account = self.accounts.get_mut(&account_uuid).unwrap();
-                        // remove the position from the pending hashmap
-                        let mut hm_order = account.ledger.pending_positions.remove(&pos_uuid).unwrap();
-                        hm_order.execution_time = Some(self.timestamp);
-                        hm_order.execution_price = Some(entry_price);
-                        // add it to the open hashmap
-                        account.ledger.open_position(pos_uuid, order.clone())
-                    };
-                    // that should always succeed
+                    let cache_ix = self.pending_cache_ix(order.symbol_id, pos_uuid)
+                        .expect("Pending order was in the ledger but not in the cache");
+                    let (res, _) = self.fill_pending_order(order.symbol_id, cache_ix, entry_price);
+                    // that only fails if the order was cancelled because of the position limit
                     if res.is_err() {
                         self.logger.error_log(&format!("Error while trying to modify order: {:?}, {:?}", &order, res));
                     }
-                    // assert!(res.is_ok());
-                    // notify the cache that the position was opened
-                    self.accounts.position_opened(&order, pos_uuid);
                     return res;
                 },
                 // if it's not marketable, perform the modification on the ledger
@@ -633,11 +1224,20 @@ impl SimBroker {
                 },
             };
             // attempt to cancel the order and remove it from the hashmaps
-            let res = account.ledger.cancel_order(order_uuid, self.timestamp);
-            new_buying_power = account.ledger.buying_power;
-            res
+            account.ledger.cancel_order(order_uuid, self.timestamp)
         };
 
+        // release the buying power that was reserved for the order
+        {
+            let refund = match res {
+                Ok(BrokerMessage::OrderCancelled{ref order, ..}) => self.get_position_value(order).unwrap_or(0),
+                _ => 0,
+            };
+            let account = self.accounts.get_mut(&account_uuid).unwrap();
+            account.ledger.buying_power += refund;
+            new_buying_power = account.ledger.buying_power;
+        }
+
         // if it was successful, remove the position from the `pending` cache
         // also send notification of ledger buying power change
         match res {
@@ -656,6 +1256,262 @@ impl SimBroker {
         res
     }
 
+    /// Returns the UUID of the account holding the pending order with the supplied UUID, if one exists.
+    fn find_order_account(&self, order_uuid: Uuid) -> Option<Uuid> {
+        self.accounts.iter()
+            .find(|&(_, acct)| acct.ledger.pending_positions.contains_key(&order_uuid))
+            .map(|(acct_uuid, _)| *acct_uuid)
+    }
+
+    /// Returns `true` if the supplied UUID belongs to a position that was opened from an order.
+    fn order_was_filled(&self, order_uuid: Uuid) -> bool {
+        self.accounts.iter().any(|(_, acct)| {
+            acct.ledger.open_positions.contains_key(&order_uuid) ||
+                acct.ledger.closed_positions.contains_key(&order_uuid)
+        })
+    }
+
+    /// Modifies a pending order, leaving any parameter supplied as `None` unchanged.  `new_sl_tp` sets both
+    /// the stop loss and take profit if supplied.  If the modified order is marketable at the current price,
+    /// it is filled immediately.
+    ///
+    /// Since this takes `&mut self`, it can never interleave with the processing of a tick.  Modifications
+    /// submitted through the simulation queue are processed after any tick with the same timestamp, so an
+    /// order filled by that tick yields `AlreadyFilled`.
+    pub fn modify_pending_order(
+        &mut self, order_uuid: Uuid, new_price: Option<usize>, new_size: Option<usize>,
+        new_sl_tp: Option<(Option<usize>, Option<usize>)>,
+    ) -> OrderUpdateResult {
+        let account_uuid = match self.find_order_account(order_uuid) {
+            Some(uuid) => uuid,
+            None => return if self.order_was_filled(order_uuid) {
+                OrderUpdateResult::AlreadyFilled
+            } else {
+                OrderUpdateResult::NotFound
+            },
+        };
+
+        let old_order = self.accounts.get(&account_uuid).unwrap().ledger.pending_positions[&order_uuid].clone();
+        let mut order = old_order.clone();
+        if let Some(price) = new_price {
+            order.price = Some(price);
+        }
+        if let Some(size) = new_size {
+            if size == 0 {
+                return OrderUpdateResult::from_error(BrokerError::rejected(
+                    RejectionReason::InvalidSize, "Orders must have a nonzero size; cancel the order instead."
+                ));
+            }
+            if let Err(err) = self.symbol_spec(old_order.symbol_id).check_volume(size) {
+                return OrderUpdateResult::from_error(err);
+            }
+            order.size = size;
+        }
+        if let Some((stop, take_profit)) = new_sl_tp {
+            order.stop = stop;
+            order.take_profit = take_profit;
+        }
+
+        if let Err(err) = order.check_sanity() {
+            return OrderUpdateResult::from_error(err);
+        }
+
+        // look the price up before touching buying power so that nothing has to be refunded if it's missing
+        let (bid, ask) = match self.get_price(order.symbol_id) {
+            Some(price) => price,
+            None => return OrderUpdateResult::from_error(BrokerError::NoSuchSymbol),
+        };
+
+        // the order's buying power was reserved when it was placed, so only the difference is charged/refunded
+        let (old_value, new_value) = match (self.get_position_value(&old_order), self.get_position_value(&order)) {
+            (Ok(old_value), Ok(new_value)) => (old_value, new_value),
+            (Err(err), _) | (_, Err(err)) => return OrderUpdateResult::from_error(err),
+        };
+        let new_buying_power = {
+            let ledger = &mut self.accounts.get_mut(&account_uuid).unwrap().ledger;
+            if new_value > old_value && ledger.buying_power < new_value - old_value {
+                return OrderUpdateResult::from_error(BrokerError::rejected(
+                    RejectionReason::InsufficientMargin, "Not enough buying power to enlarge the order."
+                ));
+            }
+            ledger.buying_power = ledger.buying_power + old_value - new_value;
+            ledger.buying_power
+        };
+        if new_value != old_value {
+            self.buying_power_changed(account_uuid, new_buying_power);
+        }
+
+        self.accounts.get_mut(&account_uuid).unwrap().ledger.pending_positions.insert(order_uuid, order.clone());
+        self.accounts.order_modified(&order, order_uuid);
+
+        // if the modification made the order marketable, fill as much of it as possible right away.
+        if let Some(entry_price) = order.is_open_satisfied(bid, ask) {
+            let cache_ix = self.pending_cache_ix(order.symbol_id, order_uuid)
+                .expect("Pending order was in the ledger but not in the cache");
+            match self.fill_pending_order(order.symbol_id, cache_ix, entry_price) {
+                (Ok(BrokerMessage::PositionOpened{position, ..}), _) |
+                (Ok(BrokerMessage::PositionModified{position, ..}), _) => return OrderUpdateResult::Ok{updated: position},
+                (Err(err), _) => return OrderUpdateResult::from_error(err),
+                (res, _) => return OrderUpdateResult::Rejected{
+                    reason: None, message: format!("Unexpected result of filling modified order: {:?}", res),
+                },
+            }
+        }
+
+        OrderUpdateResult::Ok{updated: order}
+    }
+
+    /// Cancels a pending order, returning the cancelled order if successful.
+    pub fn cancel_pending_order(&mut self, order_uuid: Uuid) -> OrderUpdateResult {
+        let account_uuid = match self.find_order_account(order_uuid) {
+            Some(uuid) => uuid,
+            None => return if self.order_was_filled(order_uuid) {
+                OrderUpdateResult::AlreadyFilled
+            } else {
+                OrderUpdateResult::NotFound
+            },
+        };
+
+        match self.cancel_order(account_uuid, order_uuid) {
+            Ok(BrokerMessage::OrderCancelled{order, order_id: _, timestamp: _}) => OrderUpdateResult::Ok{updated: order},
+            Ok(msg) => OrderUpdateResult::Rejected{reason: None, message: format!("Unexpected response to cancellation: {:?}", msg)},
+            Err(err) => OrderUpdateResult::from_error(err),
+        }
+    }
+
+    /// Cancels every pending order of every account, releasing the buying power reserved for them, and returns how
+    /// many were cancelled.  An `AllOrdersCancelled` event is emitted afterwards even if there was nothing to cancel
+    /// so that subscribers can see that trading was halted.
+    pub fn cancel_all_orders(&mut self) -> usize {
+        let orders: Vec<(Uuid, Uuid)> = self.accounts.iter()
+            .flat_map(|(acct_uuid, acct)| {
+                acct.ledger.pending_positions.keys().map(move |order_uuid| (*acct_uuid, *order_uuid))
+            })
+            .collect();
+        let count = orders.into_iter()
+            .filter(|&(account_uuid, order_uuid)| self.cancel_order(account_uuid, order_uuid).is_ok())
+            .count();
+
+        self.emit_event(BrokerEvent::AllOrdersCancelled{count: count});
+        self.publish_fill_event(FillEvent::AllOrdersCancelled{count: count});
+        count
+    }
+
+    /// Returns the channel that the broker's fill events are published to.
+    pub fn fills_channel(&self) -> String {
+        format!("fills:{}", self.uuid.hyphenated())
+    }
+
+    /// Publishes an event to the broker's fills channel whether or not `event_publish` is set.
+    fn publish_fill_event(&mut self, event: FillEvent) {
+        let published = PublishedFillEvent {timestamp: self.timestamp, event: event};
+        let ser = match serde_json::to_string(&published) {
+            Ok(ser) => ser,
+            Err(err) => return self.cs.error(None, &format!("Unable to serialize fill event: {:?}", err)),
+        };
+        match self.redis_client {
+            Some(ref client) => publish(client, &self.fills_channel(), &ser),
+            None => publish(&get_client(CONF.redis_host), &self.fills_channel(), &ser),
+        }
+    }
+
+    /// Returns the number of pending orders across all accounts.
+    pub fn open_order_count(&self) -> usize {
+        self.accounts.iter().map(|(_, acct)| acct.ledger.pending_positions.len()).sum()
+    }
+
+    /// Opens a position that the account already held when the simulation started, such as one that a backtest
+    /// starts out with, at `entry_price`.  Positive quantities are long and negative ones short.  The position's
+    /// value isn't taken out of the buying power since it was paid for before the account's balance was counted,
+    /// but it's logged as a fill and is valued and closed like any other position from then on.
+    pub fn preload_position(&mut self, symbol: &str, qty: f64, entry_price: usize) -> BrokerResult {
+        let symbol_ix = match self.symbols.get_index(&String::from(symbol)) {
+            Some(ix) => ix,
+            None => return Err(BrokerError::NoSuchSymbol),
+        };
+        let size = qty.abs().round() as usize;
+        self.symbol_spec(symbol_ix).check_volume(size)?;
+        let account_uuid = match self.accounts.data.keys().next() {
+            Some(uuid) => *uuid,
+            None => return Err(BrokerError::NoSuchAccount),
+        };
+
+        let pos = Position {
+            creation_time: self.timestamp,
+            symbol_id: symbol_ix,
+            size: size,
+            price: Some(entry_price),
+            long: qty > 0.,
+            stop: None,
+            take_profit: None,
+            execution_time: Some(self.timestamp),
+            execution_price: Some(entry_price),
+            exit_price: None,
+            exit_time: None,
+            tag: None,
+        };
+        let pos_uuid = gen_uuid(self.prng);
+        let res = self.accounts.data.get_mut(&account_uuid).unwrap().ledger.open_position(pos_uuid, pos.clone());
+        self.accounts.position_opened_immediate(&pos, pos_uuid, account_uuid);
+        self.log_trade(TradeEventType::Fill, pos_uuid, &pos);
+        self.starting_equity += self.get_position_value(&pos).unwrap_or(0) as f64;
+        res
+    }
+
+    /// Changes the balance that the broker's accounts start with to `starting_balance`.  The difference from the
+    /// current starting balance is deposited into (or withdrawn from) every account so that any trading that has
+    /// already taken place is kept, and the settings are updated so that the new balance survives resets.
+    pub fn set_starting_balance(&mut self, starting_balance: usize) {
+        let old_balance = self.settings.starting_balance;
+        let account_uuids: Vec<Uuid> = self.accounts.data.keys().cloned().collect();
+        for account_uuid in account_uuids {
+            let new_buying_power = {
+                let ledger = &mut self.accounts.data.get_mut(&account_uuid).unwrap().ledger;
+                ledger.buying_power = (ledger.buying_power + starting_balance).saturating_sub(old_balance);
+                ledger.buying_power
+            };
+            self.buying_power_changed(account_uuid, new_buying_power);
+            self.starting_equity += starting_balance as f64 - old_balance as f64;
+        }
+        self.settings.starting_balance = starting_balance;
+    }
+
+    /// Returns the combined equity of all accounts at the start of the simulation: their starting balances plus
+    /// the value of the positions they were preloaded with.  In the same units as `equity()`.
+    pub fn starting_equity(&self) -> f64 {
+        self.starting_equity
+    }
+
+    /// Returns all pending orders across all accounts as `(order_uuid, order)` pairs sorted by
+    /// creation time.
+    pub fn pending_orders(&self) -> Vec<(Uuid, Position)> {
+        let mut orders: Vec<(Uuid, Position)> = self.accounts.iter()
+            .flat_map(|(_, acct)| acct.ledger.pending_positions.iter().map(|(uuid, order)| (*uuid, order.clone())))
+            .collect();
+        orders.sort_by(|&(ref uuid1, ref o1), &(ref uuid2, ref o2)| {
+            o1.creation_time.cmp(&o2.creation_time).then_with(|| uuid1.as_bytes().cmp(uuid2.as_bytes()))
+        });
+        orders
+    }
+
+    /// Returns all pending orders, open positions, and closed positions across all accounts that were submitted
+    /// with the given tag as `(uuid, position)` pairs sorted by creation time.  A partially filled order shows up
+    /// both as a pending order and as an open position under the same UUID.
+    pub fn find_by_tag(&self, tag: &str) -> Vec<(Uuid, Position)> {
+        let mut found: Vec<(Uuid, Position)> = self.accounts.iter()
+            .flat_map(|(_, acct)| {
+                let ledger = &acct.ledger;
+                ledger.pending_positions.iter().chain(ledger.open_positions.iter()).chain(ledger.closed_positions.iter())
+            })
+            .filter(|&(_, pos)| pos.tag.as_ref().map(|t| t.as_str()) == Some(tag))
+            .map(|(uuid, pos)| (*uuid, pos.clone()))
+            .collect();
+        found.sort_by(|&(ref uuid1, ref pos1), &(ref uuid2, ref pos2)| {
+            pos1.creation_time.cmp(&pos2.creation_time).then_with(|| uuid1.as_bytes().cmp(uuid2.as_bytes()))
+        });
+        found
+    }
+
     /// Modifies the stop loss or take profit of a position.  SL and TP are double option-wrapped; the outer
     /// option indicates if they should be changed and the inner option indicates if the value should be set
     /// or not (`Some(None)` indicates that the current SL should be removed, for example).
@@ -687,6 +1543,208 @@ impl SimBroker {
         res
     }
 
+    /// Adds an entry for the supplied position to the trade log.  Fills are logged at the position's
+    /// execution price and closures at its exit price.
+    fn log_trade(&mut self, event: TradeEventType, pos_uuid: Uuid, pos: &Position) {
+        let price = match event {
+            TradeEventType::Fill => pos.execution_price,
+            TradeEventType::Close | TradeEventType::MarginCall => pos.exit_price,
+            TradeEventType::Rejected | TradeEventType::Rollover | TradeEventType::Expired => pos.price,
+        };
+        let entry = TradeLogEntry {
+            timestamp: self.timestamp,
+            event: event,
+            position_uuid: pos_uuid,
+            symbol: self.symbols[pos.symbol_id].name.clone(),
+            long: pos.long,
+            size: pos.size,
+            price: price.unwrap_or(0),
+            rejection_reason: None,
+            swap: None,
+            tag: pos.tag.clone(),
+        };
+
+        if let Err(err) = self.trade_log.record(entry) {
+            self.cs.error(None, &err);
+        }
+    }
+
+    /// Returns all trades that have taken place on the broker.
+    pub fn trade_log(&self) -> &[TradeLogEntry] {
+        &self.trade_log.entries
+    }
+
+    /// Tags all trades written to the database with the UUID of the backtest driving the broker.
+    pub fn set_backtest_uuid(&mut self, backtest_uuid: Uuid) {
+        self.trade_log.set_backtest_uuid(backtest_uuid);
+    }
+
+    /// Writes any buffered trade log entries to the database.  Should be called when the backtest
+    /// driving the broker completes.
+    pub fn flush_trade_log(&mut self) -> Result<(), String> {
+        self.trade_log.flush()
+    }
+
+    /// Returns the profit or loss of an open position at current prices.  See `pnl()` for units.
+    fn unrealized_pnl(&self, pos: &Position) -> f64 {
+        let (bid, ask) = match self.get_price(pos.symbol_id) {
+            Some(price) => price,
+            None => return 0.,
+        };
+        self.pnl(pos, if pos.long { bid } else { ask }, pos.size).unwrap_or(0.)
+    }
+
+    /// Returns the contract specification of a symbol: its entry in the `symbol_specs` setting if it has one and
+    /// the defaults for its kind of symbol otherwise.
+    pub fn symbol_spec(&self, symbol_ix: usize) -> SymbolSpec {
+        let sym = &self.symbols[symbol_ix];
+        match self.symbol_specs.get(&sym.name) {
+            Some(spec) => *spec,
+            None => SymbolSpec::default_for(&sym.name, sym.is_fx(), sym.metadata.decimal_precision, &self.settings),
+        }
+    }
+
+    /// Converts a difference between two of a symbol's prices into pips.
+    pub fn price_to_pips(&self, symbol_ix: usize, price_diff: f64) -> f64 {
+        let decimals = self.symbols[symbol_ix].metadata.decimal_precision;
+        price_diff / 10f64.powi(decimals as i32) / self.symbol_spec(symbol_ix).pip_size
+    }
+
+    /// Returns the profit or loss of closing `size` units of a position at `exit_price`.  For FX symbols, this
+    /// is converted from the pair's quote currency into the lowest division of the account currency; for other
+    /// symbols it is in units of the symbol's price.
+    fn pnl(&self, pos: &Position, exit_price: usize, size: usize) -> Result<f64, BrokerError> {
+        let entry_price = pos.execution_price.unwrap_or(0) as f64;
+        let diff = if pos.long { exit_price as f64 - entry_price } else { entry_price - exit_price as f64 };
+
+        let sym = &self.symbols[pos.symbol_id];
+        let spec = self.symbol_spec(pos.symbol_id);
+        if !sym.is_fx() {
+            return Ok(diff * (size * spec.lot_size) as f64);
+        }
+
+        // the value of one pip of the position in the quote currency
+        let pip_value = spec.pip_size * (size * spec.lot_size) as f64;
+        let rate = self.conversion_rate(fx_currencies(&sym.name)?.1)?;
+        let account_units = 10f64.powi(self.settings.account_currency_decimals as i32);
+        Ok(self.price_to_pips(pos.symbol_id, diff) * pip_value * rate * account_units)
+    }
+
+    /// Returns how many units of the account currency one unit of `currency` is worth.  The rate is taken
+    /// from a registered symbol for the pair if one exists and from the `conversion_rates` setting otherwise.
+    fn conversion_rate(&self, currency: &str) -> Result<f64, BrokerError> {
+        let account_currency = &self.settings.account_currency;
+        if currency == account_currency {
+            return Ok(1.);
+        }
+
+        let direct_pair = format!("{}{}", currency, account_currency);
+        let reverse_pair = format!("{}{}", account_currency, currency);
+        for &(ref pair, reverse) in &[(&direct_pair, false), (&reverse_pair, true)] {
+            let rate = if self.symbols.contains(pair) {
+                let (bid, ask, decimals) = self.symbols[*pair].get_price();
+                (bid + ask) as f64 / 2. / 10f64.powi(decimals as i32)
+            } else {
+                match self.conversion_rates.get(pair.as_str()) {
+                    Some(&rate) => rate,
+                    None => continue,
+                }
+            };
+
+            if rate <= 0. {
+                return Err(BrokerError::Message{
+                    message: format!("The exchange rate for {} is {}; unable to convert {} to {}.", pair, rate, currency, account_currency),
+                });
+            }
+            return Ok(if reverse { 1. / rate } else { rate });
+        }
+
+        Err(BrokerError::Message{
+            message: format!(
+                "No exchange rate is available to convert {} into the account currency {}; register a tickstream for {} or {} \
+                or add one of them to the `conversion_rates` setting.", currency, account_currency, reverse_pair, direct_pair
+            ),
+        })
+    }
+
+    /// Makes sure that profits of positions in the given symbol can be converted into the account currency.
+    fn check_conversion_available(&self, symbol_ix: usize) -> Result<(), BrokerError> {
+        let sym = &self.symbols[symbol_ix];
+        if sym.is_fx() {
+            self.conversion_rate(fx_currencies(&sym.name)?.1)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the combined buying power of all accounts along with their equity: the buying power plus the
+    /// value and unrealized P&L of all open positions.
+    pub fn equity(&self) -> (usize, f64) {
+        let mut balance = 0;
+        let mut equity = 0.;
+        for (_, acct) in self.accounts.iter() {
+            balance += acct.ledger.buying_power;
+            equity += acct.ledger.buying_power as f64;
+            for pos in acct.ledger.open_positions.values() {
+                equity += self.get_position_value(pos).unwrap_or(0) as f64 + self.unrealized_pnl(pos);
+            }
+        }
+
+        (balance, equity)
+    }
+
+    /// Returns the names of all symbols with open positions whose prices are stale.
+    fn stale_position_symbols(&self) -> Vec<String> {
+        let mut stale = Vec::new();
+        for (symbol_ix, positions) in self.accounts.positions.iter().enumerate() {
+            if !positions.open.is_empty() && self.stale_price_age(symbol_ix).is_some() {
+                stale.push(self.symbols[symbol_ix].name.clone());
+            }
+        }
+
+        stale
+    }
+
+    /// Returns the UUIDs and tags of all open positions, sorted by UUID.
+    fn sampled_positions(&self) -> Vec<SampledPosition> {
+        let mut positions: Vec<SampledPosition> = self.accounts.iter()
+            .flat_map(|(_, acct)| acct.ledger.open_positions.iter())
+            .map(|(uuid, pos)| SampledPosition {uuid: *uuid, tag: pos.tag.clone()})
+            .collect();
+        positions.sort_by(|pos1, pos2| pos1.uuid.as_bytes().cmp(pos2.uuid.as_bytes()));
+        positions
+    }
+
+    /// Called after every tick is processed.  Records an equity sample if one is due according to the
+    /// sampling settings and publishes it if publishing is enabled.
+    fn sample_equity_if_due(&mut self) {
+        if !self.equity_curve.tick(self.timestamp, self.settings.equity_sample_ticks, self.settings.equity_sample_interval_ms) {
+            return;
+        }
+
+        let (balance, equity) = self.equity();
+        let sample = EquitySample {
+            timestamp: self.timestamp,
+            balance: balance,
+            equity: equity,
+            open_positions: self.accounts.positions.iter().map(|p| p.open.len()).sum(),
+            stale_symbols: self.stale_position_symbols(),
+            positions: self.sampled_positions(),
+        };
+
+        if let Some(ref client) = self.redis_client {
+            match serde_json::to_string(&sample) {
+                Ok(ser) => publish(client, &format!("equity_{}", self.uuid.hyphenated()), &ser),
+                Err(err) => self.cs.error(None, &format!("Unable to serialize equity sample: {:?}", err)),
+            }
+        }
+        self.equity_curve.push(sample);
+    }
+
+    /// Returns all retained equity curve samples.
+    pub fn equity_curve(&self) -> &[EquitySample] {
+        &self.equity_curve.samples
+    }
+
     /// Dumps the SimBroker state to a file that can be resumed later.
     fn dump_to_file(&mut self, filename: &str) {
         unimplemented!(); // TODO
@@ -708,6 +1766,9 @@ impl SimBroker {
         }
 
         let base_currency = &self.settings.fx_base_currency;
+        if currency == base_currency {
+            return Ok(10usize.pow(desired_decimals as u32));
+        }
         let base_pair = format!("{}{}", currency, base_currency);
 
         let (_, ask, decimals) = if !self.symbols.contains(&base_pair) {
@@ -730,11 +1791,120 @@ impl SimBroker {
         let ix = pos.symbol_id;
 
         let sym = &self.symbols[ix];
+        let lot_size = self.symbol_spec(ix).lot_size;
         if sym.is_fx() {
-            let base_rate: usize = self.get_base_rate(&sym.name[0..3], sym.metadata.decimal_precision)?;
-            Ok(pos.size * base_rate * self.settings.fx_lot_size)
+            let base_currency = fx_currencies(&sym.name)?.0;
+            let base_rate: usize = self.get_base_rate(base_currency, sym.metadata.decimal_precision)?;
+            Ok(pos.size * base_rate * lot_size)
         } else {
-            Ok(pos.size)
+            Ok(pos.size * lot_size)
+        }
+    }
+
+    /// Returns how much of an order of the given size can be filled during a single tick.
+    fn fill_size(&self, size: usize) -> usize {
+        let max = self.settings.max_fill_per_tick;
+        if max != 0 && size > max { max } else { size }
+    }
+
+    /// Returns the index of the pending order with the given UUID in the symbol's pending cache.
+    fn pending_cache_ix(&self, symbol_ix: usize, order_uuid: Uuid) -> Option<usize> {
+        self.accounts.positions[symbol_ix].pending.iter().position(|cached| cached.pos_uuid == order_uuid)
+    }
+
+    /// Fills as much of the pending order at `cache_ix` in the symbol's pending cache as `max_fill_per_tick` and
+    /// the symbol's order book allow, walking the book from the supplied top-of-book price.  The filled units are added to the open position with the order's UUID,
+    /// creating it if this is the first fill, and its entry price becomes the volume-weighted average of all
+    /// fills.  Returns the resulting `PositionOpened` or `PositionModified` message and `true` if nothing of
+    /// the order remains pending.
+    ///
+    /// In netting mode, the filled units are first netted against the account's opposite positions; if that
+    /// uses up all of them, the message for the last position reduced is returned instead.
+    fn fill_pending_order(&mut self, symbol_ix: usize, cache_ix: usize, price: usize) -> (BrokerResult, bool) {
+        let (order_uuid, acct_uuid, order) = {
+            let cached = &self.accounts.positions[symbol_ix].pending[cache_ix];
+            (cached.pos_uuid, cached.acct_uuid, cached.pos.clone())
+        };
+        let (fill_size, price) = self.walk_book(symbol_ix, price, order.long, self.fill_size(order.size), order.price);
+        // an order that can't be filled because of the position limit would otherwise be retried on every tick
+        if self.fill_opens_position(acct_uuid, order_uuid, &order, fill_size) {
+            if let Err(err) = self.check_position_limit() {
+                let _ = self.cancel_order(acct_uuid, order_uuid);
+                return (Err(err), true);
+            }
+        }
+        let remaining = order.size - fill_size;
+        let (netted, netting_msg) = match self.net_order_fill(acct_uuid, &order, fill_size) {
+            Ok(Some((netted, msg))) => (netted, Some(msg)),
+            Ok(None) => (0, None),
+            Err(err) => return (Err(err), false),
+        };
+        let fill = Position {
+            size: fill_size - netted,
+            execution_price: Some(price),
+            execution_time: Some(self.timestamp),
+            ..order.clone()
+        };
+
+        let res = {
+            let ledger = &mut self.accounts.data.get_mut(&acct_uuid).unwrap().ledger;
+            if remaining > 0 {
+                ledger.pending_positions.get_mut(&order_uuid).unwrap().size = remaining;
+            } else {
+                ledger.pending_positions.remove(&order_uuid);
+            }
+
+            if fill.size == 0 {
+                Ok(netting_msg.clone().unwrap())
+            } else if ledger.open_positions.contains_key(&order_uuid) {
+                let pos = ledger.open_positions.get_mut(&order_uuid).unwrap();
+                let total_size = pos.size + fill.size;
+                let total_cost = pos.execution_price.unwrap() * pos.size + price * fill.size;
+                pos.execution_price = Some((total_cost + total_size / 2) / total_size);
+                pos.size = total_size;
+                Ok(BrokerMessage::PositionModified{
+                    position_id: order_uuid,
+                    position: pos.clone(),
+                    timestamp: self.timestamp,
+                })
+            } else {
+                ledger.open_position(order_uuid, fill.clone())
+            }
+        };
+
+        // update the caches
+        if remaining > 0 {
+            self.accounts.positions[symbol_ix].pending[cache_ix].pos.size = remaining;
+        } else {
+            self.accounts.positions[symbol_ix].pending.remove(cache_ix);
+        }
+        if fill.size == 0 {
+            return (res, remaining == 0);
+        }
+        match res {
+            Ok(BrokerMessage::PositionOpened{ref position, ..}) => {
+                self.accounts.position_opened_immediate(position, order_uuid, acct_uuid);
+            },
+            Ok(BrokerMessage::PositionModified{ref position, ..}) => self.accounts.position_modified(position, order_uuid),
+            _ => (),
+        }
+        if let Some(msg) = netting_msg {
+            self.push_notification(Ok(msg));
+        }
+        self.log_trade(TradeEventType::Fill, order_uuid, &fill);
+
+        (res, remaining == 0)
+    }
+
+    /// Returns true if filling `units` of the pending order opens a new position instead of adding to the one opened
+    /// by its earlier fills or being netted entirely against the account's opposite positions.
+    fn fill_opens_position(&self, acct_uuid: Uuid, order_uuid: Uuid, order: &Position, units: usize) -> bool {
+        if self.accounts.data[&acct_uuid].ledger.open_positions.contains_key(&order_uuid) {
+            return false;
+        }
+        match self.netting_plan(acct_uuid, order.symbol_id, order.long, units) {
+            Ok(plan) => plan.iter().map(|&(_, netted, _)| netted).sum::<usize>() < units,
+            Err(_) => true,
         }
     }
 
@@ -755,52 +1925,38 @@ impl SimBroker {
         // manually keep track of the index because we remove things from the vector dynamically
         let mut i = 0;
         while i < self.accounts.positions[symbol_id].pending.len() {
-            let push_msg_opt = {
-                let &CachedPosition { pos_uuid, acct_uuid, ref pos } = &self.accounts.positions[symbol_id].pending[i];
-                match pos.is_open_satisfied(bid, ask) {
-                    Some(open_price) => {
-                        // if the position should be opened, remove it from the pending `HashMap` and the cache and open it.
-                        let mut ledger = &mut self.accounts.data.get_mut(&acct_uuid).unwrap().ledger;
-                        // remove from the hashmap
-                        let mut hm_pos = ledger.pending_positions.remove(&pos_uuid).unwrap();
-                        hm_pos.execution_price = Some(open_price);
-                        hm_pos.execution_time = Some(self.timestamp);
-
-                        Some(ledger.open_position(pos_uuid, hm_pos))
-                    },
-                    None => None,
-                }
+            let open_price = match self.accounts.positions[symbol_id].pending[i].pos.is_open_satisfied(bid, ask) {
+                Some(open_price) => open_price,
+                None => {
+                    i += 1;
+                    continue;
+                },
             };
 
-            i += 1;
+            let (push_msg, fully_filled) = self.fill_pending_order(symbol_id, i, open_price);
+            // if the order was fully filled, it was removed from the cache so the index already points to the next one
+            if !fully_filled {
+                i += 1;
+            }
 
-            match push_msg_opt {
-                Some(Ok(BrokerMessage::PositionOpened{position_id: _, position: ref hm_pos, timestamp: _})) => {
-                    // remove from the pending cache
-                    let mut cached_pos = self.accounts.positions[symbol_id].pending.remove(i-1);
-                    // update the cached position with the one with execution data
-                    cached_pos.pos = hm_pos.clone();
-                    let push_msg = push_msg_opt.as_ref().unwrap();
-                    // this should always succeed
-                    // if push_msg.is_err() {
-                    //     let err_msg = format!("Error while trying to open position during tick check: {:?}, {:?}", &cached_pos.pos, push_msg);
-                    //     self.logger.error_log(&err_msg);
-                    // }
-                    assert!(push_msg.is_ok());
-                    // add it to the open cache
-                    self.accounts.positions[symbol_id].open.push(cached_pos);
+            match push_msg {
+                // the fill of an order that was entirely netted closes a position in netting mode
+                Ok(BrokerMessage::PositionOpened{..}) | Ok(BrokerMessage::PositionModified{..}) |
+                Ok(BrokerMessage::PositionClosed{..}) => {
                     // send the push message to the client
-                    self.push_msg(Ok(push_msg.as_ref().unwrap().clone()));
+                    self.push_msg(push_msg.clone());
                     // put the new tick into the buffer to be returned to the client
-                    let output = TickOutput::Pushstream(self.timestamp, Ok(push_msg.as_ref().unwrap().clone()));
-                    buffer[cur_index + push_msg_count] = output;
+                    buffer[cur_index + push_msg_count] = TickOutput::Pushstream(self.timestamp, push_msg);
                     push_msg_count += 1;
-                    // decrement i since we modified the cache
-                    i -= 1;
                 },
-                Some(Err(err)) => self.logger.error_log(&format!("Push message from opening pending position was error: {:?}", err)),
-                Some(Ok(msg)) => self.logger.error_log(&format!("Received unexpected response type when opening pending position: {:?}", msg)),
-                None => (),
+                // orders cancelled because of the position limit
+                Err(BrokerError::Rejected{..}) => {
+                    self.push_msg(push_msg.clone());
+                    buffer[cur_index + push_msg_count] = TickOutput::Pushstream(self.timestamp, push_msg);
+                    push_msg_count += 1;
+                },
+                Err(err) => self.logger.error_log(&format!("Push message from opening pending position was error: {:?}", err)),
+                Ok(msg) => self.logger.error_log(&format!("Received unexpected response type when opening pending position: {:?}", msg)),
             }
         }
 
@@ -810,13 +1966,30 @@ impl SimBroker {
             let mut new_buying_power = 0;
             let push_msg_opt: Option<(usize, BrokerResult)> = {
                 let &CachedPosition { pos_uuid, acct_uuid, ref pos } = &self.accounts.positions[symbol_id].open[i];
+                let filled_this_tick = pos.execution_time.map(|t| t >= self.timestamp).unwrap_or(false);
+                if filled_this_tick && !self.settings.fill_on_submission_tick {
+                    i += 1;
+                    continue;
+                }
+
                 match pos.is_close_satisfied(bid, ask) {
                     Some((closure_price, closure_reason)) => {
                         let pos_value = self.get_position_value(&pos).expect("Unable to get position value for pending position!");
+                        let pnl = match self.pnl(&pos, closure_price, pos.size) {
+                            Ok(pnl) => pnl.round() as isize,
+                            Err(err) => {
+                                self.logger.error_log(&format!("Unable to realize P&L of closed position: {:?}", err));
+                                0
+                            },
+                        };
                         // if the position should be closed, remove it from the cache.
                         let mut ledger = &mut self.accounts.data.get_mut(&acct_uuid).unwrap().ledger;
 
                         let res = ledger.close_position(pos_uuid, pos_value, self.timestamp, closure_reason);
+                        if res.is_ok() {
+                            realize_pnl(ledger, pnl);
+                            self.realized_pnl += pnl as f64;
+                        }
                         new_buying_power = ledger.buying_power;
                         Some((closure_price, res))
                     },
@@ -834,11 +2007,17 @@ impl SimBroker {
                 cached_pos.pos.exit_time = Some(self.timestamp);
                 // this should always succeed
                 assert!(push_msg.is_ok());
+                let event_type = match push_msg {
+                    Ok(BrokerMessage::PositionClosed{reason: PositionClosureReason::MarginCall, ..}) => TradeEventType::MarginCall,
+                    _ => TradeEventType::Close,
+                };
+                self.log_trade(event_type, cached_pos.pos_uuid, &cached_pos.pos);
                 // send notification of ledger buying power change to client
                 let buying_power_notification = BrokerMessage::LedgerBalanceChange{
                     account_uuid: cached_pos.acct_uuid,
                     new_buying_power: new_buying_power,
                 };
+                self.emit_event(BrokerEvent::from_result(Ok(buying_power_notification.clone())));
                 let output = TickOutput::Pushstream(self.timestamp, Ok(buying_power_notification));
                 // add the message to the buffer and increment the length
                 buffer[cur_index + push_msg_count] = output;
@@ -859,23 +2038,77 @@ impl SimBroker {
     }
 
     /// Sets the price for a symbol.  If no Symbol currently exists with that designation, a new one
-    /// will be initialized with a static price.
+    /// will be initialized with a static price.  FX symbols whose names aren't currency pairs are accepted, but
+    /// orders for them are rejected; see `fx_currencies()`.
     fn oneshot_price_set(
         &mut self, name: String, price: (usize, usize), is_fx: bool, decimal_precision: usize,
     ) {
-        if is_fx {
-            assert_eq!(name.len(), 6);
-        }
-
         // insert new entry into `self.prices` or update if one exists
         if self.symbols.contains(&name) {
             self.symbols[&name].price = price;
         } else {
+            // allocate space for positions of the new symbol in `Accounts`
+            self.accounts.add_symbol();
             let symbol = Symbol::new_oneshot(price, is_fx, decimal_precision, name.clone());
             self.symbols.add(name, symbol).expect("Unable to set oneshot price for new symbol");
         }
     }
 
+    /// Processes a tick of the named symbol that was handed to the broker directly instead of being read from one of
+    /// its tickstreams, such as one from a backtest.  The tick is handled exactly like a tickstream tick by
+    /// `process_new_tick()`, so it fills pending orders, triggers stop losses and take profits, moves the simulated
+    /// clock, and is counted for equity sampling.  Everything the broker scheduled up to the tick's timestamp is
+    /// delivered beforehand.  Symbols that don't exist yet are created as by `oneshot_price_set()`.
+    ///
+    /// Must not be called while the simulation loop is running.  Returns the number of messages the tick generated.
+    pub fn push_tick(&mut self, name: String, tick: Tick, is_fx: bool, decimal_precision: usize) -> usize {
+        let symbol_ix = match self.symbols.get_index(&name) {
+            Some(ix) => ix,
+            None => {
+                self.oneshot_price_set(name.clone(), (0, 0), is_fx, decimal_precision);
+                self.symbols.get_index(&name).unwrap()
+            },
+        };
+
+        self.timestamp = cmp::max(self.timestamp, tick.timestamp);
+        self.deliver_due_items();
+
+        // pending orders can be filled and closed by the same tick
+        let max_messages = {
+            let positions = &self.accounts.positions[symbol_ix];
+            2 * positions.pending.len() + positions.open.len() + self.order_expiries.len() + 1
+        };
+        let mut buffer = vec![TickOutput::Tick(0, Tick::null()); max_messages];
+        self.process_new_tick(symbol_ix, tick, 0, &mut buffer)
+    }
+
+    /// Delivers everything in the simulation queue that's due by the current timestamp for brokers that are driven
+    /// by `push_tick()` rather than the simulation loop.  Nothing reads the client tick streams outside of the loop,
+    /// so ticks only update the price that the client last saw.
+    fn deliver_due_items(&mut self) {
+        let now = self.timestamp;
+        while self.pq.q.peek().map(|item| item.timestamp <= now).unwrap_or(false) {
+            match self.pq.pop().unwrap().unit {
+                WorkUnit::ClientTick(symbol_ix, tick) => {
+                    self.symbols[symbol_ix].client_price = Some((tick.bid, tick.ask));
+                },
+                WorkUnit::Response(future, res) => {
+                    future.complete(res.clone());
+                    self.push_msg(res);
+                },
+                WorkUnit::Notification(res) => self.push_msg(res),
+                WorkUnit::ActionComplete(future, action) => {
+                    let res = self.exec_action(&action);
+                    self.push_response(future, res);
+                },
+                WorkUnit::NewTick(symbol_ix, tick) => self.cs.error(None, &format!(
+                    "Tick from a tickstream of {} was queued outside of the simulation loop: {:?}",
+                    self.symbols[symbol_ix].name, tick
+                )),
+            }
+        }
+    }
+
     /// Returns a clone of an account's ledger or an error if it doesn't exist.
     pub fn get_ledger_clone(&mut self, account_uuid: Uuid) -> Result<Ledger, BrokerError> {
         match self.accounts.get(&account_uuid) {
@@ -901,10 +2134,22 @@ impl SimBroker {
         self.symbols.add(name, sym)
     }
 
+    /// Returns the current bid and ask of a symbol that an order can be filled at.  Symbols that haven't
+    /// received a tick yet have no prices and are treated as closed.
+    fn get_quote(&self, ix: usize) -> Result<(usize, usize), BrokerError> {
+        match self.get_price(ix) {
+            Some((bid, ask)) if bid == 0 || ask == 0 => Err(BrokerError::rejected(
+                RejectionReason::MarketClosed, &format!("No prices are available for {}.", self.symbols[ix].name)
+            )),
+            Some(price) => Ok(price),
+            None => Err(BrokerError::rejected(RejectionReason::UnknownSymbol, "No symbol exists at that index.")),
+        }
+    }
+
     /// Returns the current price for a given symbol or None if the SimBroker
     /// doensn't have a price.
     pub fn get_price(&self, ix: usize) -> Option<(usize, usize)> {
-        if !self.symbols.len() > ix {
+        if ix < self.symbols.len() {
             return Some(self.symbols[ix].price)
         }
 
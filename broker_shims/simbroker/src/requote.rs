@@ -0,0 +1,49 @@
+//! Simulates requotes, where a broker refuses to fill a market order at the requested price during fast markets
+//! and offers a new price instead.  Which orders are requoted is decided by a seeded random number generator so
+//! that backtests stay reproducible.
+
+use rand::SeedableRng;
+
+use super::*;
+
+/// Creates the random number generator used to decide which market orders are requoted.
+pub fn requote_rng(settings: &SimBrokerSettings) -> XorShiftRng {
+    // XorShift can't be seeded with all zeroes, so the setting only supplies part of the seed
+    XorShiftRng::from_seed([0x2d1c4f73, 0x6a09e667, 0xbb67ae85, settings.requote_seed])
+}
+
+impl SimBroker {
+    /// Decides whether a market order for the symbol should be requoted, returning the price it could be filled
+    /// at instead if it should.  Orders are only eligible if `requote_threshold` is zero or the price has moved
+    /// more than that since the last tick delivered to the client; eligible orders are requoted with a
+    /// probability of `requote_probability`.
+    pub fn requote_price(&mut self, symbol_ix: usize, long: bool) -> Option<usize> {
+        if self.settings.requote_probability <= 0. {
+            return None;
+        }
+        let cur_price = match self.get_quote(symbol_ix) {
+            Ok((bid, ask)) => if long { ask } else { bid },
+            // the order is rejected for the lack of a price instead
+            Err(_) => return None,
+        };
+
+        if self.settings.requote_threshold != 0 {
+            let seen_price = match self.symbols[symbol_ix].client_price {
+                Some((bid, ask)) => if long { ask } else { bid },
+                None => cur_price,
+            };
+            let moved = if cur_price > seen_price { cur_price - seen_price } else { seen_price - cur_price };
+            if moved <= self.settings.requote_threshold {
+                return None;
+            }
+        }
+
+        // the raw integer draw is used so that the requotes only depend on the seed and not on float generation
+        let roll = self.requote_rng.next_u32() as f64;
+        if roll < self.settings.requote_probability * 4294967296. {
+            Some(cur_price)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Simulates the overnight financing (swap) of open positions.  Once a day at `rollover_time_ms` of simulated time,
+//! every open position in a symbol with swap rates is credited or charged swap depending on its side and size, with
+//! three days' worth applied on `triple_swap_weekday`.
+
+use super::*;
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Returns the number of the day since the epoch of the last rollover at or before `timestamp`, or `None` if
+/// `timestamp` is before the first one.
+fn rollover_day(timestamp: u64, rollover_time_ms: u64) -> Option<u64> {
+    if timestamp < rollover_time_ms {
+        None
+    } else {
+        Some((timestamp - rollover_time_ms) / DAY_MS)
+    }
+}
+
+/// Returns the day of the week of a day since the epoch, with 0 being Sunday.  The epoch was a Thursday.
+fn weekday(day: u64) -> u64 {
+    (day + 4) % 7
+}
+
+impl SimBroker {
+    /// Applies every rollover that the simulated clock has passed since the last one that was applied.  If there
+    /// was a gap in the data, several days' rollovers are applied at once.
+    pub fn apply_rollovers(&mut self) {
+        if self.swap_rates.is_empty() {
+            return;
+        }
+        let cur_day = match rollover_day(self.timestamp, self.settings.rollover_time_ms) {
+            Some(day) => day,
+            None => return,
+        };
+
+        let first_day = match self.last_rollover {
+            Some(last_day) if last_day >= cur_day => return,
+            Some(last_day) => last_day + 1,
+            // no positions can have been open during any earlier rollover
+            None => cur_day + 1,
+        };
+        for day in first_day..(cur_day + 1) {
+            self.rollover(day);
+        }
+        self.last_rollover = Some(cur_day);
+    }
+
+    /// Credits or charges the swap of the given day's rollover to every open position and logs it.
+    fn rollover(&mut self, day: u64) {
+        let multiplier = if weekday(day) == self.settings.triple_swap_weekday { 3. } else { 1. };
+        let rollover_time = day * DAY_MS + self.settings.rollover_time_ms;
+
+        let mut swaps: Vec<(Uuid, Uuid, Position, f64)> = Vec::new();
+        for (acct_uuid, acct) in self.accounts.iter() {
+            for (pos_uuid, pos) in acct.ledger.open_positions.iter() {
+                let (long_rate, short_rate) = match self.swap_rates.get(&self.symbols[pos.symbol_id].name) {
+                    Some(&rates) => rates,
+                    None => continue,
+                };
+                let rate = if pos.long { long_rate } else { short_rate };
+                swaps.push((*acct_uuid, *pos_uuid, pos.clone(), (rate * pos.size as f64 * multiplier).round()));
+            }
+        }
+        // keep the trade log deterministic
+        swaps.sort_by(|&(_, ref uuid1, _, _), &(_, ref uuid2, _, _)| uuid1.as_bytes().cmp(uuid2.as_bytes()));
+
+        for (acct_uuid, pos_uuid, pos, swap) in swaps {
+            let new_buying_power = {
+                let ledger = &mut self.accounts.get_mut(&acct_uuid).unwrap().ledger;
+                realize_pnl(ledger, swap as isize);
+                ledger.buying_power
+            };
+            self.total_swap += swap;
+            self.buying_power_changed(acct_uuid, new_buying_power);
+
+            let entry = TradeLogEntry {
+                timestamp: rollover_time,
+                event: TradeEventType::Rollover,
+                position_uuid: pos_uuid,
+                symbol: self.symbols[pos.symbol_id].name.clone(),
+                long: pos.long,
+                size: pos.size,
+                price: pos.execution_price.unwrap_or(0),
+                rejection_reason: None,
+                swap: Some(swap),
+                tag: pos.tag.clone(),
+            };
+            if let Err(err) = self.trade_log.record(entry) {
+                self.cs.error(None, &err);
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Captures the complete trading state of the SimBroker so that a backtest can be checkpointed and resumed later
+//! or its state inspected after the fact.  Snapshots are plain data and can be serialized with serde.
+
+use super::*;
+
+/// The state of one of the broker's symbols at the time a snapshot was taken.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SymbolSnapshot {
+    pub name: String,
+    pub is_fx: bool,
+    pub decimal_precision: usize,
+    /// The contract specification the symbol was traded with
+    pub spec: SymbolSpec,
+    /// The broker's (bid, ask) for the symbol as of its last tick
+    pub price: (usize, usize),
+    /// Timestamp at which the broker received the symbol's last tick
+    pub last_tick_time: Option<u64>,
+    /// Pending orders for the symbol in the order in which they're checked for fills
+    pub pending: Vec<CachedPosition>,
+    /// Open positions in the symbol in the order in which they're checked for closures
+    pub open: Vec<CachedPosition>,
+}
+
+/// Everything needed to continue a simulation from the point at which the snapshot was taken.  Symbols are listed
+/// in the order of their indexes.
+///
+/// Tickstreams, actions that are still being processed, event subscribers, and the state of the requote random
+/// number generator aren't part of a snapshot.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BrokerSnapshot {
+    /// Time of the last event processed by the simulation loop
+    pub timestamp: u64,
+    /// The simulated clock; see `SimBroker::sim_time()`
+    pub sim_time: u64,
+    /// The balances and orders and positions of every account, keyed by account UUID
+    pub accounts: HashMap<Uuid, Account>,
+    pub symbols: Vec<SymbolSnapshot>,
+    /// Expiry times of good 'til date orders, keyed by order UUID
+    pub order_expiries: HashMap<Uuid, u64>,
+    pub realized_pnl: f64,
+    pub total_swap: f64,
+    /// See `SimBroker::starting_equity()`
+    #[serde(default)]
+    pub starting_equity: f64,
+    /// Number of the day since the epoch of the last rollover that was applied
+    pub last_rollover: Option<u64>,
+    /// Anomaly counts and the timestamps used to check incoming ticks
+    pub tick_validator: TickValidationState,
+    pub trade_log: Vec<TradeLogEntry>,
+    pub equity_curve: EquityCurve,
+}
+
+impl SimBroker {
+    /// Returns a copy of the broker's current state.
+    pub fn snapshot(&self) -> BrokerSnapshot {
+        let symbols = self.symbols.iter().enumerate().map(|(ix, symbol)| {
+            let positions = &self.accounts.positions[ix];
+            SymbolSnapshot {
+                name: symbol.name.clone(),
+                is_fx: symbol.is_fx(),
+                decimal_precision: symbol.metadata.decimal_precision,
+                spec: self.symbol_spec(ix),
+                price: symbol.price,
+                last_tick_time: symbol.last_tick_time,
+                pending: positions.pending.clone(),
+                open: positions.open.clone(),
+            }
+        }).collect();
+
+        BrokerSnapshot {
+            timestamp: self.timestamp,
+            sim_time: self.sim_time,
+            accounts: self.accounts.data.clone(),
+            symbols: symbols,
+            order_expiries: self.order_expiries.clone(),
+            realized_pnl: self.realized_pnl,
+            total_swap: self.total_swap,
+            starting_equity: self.starting_equity,
+            last_rollover: self.last_rollover,
+            tick_validator: self.tick_validator.clone(),
+            trade_log: self.trade_log.entries.clone(),
+            equity_curve: self.equity_curve.clone(),
+        }
+    }
+
+    /// Replaces the broker's state with that of a snapshot.  The broker must have the same symbols as the one the
+    /// snapshot was taken from, registered in the same order and with the same contract specifications; if it
+    /// doesn't, an error is returned and nothing is changed.
+    ///
+    /// The broker keeps its own tickstreams, which continue from wherever they are.  Trade log entries from the
+    /// snapshot are assumed to have been written to the database already.
+    pub fn restore(&mut self, snapshot: BrokerSnapshot) -> BrokerResult {
+        self.check_snapshot_symbols(&snapshot)?;
+
+        self.timestamp = snapshot.timestamp;
+        self.sim_time = snapshot.sim_time;
+        self.accounts = Accounts::new(self.logger.clone());
+        for (uuid, account) in snapshot.accounts {
+            self.accounts.insert(uuid, account);
+        }
+        for (ix, symbol) in snapshot.symbols.into_iter().enumerate() {
+            self.accounts.add_symbol();
+            self.accounts.positions[ix].pending = symbol.pending;
+            self.accounts.positions[ix].open = symbol.open;
+            self.symbols[ix].price = symbol.price;
+            self.symbols[ix].last_tick_time = symbol.last_tick_time;
+        }
+        self.order_expiries = snapshot.order_expiries;
+        self.realized_pnl = snapshot.realized_pnl;
+        self.total_swap = snapshot.total_swap;
+        self.starting_equity = snapshot.starting_equity;
+        self.last_rollover = snapshot.last_rollover;
+        self.tick_validator = snapshot.tick_validator;
+        self.trade_log.restore(snapshot.trade_log);
+        self.equity_curve = snapshot.equity_curve;
+
+        self.cs.notice(None, "SimBroker state has been restored from a snapshot.");
+        Ok(BrokerMessage::Success)
+    }
+
+    /// Makes sure that the symbols of the snapshot match those of the broker so that positions are restored to
+    /// the right symbols and valued the same way they were before.
+    fn check_snapshot_symbols(&self, snapshot: &BrokerSnapshot) -> Result<(), BrokerError> {
+        let mismatch = |message: String| Err(BrokerError::Message{
+            message: format!("Unable to restore the snapshot: {}", message),
+        });
+
+        if snapshot.symbols.len() != self.symbols.len() {
+            return mismatch(format!(
+                "it has {} symbols but the broker has {}.", snapshot.symbols.len(), self.symbols.len()
+            ));
+        }
+
+        for (ix, snap_symbol) in snapshot.symbols.iter().enumerate() {
+            let symbol = &self.symbols[ix];
+            if snap_symbol.name != symbol.name {
+                return mismatch(format!(
+                    "symbol {} is {} in the snapshot but {} on the broker; symbols must be registered in the same order.",
+                    ix, snap_symbol.name, symbol.name
+                ));
+            }
+            if snap_symbol.is_fx != symbol.is_fx() || snap_symbol.decimal_precision != symbol.metadata.decimal_precision {
+                return mismatch(format!(
+                    "{} has {} decimals and is_fx={} in the snapshot but {} decimals and is_fx={} on the broker.",
+                    symbol.name, snap_symbol.decimal_precision, snap_symbol.is_fx, symbol.metadata.decimal_precision,
+                    symbol.is_fx()
+                ));
+            }
+            let spec = self.symbol_spec(ix);
+            if snap_symbol.spec != spec {
+                return mismatch(format!(
+                    "the contract specification of {} is {:?} in the snapshot but {:?} on the broker.",
+                    symbol.name, snap_symbol.spec, spec
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
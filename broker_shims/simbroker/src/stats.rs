@@ -0,0 +1,91 @@
+//! Aggregate statistics about the trading activity that has taken place on the SimBroker.
+
+use super::*;
+
+/// Summarizes the performance of all accounts on the SimBroker.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SimBrokerStats {
+    /// Number of times an order or position was (partially) filled
+    pub total_fills: u64,
+    /// Combined size of all fills
+    pub total_volume: f64,
+    pub realized_pnl: f64,
+    /// Profit or loss of all open positions at current prices
+    pub unrealized_pnl: f64,
+    pub open_orders: u64,
+    /// Net open size of each symbol; positive if long and negative if short
+    pub positions: HashMap<String, f64>,
+    /// Total swap credited (positive) or charged (negative) at rollovers; not included in `realized_pnl`
+    pub swap: f64,
+    /// Anomalous ticks received from the tickstreams
+    #[serde(default)]
+    pub tick_anomalies: TickAnomalyCounts,
+    /// Set if the simulation was aborted because of a bad tick
+    #[serde(default)]
+    pub abort_reason: Option<String>,
+    /// Number of market orders that were requoted instead of filled
+    #[serde(default)]
+    pub requotes: u64,
+    /// Number of positions that have been closed
+    #[serde(default)]
+    pub closed_trades: u64,
+    /// Number of closed positions that exited at a better price than they were opened at
+    #[serde(default)]
+    pub winning_trades: u64,
+}
+
+impl SimBroker {
+    /// Computes statistics about all trading activity since the broker was created or last reset.
+    pub fn stats(&self) -> SimBrokerStats {
+        let mut total_fills = 0;
+        let mut total_volume = 0.;
+        let mut requotes = 0;
+        for entry in self.trade_log.entries.iter() {
+            match (entry.event, entry.rejection_reason) {
+                (TradeEventType::Fill, _) => {
+                    total_fills += 1;
+                    total_volume += entry.size as f64;
+                },
+                (TradeEventType::Rejected, Some(RejectionReason::Requote{..})) => requotes += 1,
+                _ => (),
+            }
+        }
+
+        let mut unrealized_pnl = 0.;
+        let mut open_orders = 0;
+        let mut positions = HashMap::new();
+        let mut closed_trades = 0;
+        let mut winning_trades = 0;
+        for (_, acct) in self.accounts.iter() {
+            open_orders += acct.ledger.pending_positions.len() as u64;
+            for (_, pos) in acct.ledger.closed_positions.iter() {
+                closed_trades += 1;
+                if let (Some(entry), Some(exit)) = (pos.execution_price, pos.exit_price) {
+                    if (pos.long && exit > entry) || (!pos.long && exit < entry) {
+                        winning_trades += 1;
+                    }
+                }
+            }
+            for (_, pos) in acct.ledger.open_positions.iter() {
+                unrealized_pnl += self.unrealized_pnl(pos);
+                let size = if pos.long { pos.size as f64 } else { -(pos.size as f64) };
+                *positions.entry(self.symbols[pos.symbol_id].name.clone()).or_insert(0.) += size;
+            }
+        }
+
+        SimBrokerStats {
+            total_fills: total_fills,
+            total_volume: total_volume,
+            realized_pnl: self.realized_pnl,
+            unrealized_pnl: unrealized_pnl,
+            open_orders: open_orders,
+            positions: positions,
+            swap: self.total_swap,
+            tick_anomalies: self.tick_anomalies().clone(),
+            abort_reason: self.abort_reason().map(String::from),
+            requotes: requotes,
+            closed_trades: closed_trades,
+            winning_trades: winning_trades,
+        }
+    }
+}
@@ -140,3 +140,1546 @@ fn symbols_contains(b: &mut test::Bencher) {
     symbols.add(name, symbol).unwrap();
     b.iter(|| symbols.contains(&name_clone))
 }
+
+/// Creates a `SimBroker` with a static-priced non-FX symbol "ORDR" and returns it along with the
+/// UUID of its account and the index of the symbol.
+fn init_order_test_broker() -> (SimBroker, Uuid, usize) {
+    init_order_test_broker_with(SimBrokerSettings::default())
+}
+
+fn init_order_test_broker_with(settings: SimBrokerSettings) -> (SimBroker, Uuid, usize) {
+    let (_, dummy_rx) = mpsc::channel();
+    let cs = CommandServer::new(Uuid::new_v4(), "SimBroker Order Test");
+    let mut sim = SimBroker::new(settings, cs, dummy_rx).unwrap();
+    sim.oneshot_price_set(String::from("ORDR"), (999, 1001), false, 4);
+    let symbol_ix = sim.symbols.get_index(&String::from("ORDR")).unwrap();
+    let account_uuid = *sim.accounts.data.keys().next().unwrap();
+    (sim, account_uuid, symbol_ix)
+}
+
+/// Places a long limit order for "ORDR" and returns its UUID.
+fn place_long_limit(sim: &mut SimBroker, account_uuid: Uuid, entry_price: usize) -> Uuid {
+    let action = BrokerAction::TradingAction {
+        account_uuid: account_uuid,
+        action: TradingAction::LimitOrder {
+            symbol: String::from("ORDR"), long: true, size: 10, stop: None, take_profit: None, entry_price: entry_price,
+            time_in_force: TimeInForce::GTC, tag: None,
+        },
+    };
+    match sim.exec_action(&action) {
+        Ok(BrokerMessage::OrderPlaced{order_id, order: _, timestamp: _}) => order_id,
+        res => panic!("Unexpected result from placing limit order: {:?}", res),
+    }
+}
+
+/// Simulates a tick arriving at the broker for the given symbol.
+fn apply_tick(sim: &mut SimBroker, symbol_ix: usize, price: (usize, usize)) {
+    let mut buffer = vec![TickOutput::Tick(0, Tick::null()); 16];
+    sim.symbols[symbol_ix].price = price;
+    sim.tick_positions(symbol_ix, price, 0, &mut buffer);
+}
+
+#[test]
+fn pending_order_modification() {
+    let (mut sim, account_uuid, _) = init_order_test_broker();
+    let order_uuid = place_long_limit(&mut sim, account_uuid, 990);
+    assert_eq!(sim.pending_orders().len(), 1);
+
+    match sim.modify_pending_order(order_uuid, Some(995), Some(20), Some((Some(980), None))) {
+        OrderUpdateResult::Ok{updated} => {
+            assert_eq!(updated.price, Some(995));
+            assert_eq!(updated.size, 20);
+            assert_eq!(updated.stop, Some(980));
+            assert_eq!(updated.execution_price, None);
+        },
+        res => panic!("Unexpected modification result: {:?}", res),
+    }
+
+    // the cache and the ledger should agree on the modified order
+    let (_, listed) = sim.pending_orders()[0].clone();
+    assert_eq!(listed.price, Some(995));
+    assert_eq!(sim.accounts.positions[listed.symbol_id].pending[0].pos, listed);
+
+    // an invalid stop should be rejected without changing the order
+    match sim.modify_pending_order(order_uuid, None, None, Some((Some(1000), None))) {
+        OrderUpdateResult::Rejected{reason: Some(RejectionReason::PriceOutOfRange), ..} => (),
+        res => panic!("Expected rejection, got {:?}", res),
+    }
+    assert_eq!(sim.pending_orders()[0].1.stop, Some(980));
+
+    assert_eq!(sim.modify_pending_order(Uuid::new_v4(), Some(1), None, None), OrderUpdateResult::NotFound);
+}
+
+/// Modifications that are rejected leave the account's buying power as it was before they were submitted.
+#[test]
+fn rejected_modification_keeps_buying_power() {
+    let (mut sim, account_uuid, _) = init_order_test_broker();
+    let order_uuid = place_long_limit(&mut sim, account_uuid, 990);
+    let buying_power = sim.get_ledger_clone(account_uuid).unwrap().buying_power;
+
+    match sim.modify_pending_order(order_uuid, None, Some(buying_power * 2), None) {
+        OrderUpdateResult::Rejected{reason: Some(RejectionReason::InsufficientMargin), ..} => (),
+        res => panic!("Expected rejection, got {:?}", res),
+    }
+    assert_eq!(sim.get_ledger_clone(account_uuid).unwrap().buying_power, buying_power);
+    match sim.modify_pending_order(order_uuid, None, Some(20), Some((Some(1000), None))) {
+        OrderUpdateResult::Rejected{reason: Some(RejectionReason::PriceOutOfRange), ..} => (),
+        res => panic!("Expected rejection, got {:?}", res),
+    }
+    assert_eq!(sim.get_ledger_clone(account_uuid).unwrap().buying_power, buying_power);
+    assert_eq!(sim.pending_orders()[0].1.size, 10);
+}
+
+/// FX symbols whose names aren't two three-letter currency codes are rejected instead of panicking the broker.
+#[test]
+fn short_fx_symbol_names() {
+    let (mut sim, account_uuid, _) = init_order_test_broker();
+    assert_eq!(fx_currencies("EURUSD").unwrap(), ("EUR", "USD"));
+    assert!(fx_currencies("TEST").is_err());
+    // six bytes, but the third one is in the middle of a character
+    assert!(fx_currencies("ééé").is_err());
+
+    sim.oneshot_price_set(String::from("TEST"), (999, 1001), true, 4);
+    let market_order = TradingAction::MarketOrder {
+        symbol: String::from("TEST"), long: true, size: 10, stop: None, take_profit: None, max_range: None, tag: None,
+    };
+    match place(&mut sim, account_uuid, market_order) {
+        Err(BrokerError::Message{..}) => (),
+        res => panic!("Unexpected response to an order for a short FX symbol: {:?}", res),
+    }
+    assert!(sim.get_ledger_clone(account_uuid).unwrap().open_positions.is_empty());
+}
+
+#[test]
+fn pending_order_cancellation() {
+    let (mut sim, account_uuid, _) = init_order_test_broker();
+    let order_uuid = place_long_limit(&mut sim, account_uuid, 990);
+
+    match sim.cancel_pending_order(order_uuid) {
+        OrderUpdateResult::Ok{updated} => assert_eq!(updated.price, Some(990)),
+        res => panic!("Unexpected cancellation result: {:?}", res),
+    }
+    assert!(sim.pending_orders().is_empty());
+    assert_eq!(sim.cancel_pending_order(order_uuid), OrderUpdateResult::NotFound);
+}
+
+/// Modifying an order so that it becomes marketable fills it right away.
+#[test]
+fn marketable_modification_fills() {
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker();
+    let order_uuid = place_long_limit(&mut sim, account_uuid, 990);
+
+    match sim.modify_pending_order(order_uuid, Some(1005), None, None) {
+        OrderUpdateResult::Ok{updated} => assert_eq!(updated.execution_price, Some(1001)),
+        res => panic!("Unexpected modification result: {:?}", res),
+    }
+    assert!(sim.pending_orders().is_empty());
+    assert_eq!(sim.accounts.positions[symbol_ix].open.len(), 1);
+    assert_eq!(sim.cancel_pending_order(order_uuid), OrderUpdateResult::AlreadyFilled);
+}
+
+/// A tick and a modification with the same timestamp must resolve with the tick first.
+#[test]
+fn ticks_processed_before_actions() {
+    let (c, _) = oneshot::<BrokerResult>();
+    let mut q = SimulationQueue::new();
+    q.push(QueueItem {
+        timestamp: 5,
+        unit: WorkUnit::ActionComplete(c, BrokerAction::Ping),
+    });
+    q.push(QueueItem {
+        timestamp: 5,
+        unit: WorkUnit::NewTick(0, Tick::null()),
+    });
+
+    assert_eq!(q.pop().unwrap().unit, WorkUnit::NewTick(0, Tick::null()));
+    match q.pop().unwrap().unit {
+        WorkUnit::ActionComplete(_, _) => (),
+        unit => panic!("Expected ActionComplete, got {:?}", unit),
+    }
+
+    // the filling tick comes first, so the cancellation sees a filled order
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker();
+    let order_uuid = place_long_limit(&mut sim, account_uuid, 990);
+    apply_tick(&mut sim, symbol_ix, (988, 990));
+    assert_eq!(sim.cancel_pending_order(order_uuid), OrderUpdateResult::AlreadyFilled);
+}
+
+/// Races a stream of ticks against a stream of modifications and makes sure that the order ends up
+/// in exactly one place and that results never go backwards once the order has filled.
+#[test]
+fn concurrent_ticks_and_modifications() {
+    use std::sync::Mutex;
+
+    for _ in 0..25 {
+        let (mut sim, account_uuid, symbol_ix) = init_order_test_broker();
+        let order_uuid = place_long_limit(&mut sim, account_uuid, 900);
+        let sim = Arc::new(Mutex::new(sim));
+
+        let tick_sim = sim.clone();
+        let ticker = thread::spawn(move || {
+            // walk the price down until it crosses every price the modifier can set
+            for i in 0..200 {
+                let ask = 1001 - i;
+                apply_tick(&mut tick_sim.lock().unwrap(), symbol_ix, (ask - 2, ask));
+            }
+        });
+
+        let mut filled = false;
+        for i in 0..200 {
+            let res = sim.lock().unwrap().modify_pending_order(order_uuid, Some(850 + (i % 50)), None, None);
+            match res {
+                OrderUpdateResult::Ok{updated} => {
+                    assert!(!filled, "Order was modified after it had already been filled");
+                    if updated.execution_price.is_some() {
+                        filled = true;
+                    }
+                },
+                OrderUpdateResult::AlreadyFilled => filled = true,
+                res => panic!("Unexpected result during race: {:?}", res),
+            }
+        }
+        ticker.join().unwrap();
+
+        let sim = sim.lock().unwrap();
+        let ledger = &sim.accounts.data[&account_uuid].ledger;
+        let pending = ledger.pending_positions.contains_key(&order_uuid);
+        let open = ledger.open_positions.contains_key(&order_uuid);
+        assert!(pending ^ open);
+        assert_eq!(sim.accounts.positions[symbol_ix].pending.len(), if pending { 1 } else { 0 });
+        assert_eq!(sim.accounts.positions[symbol_ix].open.len(), if open { 1 } else { 0 });
+    }
+}
+
+/// Runs a scripted sequence of trades and makes sure they all end up in the trade log table.
+#[test]
+fn trade_log_persistence() {
+    use tickgrinder_util::transport::postgres::get_client;
+
+    let table = "simbroker_trade_log_test";
+    let mut settings = SimBrokerSettings::default();
+    settings.trade_log_table = String::from(table);
+    settings.trade_log_batch_size = 2;
+    let (mut sim, account_uuid, _) = init_order_test_broker_with(settings);
+    let backtest_uuid = Uuid::new_v4();
+    sim.set_backtest_uuid(backtest_uuid);
+
+    let market_order = BrokerAction::TradingAction {
+        account_uuid: account_uuid,
+        action: TradingAction::MarketOrder {
+            symbol: String::from("ORDR"), long: true, size: 10, stop: None, take_profit: None, max_range: None,
+            tag: None,
+        },
+    };
+    let pos_uuid = match sim.exec_action(&market_order) {
+        Ok(BrokerMessage::PositionOpened{position_id, ..}) => position_id,
+        res => panic!("Unexpected result from market order: {:?}", res),
+    };
+    let close = BrokerAction::TradingAction {
+        account_uuid: account_uuid,
+        action: TradingAction::MarketClose{uuid: pos_uuid, size: 10},
+    };
+    sim.exec_action(&close).unwrap();
+    sim.exec_action(&market_order).unwrap();
+
+    let events: Vec<TradeEventType> = sim.trade_log().iter().map(|e| e.event).collect();
+    assert_eq!(events, vec![TradeEventType::Fill, TradeEventType::Close, TradeEventType::Fill]);
+    assert_eq!(sim.trade_log()[0].price, 1001);
+    assert_eq!(sim.trade_log()[1].price, 999);
+
+    let count_query = format!("SELECT COUNT(*) FROM {} WHERE backtest_uuid = '{}';", table, backtest_uuid.hyphenated());
+    let conn = get_client().unwrap();
+    // the first batch of two is written automatically
+    let count: i64 = conn.query(&count_query, &[]).unwrap().get(0).get(0);
+    assert_eq!(count, 2);
+    sim.flush_trade_log().unwrap();
+    let count: i64 = conn.query(&count_query, &[]).unwrap().get(0).get(0);
+    assert_eq!(count, 3);
+
+    let rows_query = format!(
+        "SELECT event, symbol, price FROM {} WHERE backtest_uuid = '{}' ORDER BY id;", table, backtest_uuid.hyphenated()
+    );
+    let rows = conn.query(&rows_query, &[]).unwrap();
+    let row = rows.get(1);
+    let event: String = row.get(0);
+    let symbol: String = row.get(1);
+    let price: i64 = row.get(2);
+    assert_eq!((event.as_str(), symbol.as_str(), price), ("close", "ORDR", 999));
+}
+
+/// Once the equity curve reaches its maximum length, it should be thinned uniformly.
+#[test]
+fn equity_curve_thinning() {
+    let mut curve = EquityCurve::new(4);
+    for i in 0..17 {
+        curve.push(EquitySample {timestamp: i, balance: 0, equity: 0., open_positions: 0, stale_symbols: Vec::new(), positions: Vec::new()});
+        assert!(curve.samples.len() <= 4);
+    }
+
+    let timestamps: Vec<u64> = curve.samples.iter().map(|s| s.timestamp).collect();
+    assert_eq!(timestamps, vec![0, 8, 16]);
+}
+
+#[test]
+fn equity_curve_sampling() {
+    let mut settings = SimBrokerSettings::default();
+    settings.equity_sample_ticks = 2;
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+    let starting_balance = sim.equity().0;
+
+    let market_order = BrokerAction::TradingAction {
+        account_uuid: account_uuid,
+        action: TradingAction::MarketOrder {
+            symbol: String::from("ORDR"), long: true, size: 10, stop: None, take_profit: None, max_range: None,
+            tag: None,
+        },
+    };
+    sim.exec_action(&market_order).unwrap();
+
+    // price moves up 10 pips on each tick; a sample is taken every other tick
+    for i in 1..5 {
+        apply_tick(&mut sim, symbol_ix, (999 + 10 * i, 1001 + 10 * i));
+        sim.sample_equity_if_due();
+    }
+
+    let curve = sim.equity_curve();
+    assert_eq!(curve.len(), 2);
+    assert_eq!(curve[0].open_positions, 1);
+    assert_eq!(curve[0].balance, starting_balance - 10);
+    // entered at 1001, bid is now 1019 and then 1039
+    assert_eq!(curve[0].equity, starting_balance as f64 + 18. * 10.);
+    assert_eq!(curve[1].equity, starting_balance as f64 + 38. * 10.);
+}
+
+#[test]
+fn broker_trait_strategy() {
+    let mut sim_client = SimBrokerClient::init(HashMap::new()).wait().unwrap().unwrap();
+    sim_client.oneshot_price_set(String::from("ORDR"), (999, 1001), false, 4).unwrap();
+    let account_uuid = match sim_client.execute(BrokerAction::ListAccounts).wait().unwrap() {
+        Ok(BrokerMessage::AccountListing{accounts}) => accounts[0].uuid,
+        res => panic!("Unexpected response to account listing: {:?}", res),
+    };
+
+    let ledger = round_trip_strategy(&mut sim_client, account_uuid, "ORDR");
+    assert!(ledger.open_positions.is_empty());
+    assert!(ledger.pending_positions.is_empty());
+    assert_eq!(ledger.closed_positions.len(), 1);
+    // bought at the ask and sold at the bid without the price moving
+    let stats = sim_client.stats();
+    assert_eq!(stats.closed_trades, 1);
+    assert_eq!(stats.winning_trades, 0);
+}
+
+/// Delivers a tick for the given symbol to the broker at the supplied timestamp.
+fn deliver_tick(sim: &mut SimBroker, symbol_ix: usize, timestamp: u64, price: (usize, usize)) {
+    let mut buffer = vec![TickOutput::Tick(0, Tick::null()); 16];
+    sim.timestamp = timestamp;
+    sim.process_new_tick(symbol_ix, Tick {timestamp: timestamp, bid: price.0, ask: price.1}, 0, &mut buffer);
+}
+
+/// Simulates the broker finishing processing of an action at the supplied timestamp.
+fn deliver_action(sim: &mut SimBroker, timestamp: u64, action: BrokerAction) {
+    let mut buffer = vec![TickOutput::Tick(0, Tick::null()); 16];
+    let (c, _) = oneshot::<BrokerResult>();
+    sim.timestamp = timestamp;
+    sim.process_action(c, action, 0, &mut buffer);
+}
+
+/// Like `deliver_action`, but also delivers the broker's response if one was scheduled, emitting it as an event,
+/// and returns it.  Returns `None` if the action was deferred.
+fn deliver_action_response(sim: &mut SimBroker, timestamp: u64, action: BrokerAction) -> Option<BrokerResult> {
+    let mut buffer = vec![TickOutput::Tick(0, Tick::null()); 16];
+    let (c, o) = oneshot::<BrokerResult>();
+    sim.timestamp = timestamp;
+    sim.process_action(c, action, 0, &mut buffer);
+
+    let mut responded = false;
+    while let Some(item) = sim.pq.pop() {
+        if let WorkUnit::Response(complete, res) = item.unit {
+            sim.push_msg(res.clone());
+            complete.complete(res);
+            responded = true;
+        }
+    }
+    if responded { Some(o.wait().unwrap()) } else { None }
+}
+
+fn ordr_market_long(stop: Option<usize>) -> TradingAction {
+    TradingAction::MarketOrder {
+        symbol: String::from("ORDR"), long: true, size: 10, stop: stop, take_profit: None, max_range: None, tag: None,
+    }
+}
+
+fn ordr_limit_long(entry_price: usize, stop: Option<usize>) -> TradingAction {
+    TradingAction::LimitOrder {
+        symbol: String::from("ORDR"), long: true, size: 10, stop: stop, take_profit: None, entry_price: entry_price,
+        time_in_force: TimeInForce::GTC, tag: None,
+    }
+}
+
+fn ordr_limit_long_tif(entry_price: usize, time_in_force: TimeInForce) -> TradingAction {
+    TradingAction::LimitOrder {
+        symbol: String::from("ORDR"), long: true, size: 10, stop: None, take_profit: None, entry_price: entry_price,
+        time_in_force: time_in_force, tag: None,
+    }
+}
+
+/// Places an order for the account directly, bypassing any delays, and returns the broker's response.
+fn place(sim: &mut SimBroker, account_uuid: Uuid, action: TradingAction) -> BrokerResult {
+    sim.exec_action(&BrokerAction::TradingAction{account_uuid: account_uuid, action: action})
+}
+
+fn placed_order_id(res: BrokerResult) -> Uuid {
+    match res {
+        Ok(BrokerMessage::OrderPlaced{order_id, ..}) => order_id,
+        res => panic!("Unexpected result from placing limit order: {:?}", res),
+    }
+}
+
+/// Good 'til date orders are cancelled once the simulated clock reaches their expiry, including when a tick
+/// arrives at exactly that time, and the clock never runs backwards.
+#[test]
+fn good_til_date_expiry() {
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker();
+    let events = sim.events();
+    let starting_balance = sim.settings.starting_balance;
+    deliver_tick(&mut sim, symbol_ix, 1000, (999, 1001));
+    assert_eq!(sim.sim_time(), 1000);
+
+    let res = place(&mut sim, account_uuid, ordr_limit_long_tif(990, TimeInForce::GTD{expires_at: 2000}));
+    let expiring = placed_order_id(res);
+    let res = place(&mut sim, account_uuid, ordr_limit_long_tif(990, TimeInForce::GTD{expires_at: 2500}));
+    let later = placed_order_id(res);
+    let gtc = place_long_limit(&mut sim, account_uuid, 990);
+
+    deliver_tick(&mut sim, symbol_ix, 1999, (999, 1001));
+    assert_eq!(position_counts(&sim, account_uuid), (0, 3, 0));
+
+    // the first order expires on this tick before its price could fill it; the others are filled
+    deliver_tick(&mut sim, symbol_ix, 2000, (985, 989));
+    assert_eq!(sim.sim_time(), 2000);
+    let ledger = &sim.accounts.data[&account_uuid].ledger;
+    assert!(!ledger.pending_positions.contains_key(&expiring) && !ledger.open_positions.contains_key(&expiring));
+    assert!(ledger.open_positions.contains_key(&later) && ledger.open_positions.contains_key(&gtc));
+    // only the filled orders still tie up buying power
+    assert_eq!(ledger.buying_power, starting_balance - 20);
+
+    let expired_events: Vec<Uuid> = events.take(3).wait().filter_map(|e| match e.unwrap().1 {
+        BrokerEvent::OrderExpired{order_id, ..} => Some(order_id),
+        _ => None,
+    }).collect();
+    assert_eq!(expired_events, vec![expiring]);
+    let expiries: Vec<&TradeLogEntry> = sim.trade_log().iter().filter(|e| e.event == TradeEventType::Expired).collect();
+    assert_eq!(expiries.len(), 1);
+    assert_eq!((expiries[0].position_uuid, expiries[0].timestamp, expiries[0].price), (expiring, 2000, 990));
+
+    // an out-of-order tick doesn't move the clock back
+    deliver_tick(&mut sim, symbol_ix, 1500, (985, 989));
+    assert_eq!(sim.sim_time(), 2000);
+
+    // an order whose expiry has already passed can only be filled immediately
+    match place(&mut sim, account_uuid, ordr_limit_long_tif(980, TimeInForce::GTD{expires_at: 1000})) {
+        Ok(BrokerMessage::OrderExpired{..}) => (),
+        res => panic!("Expected the order to expire immediately, got {:?}", res),
+    }
+    assert_eq!(position_counts(&sim, account_uuid), (2, 0, 0));
+}
+
+/// Immediate or cancel orders fill as much as they can right away and expire the rest.
+#[test]
+fn immediate_or_cancel_orders() {
+    let mut settings = SimBrokerSettings::default();
+    settings.max_fill_per_tick = 4;
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+    let starting_balance = sim.settings.starting_balance;
+    deliver_tick(&mut sim, symbol_ix, 1000, (999, 1001));
+
+    // not marketable, so nothing is filled and nothing is left pending
+    match place(&mut sim, account_uuid, ordr_limit_long_tif(990, TimeInForce::IOC)) {
+        Ok(BrokerMessage::OrderExpired{order, ..}) => assert_eq!(order.size, 10),
+        res => panic!("Expected the order to expire, got {:?}", res),
+    }
+    assert_eq!(position_counts(&sim, account_uuid), (0, 0, 0));
+
+    // only `max_fill_per_tick` units can be filled right away
+    let pos_uuid = match place(&mut sim, account_uuid, ordr_limit_long_tif(1005, TimeInForce::IOC)) {
+        Ok(BrokerMessage::PositionOpened{position_id, position, ..}) => {
+            assert_eq!(position.size, 4);
+            position_id
+        },
+        res => panic!("Unexpected response to IOC order: {:?}", res),
+    };
+    assert_eq!(position_counts(&sim, account_uuid), (1, 0, 0));
+    assert_eq!(sim.accounts.data[&account_uuid].ledger.buying_power, starting_balance - 4);
+
+    // the expired remainder never fills
+    deliver_tick(&mut sim, symbol_ix, 1001, (999, 1001));
+    assert_eq!(sim.accounts.data[&account_uuid].ledger.open_positions[&pos_uuid].size, 4);
+
+    let logged: Vec<(TradeEventType, usize)> = sim.trade_log().iter().map(|e| (e.event, e.size)).collect();
+    assert_eq!(logged, vec![(TradeEventType::Expired, 10), (TradeEventType::Fill, 4), (TradeEventType::Expired, 6)]);
+}
+
+/// Returns the (open, pending, closed) position counts of the account.
+fn position_counts(sim: &SimBroker, account_uuid: Uuid) -> (usize, usize, usize) {
+    let ledger = &sim.accounts.data[&account_uuid].ledger;
+    (ledger.open_positions.len(), ledger.pending_positions.len(), ledger.closed_positions.len())
+}
+
+/// Every order type is run through both fill timing modes.  Each case submits an action at t=20 in reaction
+/// to tick N (999/1001 at t=10) and is then fed tick N+1 (1009/1011 at t=30) and tick N+2 (995/997 at t=40).
+/// The expected (open, pending, closed) counts are checked after the submission and after each tick, and the
+/// execution price of the resulting position is checked at the end.
+#[test]
+fn fill_timing_modes() {
+    struct Case {
+        name: &'static str,
+        same_tick: bool,
+        /// Returns the action to submit, setting up any prerequisite state
+        setup: fn(&mut SimBroker, Uuid) -> TradingAction,
+        expected: [(usize, usize, usize); 3],
+        execution_price: usize,
+    }
+
+    fn market(_: &mut SimBroker, _: Uuid) -> TradingAction { ordr_market_long(None) }
+    fn marketable_limit(_: &mut SimBroker, _: Uuid) -> TradingAction { ordr_limit_long(1005, None) }
+    fn market_with_stop(_: &mut SimBroker, _: Uuid) -> TradingAction { ordr_market_long(Some(1000)) }
+    fn limit_with_stop(_: &mut SimBroker, _: Uuid) -> TradingAction { ordr_limit_long(998, Some(996)) }
+    fn market_close(sim: &mut SimBroker, account_uuid: Uuid) -> TradingAction {
+        let action = BrokerAction::TradingAction{account_uuid: account_uuid, action: ordr_market_long(None)};
+        match sim.exec_action(&action) {
+            Ok(BrokerMessage::PositionOpened{position_id, ..}) => TradingAction::MarketClose{uuid: position_id, size: 10},
+            res => panic!("Unable to open position to close: {:?}", res),
+        }
+    }
+
+    let cases = [
+        Case {name: "market", same_tick: true, setup: market, expected: [(1, 0, 0), (1, 0, 0), (1, 0, 0)], execution_price: 1001},
+        Case {name: "market", same_tick: false, setup: market, expected: [(0, 0, 0), (1, 0, 0), (1, 0, 0)], execution_price: 1011},
+        Case {
+            name: "marketable limit", same_tick: true, setup: marketable_limit,
+            expected: [(1, 0, 0), (1, 0, 0), (1, 0, 0)], execution_price: 1001,
+        },
+        Case {
+            // no longer marketable once tick N+1 arrives, so it fills on tick N+2
+            name: "marketable limit", same_tick: false, setup: marketable_limit,
+            expected: [(0, 0, 0), (0, 1, 0), (1, 0, 0)], execution_price: 997,
+        },
+        Case {
+            // the stop is already hit by tick N's price
+            name: "market with stop", same_tick: true, setup: market_with_stop,
+            expected: [(0, 0, 1), (0, 0, 1), (0, 0, 1)], execution_price: 1001,
+        },
+        Case {
+            name: "market with stop", same_tick: false, setup: market_with_stop,
+            expected: [(0, 0, 0), (1, 0, 0), (0, 0, 1)], execution_price: 1011,
+        },
+        Case {
+            // filled by tick N+2, which also hits the stop
+            name: "limit with stop", same_tick: true, setup: limit_with_stop,
+            expected: [(0, 1, 0), (0, 1, 0), (0, 0, 1)], execution_price: 997,
+        },
+        Case {
+            // the stop isn't checked on the tick that filled the order
+            name: "limit with stop", same_tick: false, setup: limit_with_stop,
+            expected: [(0, 0, 0), (0, 1, 0), (1, 0, 0)], execution_price: 997,
+        },
+        Case {name: "market close", same_tick: true, setup: market_close, expected: [(0, 0, 1), (0, 0, 1), (0, 0, 1)], execution_price: 1001},
+        Case {name: "market close", same_tick: false, setup: market_close, expected: [(1, 0, 0), (0, 0, 1), (0, 0, 1)], execution_price: 1001},
+    ];
+
+    for case in cases.iter() {
+        let mut settings = SimBrokerSettings::default();
+        settings.fill_on_submission_tick = case.same_tick;
+        let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+        let desc = format!("{} (same_tick: {})", case.name, case.same_tick);
+
+        deliver_tick(&mut sim, symbol_ix, 10, (999, 1001));
+        let action = (case.setup)(&mut sim, account_uuid);
+        deliver_action(&mut sim, 20, BrokerAction::TradingAction{account_uuid: account_uuid, action: action});
+        assert_eq!(position_counts(&sim, account_uuid), case.expected[0], "after submission: {}", desc);
+        deliver_tick(&mut sim, symbol_ix, 30, (1009, 1011));
+        assert_eq!(position_counts(&sim, account_uuid), case.expected[1], "after tick N+1: {}", desc);
+        deliver_tick(&mut sim, symbol_ix, 40, (995, 997));
+        assert_eq!(position_counts(&sim, account_uuid), case.expected[2], "after tick N+2: {}", desc);
+
+        let ledger = &sim.accounts.data[&account_uuid].ledger;
+        let pos = ledger.open_positions.values().chain(ledger.closed_positions.values()).next().unwrap();
+        assert_eq!(pos.execution_price, Some(case.execution_price), "execution price: {}", desc);
+    }
+}
+
+/// Orders larger than `max_fill_per_tick` fill across several ticks into a single position whose entry
+/// price is the volume-weighted average of the fills.
+#[test]
+fn partial_fill_weighted_entry() {
+    let mut settings = SimBrokerSettings::default();
+    settings.max_fill_per_tick = 4;
+    let starting_balance = settings.starting_balance;
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+
+    let action = BrokerAction::TradingAction{account_uuid: account_uuid, action: ordr_market_long(None)};
+    let pos_uuid = match sim.exec_action(&action) {
+        Ok(BrokerMessage::PositionOpened{position_id, position, ..}) => {
+            assert_eq!(position.size, 4);
+            position_id
+        },
+        res => panic!("Unexpected response to market order: {:?}", res),
+    };
+    // the remainder is pending under the same UUID
+    assert_eq!(sim.pending_orders().len(), 1);
+    assert_eq!(sim.pending_orders()[0].0, pos_uuid);
+    assert_eq!(sim.pending_orders()[0].1.size, 6);
+
+    apply_tick(&mut sim, symbol_ix, (1003, 1005));
+    apply_tick(&mut sim, symbol_ix, (1005, 1007));
+    assert!(sim.pending_orders().is_empty());
+
+    let ledger = &sim.accounts.data[&account_uuid].ledger;
+    let pos = &ledger.open_positions[&pos_uuid];
+    assert_eq!(pos.size, 10);
+    // (4 * 1001 + 4 * 1005 + 2 * 1007) / 10 = 1003.8
+    assert_eq!(pos.execution_price, Some(1004));
+    // the whole order was paid for exactly once
+    assert_eq!(ledger.buying_power, starting_balance - 10);
+    assert_eq!(sim.accounts.positions[symbol_ix].open.len(), 1);
+    assert_eq!(sim.accounts.positions[symbol_ix].open[0].pos, *pos);
+
+    let fills: Vec<(usize, usize)> = sim.trade_log().iter().map(|e| (e.size, e.price)).collect();
+    assert_eq!(fills, vec![(4, 1001), (4, 1005), (2, 1007)]);
+}
+
+/// Cancelling the remainder of a partially filled order keeps the filled portion open and releases the
+/// buying power reserved for the rest.
+#[test]
+fn partial_fill_cancellation() {
+    let mut settings = SimBrokerSettings::default();
+    settings.max_fill_per_tick = 4;
+    let starting_balance = settings.starting_balance;
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+
+    // marketable right away, so 4 units fill immediately and the rest keep the limit price
+    let action = BrokerAction::TradingAction{account_uuid: account_uuid, action: ordr_limit_long(1005, None)};
+    let order_uuid = match sim.exec_action(&action) {
+        Ok(BrokerMessage::PositionOpened{position_id, ..}) => position_id,
+        res => panic!("Unexpected response to limit order: {:?}", res),
+    };
+    assert_eq!(sim.pending_orders()[0].1.price, Some(1005));
+    apply_tick(&mut sim, symbol_ix, (1003, 1005));
+    // not marketable at this price
+    apply_tick(&mut sim, symbol_ix, (1009, 1011));
+    assert_eq!(sim.pending_orders()[0].1.size, 2);
+
+    match sim.cancel_pending_order(order_uuid) {
+        OrderUpdateResult::Ok{updated} => assert_eq!(updated.size, 2),
+        res => panic!("Unexpected cancellation result: {:?}", res),
+    }
+    assert!(sim.pending_orders().is_empty());
+    apply_tick(&mut sim, symbol_ix, (999, 1001));
+
+    let ledger = &sim.accounts.data[&account_uuid].ledger;
+    assert_eq!(ledger.open_positions[&order_uuid].size, 8);
+    assert_eq!(ledger.open_positions[&order_uuid].execution_price, Some(1003));
+    assert_eq!(ledger.buying_power, starting_balance - 8);
+    assert_eq!(sim.trade_log().len(), 2);
+}
+
+/// Creates a SimBroker with a USD account, EURUSD priced at 1.10000 for position values, and EURJPY (which is
+/// quoted in JPY) at 129.990/130.000.  Returns the broker, the account UUID, and the index of EURJPY.
+fn init_jpy_quote_broker(conversion_rates: &str) -> (SimBroker, Uuid, usize) {
+    let mut settings = SimBrokerSettings::default();
+    settings.conversion_rates = String::from(conversion_rates);
+    let (_, dummy_rx) = mpsc::channel();
+    let cs = CommandServer::new(Uuid::new_v4(), "SimBroker Conversion Test");
+    let mut sim = SimBroker::new(settings, cs, dummy_rx).unwrap();
+    sim.oneshot_price_set(String::from("EURUSD"), (110000, 110000), true, 5);
+    sim.oneshot_price_set(String::from("EURJPY"), (129990, 130000), true, 3);
+    let symbol_ix = sim.symbols.get_index(&String::from("EURJPY")).unwrap();
+    let account_uuid = *sim.accounts.data.keys().next().unwrap();
+    (sim, account_uuid, symbol_ix)
+}
+
+fn eurjpy_market(long: bool) -> TradingAction {
+    TradingAction::MarketOrder {
+        symbol: String::from("EURJPY"), long: long, size: 1, stop: None, take_profit: None, max_range: None, tag: None,
+    }
+}
+
+/// P&L of a JPY-quoted position is converted into USD using the price of USDJPY at the time it's realized.
+#[test]
+fn cross_currency_pnl_from_stream() {
+    let (mut sim, account_uuid, symbol_ix) = init_jpy_quote_broker("");
+    let starting_balance = sim.settings.starting_balance;
+    sim.oneshot_price_set(String::from("USDJPY"), (100000, 100000), true, 3);
+    let usdjpy_ix = sim.symbols.get_index(&String::from("USDJPY")).unwrap();
+
+    let action = BrokerAction::TradingAction{account_uuid: account_uuid, action: eurjpy_market(true)};
+    let pos_uuid = match sim.exec_action(&action) {
+        Ok(BrokerMessage::PositionOpened{position_id, position, ..}) => {
+            assert_eq!(position.execution_price, Some(130000));
+            position_id
+        },
+        res => panic!("Unexpected response to market order: {:?}", res),
+    };
+
+    apply_tick(&mut sim, usdjpy_ix, (110000, 110000));
+    apply_tick(&mut sim, symbol_ix, (131000, 131010));
+    // 1.000 JPY * 1000 units / 110 = $9.0909...
+    let (_, equity) = sim.equity();
+    assert!((equity - (starting_balance as f64 + 909.0909)).abs() < 0.001);
+
+    let action = BrokerAction::TradingAction{account_uuid: account_uuid, action: TradingAction::MarketClose{uuid: pos_uuid, size: 1}};
+    match sim.exec_action(&action) {
+        Ok(BrokerMessage::PositionClosed{..}) => (),
+        res => panic!("Unexpected response to market close: {:?}", res),
+    }
+    assert_eq!(sim.accounts.data[&account_uuid].ledger.buying_power, starting_balance + 909);
+}
+
+/// The static rate table is used when no symbol for the conversion pair exists, and positions can't be opened
+/// at all if there's no way to convert their P&L.
+#[test]
+fn cross_currency_pnl_from_static_rates() {
+    let (mut sim, account_uuid, _) = init_jpy_quote_broker("");
+    let action = BrokerAction::TradingAction{account_uuid: account_uuid, action: eurjpy_market(true)};
+    match sim.exec_action(&action) {
+        Err(BrokerError::Message{message}) => assert!(message.contains("USDJPY")),
+        res => panic!("Expected missing conversion rate error but got {:?}", res),
+    }
+    assert_eq!(position_counts(&sim, account_uuid), (0, 0, 0));
+
+    let (mut sim, account_uuid, symbol_ix) = init_jpy_quote_broker("{\"USDJPY\": 125.0}");
+    let starting_balance = sim.settings.starting_balance;
+    let action = BrokerAction::TradingAction{account_uuid: account_uuid, action: eurjpy_market(false)};
+    let pos_uuid = match sim.exec_action(&action) {
+        Ok(BrokerMessage::PositionOpened{position_id, ..}) => position_id,
+        res => panic!("Unexpected response to market order: {:?}", res),
+    };
+
+    // short from 129.990, closed at the ask of 130.490 for a loss of 0.500 JPY * 1000 units / 125 = $4.00
+    apply_tick(&mut sim, symbol_ix, (130480, 130490));
+    let action = BrokerAction::TradingAction{account_uuid: account_uuid, action: TradingAction::MarketClose{uuid: pos_uuid, size: 1}};
+    match sim.exec_action(&action) {
+        Ok(BrokerMessage::PositionClosed{..}) => (),
+        res => panic!("Unexpected response to market close: {:?}", res),
+    }
+    assert_eq!(sim.accounts.data[&account_uuid].ledger.buying_power, starting_balance - 400);
+}
+
+/// JPY-quoted pairs get a pip size of 0.01 by default, and a spec set for a symbol determines its pip value
+/// and which order volumes are accepted.
+#[test]
+fn symbol_specs() {
+    let (sim, _, symbol_ix) = init_jpy_quote_broker("");
+    let eurusd_ix = sim.symbols.get_index(&String::from("EURUSD")).unwrap();
+    assert_eq!(sim.symbol_spec(symbol_ix).pip_size, 0.01);
+    assert_eq!(sim.symbol_spec(eurusd_ix).pip_size, 0.0001);
+    assert_eq!(sim.symbol_spec(symbol_ix).lot_size, sim.settings.fx_lot_size);
+    // 0.500 JPY
+    assert!((sim.price_to_pips(symbol_ix, 500.) - 50.).abs() < 1e-9);
+
+    let mut settings = SimBrokerSettings::default();
+    settings.conversion_rates = String::from("{\"USDJPY\": 125.0}");
+    settings.symbol_specs = String::from(
+        "{\"EURJPY\": {\"pip_size\": 0.01, \"lot_size\": 2000, \"min_volume\": 2, \"volume_step\": 2, \"max_volume\": 10}}"
+    );
+    let mut sim = SimBroker::new(settings, CommandServer::new(Uuid::new_v4(), "SimBroker Spec Test"), mpsc::channel().1).unwrap();
+    sim.oneshot_price_set(String::from("EURUSD"), (110000, 110000), true, 5);
+    sim.oneshot_price_set(String::from("EURJPY"), (129990, 130000), true, 3);
+    let symbol_ix = sim.symbols.get_index(&String::from("EURJPY")).unwrap();
+    let account_uuid = *sim.accounts.data.keys().next().unwrap();
+    let starting_balance = sim.settings.starting_balance;
+
+    let market_long = |size: usize| BrokerAction::TradingAction{account_uuid: account_uuid, action: TradingAction::MarketOrder {
+        symbol: String::from("EURJPY"), long: true, size: size, stop: None, take_profit: None, max_range: None, tag: None,
+    }};
+    // off-step, below the minimum, and above the maximum
+    for &size in &[3, 1, 12] {
+        match sim.exec_action(&market_long(size)) {
+            Err(err) => assert_eq!(err.rejection_reason(), Some(RejectionReason::InvalidSize)),
+            res => panic!("Expected an order of {} lots to be rejected but got {:?}", size, res),
+        }
+    }
+    assert_eq!(position_counts(&sim, account_uuid), (0, 0, 0));
+
+    let pos_uuid = match sim.exec_action(&market_long(2)) {
+        Ok(BrokerMessage::PositionOpened{position_id, ..}) => position_id,
+        res => panic!("Unexpected response to market order: {:?}", res),
+    };
+    // 2 lots of 2000 units at 1.10000 USD per EUR
+    assert_eq!(sim.accounts.data[&account_uuid].ledger.buying_power, starting_balance - 4_400_000);
+
+    // 100 pips * 0.01 JPY * 4000 units / 125 = $32.00
+    apply_tick(&mut sim, symbol_ix, (131000, 131010));
+    let action = BrokerAction::TradingAction{account_uuid: account_uuid, action: TradingAction::MarketClose{uuid: pos_uuid, size: 2}};
+    match sim.exec_action(&action) {
+        Ok(BrokerMessage::PositionClosed{..}) => (),
+        res => panic!("Unexpected response to market close: {:?}", res),
+    }
+    assert_eq!(sim.accounts.data[&account_uuid].ledger.buying_power, starting_balance + 3200);
+}
+
+/// A subscriber that doesn't keep up only sees the newest events while one with enough room sees all of them,
+/// and invalid ticks are reported instead of being applied.
+#[test]
+fn event_stream_slow_consumer() {
+    let mut settings = SimBrokerSettings::default();
+    settings.event_buffer_size = 3;
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+    let slow = sim.events();
+    sim.settings.event_buffer_size = 10;
+    let fast = sim.events();
+
+    let order_uuids: Vec<Uuid> = (0..5).map(|_| place_long_limit(&mut sim, account_uuid, 995)).collect();
+    apply_tick(&mut sim, symbol_ix, (993, 995));
+
+    let mut buffer = vec![TickOutput::Tick(0, Tick::null()); 16];
+    let bad_tick = Tick {bid: 0, ask: 995, timestamp: 10};
+    assert_eq!(sim.process_new_tick(symbol_ix, bad_tick, 0, &mut buffer), 0);
+    assert_eq!(sim.get_price(symbol_ix), Some((993, 995)));
+
+    let filled_uuids = |events: Vec<(u64, BrokerEvent)>| -> Vec<Uuid> {
+        events.into_iter().filter_map(|(_, event)| match event {
+            BrokerEvent::Filled{position_id, ..} => Some(position_id),
+            _ => None,
+        }).collect()
+    };
+
+    assert_eq!(fast.dropped(), 0);
+    let fast_events: Vec<(u64, BrokerEvent)> = fast.take(6).wait().map(|e| e.unwrap()).collect();
+    assert_eq!(fast_events[5].1, BrokerEvent::TickRejected {
+        symbol: String::from("ORDR"), tick: bad_tick, reason: String::from("Ticks must have a nonzero bid and ask."),
+    });
+    assert_eq!(filled_uuids(fast_events), order_uuids);
+
+    // the oldest three fills were dropped to make room
+    assert_eq!(slow.dropped(), 3);
+    let slow_events: Vec<(u64, BrokerEvent)> = slow.take(3).wait().map(|e| e.unwrap()).collect();
+    assert_eq!(filled_uuids(slow_events), order_uuids[3..].to_vec());
+}
+
+/// After a gap in the ticks of a symbol that's longer than `max_price_age_ms`, orders are rejected or held until
+/// the next tick depending on `stale_price_queue`, and equity samples flag the stale price.
+#[test]
+fn stale_price_handling() {
+    let mut settings = SimBrokerSettings::default();
+    settings.fill_on_submission_tick = true;
+    settings.max_price_age_ms = 100;
+    settings.equity_sample_ticks = 1;
+
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings.clone());
+    let market = BrokerAction::TradingAction{account_uuid: account_uuid, action: ordr_market_long(None)};
+    deliver_tick(&mut sim, symbol_ix, 10, (999, 1001));
+    match deliver_action_response(&mut sim, 50, market.clone()) {
+        Some(Ok(BrokerMessage::PositionOpened{..})) => (),
+        res => panic!("Order against a fresh price wasn't filled: {:?}", res),
+    }
+    // 490ms since the last tick
+    match deliver_action_response(&mut sim, 500, market.clone()) {
+        Some(Err(BrokerError::Rejected{reason: RejectionReason::StalePrice, message})) => {
+            assert_eq!(message, "The last price of ORDR is 490ms old.");
+        },
+        res => panic!("Order against a stale price wasn't rejected: {:?}", res),
+    }
+    assert_eq!(position_counts(&sim, account_uuid), (1, 0, 0));
+    sim.sample_equity_if_due();
+    assert_eq!(sim.equity_curve().last().unwrap().stale_symbols, vec![String::from("ORDR")]);
+    deliver_tick(&mut sim, symbol_ix, 510, (1003, 1005));
+    assert!(sim.equity_curve().last().unwrap().stale_symbols.is_empty());
+
+    settings.stale_price_queue = true;
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+    let market = BrokerAction::TradingAction{account_uuid: account_uuid, action: ordr_market_long(None)};
+    deliver_tick(&mut sim, symbol_ix, 10, (999, 1001));
+    assert_eq!(deliver_action_response(&mut sim, 500, market), None);
+    assert_eq!(position_counts(&sim, account_uuid), (0, 0, 0));
+    // filled at the price of the first tick after the gap
+    deliver_tick(&mut sim, symbol_ix, 600, (1009, 1011));
+    let ledger = &sim.accounts.data[&account_uuid].ledger;
+    assert_eq!(ledger.open_positions.len(), 1);
+    assert_eq!(ledger.open_positions.values().next().unwrap().execution_price, Some(1011));
+}
+
+/// Each kind of order rejection is reported with its reason in the response, the event stream, and the trade log.
+#[test]
+fn order_rejection_reasons() {
+    let mut settings = SimBrokerSettings::default();
+    settings.fill_on_submission_tick = true;
+    let (mut sim, account_uuid, _) = init_order_test_broker_with(settings);
+    let events = sim.events();
+    // a symbol that hasn't had any prices yet
+    sim.oneshot_price_set(String::from("SHUT"), (0, 0), false, 4);
+
+    let order = |action: TradingAction| BrokerAction::TradingAction{account_uuid: account_uuid, action: action};
+    let market_long = |symbol: &str, size: usize| TradingAction::MarketOrder {
+        symbol: String::from(symbol), long: true, size: size, stop: None, take_profit: None, max_range: None, tag: None,
+    };
+    let cases = vec![
+        (market_long("ORDR", 10_000_000), RejectionReason::InsufficientMargin),
+        (market_long("ORDR", 0), RejectionReason::InvalidSize),
+        (market_long("NONE", 10), RejectionReason::UnknownSymbol),
+        (market_long("SHUT", 10), RejectionReason::MarketClosed),
+        // stop above the entry price of a long order
+        (ordr_limit_long(990, Some(995)), RejectionReason::PriceOutOfRange),
+    ];
+    let reasons: Vec<RejectionReason> = cases.iter().map(|&(_, reason)| reason).collect();
+
+    for (action, reason) in cases {
+        match deliver_action_response(&mut sim, 20, order(action)) {
+            Some(Err(ref err)) if err.rejection_reason() == Some(reason) => (),
+            res => panic!("Expected a rejection for {:?}, got {:?}", reason, res),
+        }
+    }
+    assert_eq!(position_counts(&sim, account_uuid), (0, 0, 0));
+
+    let event_reasons: Vec<Option<RejectionReason>> = events.take(5).wait().map(|e| match e.unwrap().1 {
+        BrokerEvent::OrderRejected{reason, ..} => reason,
+        event => panic!("Unexpected event: {:?}", event),
+    }).collect();
+    assert_eq!(event_reasons, reasons.iter().cloned().map(Some).collect::<Vec<_>>());
+
+    let logged: Vec<(TradeEventType, Option<RejectionReason>)> = sim.trade_log().iter()
+        .map(|e| (e.event, e.rejection_reason))
+        .collect();
+    assert_eq!(logged, reasons.iter().map(|&reason| (TradeEventType::Rejected, Some(reason))).collect::<Vec<_>>());
+    let limit_entry = &sim.trade_log()[4];
+    assert_eq!((limit_entry.position_uuid, limit_entry.price), (Uuid::nil(), 990));
+
+    // modifications are rejected with reasons as well
+    let order_uuid = place_long_limit(&mut sim, account_uuid, 990);
+    match sim.modify_pending_order(order_uuid, None, Some(0), None) {
+        OrderUpdateResult::Rejected{reason: Some(RejectionReason::InvalidSize), ..} => (),
+        res => panic!("Expected an invalid size rejection, got {:?}", res),
+    }
+
+    // actions still waiting to be processed when the broker is reset are rejected
+    let (c, o) = oneshot::<BrokerResult>();
+    sim.pq.push(QueueItem {timestamp: 30, unit: WorkUnit::ActionComplete(c, order(ordr_market_long(None)))});
+    sim.reset(None).unwrap();
+    match o.wait().unwrap() {
+        Err(BrokerError::Rejected{reason: RejectionReason::BrokerShuttingDown, ..}) => (),
+        res => panic!("Expected the pending action to be rejected by the reset, got {:?}", res),
+    }
+}
+
+fn ordr_market(long: bool, size: usize) -> TradingAction {
+    TradingAction::MarketOrder {
+        symbol: String::from("ORDR"), long: long, size: size, stop: None, take_profit: None, max_range: None, tag: None,
+    }
+}
+
+/// In netting mode, opposite orders reduce the open position and flip it once it's been closed, realizing P&L on
+/// the reduced units only.  Pending orders are netted when they're filled.
+#[test]
+fn netting_flip_through_zero() {
+    let mut settings = SimBrokerSettings::default();
+    settings.position_mode = PositionMode::Netting;
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+    let starting_balance = sim.settings.starting_balance;
+    let buying_power = |sim: &SimBroker| sim.accounts.data[&account_uuid].ledger.buying_power;
+
+    let order = |action: TradingAction| BrokerAction::TradingAction{account_uuid: account_uuid, action: action};
+    sim.exec_action(&order(ordr_market(true, 10))).unwrap();
+    apply_tick(&mut sim, symbol_ix, (1009, 1011));
+
+    // long 10 from 1001 reduced by 4 at the bid of 1009
+    match sim.exec_action(&order(ordr_market(false, 4))) {
+        Ok(BrokerMessage::PositionModified{position, ..}) => assert_eq!((position.long, position.size), (true, 6)),
+        res => panic!("Expected the long position to be reduced, got {:?}", res),
+    }
+    assert_eq!(buying_power(&sim), starting_balance - 10 + 4 + 32);
+
+    // the remaining 6 are closed and the other 5 open a short
+    match sim.exec_action(&order(ordr_market(false, 11))) {
+        Ok(BrokerMessage::PositionOpened{position, ..}) => {
+            assert_eq!((position.long, position.size, position.execution_price), (false, 5, Some(1009)));
+        },
+        res => panic!("Expected the position to be flipped, got {:?}", res),
+    }
+    assert_eq!(position_counts(&sim, account_uuid), (1, 0, 1));
+    assert_eq!(sim.realized_pnl, 80.);
+    assert_eq!(buying_power(&sim), starting_balance + 26 + 6 + 48 - 5);
+
+    let logged: Vec<(TradeEventType, usize)> = sim.trade_log().iter().map(|e| (e.event, e.size)).collect();
+    assert_eq!(logged, vec![
+        (TradeEventType::Fill, 10), (TradeEventType::Close, 4), (TradeEventType::Close, 6), (TradeEventType::Fill, 5),
+    ]);
+
+    // a long limit order for 10 closes the short 5 at the ask of 997 when it fills and opens a long 5
+    place_long_limit(&mut sim, account_uuid, 1000);
+    apply_tick(&mut sim, symbol_ix, (995, 997));
+    assert_eq!(position_counts(&sim, account_uuid), (1, 0, 2));
+    let ledger = &sim.accounts.data[&account_uuid].ledger;
+    let pos = ledger.open_positions.values().next().unwrap();
+    assert_eq!((pos.long, pos.size), (true, 5));
+    // the order's reservation for the netted units is returned along with the short's value and its P&L of 60
+    assert_eq!(ledger.buying_power, starting_balance + 75 - 10 + 5 + 60 + 5);
+}
+
+/// In hedging mode, a long and a short in the same symbol are held at the same time and each ties up margin.
+#[test]
+fn hedging_simultaneous_long_short() {
+    let (mut sim, account_uuid, _) = init_order_test_broker();
+    let starting_balance = sim.settings.starting_balance;
+    assert_eq!(sim.settings.position_mode, PositionMode::Hedging);
+
+    for &long in [true, false].iter() {
+        let action = BrokerAction::TradingAction{account_uuid: account_uuid, action: ordr_market(long, 10)};
+        match sim.exec_action(&action) {
+            Ok(BrokerMessage::PositionOpened{position, ..}) => assert_eq!((position.long, position.size), (long, 10)),
+            res => panic!("Expected a new position, got {:?}", res),
+        }
+    }
+
+    assert_eq!(position_counts(&sim, account_uuid), (2, 0, 0));
+    assert_eq!(sim.accounts.data[&account_uuid].ledger.buying_power, starting_balance - 20);
+    assert_eq!(sim.realized_pnl, 0.);
+}
+
+/// Ticks on Monday, Tuesday, and Thursday: the Monday rollover is applied on Tuesday, and the Tuesday and triple
+/// Wednesday rollovers that were skipped over by the gap are both applied on Thursday.
+#[test]
+fn swap_rollovers() {
+    const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+    let noon = |day: u64| day * DAY_MS + 12 * 60 * 60 * 1000;
+    // 1970-01-05, the first Monday after the epoch
+    let monday = 4;
+
+    let mut settings = SimBrokerSettings::default();
+    settings.swap_rates = String::from("{\"ORDR\": [-2.0, 0.6]}");
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+    let starting_balance = sim.settings.starting_balance;
+
+    deliver_tick(&mut sim, symbol_ix, noon(monday), (999, 1001));
+    for &(long, size) in [(true, 10), (false, 5)].iter() {
+        let action = BrokerAction::TradingAction{account_uuid: account_uuid, action: ordr_market(long, size)};
+        sim.exec_action(&action).unwrap();
+    }
+
+    // -2 * 10 on the long and 0.6 * 5 on the short each night
+    deliver_tick(&mut sim, symbol_ix, noon(monday + 1), (999, 1001));
+    assert_eq!(sim.stats().swap, -17.);
+    deliver_tick(&mut sim, symbol_ix, noon(monday + 3), (999, 1001));
+    assert_eq!(sim.stats().swap, -17. * 5.);
+    assert_eq!(sim.accounts.data[&account_uuid].ledger.buying_power, starting_balance - 15 - 85);
+
+    let rollovers: Vec<(u64, bool, Option<f64>)> = sim.trade_log().iter()
+        .filter(|e| e.event == TradeEventType::Rollover)
+        .map(|e| (e.timestamp / DAY_MS, e.long, e.swap))
+        .collect();
+    assert_eq!(rollovers.len(), 6);
+    for &(day, multiplier) in [(monday, 1.), (monday + 1, 1.), (monday + 2, 3.)].iter() {
+        let mut swaps: Vec<Option<f64>> = rollovers.iter()
+            .filter(|&&(rollover_day, _, _)| rollover_day == day)
+            .map(|&(_, long, swap)| if long { swap } else { swap.map(|s| -s) })
+            .collect();
+        swaps.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(swaps, vec![Some(-20. * multiplier), Some(-3. * multiplier)]);
+    }
+
+    // nothing more is applied until the clock passes the next cutoff
+    deliver_tick(&mut sim, symbol_ix, noon(monday + 3) + 1000, (999, 1001));
+    assert_eq!(sim.stats().swap, -85.);
+}
+
+/// Every registered tick callback is called in registration order with the processed tick.
+#[test]
+fn tick_callbacks() {
+    use std::sync::Mutex;
+
+    let (mut sim, _, symbol_ix) = init_order_test_broker();
+    let calls: Arc<Mutex<Vec<(usize, Tick)>>> = Arc::new(Mutex::new(Vec::new()));
+    for i in 0..2 {
+        let calls = calls.clone();
+        sim.register_tick_callback(Box::new(move |t: &Tick| calls.lock().unwrap().push((i, *t))));
+    }
+
+    deliver_tick(&mut sim, symbol_ix, 10, (1003, 1005));
+    let tick = Tick {timestamp: 10, bid: 1003, ask: 1005};
+    assert_eq!(*calls.lock().unwrap(), vec![(0, tick), (1, tick)]);
+}
+
+/// Tags supplied with orders are carried through to the resulting positions and show up in the trade log, the
+/// blotter, and the equity curve, and positions can be looked up by them after they're filled.
+#[test]
+fn client_order_tags() {
+    let mut settings = SimBrokerSettings::default();
+    settings.equity_sample_ticks = 1;
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+    let tag = String::from("entry-signal-42");
+
+    let mut market = ordr_market_long(None);
+    if let TradingAction::MarketOrder{tag: ref mut order_tag, ..} = market {
+        *order_tag = Some(tag.clone());
+    }
+    let market_uuid = match place(&mut sim, account_uuid, market) {
+        Ok(BrokerMessage::PositionOpened{position_id, position, ..}) => {
+            assert_eq!(position.tag, Some(tag.clone()));
+            position_id
+        },
+        res => panic!("Unexpected result from opening position: {:?}", res),
+    };
+
+    // tags don't have to be unique
+    let mut limit = ordr_limit_long(990, None);
+    if let TradingAction::LimitOrder{tag: ref mut order_tag, ..} = limit {
+        *order_tag = Some(tag.clone());
+    }
+    let limit_uuid = placed_order_id(place(&mut sim, account_uuid, limit));
+    let untagged = place_long_limit(&mut sim, account_uuid, 990);
+    assert_eq!(sim.order_blotter().iter().filter(|e| e.tag == Some(tag.clone())).count(), 1);
+
+    deliver_tick(&mut sim, symbol_ix, 1000, (985, 989));
+    assert_eq!(position_counts(&sim, account_uuid), (0, 3, 0));
+
+    // both were created at the same time, so their order is down to their UUIDs
+    let found: Vec<Uuid> = sim.find_by_tag(&tag).into_iter().map(|(uuid, _)| uuid).collect();
+    assert_eq!(found.len(), 2);
+    assert!(found.contains(&market_uuid) && found.contains(&limit_uuid));
+    assert!(sim.find_by_tag("entry-signal-43").is_empty());
+
+    let logged: Vec<(Uuid, Option<String>)> = sim.trade_log().iter().map(|e| (e.position_uuid, e.tag.clone())).collect();
+    assert!(logged.contains(&(market_uuid, Some(tag.clone()))));
+    assert!(logged.contains(&(limit_uuid, Some(tag.clone()))));
+    assert!(logged.contains(&(untagged, None)));
+
+    let blotter_tags: Vec<Option<String>> = sim.position_blotter().into_iter().map(|e| e.tag).collect();
+    assert_eq!(blotter_tags.iter().filter(|t| **t == Some(tag.clone())).count(), 2);
+    let sampled = &sim.equity_curve().last().unwrap().positions;
+    assert_eq!(sampled.len(), 3);
+    assert!(sampled.iter().any(|p| p.uuid == untagged && p.tag.is_none()));
+}
+
+/// Anomalous ticks are handled according to their policies and counted, and an aborted simulation ignores every
+/// tick after the one that aborted it.
+#[test]
+fn tick_anomaly_policies() {
+    let (mut sim, _, symbol_ix) = init_order_test_broker();
+    let events = sim.events();
+    assert_eq!(sim.settings.crossed_tick_policy, TickPolicy::Reject);
+
+    deliver_tick(&mut sim, symbol_ix, 5000, (999, 1001));
+    // crossed and zero-priced ticks are rejected by default
+    deliver_tick(&mut sim, symbol_ix, 5010, (1005, 1001));
+    deliver_tick(&mut sim, symbol_ix, 5020, (0, 1001));
+    assert_eq!(sim.get_price(symbol_ix), Some((999, 1001)));
+    // ticks up to a second out of order are tolerated
+    deliver_tick(&mut sim, symbol_ix, 4500, (1003, 1005));
+    assert_eq!(sim.get_price(symbol_ix), Some((1003, 1005)));
+    assert_eq!(sim.abort_reason(), None);
+
+    let rejections: Vec<String> = events.take(2).wait().map(|e| match e.unwrap().1 {
+        BrokerEvent::TickRejected{reason, ..} => reason,
+        event => panic!("Unexpected event: {:?}", event),
+    }).collect();
+    assert_eq!(rejections, vec![
+        String::from("The bid of 1005 is above the ask of 1001."),
+        String::from("Ticks must have a nonzero bid and ask."),
+    ]);
+
+    // a tick further out of order aborts the simulation
+    deliver_tick(&mut sim, symbol_ix, 3000, (1007, 1009));
+    assert!(sim.abort_reason().is_some());
+    deliver_tick(&mut sim, symbol_ix, 6000, (1009, 1011));
+    assert_eq!(sim.get_price(symbol_ix), Some((1003, 1005)));
+    let expected = TickAnomalyCounts {crossed: 1, zero_price: 1, backwards: 1, rejected: 3, clamped: 0};
+    assert_eq!(sim.stats().tick_anomalies, expected);
+    assert!(sim.stats().abort_reason.is_some());
+    // ticks with anomalies are also counted by verdict, while ticks after the abort aren't checked at all
+    let verdicts = TickVerdictCounts {ok: 2, duplicate: 0, regressed: 1, crossed: 1, out_of_range: 1};
+    assert_eq!(sim.tick_verdicts(symbol_ix), Some(&verdicts));
+
+    // the same anomalies are repaired when clamping
+    let mut settings = SimBrokerSettings::default();
+    settings.crossed_tick_policy = TickPolicy::Clamp;
+    settings.zero_price_tick_policy = TickPolicy::Clamp;
+    settings.backwards_tick_policy = TickPolicy::Clamp;
+    let (mut sim, _, symbol_ix) = init_order_test_broker_with(settings);
+    deliver_tick(&mut sim, symbol_ix, 5000, (999, 1001));
+    deliver_tick(&mut sim, symbol_ix, 5010, (1005, 1001));
+    assert_eq!(sim.get_price(symbol_ix), Some((1003, 1003)));
+    deliver_tick(&mut sim, symbol_ix, 5020, (0, 1001));
+    assert_eq!(sim.get_price(symbol_ix), Some((1001, 1001)));
+    // a tick without any prices can't be repaired
+    deliver_tick(&mut sim, symbol_ix, 5030, (0, 0));
+    assert_eq!(sim.get_price(symbol_ix), Some((1001, 1001)));
+    deliver_tick(&mut sim, symbol_ix, 3000, (1007, 1009));
+    assert_eq!(sim.get_price(symbol_ix), Some((1007, 1009)));
+    assert_eq!(sim.sim_time(), 5020);
+    assert_eq!(sim.abort_reason(), None);
+    let expected = TickAnomalyCounts {crossed: 1, zero_price: 2, backwards: 1, rejected: 1, clamped: 3};
+    assert_eq!(sim.tick_anomalies(), &expected);
+
+    sim.reset(None).unwrap();
+    assert_eq!(sim.tick_anomalies(), &TickAnomalyCounts::default());
+}
+
+/// The benchmark places and cancels the same orders on every run and leaves the broker reset with its original
+/// settings.
+#[test]
+fn bench_mode() {
+    let mut settings = SimBrokerSettings::default();
+    settings.event_publish = true;
+    let (mut sim, account_uuid, _) = init_order_test_broker_with(settings.clone());
+    place_long_limit(&mut sim, account_uuid, 990);
+
+    let first = sim.bench(500, 4).unwrap();
+    assert_eq!(first.ticks, 500);
+    assert_eq!(first.orders_placed, 2000);
+    assert!(first.orders_cancelled > 0 && first.orders_cancelled < first.orders_placed);
+    // no order stays in the book for more than its lifetime
+    assert!(first.peak_pending_orders > 0 && first.peak_pending_orders <= 4 * 10);
+
+    assert_eq!(sim.settings, settings);
+    assert!(sim.pending_orders().is_empty());
+    assert!(sim.trade_log().is_empty());
+    assert!(sim.symbols.get_index(&String::from("BENCH")).is_none());
+    assert_eq!(sim.get_ledger_clone(account_uuid).unwrap().buying_power, settings.starting_balance);
+
+    let second = sim.bench(500, 4).unwrap();
+    assert_eq!(
+        (second.orders_placed, second.orders_cancelled, second.peak_pending_orders),
+        (first.orders_placed, first.orders_cancelled, first.peak_pending_orders)
+    );
+}
+
+/// A broker restored from a snapshot taken in the middle of a backtest ends up in exactly the same state as one that
+/// ran through the whole backtest, and snapshots can't be restored into a broker with different symbol specs.
+#[test]
+fn snapshot_round_trip() {
+    use serde_json;
+
+    // fills the first limit order, then stops out both positions, fills the second limit order, and expires the
+    // good 'til date order
+    let ticks: Vec<(u64, (usize, usize))> = vec![
+        (2000, (995, 997)), (3000, (1005, 1007)), (4000, (984, 986)),
+        (5000, (1020, 1022)), (6000, (979, 981)), (7000, (1031, 1033)),
+    ];
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker();
+    deliver_tick(&mut sim, symbol_ix, 1000, (999, 1001));
+    place(&mut sim, account_uuid, ordr_market_long(Some(980))).unwrap();
+    place(&mut sim, account_uuid, ordr_limit_long(996, Some(982))).unwrap();
+    place(&mut sim, account_uuid, ordr_limit_long(985, None)).unwrap();
+    place(&mut sim, account_uuid, ordr_limit_long_tif(975, TimeInForce::GTD{expires_at: 5500})).unwrap();
+
+    for &(timestamp, price) in &ticks[..3] {
+        deliver_tick(&mut sim, symbol_ix, timestamp, price);
+    }
+    let snapshot = sim.snapshot();
+    let serialized = serde_json::to_string(&snapshot).unwrap();
+    for &(timestamp, price) in &ticks[3..] {
+        deliver_tick(&mut sim, symbol_ix, timestamp, price);
+    }
+
+    let (mut restored, _, _) = init_order_test_broker();
+    restored.restore(serde_json::from_str(&serialized).unwrap()).unwrap();
+    assert_eq!(restored.snapshot(), snapshot);
+    assert_eq!(restored.sim_time(), 4000);
+    for &(timestamp, price) in &ticks[3..] {
+        deliver_tick(&mut restored, symbol_ix, timestamp, price);
+    }
+
+    assert_eq!(position_counts(&sim, account_uuid), (1, 0, 2));
+    assert_eq!(restored.snapshot(), sim.snapshot());
+    assert_eq!(restored.stats(), sim.stats());
+    assert_eq!(restored.get_ledger_clone(account_uuid), sim.get_ledger_clone(account_uuid));
+
+    let mut settings = SimBrokerSettings::default();
+    settings.symbol_specs = String::from("{\"ORDR\": {\"lot_size\": 100}}");
+    let (mut other, other_account_uuid, _) = init_order_test_broker_with(settings);
+    match other.restore(snapshot) {
+        Err(BrokerError::Message{message}) => assert!(message.contains("contract specification of ORDR")),
+        res => panic!("Expected the restore to be rejected, got {:?}", res),
+    }
+    // nothing was changed by the failed restore
+    assert!(other.accounts.data.contains_key(&other_account_uuid));
+    assert_eq!(other.sim_time(), 0);
+}
+
+/// With a fixed seed the same market orders are requoted on every run, and requotes are counted in the stats.
+/// Setting a threshold exempts orders placed after small price moves.
+#[test]
+fn market_order_requotes() {
+    let mut settings = SimBrokerSettings::default();
+    settings.requote_probability = 0.5;
+    settings.requote_seed = 42;
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+
+    let mut requotes = Vec::new();
+    for i in 0..10 {
+        deliver_tick(&mut sim, symbol_ix, 1000 + i as u64 * 10, (999 + i, 1001 + i));
+        match place(&mut sim, account_uuid, ordr_market_long(None)) {
+            Ok(BrokerMessage::PositionOpened{..}) => (),
+            Err(BrokerError::Rejected{reason: RejectionReason::Requote{new_price}, ..}) => requotes.push((i, new_price)),
+            res => panic!("Unexpected result from placing market order: {:?}", res),
+        }
+    }
+    assert_eq!(requotes, vec![(2, 1003), (3, 1004), (8, 1009)]);
+    assert_eq!(position_counts(&sim, account_uuid), (7, 0, 0));
+    assert_eq!(sim.stats().requotes, 3);
+
+    let mut settings = SimBrokerSettings::default();
+    settings.requote_probability = 1.;
+    settings.requote_threshold = 5;
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+    sim.symbols[symbol_ix].client_price = Some((999, 1001));
+    deliver_tick(&mut sim, symbol_ix, 1000, (1004, 1006));
+    assert!(place(&mut sim, account_uuid, ordr_market_long(None)).is_ok());
+    deliver_tick(&mut sim, symbol_ix, 1010, (1005, 1007));
+    match place(&mut sim, account_uuid, ordr_market_long(None)) {
+        Err(BrokerError::Rejected{reason: RejectionReason::Requote{new_price: 1007}, ..}) => (),
+        res => panic!("Expected the order to be requoted, got {:?}", res),
+    }
+    assert_eq!(sim.stats().requotes, 1);
+}
+
+/// Returns the execution price of the position opened or modified by an action.
+fn execution_price(res: BrokerResult) -> usize {
+    match res {
+        Ok(BrokerMessage::PositionOpened{position, ..}) | Ok(BrokerMessage::PositionModified{position, ..}) => {
+            position.execution_price.unwrap()
+        },
+        res => panic!("Expected a fill, got {:?}", res),
+    }
+}
+
+/// With the depth model enabled, small orders fill at the top of the book while larger ones walk it and get the
+/// volume-weighted average price of the levels they take.  Whatever doesn't fit in the book is filled later.
+#[test]
+fn depth_fill_prices() {
+    let mut settings = SimBrokerSettings::default();
+    settings.depth_levels = 3;
+    settings.depth_level_spacing = 2;
+    settings.depth_level_volume = 10;
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+    deliver_tick(&mut sim, symbol_ix, 1000, (999, 1001));
+
+    assert_eq!(sim.book_levels(symbol_ix, 1001, true), vec![(1001, 10), (1003, 10), (1005, 10)]);
+    assert_eq!(sim.book_levels(symbol_ix, 999, false), vec![(999, 10), (997, 10), (995, 10)]);
+
+    assert_eq!(execution_price(place(&mut sim, account_uuid, ordr_market(true, 10))), 1001);
+    // (1001 * 10 + 1003 * 10) / 20
+    assert_eq!(execution_price(place(&mut sim, account_uuid, ordr_market(true, 20))), 1002);
+    // (1001 * 10 + 1003 * 10 + 1005 * 5) / 25 = 1002.6
+    assert_eq!(execution_price(place(&mut sim, account_uuid, ordr_market(true, 25))), 1003);
+    // (999 * 10 + 997 * 10 + 995 * 5) / 25 = 997.4
+    assert_eq!(execution_price(place(&mut sim, account_uuid, ordr_market(false, 25))), 997);
+    assert_eq!(position_counts(&sim, account_uuid), (4, 0, 0));
+
+    // the book only holds 30 units, so the rest of the order is filled on the next tick
+    let res = place(&mut sim, account_uuid, ordr_market(true, 40));
+    let position_id = match res {
+        Ok(BrokerMessage::PositionOpened{position_id, ref position, ..}) => {
+            assert_eq!(position.size, 30);
+            position_id
+        },
+        ref res => panic!("Expected a partial fill, got {:?}", res),
+    };
+    assert_eq!(execution_price(res), 1003);
+    assert_eq!(position_counts(&sim, account_uuid), (5, 1, 0));
+    deliver_tick(&mut sim, symbol_ix, 1010, (1009, 1011));
+    let position = &sim.accounts.data[&account_uuid].ledger.open_positions[&position_id];
+    assert_eq!(position.size, 40);
+    // (1003 * 30 + 1011 * 10) / 40
+    assert_eq!(position.execution_price, Some(1005));
+    assert_eq!(position_counts(&sim, account_uuid), (5, 0, 0));
+}
+
+/// Limit orders only take the levels of the book at or better than their limit price.
+#[test]
+fn depth_limit_order_fills() {
+    let mut settings = SimBrokerSettings::default();
+    settings.depth_levels = 3;
+    settings.depth_level_spacing = 2;
+    settings.depth_level_volume = 10;
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+    deliver_tick(&mut sim, symbol_ix, 1000, (999, 1001));
+
+    let order = TradingAction::LimitOrder {
+        symbol: String::from("ORDR"), long: true, size: 40, stop: None, take_profit: None, entry_price: 1003,
+        time_in_force: TimeInForce::GTC, tag: None,
+    };
+    // only the levels at 1001 and 1003 are within the limit
+    let res = place(&mut sim, account_uuid, order);
+    let position_id = match res {
+        Ok(BrokerMessage::PositionOpened{position_id, ref position, ..}) => {
+            assert_eq!(position.size, 20);
+            position_id
+        },
+        ref res => panic!("Expected a partial fill, got {:?}", res),
+    };
+    assert_eq!(execution_price(res), 1002);
+    assert_eq!(position_counts(&sim, account_uuid), (1, 1, 0));
+
+    // the levels at 997 and 999 are enough to fill the rest
+    deliver_tick(&mut sim, symbol_ix, 1010, (995, 997));
+    let position = &sim.accounts.data[&account_uuid].ledger.open_positions[&position_id];
+    assert_eq!(position.size, 40);
+    // (1002 * 20 + 998 * 20) / 40
+    assert_eq!(position.execution_price, Some(1000));
+    assert_eq!(position_counts(&sim, account_uuid), (1, 0, 0));
+}
+
+/// Market orders walk a book set with `set_order_book_depth`, taking liquidity from each level in turn.
+#[test]
+fn explicit_order_book_fills() {
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker();
+    deliver_tick(&mut sim, symbol_ix, 1000, (999, 1001));
+    let book = vec![(1005, 20.), (997, 10.), (1001, 10.5), (999, 5.), (1003, 15.)];
+    sim.set_order_book_depth("ORDR", book).unwrap();
+    assert!(sim.set_order_book_depth("NONE", vec![(1001, 1.)]).is_err());
+
+    assert_eq!(sim.book_levels(symbol_ix, 1001, true), vec![(1001, 10), (1003, 15), (1005, 20)]);
+    assert_eq!(sim.book_levels(symbol_ix, 999, false), vec![(999, 5), (997, 10)]);
+
+    // (1001 * 10 + 1003 * 10) / 20
+    let price = execution_price(place(&mut sim, account_uuid, ordr_market(true, 20)));
+    assert!(price > 1001 && price < 1003);
+    assert_eq!(price, 1002);
+    // (999 * 5 + 997 * 3) / 8 = 998.25
+    assert_eq!(execution_price(place(&mut sim, account_uuid, ordr_market(false, 8))), 998);
+
+    // without a book, orders fill at the top of the book again
+    sim.set_order_book_depth("ORDR", Vec::new()).unwrap();
+    assert_eq!(execution_price(place(&mut sim, account_uuid, ordr_market(true, 20))), 1001);
+}
+
+/// Orders that would open a position beyond `max_open_positions` are rejected before they're matched, while
+/// orders that rest on the book are still accepted.
+#[test]
+fn max_open_positions_limit() {
+    let mut settings = SimBrokerSettings::default();
+    settings.max_open_positions = 2;
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+    deliver_tick(&mut sim, symbol_ix, 1000, (999, 1001));
+
+    // all three orders cross the market
+    let results: Vec<BrokerResult> = (0..3)
+        .map(|_| place(&mut sim, account_uuid, ordr_limit_long(1005, None)))
+        .collect();
+    assert!(results[..2].iter().all(|res| match *res {
+        Ok(BrokerMessage::PositionOpened{..}) => true,
+        _ => false,
+    }));
+    match results[2] {
+        Err(BrokerError::Rejected{reason: RejectionReason::MaxPositionsReached, ..}) => (),
+        ref res => panic!("Expected the third order to be rejected, got {:?}", res),
+    }
+    assert_eq!(sim.open_position_count(), 2);
+    match place(&mut sim, account_uuid, ordr_market_long(None)) {
+        Err(BrokerError::Rejected{reason: RejectionReason::MaxPositionsReached, ..}) => (),
+        res => panic!("Expected the market order to be rejected, got {:?}", res),
+    }
+    placed_order_id(place(&mut sim, account_uuid, ordr_limit_long(990, None)));
+    assert_eq!(position_counts(&sim, account_uuid), (2, 1, 0));
+}
+
+/// A resting order that's reached by the market while `max_open_positions` positions are open is cancelled rather
+/// than opening another position, and its buying power is released.
+#[test]
+fn max_open_positions_resting_fill() {
+    let mut settings = SimBrokerSettings::default();
+    settings.max_open_positions = 1;
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker_with(settings);
+    deliver_tick(&mut sim, symbol_ix, 1000, (999, 1001));
+
+    let starting_balance = sim.settings.starting_balance;
+    let order_uuid = placed_order_id(place(&mut sim, account_uuid, ordr_limit_long(990, None)));
+    let reserved = starting_balance - sim.accounts.data[&account_uuid].ledger.buying_power;
+    match place(&mut sim, account_uuid, ordr_market_long(None)) {
+        Ok(BrokerMessage::PositionOpened{..}) => (),
+        res => panic!("Unexpected response to market order: {:?}", res),
+    }
+    let buying_power = sim.accounts.data[&account_uuid].ledger.buying_power;
+
+    deliver_tick(&mut sim, symbol_ix, 2000, (985, 987));
+    assert_eq!(position_counts(&sim, account_uuid), (1, 0, 0));
+    assert_eq!(sim.open_position_count(), 1);
+    assert!(sim.accounts.positions[symbol_ix].pending.is_empty());
+    // the order's buying power came back once it was cancelled
+    assert_eq!(sim.accounts.data[&account_uuid].ledger.buying_power, buying_power + reserved);
+    assert_eq!(sim.cancel_pending_order(order_uuid), OrderUpdateResult::NotFound);
+}
+
+/// Cancelling all orders clears the book across symbols, refunds their buying power, and reports how many there were.
+#[test]
+fn cancel_all_orders() {
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker();
+    sim.oneshot_price_set(String::from("ORDR2"), (1999, 2001), false, 4);
+    let events = sim.events();
+    let starting_balance = sim.settings.starting_balance;
+    deliver_tick(&mut sim, symbol_ix, 1000, (999, 1001));
+
+    for entry_price in 985..990 {
+        place_long_limit(&mut sim, account_uuid, entry_price);
+    }
+    let mut other = ordr_limit_long(1990, None);
+    if let TradingAction::LimitOrder{ref mut symbol, ..} = other {
+        *symbol = String::from("ORDR2");
+    }
+    placed_order_id(place(&mut sim, account_uuid, other));
+    assert_eq!(sim.open_order_count(), 6);
+    assert!(sim.accounts.data[&account_uuid].ledger.buying_power < starting_balance);
+
+    assert_eq!(sim.cancel_all_orders(), 6);
+    assert_eq!(sim.open_order_count(), 0);
+    assert_eq!(sim.accounts.data[&account_uuid].ledger.buying_power, starting_balance);
+    // there's nothing left to cancel, but the event is still emitted
+    assert_eq!(sim.cancel_all_orders(), 0);
+
+    let mut cancellations = Vec::new();
+    for event in events.wait() {
+        if let BrokerEvent::AllOrdersCancelled{count} = event.unwrap().1 {
+            cancellations.push(count);
+            if count == 0 {
+                break;
+            }
+        }
+    }
+    assert_eq!(cancellations, vec![6, 0]);
+}
+
+/// The number of cancelled orders is published to the broker's fills channel even if event publishing is off.
+#[test]
+fn cancel_all_orders_fills_channel() {
+    use tickgrinder_util::transport::redis::sub_channel;
+
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker();
+    assert!(!sim.settings.event_publish);
+    let rx = sub_channel(CONF.redis_host, &sim.fills_channel());
+    deliver_tick(&mut sim, symbol_ix, 1000, (999, 1001));
+    for entry_price in 985..988 {
+        place_long_limit(&mut sim, account_uuid, entry_price);
+    }
+
+    assert_eq!(sim.cancel_all_orders(), 3);
+    let msg = rx.wait().next().unwrap().unwrap();
+    let published: PublishedFillEvent = serde_json::from_str(&msg).unwrap();
+    assert_eq!(published, PublishedFillEvent {timestamp: sim.timestamp, event: FillEvent::AllOrdersCancelled{count: 3}});
+}
+
+/// Preloaded positions are opened at the given price without touching the buying power and are valued at the
+/// current price like any other position.
+#[test]
+fn preload_position() {
+    let (mut sim, account_uuid, symbol_ix) = init_order_test_broker();
+    let starting_balance = sim.settings.starting_balance;
+
+    sim.preload_position("ORDR", 10., 1000).unwrap();
+    sim.preload_position("ORDR", -5., 1000).unwrap();
+    let empty = sim.preload_position("ORDR", 0., 1000);
+    assert_eq!(empty.map_err(|err| err.rejection_reason()), Err(Some(RejectionReason::InvalidSize)));
+    assert_eq!(sim.preload_position("NONE", 10., 1000), Err(BrokerError::NoSuchSymbol));
+
+    let ledger = &sim.accounts.data[&account_uuid].ledger;
+    assert_eq!(ledger.buying_power, starting_balance);
+    assert_eq!(ledger.open_positions.len(), 2);
+    assert_eq!(sim.accounts.positions[symbol_ix].open.len(), 2);
+    assert_eq!(sim.trade_log().len(), 2);
+
+    // the long position loses 11 on each of its 10 units and the short one gains 9 on each of its 5
+    deliver_tick(&mut sim, symbol_ix, 1000, (989, 991));
+    let stats = sim.stats();
+    assert_eq!(stats.unrealized_pnl, -110. + 45.);
+    assert_eq!(stats.positions.get("ORDR"), Some(&5.));
+}
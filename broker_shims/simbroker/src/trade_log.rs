@@ -0,0 +1,184 @@
+//! Keeps a record of every trade executed by the SimBroker and optionally persists it to a Postgres table
+//! so that the trades of a backtest can be analyzed after it finishes.
+
+use postgres::Connection;
+
+use tickgrinder_util::transport::postgres::get_client;
+
+use super::*;
+
+/// What happened to a position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TradeEventType {
+    Fill,
+    Close,
+    MarginCall,
+    /// An order was refused by the broker
+    Rejected,
+    /// Swap was applied to a position at rollover
+    Rollover,
+    /// A pending order was cancelled because its time in force ran out
+    Expired,
+}
+
+impl TradeEventType {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            TradeEventType::Fill => "fill",
+            TradeEventType::Close => "close",
+            TradeEventType::MarginCall => "margin_call",
+            TradeEventType::Rejected => "rejected",
+            TradeEventType::Rollover => "rollover",
+            TradeEventType::Expired => "expired",
+        }
+    }
+}
+
+/// A single entry in the trade log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TradeLogEntry {
+    pub timestamp: u64,
+    pub event: TradeEventType,
+    pub position_uuid: Uuid,
+    pub symbol: String,
+    pub long: bool,
+    pub size: usize,
+    /// The execution price for fills, the exit price for closures, the limit price (0 for market orders)
+    /// of rejected orders, the limit price of expired orders, and the entry price of the position for rollovers
+    pub price: usize,
+    /// Why the order was refused if this is a rejection
+    #[serde(default)]
+    pub rejection_reason: Option<RejectionReason>,
+    /// The swap credited (positive) or charged (negative) if this is a rollover
+    #[serde(default)]
+    pub swap: Option<f64>,
+    /// The tag the client submitted the order with, if any
+    #[serde(default)]
+    pub tag: Option<String>,
+}
+
+/// Holds the trade log of a SimBroker and writes it to Postgres in batches if a table is configured.
+pub struct TradeLog {
+    pub entries: Vec<TradeLogEntry>,
+    /// Index of the first entry that hasn't yet been written to the database
+    unflushed_ix: usize,
+    /// Name of the table to write to or `None` if persistence is disabled
+    table: Option<String>,
+    batch_size: usize,
+    /// The backtest that the trades belong to, used to tag the rows in the database
+    backtest_uuid: Option<Uuid>,
+    conn: Option<Connection>,
+}
+
+impl TradeLog {
+    pub fn new(settings: &SimBrokerSettings) -> TradeLog {
+        TradeLog {
+            entries: Vec::new(),
+            unflushed_ix: 0,
+            table: if settings.trade_log_table.is_empty() { None } else { Some(settings.trade_log_table.clone()) },
+            batch_size: settings.trade_log_batch_size,
+            backtest_uuid: None,
+            conn: None,
+        }
+    }
+
+    pub fn set_backtest_uuid(&mut self, uuid: Uuid) {
+        self.backtest_uuid = Some(uuid);
+    }
+
+    /// Replaces all entries of the log with ones that have already been written to the database, such as those of
+    /// a restored snapshot.
+    pub fn restore(&mut self, entries: Vec<TradeLogEntry>) {
+        self.unflushed_ix = entries.len();
+        self.entries = entries;
+    }
+
+    /// Adds an entry to the log, flushing to the database if a full batch has accumulated.
+    pub fn record(&mut self, entry: TradeLogEntry) -> Result<(), String> {
+        self.entries.push(entry);
+        if self.table.is_some() && self.entries.len() - self.unflushed_ix >= self.batch_size {
+            return self.flush();
+        }
+
+        Ok(())
+    }
+
+    /// Writes all entries that haven't yet been persisted to the database.  Does nothing if persistence
+    /// is disabled.
+    pub fn flush(&mut self) -> Result<(), String> {
+        let table = match self.table {
+            Some(ref table) => table.clone(),
+            None => return Ok(()),
+        };
+        if self.unflushed_ix == self.entries.len() {
+            return Ok(());
+        }
+
+        if self.conn.is_none() {
+            let conn = get_client().map_err(|err| format!("Unable to connect to Postgres: {:?}", err))?;
+            init_trade_log_table(&conn, &table)?;
+            self.conn = Some(conn);
+        }
+
+        let backtest_uuid = match self.backtest_uuid {
+            Some(uuid) => format!("'{}'", uuid.hyphenated()),
+            None => String::from("NULL"),
+        };
+        let values: Vec<String> = self.entries[self.unflushed_ix..].iter().map(|e| {
+            let rejection_reason = match e.rejection_reason {
+                Some(reason) => format!("'{:?}'", reason),
+                None => String::from("NULL"),
+            };
+            let swap = match e.swap {
+                Some(swap) => swap.to_string(),
+                None => String::from("NULL"),
+            };
+            let tag = match e.tag {
+                Some(ref tag) => format!("'{}'", tag.replace("'", "''")),
+                None => String::from("NULL"),
+            };
+            format!(
+                "({}, '{}', '{}', '{}', {}, {}, {}, {}, {}, {}, {})", backtest_uuid, e.symbol.replace("'", "''"), e.event.as_str(),
+                e.position_uuid.hyphenated(), e.timestamp as i64, e.long, e.size as i64, e.price as i64, rejection_reason, swap, tag
+            )
+        }).collect();
+        let query = format!(
+            "INSERT INTO {} (backtest_uuid, symbol, event, position_uuid, event_time, long, size, price, rejection_reason, swap, tag) \
+            VALUES {};",
+            table, values.join(", ")
+        );
+
+        self.conn.as_ref().unwrap().batch_execute(&query)
+            .map_err(|err| format!("Error while writing trade log to Postgres: {:?}", err))?;
+        self.unflushed_ix = self.entries.len();
+        Ok(())
+    }
+}
+
+/// Creates a table to hold SimBroker trade logs if it doesn't already exist.
+pub fn init_trade_log_table(client: &Connection, table_name: &str) -> Result<(), String> {
+    let query = format!(
+    "CREATE TABLE IF NOT EXISTS {}
+    (
+      id BIGSERIAL PRIMARY KEY,
+      backtest_uuid TEXT,
+      symbol TEXT NOT NULL,
+      event TEXT NOT NULL,
+      position_uuid TEXT NOT NULL,
+      event_time BIGINT NOT NULL,
+      long BOOLEAN NOT NULL,
+      size BIGINT NOT NULL,
+      price BIGINT NOT NULL,
+      rejection_reason TEXT,
+      swap DOUBLE PRECISION,
+      tag TEXT
+    )
+    WITH (
+      OIDS=FALSE
+    );
+    ALTER TABLE {0} ADD COLUMN IF NOT EXISTS rejection_reason TEXT;
+    ALTER TABLE {0} ADD COLUMN IF NOT EXISTS swap DOUBLE PRECISION;
+    ALTER TABLE {0} ADD COLUMN IF NOT EXISTS tag TEXT;", table_name);
+    client.batch_execute(&query)
+        .map_err(|err| format!("Error while querying postgres to set up trade log table: {:?}", err))
+}
@@ -0,0 +1,184 @@
+//! Checks the ticks coming out of registered tickstreams before they reach the fill engine.  A single bad row in a
+//! data source (a crossed quote, a missing price, or a timestamp that jumps backwards) would otherwise be used to
+//! fill orders and could skew the results of an entire backtest.  What happens to each kind of anomaly is set by
+//! its `TickPolicy` in the settings.
+
+use std::str::FromStr;
+use std::usize;
+
+use super::*;
+
+/// What the SimBroker does with a tick that has an anomaly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TickPolicy {
+    /// Discard the tick, count it, and report it with a `TickRejected` event.
+    Reject,
+    /// Repair the tick and process it.  Crossed ticks are set to their mid price on both sides, a missing bid or
+    /// ask is set to the other side, and backwards timestamps are set to that of the symbol's previous tick.  Ticks
+    /// that can't be repaired (both prices zero) are rejected instead.
+    Clamp,
+    /// Discard the tick and stop the simulation; no further ticks are read from any tickstream.
+    Abort,
+}
+
+impl FromStr for TickPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TickPolicy, String> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(TickPolicy::Reject),
+            "clamp" => Ok(TickPolicy::Clamp),
+            "abort" => Ok(TickPolicy::Abort),
+            _ => Err(format!("Unknown tick policy: {}", s)),
+        }
+    }
+}
+
+/// Number of anomalous ticks the SimBroker has seen since it was created or last reset.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TickAnomalyCounts {
+    pub crossed: u64,
+    pub zero_price: u64,
+    pub backwards: u64,
+    /// Ticks that were discarded, including the one that aborted the simulation
+    pub rejected: u64,
+    /// Ticks that were repaired and processed
+    pub clamped: u64,
+}
+
+/// Keeps track of the state needed to validate ticks.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TickValidationState {
+    pub counts: TickAnomalyCounts,
+    /// The validator for each symbol's ticks, keyed by symbol index
+    validators: HashMap<usize, TickValidator>,
+    /// Set once a tick with an `Abort` policy anomaly has been received
+    abort_reason: Option<String>,
+}
+
+impl SimBroker {
+    /// Checks a tick that arrived at the broker for anomalies and applies the configured policies to any that are
+    /// found.  Returns the tick to process, which may have been clamped, or `None` if it was discarded.  Every
+    /// tick is discarded once the simulation has been aborted.
+    pub fn validate_tick(&mut self, symbol_ix: usize, tick: Tick) -> Option<Tick> {
+        if self.tick_validator.abort_reason.is_some() {
+            return None;
+        }
+        let mut tick = tick;
+        // equal timestamps are fine here and slightly backwards ones are allowed by `backwards_tick_tolerance_ms`
+        let validator = self.tick_validator.validators.remove(&symbol_ix)
+            .unwrap_or_else(|| TickValidator::new(1, usize::MAX, usize::MAX));
+        let mut verdict = TickVerdict::Ok;
+
+        if validator.check_prices(&tick) == TickVerdict::OutOfRange {
+            verdict = TickVerdict::OutOfRange;
+            self.tick_validator.counts.zero_price += 1;
+            let reason = "Ticks must have a nonzero bid and ask.";
+            // there's nothing to clamp a tick without any prices to
+            let policy = if tick.bid == 0 && tick.ask == 0 {
+                TickPolicy::Reject
+            } else {
+                self.settings.zero_price_tick_policy
+            };
+            if !self.apply_tick_policy(policy, symbol_ix, tick, reason) {
+                return self.finish_validation(symbol_ix, validator, verdict, None);
+            }
+            let price = if tick.bid == 0 { tick.ask } else { tick.bid };
+            tick.bid = price;
+            tick.ask = price;
+        }
+
+        if validator.check_prices(&tick) == TickVerdict::Crossed {
+            if verdict == TickVerdict::Ok {
+                verdict = TickVerdict::Crossed;
+            }
+            self.tick_validator.counts.crossed += 1;
+            let reason = format!("The bid of {} is above the ask of {}.", tick.bid, tick.ask);
+            let policy = self.settings.crossed_tick_policy;
+            if !self.apply_tick_policy(policy, symbol_ix, tick, &reason) {
+                return self.finish_validation(symbol_ix, validator, verdict, None);
+            }
+            let mid = (tick.bid + tick.ask) / 2;
+            tick.bid = mid;
+            tick.ask = mid;
+        }
+
+        let last_timestamp = validator.last_timestamp().unwrap_or(0);
+        if validator.check_timestamp(&tick) == TickVerdict::Regressed
+            && tick.timestamp + self.settings.backwards_tick_tolerance_ms < last_timestamp {
+            if verdict == TickVerdict::Ok {
+                verdict = TickVerdict::Regressed;
+            }
+            self.tick_validator.counts.backwards += 1;
+            let reason = format!(
+                "The timestamp is {}ms before that of the symbol's previous tick.", last_timestamp - tick.timestamp
+            );
+            let policy = self.settings.backwards_tick_policy;
+            if !self.apply_tick_policy(policy, symbol_ix, tick, &reason) {
+                return self.finish_validation(symbol_ix, validator, verdict, None);
+            }
+            tick.timestamp = last_timestamp;
+        }
+
+        self.finish_validation(symbol_ix, validator, verdict, Some(tick))
+    }
+
+    /// Counts the verdict of the first anomaly found in a tick, or `Ok` if there weren't any, and records the
+    /// timestamp of the tick that will be processed, if any.
+    fn finish_validation(
+        &mut self, symbol_ix: usize, mut validator: TickValidator, verdict: TickVerdict, processed: Option<Tick>
+    ) -> Option<Tick> {
+        validator.record(verdict);
+        if let Some(ref tick) = processed {
+            validator.accept(tick);
+        }
+        self.tick_validator.validators.insert(symbol_ix, validator);
+        processed
+    }
+
+    /// Handles an anomalous tick according to `policy`.  Returns `true` if the tick should be clamped and `false`
+    /// if it has been discarded.
+    fn apply_tick_policy(&mut self, policy: TickPolicy, symbol_ix: usize, tick: Tick, reason: &str) -> bool {
+        if policy == TickPolicy::Clamp {
+            self.tick_validator.counts.clamped += 1;
+            return true;
+        }
+
+        self.tick_validator.counts.rejected += 1;
+        let reason = if policy == TickPolicy::Abort {
+            let errmsg = format!(
+                "Aborting the simulation because of a bad tick for {}: {:?}; {}",
+                self.symbols[symbol_ix].name, tick, reason
+            );
+            self.cs.error(None, &errmsg);
+            self.tick_validator.abort_reason = Some(errmsg);
+            format!("{}  The simulation has been aborted.", reason)
+        } else {
+            String::from(reason)
+        };
+
+        let event = BrokerEvent::TickRejected {
+            symbol: self.symbols[symbol_ix].name.clone(),
+            tick: tick,
+            reason: reason,
+        };
+        self.emit_event(event);
+        false
+    }
+
+    /// Returns the number of anomalous ticks that have been received.
+    pub fn tick_anomalies(&self) -> &TickAnomalyCounts {
+        &self.tick_validator.counts
+    }
+
+    /// Returns the number of ticks of a symbol that have been given each `TickVerdict`.  Ticks with more than one
+    /// anomaly are counted under the first that was found.
+    pub fn tick_verdicts(&self, symbol_ix: usize) -> Option<&TickVerdictCounts> {
+        self.tick_validator.validators.get(&symbol_ix).map(|validator| validator.counts())
+    }
+
+    /// Returns a description of the tick that aborted the simulation, if one has.
+    pub fn abort_reason(&self) -> Option<&str> {
+        self.tick_validator.abort_reason.as_ref().map(|reason| reason.as_str())
+    }
+}
@@ -54,6 +54,27 @@ pub const POSTGRES_SETTINGS: SettingsPage = SettingsPage {
             SettingType::String,
             comment: None,
         },
+        SettingRow {
+            id: "tick_sink_batch_size",
+            name: "Tick Recording Batch Size",
+            default: Some("1000"),
+            setting_type: SettingType::Usize,
+            comment: Some("Number of ticks recorded by a Tick Processor that are written to Postgres at once."),
+        },
+        SettingRow {
+            id: "tick_sink_flush_ms",
+            name: "Tick Recording Flush Interval",
+            default: Some("1000"),
+            setting_type: SettingType::Usize,
+            comment: Some("Milliseconds that recorded ticks can wait before they're written even if the batch isn't full."),
+        },
+        SettingRow {
+            id: "tick_sink_max_buffered",
+            name: "Tick Recording Buffer Size",
+            default: Some("100000"),
+            setting_type: SettingType::Usize,
+            comment: Some("How many recorded ticks can be buffered while Postgres is unavailable before the oldest are dropped."),
+        },
     ],
     comment: Some(&["PostgreSQL Settings"]),
 };
@@ -68,6 +89,20 @@ pub const REDIS_SETTINGS: SettingsPage = SettingsPage {
             setting_type: SettingType::String,
             comment: Some("In this format: redis://hostname:port/"),
         },
+        SettingRow {
+            id: "reader_buffer_size",
+            name: "Reader Buffer Size",
+            default: Some("10000"),
+            setting_type: SettingType::Usize,
+            comment: Some("How many ticks read from Redis can wait to be processed before new ones are dropped."),
+        },
+        SettingRow {
+            id: "indicator_publish_buffer_size",
+            name: "Indicator Publish Buffer Size",
+            default: Some("10000"),
+            setting_type: SettingType::Usize,
+            comment: Some("How many indicator values the Tick Processor can have waiting to be published before new ones are dropped."),
+        },
     ],
     comment: Some(&["Redis Settings"]),
 };
@@ -156,6 +191,20 @@ pub const GENERAL_SETTINGS: SettingsPage = SettingsPage {
             setting_type: SettingType::String,
             comment: Some("The redis pub/sub channel on which log messages will be sent."),
         },
+        SettingRow {
+            id: "redis_notifications_channel",
+            name: "Notifications Channel",
+            default: Some("notifications"),
+            setting_type: SettingType::String,
+            comment: Some("The redis pub/sub channel on which alerts such as feed health warnings will be sent."),
+        },
+        SettingRow {
+            id: "log_level",
+            name: "Log Level",
+            default: Some("Debug"),
+            setting_type: SettingType::String,
+            comment: Some("The least severe log messages that instances send to the logger; overridden by `RUST_LOG`."),
+        },
         SettingRow {
             id: "data_dir",
             name: "Data Directory",
@@ -163,6 +212,69 @@ pub const GENERAL_SETTINGS: SettingsPage = SettingsPage {
             setting_type: SettingType::String,
             comment: Some("Data directory for the platform where things like historical ticks and settings are stored."),
         },
+        SettingRow {
+            id: "flatfile_buffer_bytes",
+            name: "Flatfile Buffer Size",
+            default: Some("65536"),
+            setting_type: SettingType::Usize,
+            comment: Some("Size in bytes of the buffer used when reading historical ticks out of CSV files."),
+        },
+        SettingRow {
+            id: "processor_tick_capacity",
+            name: "Tick Processor Tick Capacity",
+            default: Some("100000"),
+            setting_type: SettingType::Usize,
+            comment: Some("How many of the most recent ticks each Tick Processor keeps in memory; older ones are dropped."),
+        },
+        SettingRow {
+            id: "processor_intake_capacity",
+            name: "Tick Processor Intake Capacity",
+            default: Some("10000"),
+            setting_type: SettingType::Usize,
+            comment: Some("How many incoming ticks can wait to be processed before the intake policy starts dropping them."),
+        },
+        SettingRow {
+            id: "processor_intake_policy",
+            name: "Tick Processor Intake Policy",
+            default: Some("DropOldest"),
+            setting_type: SettingType::String,
+            comment: Some("Which ticks are dropped once the intake is full: DropOldest, DropNewest, or CoalesceToLatest."),
+        },
+        SettingRow {
+            id: "tick_min_price",
+            name: "Minimum Tick Price",
+            default: Some("1"),
+            setting_type: SettingType::Usize,
+            comment: Some("Ticks with a bid or ask below this many pips are rejected as implausible."),
+        },
+        SettingRow {
+            id: "tick_max_price",
+            name: "Maximum Tick Price",
+            default: Some("1000000000000"),
+            setting_type: SettingType::Usize,
+            comment: Some("Ticks with a bid or ask above this many pips are rejected as implausible."),
+        },
+        SettingRow {
+            id: "tick_equal_timestamp_tolerance",
+            name: "Equal Tick Timestamp Tolerance",
+            default: Some("0"),
+            setting_type: SettingType::Usize,
+            comment: Some("How many ticks after the first can share a timestamp before the rest are rejected."),
+        },
+        SettingRow {
+            id: "tick_dedup_unchanged_window_ms",
+            name: "Unchanged Tick Window",
+            default: Some("0"),
+            setting_type: SettingType::Usize,
+            comment: Some("Ticks with the same bid and ask as the last one are dropped if they arrive within this many ms of it; 0 only drops exact duplicates."),
+        },
+        SettingRow {
+            id: "tick_timestamp_unit",
+            name: "Tick Timestamp Unit",
+            default: Some(""),
+            setting_type: SettingType::OptionString,
+            comment: Some("Unit of the timestamps of incoming ticks: Seconds, Milliseconds, or Microseconds.  Empty to detect it from their size."),
+        },
         SettingRow {
             id: "websocket_port",
             name: "MM Websocket Port",
@@ -177,6 +289,27 @@ pub const GENERAL_SETTINGS: SettingsPage = SettingsPage {
             setting_type: SettingType::Usize,
             comment: Some("The port the MM web GUI will listen on.  Deprecated."),
         },
+        SettingRow {
+            id: "backtester_metrics_port",
+            name: "Backtester Metrics Port",
+            default: Some("9184"),
+            setting_type: SettingType::Usize,
+            comment: Some("The port on which Backtesters serve Prometheus metrics.  Set to 0 to disable the endpoint."),
+        },
+        SettingRow {
+            id: "jaeger_endpoint",
+            name: "Jaeger Traces Endpoint",
+            default: Some(""),
+            setting_type: SettingType::OptionString,
+            comment: Some("The OTLP/HTTP endpoint of a Jaeger collector that command traces are sent to, such as http://localhost:4318/v1/traces.  Empty to not send them."),
+        },
+        SettingRow {
+            id: "restart_state_file",
+            name: "Backtester Restart State File",
+            default: Some("backtester_restart_state.json"),
+            setting_type: SettingType::String,
+            comment: Some("Where the Backtester saves its backtests before restarting.  It resumes them on startup if this file exists."),
+        },
         SettingRow {
             id: "node_binary_path",
             name: "NodeJS Binary Path",
@@ -257,6 +390,13 @@ pub const RUNTIME_SETTINGS: SettingsPage = SettingsPage {
             setting_type: SettingType::Boolean,
             comment: Some("If instances from a previous spawner are detected when the spawner spawns, kill them?"),
         },
+        SettingRow {
+            id: "heartbeat_interval_ms",
+            name: "Heartbeat Interval",
+            default: Some("350"),
+            setting_type: SettingType::Usize,
+            comment: Some("How many ms the spawner waits between pinging all instances to make sure they're still alive."),
+        },
         SettingRow {
             id: "reset_db_on_load",
             name: "Reset DB On Load",
@@ -324,5 +464,47 @@ pub const DATA_DOWNLOADER_SETTINGS: SettingsPage = SettingsPage {
             setting_type: SettingType::Usize,
             comment: Some("MUST BE MULTIPLE OF 10!  How many messages to buffer before flushing into the sink.  The buffer is used to catch unordered messages."),
         },
+        SettingRow {
+            id: "fxcm_native_downloader_path",
+            name: "FXCM Native Data Downloader Path",
+            default: Some("./fxcm_native_downloader"),
+            setting_type: SettingType::String,
+            comment: Some("The path to the FXCM native data downloader binary, relative to the spawner."),
+        },
+        SettingRow {
+            id: "fxcm_flatfile_downloader_path",
+            name: "FXCM Flatfile Data Downloader Path",
+            default: Some("./fxcm_flatfile_downloader"),
+            setting_type: SettingType::String,
+            comment: Some("The path to the FXCM flatfile data downloader binary, relative to the spawner."),
+        },
+        SettingRow {
+            id: "iex_downloader_path",
+            name: "IEX Data Downloader Script Path",
+            default: Some("./iex_dd/iex.js"),
+            setting_type: SettingType::String,
+            comment: Some("The path to the IEX data downloader's script, which is run with NodeJS."),
+        },
+        SettingRow {
+            id: "poloniex_downloader_path",
+            name: "Poloniex Data Downloader Script Path",
+            default: Some("./poloniex_dd/index.js"),
+            setting_type: SettingType::String,
+            comment: Some("The path to the Poloniex data downloader's script, which is run with NodeJS."),
+        },
+        SettingRow {
+            id: "postgres_downloader_path",
+            name: "Postgres Data Downloader Path",
+            default: Some(""),
+            setting_type: SettingType::OptionString,
+            comment: Some("The path to a binary that downloads ticks out of Postgres.  Empty if there isn't one."),
+        },
+        SettingRow {
+            id: "console_tick_decimals",
+            name: "Console Tick Decimal Places",
+            default: Some("5"),
+            setting_type: SettingType::Usize,
+            comment: Some("How many of the digits of prices are decimal places when downloaded ticks are printed to the console in the human-readable format."),
+        },
     ],
 };
@@ -32,7 +32,7 @@ use tempdir::TempDir;
 
 use tickgrinder_util::instance::PlatformInstance;
 use tickgrinder_util::transport::commands::{Command, Response, Instance, HistTickDst, RunningDownload};
-use tickgrinder_util::transport::command_server::CommandServer;
+use tickgrinder_util::transport::command_server::{CommandServer, set_log_level_response};
 use tickgrinder_util::transport::data::transfer_data;
 use tickgrinder_util::conf::CONF;
 
@@ -85,6 +85,12 @@ impl PlatformInstance for Downloader {
         match cmd {
             Command::Ping => Some(Response::Pong{ args: vec![self.us.uuid.hyphenated().to_string()] }),
             Command::Type => Some(Response::Info{ info: String::from(NAME) }),
+            Command::SetLogLevel{uuid, level} => {
+                if uuid != self.us.uuid {
+                    return None;
+                }
+                Some(set_log_level_response(&level))
+            },
             Command::Kill => {
                 thread::spawn(|| {
                     thread::sleep(std::time::Duration::from_secs(3));
@@ -131,10 +137,7 @@ impl Downloader {
     pub fn new(uuid: Uuid) -> Downloader {
         let cs = CommandServer::new(uuid, NAME);
         Downloader {
-            us: Instance {
-                instance_type: String::from(NAME),
-                uuid: uuid,
-            },
+            us: Instance::new(NAME, uuid),
             cs: cs,
             running_downloads: Arc::new(Mutex::new(HashMap::new())),
             http_client: Arc::new(Client::new()),
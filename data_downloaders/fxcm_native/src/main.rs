@@ -122,7 +122,8 @@ impl DataDownloader {
         let cmd_rx = sub_multiple(CONF.redis_host, &[self.uuid.hyphenated().to_string().as_str(), CONF.redis_control_channel]);
         send_command(&Command::Ready{
             instance_type: "FXCM Native Data Downloader".to_string(),
-            uuid: self.uuid
+            uuid: self.uuid,
+            depends_on: Vec::new(),
         }.wrap(), &client, CONF.redis_control_channel)
             .expect("Unable to send Ready command over Redis.");
 
@@ -140,10 +141,7 @@ impl DataDownloader {
                 Command::DownloadTicks{start_time, end_time, symbol, dst} => {
                     let running_downloads = self.running_downloads.clone();
                     let mut cs = self.cs.clone();
-                    let our_instance = Instance {
-                        uuid: self.uuid,
-                        instance_type: String::from("FXCM Native Data Downloader"),
-                    };
+                    let our_instance = Instance::new("FXCM Native Data Downloader", self.uuid);
                     thread::spawn(move || {
                         let res = DataDownloader::init_download::<TxCallback>(
                             our_instance, symbol.as_str(), dst, start_time, end_time, running_downloads, &mut cs
@@ -54,7 +54,7 @@ impl Logger {
             thread::sleep(Duration::from_secs(1));
 
             cs_clone.send_forget(
-                &Command::Ready{uuid: uuid, instance_type: String::from("Logger")},
+                &Command::Ready{uuid: uuid, instance_type: String::from("Logger"), depends_on: Vec::new()},
                 CONF.redis_control_channel
             );
         });
@@ -178,7 +178,7 @@ fn logmessage_to_query(b: &mut test::Bencher) {
     let msg = LogMessage {
         message_type: String::from("General"),
         level: LogLevel::Notice,
-        sender: Instance {uuid: Uuid::new_v4(), instance_type: String::from("Example Instance") },
+        sender: Instance::new("Example Instance", Uuid::new_v4()),
         message: String::from("This is a test message that could be logged with the logger."),
     };
 
@@ -153,7 +153,9 @@ const HistTickDstShape = {
     host: React.PropTypes.string.isRequired,
     set_name: React.PropTypes.string.isRequired,
   }),
-  Console: React.PropTypes.string,
+  Console: React.PropTypes.shape({
+    format: React.PropTypes.string.isRequired,
+  }),
 };
 
 /**
@@ -216,7 +216,9 @@ const tickSinkDefs
     {clearName: 'Redis Host', jsonName: 'host', paramType: 'str'},
     {clearName: 'Set Name', jsonName: 'set_name', paramType: 'str'},
    ]},
-   Console: {name: 'Stdout', params: []},
+   Console: {name: 'Stdout', params: [
+    {clearName: 'Format (Raw, Csv, Json, or HumanReadable)', jsonName: 'format', paramType: 'str'},
+   ]},
  };
 
 export default {
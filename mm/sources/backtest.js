@@ -40,6 +40,12 @@ $(document).ready(function(){
     var dataSrc = $("#backtestDataSrc").val();
     if(dataSrc == "Redis"){
       dataSrc = {Redis: {host: $("#redisSrcHost").val(), channel: $("#redisSrcChannel").val()}};
+    } else if(dataSrc == "TimescaleDB"){
+      dataSrc = {TimescaleDB: {
+        connection_str: $("#timescaleSrcConnection").val(),
+        hypertable: $("#timescaleSrcHypertable").val(),
+        symbol: $("#timescaleSrcSymbol").val(),
+      }};
     }
     var dataDst = $("#backtestDataDst").val();
     if(dataDst == "Redis"){
@@ -67,12 +73,15 @@ $(document).ready(function(){
     });
   });
 
-  // Show extended redis-only options only if Redis is selected.
+  // Show the extended options of the selected data source
   $("#backtestDataSrc").change(function(){
+    $("#redisSrcOptions").hide();
+    $("#timescaleSrcOptions").hide();
+
     if($("#backtestDataSrc").val() == "Redis"){
       $("#redisSrcOptions").show();
-    } else {
-      $("#redisSrcOptions").hide();
+    } else if($("#backtestDataSrc").val() == "TimescaleDB"){
+      $("#timescaleSrcOptions").show();
     }
   });
 
@@ -137,7 +146,7 @@ function listSimBrokers(){
 function writeBacktests(backtest_list){
   var html = "<tr><td>Backtest ID</td><td>Symbol</td></tr>";
   for(var i=0; i<backtest_list.length; i++){
-    html += `<tr><td>${backtest_list[i].uuid}</td><td>${backtest_list[i].symbol}</td></tr>`;
+    html += `<tr><td>${backtest_list[i].uuid}</td><td>${backtest_list[i].definition.symbol}</td></tr>`;
   }
   if(backtest_list.length === 0){
     html += "<tr><td>No active backtests!</td></tr>";
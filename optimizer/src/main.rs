@@ -59,6 +59,7 @@ impl Optimizer {
         let _ = self.cs.execute(Command::Ready{
             instance_type: "Optimizer".to_string(),
             uuid: self.uuid,
+            depends_on: Vec::new(),
         }, CONF.redis_control_channel.to_string());
 
         for msg in rx.wait() {
@@ -70,6 +71,7 @@ impl Optimizer {
             }
 
             let wr_cmd = wr_msg_res.unwrap();
+            let _span = wr_cmd.span("Optimizer");
             let res = self.get_response(&wr_cmd.cmd);
             let wr_res = res.wrap(wr_cmd.uuid);
             let _ = send_response(&wr_res, &client, CONF.redis_responses_channel);
@@ -7,7 +7,7 @@ use tickgrinder_util::transport::commands::*;
 #[bench]
 fn wrappedcmd_to_string(b: &mut test::Bencher) {
     let cmd = Command::AddSMA{period: 42.23423f64};
-    let wr_cmd = WrappedCommand{uuid: Uuid::new_v4(), cmd: cmd};
+    let wr_cmd = WrappedCommand{uuid: Uuid::new_v4(), cmd: cmd, trace_context: None};
     b.iter(|| {
         let wr_cmd = &wr_cmd;
         let _ = serde_json::to_string(wr_cmd);
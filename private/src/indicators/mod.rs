@@ -4,6 +4,5 @@
 // use tickgrinder_util::trading::trading_condition::*;
 // use tickgrinder_util::conf::CONF;
 
-mod sma;
-
-pub use self::sma::Sma;
+// the time-weighted SMA is shared with the tick processor, so it lives in util
+pub use tickgrinder_util::trading::sma::Sma;
@@ -13,7 +13,7 @@ use tickgrinder_util::strategies::{ManagedStrategy, Helper, StrategyAction, Tick
 use tickgrinder_util::trading::broker::{Broker, BrokerResult};
 use tickgrinder_util::trading::objects::{BrokerAction, BrokerMessage, Account, Ledger};
 use tickgrinder_util::trading::tick::{Tick, GenTick};
-use tickgrinder_util::trading::trading_condition::TradingAction;
+use tickgrinder_util::trading::trading_condition::{TradingAction, TimeInForce};
 use tickgrinder_util::transport::textlog::get_logger_handle;
 use tickgrinder_util::conf::CONF;
 
@@ -147,6 +147,7 @@ pub fn get_action(state: &mut FuzzerState, t: &Tick, rng: *mut c_void) -> Option
                 stop: if random_bool(rng) { Some(price + unsafe { rand_int_range(rng, 0, 5) as usize }) } else { None },
                 max_range: None,
                 take_profit: if random_bool(rng) { Some(price + unsafe { rand_int_range(rng, 0, 5) as usize }) } else { None },
+                tag: None,
             };
             Some(StrategyAction::BrokerAction(BrokerAction::TradingAction{
                 account_uuid: state.account_uuid.unwrap(),
@@ -170,6 +171,8 @@ pub fn get_action(state: &mut FuzzerState, t: &Tick, rng: *mut c_void) -> Option
                 stop: if random_bool(rng) { Some(price + unsafe { rand_int_range(rng, 0, 5) as usize }) } else { None },
                 take_profit: if random_bool(rng) { Some(price + unsafe { rand_int_range(rng, 0, 5) as usize }) } else { None },
                 entry_price: price,
+                time_in_force: TimeInForce::GTC,
+                tag: None,
             };
 
             Some(StrategyAction::BrokerAction(BrokerAction::TradingAction{
@@ -286,6 +289,10 @@ pub fn handle_pushstream(state: &mut FuzzerState, msg: &BrokerResult, _: *mut c_
                     let cancelled_order = state.get_ledger().pending_positions.remove(&order_id).unwrap();
                     assert_eq!(&cancelled_order, order);
                 }
+                &BrokerMessage::OrderExpired{order_id, timestamp: _, ..} => {
+                    // immediate-or-cancel orders that couldn't be filled at all were never pending
+                    let _ = state.get_ledger().pending_positions.remove(&order_id);
+                },
                 &BrokerMessage::PositionOpened{ref position_id, ref position, timestamp: _} => {
                     let ledger = state.get_ledger();
                     let _ = ledger.pending_positions.remove(position_id);
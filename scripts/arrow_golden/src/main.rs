@@ -0,0 +1,64 @@
+//! Regenerates `GOLDEN_STREAM` in `util/src/transport/tickstream/sinks/arrow_sink.rs`, which the `ArrowSink` tests
+//! compare the sink's output against.  It writes the ticks of `golden_ticks()` with the `StreamWriter` of arrow-rs
+//! 54 and prints the stream as the body of a Rust byte array.  The output is an Arrow IPC *stream*, which is what
+//! `ArrowSink` writes, rather than the IPC file format with its footer.
+//!
+//! arrow-rs needs a much newer compiler than the rest of the platform, so this is built on its own with a current
+//! stable toolchain:
+//!
+//! ```sh
+//! cd scripts/arrow_golden && cargo +stable run --release
+//! ```
+
+extern crate arrow;
+
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Int32Array, Int64Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::ipc::writer::{IpcWriteOptions, StreamWriter};
+use arrow::ipc::MetadataVersion;
+use arrow::record_batch::RecordBatch;
+
+/// Same as `golden_ticks()` in the sink's tests, as (timestamp, bid, ask)
+fn golden_ticks() -> Vec<(i64, i32, i32)> {
+    (0..5).map(|i| (1500000000000 + i as i64, 1000 + i, 1002 + i)).collect()
+}
+
+/// The ticks are split into record batches after each of these numbers of ticks, like `write_arrow_file(.., &[3])`.
+const BATCH_ENDS: [usize; 1] = [3];
+
+fn main() {
+    // matches `TICK_COLUMNS`
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("timestamp", DataType::Int64, false),
+        Field::new("bid", DataType::Int32, false),
+        Field::new("ask", DataType::Int32, false),
+    ]));
+    let options = IpcWriteOptions::try_new(8, false, MetadataVersion::V5).unwrap();
+
+    let ticks = golden_ticks();
+    let mut bounds = vec![0];
+    bounds.extend_from_slice(&BATCH_ENDS);
+    bounds.push(ticks.len());
+
+    let mut stream = Vec::new();
+    {
+        let mut writer = StreamWriter::try_new_with_options(&mut stream, &schema, options).unwrap();
+        for range in bounds.windows(2) {
+            let batch_ticks = &ticks[range[0]..range[1]];
+            let columns: Vec<ArrayRef> = vec![
+                Arc::new(Int64Array::from(batch_ticks.iter().map(|t| t.0).collect::<Vec<_>>())),
+                Arc::new(Int32Array::from(batch_ticks.iter().map(|t| t.1).collect::<Vec<_>>())),
+                Arc::new(Int32Array::from(batch_ticks.iter().map(|t| t.2).collect::<Vec<_>>())),
+            ];
+            writer.write(&RecordBatch::try_new(schema.clone(), columns).unwrap()).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    for line in stream.chunks(16) {
+        let bytes: Vec<String> = line.iter().map(|byte| format!("0x{:02x},", byte)).collect();
+        println!("    {}", bytes.join(" "));
+    }
+}
@@ -3,7 +3,7 @@
 //! Responsible for spawning, destroying, and managing all instances of the bot4
 //! platform's modules and reporting on their status.
 
-#![feature(plugin, test, conservative_impl_trait, custom_derive)]
+#![feature(plugin, test, conservative_impl_trait, custom_derive, integer_atomics)]
 
 extern crate uuid;
 extern crate redis;
@@ -16,13 +16,21 @@ extern crate ws;
 #[macro_use]
 extern crate serde_derive;
 extern crate tantivy;
+extern crate toml;
+#[cfg(test)]
+extern crate tempdir;
 
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 use std::process;
 use std::str::FromStr;
 use std::mem;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
 
 use uuid::Uuid;
 use futures::{Future, Sink, oneshot, Complete};
@@ -31,12 +39,16 @@ use futures::stream::Stream;
 use tickgrinder_util::transport::redis::{sub_channel, sub_multiple, get_client};
 use tickgrinder_util::transport::commands::*;
 use tickgrinder_util::transport::command_server::*;
-use tickgrinder_util::conf::CONF;
+use tickgrinder_util::transport::tracing;
+use tickgrinder_util::conf::{CONF, Conf};
 
 mod redis_proxy;
 mod documents;
 use documents::*;
 
+/// How long to wait for a replacement instance to send its `Ready` message during a rolling restart
+const READY_TIMEOUT_MS: u64 = 30000;
+
 /// Holds a list of all instances that the spawner has spawned and thinks are alive
 #[derive(Clone)]
 struct InstanceManager {
@@ -44,9 +56,16 @@ struct InstanceManager {
     pub living: Arc<Mutex<Vec<Instance>>>,
     pub cs: CommandServer,
     pub store_handle: StoreHandle,
+    /// Channels to notify when the instance with the given Uuid sends a `Ready` message
+    pub ready_waiters: Arc<Mutex<HashMap<Uuid, mpsc::Sender<()>>>>,
+    /// How many ms to wait between heartbeat pings; shared between all clones so it can be changed at runtime
+    pub heartbeat_interval_ms: Arc<AtomicU64>,
+    /// The Uuids of the instances that each instance declared it depends on in its `Ready` message
+    pub dependencies: Arc<Mutex<HashMap<Uuid, Vec<Uuid>>>>,
 }
 
 fn main() {
+    tracing::init_from_conf("Spawner");
     let mut spawner = InstanceManager::new();
     spawner.init();
 }
@@ -71,6 +90,9 @@ impl InstanceManager {
             living: Arc::new(Mutex::new(Vec::new())),
             cs: cs,
             store_handle: store_handle,
+            ready_waiters: Arc::new(Mutex::new(HashMap::new())),
+            heartbeat_interval_ms: Arc::new(AtomicU64::new(CONF.heartbeat_interval_ms as u64)),
+            dependencies: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -134,7 +156,7 @@ impl InstanceManager {
         // register self as in instance
         {
             let mut living = self.living.lock().unwrap();
-            (*living).push(Instance{instance_type: "Spawner".to_string(), uuid: self.uuid});
+            (*living).push(Instance::new("Spawner", self.uuid));
         }
 
         // start ping heartbeat
@@ -149,40 +171,35 @@ impl InstanceManager {
                 println!("{}",wrnmsg);
                 cs.warning(None, &wrnmsg);
 
-                // deregister the old instance
-                self.remove_instance(dead_instance.uuid);
-
-                let res_outer = self.cs.execute(
-                    Command::Type,
-                    dead_instance.uuid.hyphenated().to_string()
-                ).wait().unwrap();
-
-                match res_outer {
-                    Ok(response) => { // we actually got a reply from the presumed dead instance
-                        match response {
-                            Response::Info{info} => {
-                                let infomsg = format!("{:?} wasn't dead after all...", dead_instance);
-                                println!("{}", infomsg);
-                                cs.notice(None, &infomsg);
-                                self.add_instance(Instance{instance_type: info, uuid: dead_instance.uuid});
-                            },
-                            _ => {
-                                let errmsg = format!("Received unexpected response from Type query: {:?}", response);
-                                println!("{}", errmsg);
-                                cs.error(None, &errmsg);
-                            }
-                        }
+                // ping the suspect directly before giving up on it
+                let uuid_string = dead_instance.uuid.hyphenated().to_string();
+                match self.ping_instance(dead_instance.uuid).wait() {
+                    // we actually got a reply from the presumed dead instance
+                    Ok(Ok(Response::Pong{ref args})) if args.first() == Some(&uuid_string) => {
+                        let infomsg = format!("{:?} wasn't dead after all...", dead_instance);
+                        println!("{}", infomsg);
+                        cs.notice(None, &infomsg);
                     },
-                    Err(_) => {
-                        let wrnmsg = format!("{:?} is really, truly, dead.", dead_instance);
+                    Ok(Ok(response)) => {
+                        let errmsg = format!("Received unexpected response to Ping: {:?}", response);
+                        println!("{}", errmsg);
+                        cs.error(None, &errmsg);
+                    },
+                    _ => {
+                        let uptime = self.uptime(dead_instance.uuid).unwrap_or(Duration::from_millis(0));
+                        let wrnmsg = format!(
+                            "{:?} is really, truly, dead after {} seconds of uptime.", dead_instance, uptime.as_secs()
+                        );
                         println!("{}", wrnmsg);
-                        cs.warning(None, &wrnmsg)
+                        cs.warning(None, &wrnmsg);
+                        // deregister the old instance
+                        self.remove_instance(dead_instance.uuid);
                         // TODO: respawn dead instance
                     }
                 }
             }
 
-            thread::sleep(Duration::from_millis(350));
+            thread::sleep(self.heartbeat_interval());
         }
     }
 
@@ -248,6 +265,7 @@ impl InstanceManager {
 
                 match WrappedCommand::from_str(cmd_string.as_str()) {
                     Ok(wr_cmd) => {
+                        let _span = wr_cmd.span("Spawner");
                         let (c, o) = oneshot::<Response>();
                         dup.handle_command(wr_cmd.cmd, c);
 
@@ -289,17 +307,35 @@ impl InstanceManager {
                 Response::Info{info: "Shutting down in 3 seconds...".to_string()}
             },
             Command::Type => Response::Info{info: "Spawner".to_string()},
+            Command::SetLogLevel{uuid, level} => {
+                if uuid == self.uuid {
+                    set_log_level_response(&level)
+                } else {
+                    Response::Error{status: format!("This is the Spawner, not instance {}", uuid)}
+                }
+            },
             // This means a new instance has spawned and we should register it in our internal instance list
-            Command::Ready{instance_type, uuid} => {
-                self.add_instance(Instance{instance_type: instance_type, uuid: uuid});
+            Command::Ready{instance_type, uuid, depends_on} => {
+                self.add_instance(Instance::new(&instance_type, uuid));
+                self.dependencies.lock().unwrap().insert(uuid, depends_on);
+                if let Some(waiter) = self.ready_waiters.lock().unwrap().remove(&uuid) {
+                    let _ = waiter.send(());
+                }
                 Response::Ok
             },
             Command::KillAllInstances => self.kill_all(),
+            Command::KillGroup{instance_type, metadata_filter} => self.kill_group(instance_type, metadata_filter),
+            Command::RollingRestart{instance_type, delay_ms} => self.rolling_restart(instance_type, delay_ms),
             Command::Census => self.census(),
+            Command::DependencyGraph => self.dependency_graph(),
             // Command::SpawnMM => self.spawn_mm(),
-            Command::SpawnOptimizer{strategy} => self.spawn_optimizer(strategy),
-            Command::SpawnTickParser{symbol} => self.spawn_tick_parser(symbol),
-            Command::SpawnBacktester => self.spawn_backtester(),
+            Command::SpawnOptimizer{strategy} => spawn_response(self.spawn_optimizer(strategy)),
+            Command::SpawnTickParser{symbol, metadata} => spawn_response(self.spawn_tick_parser(symbol, metadata)),
+            Command::SpawnTickParserGroup{symbols} => self.spawn_tick_parser_group(symbols),
+            Command::TickProcessorSymbols{uuid, symbols} => self.set_tick_parser_symbols(uuid, symbols),
+            Command::SpawnBacktester => spawn_response(self.spawn_backtester()),
+            Command::SpawnFromConfig{config_path} => self.spawn_from_config(&config_path),
+            Command::SetHeartbeatInterval{ms} => self.set_heartbeat_interval(ms),
             Command::InsertIntoDocumentStore{doc} => {
                 let tx = mem::replace(&mut self.store_handle.insertion_tx, None).unwrap();
                 let new_tx = tx.send((doc, c)).wait().unwrap();
@@ -319,10 +355,15 @@ impl InstanceManager {
                 self.store_handle.get_doc_by_title(title, c);
                 return;
             },
-            Command::SpawnFxcmFlatfileDataDownloader => self.spawn_fxcm_flatfile_dd(),
-            Command::SpawnFxcmNativeDataDownloader => self.spawn_fxcm_dd(),
-            Command::SpawnIexDataDownloader => self.spawn_iex_dd(),
-            Command::SpawnPoloniexDataDownloader => self.spawn_poloniex_dd(),
+            Command::SpawnFxcmFlatfileDataDownloader => {
+                spawn_response(self.spawn_downloader(&HistTickSrc::FxcmFlatfile))
+            },
+            Command::SpawnFxcmNativeDataDownloader => spawn_response(self.spawn_downloader(&HistTickSrc::FxcmNative)),
+            Command::SpawnIexDataDownloader => spawn_response(self.spawn_downloader(&HistTickSrc::Iex)),
+            Command::SpawnPoloniexDataDownloader => spawn_response(self.spawn_downloader(&HistTickSrc::Poloniex)),
+            Command::SpawnDataDownloader{source, dst, symbol, start, end} => {
+                spawn_response(self.spawn_data_downloader(source, dst, symbol, &start, &end))
+            },
             _ => Response::Error{
                 status: format!("Command not accepted by the instance spawner: {:?}", cmd),
             },
@@ -332,23 +373,83 @@ impl InstanceManager {
         c.send(res).expect("Error whle sending response from command handling; receiver probably went away.");
     }
 
-    /// Returns a list of all living instances
+    /// Returns how long the heartbeat loop waits between pings.
+    fn heartbeat_interval(&self) -> Duration {
+        Duration::from_millis(self.heartbeat_interval_ms.load(Ordering::Relaxed))
+    }
+
+    /// Changes the heartbeat interval, taking effect after the current wait.
+    fn set_heartbeat_interval(&self, ms: u64) -> Response {
+        if ms == 0 {
+            return Response::Error{status: String::from("The heartbeat interval must be greater than 0.")};
+        }
+        self.heartbeat_interval_ms.store(ms, Ordering::Relaxed);
+
+        Response::Ok
+    }
+
+    /// Returns a list of all living instances along with how long they've been up in `uptime_ms`
     fn census(&self) -> Response {
         let living = self.living.lock().unwrap();
         let mut partials = Vec::new();
         for inst in living.iter() {
-            match serde_json::to_string(inst) {
-                Ok(ser) => partials.push(ser),
+            let mut val = match serde_json::to_value(inst) {
+                Ok(val) => val,
                 Err(e) => return Response::Error{
                     status: format!("Error serializing instance: {:?}", e)
                 }
+            };
+            let uptime = inst.uptime();
+            let uptime_ms = uptime.as_secs() * 1000 + (uptime.subsec_nanos() / 1_000_000) as u64;
+            if let Some(obj) = val.as_object_mut() {
+                obj.insert(String::from("uptime_ms"), serde_json::Value::from(uptime_ms));
             }
+            partials.push(val.to_string());
         }
 
         let res_string = format!("[{}]", partials.join(", "));
         Response::Info{info: res_string}
     }
 
+    /// Returns a DOT graph of the dependencies between instances with an edge from each instance to the ones that
+    /// depend on it.  Nodes are labeled with the instance's type and, for Tick Processors, their symbol.
+    fn dependency_graph(&self) -> Response {
+        let mut labels: HashMap<Uuid, String> = HashMap::new();
+        labels.insert(self.uuid, String::from("Spawner"));
+        for inst in self.living.lock().unwrap().iter() {
+            let label = match inst.metadata.get("symbol") {
+                Some(symbol) => format!("{}[{}]", inst.instance_type, symbol),
+                None => inst.instance_type.clone(),
+            };
+            labels.insert(inst.uuid, label);
+        }
+
+        let dependencies = self.dependencies.lock().unwrap();
+        let mut edges: Vec<(Uuid, Uuid)> = dependencies.iter()
+            .flat_map(|(dependent, depends_on)| depends_on.iter().map(move |dependency| (*dependency, *dependent)))
+            .collect();
+        edges.sort_by_key(|&(from, to)| (from.hyphenated().to_string(), to.hyphenated().to_string()));
+
+        let mut nodes: Vec<Uuid> = labels.keys().cloned()
+            .chain(edges.iter().flat_map(|&(from, to)| vec![from, to]))
+            .collect();
+        nodes.sort_by_key(|uuid| uuid.hyphenated().to_string());
+        nodes.dedup();
+
+        let mut lines = vec![String::from("digraph dependencies {")];
+        for uuid in nodes {
+            // instances that aren't alive anymore are labeled with their Uuid
+            let label = labels.get(&uuid).cloned().unwrap_or_else(|| uuid.hyphenated().to_string());
+            lines.push(format!("    \"{}\" [label=\"{}\"];", uuid.hyphenated(), label));
+        }
+        for (from, to) in edges {
+            lines.push(format!("    \"{}\" -> \"{}\";", from.hyphenated(), to.hyphenated()));
+        }
+        lines.push(String::from("}"));
+
+        Response::Info{info: lines.join("\n")}
+    }
+
     /// Spawns a logger instance and inserts it into the list of running instances
     fn spawn_logger(&mut self) -> Response {
         let mod_uuid = Uuid::new_v4();
@@ -360,93 +461,285 @@ impl InstanceManager {
         Response::Ok
     }
 
-    /// Spawns a new Tick Processor instance with the given symbol and inserts its Uuid into
-    /// the living instances list
-    fn spawn_tick_parser(&mut self, symbol: String) -> Response {
+    /// Spawns a new Tick Processor instance with the given symbol and inserts it into the living
+    /// instances list along with the supplied metadata.  Fails if a living Tick Processor already follows the symbol.
+    fn spawn_tick_parser(&mut self, symbol: String, metadata: HashMap<String, String>) -> Result<Uuid, String> {
+        if let Some(owner) = self.tick_parser_following(&symbol) {
+            return Err(format!("Tick Processor {} already follows {}", owner.hyphenated(), symbol));
+        }
         let mod_uuid = Uuid::new_v4();
+        self.start_tick_parser(mod_uuid, symbol, metadata)?;
+        Ok(mod_uuid)
+    }
+
+    /// Spawns a Tick Processor for each symbol, responding with a JSON array of their Uuids in the order of the
+    /// symbols, with `null` in place of any that couldn't be spawned.
+    fn spawn_tick_parser_group(&mut self, symbols: Vec<String>) -> Response {
+        let mut uuids: Vec<Option<Uuid>> = Vec::with_capacity(symbols.len());
+        for symbol in symbols {
+            match self.spawn_tick_parser(symbol.clone(), HashMap::new()) {
+                Ok(uuid) => uuids.push(Some(uuid)),
+                Err(err) => {
+                    self.cs.error(None, &format!("Unable to spawn Tick Processor for {}: {}", symbol, err));
+                    uuids.push(None);
+                },
+            }
+        }
+
+        match serde_json::to_string(&uuids) {
+            Ok(info) => Response::Info{info: info},
+            Err(err) => Response::Error{status: format!("Unable to serialize spawned Uuids: {:?}", err)},
+        }
+    }
+
+    /// Starts a Tick Processor process with the given Uuid and registers it.  The symbol is stored in
+    /// the instance's metadata so that it can be respawned later, along with any symbols that were added to the
+    /// instance being replaced.
+    fn start_tick_parser(&mut self, mod_uuid: Uuid, symbol: String, mut metadata: HashMap<String, String>) -> Result<(), String> {
         let path = "./tick_processor";
-        let _ = process::Command::new(path)
-                                .arg(mod_uuid.to_string().as_str())
-                                .arg(symbol.as_str())
-                                .spawn()
-                                .expect("Unable to spawn Tick Parser");
+        let extra_symbols: Vec<String> = metadata.get("symbols")
+            .map(|symbols| symbols.split(',').filter(|s| !s.is_empty() && *s != symbol).map(String::from).collect())
+            .unwrap_or_default();
+        // registered before it's started so that a `Ready` message sent right away doesn't register it without its
+        // metadata first
+        metadata.insert(String::from("symbol"), symbol.clone());
+        self.add_instance(Instance{metadata: metadata, ..Instance::new("Tick Processor", mod_uuid)});
+        let res = process::Command::new(path)
+            .arg(mod_uuid.to_string().as_str())
+            .arg(symbol.as_str())
+            .args(&extra_symbols)
+            .spawn();
+        if let Err(err) = res {
+            self.remove_instance(mod_uuid);
+            return Err(format!("Unable to spawn Tick Parser: {:?}", err));
+        }
 
-        Response::Ok
+        Ok(())
+    }
+
+    /// Returns the Uuid of the living Tick Processor that follows `symbol`, either as the symbol it was spawned with
+    /// or as one it added later, if there is one.
+    fn tick_parser_following(&self, symbol: &str) -> Option<Uuid> {
+        self.find_instances(Some("Tick Processor"), None).into_iter()
+            .find(|inst| followed_symbols(inst).iter().any(|followed| followed == symbol))
+            .map(|inst| inst.uuid)
+    }
+
+    /// Records the symbols that a Tick Processor follows in its metadata, as a comma-separated list under `symbols`.
+    fn set_tick_parser_symbols(&self, uuid: Uuid, symbols: Vec<String>) -> Response {
+        let mut living = self.living.lock().unwrap();
+        match living.iter_mut().find(|inst| inst.uuid == uuid) {
+            Some(inst) => {
+                inst.metadata.insert(String::from("symbols"), symbols.join(","));
+                Response::Ok
+            },
+            None => Response::Error{status: format!("No living instance with the Uuid {}", uuid.hyphenated())},
+        }
+    }
+
+    /// Registers interest in the `Ready` message of the instance with the given Uuid, returning a
+    /// channel that receives a message once it arrives.
+    fn await_ready(&self, uuid: Uuid) -> mpsc::Receiver<()> {
+        let (tx, rx) = mpsc::channel();
+        self.ready_waiters.lock().unwrap().insert(uuid, tx);
+        rx
     }
 
-    /// Spawns a new Optimizer instance with the specified strategy and inserts its Uuid into
-    /// the living instances list
-    fn spawn_optimizer(&mut self, strategy: String) -> Response {
+    /// Spawns a new instance configured the same way as `inst`.  Returns the Uuid of the new instance
+    /// along with a channel that receives a message once it's ready.
+    fn respawn(&mut self, inst: &Instance) -> Result<(Uuid, mpsc::Receiver<()>), String> {
+        let new_uuid = Uuid::new_v4();
+        let ready_rx = self.await_ready(new_uuid);
+        let res = match inst.instance_type.as_str() {
+            "Tick Processor" => match inst.metadata.get("symbol") {
+                Some(symbol) => self.start_tick_parser(new_uuid, symbol.clone(), inst.metadata.clone()),
+                None => Err(format!("No symbol stored for Tick Processor {}", inst.uuid.hyphenated())),
+            },
+            _ => Err(format!("Unable to respawn instances of type {}", inst.instance_type)),
+        };
+
+        match res {
+            Ok(()) => Ok((new_uuid, ready_rx)),
+            Err(err) => {
+                self.ready_waiters.lock().unwrap().remove(&new_uuid);
+                Err(err)
+            },
+        }
+    }
+
+    /// Starts replacing all living instances of the given type in the background, one at a time.
+    fn rolling_restart(&mut self, instance_type: String, delay_ms: u64) -> Response {
+        let targets = self.find_instances(Some(instance_type.as_str()), None);
+        if targets.is_empty() {
+            return Response::Error{status: format!("No living instances of type {}", instance_type)};
+        }
+        if instance_type != "Tick Processor" {
+            return Response::Error{status: format!("Rolling restarts aren't supported for instances of type {}", instance_type)};
+        }
+
+        let count = targets.len();
+        let mut dup = self.clone();
+        thread::spawn(move || {
+            if let Err(err) = dup.run_rolling_restart(targets, delay_ms, InstanceManager::respawn) {
+                let errmsg = format!("Rolling restart aborted: {}", err);
+                println!("{}", errmsg);
+                dup.cs.error(None, &errmsg);
+            }
+        });
+
+        Response::Info{info: format!("Started rolling restart of {} instances", count)}
+    }
+
+    /// Replaces each of the supplied instances in turn.  The replacement is registered as living and
+    /// must send its `Ready` message before the old instance is killed so that the number of living
+    /// instances never drops.  If a replacement fails to become ready, it is killed and the restart is
+    /// aborted, leaving the remaining old instances running.
+    fn run_rolling_restart<F>(&mut self, targets: Vec<Instance>, delay_ms: u64, mut respawn: F) -> Result<(), String>
+        where F: FnMut(&mut InstanceManager, &Instance) -> Result<(Uuid, mpsc::Receiver<()>), String>
+    {
+        for (i, old) in targets.iter().enumerate() {
+            let (new_uuid, ready_rx) = respawn(self, old)?;
+            if ready_rx.recv_timeout(Duration::from_millis(READY_TIMEOUT_MS)).is_err() {
+                self.ready_waiters.lock().unwrap().remove(&new_uuid);
+                let _ = self.cs.execute(Command::Kill, new_uuid.hyphenated().to_string()).wait();
+                self.remove_instance(new_uuid);
+                return Err(format!("Replacement {} for {} never became ready", new_uuid.hyphenated(), old.uuid.hyphenated()));
+            }
+
+            let _ = self.cs.execute(Command::Kill, old.uuid.hyphenated().to_string()).wait();
+            self.remove_instance(old.uuid);
+            let infomsg = format!("Replaced {} with {}", old.uuid.hyphenated(), new_uuid.hyphenated());
+            println!("{}", infomsg);
+            self.cs.notice(None, &infomsg);
+
+            if i != targets.len() - 1 {
+                thread::sleep(Duration::from_millis(delay_ms));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Spawns a new Optimizer instance with the specified strategy.
+    fn spawn_optimizer(&mut self, strategy: String) -> Result<Uuid, String> {
         let mod_uuid = Uuid::new_v4();
         let path = "./optimizer";
-        let _ = process::Command::new(path)
-                                .arg(mod_uuid.to_string().as_str())
-                                .arg(strategy.as_str())
-                                .spawn()
-                                .expect("Unable to spawn Optimizer");
+        process::Command::new(path)
+            .arg(mod_uuid.to_string().as_str())
+            .arg(strategy.as_str())
+            .spawn()
+            .map_err(|err| format!("Unable to spawn Optimizer: {:?}", err))?;
 
-        Response::Ok
+        Ok(mod_uuid)
     }
 
     /// Spawns a Backtester instance.
-    fn spawn_backtester(&mut self) -> Response {
+    fn spawn_backtester(&mut self) -> Result<Uuid, String> {
         let mod_uuid = Uuid::new_v4();
         let path = "./backtester";
-        let _ = process::Command::new(path)
-                                .arg(&mod_uuid.to_string())
-                                .spawn()
-                                .expect("Unable to spawn Optimizer");
+        process::Command::new(path)
+            .arg(&mod_uuid.to_string())
+            .spawn()
+            .map_err(|err| format!("Unable to spawn Backtester: {:?}", err))?;
 
-        Response::Ok
+        Ok(mod_uuid)
     }
 
-    /// Spawns a FXCM Native Data Downloader instance.
-    fn spawn_fxcm_dd(&mut self) -> Response {
+    /// Spawns the data downloader that handles `source`.
+    fn spawn_downloader(&mut self, source: &HistTickSrc) -> Result<Uuid, String> {
         let mod_uuid = Uuid::new_v4();
-        let path = "./fxcm_native_downloader";
-        let _ = process::Command::new(path)
-                                .arg(&mod_uuid.to_string())
-                                .spawn()
-                                .expect("Unable to spawn FXCM Native Data Downloader");
-
-        Response::Ok
+        self.start_downloader(source, mod_uuid)?;
+        Ok(mod_uuid)
     }
 
-    /// Spawns a FXCM Flatfile Data Downloader
-    fn spawn_fxcm_flatfile_dd(&mut self) -> Response {
-        let mod_uuid = Uuid::new_v4();
-        let path = "./fxcm_flatfile_downloader";
-        let _ = process::Command::new(path)
-                                .arg(&mod_uuid.to_string())
-                                .spawn()
-                                .expect("Unable to spawn FXCM Flatfile Data Downloader");
-        Response::Ok
+    /// Starts the data downloader process that handles `source` with the given Uuid.
+    fn start_downloader(&self, source: &HistTickSrc, mod_uuid: Uuid) -> Result<(), String> {
+        let (program, args) = downloader_command(source, &CONF)?;
+        process::Command::new(program)
+            .args(&args)
+            .arg(&mod_uuid.to_string())
+            .spawn()
+            .map_err(|err| format!("Unable to spawn {:?} Data Downloader: {:?}", source, err))?;
+
+        Ok(())
     }
 
-    /// Spawns an IEX Data Downloader
-    fn spawn_iex_dd(&mut self) -> Response {
+    /// Spawns the data downloader that handles `source` and, once it's ready, tells it to download the ticks of
+    /// `symbol` between the `start` and `end` timestamps to `dst`.
+    fn spawn_data_downloader(
+        &mut self, source: HistTickSrc, dst: HistTickDst, symbol: String, start: &str, end: &str
+    ) -> Result<Uuid, String> {
+        let start_time = start.parse::<u64>().map_err(|_| format!("Invalid start timestamp: {}", start))?;
+        let end_time = end.parse::<u64>().map_err(|_| format!("Invalid end timestamp: {}", end))?;
+        if end_time < start_time {
+            return Err(format!("The end timestamp ({}) is before the start timestamp ({}).", end_time, start_time));
+        }
+
         let mod_uuid = Uuid::new_v4();
-        let path = "./iex_dd/iex.js";
-        match process::Command::new(CONF.node_binary_path)
-                                .arg(path)
-                                .arg(&mod_uuid.to_string())
-                                .spawn() {
-            Ok(_) => Response::Ok,
-            Err(err) => Response::Error{status: format!("Error while attempting to spawn IEX Data Downloader: {:?}", err)},
+        let ready_rx = self.await_ready(mod_uuid);
+        if let Err(err) = self.start_downloader(&source, mod_uuid) {
+            self.ready_waiters.lock().unwrap().remove(&mod_uuid);
+            return Err(err);
         }
+
+        // the downloader can't receive commands until it's subscribed, so wait for it in the background
+        let mut cs = self.cs.clone();
+        thread::spawn(move || {
+            if ready_rx.recv_timeout(Duration::from_millis(READY_TIMEOUT_MS)).is_err() {
+                let errmsg = format!(
+                    "{:?} Data Downloader {} never became ready; not starting its download.", source, mod_uuid
+                );
+                cs.error(None, &errmsg);
+                return;
+            }
+
+            let cmd = Command::DownloadTicks{start_time: start_time, end_time: end_time, symbol: symbol, dst: dst};
+            match cs.execute(cmd, mod_uuid.hyphenated().to_string()).wait() {
+                Ok(Ok(Response::Error{status})) => cs.error(None, &format!("Unable to start download: {}", status)),
+                Ok(Err(err)) => cs.error(None, &format!("Unable to start download: {}", err)),
+                _ => (),
+            }
+        });
+
+        Ok(mod_uuid)
     }
 
-    /// Spawns a Poloniex Data Downloader
-    fn spawn_poloniex_dd(&mut self) -> Response {
-        let mod_uuid = Uuid::new_v4();
-        let path = "./poloniex_dd/index.js";
-        match process::Command::new(CONF.node_binary_path)
-                                .arg(path)
-                                .arg(&mod_uuid.to_string())
-                                .spawn() {
-            Ok(_) => Response::Ok,
-            Err(err) => Response::Error{status: format!("Error while attempting to spawn Poloniex Data Downloader: {:?}", err)},
+    /// Spawns the instance described by a config entry and registers it along with the entry's metadata.
+    fn spawn_entry(&mut self, entry: &SpawnEntry) -> Result<Uuid, String> {
+        let uuid = match entry.instance_type.as_str() {
+            "Tick Processor" => return self.spawn_tick_parser(entry.symbol.clone().unwrap(), entry.metadata.clone()),
+            "Optimizer" => self.spawn_optimizer(entry.strategy.clone().unwrap())?,
+            "Backtester" => self.spawn_backtester()?,
+            "FXCM Native Data Downloader" => self.spawn_downloader(&HistTickSrc::FxcmNative)?,
+            "FXCM Flatfile Data Downloader" => self.spawn_downloader(&HistTickSrc::FxcmFlatfile)?,
+            "IEX Data Downloader" => self.spawn_downloader(&HistTickSrc::Iex)?,
+            "Poloniex Data Downloader" => self.spawn_downloader(&HistTickSrc::Poloniex)?,
+            _ => return Err(format!("Unknown instance type: {}", entry.instance_type)),
+        };
+        self.add_instance(Instance{metadata: entry.metadata.clone(), ..Instance::new(&entry.instance_type, uuid)});
+
+        Ok(uuid)
+    }
+
+    /// Spawns all of the instances listed in the config file at the given path, returning a JSON array of
+    /// their UUIDs.  The whole file is validated before anything is spawned.
+    fn spawn_from_config(&mut self, config_path: &str) -> Response {
+        let config = match read_spawn_config(config_path) {
+            Ok(config) => config,
+            Err(err) => return Response::Error{status: err},
+        };
+
+        let mut uuids = Vec::with_capacity(config.spawn.len());
+        for (i, entry) in config.spawn.iter().enumerate() {
+            match self.spawn_entry(entry) {
+                Ok(uuid) => uuids.push(uuid.hyphenated().to_string()),
+                Err(err) => return Response::Error{
+                    status: format!("Error while spawning entry {} of {}: {}; spawned so far: {:?}", i, config_path, err, uuids),
+                },
+            }
         }
+
+        Response::Info{info: serde_json::to_string(&uuids).unwrap()}
     }
 
     /// Broadcasts a Ping message on the broadcast channel to all running instances.  Returns
@@ -458,6 +751,12 @@ impl InstanceManager {
         )
     }
 
+    /// Sends a Ping message to the instance with the given Uuid only.  Returns a future that fulfills to its
+    /// response, or to an error if it doesn't respond before the CommandServer's timeout.
+    fn ping_instance(&mut self, uuid: Uuid) -> impl Future<Item = Result<Response, String>, Error = futures::Canceled> {
+        self.cs.execute(Command::Ping, uuid.hyphenated().to_string())
+    }
+
     /// Kills all currently running instances managed by this spawner
     fn kill_all(&mut self) -> Response {
         // TODO: Maybe make this actually verify the responses before returning Ok.
@@ -469,11 +768,51 @@ impl InstanceManager {
         Response::Ok
     }
 
-    /// Adds an instance to the internal living instances list
+    /// Kills all living instances of the given type (or of any type if `None`) whose metadata matches
+    /// the supplied filter.  Returns the UUIDs of the killed instances.
+    fn kill_group(&mut self, instance_type: Option<String>, metadata_filter: Option<HashMap<String, String>>) -> Response {
+        let group = self.find_instances(instance_type.as_ref().map(|s| s.as_str()), metadata_filter.as_ref());
+        let mut killed = Vec::with_capacity(group.len());
+        for inst in group {
+            let _ = self.cs.execute(Command::Kill, inst.uuid.hyphenated().to_string()).wait();
+            self.remove_instance(inst.uuid);
+            killed.push(inst.uuid.hyphenated().to_string());
+        }
+
+        Response::Info{info: serde_json::to_string(&killed).unwrap()}
+    }
+
+    /// Returns all living instances of the given type (or of any type if `None`) whose metadata
+    /// matches the supplied filter.
+    fn find_instances(&self, instance_type: Option<&str>, metadata_filter: Option<&HashMap<String, String>>) -> Vec<Instance> {
+        let living = self.living.lock().unwrap();
+        living.iter()
+            .filter(|inst| instance_type.map(|t| inst.instance_type == t).unwrap_or(true))
+            .filter(|inst| metadata_filter.map(|f| inst.matches_metadata(f)).unwrap_or(true))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns how long the living instance with the given Uuid has been up, or `None` if there's no such instance.
+    fn uptime(&self, uuid: Uuid) -> Option<Duration> {
+        let living = self.living.lock().unwrap();
+        living.iter().find(|inst| inst.uuid == uuid).map(|inst| inst.uptime())
+    }
+
+    /// Adds an instance to the internal living instances list and sends an `InstanceSpawned` event for it.  If an
+    /// instance with the same Uuid is already registered (for example if it was registered when spawned and then
+    /// sent a `Ready` message), only its type is updated so that its metadata is preserved, and no event is sent.
     fn add_instance(&self, inst: Instance) {
         let l = self.living.clone();
         let mut ll = l.lock().unwrap();
-        ll.push(inst);
+        match ll.iter_mut().find(|existing| existing.uuid == inst.uuid) {
+            Some(existing) => {
+                existing.instance_type = inst.instance_type;
+                return;
+            },
+            None => ll.push(inst.clone()),
+        }
+        self.cs.send_event(PlatformEvent::InstanceSpawned{instance: inst});
     }
 
     /// Removes an instance with the given Uuid from the internal instances list
@@ -491,7 +830,85 @@ impl InstanceManager {
         if _i.is_some() {
             ll.remove(_i.unwrap());
         }
+        self.dependencies.lock().unwrap().remove(&uuid);
+    }
+}
+
+/// Returns the program that runs the data downloader for `source` along with the arguments that go before its
+/// Uuid, as configured in `conf`.
+fn downloader_command(source: &HistTickSrc, conf: &Conf) -> Result<(&'static str, Vec<&'static str>), String> {
+    match *source {
+        HistTickSrc::FxcmNative => Ok((conf.fxcm_native_downloader_path, Vec::new())),
+        HistTickSrc::FxcmFlatfile => Ok((conf.fxcm_flatfile_downloader_path, Vec::new())),
+        // the JavaScript downloaders are scripts run by NodeJS
+        HistTickSrc::Iex => Ok((conf.node_binary_path, vec![conf.iex_downloader_path])),
+        HistTickSrc::Poloniex => Ok((conf.node_binary_path, vec![conf.poloniex_downloader_path])),
+        HistTickSrc::Postgres => match conf.postgres_downloader_path {
+            Some(path) => Ok((path, Vec::new())),
+            None => Err(String::from("No data downloader is configured for Postgres.")),
+        },
+    }
+}
+
+/// Converts the result of spawning an instance into the response sent to the spawn command, which holds the Uuid of
+/// the spawned instance so that it can be tracked.
+fn spawn_response(res: Result<Uuid, String>) -> Response {
+    match res {
+        Ok(uuid) => Response::Info{info: uuid.hyphenated().to_string()},
+        Err(err) => Response::Error{status: err},
+    }
+}
+
+/// Returns the symbols that a Tick Processor follows according to its metadata.
+fn followed_symbols(inst: &Instance) -> Vec<String> {
+    match inst.metadata.get("symbols") {
+        Some(symbols) => symbols.split(',').filter(|s| !s.is_empty()).map(String::from).collect(),
+        None => inst.metadata.get("symbol").cloned().into_iter().collect(),
+    }
+}
+
+/// The contents of a file used with `SpawnFromConfig`.
+#[derive(Deserialize)]
+struct SpawnConfig {
+    spawn: Vec<SpawnEntry>,
+}
+
+/// An instance to be spawned from a config file
+#[derive(Deserialize)]
+struct SpawnEntry {
+    #[serde(rename = "type")]
+    instance_type: String,
+    /// Required for Tick Processors
+    symbol: Option<String>,
+    /// Required for Optimizers
+    strategy: Option<String>,
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+}
+
+/// Reads and validates a spawn config file from the local filesystem.
+fn read_spawn_config(config_path: &str) -> Result<SpawnConfig, String> {
+    let mut contents = String::new();
+    File::open(config_path)
+        .and_then(|mut f| f.read_to_string(&mut contents))
+        .map_err(|err| format!("Unable to read spawn config {}: {:?}", config_path, err))?;
+    let config: SpawnConfig = toml::from_str(&contents)
+        .map_err(|err| format!("Unable to parse spawn config {}: {}", config_path, err))?;
+
+    for (i, entry) in config.spawn.iter().enumerate() {
+        let missing = match entry.instance_type.as_str() {
+            "Tick Processor" if entry.symbol.is_none() => Some("symbol"),
+            "Optimizer" if entry.strategy.is_none() => Some("strategy"),
+            "Tick Processor" | "Optimizer" | "Backtester" | "FXCM Native Data Downloader" | "FXCM Flatfile Data Downloader" |
+                "IEX Data Downloader" | "Poloniex Data Downloader" => None,
+            _ => return Err(format!("Entry {} has unknown instance type: {}", i, entry.instance_type)),
+        };
+        if let Some(field) = missing {
+            return Err(format!("Entry {} ({}) is missing the `{}` field", i, entry.instance_type, field));
+        }
     }
+
+    Ok(config)
 }
 
 /// Tests the instance manager's ability to process incoming Commands.
@@ -520,3 +937,360 @@ fn spawner_command_processing() {
         Response::Pong{args: vec![spawner.uuid.hyphenated().to_string()]}
     );
 }
+
+/// Pinging a single instance by its Uuid gets a Pong from that instance.
+#[test]
+fn ping_single_instance() {
+    let mut spawner = InstanceManager::new();
+    spawner.listen();
+    // give the listener a chance to subscribe
+    thread::sleep(Duration::from_millis(150));
+
+    let uuid = spawner.uuid;
+    let res = spawner.ping_instance(uuid).wait().unwrap();
+    assert_eq!(res, Ok(Response::Pong{args: vec![uuid.hyphenated().to_string()]}));
+}
+
+/// The heartbeat interval can be changed at runtime through any clone of the spawner, such as the one
+/// handling commands.
+#[test]
+fn heartbeat_interval_command() {
+    let spawner = InstanceManager::new();
+    assert_eq!(spawner.heartbeat_interval(), Duration::from_millis(CONF.heartbeat_interval_ms as u64));
+
+    let mut dup = spawner.clone();
+    let (c, o) = oneshot::<Response>();
+    dup.handle_command(Command::SetHeartbeatInterval{ms: 1234}, c);
+    assert_eq!(o.wait().unwrap(), Response::Ok);
+    assert_eq!(spawner.heartbeat_interval(), Duration::from_millis(1234));
+
+    let (c, o) = oneshot::<Response>();
+    dup.handle_command(Command::SetHeartbeatInterval{ms: 0}, c);
+    match o.wait().unwrap() {
+        Response::Error{..} => (),
+        res => panic!("Expected an interval of 0 to be refused, got {:?}", res),
+    }
+    assert_eq!(spawner.heartbeat_interval(), Duration::from_millis(1234));
+}
+
+/// Instances can be filtered by the metadata they were spawned with, and that metadata is included in the census.
+#[test]
+fn instance_metadata_filtering() {
+    let spawner = InstanceManager::new();
+    let mut prod_metadata = HashMap::new();
+    prod_metadata.insert(String::from("env"), String::from("prod"));
+    prod_metadata.insert(String::from("region"), String::from("us-east"));
+    let mut dev_metadata = HashMap::new();
+    dev_metadata.insert(String::from("env"), String::from("dev"));
+
+    let prod_uuid = Uuid::new_v4();
+    spawner.add_instance(Instance{metadata: prod_metadata, ..Instance::new("Tick Processor", prod_uuid)});
+    spawner.add_instance(Instance{metadata: dev_metadata, ..Instance::new("Tick Processor", Uuid::new_v4())});
+    // a `Ready` message from the instance shouldn't clobber its metadata
+    spawner.add_instance(Instance::new("Tick Processor", prod_uuid));
+
+    let mut filter = HashMap::new();
+    filter.insert(String::from("env"), String::from("prod"));
+    let matching = spawner.find_instances(Some("Tick Processor"), Some(&filter));
+    assert_eq!(matching.len(), 1);
+    assert_eq!(matching[0].uuid, prod_uuid);
+    assert_eq!(spawner.find_instances(Some("Backtester"), Some(&filter)).len(), 0);
+    assert_eq!(spawner.find_instances(None, None).len(), 2);
+
+    let census: Vec<Instance> = match spawner.census() {
+        Response::Info{info} => serde_json::from_str(&info).unwrap(),
+        res => panic!("Unexpected census response: {:?}", res),
+    };
+    let prod = census.iter().find(|inst| inst.uuid == prod_uuid).unwrap();
+    assert_eq!(prod.metadata.get("region"), Some(&String::from("us-east")));
+}
+
+/// During a rolling restart, the number of living instances of the restarted type never drops below
+/// the number that existed before it started.
+#[test]
+fn rolling_restart_keeps_instances_alive() {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    let mut spawner = InstanceManager::new();
+    let mut old_uuids = Vec::new();
+    for symbol in &["EURUSD", "USDJPY", "GBPUSD"] {
+        let uuid = Uuid::new_v4();
+        let mut metadata = HashMap::new();
+        metadata.insert(String::from("symbol"), String::from(*symbol));
+        spawner.add_instance(Instance{metadata: metadata, ..Instance::new("Tick Processor", uuid)});
+        old_uuids.push(uuid);
+    }
+
+    // keep taking censuses while the restart is in progress and record the smallest count seen
+    let done = Arc::new(AtomicBool::new(false));
+    let min_seen = Arc::new(AtomicUsize::new(std::usize::MAX));
+    let (done_clone, min_clone, census_spawner) = (done.clone(), min_seen.clone(), spawner.clone());
+    let census_thread = thread::spawn(move || {
+        while !done_clone.load(Ordering::SeqCst) {
+            let census: Vec<Instance> = match census_spawner.census() {
+                Response::Info{info} => serde_json::from_str(&info).unwrap(),
+                res => panic!("Unexpected census response: {:?}", res),
+            };
+            let count = census.iter().filter(|inst| inst.instance_type == "Tick Processor").count();
+            if count < min_clone.load(Ordering::SeqCst) {
+                min_clone.store(count, Ordering::SeqCst);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    });
+
+    // simulate replacements that register themselves and send a `Ready` message after a short delay
+    let targets = spawner.find_instances(Some("Tick Processor"), None);
+    spawner.run_rolling_restart(targets, 10, |manager, old| {
+        let new_uuid = Uuid::new_v4();
+        let ready_rx = manager.await_ready(new_uuid);
+        manager.add_instance(Instance{metadata: old.metadata.clone(), ..Instance::new("Tick Processor", new_uuid)});
+        let mut dup = manager.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let (c, _o) = oneshot::<Response>();
+            let ready = Command::Ready{
+                instance_type: String::from("Tick Processor"), uuid: new_uuid, depends_on: Vec::new(),
+            };
+            dup.handle_command(ready, c);
+        });
+        Ok((new_uuid, ready_rx))
+    }).unwrap();
+
+    done.store(true, Ordering::SeqCst);
+    census_thread.join().unwrap();
+    assert!(min_seen.load(Ordering::SeqCst) >= 3);
+
+    let remaining = spawner.find_instances(Some("Tick Processor"), None);
+    assert_eq!(remaining.len(), 3);
+    assert!(remaining.iter().all(|inst| !old_uuids.contains(&inst.uuid)));
+    assert!(remaining.iter().all(|inst| inst.metadata.contains_key("symbol")));
+}
+
+/// Spawns instances from a config file.  Like the spawner itself, this needs to be run from the directory
+/// containing the instance binaries.
+#[test]
+fn spawn_from_config_file() {
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    let dir = TempDir::new("spawn_config").unwrap();
+    let path = dir.path().join("spawn.toml");
+    let config = r#"
+[[spawn]]
+type = "Tick Processor"
+symbol = "EURUSD"
+metadata = { env = "test" }
+
+[[spawn]]
+type = "Backtester"
+
+[spawn.metadata]
+env = "test"
+"#;
+    File::create(&path).unwrap().write_all(config.as_bytes()).unwrap();
+    let path_str = path.to_str().unwrap();
+
+    let parsed = read_spawn_config(path_str).unwrap();
+    assert_eq!(parsed.spawn.len(), 2);
+    assert_eq!(parsed.spawn[0].symbol, Some(String::from("EURUSD")));
+    assert_eq!(parsed.spawn[1].metadata.get("env"), Some(&String::from("test")));
+
+    let mut spawner = InstanceManager::new();
+    let uuids: Vec<Uuid> = match spawner.spawn_from_config(path_str) {
+        Response::Info{info} => serde_json::from_str(&info).unwrap(),
+        res => panic!("Unexpected response to SpawnFromConfig: {:?}", res),
+    };
+    assert_eq!(uuids.len(), 2);
+    let mut filter = HashMap::new();
+    filter.insert(String::from("env"), String::from("test"));
+    assert_eq!(spawner.find_instances(None, Some(&filter)).len(), 2);
+
+    // entries missing required fields are rejected before anything is spawned
+    File::create(&path).unwrap().write_all(b"[[spawn]]\ntype = \"Optimizer\"\n").unwrap();
+    match spawner.spawn_from_config(path_str) {
+        Response::Error{status} => assert!(status.contains("strategy")),
+        res => panic!("Expected error, got {:?}", res),
+    }
+}
+
+/// Tick Processors spawned as a group are all registered and returned in the order of their symbols.
+#[test]
+fn spawn_tick_parser_group() {
+    let mut spawner = InstanceManager::new();
+    let symbols: Vec<String> = ["EURUSD", "USDJPY", "GBPUSD", "AUDUSD", "USDCAD"].iter()
+        .map(|symbol| String::from(*symbol))
+        .collect();
+    let uuids: Vec<Option<Uuid>> = match spawner.spawn_tick_parser_group(symbols.clone()) {
+        Response::Info{info} => serde_json::from_str(&info).unwrap(),
+        res => panic!("Unexpected response to SpawnTickParserGroup: {:?}", res),
+    };
+    assert_eq!(uuids.len(), 5);
+
+    let living: Vec<Instance> = match spawner.census() {
+        Response::Info{info} => serde_json::from_str(&info).unwrap(),
+        res => panic!("Unexpected response to Census: {:?}", res),
+    };
+    for (uuid, symbol) in uuids.iter().zip(symbols.iter()) {
+        let uuid = uuid.expect("Tick Processor wasn't spawned");
+        let inst = living.iter().find(|inst| inst.uuid == uuid).expect("Spawned Tick Processor isn't alive");
+        assert_eq!(inst.metadata.get("symbol"), Some(symbol));
+    }
+}
+
+/// Instances report their spawn time and how long they've been up in the census.
+#[test]
+fn instance_uptime() {
+    let mut spawner = InstanceManager::new();
+    let tp_uuid = match spawner.spawn_tick_parser(String::from("EURUSD"), HashMap::new()) {
+        Ok(uuid) => uuid,
+        Err(err) => panic!("Unable to spawn Tick Processor: {}", err),
+    };
+    thread::sleep(Duration::from_millis(100));
+
+    assert!(spawner.uptime(tp_uuid).unwrap() >= Duration::from_millis(100));
+    assert_eq!(spawner.uptime(Uuid::new_v4()), None);
+    let living: Vec<serde_json::Value> = match spawner.census() {
+        Response::Info{info} => serde_json::from_str(&info).unwrap(),
+        res => panic!("Unexpected response to Census: {:?}", res),
+    };
+    let tp_uuid_string = tp_uuid.hyphenated().to_string();
+    let inst = living.iter().find(|inst| inst["uuid"] == tp_uuid_string.as_str()).unwrap();
+    assert!(inst["uptime_ms"].as_u64().unwrap() >= 100);
+    assert!(inst["spawn_time_unix_ms"].as_u64().unwrap() + 100 <= unix_time_ms());
+}
+
+/// A second Tick Processor isn't spawned for a symbol that a living one already follows, including symbols that
+/// were added to it after it was spawned.
+#[test]
+fn duplicate_symbol_guard() {
+    let mut spawner = InstanceManager::new();
+    let tp_uuid = Uuid::new_v4();
+    let mut metadata = HashMap::new();
+    metadata.insert(String::from("symbol"), String::from("EURUSD"));
+    spawner.add_instance(Instance{metadata: metadata, ..Instance::new("Tick Processor", tp_uuid)});
+
+    assert!(spawner.spawn_tick_parser(String::from("EURUSD"), HashMap::new()).is_err());
+    assert_eq!(spawner.tick_parser_following("USDJPY"), None);
+
+    let (c, o) = oneshot::<Response>();
+    let symbols = vec![String::from("EURUSD"), String::from("USDJPY")];
+    spawner.handle_command(Command::TickProcessorSymbols{uuid: tp_uuid, symbols: symbols}, c);
+    assert_eq!(o.wait().unwrap(), Response::Ok);
+    assert_eq!(spawner.tick_parser_following("USDJPY"), Some(tp_uuid));
+    match spawner.spawn_tick_parser(String::from("USDJPY"), HashMap::new()) {
+        Err(err) => assert!(err.contains(&tp_uuid.hyphenated().to_string())),
+        res => panic!("Expected the duplicate symbol to be refused, got {:?}", res),
+    }
+
+    // symbols that are removed from a Tick Processor are free again
+    assert_eq!(spawner.set_tick_parser_symbols(tp_uuid, vec![String::from("EURUSD")]), Response::Ok);
+    assert_eq!(spawner.tick_parser_following("USDJPY"), None);
+    assert_eq!(spawner.tick_parser_following("EURUSD"), Some(tp_uuid));
+}
+
+/// Instances registered by a `Ready` message are announced with an `InstanceSpawned` event.
+#[test]
+fn instance_spawned_events() {
+    let mut spawner = InstanceManager::new();
+    let events = spawner.cs.subscribe_to_events();
+    let tp_uuid = Uuid::new_v4();
+    let (event_tx, event_rx) = mpsc::channel();
+    thread::spawn(move || {
+        // other tests may be sending events of their own at the same time
+        for wr in events.wait() {
+            if let Response::Event{event: PlatformEvent::InstanceSpawned{instance}} = wr.unwrap().res {
+                if instance.uuid == tp_uuid {
+                    let _ = event_tx.send(instance);
+                    break;
+                }
+            }
+        }
+    });
+
+    let (c, o) = oneshot::<Response>();
+    let ready = Command::Ready{instance_type: String::from("Tick Processor"), uuid: tp_uuid, depends_on: Vec::new()};
+    spawner.handle_command(ready, c);
+    assert_eq!(o.wait().unwrap(), Response::Ok);
+
+    let instance = event_rx.recv_timeout(Duration::from_millis(500)).unwrap();
+    assert_eq!(instance.instance_type, "Tick Processor");
+}
+
+/// Dependencies declared in `Ready` messages show up as edges in the dependency graph.
+#[test]
+fn dependency_graph_edges() {
+    let mut spawner = InstanceManager::new();
+    let (mm_uuid, tp_uuid) = (Uuid::new_v4(), Uuid::new_v4());
+
+    let (c, o) = oneshot::<Response>();
+    let ready = Command::Ready{instance_type: String::from("MM"), uuid: mm_uuid, depends_on: vec![spawner.uuid]};
+    spawner.handle_command(ready, c);
+    assert_eq!(o.wait().unwrap(), Response::Ok);
+    let mut metadata = HashMap::new();
+    metadata.insert(String::from("symbol"), String::from("EURUSD"));
+    spawner.add_instance(Instance{metadata: metadata, ..Instance::new("Tick Processor", tp_uuid)});
+    let (c, o) = oneshot::<Response>();
+    let ready = Command::Ready{instance_type: String::from("Tick Processor"), uuid: tp_uuid, depends_on: vec![mm_uuid]};
+    spawner.handle_command(ready, c);
+    assert_eq!(o.wait().unwrap(), Response::Ok);
+
+    let (c, o) = oneshot::<Response>();
+    spawner.handle_command(Command::DependencyGraph, c);
+    let graph = match o.wait().unwrap() {
+        Response::Info{info} => info,
+        res => panic!("Unexpected response to dependency graph request: {:?}", res),
+    };
+    assert!(graph.starts_with("digraph dependencies {"));
+    assert!(graph.contains(&format!("\"{}\" [label=\"Spawner\"];", spawner.uuid.hyphenated())));
+    assert!(graph.contains(&format!("\"{}\" [label=\"MM\"];", mm_uuid.hyphenated())));
+    assert!(graph.contains(&format!("\"{}\" [label=\"Tick Processor[EURUSD]\"];", tp_uuid.hyphenated())));
+    assert!(graph.contains(&format!("\"{}\" -> \"{}\";", spawner.uuid.hyphenated(), mm_uuid.hyphenated())));
+    assert!(graph.contains(&format!("\"{}\" -> \"{}\";", mm_uuid.hyphenated(), tp_uuid.hyphenated())));
+
+    // edges out of an instance that's gone away are kept, but its own dependencies are dropped
+    spawner.remove_instance(mm_uuid);
+    let (c, o) = oneshot::<Response>();
+    spawner.handle_command(Command::DependencyGraph, c);
+    match o.wait().unwrap() {
+        Response::Info{info} => {
+            assert!(!info.contains(&format!("\"{}\" -> \"{}\";", spawner.uuid.hyphenated(), mm_uuid.hyphenated())));
+            assert!(info.contains(&format!("\"{}\" [label=\"{}\"];", mm_uuid.hyphenated(), mm_uuid.hyphenated())));
+        },
+        res => panic!("Unexpected response to dependency graph request: {:?}", res),
+    }
+}
+
+/// Data downloaders are started with the paths configured for their sources, and downloads with invalid time ranges
+/// are refused before anything is spawned.
+#[test]
+fn data_downloader_dispatch() {
+    let conf = Conf {postgres_downloader_path: Some("./postgres_downloader"), ..CONF};
+    assert_eq!(downloader_command(&HistTickSrc::Postgres, &conf), Ok(("./postgres_downloader", Vec::new())));
+    let unconfigured = Conf {postgres_downloader_path: None, ..CONF};
+    assert!(downloader_command(&HistTickSrc::Postgres, &unconfigured).is_err());
+    let iex = downloader_command(&HistTickSrc::Iex, &conf);
+    assert_eq!(iex, Ok((CONF.node_binary_path, vec![CONF.iex_downloader_path])));
+
+    // the Uuid of a spawned instance is sent back so that it can be tracked
+    let uuid = Uuid::new_v4();
+    assert_eq!(spawn_response(Ok(uuid)), Response::Info{info: uuid.hyphenated().to_string()});
+    let err = String::from("Unable to spawn");
+    assert_eq!(spawn_response(Err(err.clone())), Response::Error{status: err});
+
+    let mut spawner = InstanceManager::new();
+    let dst = HistTickDst::Console{format: ConsoleTickFormat::Raw};
+    for &(start, end) in &[("yesterday", "10"), ("10", "today"), ("10", "5")] {
+        let (c, o) = oneshot::<Response>();
+        let cmd = Command::SpawnDataDownloader{
+            source: HistTickSrc::Postgres, dst: dst.clone(), symbol: String::from("EURUSD"),
+            start: String::from(start), end: String::from(end),
+        };
+        spawner.handle_command(cmd, c);
+        match o.wait().unwrap() {
+            Response::Error{..} => (),
+            res => panic!("Expected a download from {} to {} to be refused, got {:?}", start, end, res),
+        }
+    }
+    assert!(spawner.ready_waiters.lock().unwrap().is_empty());
+}
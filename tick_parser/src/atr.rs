@@ -0,0 +1,160 @@
+//! Live average true range calculated over fixed-length bars of the mid price.
+
+#[allow(unused_imports)]
+use test;
+
+use serde_json::Value;
+
+use tickgrinder_util::trading::tick::Tick;
+use tickgrinder_util::trading::bar::{Bar, OhlcvBuilder};
+use tickgrinder_util::trading::indicators::SmaError;
+
+use indicators::{Indicator, IndicatorValue};
+#[cfg(test)]
+use indicators::ticks_from_rows;
+
+/// An average true range over `period` bars of `bar_ms` milliseconds using Wilder's smoothing.  A bar's true range
+/// is the largest of its own range and the distances from the previous bar's close to its high and low, so gaps
+/// between bars count towards it.  The first bar has no previous close, so its true range is just its range.
+pub struct Atr {
+    pub period: usize,
+    pub bars: OhlcvBuilder,
+    /// The ATR as of the last closed bar, or `None` until `period` bars have closed
+    pub value: Option<f64>,
+    prev_close: Option<f64>,
+    /// Number of bars that have closed, up to `period`
+    bar_count: usize,
+    /// Sum of the true ranges of the bars during the warm-up
+    tr_sum: f64,
+}
+
+impl Atr {
+    pub fn new(bar_ms: u64, period: usize) -> Atr {
+        assert!(period > 0, "ATR period must be greater than zero!");
+        Atr {
+            period: period,
+            bars: OhlcvBuilder::new(bar_ms),
+            value: None,
+            prev_close: None,
+            bar_count: 0,
+            tr_sum: 0.,
+        }
+    }
+
+    /// Adds a tick and returns the new ATR if it closed a bar and enough bars have closed to calculate it.  Ticks
+    /// that aren't newer than the previous one are refused.
+    pub fn push(&mut self, t: &Tick) -> Result<Option<f64>, SmaError> {
+        // gaps are skipped, so at most one bar is completed
+        let mut value = None;
+        for bar in self.bars.push_tick(t)? {
+            value = self.push_bar(&bar);
+        }
+        Ok(value)
+    }
+
+    fn push_bar(&mut self, bar: &Bar) -> Option<f64> {
+        let (high, low) = (bar.high as f64, bar.low as f64);
+        let range = high - low;
+        let true_range = match self.prev_close {
+            Some(prev_close) => range.max((high - prev_close).abs()).max((low - prev_close).abs()),
+            None => range,
+        };
+        self.prev_close = Some(bar.close as f64);
+
+        let period = self.period as f64;
+        if self.bar_count < self.period {
+            self.tr_sum += true_range;
+            self.bar_count += 1;
+            if self.bar_count == self.period {
+                // the first average is the plain mean of the warm-up bars
+                self.value = Some(self.tr_sum / period);
+            }
+        } else {
+            self.value = self.value.map(|atr| (atr * (period - 1.) + true_range) / period);
+        }
+        self.value
+    }
+}
+
+impl Indicator for Atr {
+    /// Only returns a value when the tick closes a bar.
+    fn push(&mut self, t: Tick) -> Result<Option<IndicatorValue>, SmaError> {
+        Ok(Atr::push(self, &t)?.map(IndicatorValue::Value))
+    }
+
+    fn name(&self) -> &str {
+        "atr"
+    }
+
+    fn params(&self) -> Value {
+        json!({"bar_ms": self.bars.period_ms, "period": self.period})
+    }
+
+    /// Complete once `period` bars have closed.
+    fn warm_up_complete(&self) -> bool {
+        self.value.is_some()
+    }
+}
+
+/// Ticks with a given timestamp in milliseconds and a price used for both the bid and ask
+#[cfg(test)]
+fn bar_fixture_ticks() -> Vec<Tick> {
+    let rows: Vec<(u64, usize, usize)> = [
+        // H 104 L 98 C 101; TR 6
+        (0, 100), (300, 104), (600, 98), (900, 101),
+        // H 107 L 102 C 103; TR 107 - 101 = 6
+        (1000, 102), (1500, 107), (1900, 103),
+        // gaps up; H 112 L 109 C 109; TR 112 - 103 = 9
+        (2000, 110), (2400, 112), (2800, 109),
+        // nothing during 3000-3999, then gaps down; H 103 L 100 C 101; TR 109 - 100 = 9
+        (4000, 100), (4200, 103), (4700, 101),
+        // H 102 L 101 C 102; TR 1
+        (5000, 101), (5100, 102),
+        // closes the last bar
+        (6000, 102),
+    ].iter()
+        .map(|&(timestamp, price)| (timestamp, price, price))
+        .collect();
+    ticks_from_rows(&rows)
+}
+
+/// Matches a 3-bar ATR over 1-second bars calculated by hand from the true ranges in `bar_fixture_ticks`.
+#[test]
+fn atr_fixture_values() {
+    let mut atr = Atr::new(1000, 3);
+    let values: Vec<f64> = bar_fixture_ticks().iter()
+        .filter_map(|t| atr.push(t).unwrap())
+        .collect();
+
+    // (6 + 6 + 9) / 3, then (7 * 2 + 9) / 3, then (23 / 3 * 2 + 1) / 3
+    let expected = [7., 23. / 3., 49. / 9.];
+    assert_eq!(values.len(), expected.len());
+    for (value, expected) in values.iter().zip(expected.iter()) {
+        assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected);
+    }
+    assert_eq!(atr.value, Some(values[2]));
+
+    assert_eq!(atr.push(&Tick {timestamp: 10, bid: 1, ask: 1}), Err(SmaError::OutOfOrder{last: 6000, got: 10}));
+    assert_eq!(atr.value, Some(values[2]));
+}
+
+/// Only closed bars count, and the very first bar's true range ignores the missing previous close.
+#[test]
+fn atr_first_bar() {
+    let mut atr = Atr::new(1000, 1);
+    assert_eq!(atr.push(&Tick {timestamp: 0, bid: 100, ask: 100}), Ok(None));
+    assert_eq!(atr.push(&Tick {timestamp: 500, bid: 104, ask: 104}), Ok(None));
+    assert_eq!(atr.value, None);
+    assert_eq!(atr.push(&Tick {timestamp: 1000, bid: 90, ask: 90}), Ok(Some(4.)));
+}
+
+#[bench]
+fn atr_calculation(b: &mut test::Bencher) {
+    let mut atr = Atr::new(1000, 14);
+    let mut timestamp = 1;
+
+    b.iter(|| {
+        atr.push(&Tick {bid: 1239123 + (timestamp % 7) as usize, ask: 1239125, timestamp: timestamp}).unwrap();
+        timestamp += 20;
+    });
+}
@@ -0,0 +1,229 @@
+//! OHLC candles of the mid price published and stored by the tick processor.  The candles are built by util's
+//! `OhlcvBuilder`, so candles of the same duration line up no matter when the ticks started.
+
+use redis;
+use serde_json::Value;
+
+use tickgrinder_util::trading::tick::Tick;
+use tickgrinder_util::trading::bar::{Bar, GapPolicy, OhlcvBuilder};
+use tickgrinder_util::trading::indicators::SmaError;
+use tickgrinder_util::transport::query_server::QueryServer;
+use tickgrinder_util::transport::redis::publish;
+
+/// The open, high, low, and close mid prices of the ticks that arrived during one candle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Candle {
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Start of the candle, a multiple of its duration
+    pub open_ts: u64,
+    /// End of the candle, exclusive; a tick with this timestamp belongs to the next candle
+    pub close_ts: u64,
+    /// Number of ticks in the candle, which is 0 for candles carried forward over a gap
+    pub tick_count: u64,
+}
+
+impl Candle {
+    /// Converts a bar of `duration_ms` milliseconds into a candle.
+    pub fn from_bar(bar: &Bar, duration_ms: u64) -> Candle {
+        Candle {
+            open: bar.open as f64,
+            high: bar.high as f64,
+            low: bar.low as f64,
+            close: bar.close as f64,
+            open_ts: bar.timestamp,
+            close_ts: bar.timestamp + duration_ms,
+            tick_count: bar.tick_count,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "open": self.open,
+            "high": self.high,
+            "low": self.low,
+            "close": self.close,
+            "open_ts": self.open_ts,
+            "close_ts": self.close_ts,
+            "tick_count": self.tick_count,
+        })
+    }
+
+    /// Saves the candle in the specified table.  The table must exist.
+    pub fn store(&self, table: &str, qs: &mut QueryServer) {
+        let query = format!(
+            "INSERT INTO {} (open_ts, close_ts, open, high, low, close, tick_count) \
+             VALUES ({}, {}, {}, {}, {}, {}, {});",
+            table, self.open_ts, self.close_ts, self.open, self.high, self.low, self.close, self.tick_count
+        );
+
+        // Asynchronously store the candle in the database
+        qs.execute(query);
+    }
+}
+
+/// Candles of `duration_ms` milliseconds built by the tick processor along with where they're sent
+pub struct CandleFeed {
+    pub bars: OhlcvBuilder,
+    /// Redis channel that completed candles are published to, which is also the name of their Postgres table
+    pub channel: String,
+    /// If set, candles are also written to Postgres
+    pub store: bool,
+}
+
+impl CandleFeed {
+    pub fn new(symbol: &str, duration_ms: u64, gap_policy: GapPolicy, store: bool) -> CandleFeed {
+        CandleFeed {
+            bars: OhlcvBuilder::with_gap_policy(duration_ms, gap_policy),
+            channel: format!("candles_{}_{}", symbol, duration_ms),
+            store: store,
+        }
+    }
+
+    /// Adds a tick to the current candle and returns the candles it completed; see `OhlcvBuilder::push_tick`.
+    pub fn push(&mut self, t: &Tick) -> Result<Vec<Candle>, SmaError> {
+        let duration_ms = self.bars.period_ms;
+        let bars = self.bars.push_tick(t)?;
+        Ok(bars.iter().map(|bar| Candle::from_bar(bar, duration_ms)).collect())
+    }
+
+    /// Returns the current candle even though its period hasn't ended; see `OhlcvBuilder::flush`.
+    pub fn flush(&mut self) -> Option<Candle> {
+        let duration_ms = self.bars.period_ms;
+        self.bars.flush().map(|bar| Candle::from_bar(&bar, duration_ms))
+    }
+
+    /// Publishes a candle as JSON and stores it if the feed is set to.
+    pub fn emit(&self, candle: &Candle, client: &redis::Client, qs: &mut QueryServer) {
+        publish(client, &self.channel, &candle.to_json().to_string());
+        if self.store {
+            candle.store(&self.channel, qs);
+        }
+    }
+}
+
+/// Smooths candles into Heikin-Ashi candles.  Each one closes at the average of its candle's prices and opens
+/// halfway between the open and close of the previous Heikin-Ashi candle, or of its own candle if it's the first.
+/// Its high and low extend to cover its open and close.
+pub struct HeikinAshi {
+    /// Open and close of the previous Heikin-Ashi candle
+    prev: Option<(f64, f64)>,
+}
+
+impl HeikinAshi {
+    pub fn new() -> HeikinAshi {
+        HeikinAshi { prev: None }
+    }
+
+    /// Returns the Heikin-Ashi candle of the next candle of the series, keeping its timestamps and tick count.
+    pub fn push(&mut self, candle: &Candle) -> Candle {
+        let close = (candle.open + candle.high + candle.low + candle.close) / 4.;
+        let open = match self.prev {
+            Some((prev_open, prev_close)) => (prev_open + prev_close) / 2.,
+            None => (candle.open + candle.close) / 2.,
+        };
+        self.prev = Some((open, close));
+        Candle {
+            open: open,
+            high: candle.high.max(open).max(close),
+            low: candle.low.min(open).min(close),
+            close: close,
+            ..*candle
+        }
+    }
+}
+
+/// Heikin-Ashi candles built from the bars of an `OhlcvBuilder`, published to their own channel
+pub struct HeikinAshiFeed {
+    pub bars: OhlcvBuilder,
+    heikin_ashi: HeikinAshi,
+    /// Redis channel that completed Heikin-Ashi candles are published to
+    pub channel: String,
+}
+
+impl HeikinAshiFeed {
+    pub fn new(symbol: &str, duration_ms: u64, gap_policy: GapPolicy) -> HeikinAshiFeed {
+        HeikinAshiFeed {
+            bars: OhlcvBuilder::with_gap_policy(duration_ms, gap_policy),
+            heikin_ashi: HeikinAshi::new(),
+            channel: format!("heikin_ashi_{}_{}", symbol, duration_ms),
+        }
+    }
+
+    /// Adds a tick to the current candle and returns the Heikin-Ashi candles of any candles it completed.
+    pub fn push(&mut self, t: &Tick) -> Result<Vec<Candle>, SmaError> {
+        let duration_ms = self.bars.period_ms;
+        let bars = self.bars.push_tick(t)?;
+        Ok(bars.iter().map(|bar| self.heikin_ashi.push(&Candle::from_bar(bar, duration_ms))).collect())
+    }
+
+    /// Returns the Heikin-Ashi candle of the current candle even though its period hasn't ended; see
+    /// `OhlcvBuilder::flush`.
+    pub fn flush(&mut self) -> Option<Candle> {
+        let duration_ms = self.bars.period_ms;
+        let heikin_ashi = &mut self.heikin_ashi;
+        self.bars.flush().map(|bar| heikin_ashi.push(&Candle::from_bar(&bar, duration_ms)))
+    }
+
+    /// Publishes a Heikin-Ashi candle as JSON.
+    pub fn emit(&self, candle: &Candle, client: &redis::Client) {
+        publish(client, &self.channel, &candle.to_json().to_string());
+    }
+}
+
+/// Pushes ticks with the given timestamps and prices, returning every candle completed.
+#[cfg(test)]
+fn push_all(feed: &mut CandleFeed, prices: &[(u64, usize)]) -> Vec<Candle> {
+    prices.iter()
+        .flat_map(|&(timestamp, price)| feed.push(&Tick {timestamp: timestamp, bid: price, ask: price}).unwrap())
+        .collect()
+}
+
+/// Candles carry the prices and tick counts of the feed's bars, and end where the next candle starts.
+#[test]
+fn candle_feed() {
+    let mut feed = CandleFeed::new("TEST", 60000, GapPolicy::CarryForward, false);
+    assert_eq!(feed.channel, "candles_TEST_60000");
+    let completed = push_all(&mut feed, &[(1500000, 100), (1559999, 102), (1680000, 110)]);
+
+    assert_eq!(completed, vec![
+        Candle {open: 100., high: 102., low: 100., close: 102., open_ts: 1500000, close_ts: 1560000, tick_count: 2},
+        Candle {open: 102., high: 102., low: 102., close: 102., open_ts: 1560000, close_ts: 1620000, tick_count: 0},
+        Candle {open: 102., high: 102., low: 102., close: 102., open_ts: 1620000, close_ts: 1680000, tick_count: 0},
+    ]);
+    assert_eq!(feed.push(&Tick {timestamp: 10, bid: 1, ask: 1}), Err(SmaError::OutOfOrder{last: 1680000, got: 10}));
+    assert_eq!(
+        feed.flush(),
+        Some(Candle {
+            open: 110., high: 110., low: 110., close: 110., open_ts: 1680000, close_ts: 1740000, tick_count: 1,
+        })
+    );
+    assert_eq!(feed.flush(), None);
+}
+
+/// Heikin-Ashi candles average each candle's prices and open from the middle of the previous Heikin-Ashi candle.
+#[test]
+fn heikin_ashi_candles() {
+    let candle = |open: f64, high: f64, low: f64, close: f64, open_ts: u64| Candle {
+        open: open, high: high, low: low, close: close, open_ts: open_ts, close_ts: open_ts + 1000, tick_count: 4,
+    };
+    let mut heikin_ashi = HeikinAshi::new();
+    assert_eq!(heikin_ashi.push(&candle(100., 110., 90., 104., 0)), candle(102., 110., 90., 101., 0));
+    // the open is below the candle's low, so the low extends down to it
+    assert_eq!(heikin_ashi.push(&candle(120., 130., 110., 128., 1000)), candle(101.5, 130., 101.5, 122., 1000));
+    assert_eq!(heikin_ashi.push(&candle(128., 128., 100., 100., 2000)), candle(111.75, 128., 100., 114., 2000));
+
+    let mut feed = HeikinAshiFeed::new("TEST", 1000, GapPolicy::Skip);
+    let completed: Vec<Candle> = [(0, 100), (500, 110), (1000, 120)].iter()
+        .flat_map(|&(timestamp, price)| feed.push(&Tick {timestamp: timestamp, bid: price, ask: price}).unwrap())
+        .collect();
+    assert_eq!(completed, vec![
+        Candle {open: 105., high: 110., low: 100., close: 105., open_ts: 0, close_ts: 1000, tick_count: 2},
+    ]);
+    assert_eq!(
+        feed.flush(),
+        Some(Candle {open: 105., high: 120., low: 105., close: 120., open_ts: 1000, close_ts: 2000, tick_count: 1})
+    );
+}
@@ -0,0 +1,105 @@
+//! Crossovers between two of the indicators in an `IndicatorRegistry`, detected by comparing their latest values
+//! after each tick.
+
+use serde_json::Value;
+use uuid::Uuid;
+
+use tickgrinder_util::trading::indicators::CrossoverEvent;
+
+/// Watches for indicator `a` crossing above or below indicator `b`.  Events are reported as `CrossoverEvent`s with
+/// `a`'s value as the fast value and `b`'s as the slow value.
+pub struct Crossover {
+    pub a: Uuid,
+    pub b: Uuid,
+    /// Whether `a` was above `b` the last time their values differed, or `None` if they haven't been compared since
+    /// both of them warmed up
+    a_above: Option<bool>,
+    /// The most recent crossing
+    pub last_event: Option<CrossoverEvent>,
+}
+
+impl Crossover {
+    pub fn new(a: Uuid, b: Uuid) -> Crossover {
+        Crossover {
+            a: a,
+            b: b,
+            a_above: None,
+            last_event: None,
+        }
+    }
+
+    /// Compares the latest values of the two indicators, which are `None` if that indicator isn't warmed up, and
+    /// returns an event if `a` is on the other side of `b` than it was the last time they differed.  Equal values
+    /// aren't on either side, so touching the other indicator and turning back isn't a crossing while passing
+    /// through it is one, as of the first tick on the other side.  The first comparison after both indicators have
+    /// warmed up only establishes which side `a` is on.
+    pub fn push(&mut self, timestamp: u64, a: Option<f64>, b: Option<f64>) -> Option<CrossoverEvent> {
+        let (a, b) = match (a, b) {
+            (Some(a), Some(b)) => (a, b),
+            _ => {
+                self.a_above = None;
+                return None;
+            },
+        };
+        if a == b {
+            return None;
+        }
+
+        let a_above = a > b;
+        let event = match self.a_above {
+            Some(was_above) if was_above != a_above => Some(if a_above {
+                CrossoverEvent::BullishCross{timestamp: timestamp, fast_value: a, slow_value: b}
+            } else {
+                CrossoverEvent::BearishCross{timestamp: timestamp, fast_value: a, slow_value: b}
+            }),
+            _ => None,
+        };
+        self.a_above = Some(a_above);
+        if event.is_some() {
+            self.last_event = event;
+        }
+        event
+    }
+
+    /// The ids of the two indicators in the format given to `AddIndicator`
+    pub fn params(&self) -> Value {
+        json!({"a": self.a.hyphenated().to_string(), "b": self.b.hyphenated().to_string()})
+    }
+
+    /// Returns the timestamp of the most recent crossing, if there has been one.
+    pub fn last_crossed(&self) -> Option<u64> {
+        self.last_event.map(|event| match event {
+            CrossoverEvent::BullishCross{timestamp, ..} | CrossoverEvent::BearishCross{timestamp, ..} => timestamp,
+        })
+    }
+
+    /// Returns true once the indicators have been compared while both were warmed up and had different values.
+    pub fn warm_up_complete(&self) -> bool {
+        self.a_above.is_some()
+    }
+}
+
+/// Touching without crossing and waiting on a cold indicator don't produce events.
+#[test]
+fn crossover_edge_cases() {
+    let mut crossover = Crossover::new(Uuid::new_v4(), Uuid::new_v4());
+    assert_eq!(crossover.push(1, Some(1.), None), None);
+    assert_eq!(crossover.push(2, Some(1.), Some(1.)), None);
+    assert!(!crossover.warm_up_complete());
+    assert_eq!(crossover.push(3, Some(1.), Some(2.)), None);
+    assert!(crossover.warm_up_complete());
+
+    // touching and turning back
+    assert_eq!(crossover.push(4, Some(2.), Some(2.)), None);
+    assert_eq!(crossover.push(5, Some(1.), Some(2.)), None);
+    // passing through equality
+    assert_eq!(crossover.push(6, Some(2.), Some(2.)), None);
+    let event = CrossoverEvent::BullishCross{timestamp: 7, fast_value: 3., slow_value: 2.};
+    assert_eq!(crossover.push(7, Some(3.), Some(2.)), Some(event));
+    assert_eq!(crossover.last_event, Some(event));
+
+    // a cold indicator resets the side, so nothing is reported for the ticks around it
+    assert_eq!(crossover.push(8, None, Some(2.)), None);
+    assert_eq!(crossover.push(9, Some(1.), Some(2.)), None);
+    assert_eq!(crossover.last_event, Some(event));
+}
@@ -0,0 +1,185 @@
+//! Live exponential moving averages of the bid, ask, and mid price that decay with the time between ticks.
+
+#[allow(unused_imports)]
+use test;
+
+use serde_json::Value;
+
+use tickgrinder_util::trading::tick::Tick;
+use tickgrinder_util::trading::indicators::SmaError;
+
+use indicators::{Indicator, IndicatorValue};
+#[cfg(test)]
+use indicators::fixture_ticks;
+
+/// The weight that an exponential moving average with a period of `period` milliseconds keeps on its old value after
+/// `elapsed` milliseconds.
+pub fn decay(elapsed: u64, period: u64) -> f64 {
+    (-(elapsed as f64) / period as f64).exp()
+}
+
+/// An exponential moving average with a period in milliseconds.  Ticks don't arrive at regular intervals, so
+/// instead of decaying by a fixed factor per tick the average decays by `exp(-elapsed / period)` for the exact time
+/// between ticks.  Like the time-weighted SMA, the price is assumed to have stayed at each tick's level until the
+/// next one arrived, so a tick only moves the average once the tick after it is pushed.
+pub struct Ema {
+    pub period: u64,
+    /// The average mid price, or `None` if no ticks have been pushed yet
+    pub value: Option<f64>,
+    bid: f64,
+    ask: f64,
+    /// The most recent tick
+    last_tick: Option<Tick>,
+    /// Timestamp of the first tick
+    first_timestamp: Option<u64>,
+}
+
+impl Ema {
+    pub fn new(period: u64) -> Ema {
+        assert!(period > 0, "EMA period must be greater than zero!");
+        Ema {
+            period: period,
+            value: None,
+            bid: 0.,
+            ask: 0.,
+            last_tick: None,
+            first_timestamp: None,
+        }
+    }
+
+    /// Adds a tick and returns the new average mid price rounded down.  Ticks that aren't newer than the previous
+    /// one are refused and leave the EMA unchanged.
+    pub fn push(&mut self, t: &Tick) -> Result<usize, SmaError> {
+        self.push_f64(t).map(|value| value as usize)
+    }
+
+    /// Same as `push` but returns the unrounded average.
+    pub fn push_f64(&mut self, t: &Tick) -> Result<f64, SmaError> {
+        let value = match self.last_tick {
+            Some(last) if t.timestamp < last.timestamp => {
+                return Err(SmaError::OutOfOrder{last: last.timestamp, got: t.timestamp});
+            },
+            Some(last) if t.timestamp == last.timestamp => return Err(SmaError::EqualTimestamp),
+            Some(last) => {
+                let decay = decay(t.timestamp - last.timestamp, self.period);
+                self.bid = self.bid * decay + last.bid as f64 * (1. - decay);
+                self.ask = self.ask * decay + last.ask as f64 * (1. - decay);
+                self.value.unwrap() * decay + last.mid_f64() * (1. - decay)
+            },
+            None => {
+                self.bid = t.bid as f64;
+                self.ask = t.ask as f64;
+                self.first_timestamp = Some(t.timestamp);
+                t.mid_f64()
+            },
+        };
+
+        self.value = Some(value);
+        self.last_tick = Some(*t);
+        Ok(value)
+    }
+
+    /// Returns the time in milliseconds between the first and most recent ticks.
+    pub fn span(&self) -> u64 {
+        match (self.first_timestamp, self.last_tick) {
+            (Some(first), Some(last)) => last.timestamp - first,
+            _ => 0,
+        }
+    }
+
+    /// Same as `push` but returns a tick holding the average bid and ask, rounded down, with the timestamp of the
+    /// tick that was pushed.
+    pub fn push_tick(&mut self, t: &Tick) -> Result<Tick, SmaError> {
+        self.push_f64(t)?;
+        Ok(Tick {
+            bid: self.bid as usize,
+            ask: self.ask as usize,
+            timestamp: t.timestamp,
+        })
+    }
+}
+
+impl Indicator for Ema {
+    fn push(&mut self, t: Tick) -> Result<Option<IndicatorValue>, SmaError> {
+        Ok(Some(IndicatorValue::Value(self.push_f64(&t)?)))
+    }
+
+    fn name(&self) -> &str {
+        "ema"
+    }
+
+    fn params(&self) -> Value {
+        json!({"period_ms": self.period})
+    }
+
+    /// Complete once the ticks span a whole period, by which point the first tick's weight has decayed to `1 / e`.
+    fn warm_up_complete(&self) -> bool {
+        self.last_tick.is_some() && self.span() >= self.period
+    }
+}
+
+/// Values of a 1-second EMA over the first six fixture ticks, calculated separately with
+/// `v = v * exp(-dt / 1000) + prev_price * (1 - exp(-dt / 1000))`.
+#[test]
+fn ema_fixture_values() {
+    let expected = [
+        (128.0, 127.0, 129.0),
+        // the first tick was held for the whole interval, so the average doesn't move
+        (128.0, 127.0, 129.0),
+        (113.46122714694317, 112.46122714694317, 114.46122714694317),
+        (112.08505943327158, 111.0850594332716, 113.0850594332716),
+        (101.55189261186612, 100.55189261186612, 102.55189261186612),
+        (101.56133599677486, 100.56133599677484, 102.56133599677486),
+    ];
+
+    let mut ema = Ema::new(1000);
+    let mut tick_ema = Ema::new(1000);
+    for (t, &(mid, bid, ask)) in fixture_ticks().iter().zip(expected.iter()) {
+        let value = ema.push_f64(t).unwrap();
+        assert!((value - mid).abs() < 1e-9, "{} != {} at {}", value, mid, t.timestamp);
+        assert!((ema.bid - bid).abs() < 1e-9);
+        assert!((ema.ask - ask).abs() < 1e-9);
+
+        // the averaged tick's prices are rounded down
+        let avg_t = tick_ema.push_tick(t).unwrap();
+        assert!(avg_t.bid as f64 <= bid + 1e-9 && bid - (avg_t.bid as f64) < 1.);
+        assert!(avg_t.ask as f64 <= ask + 1e-9 && ask - (avg_t.ask as f64) < 1.);
+        assert_eq!(avg_t.timestamp, t.timestamp);
+    }
+    assert_eq!(ema.value.map(|v| v as usize), Some(101));
+}
+
+/// The decay depends on the time between ticks rather than how many there are.
+#[test]
+fn ema_irregular_spacing() {
+    let mut sparse = Ema::new(1000);
+    sparse.push(&Tick {timestamp: 0, bid: 100, ask: 100}).unwrap();
+    sparse.push(&Tick {timestamp: 1000, bid: 200, ask: 200}).unwrap();
+    let sparse_value = sparse.push_f64(&Tick {timestamp: 2000, bid: 200, ask: 200}).unwrap();
+
+    // splitting the same price history into more ticks gives the same average
+    let mut dense = Ema::new(1000);
+    dense.push(&Tick {timestamp: 0, bid: 100, ask: 100}).unwrap();
+    dense.push(&Tick {timestamp: 250, bid: 100, ask: 100}).unwrap();
+    dense.push(&Tick {timestamp: 1000, bid: 200, ask: 200}).unwrap();
+    dense.push(&Tick {timestamp: 1999, bid: 200, ask: 200}).unwrap();
+    let dense_value = dense.push_f64(&Tick {timestamp: 2000, bid: 200, ask: 200}).unwrap();
+    assert!((sparse_value - dense_value).abs() < 1e-9);
+    // 100 * e^-1 + 200 * (1 - e^-1)
+    assert!((sparse_value - 163.21205588285576).abs() < 1e-9);
+
+    assert_eq!(sparse.push(&Tick {timestamp: 2000, bid: 1, ask: 1}), Err(SmaError::EqualTimestamp));
+    assert_eq!(sparse.push(&Tick {timestamp: 5, bid: 1, ask: 1}), Err(SmaError::OutOfOrder{last: 2000, got: 5}));
+    assert_eq!(sparse.value, Some(sparse_value));
+}
+
+#[bench]
+fn ema_calculation(b: &mut test::Bencher) {
+    let mut ema = Ema::new(60000);
+    let mut timestamp = 1;
+
+    b.iter(|| {
+        ema.push(&Tick {bid: 1239123, ask: 1239125, timestamp: timestamp}).unwrap();
+        timestamp += 20;
+    });
+}
@@ -0,0 +1,292 @@
+//! Indicators that monitor the health of a symbol's feed: how many ticks are arriving and how wide the spread is.
+//! Each can be given a threshold at which it raises an alert.
+
+use std::collections::VecDeque;
+
+use serde_json::Value;
+
+use tickgrinder_util::trading::tick::Tick;
+use tickgrinder_util::trading::indicators::SmaError;
+
+use indicators::{Indicator, IndicatorValue};
+
+/// A value that crossed its indicator's alert threshold
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Alert {
+    pub value: f64,
+    pub threshold: f64,
+    /// Timestamp of the tick that produced the value
+    pub timestamp: u64,
+}
+
+/// Which side of the threshold a value has to be on to breach it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThresholdKind {
+    Below,
+    Above,
+}
+
+/// Raises an alert when a value crosses over a threshold.  Alerts are edge-triggered: the value has to go back to
+/// the other side of the threshold before it can raise another one, and no more than one alert is raised every
+/// `rearm_ms` milliseconds.  A crossing that happens sooner than that is ignored rather than delayed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlertThreshold {
+    pub threshold: f64,
+    pub kind: ThresholdKind,
+    pub rearm_ms: u64,
+    /// Whether the value was on the safe side of the threshold the last time it was checked
+    armed: bool,
+    /// Timestamp of the last alert
+    last_alert: Option<u64>,
+}
+
+impl AlertThreshold {
+    pub fn new(threshold: f64, kind: ThresholdKind, rearm_ms: u64) -> AlertThreshold {
+        AlertThreshold {
+            threshold: threshold,
+            kind: kind,
+            rearm_ms: rearm_ms,
+            armed: true,
+            last_alert: None,
+        }
+    }
+
+    /// Returns an alert if the value breaches the threshold and the previous value didn't.
+    pub fn check(&mut self, timestamp: u64, value: f64) -> Option<Alert> {
+        let breached = match self.kind {
+            ThresholdKind::Below => value < self.threshold,
+            ThresholdKind::Above => value > self.threshold,
+        };
+        if !breached {
+            self.armed = true;
+            return None;
+        }
+        if !self.armed {
+            return None;
+        }
+
+        self.armed = false;
+        if let Some(last_alert) = self.last_alert {
+            if timestamp < last_alert + self.rearm_ms {
+                return None;
+            }
+        }
+        self.last_alert = Some(timestamp);
+        Some(Alert {value: value, threshold: self.threshold, timestamp: timestamp})
+    }
+}
+
+/// The number of ticks per second over the last `window_ms` milliseconds.  Ticks are counted as they arrive, so a
+/// feed that stops completely is only noticed once it starts back up again.  Its alert is raised when the rate drops
+/// below the threshold, but not before the ticks span a whole window.
+pub struct TickRate {
+    pub window_ms: u64,
+    timestamps: VecDeque<u64>,
+    first_timestamp: Option<u64>,
+    pub value: Option<f64>,
+    pub alert: Option<AlertThreshold>,
+    pending_alert: Option<Alert>,
+}
+
+impl TickRate {
+    pub fn new(window_ms: u64, alert: Option<AlertThreshold>) -> TickRate {
+        assert!(window_ms > 0, "Tick rate window must be greater than zero!");
+        TickRate {
+            window_ms: window_ms,
+            timestamps: VecDeque::new(),
+            first_timestamp: None,
+            value: None,
+            alert: alert,
+            pending_alert: None,
+        }
+    }
+
+    /// Counts a tick and returns the new tick rate.  Several ticks can arrive in the same millisecond, but ticks
+    /// older than the previous one are refused.
+    pub fn push(&mut self, t: &Tick) -> Result<f64, SmaError> {
+        if let Some(&last) = self.timestamps.back() {
+            if t.timestamp < last {
+                return Err(SmaError::OutOfOrder{last: last, got: t.timestamp});
+            }
+        }
+        if self.first_timestamp.is_none() {
+            self.first_timestamp = Some(t.timestamp);
+        }
+        self.timestamps.push_back(t.timestamp);
+        while self.timestamps.front().map(|&ts| ts + self.window_ms <= t.timestamp).unwrap_or(false) {
+            self.timestamps.pop_front();
+        }
+
+        let rate = self.timestamps.len() as f64 * 1000. / self.window_ms as f64;
+        self.value = Some(rate);
+        if self.is_warmed_up() {
+            if let Some(ref mut alert) = self.alert {
+                self.pending_alert = alert.check(t.timestamp, rate);
+            }
+        }
+        Ok(rate)
+    }
+
+    /// Returns true once the ticks have spanned a whole window.
+    pub fn is_warmed_up(&self) -> bool {
+        match (self.first_timestamp, self.timestamps.back()) {
+            (Some(first), Some(&last)) => last - first >= self.window_ms,
+            _ => false,
+        }
+    }
+}
+
+impl Indicator for TickRate {
+    fn push(&mut self, t: Tick) -> Result<Option<IndicatorValue>, SmaError> {
+        Ok(Some(IndicatorValue::Value(TickRate::push(self, &t)?)))
+    }
+
+    fn name(&self) -> &str {
+        "tick_rate"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "window_ms": self.window_ms,
+            "min_rate": self.alert.as_ref().map(|alert| alert.threshold),
+            "rearm_ms": self.alert.as_ref().map(|alert| alert.rearm_ms),
+        })
+    }
+
+    fn warm_up_complete(&self) -> bool {
+        self.is_warmed_up()
+    }
+
+    fn take_alert(&mut self) -> Option<Alert> {
+        self.pending_alert.take()
+    }
+}
+
+/// The current spread (ask minus bid) along with its average over the ticks of the last `window_ms` milliseconds.
+/// Its alert is raised when the current spread rises above the threshold.
+pub struct Spread {
+    pub window_ms: u64,
+    /// Timestamps and spreads of the ticks in the window
+    spreads: VecDeque<(u64, i64)>,
+    sum: i64,
+    first_timestamp: Option<u64>,
+    pub alert: Option<AlertThreshold>,
+    pending_alert: Option<Alert>,
+}
+
+impl Spread {
+    pub fn new(window_ms: u64, alert: Option<AlertThreshold>) -> Spread {
+        assert!(window_ms > 0, "Spread window must be greater than zero!");
+        Spread {
+            window_ms: window_ms,
+            spreads: VecDeque::new(),
+            sum: 0,
+            first_timestamp: None,
+            alert: alert,
+            pending_alert: None,
+        }
+    }
+
+    /// Adds a tick and returns its spread along with the average spread of the window.  Ticks older than the
+    /// previous one are refused.
+    pub fn push(&mut self, t: &Tick) -> Result<(f64, f64), SmaError> {
+        if let Some(&(last, _)) = self.spreads.back() {
+            if t.timestamp < last {
+                return Err(SmaError::OutOfOrder{last: last, got: t.timestamp});
+            }
+        }
+        if self.first_timestamp.is_none() {
+            self.first_timestamp = Some(t.timestamp);
+        }
+        // crossed quotes have negative spreads
+        let spread = t.ask as i64 - t.bid as i64;
+        self.spreads.push_back((t.timestamp, spread));
+        self.sum += spread;
+        while self.spreads.front().map(|&(ts, _)| ts + self.window_ms <= t.timestamp).unwrap_or(false) {
+            self.sum -= self.spreads.pop_front().unwrap().1;
+        }
+
+        let average = self.sum as f64 / self.spreads.len() as f64;
+        if let Some(ref mut alert) = self.alert {
+            self.pending_alert = alert.check(t.timestamp, spread as f64);
+        }
+        Ok((spread as f64, average))
+    }
+
+    /// Returns true once the ticks have spanned a whole window.
+    pub fn is_warmed_up(&self) -> bool {
+        match (self.first_timestamp, self.spreads.back()) {
+            (Some(first), Some(&(last, _))) => last - first >= self.window_ms,
+            _ => false,
+        }
+    }
+}
+
+impl Indicator for Spread {
+    fn push(&mut self, t: Tick) -> Result<Option<IndicatorValue>, SmaError> {
+        let (current, average) = Spread::push(self, &t)?;
+        Ok(Some(IndicatorValue::Spread{current: current, average: average}))
+    }
+
+    fn name(&self) -> &str {
+        "spread"
+    }
+
+    fn params(&self) -> Value {
+        json!({
+            "window_ms": self.window_ms,
+            "max_spread": self.alert.as_ref().map(|alert| alert.threshold),
+            "rearm_ms": self.alert.as_ref().map(|alert| alert.rearm_ms),
+        })
+    }
+
+    fn warm_up_complete(&self) -> bool {
+        self.is_warmed_up()
+    }
+
+    fn take_alert(&mut self) -> Option<Alert> {
+        self.pending_alert.take()
+    }
+}
+
+/// Alerts are only raised on crossings, and crossings within the re-arm delay of the last alert are ignored.
+#[test]
+fn alert_threshold_edges() {
+    let mut threshold = AlertThreshold::new(10., ThresholdKind::Above, 100);
+    let values = [(0, 5.), (10, 11.), (20, 15.), (30, 5.), (40, 12.), (50, 5.), (200, 12.), (210, 10.), (310, 11.)];
+    let alerts: Vec<u64> = values.iter()
+        .filter_map(|&(timestamp, value)| threshold.check(timestamp, value))
+        .map(|alert| alert.timestamp)
+        .collect();
+    // the crossing at 40 is too soon after the alert at 10, and the value has to go back below 10 to cross again
+    assert_eq!(alerts, vec![10, 200, 310]);
+
+    let mut threshold = AlertThreshold::new(2., ThresholdKind::Below, 0);
+    assert_eq!(threshold.check(0, 1.), Some(Alert {value: 1., threshold: 2., timestamp: 0}));
+    assert_eq!(threshold.check(1, 2.), None);
+    assert!(threshold.check(2, 0.).is_some());
+}
+
+/// The tick rate counts the ticks in the window, and its spread counterpart averages over the same ticks.
+#[test]
+fn tick_rate_and_spread_values() {
+    let mut rate = TickRate::new(400, None);
+    let mut spread = Spread::new(400, None);
+    for i in 0..20 {
+        let t = Tick {timestamp: i * 100, bid: 100, ask: 100 + i as usize % 4};
+        let value = rate.push(&t).unwrap();
+        let (current, average) = spread.push(&t).unwrap();
+        assert_eq!(current, (i % 4) as f64);
+        // the window holds 4 ticks, one of each spread
+        if i >= 3 {
+            assert_eq!(value, 10.);
+            assert_eq!(average, 1.5);
+        }
+        assert_eq!(rate.is_warmed_up(), i >= 4);
+        assert_eq!(spread.is_warmed_up(), i >= 4);
+    }
+    // ticks can share a timestamp but can't go back in time
+    assert_eq!(rate.push(&Tick {timestamp: 1900, bid: 100, ask: 101}), Ok(12.5));
+    let late = Tick {timestamp: 1800, bid: 100, ask: 101};
+    assert_eq!(rate.push(&late), Err(SmaError::OutOfOrder{last: 1900, got: 1800}));
+}
@@ -0,0 +1,78 @@
+//! Hooks that publish a `HookEvent` when a condition on a symbol's indicators starts being met, such as every one
+//! of them completing its warm-up so that whatever is driving a backtest knows when the indicators can be trusted.
+
+use redis;
+use serde_json;
+
+use tickgrinder_util::transport::commands::{HookCondition, HookEvent};
+use tickgrinder_util::transport::redis::publish;
+
+use indicators::IndicatorRegistry;
+
+pub struct Hook {
+    pub condition: HookCondition,
+    /// Redis channel that the hook's events are published to
+    pub channel: String,
+    /// Whether the condition was met after the last tick
+    met: bool,
+}
+
+impl Hook {
+    pub fn new(condition: HookCondition, channel: String) -> Hook {
+        Hook {
+            condition: condition,
+            channel: channel,
+            met: false,
+        }
+    }
+
+    /// Checks the condition against the indicators after a tick has been pushed to them.  Returns true if it has
+    /// started being met since the last check.
+    pub fn check(&mut self, indicators: &IndicatorRegistry) -> bool {
+        let met = match self.condition {
+            HookCondition::AllIndicatorsWarmedUp => indicators.all_warmed_up(),
+        };
+        let triggered = met && !self.met;
+        self.met = met;
+        triggered
+    }
+
+    /// Publishes the event of the hook being triggered by the tick of `symbol` with the given timestamp.
+    pub fn emit(&self, symbol: &str, timestamp: u64, redis_client: &redis::Client) {
+        let event = HookEvent {
+            condition: self.condition,
+            symbol: String::from(symbol),
+            timestamp: timestamp,
+        };
+        match serde_json::to_string(&event) {
+            Ok(ser) => publish(redis_client, &self.channel, &ser),
+            Err(err) => println!("Unable to serialize hook event: {:?}", err),
+        }
+    }
+}
+
+/// The hook triggers once every indicator has warmed up, and again after an indicator added later has.
+#[test]
+fn all_indicators_warmed_up() {
+    use tickgrinder_util::trading::tick::Tick;
+
+    let mut registry = IndicatorRegistry::new();
+    let mut hook = Hook::new(HookCondition::AllIndicatorsWarmedUp, String::from("warm"));
+    // there's nothing to warm up without any indicators
+    assert!(!hook.check(&registry));
+
+    registry.add("sma", &json!({"period_ms": 2}), None, false).unwrap();
+    registry.add("sma", &json!({"period_ms": 4}), None, false).unwrap();
+    let mut triggered = Vec::new();
+    for timestamp in 1..13 {
+        if timestamp == 8 {
+            registry.add("sma", &json!({"period_ms": 3}), None, false).unwrap();
+        }
+        registry.push_all(&Tick {timestamp: timestamp, bid: 100, ask: 102});
+        if hook.check(&registry) {
+            triggered.push(timestamp);
+        }
+    }
+    // the 4ms SMA's ticks span its period at 5, and those of the 3ms one added before the tick at 8 do at 11
+    assert_eq!(triggered, vec![5, 11]);
+}
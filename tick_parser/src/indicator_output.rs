@@ -0,0 +1,126 @@
+//! The destinations that the tick processor sends indicator values to.  Each symbol's `IndicatorPublisher` has one
+//! for all of its indicators plus one for every indicator that was given its own with `SetIndicatorOutput`, and they
+//! can be swapped out without touching the indicators themselves.
+
+use postgres::Connection;
+
+use tickgrinder_util::transport::commands::IndicatorDest;
+use tickgrinder_util::transport::postgres::init_indicator_table;
+use tickgrinder_util::transport::redis::BoundedPublisher;
+use tickgrinder_util::conf::CONF;
+
+use indicators::IndicatorUpdate;
+use tick_sink::{BatchRow, BatchWriter};
+
+/// An indicator value as it's stored in Postgres
+pub struct IndicatorRow {
+    pub symbol: String,
+    pub update: IndicatorUpdate,
+}
+
+/// Quotes a string as a SQL string literal.
+fn sql_string(s: &str) -> String {
+    format!("'{}'", s.replace("'", "''"))
+}
+
+impl BatchRow for IndicatorRow {
+    fn init_table(table: &str, client: &Connection) -> Result<(), String> {
+        init_indicator_table(table, client, CONF.postgres_user)
+    }
+
+    fn columns() -> &'static str {
+        "symbol, indicator_id, kind, value, value_time, warm_up_complete"
+    }
+
+    fn values(&self) -> String {
+        format!(
+            "({}, {}, {}, {}, {}, {})",
+            sql_string(&self.symbol),
+            sql_string(&self.update.id.hyphenated().to_string()),
+            sql_string(&self.update.kind),
+            sql_string(&self.update.value.to_json().to_string()),
+            self.update.timestamp,
+            self.update.warm_up_complete
+        )
+    }
+}
+
+enum Sender {
+    Redis{channel: String, publisher: BoundedPublisher},
+    Postgres(BatchWriter<IndicatorRow>),
+    Console,
+    Null,
+}
+
+/// An open `IndicatorDest`
+pub struct IndicatorOutput {
+    pub dest: IndicatorDest,
+    sender: Sender,
+}
+
+impl IndicatorOutput {
+    /// Creates an output that publishes to `channel` on `host` through an existing publisher.
+    pub fn redis(host: &str, channel: String, publisher: BoundedPublisher) -> IndicatorOutput {
+        IndicatorOutput {
+            dest: IndicatorDest::RedisChannel{host: String::from(host), channel: channel.clone()},
+            sender: Sender::Redis{channel: channel, publisher: publisher},
+        }
+    }
+
+    /// Opens a destination.  Channels on `shared_host` are published to through `shared` instead of a publisher of
+    /// their own, and Postgres tables are created if they don't exist.
+    pub fn open(dest: IndicatorDest, shared: &BoundedPublisher, shared_host: &str) -> Result<IndicatorOutput, String> {
+        let sender = match dest {
+            IndicatorDest::RedisChannel{ref host, ref channel} => {
+                if channel.is_empty() {
+                    return Err(String::from("Channels can't be empty."));
+                }
+                let publisher = if host == shared_host {
+                    shared.clone()
+                } else {
+                    BoundedPublisher::new(host, CONF.indicator_publish_buffer_size)
+                };
+                Sender::Redis{channel: channel.clone(), publisher: publisher}
+            },
+            IndicatorDest::Postgres{ref table} => Sender::Postgres(BatchWriter::from_conf(table)?),
+            IndicatorDest::Console => Sender::Console,
+            IndicatorDest::Null => Sender::Null,
+        };
+        Ok(IndicatorOutput {
+            dest: dest,
+            sender: sender,
+        })
+    }
+
+    /// Sends a value of one of the indicators of `symbol`.
+    pub fn send(&mut self, symbol: &str, update: &IndicatorUpdate) {
+        match self.sender {
+            Sender::Redis{ref channel, ref publisher} => {
+                publisher.publish(channel.clone(), update.to_json(symbol).to_string());
+            },
+            Sender::Postgres(ref mut writer) => writer.push(IndicatorRow {
+                symbol: String::from(symbol),
+                update: update.clone(),
+            }),
+            Sender::Console => println!("{}", update.to_json(symbol)),
+            Sender::Null => (),
+        }
+    }
+
+    /// Writes the values that are waiting to be written to Postgres if they've been waiting for too long.
+    pub fn flush_if_due(&mut self) {
+        if let Sender::Postgres(ref mut writer) = self.sender {
+            writer.flush_if_due();
+        }
+    }
+
+    /// Writes the values that are waiting to be written to Postgres.
+    pub fn flush(&mut self) -> Result<(), String> {
+        match self.sender {
+            Sender::Postgres(ref mut writer) => writer.flush()
+                .map(|_| ())
+                .map_err(|err| format!("{}; {} indicator values weren't written", err, writer.buffered())),
+            _ => Ok(()),
+        }
+    }
+}
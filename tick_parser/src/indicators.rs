@@ -0,0 +1,1352 @@
+//! A common interface for the tick processor's indicators so that they can be added, listed, and removed by kind
+//! and parameters instead of needing separate commands and lists for each one.
+
+use std::mem;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[allow(unused_imports)]
+use test;
+use rayon::iter::{IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use tickgrinder_util::trading::tick::Tick;
+use tickgrinder_util::trading::indicators::{CrossoverEvent, SmaError};
+use tickgrinder_util::transport::commands::IndicatorDest;
+use tickgrinder_util::transport::redis::BoundedPublisher;
+use tickgrinder_util::conf::CONF;
+
+use tickgrinder_util::trading::sma::Sma;
+use sma::{MultiSMA, SharedSma};
+use ema::Ema;
+use rsi::Rsi;
+use macd::{Macd, MacdValues, macd_periods};
+use atr::Atr;
+use stddev::{RollingStdDev, StdDevSource};
+use crossover::Crossover;
+use feed_health::{Alert, AlertThreshold, ThresholdKind, TickRate, Spread};
+use indicator_output::IndicatorOutput;
+
+/// The output of an indicator after a tick
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IndicatorValue {
+    Value(f64),
+    Macd(MacdValues),
+    Crossover(CrossoverEvent),
+    Spread{current: f64, average: f64},
+}
+
+impl IndicatorValue {
+    pub fn to_json(&self) -> Value {
+        match *self {
+            IndicatorValue::Value(value) => json!(value),
+            IndicatorValue::Macd(values) => json!({
+                "macd": values.macd,
+                "signal": values.signal,
+                "histogram": values.histogram,
+            }),
+            IndicatorValue::Crossover(CrossoverEvent::BullishCross{fast_value, slow_value, ..}) => json!({
+                "event": "CrossUp",
+                "a": fast_value,
+                "b": slow_value,
+            }),
+            IndicatorValue::Crossover(CrossoverEvent::BearishCross{fast_value, slow_value, ..}) => json!({
+                "event": "CrossDown",
+                "a": fast_value,
+                "b": slow_value,
+            }),
+            IndicatorValue::Spread{current, average} => json!({"current": current, "average": average}),
+        }
+    }
+}
+
+/// An indicator calculated live from the processor's ticks.
+pub trait Indicator {
+    /// Adds a tick and returns the indicator's new value, or `None` if it doesn't have one yet or the tick didn't
+    /// produce one.  Ticks that aren't newer than the last one are refused and leave the indicator unchanged.
+    fn push(&mut self, t: Tick) -> Result<Option<IndicatorValue>, SmaError>;
+
+    /// The kind of the indicator, as given to `AddIndicator`
+    fn name(&self) -> &str;
+
+    /// The parameters of the indicator in the format given to `AddIndicator`, with any defaults filled in
+    fn params(&self) -> Value;
+
+    /// Returns true once the indicator has seen enough data for its values to cover its whole period.  Values from
+    /// before then are calculated over less data than the indicator's parameters ask for.
+    fn warm_up_complete(&self) -> bool;
+
+    /// Returns the alert raised by the last tick pushed, if the indicator has an alert threshold and it was crossed.
+    fn take_alert(&mut self) -> Option<Alert> {
+        None
+    }
+
+    /// Returns the crossing of the indicator's own lines caused by the last tick it produced a value for, if there
+    /// was one.
+    fn crossover(&self) -> Option<CrossoverEvent> {
+        None
+    }
+}
+
+/// Creates an indicator of the given kind from the parameters supplied with `AddIndicator`.
+pub fn create_indicator(kind: &str, params: &Value) -> Result<Box<Indicator + Send>, String> {
+    let indicator: Box<Indicator + Send> = match kind {
+        "sma" => Box::new(Sma::new(required_param(kind, params, "period_ms")?)),
+        "ema" => Box::new(Ema::new(required_param(kind, params, "period_ms")?)),
+        "rsi" => Box::new(Rsi::new(
+            required_param(kind, params, "period")? as usize, required_param(kind, params, "interval_ms")?
+        )),
+        "macd" => {
+            let (fast, slow, signal) = macd_periods(
+                optional_param(kind, params, "fast_period_ms")?,
+                optional_param(kind, params, "slow_period_ms")?,
+                optional_param(kind, params, "signal_period_ms")?,
+            );
+            Box::new(Macd::new(fast, slow, signal))
+        },
+        "atr" => Box::new(Atr::new(
+            required_param(kind, params, "bar_ms")?, required_param(kind, params, "period")? as usize
+        )),
+        "stddev" => {
+            let source = if bool_param(kind, params, "log_returns")? {
+                StdDevSource::LogReturns
+            } else {
+                StdDevSource::Price
+            };
+            Box::new(RollingStdDev::new(required_param(kind, params, "period_ms")?, source))
+        },
+        "tick_rate" => Box::new(TickRate::new(
+            required_param(kind, params, "window_ms")?,
+            alert_threshold(kind, params, "min_rate", ThresholdKind::Below)?,
+        )),
+        "spread" => Box::new(Spread::new(
+            required_param(kind, params, "window_ms")?,
+            alert_threshold(kind, params, "max_spread", ThresholdKind::Above)?,
+        )),
+        _ => return Err(format!("Unknown indicator kind: {}", kind)),
+    };
+    Ok(indicator)
+}
+
+/// Returns the positive integer parameter `name` of an indicator, or an error if it's missing or invalid.
+fn required_param(kind: &str, params: &Value, name: &str) -> Result<u64, String> {
+    optional_param(kind, params, name)?
+        .ok_or_else(|| format!("The {} indicator requires the `{}` parameter.", kind, name))
+}
+
+/// Returns the positive integer parameter `name` of an indicator if it was supplied.
+fn optional_param(kind: &str, params: &Value, name: &str) -> Result<Option<u64>, String> {
+    match params.get(name) {
+        None | Some(&Value::Null) => Ok(None),
+        Some(val) => match val.as_u64() {
+            Some(n) if n > 0 => Ok(Some(n)),
+            _ => Err(format!("The `{}` parameter of the {} indicator must be a positive integer.", name, kind)),
+        },
+    }
+}
+
+/// Returns the boolean parameter `name` of an indicator, or `false` if it wasn't supplied.
+fn bool_param(kind: &str, params: &Value, name: &str) -> Result<bool, String> {
+    match params.get(name) {
+        None | Some(&Value::Null) => Ok(false),
+        Some(&Value::Bool(b)) => Ok(b),
+        Some(_) => Err(format!("The `{}` parameter of the {} indicator must be a boolean.", name, kind)),
+    }
+}
+
+/// Returns the alert threshold given as the `name` parameter of an indicator, re-armed after the `rearm_ms`
+/// parameter, or `None` if the threshold wasn't supplied.
+fn alert_threshold(
+    kind: &str, params: &Value, name: &str, threshold_kind: ThresholdKind
+) -> Result<Option<AlertThreshold>, String> {
+    let threshold = match params.get(name) {
+        None | Some(&Value::Null) => return Ok(None),
+        Some(val) => val.as_f64()
+            .ok_or_else(|| format!("The `{}` parameter of the {} indicator must be a number.", name, kind))?,
+    };
+    let rearm_ms = optional_param(kind, params, "rearm_ms")?.unwrap_or(0);
+    Ok(Some(AlertThreshold::new(threshold, threshold_kind, rearm_ms)))
+}
+
+/// A new value of an indicator that is due to be published
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndicatorUpdate {
+    pub id: Uuid,
+    pub kind: String,
+    pub value: IndicatorValue,
+    /// Timestamp of the tick that produced the value
+    pub timestamp: u64,
+    pub warm_up_complete: bool,
+}
+
+impl IndicatorUpdate {
+    /// Returns the update as a JSON object holding the symbol, indicator id and kind, value, timestamp, and whether
+    /// the indicator's warm-up is complete.  MACD values are objects holding all three of its lines.
+    pub fn to_json(&self, symbol: &str) -> Value {
+        json!({
+            "symbol": symbol,
+            "indicator_id": self.id.hyphenated().to_string(),
+            "kind": self.kind,
+            "value": self.value.to_json(),
+            "timestamp": self.timestamp,
+            "warm_up_complete": self.warm_up_complete,
+        })
+    }
+}
+
+/// The default minimum number of indicators for `push_all_parallel` to update them in parallel
+pub const DEFAULT_PARALLEL_THRESHOLD: usize = 16;
+
+/// An alert raised by one of the registry's indicators that is due to be published
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndicatorAlert {
+    pub id: Uuid,
+    pub kind: String,
+    pub alert: Alert,
+}
+
+struct RegisteredIndicator {
+    id: Uuid,
+    indicator: Box<Indicator + Send>,
+    /// The last value produced by the indicator
+    value: Option<IndicatorValue>,
+    /// Timestamp of the tick that produced the last value
+    updated: Option<u64>,
+    /// Minimum time between published values in milliseconds
+    throttle_ms: Option<u64>,
+    /// Timestamp of the tick that produced the last published value
+    last_published: Option<u64>,
+    /// If set, values aren't published until the indicator's warm-up is complete
+    hold_until_warm: bool,
+    /// Number of ticks that the indicator refused for being out of order
+    dropped_ticks: u64,
+    /// The `MultiSMA` that calculates the indicator if it's an SMA
+    sma_group: Option<Arc<Mutex<MultiSMA>>>,
+}
+
+impl RegisteredIndicator {
+    /// Returns true if the indicator is calculated by `group`.
+    fn in_sma_group(&self, group: &Arc<Mutex<MultiSMA>>) -> bool {
+        self.sma_group.as_ref().map(|sma_group| Arc::ptr_eq(sma_group, group)).unwrap_or(false)
+    }
+
+    /// Updates the indicator with a new tick, recording its new value along with any alert it raised and crossing it
+    /// reported.  Returns the value if it's due to be published, or the reason the tick was refused.
+    fn push(
+        &mut self, t: &Tick, alerts: &mut Vec<IndicatorAlert>, crossings: &mut Vec<(Uuid, CrossoverEvent)>
+    ) -> Result<Option<IndicatorUpdate>, SmaError> {
+        let value = match self.indicator.push(*t) {
+            Ok(value) => value,
+            Err(err) => {
+                self.dropped_ticks += 1;
+                return Err(err);
+            },
+        };
+        if let Some(alert) = self.indicator.take_alert() {
+            alerts.push(IndicatorAlert {
+                id: self.id,
+                kind: String::from(self.indicator.name()),
+                alert: alert,
+            });
+        }
+        let value = match value {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        self.value = Some(value);
+        self.updated = Some(t.timestamp);
+        if let Some(event) = self.indicator.crossover() {
+            crossings.push((self.id, event));
+        }
+
+        let warm_up_complete = self.indicator.warm_up_complete();
+        if self.hold_until_warm && !warm_up_complete {
+            return Ok(None);
+        }
+        let throttled = match (self.throttle_ms, self.last_published) {
+            (Some(throttle_ms), Some(last_published)) => t.timestamp < last_published + throttle_ms,
+            _ => false,
+        };
+        if throttled {
+            return Ok(None);
+        }
+        self.last_published = Some(t.timestamp);
+        Ok(Some(IndicatorUpdate {
+            id: self.id,
+            kind: String::from(self.indicator.name()),
+            value: value,
+            timestamp: t.timestamp,
+            warm_up_complete: warm_up_complete,
+        }))
+    }
+}
+
+/// Holds all of the indicators calculated by the tick processor, keyed by the id assigned when they were added.
+/// Each combination of kind and parameters is only calculated once, no matter how many times it's added.
+///
+/// Crossovers are added like any other kind of indicator, but they compare two of the registry's other indicators
+/// instead of calculating anything from the ticks themselves.
+///
+/// SMAs are calculated by `MultiSMA`s so that SMAs that see the same ticks only buffer them once.  SMAs added before
+/// any tick has been pushed since the last one was added share a `MultiSMA`; one added after that starts a new one,
+/// since it has to warm up on its own and may accept a tick that the earlier ones refuse.
+///
+/// Ticks that an indicator refuses for being out of order are logged and counted against it.  In strict mode they
+/// cause a panic instead.
+pub struct IndicatorRegistry {
+    indicators: Vec<RegisteredIndicator>,
+    /// The `MultiSMA`s that calculate the SMAs in `indicators`, which are pushed each tick before the SMAs
+    sma_groups: Vec<Arc<Mutex<MultiSMA>>>,
+    crossovers: Vec<(Uuid, Crossover)>,
+    /// Alerts raised since they were last taken
+    alerts: Vec<IndicatorAlert>,
+    /// Crossings since they were last taken, along with the id of the crossover or indicator that reported them
+    crossings: Vec<(Uuid, CrossoverEvent)>,
+    /// With fewer indicators than this, `push_all_parallel` updates them sequentially
+    parallel_threshold: usize,
+    strict: bool,
+}
+
+impl IndicatorRegistry {
+    pub fn new() -> IndicatorRegistry {
+        IndicatorRegistry {
+            indicators: Vec::new(),
+            sma_groups: Vec::new(),
+            crossovers: Vec::new(),
+            alerts: Vec::new(),
+            crossings: Vec::new(),
+            parallel_threshold: DEFAULT_PARALLEL_THRESHOLD,
+            strict: false,
+        }
+    }
+
+    /// Sets the minimum number of indicators for `push_all_parallel` to spread the work across threads.  Below it,
+    /// the overhead of handing the indicators out to the thread pool outweighs the time saved.
+    pub fn set_parallel_threshold(&mut self, n: usize) {
+        self.parallel_threshold = n;
+    }
+
+    /// If set, pushing a tick that any indicator refuses for being out of order panics instead of skipping it.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Starts calculating an indicator of the given kind and returns its id.  Its values are published at most once
+    /// every `throttle_ms` milliseconds of tick time, or for every tick if it isn't throttled, and only once its
+    /// warm-up is complete if `hold_until_warm` is set.  If an identical indicator already exists, its id is returned
+    /// instead and its publishing options are left as they were.
+    pub fn add(
+        &mut self, kind: &str, params: &Value, throttle_ms: Option<u64>, hold_until_warm: bool
+    ) -> Result<Uuid, String> {
+        if kind == "crossover" {
+            return self.add_crossover(params);
+        }
+        let mut indicator = create_indicator(kind, params)?;
+        if let Some(id) = self.find(&*indicator) {
+            return Ok(id);
+        }
+        let mut sma_group = None;
+        if kind == "sma" {
+            let group = self.sma_group();
+            indicator = Box::new(SharedSma::new(required_param(kind, params, "period_ms")?, group.clone()));
+            sma_group = Some(group);
+        }
+
+        let id = Uuid::new_v4();
+        self.indicators.push(RegisteredIndicator {
+            id: id,
+            indicator: indicator,
+            value: None,
+            updated: None,
+            throttle_ms: throttle_ms,
+            last_published: None,
+            hold_until_warm: hold_until_warm,
+            dropped_ticks: 0,
+            sma_group: sma_group,
+        });
+        Ok(id)
+    }
+
+    /// Returns the `MultiSMA` that a new SMA is to be calculated by, which is the last one if no ticks have been
+    /// pushed to it yet and a new one otherwise.
+    fn sma_group(&mut self) -> Arc<Mutex<MultiSMA>> {
+        let shares_window = self.sma_groups.last().map(|group| !group.lock().unwrap().has_ticks()).unwrap_or(false);
+        if !shares_window {
+            self.sma_groups.push(Arc::new(Mutex::new(MultiSMA::new())));
+        }
+        self.sma_groups.last().unwrap().clone()
+    }
+
+    /// Starts watching for crossovers between the indicators whose ids are given as the `a` and `b` parameters and
+    /// returns the crossover's id.  Crossovers are published as soon as they happen, so they're never throttled.
+    fn add_crossover(&mut self, params: &Value) -> Result<Uuid, String> {
+        let a = self.crossover_param(params, "a")?;
+        let b = self.crossover_param(params, "b")?;
+        if a == b {
+            return Err(String::from("A crossover needs two different indicators."));
+        }
+        let existing = self.crossovers.iter()
+            .find(|&&(_, ref crossover)| crossover.a == a && crossover.b == b)
+            .map(|&(id, _)| id);
+        if let Some(id) = existing {
+            return Ok(id);
+        }
+
+        let id = Uuid::new_v4();
+        self.crossovers.push((id, Crossover::new(a, b)));
+        Ok(id)
+    }
+
+    /// Returns the indicator id given as the parameter `name` of a crossover, or an error if it isn't the id of an
+    /// indicator with a single value.
+    fn crossover_param(&self, params: &Value, name: &str) -> Result<Uuid, String> {
+        let id = params.get(name)
+            .and_then(|val| val.as_str())
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or_else(|| format!("The crossover indicator requires an indicator id as the `{}` parameter.", name))?;
+        match self.indicators.iter().find(|registered| registered.id == id) {
+            Some(registered) if registered.indicator.name() == "macd" || registered.indicator.name() == "spread" => {
+                Err(format!(
+                    "Crossovers can't be watched on {} indicators since they have more than one line.",
+                    registered.indicator.name()
+                ))
+            },
+            Some(_) => Ok(id),
+            None => Err(format!("No indicator with the id {} is being calculated.", id)),
+        }
+    }
+
+    /// Stops calculating the indicator with the given id, along with any crossovers that it's a part of.  Returns
+    /// `false` if there wasn't one.
+    pub fn remove(&mut self, id: Uuid) -> bool {
+        let len = self.indicators.len() + self.crossovers.len();
+        self.indicators.retain(|registered| registered.id != id);
+        // the SMA stops being calculated by its `MultiSMA` once it's dropped
+        self.sma_groups.retain(|group| !group.lock().unwrap().periods().is_empty());
+        self.crossovers.retain(|&(crossover_id, ref crossover)| {
+            crossover_id != id && crossover.a != id && crossover.b != id
+        });
+        self.indicators.len() + self.crossovers.len() != len
+    }
+
+    /// Stops calculating the indicator with the given kind and parameters.  Returns `false` if there wasn't one.
+    pub fn remove_matching(&mut self, kind: &str, params: &Value) -> Result<bool, String> {
+        let indicator = create_indicator(kind, params)?;
+        Ok(match self.find(&*indicator) {
+            Some(id) => self.remove(id),
+            None => false,
+        })
+    }
+
+    /// Returns the id of the indicator with the same kind and parameters as `indicator`, if there is one.
+    fn find(&self, indicator: &Indicator) -> Option<Uuid> {
+        let params = indicator.params();
+        self.indicators.iter()
+            .find(|registered| {
+                registered.indicator.name() == indicator.name() && registered.indicator.params() == params
+            })
+            .map(|registered| registered.id)
+    }
+
+    /// Returns true if an indicator or crossover with the given id is being calculated.
+    pub fn contains(&self, id: Uuid) -> bool {
+        self.indicators.iter().any(|registered| registered.id == id)
+            || self.crossovers.iter().any(|&(crossover_id, _)| crossover_id == id)
+    }
+
+    /// Returns the latest value of the indicator with the given id, or `None` if it doesn't exist or doesn't have
+    /// a value yet.
+    pub fn value(&self, id: Uuid) -> Option<IndicatorValue> {
+        self.indicators.iter()
+            .find(|registered| registered.id == id)
+            .and_then(|registered| registered.value)
+            .or_else(|| {
+                self.crossovers.iter()
+                    .find(|&&(crossover_id, _)| crossover_id == id)
+                    .and_then(|&(_, ref crossover)| crossover.last_event)
+                    .map(IndicatorValue::Crossover)
+            })
+    }
+
+    /// Returns the number of ticks that the indicator with the given id refused for being out of order, or `None` if
+    /// it doesn't exist.
+    pub fn dropped_ticks(&self, id: Uuid) -> Option<u64> {
+        self.indicators.iter()
+            .find(|registered| registered.id == id)
+            .map(|registered| registered.dropped_ticks)
+    }
+
+    /// Returns true if there are any indicators and every one of them has completed its warm-up.  Crossovers aren't
+    /// counted since they only compare the other indicators.
+    pub fn all_warmed_up(&self) -> bool {
+        !self.indicators.is_empty() && self.indicators.iter().all(|registered| registered.indicator.warm_up_complete())
+    }
+
+    /// Updates every indicator with a new tick and returns the new values that are due to be published.  A value
+    /// is held back if its indicator is throttled and published another one too recently, or if it's held until
+    /// its warm-up is complete and that hasn't happened yet.  Crossovers are checked once all of the indicators have
+    /// been updated.  Indicators that refuse the tick for being out of order skip it.
+    pub fn push_all(&mut self, t: &Tick) -> Vec<IndicatorUpdate> {
+        for group in self.sma_groups.iter() {
+            // the SMAs that the group calculates report whether it refused the tick
+            let _ = group.lock().unwrap().push(t);
+        }
+        let mut updates = Vec::new();
+        let mut errors = Vec::new();
+        for registered in self.indicators.iter_mut() {
+            match registered.push(t, &mut self.alerts, &mut self.crossings) {
+                Ok(Some(update)) => updates.push(update),
+                Ok(None) => (),
+                Err(err) => errors.push((registered.id, err)),
+            }
+        }
+        self.finish_push(t, updates, errors)
+    }
+
+    /// Updates every indicator with a new tick like `push_all`, calculating them concurrently if there are at least
+    /// as many as the parallel threshold.  The updates, alerts, and crossings are in the same order either way.
+    pub fn push_all_parallel(&mut self, t: &Tick) -> Vec<IndicatorUpdate> {
+        if self.indicators.len() < self.parallel_threshold {
+            return self.push_all(t);
+        }
+
+        self.sma_groups.par_iter().for_each(|group| {
+            let _ = group.lock().unwrap().push(t);
+        });
+        let results: Vec<_> = self.indicators.par_iter_mut()
+            .map(|registered| {
+                let (mut alerts, mut crossings) = (Vec::new(), Vec::new());
+                let res = registered.push(t, &mut alerts, &mut crossings).map_err(|err| (registered.id, err));
+                (res, alerts, crossings)
+            })
+            .collect();
+        let mut updates = Vec::new();
+        let mut errors = Vec::new();
+        for (res, alerts, crossings) in results {
+            match res {
+                Ok(update) => updates.extend(update),
+                Err(err) => errors.push(err),
+            }
+            self.alerts.extend(alerts);
+            self.crossings.extend(crossings);
+        }
+        self.finish_push(t, updates, errors)
+    }
+
+    /// Checks the crossovers once every indicator has been updated with a tick, adding the crossings to `updates`, and
+    /// logs the tick if any of the indicators refused it.
+    fn finish_push(
+        &mut self, t: &Tick, mut updates: Vec<IndicatorUpdate>, errors: Vec<(Uuid, SmaError)>
+    ) -> Vec<IndicatorUpdate> {
+        for &mut (id, ref mut crossover) in self.crossovers.iter_mut() {
+            let a = warm_value(&self.indicators, crossover.a);
+            let b = warm_value(&self.indicators, crossover.b);
+            if let Some(event) = crossover.push(t.timestamp, a, b) {
+                self.crossings.push((id, event));
+                updates.push(IndicatorUpdate {
+                    id: id,
+                    kind: String::from("crossover"),
+                    value: IndicatorValue::Crossover(event),
+                    timestamp: t.timestamp,
+                    warm_up_complete: true,
+                });
+            }
+        }
+        self.tick_refused(t, errors);
+        updates
+    }
+
+    /// Updates every indicator with a batch of ticks, leaving them in the same state as calling `push_all` for each
+    /// tick in order.  Returns one row per tick holding the latest value of each indicator (in the order they were
+    /// added, without the crossovers) after that tick.  The values that `push_all` would have returned for
+    /// publishing are dropped, but the alerts and crossings are kept to be taken as usual.
+    ///
+    /// All ticks are run through one indicator (or `MultiSMA` along with its SMAs) before moving on to the next so that
+    /// only one indicator's state is being worked on at a time, which is considerably faster than `push_all` when
+    /// loading historical data.
+    /// Crossovers are checked afterwards against the values that the indicators had after each tick.
+    pub fn bulk_update(&mut self, ticks: &[Tick]) -> Vec<Vec<Option<IndicatorValue>>> {
+        let mut values = vec![vec![None; self.indicators.len()]; ticks.len()];
+        let mut warm = vec![vec![false; self.indicators.len()]; ticks.len()];
+        let mut alerts = vec![Vec::new(); ticks.len()];
+        let mut crossings = vec![Vec::new(); ticks.len()];
+        let mut errors = vec![Vec::new(); ticks.len()];
+        {
+            let mut push = |registered: &mut RegisteredIndicator, row: usize, col: usize, t: &Tick| {
+                if let Err(err) = registered.push(t, &mut alerts[row], &mut crossings[row]) {
+                    errors[row].push((registered.id, err));
+                }
+                values[row][col] = registered.value;
+                warm[row][col] = registered.indicator.warm_up_complete();
+            };
+            // the SMAs calculated by a `MultiSMA` are run through the ticks along with it
+            for group in self.sma_groups.iter() {
+                let members: Vec<usize> = self.indicators.iter()
+                    .enumerate()
+                    .filter(|&(_, registered)| registered.in_sma_group(group))
+                    .map(|(col, _)| col)
+                    .collect();
+                for (row, t) in ticks.iter().enumerate() {
+                    let _ = group.lock().unwrap().push(t);
+                    for &col in members.iter() {
+                        push(&mut self.indicators[col], row, col, t);
+                    }
+                }
+            }
+            for (col, registered) in self.indicators.iter_mut().enumerate() {
+                if registered.sma_group.is_none() {
+                    for (row, t) in ticks.iter().enumerate() {
+                        push(registered, row, col, t);
+                    }
+                }
+            }
+        }
+
+        {
+            let indicators = &self.indicators;
+            let column = |id: Uuid| indicators.iter().position(|registered| registered.id == id);
+            for (row, t) in ticks.iter().enumerate() {
+                for &mut (id, ref mut crossover) in self.crossovers.iter_mut() {
+                    let a = column(crossover.a).and_then(|col| single_value(values[row][col], warm[row][col]));
+                    let b = column(crossover.b).and_then(|col| single_value(values[row][col], warm[row][col]));
+                    if let Some(event) = crossover.push(t.timestamp, a, b) {
+                        crossings[row].push((id, event));
+                    }
+                }
+            }
+        }
+
+        for ((t, row_errors), (row_alerts, row_crossings)) in ticks.iter().zip(errors.into_iter())
+            .zip(alerts.into_iter().zip(crossings.into_iter()))
+        {
+            self.alerts.extend(row_alerts);
+            self.crossings.extend(row_crossings);
+            self.tick_refused(t, row_errors);
+        }
+        values
+    }
+
+    /// Logs a tick that some of the indicators refused for being out of order, or panics in strict mode.  `errors`
+    /// holds the id of each indicator that refused it along with the reason.
+    fn tick_refused(&self, t: &Tick, errors: Vec<(Uuid, SmaError)>) {
+        if errors.is_empty() {
+            return;
+        }
+        if self.strict {
+            panic!("Out-of-order tick pushed to indicators: {:?}; {:?}", t, errors);
+        }
+        println!("Skipping out-of-order tick {:?} in {} indicators: {:?}", t, errors.len(), errors);
+    }
+
+    /// Returns the alerts that the indicators have raised since this was last called, oldest first.
+    pub fn take_alerts(&mut self) -> Vec<IndicatorAlert> {
+        mem::replace(&mut self.alerts, Vec::new())
+    }
+
+    /// Returns the crossings since this was last called, oldest first, along with the id of the crossover that
+    /// reported each one.  Indicators with more than one line, such as MACDs, report their lines crossing each other
+    /// under their own id.
+    pub fn take_crossings(&mut self) -> Vec<(Uuid, CrossoverEvent)> {
+        mem::replace(&mut self.crossings, Vec::new())
+    }
+
+    /// Returns a JSON array holding the id, kind, parameters, and latest value of every indicator, along with the
+    /// number of ticks that each one dropped for being out of order.
+    pub fn list(&self) -> Value {
+        let mut indicators: Vec<Value> = self.indicators.iter()
+            .map(|registered| json!({
+                "id": registered.id.hyphenated().to_string(),
+                "kind": registered.indicator.name(),
+                "params": registered.indicator.params(),
+                "throttle_ms": registered.throttle_ms,
+                "hold_until_warm": registered.hold_until_warm,
+                "value": registered.value.map(|value| value.to_json()),
+                "dropped_ticks": registered.dropped_ticks,
+            }))
+            .collect();
+        indicators.extend(self.crossovers.iter().map(|&(id, ref crossover)| json!({
+            "id": id.hyphenated().to_string(),
+            "kind": "crossover",
+            "params": crossover.params(),
+            "throttle_ms": null,
+            "hold_until_warm": false,
+            "value": crossover.last_event.map(|event| IndicatorValue::Crossover(event).to_json()),
+            "dropped_ticks": 0,
+        })));
+        Value::Array(indicators)
+    }
+
+    /// Returns the kind, parameters, latest value, and timestamp of the latest value of every indicator, keyed by
+    /// id.  Indicators that are still warming up or haven't produced a value yet are included with a null value.  The
+    /// value of a crossover is its most recent crossing.
+    pub fn snapshot(&self) -> Map<String, Value> {
+        let mut snapshot: Map<String, Value> = self.indicators.iter()
+            .map(|registered| {
+                let warm_up_complete = registered.indicator.warm_up_complete();
+                let value = match registered.value {
+                    Some(value) if warm_up_complete => value.to_json(),
+                    _ => Value::Null,
+                };
+                let entry = json!({
+                    "kind": registered.indicator.name(),
+                    "params": registered.indicator.params(),
+                    "value": value,
+                    "timestamp": registered.updated,
+                    "warm_up_complete": warm_up_complete,
+                });
+                (registered.id.hyphenated().to_string(), entry)
+            }).collect();
+        for &(id, ref crossover) in self.crossovers.iter() {
+            let warm_up_complete = crossover.warm_up_complete();
+            let value = match crossover.last_event {
+                Some(event) if warm_up_complete => IndicatorValue::Crossover(event).to_json(),
+                _ => Value::Null,
+            };
+            snapshot.insert(id.hyphenated().to_string(), json!({
+                "kind": "crossover",
+                "params": crossover.params(),
+                "value": value,
+                "timestamp": crossover.last_crossed(),
+                "warm_up_complete": warm_up_complete,
+            }));
+        }
+        snapshot
+    }
+}
+
+/// Returns the latest value of the indicator with the given id if it has completed its warm-up and has a single value.
+fn warm_value(indicators: &[RegisteredIndicator], id: Uuid) -> Option<f64> {
+    indicators.iter()
+        .find(|registered| registered.id == id)
+        .and_then(|registered| single_value(registered.value, registered.indicator.warm_up_complete()))
+}
+
+/// Returns an indicator's value if it's a single value and the indicator had completed its warm-up.
+fn single_value(value: Option<IndicatorValue>, warm_up_complete: bool) -> Option<f64> {
+    match value {
+        Some(IndicatorValue::Value(value)) if warm_up_complete => Some(value),
+        _ => None,
+    }
+}
+
+/// Sends the values of a symbol's indicators to their outputs, which is its `indicators_<symbol>` channel unless
+/// it's been changed, and publishes their alerts to the notifications channel.
+pub struct IndicatorPublisher {
+    pub symbol: String,
+    pub channel: String,
+    pub notifications_channel: String,
+    /// The Redis host that `publisher` publishes to
+    host: String,
+    publisher: BoundedPublisher,
+    /// Where the values of indicators without an output of their own are sent
+    output: IndicatorOutput,
+    /// Outputs of single indicators, keyed by indicator id
+    indicator_outputs: HashMap<Uuid, IndicatorOutput>,
+}
+
+impl IndicatorPublisher {
+    pub fn new(symbol: String, redis_host: &str, buffer_size: usize) -> IndicatorPublisher {
+        let channel = format!("indicators_{}", symbol);
+        let publisher = BoundedPublisher::new(redis_host, buffer_size);
+        IndicatorPublisher {
+            output: IndicatorOutput::redis(redis_host, channel.clone(), publisher.clone()),
+            channel: channel,
+            notifications_channel: String::from(CONF.redis_notifications_channel),
+            symbol: symbol,
+            host: String::from(redis_host),
+            publisher: publisher,
+            indicator_outputs: HashMap::new(),
+        }
+    }
+
+    /// Sends each update to the output of its indicator.  Updates are sent as they're formatted by
+    /// `IndicatorUpdate::to_json` everywhere but Postgres.
+    pub fn publish_all(&mut self, updates: &[IndicatorUpdate]) {
+        for update in updates {
+            let output = match self.indicator_outputs.get_mut(&update.id) {
+                Some(output) => output,
+                None => &mut self.output,
+            };
+            output.send(&self.symbol, update);
+        }
+    }
+
+    /// Opens an output that can be given to `set_output` or `set_indicator_output`.
+    pub fn open(&self, dest: IndicatorDest) -> Result<IndicatorOutput, String> {
+        IndicatorOutput::open(dest, &self.publisher, &self.host)
+    }
+
+    /// Sends the values of every indicator to `output` from now on, dropping the outputs of single indicators.  The
+    /// values that the replaced outputs were holding back are written first.
+    pub fn set_output(&mut self, output: IndicatorOutput) -> Result<(), String> {
+        let mut res = mem::replace(&mut self.output, output).flush();
+        for (_, mut output) in self.indicator_outputs.drain() {
+            res = res.and(output.flush());
+        }
+        res
+    }
+
+    /// Sends the values of the indicator with the given id to `output` from now on.
+    pub fn set_indicator_output(&mut self, id: Uuid, output: IndicatorOutput) -> Result<(), String> {
+        match self.indicator_outputs.insert(id, output) {
+            Some(mut replaced) => replaced.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns where the values of the indicator with the given id are sent.
+    pub fn dest(&self, id: Uuid) -> &IndicatorDest {
+        &self.indicator_outputs.get(&id).unwrap_or(&self.output).dest
+    }
+
+    /// Closes the outputs of the indicators for which `keep` returns false.
+    pub fn retain_outputs<F: Fn(Uuid) -> bool>(&mut self, keep: F) {
+        let removed: Vec<Uuid> = self.indicator_outputs.keys().cloned().filter(|&id| !keep(id)).collect();
+        for id in removed {
+            if let Err(err) = self.indicator_outputs.remove(&id).unwrap().flush() {
+                println!("{}", err);
+            }
+        }
+    }
+
+    /// Writes the values that every output is holding back.
+    pub fn flush(&mut self) -> Result<(), String> {
+        let mut res = self.output.flush();
+        for output in self.indicator_outputs.values_mut() {
+            res = res.and(output.flush());
+        }
+        res
+    }
+
+    /// Writes the values that any output has been holding back for too long.
+    pub fn flush_if_due(&mut self) {
+        self.output.flush_if_due();
+        for output in self.indicator_outputs.values_mut() {
+            output.flush_if_due();
+        }
+    }
+
+    /// Publishes each alert as a JSON object holding the symbol, indicator id and kind, the value that crossed the
+    /// threshold, the threshold, and the timestamp of the tick that produced the value.
+    pub fn publish_alerts(&self, alerts: &[IndicatorAlert]) {
+        for alert in alerts {
+            let msg = json!({
+                "symbol": self.symbol,
+                "indicator_id": alert.id.hyphenated().to_string(),
+                "kind": alert.kind,
+                "value": alert.alert.value,
+                "threshold": alert.alert.threshold,
+                "timestamp": alert.alert.timestamp,
+            });
+            self.publisher.publish(self.notifications_channel.clone(), msg.to_string());
+        }
+    }
+
+    /// Returns the number of values that were dropped because Redis couldn't keep up with them.
+    pub fn dropped(&self) -> u64 {
+        self.publisher.dropped()
+    }
+}
+
+#[test]
+fn indicator_registry_add_list_remove() {
+    let mut registry = IndicatorRegistry::new();
+    let ema_id = registry.add("ema", &json!({"period_ms": 1000}), None, false).unwrap();
+    // identical indicators are only calculated once
+    assert_eq!(registry.add("ema", &json!({"period_ms": 1000}), None, false), Ok(ema_id));
+    let macd_id = registry.add("macd", &json!({"fast_period_ms": 1200}), None, false).unwrap();
+    let atr_id = registry.add("atr", &json!({"bar_ms": 1000, "period": 1}), None, false).unwrap();
+    assert!(ema_id != macd_id);
+
+    for &(timestamp, price) in &[(0, 100), (500, 104), (1000, 98)] {
+        registry.push_all(&Tick {timestamp: timestamp, bid: price, ask: price});
+    }
+    // the first tick's price was held for the first half second and the second's for the next
+    let expected_ema = 100. * (-0.5f64).exp() + 104. * (1. - (-0.5f64).exp());
+    match registry.value(ema_id) {
+        Some(IndicatorValue::Value(value)) => assert!((value - expected_ema).abs() < 1e-9),
+        value => panic!("Unexpected EMA value: {:?}", value),
+    }
+    assert_eq!(registry.value(atr_id), Some(IndicatorValue::Value(4.)));
+
+    let list = registry.list();
+    let entries = list.as_array().unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0]["id"], json!(ema_id.hyphenated().to_string()));
+    assert_eq!(entries[0]["kind"], json!("ema"));
+    // defaults are filled in for parameters that weren't supplied
+    assert_eq!(entries[1]["params"], json!({"fast_period_ms": 1200, "slow_period_ms": 26, "signal_period_ms": 9}));
+    assert!(entries[1]["value"]["histogram"].is_number());
+
+    assert!(registry.remove(macd_id));
+    assert!(!registry.remove(macd_id));
+    assert_eq!(registry.remove_matching("ema", &json!({"period_ms": 1000})), Ok(true));
+    assert_eq!(registry.remove_matching("ema", &json!({"period_ms": 1000})), Ok(false));
+    assert_eq!(registry.list().as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn indicator_registry_errors() {
+    let mut registry = IndicatorRegistry::new();
+    assert!(registry.add("bollinger", &json!({}), None, false).unwrap_err().contains("Unknown indicator kind"));
+    assert!(registry.add("sma", &json!({}), None, false).unwrap_err().contains("`period_ms`"));
+    let rsi_params = json!({"period": 14, "interval_ms": 0});
+    assert!(registry.add("rsi", &rsi_params, None, false).unwrap_err().contains("positive"));
+    assert!(registry.add("ema", &json!({"period_ms": "fast"}), None, false).unwrap_err().contains("positive"));
+    assert!(registry.remove_matching("bollinger", &json!({})).is_err());
+    assert_eq!(registry.list(), json!([]));
+}
+
+/// Throttled indicators only report a value once the throttle has passed since the last one they reported.
+#[test]
+fn indicator_registry_throttle() {
+    let mut registry = IndicatorRegistry::new();
+    let every_tick = registry.add("sma", &json!({"period_ms": 1}), None, false).unwrap();
+    let throttled = registry.add("ema", &json!({"period_ms": 1000}), Some(100), false).unwrap();
+
+    let mut published = Vec::new();
+    for &timestamp in &[0, 50, 99, 100, 150, 250] {
+        for update in registry.push_all(&Tick {timestamp: timestamp, bid: 100, ask: 100}) {
+            published.push((update.id, update.timestamp));
+        }
+    }
+    let timestamps = |id: Uuid| -> Vec<u64> {
+        published.iter().filter(|&&(update_id, _)| update_id == id).map(|&(_, t)| t).collect()
+    };
+    assert_eq!(timestamps(every_tick), vec![0, 50, 99, 100, 150, 250]);
+    assert_eq!(timestamps(throttled), vec![0, 100, 250]);
+    assert_eq!(registry.list()[1]["throttle_ms"], json!(100));
+}
+
+/// A feed dropout and a spread spike each raise exactly one alert, even though the rate stays low and the spread
+/// stays wide for several ticks.
+#[test]
+fn indicator_registry_alerts() {
+    let mut registry = IndicatorRegistry::new();
+    let rate_id = registry.add("tick_rate", &json!({"window_ms": 1000, "min_rate": 5}), None, false).unwrap();
+    let spread_id = registry.add("spread", &json!({"window_ms": 1000, "max_spread": 5, "rearm_ms": 10000}), None, false)
+        .unwrap();
+    assert!(registry.add("spread", &json!({"window_ms": 1000, "max_spread": "wide"}), None, false).is_err());
+
+    // 10 ticks a second with a gap from 3 to 6 seconds; the spread spikes for half a second after 8 seconds
+    let timestamps = (0..30).chain(60..100).map(|i| i * 100);
+    let mut alerts = Vec::new();
+    for timestamp in timestamps {
+        let spread = if timestamp >= 8000 && timestamp < 8500 { 20 } else { 2 };
+        registry.push_all(&Tick {timestamp: timestamp, bid: 1000, ask: 1000 + spread});
+        alerts.extend(registry.take_alerts());
+    }
+    assert!(registry.take_alerts().is_empty());
+
+    assert_eq!(alerts, vec![
+        // the first tick after the gap is the only one in its window
+        IndicatorAlert {id: rate_id, kind: String::from("tick_rate"), alert: Alert {
+            value: 1., threshold: 5., timestamp: 6000,
+        }},
+        IndicatorAlert {id: spread_id, kind: String::from("spread"), alert: Alert {
+            value: 20., threshold: 5., timestamp: 8000,
+        }},
+    ]);
+    assert_eq!(registry.value(spread_id), Some(IndicatorValue::Spread{current: 2., average: 2.}));
+}
+
+/// Each kind of indicator reports its warm-up as complete starting with the tick that completes it.
+#[test]
+fn indicator_warm_up() {
+    let cases = [
+        ("sma", json!({"period_ms": 100}), 100),
+        ("ema", json!({"period_ms": 1000}), 1000),
+        // the first sample is at 0 and the second change is sampled at 200
+        ("rsi", json!({"period": 2, "interval_ms": 100}), 200),
+        // the second bar is closed by the tick at 2000
+        ("atr", json!({"bar_ms": 1000, "period": 2}), 2000),
+        ("macd", json!({"fast_period_ms": 100, "slow_period_ms": 200, "signal_period_ms": 300}), 300),
+        ("stddev", json!({"period_ms": 500, "log_returns": true}), 500),
+    ];
+    for &(kind, ref params, warm_at) in cases.iter() {
+        let mut indicator = create_indicator(kind, params).unwrap();
+        assert!(!indicator.warm_up_complete());
+        for i in 0..50 {
+            let timestamp = i * 50;
+            let price = 100 + (i as usize % 7);
+            indicator.push(Tick {timestamp: timestamp, bid: price, ask: price + 2}).unwrap();
+            assert_eq!(indicator.warm_up_complete(), timestamp >= warm_at, "{} at {}", kind, timestamp);
+        }
+    }
+}
+
+/// Indicators that are held until they're warmed up don't publish anything before then, and every update says
+/// whether its indicator was warmed up.
+#[test]
+fn indicator_registry_hold_until_warm() {
+    let mut registry = IndicatorRegistry::new();
+    let held = registry.add("sma", &json!({"period_ms": 2}), None, true).unwrap();
+    let unheld = registry.add("ema", &json!({"period_ms": 1000}), None, false).unwrap();
+
+    let mut published = Vec::new();
+    for timestamp in 1..5 {
+        for update in registry.push_all(&Tick {timestamp: timestamp, bid: 100, ask: 100}) {
+            published.push((update.id, update.timestamp, update.warm_up_complete));
+        }
+    }
+    assert_eq!(published, vec![
+        (unheld, 1, false), (unheld, 2, false),
+        (held, 3, true), (unheld, 3, false),
+        (held, 4, true), (unheld, 4, false),
+    ]);
+
+    let snapshot = registry.snapshot();
+    assert_eq!(snapshot[&held.hyphenated().to_string()]["warm_up_complete"], json!(true));
+    assert_eq!(snapshot[&unheld.hyphenated().to_string()]["warm_up_complete"], json!(false));
+    // the EMA has a value but is still warming up, so its value is left out
+    assert_eq!(snapshot[&unheld.hyphenated().to_string()]["value"], json!(null));
+    assert_eq!(snapshot[&held.hyphenated().to_string()]["value"], json!(100.));
+    assert_eq!(registry.list()[0]["hold_until_warm"], json!(true));
+}
+
+/// A fast SMA crosses above a slow one and back below it, which is reported as exactly two crossovers.
+#[test]
+fn indicator_registry_crossovers() {
+    let mut registry = IndicatorRegistry::new();
+    let fast = registry.add("sma", &json!({"period_ms": 2}), None, false).unwrap();
+    let slow = registry.add("sma", &json!({"period_ms": 4}), None, false).unwrap();
+    let crossover_params = |a: Uuid, b: Uuid| json!({"a": a.hyphenated().to_string(), "b": b.hyphenated().to_string()});
+    let crossover = registry.add("crossover", &crossover_params(fast, slow), None, false).unwrap();
+    assert_eq!(registry.add("crossover", &crossover_params(fast, slow), None, false), Ok(crossover));
+
+    let prices = [10, 9, 8, 7, 6, 7, 8, 9, 10, 9, 8, 7];
+    let mut events = Vec::new();
+    for (i, &price) in prices.iter().enumerate() {
+        for update in registry.push_all(&Tick {timestamp: i as u64 + 1, bid: price, ask: price}) {
+            if update.id == crossover {
+                events.push((update.timestamp, update.value.to_json()));
+            }
+        }
+    }
+    assert_eq!(events, vec![
+        (8, json!({"event": "CrossUp", "a": 7.5, "b": 7.})),
+        (12, json!({"event": "CrossDown", "a": 8.5, "b": 9.})),
+    ]);
+    assert_eq!(registry.snapshot()[&crossover.hyphenated().to_string()]["timestamp"], json!(12));
+    assert_eq!(registry.list()[2]["kind"], json!("crossover"));
+
+    // removing either indicator removes the crossover
+    assert!(registry.remove(slow));
+    assert_eq!(registry.value(crossover), None);
+    assert_eq!(registry.list().as_array().unwrap().len(), 1);
+
+    let macd = registry.add("macd", &json!({}), None, false).unwrap();
+    assert!(registry.add("crossover", &crossover_params(fast, fast), None, false).is_err());
+    assert!(registry.add("crossover", &crossover_params(fast, macd), None, false).unwrap_err().contains("MACD"));
+    let res = registry.add("crossover", &crossover_params(fast, Uuid::new_v4()), None, false);
+    assert!(res.unwrap_err().contains("No indicator"));
+    assert!(registry.add("crossover", &json!({"a": "fast"}), None, false).unwrap_err().contains("`a`"));
+}
+
+/// Indicator values are published to the symbol's channel in the order of the ticks that produced them.
+#[test]
+fn indicator_values_published() {
+    use futures::Stream;
+    use tickgrinder_util::conf::CONF;
+    use tickgrinder_util::transport::redis::sub_channel;
+
+    let symbol = format!("TEST{}", Uuid::new_v4().simple());
+    let mut publisher = IndicatorPublisher::new(symbol.clone(), CONF.redis_host, 100);
+    let rx = sub_channel(CONF.redis_host, &publisher.channel);
+
+    let mut registry = IndicatorRegistry::new();
+    let id = registry.add("sma", &json!({"period_ms": 2}), None, false).unwrap();
+    for (i, &price) in [100, 102, 110, 90].iter().enumerate() {
+        let updates = registry.push_all(&Tick {timestamp: i as u64 + 1, bid: price, ask: price});
+        publisher.publish_all(&updates);
+    }
+
+    // each price is held until the next tick, so the SMA is warmed up once its window spans 2ms
+    let expected = [(1, 100., false), (2, 100., false), (3, 101., true), (4, 106., true)];
+    let msgs: Vec<Value> = rx.wait().take(expected.len())
+        .map(|msg| ::serde_json::from_str(&msg.unwrap()).unwrap())
+        .collect();
+    for (msg, &(timestamp, value, warm_up_complete)) in msgs.iter().zip(expected.iter()) {
+        assert_eq!(msg["symbol"], json!(symbol));
+        assert_eq!(msg["indicator_id"], json!(id.hyphenated().to_string()));
+        assert_eq!(msg["kind"], json!("sma"));
+        assert_eq!(msg["timestamp"], json!(timestamp));
+        assert_eq!(msg["value"], json!(value));
+        assert_eq!(msg["warm_up_complete"], json!(warm_up_complete));
+    }
+    assert_eq!(publisher.dropped(), 0);
+}
+
+/// Duplicated and regressed ticks are skipped and counted by each indicator that refuses them without affecting its
+/// values, and an indicator added after the last tick takes them.
+#[test]
+fn indicator_registry_out_of_order_ticks() {
+    let mut clean = IndicatorRegistry::new();
+    let mut noisy = IndicatorRegistry::new();
+    let clean_sma = clean.add("sma", &json!({"period_ms": 3}), None, false).unwrap();
+    let noisy_sma = noisy.add("sma", &json!({"period_ms": 3}), None, false).unwrap();
+    let noisy_macd = noisy.add("macd", &json!({}), None, false).unwrap();
+
+    let ticks: Vec<Tick> = (1..21).map(|i| {
+        let price = 1000 + (i as usize * 37 % 101);
+        Tick {timestamp: i, bid: price, ask: price + 2}
+    }).collect();
+    for t in ticks[..10].iter() {
+        clean.push_all(t);
+        noisy.push_all(t);
+    }
+    let duplicate = Tick {timestamp: 10, bid: 5000, ask: 5002};
+    assert!(noisy.push_all(&duplicate).is_empty());
+    let late = noisy.add("ema", &json!({"period_ms": 5}), None, false).unwrap();
+    let updates = noisy.push_all(&Tick {timestamp: 4, bid: 5000, ask: 5002});
+    assert_eq!(updates.iter().map(|update| update.id).collect::<Vec<Uuid>>(), vec![late]);
+    assert_eq!(noisy.dropped_ticks(noisy_sma), Some(2));
+    assert_eq!(noisy.dropped_ticks(noisy_macd), Some(2));
+    assert_eq!(noisy.dropped_ticks(late), Some(0));
+    assert_eq!(noisy.dropped_ticks(Uuid::new_v4()), None);
+
+    for t in ticks[10..].iter() {
+        clean.push_all(t);
+        noisy.push_all(t);
+    }
+    assert_eq!(noisy.value(noisy_sma), clean.value(clean_sma));
+    assert_eq!(noisy.list()[0]["dropped_ticks"], json!(2));
+    assert_eq!(clean.dropped_ticks(clean_sma), Some(0));
+}
+
+/// In strict mode an out-of-order tick is a hard failure.
+#[test]
+#[should_panic]
+fn indicator_registry_strict_mode() {
+    let mut registry = IndicatorRegistry::new();
+    registry.add("sma", &json!({"period_ms": 3}), None, false).unwrap();
+    registry.set_strict(true);
+    registry.push_all(&Tick {timestamp: 2, bid: 1000, ask: 1002});
+    registry.push_all(&Tick {timestamp: 1, bid: 1000, ask: 1002});
+}
+
+/// Builds ticks from `(timestamp, bid, ask)` rows.
+#[cfg(test)]
+pub fn ticks_from_rows(rows: &[(u64, usize, usize)]) -> Vec<Tick> {
+    rows.iter()
+        .map(|&(timestamp, bid, ask)| Tick {timestamp: timestamp, bid: bid, ask: ask})
+        .collect()
+}
+
+/// Irregularly spaced ticks shared by the fixture tests of the time-decayed indicators.  The first mid price is a
+/// power of two so that EMAs of any period hold it exactly instead of differing by a rounding error.
+#[cfg(test)]
+pub fn fixture_ticks() -> Vec<Tick> {
+    ticks_from_rows(&[
+        (0, 127, 129), (500, 104, 106), (1500, 98, 100), (1600, 100, 102), (4600, 110, 112), (4601, 120, 122),
+        (5000, 90, 92), (7000, 95, 97),
+    ])
+}
+
+/// Returns `n` ticks one millisecond apart whose prices cycle through a fixed pattern.
+#[cfg(test)]
+pub fn bulk_test_ticks(n: usize) -> Vec<Tick> {
+    (0..n).map(|i| {
+        let price = 1000 + (i * 37 % 101);
+        Tick {bid: price, ask: price + 2, timestamp: i as u64 + 1}
+    }).collect()
+}
+
+/// `bulk_update` should produce the same values and leave the indicators in the same state as calling `push_all` for
+/// each tick, including the crossings they report and the ticks they drop.
+#[test]
+fn bulk_update_matches_push_all() {
+    let mut ticks = bulk_test_ticks(200);
+    // a duplicated tick is refused by everything
+    let duplicate = ticks[120];
+    ticks.insert(121, duplicate);
+
+    let mut bulk = IndicatorRegistry::new();
+    let mut individual = IndicatorRegistry::new();
+    let mut ids = Vec::new();
+    for registry in [&mut bulk, &mut individual].iter_mut() {
+        let mut registry_ids = Vec::new();
+        for &period in &[1, 3, 10, 50] {
+            registry_ids.push(registry.add("sma", &json!({"period_ms": period}), None, false).unwrap());
+        }
+        registry_ids.push(registry.add("ema", &json!({"period_ms": 20}), None, false).unwrap());
+        let macd_params = json!({"fast_period_ms": 5, "slow_period_ms": 12, "signal_period_ms": 4});
+        registry_ids.push(registry.add("macd", &macd_params, None, false).unwrap());
+        let crossover_params = json!({
+            "a": registry_ids[1].hyphenated().to_string(), "b": registry_ids[2].hyphenated().to_string(),
+        });
+        registry.add("crossover", &crossover_params, None, false).unwrap();
+        ids.push(registry_ids);
+    }
+
+    let values = bulk.bulk_update(&ticks);
+    assert_eq!(values.len(), ticks.len());
+    for (t, row) in ticks.iter().zip(values.iter()) {
+        individual.push_all(t);
+        let expected: Vec<Option<IndicatorValue>> = ids[1].iter().map(|&id| individual.value(id)).collect();
+        assert_eq!(row, &expected);
+    }
+
+    let crossings = |registry: &mut IndicatorRegistry| -> Vec<CrossoverEvent> {
+        registry.take_crossings().into_iter().map(|(_, event)| event).collect()
+    };
+    let bulk_crossings = crossings(&mut bulk);
+    assert!(bulk_crossings.len() > 2);
+    assert_eq!(bulk_crossings, crossings(&mut individual));
+    assert_eq!(bulk.dropped_ticks(ids[0][0]), Some(1));
+    assert_eq!(individual.dropped_ticks(ids[1][0]), Some(1));
+
+    // both should keep going identically afterwards
+    let next = Tick {bid: 2000, ask: 2002, timestamp: 201};
+    let kinds = |registry: &mut IndicatorRegistry| -> Vec<String> {
+        registry.push_all(&next).into_iter().map(|update| update.kind).collect()
+    };
+    assert_eq!(kinds(&mut bulk), kinds(&mut individual));
+    for (&bulk_id, &id) in ids[0].iter().zip(ids[1].iter()) {
+        assert_eq!(bulk.value(bulk_id), individual.value(id));
+    }
+}
+
+#[cfg(test)]
+fn bench_registry() -> IndicatorRegistry {
+    let mut registry = IndicatorRegistry::new();
+    for &period in &[5, 10, 20, 50, 100, 200] {
+        registry.add("sma", &json!({"period_ms": period}), None, false).unwrap();
+    }
+    registry
+}
+
+#[bench]
+fn bulk_sma_calculation(b: &mut test::Bencher) {
+    let ticks = bulk_test_ticks(10000);
+    b.iter(|| {
+        let mut registry = bench_registry();
+        registry.bulk_update(&ticks)
+    })
+}
+
+/// Baseline for `bulk_sma_calculation`
+#[bench]
+fn repeated_push_all_calculation(b: &mut test::Bencher) {
+    let ticks = bulk_test_ticks(10000);
+    b.iter(|| {
+        let mut registry = bench_registry();
+        let values: Vec<Vec<IndicatorUpdate>> = ticks.iter().map(|t| registry.push_all(t)).collect();
+        values
+    })
+}
+
+/// `push_all_parallel` should produce the same updates and crossings as `push_all` whether or not it runs in parallel.
+#[test]
+fn parallel_push_matches_push_all() {
+    let ticks = bulk_test_ticks(300);
+    let mut sequential = IndicatorRegistry::new();
+    let mut parallel = IndicatorRegistry::new();
+    let mut below_threshold = IndicatorRegistry::new();
+    for registry in [&mut sequential, &mut parallel, &mut below_threshold].iter_mut() {
+        let mut ids = Vec::new();
+        for period in 1..25 {
+            ids.push(registry.add("sma", &json!({"period_ms": period * 3}), None, false).unwrap());
+        }
+        registry.add("ema", &json!({"period_ms": 30}), Some(10), false).unwrap();
+        let crossover_params = json!({"a": ids[0].hyphenated().to_string(), "b": ids[9].hyphenated().to_string()});
+        registry.add("crossover", &crossover_params, None, false).unwrap();
+    }
+    parallel.set_parallel_threshold(2);
+    below_threshold.set_parallel_threshold(100);
+
+    // the ids differ between the registries, so the updates are compared without them
+    let strip = |updates: Vec<IndicatorUpdate>| -> Vec<(String, IndicatorValue, u64, bool)> {
+        updates.into_iter()
+            .map(|update| (update.kind, update.value, update.timestamp, update.warm_up_complete))
+            .collect()
+    };
+    for t in ticks.iter() {
+        let expected = strip(sequential.push_all(t));
+        assert_eq!(strip(parallel.push_all_parallel(t)), expected);
+        assert_eq!(strip(below_threshold.push_all_parallel(t)), expected);
+    }
+    // a repeated tick is dropped the same way
+    parallel.push_all_parallel(&ticks[299]);
+    assert_eq!(parallel.list()[0]["dropped_ticks"], json!(1));
+
+    let crossings = |registry: &mut IndicatorRegistry| -> Vec<CrossoverEvent> {
+        registry.take_crossings().into_iter().map(|(_, event)| event).collect()
+    };
+    let expected = crossings(&mut sequential);
+    assert!(!expected.is_empty());
+    assert_eq!(crossings(&mut parallel), expected);
+    assert_eq!(crossings(&mut below_threshold), expected);
+}
+
+#[cfg(test)]
+fn parallel_bench_registry(parallel_threshold: usize) -> IndicatorRegistry {
+    let mut registry = IndicatorRegistry::new();
+    for period in 1..21 {
+        registry.add("sma", &json!({"period_ms": period * 10}), None, false).unwrap();
+    }
+    registry.set_parallel_threshold(parallel_threshold);
+    registry
+}
+
+#[bench]
+fn parallel_sma_calculation(b: &mut test::Bencher) {
+    let ticks = bulk_test_ticks(1000);
+    b.iter(|| {
+        let mut registry = parallel_bench_registry(0);
+        let updates: Vec<Vec<IndicatorUpdate>> = ticks.iter().map(|t| registry.push_all_parallel(t)).collect();
+        updates
+    })
+}
+
+/// Baseline for `parallel_sma_calculation`
+#[bench]
+fn sequential_sma_calculation(b: &mut test::Bencher) {
+    let ticks = bulk_test_ticks(1000);
+    b.iter(|| {
+        let mut registry = parallel_bench_registry(usize::max_value());
+        let updates: Vec<Vec<IndicatorUpdate>> = ticks.iter().map(|t| registry.push_all_parallel(t)).collect();
+        updates
+    })
+}
+
+/// SMAs added before the first tick share one window no longer than the longest period, and ones added later get a
+/// window of their own.  Their values are the same as those of SMAs calculated separately.
+#[test]
+fn registry_shares_sma_windows() {
+    let mut registry = IndicatorRegistry::new();
+    let mut separate = Vec::new();
+    for period in 1..11 {
+        let id = registry.add("sma", &json!({"period_ms": period * 10}), None, false).unwrap();
+        separate.push((id, Sma::new(period * 10), None));
+    }
+    for t in bulk_test_ticks(500).iter() {
+        registry.push_all(t);
+        for &mut (_, ref mut sma, ref mut value) in separate.iter_mut() {
+            *value = sma.push_f64(*t).unwrap();
+        }
+    }
+    for &(id, _, value) in separate.iter() {
+        assert_eq!(registry.value(id), value.map(IndicatorValue::Value));
+    }
+    assert_eq!(registry.sma_groups.len(), 1);
+    // separate SMAs would buffer 10 + 20 + ... + 100 ticks
+    assert_eq!(registry.sma_groups[0].lock().unwrap().buffered(), 100);
+
+    let late_id = registry.add("sma", &json!({"period_ms": 5}), None, false).unwrap();
+    assert_eq!(registry.add("sma", &json!({"period_ms": 10}), None, false), Ok(separate[0].0));
+    registry.add("sma", &json!({"period_ms": 15}), None, false).unwrap();
+    assert_eq!(registry.sma_groups.len(), 2);
+    assert_eq!(registry.list().as_array().unwrap().len(), 12);
+    assert!(!registry.all_warmed_up());
+
+    // the later window is dropped along with the last of its SMAs
+    assert!(registry.remove(late_id));
+    assert_eq!(registry.sma_groups.len(), 2);
+    assert_eq!(registry.remove_matching("sma", &json!({"period_ms": 15})), Ok(true));
+    assert_eq!(registry.sma_groups.len(), 1);
+    assert!(registry.all_warmed_up());
+    assert!(registry.remove(separate[9].0));
+    assert_eq!(registry.sma_groups[0].lock().unwrap().buffered(), 90);
+}
@@ -0,0 +1,462 @@
+//! A bounded queue between the Redis subscription and the processing loop.  Ticks arrive on their own thread and
+//! wait here until the processor gets to them, so if processing stalls, the queue fills up and ticks are discarded
+//! according to an `IntakePolicy` instead of piling up without limit.  Commands are never dropped and are handed to
+//! the processor ahead of any waiting ticks.
+//!
+//! Repeated ticks of each symbol are dropped by a `TickDeduplicator` before they're queued, so they neither take up
+//! room in the queue nor reach the indicators.  Ticks of symbols that aren't being followed are dropped before either
+//! of those, since the processor is subscribed to the ticks of every symbol.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, Condvar};
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use tickgrinder_util::trading::tick::{Tick, TickDeduplicator};
+use tickgrinder_util::conf::CONF;
+
+/// What to do with incoming ticks once the queue is full
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntakePolicy {
+    /// Discard the oldest waiting tick to make room for the new one
+    DropOldest,
+    /// Discard the new tick
+    DropNewest,
+    /// Only keep the most recent waiting tick of each symbol, replacing the waiting one when a newer one arrives.
+    /// If the queue is still full, the oldest tick is discarded.
+    CoalesceToLatest,
+}
+
+impl FromStr for IntakePolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<IntakePolicy, String> {
+        match policy.trim().to_lowercase().replace("_", "").as_str() {
+            "dropoldest" => Ok(IntakePolicy::DropOldest),
+            "dropnewest" => Ok(IntakePolicy::DropNewest),
+            "coalescetolatest" | "coalesce" => Ok(IntakePolicy::CoalesceToLatest),
+            _ => Err(format!("Unknown intake policy: {}", policy)),
+        }
+    }
+}
+
+/// A message received from Redis that is waiting to be processed
+#[derive(Clone, Debug, PartialEq)]
+pub enum Intake {
+    Command(String),
+    Tick(String, Tick),
+    /// Nothing arrived before `pop_timeout` gave up waiting
+    Idle,
+}
+
+/// The state of the queue at one point in time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IntakeStats {
+    pub policy: IntakePolicy,
+    pub capacity: usize,
+    /// Ticks currently waiting to be processed
+    pub queued: usize,
+    /// Ticks that were discarded by the policy, including ones replaced by newer ticks of the same symbol
+    pub dropped: u64,
+    /// Exact duplicates of the previous tick of their symbol that were dropped
+    pub duplicates: u64,
+    /// Ticks that were dropped for having the same prices as the previous tick of their symbol
+    pub unchanged: u64,
+}
+
+impl IntakeStats {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "policy": format!("{:?}", self.policy),
+            "capacity": self.capacity,
+            "queued": self.queued,
+            "dropped": self.dropped,
+            "duplicates": self.duplicates,
+            "unchanged": self.unchanged,
+        })
+    }
+}
+
+struct IntakeState {
+    commands: VecDeque<String>,
+    /// Waiting ticks and their symbols, oldest first
+    ticks: VecDeque<(String, Tick)>,
+    policy: IntakePolicy,
+    capacity: usize,
+    dropped: u64,
+    /// The symbols whose ticks are queued; ticks of any other symbol are ignored
+    followed: HashSet<String>,
+    /// Drops repeated ticks of each symbol before they're queued
+    dedup: HashMap<String, TickDeduplicator>,
+    /// The `unchanged_window_ms` of the deduplicators
+    unchanged_window_ms: Option<u64>,
+    /// Set once a tick is dropped and cleared once the processor catches up with the queue
+    dropping: bool,
+    closed: bool,
+}
+
+impl IntakeState {
+    /// Passes a tick through the deduplicator of its symbol, returning `false` if it was dropped.
+    fn dedup(&mut self, symbol: &str, t: Tick) -> bool {
+        if !self.dedup.contains_key(symbol) {
+            self.dedup.insert(String::from(symbol), TickDeduplicator::new(self.unchanged_window_ms));
+        }
+        self.dedup.get_mut(symbol).unwrap().filter(t).is_some()
+    }
+
+    /// Takes the next waiting command, or the next waiting tick if there are no commands.
+    fn take(&mut self) -> Option<Intake> {
+        if let Some(cmd) = self.commands.pop_front() {
+            return Some(Intake::Command(cmd));
+        }
+        if let Some((symbol, t)) = self.ticks.pop_front() {
+            if self.ticks.is_empty() {
+                self.dropping = false;
+            }
+            return Some(Intake::Tick(symbol, t));
+        }
+        None
+    }
+
+    /// Discards ticks according to the policy until no more than `capacity` are waiting.
+    fn trim(&mut self) {
+        while self.ticks.len() > self.capacity {
+            match self.policy {
+                IntakePolicy::DropNewest => self.ticks.pop_back(),
+                IntakePolicy::DropOldest | IntakePolicy::CoalesceToLatest => self.ticks.pop_front(),
+            };
+            self.dropped += 1;
+        }
+    }
+}
+
+/// Shared between the thread that receives messages and the one that processes them.
+#[derive(Clone)]
+pub struct IntakeQueue {
+    state: Arc<(Mutex<IntakeState>, Condvar)>,
+}
+
+impl IntakeQueue {
+    /// Creates a queue that drops ticks with unchanged prices within `unchanged_window_ms` of the previous tick of
+    /// their symbol as well as exact duplicates.
+    pub fn new(policy: IntakePolicy, capacity: usize, unchanged_window_ms: Option<u64>) -> IntakeQueue {
+        let state = IntakeState {
+            commands: VecDeque::new(),
+            ticks: VecDeque::with_capacity(capacity),
+            policy: policy,
+            capacity: capacity,
+            dropped: 0,
+            followed: HashSet::new(),
+            dedup: HashMap::new(),
+            unchanged_window_ms: unchanged_window_ms,
+            dropping: false,
+            closed: false,
+        };
+        IntakeQueue {
+            state: Arc::new((Mutex::new(state), Condvar::new())),
+        }
+    }
+
+    /// Creates a queue with the policy, capacity, and unchanged tick window from the config.  An invalid policy
+    /// falls back to `DropOldest`.
+    pub fn from_conf() -> IntakeQueue {
+        let policy = IntakePolicy::from_str(CONF.processor_intake_policy).unwrap_or_else(|err| {
+            println!("{}; dropping the oldest ticks instead", err);
+            IntakePolicy::DropOldest
+        });
+        let unchanged_window_ms = TickDeduplicator::from_conf().unchanged_window_ms;
+        IntakeQueue::new(policy, CONF.processor_intake_capacity, unchanged_window_ms)
+    }
+
+    /// Queues a command to be processed before any waiting ticks.
+    pub fn push_command(&self, cmd: String) {
+        let &(ref lock, ref cvar) = &*self.state;
+        lock.lock().unwrap().commands.push_back(cmd);
+        cvar.notify_one();
+    }
+
+    /// Starts queueing the ticks of a symbol.
+    pub fn follow(&self, symbol: String) {
+        let &(ref lock, _) = &*self.state;
+        lock.lock().unwrap().followed.insert(symbol);
+    }
+
+    /// Stops queueing the ticks of a symbol, discarding its waiting ticks and deduplicator.
+    pub fn unfollow(&self, symbol: &str) {
+        let &(ref lock, _) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        state.followed.remove(symbol);
+        state.dedup.remove(symbol);
+        state.ticks.retain(|&(ref waiting, _)| waiting != symbol);
+    }
+
+    /// Queues a tick of a followed symbol unless it repeats the previous tick of its symbol, discarding ticks
+    /// according to the policy if the queue is full.  Returns `true` if this is the first tick dropped by the policy
+    /// since the processor was last caught up, which is when a warning should be raised.
+    pub fn push_tick(&self, symbol: String, t: Tick) -> bool {
+        let &(ref lock, ref cvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        if !state.followed.contains(&symbol) || !state.dedup(&symbol, t) {
+            return false;
+        }
+        let dropped = state.dropped;
+
+        let waiting_ix = if state.policy == IntakePolicy::CoalesceToLatest {
+            state.ticks.iter().position(|&(ref waiting, _)| *waiting == symbol)
+        } else {
+            None
+        };
+        match waiting_ix {
+            Some(ix) => {
+                state.ticks[ix].1 = t;
+                state.dropped += 1;
+            },
+            None if state.policy == IntakePolicy::DropNewest && state.ticks.len() >= state.capacity => {
+                state.dropped += 1;
+            },
+            None => {
+                state.ticks.push_back((symbol, t));
+                state.trim();
+            },
+        }
+        cvar.notify_one();
+
+        let began_dropping = state.dropped != dropped && !state.dropping;
+        if state.dropped != dropped {
+            state.dropping = true;
+        }
+        began_dropping
+    }
+
+    /// Waits for the next command or tick, returning commands first.  Returns `None` once the queue has been closed
+    /// and everything in it has been taken.
+    pub fn pop(&self) -> Option<Intake> {
+        let &(ref lock, ref cvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        loop {
+            if let Some(intake) = state.take() {
+                return Some(intake);
+            }
+            if state.closed {
+                return None;
+            }
+            state = cvar.wait(state).unwrap();
+        }
+    }
+
+    /// Like `pop`, but returns `Intake::Idle` if nothing arrives within `timeout`.
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<Intake> {
+        let deadline = Instant::now() + timeout;
+        let &(ref lock, ref cvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        loop {
+            if let Some(intake) = state.take() {
+                return Some(intake);
+            }
+            if state.closed {
+                return None;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Some(Intake::Idle);
+            }
+            state = cvar.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Stops accepting messages.  Whatever is already queued can still be taken with `pop`.
+    pub fn close(&self) {
+        let &(ref lock, ref cvar) = &*self.state;
+        lock.lock().unwrap().closed = true;
+        cvar.notify_all();
+    }
+
+    /// Changes the policy and capacity, discarding ticks according to the new policy if too many are waiting.
+    pub fn set_policy(&self, policy: IntakePolicy, capacity: usize) {
+        let &(ref lock, _) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        state.policy = policy;
+        state.capacity = capacity;
+        state.trim();
+    }
+
+    pub fn stats(&self) -> IntakeStats {
+        let &(ref lock, _) = &*self.state;
+        let state = lock.lock().unwrap();
+        IntakeStats {
+            policy: state.policy,
+            capacity: state.capacity,
+            queued: state.ticks.len(),
+            dropped: state.dropped,
+            duplicates: state.dedup.values().map(|dedup| dedup.duplicates()).sum(),
+            unchanged: state.dedup.values().map(|dedup| dedup.unchanged()).sum(),
+        }
+    }
+}
+
+/// Feeds `ticks` through a queue with the given policy to a consumer whose indicator takes 20ms per tick, returning
+/// the ticks that reached the indicator and the final stats.
+#[cfg(test)]
+fn run_slow_consumer(
+    policy: IntakePolicy, capacity: usize, ticks: &[(&str, u64)]
+) -> (Vec<(String, u64)>, IntakeStats, bool) {
+    use std::thread;
+    use tickgrinder_util::trading::indicators::SmaError;
+    use indicators::{Indicator, IndicatorValue};
+
+    struct SlowIndicator;
+
+    impl Indicator for SlowIndicator {
+        fn push(&mut self, t: Tick) -> Result<Option<IndicatorValue>, SmaError> {
+            thread::sleep(Duration::from_millis(20));
+            Ok(Some(IndicatorValue::Value(t.timestamp as f64)))
+        }
+
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        fn params(&self) -> Value {
+            json!({})
+        }
+
+        fn warm_up_complete(&self) -> bool {
+            true
+        }
+    }
+
+    let queue = IntakeQueue::new(policy, capacity, None);
+    queue.follow(String::from("EURUSD"));
+    queue.follow(String::from("USDJPY"));
+    let consumer_queue = queue.clone();
+    let consumer = thread::spawn(move || {
+        let mut indicator = SlowIndicator;
+        let mut processed = Vec::new();
+        while let Some(intake) = consumer_queue.pop() {
+            if let Intake::Tick(symbol, t) = intake {
+                indicator.push(t).unwrap();
+                processed.push((symbol, t.timestamp));
+            }
+        }
+        processed
+    });
+
+    let mut warned = false;
+    for &(symbol, timestamp) in ticks {
+        warned |= queue.push_tick(String::from(symbol), Tick {timestamp: timestamp, bid: 100, ask: 102});
+    }
+    let stats = queue.stats();
+    queue.close();
+    (consumer.join().unwrap(), stats, warned)
+}
+
+/// When the indicators can't keep up, each policy discards the ticks it's supposed to and counts them.
+#[test]
+fn intake_policies() {
+    let ticks: Vec<(&str, u64)> = (1..21).map(|timestamp| ("EURUSD", timestamp)).collect();
+
+    let (processed, stats, warned) = run_slow_consumer(IntakePolicy::DropOldest, 5, &ticks);
+    assert!(warned);
+    assert_eq!(stats.queued, 5);
+    assert_eq!(processed.len() as u64 + stats.dropped, 20);
+    // the waiting ticks are the newest ones
+    let last: Vec<u64> = processed.iter().rev().take(5).map(|&(_, timestamp)| timestamp).collect();
+    assert_eq!(last, vec![20, 19, 18, 17, 16]);
+
+    let (processed, stats, _) = run_slow_consumer(IntakePolicy::DropNewest, 5, &ticks);
+    assert_eq!(processed.len() as u64 + stats.dropped, 20);
+    // everything after the first few ticks is dropped
+    let timestamps: Vec<u64> = processed.iter().map(|&(_, timestamp)| timestamp).collect();
+    assert_eq!(timestamps, (1..(processed.len() as u64 + 1)).collect::<Vec<_>>());
+    assert!(processed.len() <= 7);
+
+    let interleaved: Vec<(&str, u64)> = (1..21)
+        .map(|timestamp| (if timestamp % 2 == 0 { "EURUSD" } else { "USDJPY" }, timestamp))
+        .collect();
+    let (processed, stats, warned) = run_slow_consumer(IntakePolicy::CoalesceToLatest, 5, &interleaved);
+    assert!(warned);
+    // only the latest tick of each symbol was waiting
+    assert_eq!(stats.queued, 2);
+    assert_eq!(processed.len() as u64 + stats.dropped, 20);
+    let last: Vec<(String, u64)> = processed.iter().rev().take(2).cloned().collect();
+    assert!(last.contains(&(String::from("EURUSD"), 20)));
+    assert!(last.contains(&(String::from("USDJPY"), 19)));
+
+    assert_eq!(IntakePolicy::from_str("drop_newest"), Ok(IntakePolicy::DropNewest));
+    assert!(IntakePolicy::from_str("drop_everything").is_err());
+}
+
+/// Commands skip ahead of waiting ticks, and shrinking the queue discards ticks by the new policy.
+#[test]
+fn intake_commands_and_resizing() {
+    let queue = IntakeQueue::new(IntakePolicy::DropOldest, 10, None);
+    queue.follow(String::from("EURUSD"));
+    for timestamp in 1..7 {
+        assert!(!queue.push_tick(String::from("EURUSD"), Tick {timestamp: timestamp, bid: 100, ask: 102}));
+    }
+    queue.push_command(String::from("{}"));
+    assert_eq!(queue.pop(), Some(Intake::Command(String::from("{}"))));
+
+    queue.set_policy(IntakePolicy::DropNewest, 3);
+    let stats = queue.stats();
+    assert_eq!((stats.policy, stats.capacity, stats.queued, stats.dropped), (IntakePolicy::DropNewest, 3, 3, 3));
+    assert_eq!(queue.pop(), Some(Intake::Tick(String::from("EURUSD"), Tick {timestamp: 1, bid: 100, ask: 102})));
+
+    // waiting with a timeout gives up once the queue is empty
+    let timeout = Duration::from_millis(10);
+    assert!(queue.pop_timeout(timeout).is_some());
+    assert!(queue.pop_timeout(timeout).is_some());
+    assert_eq!(queue.pop_timeout(timeout), Some(Intake::Idle));
+    queue.close();
+    assert_eq!(queue.pop_timeout(timeout), None);
+}
+
+/// Repeated ticks are dropped per symbol before they're queued and don't count as dropped by the policy.
+#[test]
+fn intake_deduplication() {
+    let tick = |timestamp: u64, bid: usize| Tick {timestamp: timestamp, bid: bid, ask: bid + 2};
+    let queue = IntakeQueue::new(IntakePolicy::DropOldest, 10, Some(100));
+    queue.follow(String::from("EURUSD"));
+    queue.follow(String::from("USDJPY"));
+    queue.push_tick(String::from("EURUSD"), tick(1, 100));
+    queue.push_tick(String::from("EURUSD"), tick(1, 100));
+    // the same tick is fine for another symbol
+    queue.push_tick(String::from("USDJPY"), tick(1, 100));
+    queue.push_tick(String::from("EURUSD"), tick(50, 100));
+    queue.push_tick(String::from("EURUSD"), tick(60, 101));
+
+    let stats = queue.stats();
+    assert_eq!((stats.queued, stats.dropped, stats.duplicates, stats.unchanged), (3, 0, 1, 1));
+    queue.close();
+    let mut timestamps = Vec::new();
+    while let Some(Intake::Tick(symbol, t)) = queue.pop() {
+        timestamps.push((symbol, t.timestamp));
+    }
+    let expected = vec![(String::from("EURUSD"), 1), (String::from("USDJPY"), 1), (String::from("EURUSD"), 60)];
+    assert_eq!(timestamps, expected);
+}
+
+/// Ticks of symbols that aren't followed never reach the queue, so they can't push out the ticks of followed ones.
+#[test]
+fn intake_unfollowed_symbols() {
+    let tick = |timestamp: u64| Tick {timestamp: timestamp, bid: 100 + timestamp as usize, ask: 102};
+    let queue = IntakeQueue::new(IntakePolicy::DropOldest, 2, None);
+    queue.follow(String::from("EURUSD"));
+    queue.push_tick(String::from("EURUSD"), tick(1));
+    for timestamp in 1..10 {
+        assert!(!queue.push_tick(String::from("USDJPY"), tick(timestamp)));
+    }
+    queue.push_tick(String::from("EURUSD"), tick(2));
+
+    let stats = queue.stats();
+    assert_eq!((stats.queued, stats.dropped, stats.duplicates, stats.unchanged), (2, 0, 0, 0));
+    assert_eq!(queue.pop_timeout(Duration::from_millis(10)), Some(Intake::Tick(String::from("EURUSD"), tick(1))));
+
+    // unfollowing a symbol discards its waiting ticks and ignores any more of them
+    queue.unfollow("EURUSD");
+    assert_eq!(queue.stats().queued, 0);
+    queue.push_tick(String::from("EURUSD"), tick(3));
+    assert_eq!(queue.pop_timeout(Duration::from_millis(10)), Some(Intake::Idle));
+}
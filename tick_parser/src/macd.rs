@@ -0,0 +1,216 @@
+//! Live moving average convergence divergence, built from the time-decayed EMAs.
+
+#[allow(unused_imports)]
+use test;
+
+use serde_json::Value;
+
+use tickgrinder_util::trading::tick::Tick;
+use tickgrinder_util::trading::indicators::{CrossoverEvent, SmaError};
+
+use ema::{Ema, decay};
+use indicators::{Indicator, IndicatorValue};
+#[cfg(test)]
+use indicators::fixture_ticks;
+
+/// Default period of the fast EMA in milliseconds
+pub const DEFAULT_FAST_PERIOD: u64 = 12;
+/// Default period of the slow EMA in milliseconds
+pub const DEFAULT_SLOW_PERIOD: u64 = 26;
+/// Default period of the signal line's EMA in milliseconds
+pub const DEFAULT_SIGNAL_PERIOD: u64 = 9;
+
+/// Fills in the default for any of the (fast, slow, signal) periods that weren't supplied.
+pub fn macd_periods(fast: Option<u64>, slow: Option<u64>, signal: Option<u64>) -> (u64, u64, u64) {
+    (
+        fast.unwrap_or(DEFAULT_FAST_PERIOD),
+        slow.unwrap_or(DEFAULT_SLOW_PERIOD),
+        signal.unwrap_or(DEFAULT_SIGNAL_PERIOD),
+    )
+}
+
+/// The output of a MACD after a tick
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MacdValues {
+    /// The fast EMA minus the slow EMA
+    pub macd: f64,
+    /// EMA of the MACD line
+    pub signal: f64,
+    /// The MACD line minus the signal line
+    pub histogram: f64,
+}
+
+/// The difference between a fast and slow EMA of the mid price along with a signal line that is an EMA of that
+/// difference.  The signal line decays with the time between ticks the same way the EMAs do, so it also trails the
+/// MACD line by one tick.
+pub struct Macd {
+    pub fast: Ema,
+    pub slow: Ema,
+    pub signal_period: u64,
+    /// The values after the last tick, or `None` if no ticks have been pushed yet
+    pub values: Option<MacdValues>,
+    prev_values: Option<MacdValues>,
+    last_timestamp: u64,
+}
+
+impl Macd {
+    pub fn new(fast_period: u64, slow_period: u64, signal_period: u64) -> Macd {
+        assert!(signal_period > 0, "MACD signal period must be greater than zero!");
+        Macd {
+            fast: Ema::new(fast_period),
+            slow: Ema::new(slow_period),
+            signal_period: signal_period,
+            values: None,
+            prev_values: None,
+            last_timestamp: 0,
+        }
+    }
+
+    /// Adds a tick and returns the new MACD, signal, and histogram values.  Ticks that aren't newer than the
+    /// previous one are refused and leave the MACD unchanged.
+    pub fn push(&mut self, t: &Tick) -> Result<MacdValues, SmaError> {
+        // both EMAs refuse the same ticks, so a refused tick hasn't changed either of them
+        let fast = self.fast.push_f64(t)?;
+        let slow = self.slow.push_f64(t)?;
+        let macd = fast - slow;
+
+        let signal = match self.values {
+            Some(last) => {
+                let decay = decay(t.timestamp - self.last_timestamp, self.signal_period);
+                last.signal * decay + last.macd * (1. - decay)
+            },
+            None => macd,
+        };
+        let values = MacdValues {
+            macd: macd,
+            signal: signal,
+            histogram: macd - signal,
+        };
+
+        self.prev_values = self.values;
+        self.values = Some(values);
+        self.last_timestamp = t.timestamp;
+        Ok(values)
+    }
+
+    /// Returns a `CrossoverEvent` if the MACD line crossed the signal line as a result of the last tick pushed.
+    /// The event's fast value is the MACD line and its slow value is the signal line.
+    pub fn detect_crossover(&self) -> Option<CrossoverEvent> {
+        let (prev, cur) = match (self.prev_values, self.values) {
+            (Some(prev), Some(cur)) => (prev, cur),
+            _ => return None,
+        };
+
+        let timestamp = self.last_timestamp;
+        if prev.macd <= prev.signal && cur.macd > cur.signal {
+            Some(CrossoverEvent::BullishCross{timestamp: timestamp, fast_value: cur.macd, slow_value: cur.signal})
+        } else if prev.macd >= prev.signal && cur.macd < cur.signal {
+            Some(CrossoverEvent::BearishCross{timestamp: timestamp, fast_value: cur.macd, slow_value: cur.signal})
+        } else {
+            None
+        }
+    }
+
+    /// Returns the (fast, slow, signal) periods of the MACD
+    pub fn periods(&self) -> (u64, u64, u64) {
+        (self.fast.period, self.slow.period, self.signal_period)
+    }
+}
+
+impl Indicator for Macd {
+    fn push(&mut self, t: Tick) -> Result<Option<IndicatorValue>, SmaError> {
+        Ok(Some(IndicatorValue::Macd(Macd::push(self, &t)?)))
+    }
+
+    fn name(&self) -> &str {
+        "macd"
+    }
+
+    fn params(&self) -> Value {
+        let (fast, slow, signal) = self.periods();
+        json!({"fast_period_ms": fast, "slow_period_ms": slow, "signal_period_ms": signal})
+    }
+
+    /// Complete once the ticks span the periods of both EMAs and the signal line.
+    fn warm_up_complete(&self) -> bool {
+        self.fast.warm_up_complete() && self.slow.warm_up_complete() && self.fast.span() >= self.signal_period
+    }
+
+    /// The MACD line crossing its signal line
+    fn crossover(&self) -> Option<CrossoverEvent> {
+        self.detect_crossover()
+    }
+}
+
+/// Values of a MACD with periods of 1200, 2600, and 900ms over the fixture ticks, calculated separately by
+/// running the time-decayed EMA formula over the mid prices and then over the resulting MACD line.
+#[test]
+fn macd_fixture_values() {
+    let expected = [
+        (0.0, 0.0),
+        // the first price was held for the whole interval so both EMAs still equal it
+        (0.0, 0.0),
+        (-5.660626365775073, 0.0),
+        (-6.122453369344925, -0.5952753358834978),
+        (-4.898444792661323, -5.925276856950142),
+        (-4.892544803130747, -5.924136566046526),
+        (-1.5322200026461985, -5.554716548164954),
+        (-5.214799289145915, -1.9681300017009709),
+    ];
+
+    let mut macd = Macd::new(1200, 2600, 900);
+    let mut crosses = Vec::new();
+    for (t, &(macd_value, signal)) in fixture_ticks().iter().zip(expected.iter()) {
+        let values = macd.push(t).unwrap();
+        assert!((values.macd - macd_value).abs() < 1e-9, "{} != {} at {}", values.macd, macd_value, t.timestamp);
+        assert!((values.signal - signal).abs() < 1e-9, "{} != {} at {}", values.signal, signal, t.timestamp);
+        assert!((values.histogram - (macd_value - signal)).abs() < 1e-9);
+
+        match macd.detect_crossover() {
+            Some(CrossoverEvent::BullishCross{timestamp, ..}) => crosses.push((timestamp, true)),
+            Some(CrossoverEvent::BearishCross{timestamp, ..}) => crosses.push((timestamp, false)),
+            None => (),
+        }
+    }
+    assert_eq!(crosses, vec![(1500, false), (4600, true), (7000, false)]);
+
+    let last = macd.values;
+    assert_eq!(macd.push(&Tick {timestamp: 7000, bid: 1, ask: 1}), Err(SmaError::EqualTimestamp));
+    assert_eq!(macd.values, last);
+}
+
+/// The registry reports the MACD line crossing its signal line under the MACD's id, and only for the ticks that
+/// the MACD accepted.
+#[test]
+fn macd_crossings_taken_from_registry() {
+    use indicators::IndicatorRegistry;
+
+    let mut registry = IndicatorRegistry::new();
+    let params = json!({"fast_period_ms": 1200, "slow_period_ms": 2600, "signal_period_ms": 900});
+    let id = registry.add("macd", &params, None, false).unwrap();
+    registry.add("macd", &json!({}), None, false).unwrap();
+
+    let mut crossings = Vec::new();
+    for t in fixture_ticks() {
+        registry.push_all(&t);
+        // a repeated tick is refused, so it can't report the same crossing again
+        registry.push_all(&t);
+        crossings.extend(registry.take_crossings().into_iter().filter(|&(crossing_id, _)| crossing_id == id));
+    }
+    let timestamps: Vec<u64> = crossings.iter().map(|&(_, event)| match event {
+        CrossoverEvent::BullishCross{timestamp, ..} | CrossoverEvent::BearishCross{timestamp, ..} => timestamp,
+    }).collect();
+    assert_eq!(timestamps, vec![1500, 4600, 7000]);
+    assert!(registry.take_crossings().is_empty());
+}
+
+#[bench]
+fn macd_calculation(b: &mut test::Bencher) {
+    let mut macd = Macd::new(DEFAULT_FAST_PERIOD * 1000, DEFAULT_SLOW_PERIOD * 1000, DEFAULT_SIGNAL_PERIOD * 1000);
+    let mut timestamp = 1;
+
+    b.iter(|| {
+        macd.push(&Tick {bid: 1239123 + (timestamp % 7) as usize, ask: 1239125, timestamp: timestamp}).unwrap();
+        timestamp += 20;
+    });
+}
@@ -1,28 +1,54 @@
 // Algobot 4 Tick Processor
 // Casey Primozic, 2016-2016
 
-#![feature(custom_derive, plugin, test, conservative_impl_trait, slice_patterns)]
+#![feature(custom_derive, plugin, test, conservative_impl_trait, slice_patterns, i128_type)]
 
 extern crate redis;
 extern crate futures;
+#[macro_use]
 extern crate serde_json;
 extern crate postgres;
 extern crate test;
 extern crate uuid;
 extern crate tickgrinder_util;
+extern crate rayon;
+#[cfg(test)]
+extern crate rand;
 
 mod transport;
 mod processor;
+mod sma;
+mod ema;
+mod rsi;
+mod macd;
+mod candles;
+mod renko;
+mod atr;
+mod stddev;
+mod indicators;
+mod indicator_output;
+mod crossover;
+mod feed_health;
+mod tick_sink;
+mod intake;
+mod relay;
+mod hooks;
 
 use std::env;
+use std::thread;
+use std::time::Duration;
 
 use futures::stream::Stream;
 use uuid::Uuid;
 
-use processor::Processor;
+use processor::{Processor, tick_channel, RELAY_POLL_MS};
+use intake::Intake;
+use tickgrinder_util::trading::tick::Tick;
 use tickgrinder_util::transport::postgres::{get_client, reset_db};
-use tickgrinder_util::transport::redis::sub_multiple;
-use tickgrinder_util::transport::commands::{Command, send_command};
+use tickgrinder_util::transport::redis::sub_multiple_patterns;
+use tickgrinder_util::transport::commands::{Command, Response, send_command};
+use tickgrinder_util::transport::command_server::CommandServer;
+use tickgrinder_util::transport::tracing;
 use tickgrinder_util::conf::CONF;
 
 struct TickProcessor {
@@ -36,51 +62,97 @@ impl TickProcessor {
         }
     }
 
-    /// Subscribes to Command channels
-    pub fn listen(&self, symbol: String) {
+    /// Subscribes to Command channels and the tick channels of every symbol.  Ticks of symbols that aren't being
+    /// followed are dropped by the intake queue before they're queued, so symbols can be added and removed without
+    /// changing the subscriptions.  Messages are
+    /// received on their own thread and wait in the processor's intake queue until they're processed.
+    pub fn listen(&self, symbol: String, extra_symbols: &[String]) {
         let control_channel = CONF.redis_control_channel;
         let uuid_string = self.uuid.hyphenated().to_string();
 
         let mut processor = Processor::new(symbol, &self.uuid);
+        for symbol in extra_symbols {
+            if let Response::Error{status} = processor.add_symbol(symbol.clone()) {
+                println!("Unable to follow {}: {}", symbol, status);
+            }
+        }
 
-        let rx = sub_multiple(
-            CONF.redis_host, &[control_channel, uuid_string.as_str()]
+        let tick_pattern = tick_channel("*");
+        let rx = sub_multiple_patterns(
+            CONF.redis_host, &[control_channel, uuid_string.as_str()], &[tick_pattern.as_str()]
         );
 
+        let intake = processor.intake.clone();
+        let mut cs = CommandServer::new(self.uuid, "Tick Processor");
+        thread::spawn(move || {
+            let tick_prefix = tick_channel("");
+            for res in rx.wait() {
+                let (channel, message) = res.unwrap();
+                if channel == uuid_string.as_str()
+                       || channel == control_channel {
+                    intake.push_command(message);
+                } else if channel.starts_with(tick_prefix.as_str()) {
+                    let t = match serde_json::from_str::<Tick>(&message) {
+                        Ok(t) => t,
+                        Err(err) => {
+                            println!("Unable to parse tick {} from {}: {:?}", message, channel, err);
+                            continue;
+                        },
+                    };
+                    if intake.push_tick(String::from(&channel[tick_prefix.len()..]), t) {
+                        let stats = intake.stats();
+                        cs.warning(Some("TickIntake"), &format!(
+                            "The Tick Processor has fallen behind; {} ticks are waiting and ticks are being dropped \
+                            by the {:?} policy.", stats.queued, stats.policy
+                        ));
+                    }
+                } else {
+                    println!(
+                        "Unexpected channel/message combination received: {},{}",
+                        channel,
+                        message
+                    );
+                }
+            }
+            intake.close();
+        });
+
         let _ = send_command(&Command::Ready{
             instance_type: "Tick Processor".to_string(),
             uuid: self.uuid,
+            depends_on: Vec::new(),
         }.wrap(), &processor.redis_client, CONF.redis_control_channel);
 
-        for res in rx.wait() {
-            let (channel, message) = res.unwrap();
-            if channel == uuid_string.as_str()
-                   || channel == control_channel {
-                processor.execute_command(CONF.redis_responses_channel, message)
-            } else {
-                println!(
-                    "Unexpected channel/message combination received: {},{}",
-                    channel,
-                    message
-                );
+        let intake = processor.intake.clone();
+        // ticks held back by the relays and partial batches waiting to be written to Postgres have to be sent once
+        // their feeds go quiet, even if nothing else arrives
+        while let Some(msg) = intake.pop_timeout(Duration::from_millis(RELAY_POLL_MS)) {
+            match msg {
+                Intake::Command(cmd) => processor.execute_command(CONF.redis_responses_channel, cmd),
+                Intake::Tick(symbol, t) => processor.process_symbol(&symbol, t),
+                Intake::Idle => (),
             }
+            processor.poll_relays();
+            processor.flush_due_batches();
         }
     }
 }
 
 fn main() {
-    // ./tick_processor uuid symbol
+    // ./tick_processor uuid symbol [extra symbols...]
     let args = env::args().collect::<Vec<String>>();
     let uuid: Uuid;
     let symbol: String;
+    let extra_symbols: Vec<String>;
 
     match *args.as_slice() {
-        [_, ref uuid_str, ref symbol_str] => {
+        [_, ref uuid_str, ref symbol_str, ref extra..] => {
             uuid = Uuid::parse_str(uuid_str.as_str())
                 .expect("Unable to parse Uuid from supplied argument");
             symbol = symbol_str.to_string();
+            extra_symbols = extra.to_vec();
         }
-        _ => panic!("Wrong number of arguments provided!  Usage: ./tick_processor [uuid] [symbol]")
+        _ => panic!("Wrong number of arguments provided!  Usage: ./tick_processor [uuid] [symbol] [symbol...]")
     }
 
     if CONF.reset_db_on_load {
@@ -89,9 +161,10 @@ fn main() {
         println!("Database reset");
     }
 
+    tracing::init_from_conf("Tick Processor");
     let tp = TickProcessor::new(uuid);
     // Start the listeners for everything and blocks
-    tp.listen(symbol);
+    tp.listen(symbol, &extra_symbols);
     // the Tick Processor will now block until it receives messages from the platform that inform
     // it to subscribe to a broker's tick stream and start processing ticks.
 }
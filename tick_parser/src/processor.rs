@@ -4,25 +4,82 @@
 // possible, so non-essential operations should be deferred asynchronously.
 
 use std::{thread, process};
-use std::time::Duration;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
 use std::env;
+use std::str::FromStr;
 
 use redis;
+use postgres::Connection;
+use serde_json::{self, Map, Value};
 use uuid::Uuid;
 use tickgrinder_util::transport::commands::*;
+use tickgrinder_util::transport::command_server::set_log_level_response;
 use tickgrinder_util::trading::datafield::DataField;
-use tickgrinder_util::trading::tick::Tick;
-use tickgrinder_util::transport::postgres::{get_client, init_tick_table};
+use tickgrinder_util::trading::tick::{Tick, TickValidator, TickVerdict, TickVerdictCounts, TimestampNormalizer};
+use tickgrinder_util::trading::bar::GapPolicy;
+use tickgrinder_util::transport::postgres::{get_client, init_tick_table, init_candle_table};
 use tickgrinder_util::transport::query_server::QueryServer;
-use tickgrinder_util::transport::redis::get_client as get_redis_client;
+use tickgrinder_util::transport::redis::{get_client as get_redis_client, publish};
 use tickgrinder_util::conf::CONF;
 
+use macd::macd_periods;
+use indicators::{IndicatorRegistry, IndicatorPublisher};
+use candles::{CandleFeed, HeikinAshiFeed};
+use renko::RenkoFeed;
+use tick_sink::TickSink;
+use intake::{IntakeQueue, IntakePolicy};
+use relay::{TickRelay, SampleMode, DEFAULT_QUIET_MS};
+use hooks::Hook;
+
+/// How often the processing loop checks whether the feeds of relayed symbols have gone quiet, in milliseconds
+pub const RELAY_POLL_MS: u64 = 50;
+
+/// The ticks, indicators, and candles of one of the symbols that a Tick Processor follows
+pub struct SymbolProcessor {
+    pub symbol: String,
+    /// The most recent ticks processed, up to `CONF.processor_tick_capacity` of them
+    pub ticks: DataField<Tick>,
+    /// Every indicator, added by kind and parameters or by the commands for specific kinds
+    pub indicators: IndicatorRegistry,
+    /// Publishes the values of the indicators in `indicators`
+    pub indicator_publisher: IndicatorPublisher,
+    pub candles: Vec<CandleFeed>,
+    pub renko: Vec<RenkoFeed>,
+    pub heikin_ashi: Vec<HeikinAshiFeed>,
+    /// The channels that the crossings reported by some of the indicators are published to, keyed by indicator id
+    pub crossing_channels: HashMap<Uuid, String>,
+    /// Publish an event when a condition on the indicators starts being met
+    pub hooks: Vec<Hook>,
+    /// Records incoming ticks to Postgres while enabled by `RecordTicks`
+    pub tick_sink: Option<TickSink>,
+    /// Republishes the ticks at a lower rate while enabled by `SetTickSampling`
+    pub relay: Option<TickRelay>,
+    /// Rejects crossed, implausible, and out-of-order ticks before they reach the indicators
+    pub validator: TickValidator,
+    /// Converts the timestamps of incoming ticks to milliseconds
+    pub timestamps: TimestampNormalizer,
+}
+
+/// Follows one or more symbols, sharing the Redis and Postgres connections between them.  Commands that don't name
+/// a symbol apply to the primary one that the Tick Processor was spawned with, which can't be removed.
 pub struct Processor {
     pub uuid: Uuid,
+    /// The primary symbol
     pub symbol: String,
-    pub ticks: DataField<Tick>,
     pub qs: QueryServer,
-    pub redis_client: redis::Client
+    pub redis_client: redis::Client,
+    /// Every followed symbol, including the primary one
+    pub symbols: HashMap<String, SymbolProcessor>,
+    /// Holds incoming ticks and commands until they're processed
+    pub intake: IntakeQueue,
+    /// Where the values of every symbol's indicators are sent, if `SetIndicatorOutput` changed it for all of them
+    pub indicator_dest: Option<IndicatorDest>,
+}
+
+/// Returns the channel that the ticks of a symbol are read from.
+pub fn tick_channel(symbol: &str) -> String {
+    format!("ticks_{}", symbol)
 }
 
 impl Processor {
@@ -31,27 +88,173 @@ impl Processor {
         let pg_client = get_client().expect("Could not connect to Postgres");
 
         println!("Successfully connected to Postgres");
-        let _ = init_tick_table(symbol.as_str(), &pg_client, CONF.postgres_user);
-
+        let mut symbols = HashMap::new();
+        symbols.insert(symbol.clone(), SymbolProcessor::new(symbol.clone(), &pg_client));
+        let intake = IntakeQueue::from_conf();
+        intake.follow(symbol.clone());
         Processor {
             uuid: *uuid,
             symbol: symbol,
-            ticks: DataField::new(),
             qs: QueryServer::new(10),
-            redis_client: get_redis_client(CONF.redis_host)
+            redis_client: get_redis_client(CONF.redis_host),
+            symbols: symbols,
+            intake: intake,
+            indicator_dest: None,
         }
     }
 
-    // Called for each new tick received by the tick processor
+    /// Returns the state of the primary symbol.
+    pub fn primary(&mut self) -> &mut SymbolProcessor {
+        self.symbols.get_mut(&self.symbol).expect("The primary symbol was removed")
+    }
+
+    /// Returns every followed symbol in alphabetical order.
+    pub fn symbol_list(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = self.symbols.keys().cloned().collect();
+        symbols.sort();
+        symbols
+    }
+
+    // Called for each new tick of the primary symbol received by the tick processor
     pub fn process(&mut self, t: Tick) {
+        let symbol = self.symbol.clone();
+        self.process_symbol(&symbol, t);
+    }
+
+    /// Processes a tick of one of the followed symbols.  Ticks of other symbols are ignored.
+    pub fn process_symbol(&mut self, symbol: &str, t: Tick) {
+        if let Some(sp) = self.symbols.get_mut(symbol) {
+            sp.process(t, &self.redis_client, &mut self.qs);
+        }
+    }
+
+    /// Starts following another symbol and lets the spawner know about it.
+    pub fn add_symbol(&mut self, symbol: String) -> Response {
+        if symbol.is_empty() {
+            return Response::Error{status: String::from("Symbols can't be empty.")};
+        }
+        if self.symbols.contains_key(&symbol) {
+            return Response::Error{status: format!("{} is already being followed.", symbol)};
+        }
+        let pg_client = match get_client() {
+            Ok(client) => client,
+            Err(err) => return Response::Error{status: format!("Unable to connect to Postgres: {:?}", err)},
+        };
+        let mut sp = SymbolProcessor::new(symbol.clone(), &pg_client);
+        if let Some(ref dest) = self.indicator_dest {
+            let res = sp.indicator_publisher.open(dest.clone())
+                .and_then(|output| sp.indicator_publisher.set_output(output));
+            if let Err(err) = res {
+                return Response::Error{status: format!("Unable to open the indicator output of {}: {}", symbol, err)};
+            }
+        }
+        self.intake.follow(symbol.clone());
+        self.symbols.insert(symbol, sp);
+        self.announce_symbols();
+        Response::Ok
+    }
+
+    /// Stops following a symbol after flushing its candles and recorded ticks, and lets the spawner know about it.
+    pub fn remove_symbol(&mut self, symbol: &str) -> Response {
+        if symbol == self.symbol {
+            return Response::Error{status: format!("{} is the primary symbol and can't be removed.", symbol)};
+        }
+        match self.symbols.remove(symbol) {
+            Some(mut sp) => {
+                self.intake.unfollow(symbol);
+                sp.flush(&self.redis_client, &mut self.qs);
+                self.announce_symbols();
+                Response::Ok
+            },
+            None => Response::Error{status: format!("{} isn't being followed.", symbol)},
+        }
+    }
+
+    /// Sends the list of followed symbols to the control channel so that the spawner knows which symbols this
+    /// Tick Processor owns.
+    pub fn announce_symbols(&self) {
+        let cmd = Command::TickProcessorSymbols{uuid: self.uuid, symbols: self.symbol_list()};
+        let _ = send_command(&cmd.wrap(), &self.redis_client, CONF.redis_control_channel);
+    }
+
+    /// Sends indicator values to `dest` from now on.  If `indicator` is supplied, only the values of the indicator
+    /// with that id are, whichever symbol it belongs to.  Otherwise the values of every indicator are, and symbols
+    /// followed later start out sending theirs there as well.  The values that the replaced outputs were holding back
+    /// are written first.
+    pub fn set_indicator_output(&mut self, dest: IndicatorDest, indicator: Option<Uuid>) -> Response {
+        let res = match indicator {
+            Some(id) => match self.symbols.values_mut().find(|sp| sp.indicators.contains(id)) {
+                Some(sp) => sp.indicator_publisher.open(dest)
+                    .and_then(|output| sp.indicator_publisher.set_indicator_output(id, output)),
+                None => Err(format!("No indicator with the id {} is being calculated.", id)),
+            },
+            None => {
+                // every output is opened before any are replaced so that a failure leaves them all as they were
+                let mut outputs = Vec::new();
+                for (symbol, sp) in self.symbols.iter() {
+                    match sp.indicator_publisher.open(dest.clone()) {
+                        Ok(output) => outputs.push((symbol.clone(), output)),
+                        Err(err) => return Response::Error{status: err},
+                    }
+                }
+                self.indicator_dest = Some(dest);
+                let mut res = Ok(());
+                for (symbol, output) in outputs {
+                    res = res.and(self.symbols.get_mut(&symbol).unwrap().indicator_publisher.set_output(output));
+                }
+                res
+            },
+        };
+        match res {
+            Ok(()) => Response::Ok,
+            Err(err) => Response::Error{status: err},
+        }
+    }
+
+    /// Returns the snapshots of the indicators of every symbol, or of only `symbol` if it's supplied, as a JSON
+    /// object keyed by indicator id.  Each entry also holds the symbol of its indicator and where its values are
+    /// sent.
+    pub fn indicator_snapshot(&self, symbol: Option<&str>) -> Result<Value, String> {
+        let symbols = match symbol {
+            Some(symbol) if !self.symbols.contains_key(symbol) => {
+                return Err(format!("{} isn't being followed.", symbol));
+            },
+            Some(symbol) => vec![String::from(symbol)],
+            None => self.symbol_list(),
+        };
 
+        let mut snapshot = Map::new();
+        for symbol in symbols {
+            let sp = &self.symbols[&symbol];
+            for (id, mut entry) in sp.indicators.snapshot() {
+                if let Value::Object(ref mut map) = entry {
+                    map.insert(String::from("symbol"), Value::String(symbol.clone()));
+                    if let Ok(uuid) = Uuid::parse_str(&id) {
+                        let dest = serde_json::to_value(sp.indicator_publisher.dest(uuid))
+                            .map_err(|err| format!("Unable to serialize an indicator output: {:?}", err))?;
+                        map.insert(String::from("output"), dest);
+                    }
+                }
+                snapshot.insert(id, entry);
+            }
+        }
+        Ok(Value::Object(snapshot))
     }
 
     /// Handle an incoming Command, take action, and return a Response
     pub fn execute_command(&mut self, res_channel: &str, raw_cmd: String) {
         let wrapped_cmd: WrappedCommand = parse_wrapped_command(raw_cmd);
+        let _span = wrapped_cmd.span("Tick Processor");
         let res = match wrapped_cmd.cmd {
-            Command::Shutdown => unimplemented!(),
+            Command::Shutdown => {
+                // candles and recorded ticks still in progress would otherwise be lost
+                self.flush();
+                thread::spawn(|| {
+                    thread::sleep(Duration::from_secs(3));
+                    process::exit(0);
+                });
+                Response::Info{info: "Shutting down in 3 seconds...".to_string()}
+            },
             Command::Kill => {
                 // initiate suicide from another thread after a 3-second timeout
                 thread::spawn(|| {
@@ -67,12 +270,414 @@ impl Processor {
             Command::Type => {
                 Response::Info{info: "Tick Processor".to_string()}
             },
+            Command::SetLogLevel{uuid, level} => {
+                if uuid != self.uuid {
+                    return;
+                }
+                set_log_level_response(&level)
+            },
+            Command::AddSymbol{symbol} => self.add_symbol(symbol),
+            Command::RemoveSymbol{symbol} => self.remove_symbol(&symbol),
+            Command::ListIndicators => {
+                let mut indicators = Vec::new();
+                for symbol in self.symbol_list() {
+                    if let Value::Array(entries) = self.symbols[&symbol].indicators.list() {
+                        for mut entry in entries {
+                            if let Value::Object(ref mut map) = entry {
+                                map.insert(String::from("symbol"), Value::String(symbol.clone()));
+                            }
+                            indicators.push(entry);
+                        }
+                    }
+                }
+                Response::Info{info: Value::Array(indicators).to_string()}
+            },
+            Command::IndicatorSnapshot{symbol} => {
+                match self.indicator_snapshot(symbol.as_ref().map(String::as_str)) {
+                    Ok(snapshot) => Response::Info{info: snapshot.to_string()},
+                    Err(status) => Response::Error{status: status},
+                }
+            },
+            Command::GetTickValidationCounts => {
+                let counts: BTreeMap<&String, &TickVerdictCounts> = self.symbols.iter()
+                    .map(|(symbol, sp)| (symbol, sp.validator.counts()))
+                    .collect();
+                match serde_json::to_string(&counts) {
+                    Ok(info) => Response::Info{info: info},
+                    Err(err) => Response::Error{status: format!("Unable to serialize the tick counts: {:?}", err)},
+                }
+            },
+            Command::SetTickIntakePolicy{policy, capacity} => match IntakePolicy::from_str(&policy) {
+                Ok(policy) => {
+                    self.intake.set_policy(policy, capacity);
+                    Response::Ok
+                },
+                Err(status) => Response::Error{status: status},
+            },
+            Command::GetTickIntakeStats => Response::Info{info: self.intake.stats().to_json().to_string()},
+            Command::SetIndicatorOutput{dest, indicator} => self.set_indicator_output(dest, indicator),
+            cmd => self.execute_symbol_command(cmd),
+        };
+
+        let wr = res.wrap(wrapped_cmd.uuid);
+        let _ = send_response(&wr, &self.redis_client, res_channel);
+    }
+
+    /// Handles a command that applies to a single symbol, which is the primary one unless the command names another.
+    pub fn execute_symbol_command(&mut self, cmd: Command) -> Response {
+        let symbol = command_symbol(&cmd).unwrap_or_else(|| self.symbol.clone());
+        match self.symbols.get_mut(&symbol) {
+            Some(sp) => {
+                let res = sp.execute(cmd, &self.redis_client);
+                // removing an indicator can remove the crossovers that depend on it as well
+                let indicators = &sp.indicators;
+                sp.indicator_publisher.retain_outputs(|id| indicators.contains(id));
+                sp.crossing_channels.retain(|&id, _| indicators.contains(id));
+                res
+            },
+            None => Response::Error{status: format!("{} isn't being followed.", symbol)},
+        }
+    }
+
+    /// Relays the ticks that were held back by the relays of symbols whose feeds have gone quiet.
+    pub fn poll_relays(&mut self) {
+        let now = Instant::now();
+        for sp in self.symbols.values_mut() {
+            if let Some(ref mut relay) = sp.relay {
+                if let Some(t) = relay.poll(now) {
+                    relay.emit(&t, &self.redis_client);
+                }
+            }
+        }
+    }
+
+    /// Writes the recorded ticks and indicator values of every symbol that have been waiting for longer than the
+    /// flush interval, so that partial batches are written while the feeds are quiet.
+    pub fn flush_due_batches(&mut self) {
+        for sp in self.symbols.values_mut() {
+            if let Some(ref mut sink) = sp.tick_sink {
+                sink.flush_if_due();
+            }
+            sp.indicator_publisher.flush_if_due();
+        }
+    }
+
+    /// Publishes and stores the candles of every symbol that are still in progress as if their periods had ended
+    /// and writes any ticks that are waiting to be recorded.
+    pub fn flush(&mut self) {
+        for sp in self.symbols.values_mut() {
+            sp.flush(&self.redis_client, &mut self.qs);
+        }
+    }
+}
+
+/// Returns the symbol that a command applies to if it names one.
+fn command_symbol(cmd: &Command) -> Option<String> {
+    match *cmd {
+        Command::RegisterCrossover{ref symbol, ..} |
+        Command::AddSMAPeriod{ref symbol, ..} |
+        Command::RemoveSMAPeriod{ref symbol, ..} |
+        Command::AddEma{ref symbol, ..} |
+        Command::RemoveEma{ref symbol, ..} |
+        Command::AddRsi{ref symbol, ..} |
+        Command::RemoveRsi{ref symbol, ..} |
+        Command::AddMacd{ref symbol, ..} |
+        Command::RemoveMacd{ref symbol, ..} |
+        Command::AddAtr{ref symbol, ..} |
+        Command::RemoveAtr{ref symbol, ..} |
+        Command::AddCandles{ref symbol, ..} |
+        Command::RemoveCandles{ref symbol, ..} |
+        Command::AddRenko{ref symbol, ..} |
+        Command::RemoveRenko{ref symbol, ..} |
+        Command::AddHeikinAshi{ref symbol, ..} |
+        Command::RemoveHeikinAshi{ref symbol, ..} |
+        Command::RecordTicks{ref symbol, ..} |
+        Command::SetTickSampling{ref symbol, ..} |
+        Command::AddIndicator{ref symbol, ..} |
+        Command::AddHook{ref symbol, ..} |
+        Command::RemoveHook{ref symbol, ..} |
+        Command::RemoveIndicator{ref symbol, ..} => symbol.clone(),
+        _ => None,
+    }
+}
+
+impl SymbolProcessor {
+    pub fn new(symbol: String, pg_client: &Connection) -> SymbolProcessor {
+        let _ = init_tick_table(symbol.as_str(), pg_client, CONF.postgres_user);
+
+        let indicator_publisher = IndicatorPublisher::new(
+            symbol.clone(), CONF.redis_host, CONF.indicator_publish_buffer_size
+        );
+        SymbolProcessor {
+            symbol: symbol,
+            ticks: DataField::with_capacity(CONF.processor_tick_capacity),
+            indicators: IndicatorRegistry::new(),
+            indicator_publisher: indicator_publisher,
+            candles: Vec::new(),
+            renko: Vec::new(),
+            heikin_ashi: Vec::new(),
+            crossing_channels: HashMap::new(),
+            hooks: Vec::new(),
+            tick_sink: None,
+            relay: None,
+            validator: TickValidator::from_conf(),
+            timestamps: TimestampNormalizer::from_conf(),
+        }
+    }
+
+    /// Updates the indicators and candles with a new tick of this symbol.
+    pub fn process(&mut self, t: Tick, redis_client: &redis::Client, qs: &mut QueryServer) {
+        let t = match self.timestamps.normalize(t) {
+            Ok(t) => t,
+            Err(err) => {
+                println!("Skipping tick {:?} of {}: {}", t, self.symbol, err);
+                return;
+            },
+        };
+        if let Some(ref mut sink) = self.tick_sink {
+            sink.push(t);
+        }
+        // a duplicated, late, or garbled tick from the feed is skipped rather than averaged in
+        let verdict = self.validator.validate(&t);
+        if verdict != TickVerdict::Ok {
+            println!(
+                "Skipping invalid tick {:?} ({:?}; {} rejected so far)", t, verdict, self.validator.counts().invalid()
+            );
+            return;
+        }
+        self.ticks.push(t);
+        if let Some(ref mut relay) = self.relay {
+            for relayed in relay.push(t, Instant::now()) {
+                relay.emit(&relayed, redis_client);
+            }
+        }
+        // indicators added since the last tick are the only ones that could refuse it, and they just skip it
+        let updates = self.indicators.push_all(&t);
+        self.indicator_publisher.publish_all(&updates);
+        self.indicator_publisher.publish_alerts(&self.indicators.take_alerts());
+        for (id, event) in self.indicators.take_crossings() {
+            if let Some(channel) = self.crossing_channels.get(&id) {
+                match serde_json::to_string(&event) {
+                    Ok(ser) => publish(redis_client, channel, &ser),
+                    Err(err) => println!("Unable to serialize crossover event: {:?}", err),
+                }
+            }
+        }
+        for hook in self.hooks.iter_mut() {
+            if hook.check(&self.indicators) {
+                hook.emit(&self.symbol, t.timestamp, redis_client);
+            }
+        }
+        for feed in self.candles.iter_mut() {
+            // the validator has already refused any ticks that are out of order
+            for candle in feed.push(&t).unwrap_or_default() {
+                feed.emit(&candle, redis_client, qs);
+            }
+        }
+        for feed in self.renko.iter_mut() {
+            for brick in feed.builder.push(&t).unwrap_or_default() {
+                feed.emit(&brick, redis_client);
+            }
+        }
+        for feed in self.heikin_ashi.iter_mut() {
+            for candle in feed.push(&t).unwrap_or_default() {
+                feed.emit(&candle, redis_client);
+            }
+        }
+    }
+
+    /// Handles a command that applies to this symbol.  `redis_client` is used to publish anything that the command
+    /// causes to be sent right away.
+    pub fn execute(&mut self, cmd: Command, redis_client: &redis::Client) -> Response {
+        match cmd {
             Command::AddCondition{condition_string} => {
                 unimplemented!();
             },
             Command::RemoveCondition{condition_string} => {
                 unimplemented!();
             },
+            Command::RegisterCrossover{fast_period, slow_period, channel, ..} => {
+                match self.add_sma_crossover(fast_period, slow_period) {
+                    Ok(id) => {
+                        self.crossing_channels.insert(id, channel);
+                        Response::Ok
+                    },
+                    Err(err) => Response::Error{status: err},
+                }
+            },
+            Command::AddSMAPeriod{period_ms, ..} => {
+                match self.indicators.add("sma", &json!({"period_ms": period_ms}), None, false) {
+                    Ok(_) => Response::Ok,
+                    Err(err) => Response::Error{status: err},
+                }
+            },
+            Command::RemoveSMAPeriod{period_ms, ..} => {
+                match self.indicators.remove_matching("sma", &json!({"period_ms": period_ms})) {
+                    Ok(true) => Response::Ok,
+                    Ok(false) => Response::Error{
+                        status: format!("No SMA with a period of {}ms is being calculated.", period_ms)
+                    },
+                    Err(err) => Response::Error{status: err},
+                }
+            },
+            Command::AddEma{period_ms, ..} => {
+                match self.indicators.add("ema", &json!({"period_ms": period_ms}), None, false) {
+                    Ok(_) => Response::Ok,
+                    Err(err) => Response::Error{status: err},
+                }
+            },
+            Command::RemoveEma{period_ms, ..} => {
+                match self.indicators.remove_matching("ema", &json!({"period_ms": period_ms})) {
+                    Ok(true) => Response::Ok,
+                    Ok(false) => Response::Error{
+                        status: format!("No EMA with a period of {}ms is being calculated.", period_ms)
+                    },
+                    Err(err) => Response::Error{status: err},
+                }
+            },
+            Command::AddRsi{period, interval_ms, ..} => {
+                let params = json!({"period": period, "interval_ms": interval_ms});
+                match self.indicators.add("rsi", &params, None, false) {
+                    Ok(_) => Response::Ok,
+                    Err(err) => Response::Error{status: err},
+                }
+            },
+            Command::RemoveRsi{period, interval_ms, ..} => {
+                match self.indicators.remove_matching("rsi", &json!({"period": period, "interval_ms": interval_ms})) {
+                    Ok(true) => Response::Ok,
+                    Ok(false) => Response::Error{status: format!(
+                        "No RSI with a period of {} and a sampling interval of {}ms is being calculated.",
+                        period, interval_ms
+                    )},
+                    Err(err) => Response::Error{status: err},
+                }
+            },
+            Command::AddMacd{fast_period_ms, slow_period_ms, signal_period_ms, channel, ..} => {
+                match macd_periods(fast_period_ms, slow_period_ms, signal_period_ms) {
+                    (0, _, _) | (_, 0, _) | (_, _, 0) => {
+                        Response::Error{status: String::from("MACD periods must be greater than zero.")}
+                    },
+                    (fast, slow, signal) => {
+                        let params = json!({
+                            "fast_period_ms": fast, "slow_period_ms": slow, "signal_period_ms": signal,
+                        });
+                        match self.indicators.add("macd", &params, None, false) {
+                            Ok(id) => {
+                                self.crossing_channels.insert(id, channel);
+                                Response::Ok
+                            },
+                            Err(err) => Response::Error{status: err},
+                        }
+                    },
+                }
+            },
+            Command::RemoveMacd{fast_period_ms, slow_period_ms, signal_period_ms, ..} => {
+                let (fast, slow, signal) = macd_periods(fast_period_ms, slow_period_ms, signal_period_ms);
+                let params = json!({"fast_period_ms": fast, "slow_period_ms": slow, "signal_period_ms": signal});
+                match self.indicators.remove_matching("macd", &params) {
+                    Ok(true) => Response::Ok,
+                    Ok(false) => Response::Error{status: format!(
+                        "No MACD with periods of {}, {}, and {}ms is being calculated.", fast, slow, signal
+                    )},
+                    Err(err) => Response::Error{status: err},
+                }
+            },
+            Command::AddAtr{bar_ms, period, ..} => {
+                match self.indicators.add("atr", &json!({"bar_ms": bar_ms, "period": period}), None, false) {
+                    Ok(_) => Response::Ok,
+                    Err(err) => Response::Error{status: err},
+                }
+            },
+            Command::RemoveAtr{bar_ms, period, ..} => {
+                match self.indicators.remove_matching("atr", &json!({"bar_ms": bar_ms, "period": period})) {
+                    Ok(true) => Response::Ok,
+                    Ok(false) => Response::Error{status: format!(
+                        "No ATR with {}ms bars and a period of {} is being calculated.", bar_ms, period
+                    )},
+                    Err(err) => Response::Error{status: err},
+                }
+            },
+            Command::AddCandles{duration_ms, carry_forward, store, ..} => {
+                self.add_candles(duration_ms, carry_forward, store)
+            },
+            Command::RecordTicks{enabled, table, ..} => self.record_ticks(enabled, table),
+            Command::SetTickSampling{every_n, bucket_ms, quiet_ms, ..} => {
+                self.set_tick_sampling(every_n, bucket_ms, quiet_ms, redis_client)
+            },
+            Command::RemoveCandles{duration_ms, ..} => {
+                let len = self.candles.len();
+                self.candles.retain(|feed| feed.bars.period_ms != duration_ms);
+                if self.candles.len() != len {
+                    Response::Ok
+                } else {
+                    Response::Error{status: format!("No {}ms candles are being aggregated.", duration_ms)}
+                }
+            },
+            Command::AddRenko{brick_size, ..} => {
+                if brick_size == 0 {
+                    Response::Error{status: String::from("Brick sizes must be greater than zero.")}
+                } else {
+                    self.renko.retain(|feed| feed.builder.brick_size != brick_size);
+                    self.renko.push(RenkoFeed::new(&self.symbol, brick_size));
+                    Response::Ok
+                }
+            },
+            Command::RemoveRenko{brick_size, ..} => {
+                let len = self.renko.len();
+                self.renko.retain(|feed| feed.builder.brick_size != brick_size);
+                if self.renko.len() != len {
+                    Response::Ok
+                } else {
+                    Response::Error{status: format!("No Renko bricks of {} units are being built.", brick_size)}
+                }
+            },
+            Command::AddHeikinAshi{duration_ms, carry_forward, ..} => {
+                if duration_ms == 0 {
+                    Response::Error{status: String::from("Candle durations must be greater than zero.")}
+                } else {
+                    let gap_policy = if carry_forward { GapPolicy::CarryForward } else { GapPolicy::Skip };
+                    self.heikin_ashi.retain(|feed| feed.bars.period_ms != duration_ms);
+                    self.heikin_ashi.push(HeikinAshiFeed::new(&self.symbol, duration_ms, gap_policy));
+                    Response::Ok
+                }
+            },
+            Command::RemoveHeikinAshi{duration_ms, ..} => {
+                let len = self.heikin_ashi.len();
+                self.heikin_ashi.retain(|feed| feed.bars.period_ms != duration_ms);
+                if self.heikin_ashi.len() != len {
+                    Response::Ok
+                } else {
+                    Response::Error{status: format!("No {}ms Heikin-Ashi candles are being built.", duration_ms)}
+                }
+            },
+            Command::AddIndicator{kind, params, throttle_ms, hold_until_warm, ..} => {
+                match self.indicators.add(&kind, &params, throttle_ms, hold_until_warm) {
+                    Ok(id) => Response::Info{info: id.hyphenated().to_string()},
+                    Err(err) => Response::Error{status: err},
+                }
+            },
+            Command::RemoveIndicator{id, ..} => {
+                if self.indicators.remove(id) {
+                    Response::Ok
+                } else {
+                    Response::Error{status: format!("No indicator with the id {} is being calculated.", id)}
+                }
+            },
+            Command::AddHook{condition, channel, ..} => {
+                if !self.hooks.iter().any(|hook| hook.condition == condition && hook.channel == channel) {
+                    self.hooks.push(Hook::new(condition, channel));
+                }
+                Response::Ok
+            },
+            Command::RemoveHook{condition, channel, ..} => {
+                let len = self.hooks.len();
+                self.hooks.retain(|hook| hook.condition != condition || hook.channel != channel);
+                if self.hooks.len() != len {
+                    Response::Ok
+                } else {
+                    Response::Error{status: format!("No {:?} hook publishes to {}.", condition, channel)}
+                }
+            },
             Command::ListConditions => {
                 unimplemented!();
                 // Response::Info{info: }
@@ -80,9 +685,133 @@ impl Processor {
             _ => {
                 Response::Error{status: "Command not recognized".to_string()}
             }
+        }
+    }
+
+    /// Starts calculating time-weighted SMAs with the given periods in milliseconds if they aren't already, and
+    /// watches for the fast one crossing the slow one.  Returns the id of the crossover.
+    fn add_sma_crossover(&mut self, fast_period: u64, slow_period: u64) -> Result<Uuid, String> {
+        let fast = self.indicators.add("sma", &json!({"period_ms": fast_period}), None, false)?;
+        let slow = self.indicators.add("sma", &json!({"period_ms": slow_period}), None, false)?;
+        let params = json!({"a": fast.hyphenated().to_string(), "b": slow.hyphenated().to_string()});
+        self.indicators.add("crossover", &params, None, false)
+    }
+
+    /// Starts aggregating candles of the given duration, replacing the settings of any that already are.  The table
+    /// that stored candles are written to is created if it doesn't exist.
+    fn add_candles(&mut self, duration_ms: u64, carry_forward: bool, store: bool) -> Response {
+        if duration_ms == 0 {
+            return Response::Error{status: String::from("Candle durations must be greater than zero.")};
+        }
+        let gap_policy = if carry_forward { GapPolicy::CarryForward } else { GapPolicy::Skip };
+        let feed = CandleFeed::new(&self.symbol, duration_ms, gap_policy, store);
+
+        if store {
+            let res = get_client()
+                .map_err(|err| format!("Unable to connect to Postgres: {:?}", err))
+                .and_then(|client| init_candle_table(&feed.channel, &client, CONF.postgres_user));
+            if let Err(err) = res {
+                return Response::Error{status: err};
+            }
+        }
+
+        self.candles.retain(|existing| existing.bars.period_ms != duration_ms);
+        self.candles.push(feed);
+        Response::Ok
+    }
+
+    /// Starts recording ticks to `table`, or `ticks_<symbol>` if it isn't set, or stops recording them.  Ticks already
+    /// waiting to be recorded are written first.
+    pub fn record_ticks(&mut self, enabled: bool, table: Option<String>) -> Response {
+        if let Some(mut sink) = self.tick_sink.take() {
+            if let Err(err) = sink.flush() {
+                return Response::Error{
+                    status: format!("Recording stopped but {} ticks couldn't be written: {}", sink.buffered(), err)
+                };
+            }
+        }
+        if !enabled {
+            return Response::Ok;
+        }
+
+        let table = table.unwrap_or_else(|| format!("ticks_{}", self.symbol));
+        match TickSink::from_conf(&table) {
+            Ok(sink) => {
+                self.tick_sink = Some(sink);
+                Response::Ok
+            },
+            Err(err) => Response::Error{status: err},
+        }
+    }
+
+    /// Starts relaying every `every_n`th tick or the last tick of every `bucket_ms` milliseconds, or stops relaying
+    /// if neither is set.  A tick that the relay was holding back is sent before anything is changed so that the
+    /// relayed ticks stay in order.
+    fn set_tick_sampling(
+        &mut self, every_n: Option<usize>, bucket_ms: Option<u64>, quiet_ms: Option<u64>, redis_client: &redis::Client
+    ) -> Response {
+        let mode = match (every_n, bucket_ms) {
+            (Some(0), _) | (_, Some(0)) => {
+                return Response::Error{status: String::from("Sampling rates must be greater than zero.")};
+            },
+            (Some(_), Some(_)) => {
+                return Response::Error{status: String::from("Only one of every_n and bucket_ms can be set.")};
+            },
+            (Some(n), None) => Some(SampleMode::EveryNth(n)),
+            (None, Some(bucket_ms)) => Some(SampleMode::Bucket(bucket_ms)),
+            (None, None) => None,
         };
 
-        let wr = res.wrap(wrapped_cmd.uuid);
-        let _ = send_response(&wr, &self.redis_client, res_channel);
+        match mode {
+            Some(mode) => {
+                let quiet_ms = quiet_ms.unwrap_or_else(|| match mode {
+                    SampleMode::EveryNth(_) => DEFAULT_QUIET_MS,
+                    SampleMode::Bucket(bucket_ms) => bucket_ms,
+                });
+                if let Some(ref mut relay) = self.relay {
+                    if let Some(t) = relay.set_mode(mode, quiet_ms) {
+                        relay.emit(&t, redis_client);
+                    }
+                    return Response::Ok;
+                }
+                self.relay = Some(TickRelay::new(&self.symbol, mode, quiet_ms));
+            },
+            None => {
+                if let Some(mut relay) = self.relay.take() {
+                    if let Some(t) = relay.flush() {
+                        relay.emit(&t, redis_client);
+                    }
+                }
+            },
+        }
+        Response::Ok
+    }
+
+    /// Publishes and stores the candles that are still in progress as if their periods had ended and writes any
+    /// ticks and indicator values that are waiting to be recorded.
+    pub fn flush(&mut self, redis_client: &redis::Client, qs: &mut QueryServer) {
+        for feed in self.candles.iter_mut() {
+            if let Some(candle) = feed.flush() {
+                feed.emit(&candle, redis_client, qs);
+            }
+        }
+        for feed in self.heikin_ashi.iter_mut() {
+            if let Some(candle) = feed.flush() {
+                feed.emit(&candle, redis_client);
+            }
+        }
+        if let Some(ref mut relay) = self.relay {
+            if let Some(t) = relay.flush() {
+                relay.emit(&t, redis_client);
+            }
+        }
+        if let Some(ref mut sink) = self.tick_sink {
+            if let Err(err) = sink.flush() {
+                println!("{}; {} recorded ticks of {} were lost", err, sink.buffered(), self.symbol);
+            }
+        }
+        if let Err(err) = self.indicator_publisher.flush() {
+            println!("{} for {}", err, self.symbol);
+        }
     }
 }
@@ -0,0 +1,171 @@
+//! Republishes a symbol's ticks at a lower rate for consumers such as charts that don't need every one of them.
+//! Ticks are relayed unchanged, with their original timestamps, and always in order.  Whichever way they're
+//! sampled, the latest tick that hasn't been relayed is sent once no ticks have arrived for `quiet_ms`
+//! milliseconds so that the last price before a quiet period is never held back.
+
+use std::time::{Duration, Instant};
+
+use redis;
+use serde_json;
+
+use tickgrinder_util::trading::tick::Tick;
+use tickgrinder_util::transport::redis::publish;
+
+/// How long the relay waits for more ticks before sending the pending one when sampling every Nth tick
+pub const DEFAULT_QUIET_MS: u64 = 1000;
+
+/// Which of the incoming ticks are relayed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleMode {
+    /// Every Nth tick, counted from the last one relayed
+    EveryNth(usize),
+    /// The last tick of each period of this many milliseconds of tick time.  Periods are aligned to multiples of
+    /// their length, and a period's last tick is known once a tick of a later period arrives.
+    Bucket(u64),
+}
+
+pub struct TickRelay {
+    pub mode: SampleMode,
+    pub quiet_ms: u64,
+    /// Redis channel that relayed ticks are published to
+    pub channel: String,
+    /// Ticks received since the last one was relayed
+    received: usize,
+    /// The latest tick that hasn't been relayed
+    pending: Option<Tick>,
+    /// When the latest tick arrived
+    last_received: Option<Instant>,
+    /// Timestamp of the latest tick relayed or pending
+    last_timestamp: Option<u64>,
+}
+
+impl TickRelay {
+    /// Creates a relay that publishes to `sampled_ticks_<symbol>`, which is outside of the `ticks_*` channels that
+    /// Tick Processors subscribe to.
+    pub fn new(symbol: &str, mode: SampleMode, quiet_ms: u64) -> TickRelay {
+        TickRelay {
+            mode: mode,
+            quiet_ms: quiet_ms,
+            channel: format!("sampled_ticks_{}", symbol),
+            received: 0,
+            pending: None,
+            last_received: None,
+            last_timestamp: None,
+        }
+    }
+
+    /// Takes a tick that arrived at `now` and returns the ticks to relay.  Ticks older than the previous one are
+    /// dropped.
+    pub fn push(&mut self, t: Tick, now: Instant) -> Vec<Tick> {
+        if self.last_timestamp.map(|last| t.timestamp < last).unwrap_or(false) {
+            return Vec::new();
+        }
+        self.last_timestamp = Some(t.timestamp);
+        self.last_received = Some(now);
+
+        match self.mode {
+            SampleMode::EveryNth(n) => {
+                self.received += 1;
+                if self.received >= n {
+                    self.received = 0;
+                    self.pending = None;
+                    vec![t]
+                } else {
+                    self.pending = Some(t);
+                    Vec::new()
+                }
+            },
+            SampleMode::Bucket(bucket_ms) => {
+                let completed = match self.pending {
+                    Some(pending) if pending.timestamp / bucket_ms != t.timestamp / bucket_ms => vec![pending],
+                    _ => Vec::new(),
+                };
+                self.pending = Some(t);
+                completed
+            },
+        }
+    }
+
+    /// Returns the pending tick if no ticks have arrived for `quiet_ms` as of `now`.
+    pub fn poll(&mut self, now: Instant) -> Option<Tick> {
+        match self.last_received {
+            Some(last) if now.duration_since(last) >= Duration::from_millis(self.quiet_ms) => self.flush(),
+            _ => None,
+        }
+    }
+
+    /// Returns the pending tick right away, such as when shutting down.
+    pub fn flush(&mut self) -> Option<Tick> {
+        self.received = 0;
+        self.pending.take()
+    }
+
+    /// Switches to another way of sampling, returning the pending tick so that it's relayed before any of the
+    /// ticks sampled the new way.
+    pub fn set_mode(&mut self, mode: SampleMode, quiet_ms: u64) -> Option<Tick> {
+        self.mode = mode;
+        self.quiet_ms = quiet_ms;
+        self.flush()
+    }
+
+    /// Publishes a tick in the same form as the ticks the Tick Processor receives.
+    pub fn emit(&self, t: &Tick, client: &redis::Client) {
+        match serde_json::to_string(t) {
+            Ok(ser) => publish(client, &self.channel, &ser),
+            Err(err) => println!("Unable to serialize relayed tick: {:?}", err),
+        }
+    }
+}
+
+/// Pushes ticks with the given timestamps, each arriving the given number of milliseconds after `start`, followed
+/// by polls at the given times, and returns the timestamps of everything relayed.
+#[cfg(test)]
+fn relayed(relay: &mut TickRelay, start: Instant, ticks: &[(u64, u64)], polls: &[u64]) -> Vec<u64> {
+    let mut relayed = Vec::new();
+    for &(timestamp, arrival) in ticks {
+        let now = start + Duration::from_millis(arrival);
+        relayed.extend(relay.push(Tick {timestamp: timestamp, bid: 100, ask: 101}, now).iter().map(|t| t.timestamp));
+    }
+    for &poll in polls {
+        relayed.extend(relay.poll(start + Duration::from_millis(poll)).map(|t| t.timestamp));
+    }
+    relayed
+}
+
+/// Each bucket's last tick is relayed once the next bucket starts, and the last tick of a burst is relayed once
+/// the feed has been quiet for long enough rather than waiting for the next tick.
+#[test]
+fn bucket_sampling_around_quiet_periods() {
+    let start = Instant::now();
+    let mut relay = TickRelay::new("EURUSD", SampleMode::Bucket(100), 100);
+    // a burst spanning three buckets, arriving in real time
+    let burst: Vec<(u64, u64)> = (0..25).map(|i| (i * 10 + 5, i * 10)).collect();
+    // the burst ends at 240, so the feed isn't quiet until 340
+    assert_eq!(relayed(&mut relay, start, &burst, &[250, 339]), vec![95, 195]);
+    assert_eq!(relayed(&mut relay, start, &[], &[340, 1000]), vec![245]);
+
+    // after the silence, a tick in the same bucket as the last one relayed is still relayed on its own
+    let mut relay = TickRelay::new("EURUSD", SampleMode::Bucket(100), 100);
+    assert_eq!(relayed(&mut relay, start, &[(10, 0), (20, 10)], &[200]), vec![20]);
+    assert_eq!(relayed(&mut relay, start, &[(30, 300), (150, 310)], &[]), vec![30]);
+
+    // an out of order tick is never relayed and doesn't delay the quiet period
+    assert_eq!(relayed(&mut relay, start, &[(140, 320)], &[409, 410]), vec![150]);
+    assert_eq!(relay.flush(), None);
+}
+
+/// Every Nth tick is relayed, the tick pending when the feed goes quiet is relayed as well, and switching modes
+/// relays the pending tick first.
+#[test]
+fn every_nth_sampling() {
+    let start = Instant::now();
+    let mut relay = TickRelay::new("EURUSD", SampleMode::EveryNth(3), 50);
+    let ticks: Vec<(u64, u64)> = (1..9).map(|i| (i, i)).collect();
+    assert_eq!(relayed(&mut relay, start, &ticks, &[20, 100]), vec![3, 6, 8]);
+    // counting starts over after the quiet period
+    let ticks: Vec<(u64, u64)> = (9..13).map(|i| (i, 200 + i)).collect();
+    assert_eq!(relayed(&mut relay, start, &ticks, &[]), vec![11]);
+
+    assert_eq!(relay.set_mode(SampleMode::Bucket(1000), 1000).map(|t| t.timestamp), Some(12));
+    assert_eq!(relay.flush(), None);
+}
@@ -0,0 +1,240 @@
+//! Builds Renko bricks out of the mid price.  Bricks are a fixed number of price units tall and are laid out on a
+//! grid anchored at the mid price of the first tick, so every brick opens and closes on a multiple of the brick
+//! size away from it.  A brick is completed each time the price moves a full brick beyond the close of the last
+//! one in the same direction; turning around takes a move of a full brick beyond the last brick's open, so the
+//! first brick in the other direction opens where the last one opened.
+
+use redis;
+use serde_json::Value;
+
+use tickgrinder_util::trading::tick::Tick;
+use tickgrinder_util::trading::indicators::SmaError;
+use tickgrinder_util::transport::redis::publish;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrickDirection {
+    Up,
+    Down,
+}
+
+/// A completed brick
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenkoBrick {
+    pub open: f64,
+    pub close: f64,
+    pub direction: BrickDirection,
+    /// Timestamp of the tick that completed the brick.  A tick that moves the price several bricks completes all
+    /// of them at once.
+    pub timestamp: u64,
+}
+
+impl RenkoBrick {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "open": self.open,
+            "close": self.close,
+            "direction": match self.direction { BrickDirection::Up => "up", BrickDirection::Down => "down" },
+            "timestamp": self.timestamp,
+        })
+    }
+}
+
+/// Rounds the quotient towards negative infinity; `divisor` must be positive.
+fn div_floor(dividend: i64, divisor: i64) -> i64 {
+    let quotient = dividend / divisor;
+    if dividend % divisor != 0 && dividend < 0 { quotient - 1 } else { quotient }
+}
+
+/// Rounds the quotient towards positive infinity; `divisor` must be positive.
+fn div_ceil(dividend: i64, divisor: i64) -> i64 {
+    let quotient = dividend / divisor;
+    if dividend % divisor != 0 && dividend > 0 { quotient + 1 } else { quotient }
+}
+
+/// Builds bricks of `brick_size` price units.  Prices are kept doubled so that mid prices halfway between two
+/// units are still whole numbers and the grid can be computed exactly.
+pub struct RenkoBuilder {
+    pub brick_size: usize,
+    /// Twice the mid price of the first tick
+    anchor: Option<i64>,
+    /// Number of bricks between the anchor and the close of the last brick, negative below the anchor
+    level: i64,
+    /// Direction of the last brick, or `None` before the first one
+    direction: Option<BrickDirection>,
+    last_timestamp: Option<u64>,
+}
+
+impl RenkoBuilder {
+    pub fn new(brick_size: usize) -> RenkoBuilder {
+        assert!(brick_size > 0, "Brick size must be greater than zero!");
+        RenkoBuilder {
+            brick_size: brick_size,
+            anchor: None,
+            level: 0,
+            direction: None,
+            last_timestamp: None,
+        }
+    }
+
+    /// Returns the mid price at the given level of the grid.
+    fn price(&self, anchor: i64, level: i64) -> f64 {
+        (anchor + level * 2 * self.brick_size as i64) as f64 / 2.
+    }
+
+    /// Moves the price to that of the tick and returns every brick it completed, oldest first.  Ticks older than
+    /// the previous one are refused.
+    pub fn push(&mut self, t: &Tick) -> Result<Vec<RenkoBrick>, SmaError> {
+        if let Some(last) = self.last_timestamp {
+            if t.timestamp < last {
+                return Err(SmaError::OutOfOrder{last: last, got: t.timestamp});
+            }
+        }
+        self.last_timestamp = Some(t.timestamp);
+
+        let price = t.bid as i64 + t.ask as i64;
+        let anchor = match self.anchor {
+            Some(anchor) => anchor,
+            None => {
+                self.anchor = Some(price);
+                return Ok(Vec::new());
+            },
+        };
+
+        // the levels from which the next brick up or down would be built
+        let (up_from, down_from) = match self.direction {
+            None => (0, 0),
+            Some(BrickDirection::Up) => (self.level, self.level - 1),
+            Some(BrickDirection::Down) => (self.level + 1, self.level),
+        };
+        let size = 2 * self.brick_size as i64;
+        let highest = div_floor(price - anchor, size);
+        let lowest = div_ceil(price - anchor, size);
+
+        let mut bricks = Vec::new();
+        if highest > up_from {
+            for level in (up_from + 1)..(highest + 1) {
+                bricks.push(RenkoBrick {
+                    open: self.price(anchor, level - 1),
+                    close: self.price(anchor, level),
+                    direction: BrickDirection::Up,
+                    timestamp: t.timestamp,
+                });
+            }
+            self.level = highest;
+            self.direction = Some(BrickDirection::Up);
+        } else if lowest < down_from {
+            for level in (lowest..down_from).rev() {
+                bricks.push(RenkoBrick {
+                    open: self.price(anchor, level + 1),
+                    close: self.price(anchor, level),
+                    direction: BrickDirection::Down,
+                    timestamp: t.timestamp,
+                });
+            }
+            self.level = lowest;
+            self.direction = Some(BrickDirection::Down);
+        }
+        Ok(bricks)
+    }
+
+    /// Returns the last brick's close, or the anchor if there hasn't been a brick yet.
+    pub fn last_close(&self) -> Option<f64> {
+        self.anchor.map(|anchor| self.price(anchor, self.level))
+    }
+}
+
+/// A `RenkoBuilder` run by the tick processor along with the channel its bricks are published to
+pub struct RenkoFeed {
+    pub builder: RenkoBuilder,
+    pub channel: String,
+}
+
+impl RenkoFeed {
+    pub fn new(symbol: &str, brick_size: usize) -> RenkoFeed {
+        RenkoFeed {
+            builder: RenkoBuilder::new(brick_size),
+            channel: format!("renko_{}_{}", symbol, brick_size),
+        }
+    }
+
+    /// Publishes a brick as JSON.
+    pub fn emit(&self, brick: &RenkoBrick, client: &redis::Client) {
+        publish(client, &self.channel, &brick.to_json().to_string());
+    }
+}
+
+/// Each row moves the price of a fresh builder with bricks of 10 units through the given mid prices and lists the
+/// (open, close) of the bricks completed by every tick.
+#[test]
+fn renko_bricks() {
+    let cases: Vec<(&str, Vec<usize>, Vec<Vec<(f64, f64)>>)> = vec![
+        ("less than a brick from the anchor", vec![100, 109, 91, 100], vec![vec![], vec![], vec![], vec![]]),
+        ("one brick up", vec![100, 110], vec![vec![], vec![(100., 110.)]]),
+        ("one brick down", vec![100, 90], vec![vec![], vec![(100., 90.)]]),
+        (
+            "several bricks in one tick",
+            vec![100, 135, 115],
+            vec![vec![], vec![(100., 110.), (110., 120.), (120., 130.)], vec![]],
+        ),
+        (
+            "continuing needs a full brick beyond the close",
+            vec![100, 110, 119, 120, 125],
+            vec![vec![], vec![(100., 110.)], vec![], vec![(110., 120.)], vec![]],
+        ),
+        (
+            "reversing needs a full brick beyond the open",
+            vec![100, 120, 101, 100],
+            vec![vec![], vec![(100., 110.), (110., 120.)], vec![], vec![(110., 100.)]],
+        ),
+        (
+            "a reversal of several bricks opens at the last brick's open",
+            vec![100, 120, 75, 80, 81, 100],
+            vec![
+                vec![], vec![(100., 110.), (110., 120.)], vec![(110., 100.), (100., 90.), (90., 80.)], vec![], vec![],
+                vec![(90., 100.)],
+            ],
+        ),
+        (
+            "down then up below the anchor",
+            vec![100, 80, 89, 90, 100],
+            vec![vec![], vec![(100., 90.), (90., 80.)], vec![], vec![], vec![(90., 100.)]],
+        ),
+        (
+            "whipsaws inside the reversal zone",
+            vec![100, 110, 95, 105, 91, 110, 90],
+            vec![vec![], vec![(100., 110.)], vec![], vec![], vec![], vec![], vec![(100., 90.)]],
+        ),
+    ];
+
+    for (name, prices, expected) in cases {
+        let mut builder = RenkoBuilder::new(10);
+        for (i, (&price, expected)) in prices.iter().zip(expected.iter()).enumerate() {
+            let bricks: Vec<(f64, f64)> = builder.push(&Tick {timestamp: i as u64, bid: price, ask: price})
+                .unwrap()
+                .iter()
+                .map(|brick| (brick.open, brick.close))
+                .collect();
+            assert_eq!(&bricks, expected, "{}: tick {}", name, i);
+        }
+    }
+}
+
+/// Mid prices halfway between two units sit exactly on the grid, and the bricks point the way they moved.
+#[test]
+fn renko_brick_metadata() {
+    let mut builder = RenkoBuilder::new(5);
+    assert_eq!(builder.last_close(), None);
+    assert!(builder.push(&Tick {timestamp: 10, bid: 100, ask: 101}).unwrap().is_empty());
+    assert_eq!(builder.last_close(), Some(100.5));
+
+    let bricks = builder.push(&Tick {timestamp: 20, bid: 105, ask: 106}).unwrap();
+    assert_eq!(bricks, vec![RenkoBrick {open: 100.5, close: 105.5, direction: BrickDirection::Up, timestamp: 20}]);
+    // a tick can share its timestamp with the previous one
+    let bricks = builder.push(&Tick {timestamp: 20, bid: 90, ask: 91}).unwrap();
+    assert_eq!(bricks, vec![
+        RenkoBrick {open: 100.5, close: 95.5, direction: BrickDirection::Down, timestamp: 20},
+        RenkoBrick {open: 95.5, close: 90.5, direction: BrickDirection::Down, timestamp: 20},
+    ]);
+    assert_eq!(builder.last_close(), Some(90.5));
+    assert_eq!(builder.push(&Tick {timestamp: 5, bid: 1, ask: 1}), Err(SmaError::OutOfOrder{last: 20, got: 5}));
+}
@@ -0,0 +1,214 @@
+//! Live relative strength indexes of the mid price.  Ticks arrive at irregular intervals, so the price is sampled
+//! at fixed intervals and the RSI is calculated over the changes between samples.
+
+#[allow(unused_imports)]
+use test;
+
+use serde_json::Value;
+
+use tickgrinder_util::trading::tick::Tick;
+use tickgrinder_util::trading::indicators::SmaError;
+
+use indicators::{Indicator, IndicatorValue};
+
+/// The RSI reported when the price hasn't moved at all during the warm-up or since.  With no gains and no losses
+/// the strength is undefined; the midpoint is used so that a flat market reads as neither overbought nor oversold.
+pub const FLAT_RSI: f64 = 50.;
+
+/// A relative strength index over `period` samples of the mid price taken every `interval_ms` milliseconds, using
+/// Wilder's smoothing.  The sample at each multiple of the interval is the mid price of the last tick at or before
+/// that time, so intervals without any ticks count as unchanged prices.
+pub struct Rsi {
+    pub period: usize,
+    pub interval_ms: u64,
+    /// The RSI as of the last sample, from 0 to 100, or `None` until `period` price changes have been sampled
+    pub value: Option<f64>,
+    /// The mid price and timestamp of the most recent tick
+    last_tick: Option<(f64, u64)>,
+    /// Time of the next sample to take
+    next_sample_time: u64,
+    last_sample: Option<f64>,
+    /// Number of price changes that have been sampled, up to `period`
+    changes: usize,
+    /// Sums of the gains and losses during the warm-up, then their smoothed averages
+    avg_gain: f64,
+    avg_loss: f64,
+}
+
+impl Rsi {
+    pub fn new(period: usize, interval_ms: u64) -> Rsi {
+        assert!(period > 0, "RSI period must be greater than zero!");
+        assert!(interval_ms > 0, "RSI sampling interval must be greater than zero!");
+        Rsi {
+            period: period,
+            interval_ms: interval_ms,
+            value: None,
+            last_tick: None,
+            next_sample_time: 0,
+            last_sample: None,
+            changes: 0,
+            avg_gain: 0.,
+            avg_loss: 0.,
+        }
+    }
+
+    /// Adds a tick, taking a sample for every interval boundary it reaches, and returns the current RSI.  Ticks
+    /// that aren't newer than the previous one are refused and leave the RSI unchanged.
+    pub fn push(&mut self, t: &Tick) -> Result<Option<f64>, SmaError> {
+        let mid = t.mid_f64();
+        match self.last_tick {
+            Some((_, last)) if t.timestamp < last => return Err(SmaError::OutOfOrder{last: last, got: t.timestamp}),
+            Some((_, last)) if t.timestamp == last => return Err(SmaError::EqualTimestamp),
+            Some((last_mid, _)) => {
+                // the previous tick's price was in effect at every boundary before this tick
+                while self.next_sample_time < t.timestamp {
+                    self.sample(last_mid);
+                }
+            },
+            None => {
+                // the first sample is taken at the first boundary at or after the first tick
+                let intervals = (t.timestamp + self.interval_ms - 1) / self.interval_ms;
+                self.next_sample_time = intervals * self.interval_ms;
+            },
+        }
+
+        if self.next_sample_time == t.timestamp {
+            self.sample(mid);
+        }
+        self.last_tick = Some((mid, t.timestamp));
+        Ok(self.value)
+    }
+
+    fn sample(&mut self, price: f64) {
+        self.next_sample_time += self.interval_ms;
+        let change = match self.last_sample {
+            Some(last_sample) => price - last_sample,
+            None => {
+                self.last_sample = Some(price);
+                return;
+            },
+        };
+        self.last_sample = Some(price);
+        let (gain, loss) = if change > 0. { (change, 0.) } else { (0., -change) };
+
+        let period = self.period as f64;
+        if self.changes < self.period {
+            self.avg_gain += gain;
+            self.avg_loss += loss;
+            self.changes += 1;
+            if self.changes < self.period {
+                return;
+            }
+            // the first averages are plain means of the warm-up changes
+            self.avg_gain /= period;
+            self.avg_loss /= period;
+        } else {
+            self.avg_gain = (self.avg_gain * (period - 1.) + gain) / period;
+            self.avg_loss = (self.avg_loss * (period - 1.) + loss) / period;
+        }
+
+        self.value = Some(if self.avg_loss == 0. {
+            if self.avg_gain == 0. { FLAT_RSI } else { 100. }
+        } else {
+            100. - 100. / (1. + self.avg_gain / self.avg_loss)
+        });
+    }
+}
+
+impl Indicator for Rsi {
+    fn push(&mut self, t: Tick) -> Result<Option<IndicatorValue>, SmaError> {
+        Ok(Rsi::push(self, &t)?.map(IndicatorValue::Value))
+    }
+
+    fn name(&self) -> &str {
+        "rsi"
+    }
+
+    fn params(&self) -> Value {
+        json!({"period": self.period, "interval_ms": self.interval_ms})
+    }
+
+    /// Complete once `period` price changes have been sampled.
+    fn warm_up_complete(&self) -> bool {
+        self.value.is_some()
+    }
+}
+
+/// Returns a tick with both prices set to `price`.
+#[cfg(test)]
+fn flat_tick(timestamp: u64, price: usize) -> Tick {
+    Tick {timestamp: timestamp, bid: price, ask: price}
+}
+
+/// Matches a 14-period RSI calculated separately over the closing prices of Wilder's example.
+#[test]
+fn rsi_fixture_values() {
+    let closes = [
+        4434, 4409, 4415, 4361, 4433, 4483, 4510, 4542, 4584, 4608, 4589, 4603, 4561, 4628, 4628, 4600, 4603, 4641,
+        4622, 4564,
+    ];
+    let expected = [
+        70.46413502109705, 66.24961855355508, 66.48094183471267, 69.3468531629087, 66.29471265892624,
+        57.91502067008556,
+    ];
+
+    let mut rsi = Rsi::new(14, 1000);
+    let mut values = Vec::new();
+    for (i, &close) in closes.iter().enumerate() {
+        // a tick in the middle of each interval that's replaced by the close right at the boundary
+        if i > 0 {
+            assert!(rsi.push(&flat_tick(i as u64 * 1000 - 500, 1)).is_ok());
+        }
+        values.push(rsi.push(&flat_tick(i as u64 * 1000, close)).unwrap());
+    }
+
+    // 15 samples are needed for 14 changes
+    assert!(values[..14].iter().all(|value| value.is_none()));
+    for (value, expected) in values[14..].iter().zip(expected.iter()) {
+        assert!((value.unwrap() - expected).abs() < 1e-9, "{:?} != {}", value, expected);
+    }
+}
+
+/// Samples are taken at interval boundaries no matter how the ticks are spaced, and gaps count as flat prices.
+#[test]
+fn rsi_irregular_ticks() {
+    let mut rsi = Rsi::new(2, 100);
+    // the first sample is at 100, where the price is still 10
+    assert_eq!(rsi.push(&flat_tick(50, 10)), Ok(None));
+    // sample of 10 at 100
+    assert_eq!(rsi.push(&flat_tick(150, 12)), Ok(None));
+    // sample of 12 at 200, a gain of 2
+    assert_eq!(rsi.push(&flat_tick(250, 11)), Ok(None));
+    // samples of 11 at 300 and 400.  The warm-up averages are a gain of 1 and a loss of 0.5, then the flat change at
+    // 400 smooths them to 0.5 and 0.25 without changing their ratio.
+    let value = rsi.push(&flat_tick(401, 11)).unwrap().unwrap();
+    assert!((value - (100. - 100. / 3.)).abs() < 1e-9);
+
+    assert_eq!(rsi.push(&flat_tick(401, 20)), Err(SmaError::EqualTimestamp));
+    assert_eq!(rsi.push(&flat_tick(5, 20)), Err(SmaError::OutOfOrder{last: 401, got: 5}));
+    assert_eq!(rsi.value, Some(value));
+}
+
+/// A price that never moves has no gains or losses, and one that only rises has an RSI of 100.
+#[test]
+fn rsi_flat_and_rising_prices() {
+    let mut flat = Rsi::new(3, 10);
+    let mut rising = Rsi::new(3, 10);
+    for i in 0..10 {
+        flat.push(&flat_tick(i * 10, 100)).unwrap();
+        rising.push(&flat_tick(i * 10, 100 + i as usize)).unwrap();
+    }
+    assert_eq!(flat.value, Some(FLAT_RSI));
+    assert_eq!(rising.value, Some(100.));
+}
+
+#[bench]
+fn rsi_calculation(b: &mut test::Bencher) {
+    let mut rsi = Rsi::new(14, 1000);
+    let mut timestamp = 1;
+
+    b.iter(|| {
+        rsi.push(&Tick {bid: 1239123 + (timestamp % 7) as usize, ask: 1239125, timestamp: timestamp}).unwrap();
+        timestamp += 20;
+    });
+}
@@ -0,0 +1,318 @@
+//! Live time-weighted simple moving averages of the mid price, each calculated over a period in milliseconds.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+#[allow(unused_imports)]
+use test;
+use serde_json::Value;
+
+use tickgrinder_util::trading::tick::Tick;
+use tickgrinder_util::trading::indicators::SmaError;
+use tickgrinder_util::trading::sma::{Sma, WeightedSums};
+
+use indicators::{Indicator, IndicatorValue};
+#[cfg(test)]
+use indicators::bulk_test_ticks;
+
+impl Indicator for Sma {
+    fn push(&mut self, t: Tick) -> Result<Option<IndicatorValue>, SmaError> {
+        Ok(self.push_f64(t)?.map(IndicatorValue::Value))
+    }
+
+    fn name(&self) -> &str {
+        "sma"
+    }
+
+    fn params(&self) -> Value {
+        json!({"period_ms": self.period})
+    }
+
+    /// Complete once the ticks pushed span the whole period.
+    fn warm_up_complete(&self) -> bool {
+        self.is_warmed_up()
+    }
+}
+
+/// The state of one of the periods calculated by a `MultiSMA`.  It's kept the same way as an `Sma` with that
+/// period, except that its ticks are the part of the shared window starting at `start`.
+pub struct SMAPeriod {
+    /// Length of the window in milliseconds
+    pub period: u64,
+    /// Index of the oldest tick in the period's window, counted from the first tick pushed to the `MultiSMA`
+    start: u64,
+    /// Time-weighted sums over the ticks in the period's window
+    sums: WeightedSums,
+    /// The last tick trimmed from the period's window, or a null tick if none has been
+    ref_tick: Tick,
+    /// The average after the most recent tick, or `None` if no ticks have been pushed since the period was added
+    pub value: Option<f64>,
+    /// The average before the most recent tick
+    pub prev_value: Option<f64>,
+}
+
+impl SMAPeriod {
+    /// Returns true once the ticks pushed since the period was added span the whole period.
+    pub fn is_warmed_up(&self) -> bool {
+        // ticks are only trimmed once they span the period
+        self.ref_tick.bid != 0
+    }
+}
+
+/// Several time-weighted simple moving averages of the same ticks that share one window sized for the longest of
+/// them.  Each period keeps its own running sums and trims the ticks that fall out of it by moving its start
+/// forward in the shared window, so its values are exactly those of an `Sma` with that period that was pushed the
+/// same ticks.
+pub struct MultiSMA {
+    periods: Vec<SMAPeriod>,
+    /// The latest ticks, back to the oldest one in the window of any period
+    window: VecDeque<Tick>,
+    /// Index of the first tick in `window`, counted from the first tick pushed
+    window_start: u64,
+    /// Timestamp of the most recent tick
+    last_timestamp: Option<u64>,
+    /// The outcome of the last push, which tells the `SharedSma`s reading the periods whether the tick was refused
+    last_push: Result<(), SmaError>,
+}
+
+impl MultiSMA {
+    pub fn new() -> MultiSMA {
+        MultiSMA {
+            periods: Vec::new(),
+            window: VecDeque::new(),
+            window_start: 0,
+            last_timestamp: None,
+            last_push: Ok(()),
+        }
+    }
+
+    /// Starts calculating the average over `period` milliseconds if it isn't already.  Its average only includes
+    /// the ticks pushed from now on.
+    pub fn add(&mut self, period: u64) {
+        assert!(period > 0, "SMA period must be greater than zero!");
+        if self.get(period).is_none() {
+            let start = self.window_start + self.window.len() as u64;
+            self.periods.push(SMAPeriod {
+                period: period,
+                start: start,
+                sums: WeightedSums::default(),
+                ref_tick: Tick::null(),
+                value: None,
+                prev_value: None,
+            });
+        }
+    }
+
+    /// Stops calculating the average over `period` milliseconds, dropping the ticks that no other period needs.
+    /// Returns `false` if it wasn't being calculated.
+    pub fn remove(&mut self, period: u64) -> bool {
+        let len = self.periods.len();
+        self.periods.retain(|sma| sma.period != period);
+        self.trim_window();
+        self.periods.len() != len
+    }
+
+    pub fn get(&self, period: u64) -> Option<&SMAPeriod> {
+        self.periods.iter().find(|sma| sma.period == period)
+    }
+
+    /// Returns the number of ticks in the shared window.
+    pub fn buffered(&self) -> usize {
+        self.window.len()
+    }
+
+    /// Returns the periods in the order they were added.
+    pub fn periods(&self) -> &[SMAPeriod] {
+        &self.periods
+    }
+
+    /// Returns true if any ticks have been pushed.
+    pub fn has_ticks(&self) -> bool {
+        self.last_timestamp.is_some()
+    }
+
+    /// Adds a tick to every period.  Ticks that aren't newer than the previous one are refused and leave every
+    /// period unchanged.
+    pub fn push(&mut self, t: &Tick) -> Result<(), SmaError> {
+        self.last_push = self.push_tick(t);
+        self.last_push
+    }
+
+    fn push_tick(&mut self, t: &Tick) -> Result<(), SmaError> {
+        match self.last_timestamp {
+            Some(last) if t.timestamp < last => return Err(SmaError::OutOfOrder{last: last, got: t.timestamp}),
+            Some(last) if t.timestamp == last => return Err(SmaError::EqualTimestamp),
+            _ => (),
+        }
+        self.last_timestamp = Some(t.timestamp);
+
+        let index = self.window_start + self.window.len() as u64;
+        self.window.push_back(*t);
+        {
+            let window = &self.window;
+            let window_start = self.window_start;
+            let tick = |i: u64| &window[(i - window_start) as usize];
+            for sma in self.periods.iter_mut() {
+                if sma.start < index {
+                    sma.sums.update(tick(index - 1), t, true);
+                }
+                // trim the ticks that the period no longer spans, keeping the newest one
+                while sma.start < index && t.timestamp - tick(sma.start).timestamp >= sma.period {
+                    sma.sums.update(tick(sma.start), tick(sma.start + 1), false);
+                    sma.ref_tick = *tick(sma.start);
+                    sma.start += 1;
+                }
+
+                sma.prev_value = sma.value;
+                sma.value = if sma.start == index {
+                    Some(t.mid_f64())
+                } else {
+                    sma.sums.with_ref_tick(&sma.ref_tick, sma.period).average_f64()
+                };
+            }
+        }
+
+        self.trim_window();
+        Ok(())
+    }
+
+    /// Drops the ticks from the front of the window that are older than the window of every period.
+    fn trim_window(&mut self) {
+        let end = self.window_start + self.window.len() as u64;
+        let oldest = self.periods.iter().map(|sma| sma.start).min().unwrap_or(end);
+        while self.window_start < oldest {
+            self.window.pop_front();
+            self.window_start += 1;
+        }
+    }
+}
+
+/// An SMA calculated as one of the periods of a `MultiSMA` that it shares with other SMAs of the same symbol.  The
+/// `IndicatorRegistry` pushes each tick to the `MultiSMA` before pushing it to the SMAs sharing it, which then report
+/// the value of their period.  Dropping it stops its period from being calculated.
+pub struct SharedSma {
+    pub period: u64,
+    group: Arc<Mutex<MultiSMA>>,
+}
+
+impl SharedSma {
+    /// Starts calculating the average over `period` milliseconds as part of `group`.
+    pub fn new(period: u64, group: Arc<Mutex<MultiSMA>>) -> SharedSma {
+        group.lock().unwrap().add(period);
+        SharedSma {
+            period: period,
+            group: group,
+        }
+    }
+}
+
+impl Indicator for SharedSma {
+    /// Returns the value of the period after its `MultiSMA` was pushed `t`, or the reason that it refused `t`.
+    fn push(&mut self, _t: Tick) -> Result<Option<IndicatorValue>, SmaError> {
+        let group = self.group.lock().unwrap();
+        group.last_push?;
+        Ok(group.get(self.period).and_then(|sma| sma.value).map(IndicatorValue::Value))
+    }
+
+    fn name(&self) -> &str {
+        "sma"
+    }
+
+    fn params(&self) -> Value {
+        json!({"period_ms": self.period})
+    }
+
+    /// Complete once the ticks pushed since the period was added span the whole period.
+    fn warm_up_complete(&self) -> bool {
+        self.group.lock().unwrap().get(self.period).map(|sma| sma.is_warmed_up()).unwrap_or(false)
+    }
+}
+
+impl Drop for SharedSma {
+    fn drop(&mut self) {
+        if let Ok(mut group) = self.group.lock() {
+            group.remove(self.period);
+        }
+    }
+}
+
+/// A `MultiSMA` gives exactly the same values as a separate `Sma` for each of its periods over random ticks,
+/// including ones refused for being out of order and periods added after ticks have been pushed.
+#[test]
+fn multi_sma_matches_sma() {
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    let mut rng = XorShiftRng::from_seed([0x5ca1ab1e, 0x0ddba11, 0xdeadbeef, 0x1337]);
+    let mut multi = MultiSMA::new();
+    // each `Sma` along with its last two values
+    let mut smas: Vec<(Sma, Option<f64>, Option<f64>)> = Vec::new();
+    for &period in &[1, 2, 7, 60, 300, 900] {
+        multi.add(period);
+        smas.push((Sma::new(period), None, None));
+    }
+
+    // periods added partway through, right after a tick that was accepted so that the next one isn't refused
+    let late = [(5000, 45), (12000, 1200)];
+    let mut last = 1;
+    for i in 0..20000 {
+        if let Some(&(_, period)) = late.iter().find(|&&(at, _)| at == i) {
+            multi.add(period);
+            smas.push((Sma::new(period), None, None));
+        }
+
+        // every so often, repeat or go back to an older timestamp
+        let t_timestamp = if i % 97 == 96 { last - rng.gen_range(0, 2) } else { last + rng.gen_range(1, 250) };
+        let bid = rng.gen_range(100000, 110000);
+        let t = Tick {bid: bid, ask: bid + rng.gen_range(0, 30), timestamp: t_timestamp};
+        let res = multi.push(&t);
+        for &mut (ref mut sma, ref mut value, ref mut prev_value) in smas.iter_mut() {
+            match sma.push_f64(t) {
+                Ok(new_value) => {
+                    assert!(res.is_ok(), "tick {}", i);
+                    *prev_value = *value;
+                    *value = new_value;
+                },
+                Err(err) => assert_eq!(Err(err), res),
+            }
+        }
+        if res.is_ok() {
+            last = t_timestamp;
+        }
+
+        assert_eq!(multi.periods().len(), smas.len());
+        for (multi_sma, &(ref sma, value, prev_value)) in multi.periods().iter().zip(smas.iter()) {
+            assert_eq!(multi_sma.period, sma.period);
+            assert_eq!((multi_sma.value, multi_sma.prev_value), (value, prev_value), "tick {}", i);
+            assert_eq!(multi_sma.is_warmed_up(), sma.is_warmed_up(), "tick {}", i);
+        }
+    }
+}
+
+/// Ten periods calculated by one `MultiSMA`, which buffers the 500 ticks of the longest one rather than the 2750
+/// held by `separate_sma_calculation`.
+#[bench]
+fn multi_sma_calculation(b: &mut test::Bencher) {
+    let ticks = bulk_test_ticks(10000);
+    b.iter(|| {
+        let mut multi = MultiSMA::new();
+        for period in 1..11 {
+            multi.add(period * 50);
+        }
+        for t in ticks.iter() {
+            multi.push(t).unwrap();
+        }
+        multi.periods().iter().map(|sma| sma.value).collect::<Vec<Option<f64>>>()
+    })
+}
+
+/// Baseline for `multi_sma_calculation`
+#[bench]
+fn separate_sma_calculation(b: &mut test::Bencher) {
+    let ticks = bulk_test_ticks(10000);
+    b.iter(|| {
+        let mut smas: Vec<Sma> = (1..11).map(|period| Sma::new(period * 50)).collect();
+        ticks.iter()
+            .map(|t| smas.iter_mut().map(|sma| sma.push_f64(*t).unwrap()).collect::<Vec<Option<f64>>>())
+            .last()
+    })
+}
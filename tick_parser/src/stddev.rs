@@ -0,0 +1,332 @@
+//! Rolling time-weighted standard deviations of the mid price, or of its log returns for realized volatility,
+//! calculated over a fixed amount of time.
+
+use std::collections::VecDeque;
+
+#[allow(unused_imports)]
+use test;
+
+use serde_json::Value;
+
+use tickgrinder_util::trading::tick::Tick;
+use tickgrinder_util::trading::indicators::SmaError;
+
+use indicators::{Indicator, IndicatorValue};
+
+/// Log returns are stored as integers in units of 10^-12 so that they can share the exact running sums of prices
+pub const LOG_RETURN_SCALE: f64 = 1_000_000_000_000.;
+
+/// What the standard deviation is calculated over
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StdDevSource {
+    /// The mid price, which is assumed to stay at each tick's level until the next tick arrives
+    Price,
+    /// The log return between each pair of consecutive ticks, weighted by the time between them
+    LogReturns,
+}
+
+/// A value of the series that was held for the time between two ticks.  Prices are stored as the bid plus the ask
+/// so that unrounded mid prices stay integers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Segment {
+    value: i64,
+    start: u64,
+    end: u64,
+}
+
+/// Time-weighted sums of the segments' values and squared values, along with the sum of their durations.  The
+/// values are shifted by `shift` before being summed so that the squares stay small no matter how large the prices
+/// are.  They're kept as integers so that adding and removing segments never introduces rounding error, and
+/// moving `shift` to follow the mean is exact.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Moments {
+    time: u64,
+    sum: i128,
+    sum_sq: i128,
+    shift: i64,
+}
+
+impl Moments {
+    /// Adds (or removes) the contribution of `dt` milliseconds at `value`.
+    fn update(&mut self, value: i64, dt: u64, add: bool) {
+        let diff = (value - self.shift) as i128;
+        let (dt_sum, dt_sum_sq) = (dt as i128 * diff, dt as i128 * diff * diff);
+        if add {
+            self.time += dt;
+            self.sum += dt_sum;
+            self.sum_sq += dt_sum_sq;
+        } else {
+            self.time -= dt;
+            self.sum -= dt_sum;
+            self.sum_sq -= dt_sum_sq;
+        }
+    }
+
+    /// Re-expresses the sums relative to a new shift.
+    fn recenter(&mut self, shift: i64) {
+        let delta = (shift - self.shift) as i128;
+        let time = self.time as i128;
+        self.sum_sq = self.sum_sq - 2 * delta * self.sum + delta * delta * time;
+        self.sum -= delta * time;
+        self.shift = shift;
+    }
+}
+
+/// A time-weighted standard deviation over the last `period` milliseconds.  Like the time-weighted SMA, the oldest
+/// segment only counts for the part of it that's inside the window, so the window always covers exactly `period`
+/// once the ticks span it.  Every tick is constant-time apart from evicting the segments that have left the window.
+///
+/// The mean is tracked alongside the standard deviation so that Bollinger Bands can be built from `bands`.
+pub struct RollingStdDev {
+    pub period: u64,
+    pub source: StdDevSource,
+    segments: VecDeque<Segment>,
+    sums: Moments,
+    /// The most recent tick
+    last_tick: Option<Tick>,
+    /// Timestamp of the first tick
+    first_timestamp: Option<u64>,
+    /// The standard deviation after the most recent tick, or `None` until two ticks have been pushed
+    pub value: Option<f64>,
+    mean: Option<f64>,
+}
+
+impl RollingStdDev {
+    pub fn new(period: u64, source: StdDevSource) -> RollingStdDev {
+        assert!(period > 0, "Standard deviation period must be greater than zero!");
+        RollingStdDev {
+            period: period,
+            source: source,
+            segments: VecDeque::new(),
+            sums: Moments::default(),
+            last_tick: None,
+            first_timestamp: None,
+            value: None,
+            mean: None,
+        }
+    }
+
+    /// Adds a tick and returns the new standard deviation, or `None` for the first tick since it doesn't span any
+    /// time.  Ticks that aren't newer than the previous one are refused and leave the standard deviation unchanged.
+    pub fn push(&mut self, t: &Tick) -> Result<Option<f64>, SmaError> {
+        match self.last_tick {
+            Some(last) if t.timestamp < last.timestamp => {
+                return Err(SmaError::OutOfOrder{last: last.timestamp, got: t.timestamp});
+            },
+            Some(last) if t.timestamp == last.timestamp => return Err(SmaError::EqualTimestamp),
+            Some(last) => {
+                let segment = Segment {value: self.sample(&last, t), start: last.timestamp, end: t.timestamp};
+                if self.segments.is_empty() {
+                    self.sums.recenter(segment.value);
+                }
+                self.sums.update(segment.value, segment.end - segment.start, true);
+                self.segments.push_back(segment);
+            },
+            None => self.first_timestamp = Some(t.timestamp),
+        }
+        self.last_tick = Some(*t);
+
+        let window_start = t.timestamp.saturating_sub(self.period);
+        while self.segments.front().map(|segment| segment.end <= window_start).unwrap_or(false) {
+            let segment = self.segments.pop_front().unwrap();
+            self.sums.update(segment.value, segment.end - segment.start, false);
+        }
+        self.update_value(window_start);
+        Ok(self.value)
+    }
+
+    /// The value that the series held between two consecutive ticks
+    fn sample(&self, prev: &Tick, t: &Tick) -> i64 {
+        match self.source {
+            StdDevSource::Price => (prev.bid + prev.ask) as i64,
+            // returns from a null price are meaningless, so they're treated as flat
+            StdDevSource::LogReturns if prev.mid_f64() == 0. || t.mid_f64() == 0. => 0,
+            StdDevSource::LogReturns => ((t.mid_f64() / prev.mid_f64()).ln() * LOG_RETURN_SCALE).round() as i64,
+        }
+    }
+
+    /// Calculates the mean and standard deviation of the window starting at `window_start` and moves the sums'
+    /// shift to the new mean.
+    fn update_value(&mut self, window_start: u64) {
+        let mut sums = self.sums;
+        if let Some(front) = self.segments.front() {
+            if front.start < window_start {
+                sums.update(front.value, window_start - front.start, false);
+            }
+        }
+        if sums.time == 0 {
+            return;
+        }
+
+        let time = sums.time as f64;
+        let mean_diff = sums.sum as f64 / time;
+        let variance = (sums.sum_sq as f64 / time - mean_diff * mean_diff).max(0.);
+        let scale = match self.source {
+            StdDevSource::Price => 2.,
+            StdDevSource::LogReturns => LOG_RETURN_SCALE,
+        };
+        self.mean = Some((sums.shift as f64 + mean_diff) / scale);
+        self.value = Some(variance.sqrt() / scale);
+
+        // keeping the shift close to the mean keeps the variance from being the difference of two huge numbers
+        let shift = self.sums.shift + mean_diff.round() as i64;
+        self.sums.recenter(shift);
+    }
+
+    /// Returns the time-weighted mean of the series over the window, or `None` until two ticks have been pushed.
+    pub fn mean(&self) -> Option<f64> {
+        self.mean
+    }
+
+    /// Returns the lower and upper bands `width` standard deviations below and above the mean.
+    pub fn bands(&self, width: f64) -> Option<(f64, f64)> {
+        match (self.mean, self.value) {
+            (Some(mean), Some(std_dev)) => Some((mean - width * std_dev, mean + width * std_dev)),
+            _ => None,
+        }
+    }
+
+    /// Returns true once the ticks pushed have spanned at least a whole period.
+    pub fn is_warmed_up(&self) -> bool {
+        match (self.first_timestamp, self.last_tick) {
+            (Some(first), Some(last)) => last.timestamp - first >= self.period,
+            _ => false,
+        }
+    }
+}
+
+impl Indicator for RollingStdDev {
+    fn push(&mut self, t: Tick) -> Result<Option<IndicatorValue>, SmaError> {
+        Ok(RollingStdDev::push(self, &t)?.map(IndicatorValue::Value))
+    }
+
+    fn name(&self) -> &str {
+        "stddev"
+    }
+
+    fn params(&self) -> Value {
+        json!({"period_ms": self.period, "log_returns": self.source == StdDevSource::LogReturns})
+    }
+
+    fn warm_up_complete(&self) -> bool {
+        self.is_warmed_up()
+    }
+}
+
+/// Calculates the mean and standard deviation of the window by walking all of the ticks in it with floating point
+/// math, subtracting the mean before squaring.
+#[cfg(test)]
+fn windowed_std_dev(ticks: &[Tick], period: u64, source: StdDevSource) -> Option<(f64, f64)> {
+    let last = ticks.last().unwrap().timestamp;
+    let window_start = last.saturating_sub(period);
+    let samples: Vec<(f64, f64)> = ticks.windows(2).filter_map(|pair| {
+        let (prev, t) = (pair[0], pair[1]);
+        let dt = t.timestamp.saturating_sub(window_start.max(prev.timestamp));
+        if dt == 0 {
+            return None;
+        }
+        let value = match source {
+            StdDevSource::Price => prev.mid_f64(),
+            StdDevSource::LogReturns => (t.mid_f64() / prev.mid_f64()).ln(),
+        };
+        Some((value, dt as f64))
+    }).collect();
+    if samples.is_empty() {
+        return None;
+    }
+
+    let time: f64 = samples.iter().map(|&(_, dt)| dt).sum();
+    let mean = samples.iter().map(|&(value, dt)| value * dt).sum::<f64>() / time;
+    let variance = samples.iter().map(|&(value, dt)| (value - mean) * (value - mean) * dt).sum::<f64>() / time;
+    Some((mean, variance.sqrt()))
+}
+
+/// Ticks with a given timestamp in milliseconds and a price used for both the bid and ask
+#[cfg(test)]
+fn price_ticks(prices: &[(u64, usize)]) -> Vec<Tick> {
+    prices.iter().map(|&(timestamp, price)| Tick {timestamp: timestamp, bid: price, ask: price}).collect()
+}
+
+/// Matches values calculated by hand, including a window that starts partway through a segment.
+#[test]
+fn std_dev_known_values() {
+    let ticks = price_ticks(&[(0, 100), (10, 102), (20, 100), (30, 102), (35, 110), (40, 104)]);
+    let mut std_dev = RollingStdDev::new(20, StdDevSource::Price);
+    assert_eq!(std_dev.push(&ticks[0]), Ok(None));
+    assert_eq!(std_dev.push(&ticks[1]), Ok(Some(0.)));
+    assert!(!std_dev.is_warmed_up());
+    // 100 for 10ms and 102 for 10ms
+    assert_eq!(std_dev.push(&ticks[2]), Ok(Some(1.)));
+    assert_eq!(std_dev.mean(), Some(101.));
+    assert_eq!(std_dev.bands(2.), Some((99., 103.)));
+    assert!(std_dev.is_warmed_up());
+    assert_eq!(std_dev.push(&ticks[3]), Ok(Some(1.)));
+    // only the last 5ms of the 102 from 10 to 20 are in the window
+    assert_eq!(std_dev.push(&ticks[4]), Ok(Some(1.)));
+    // 100 for 10ms, 102 for 5ms, and 110 for 5ms; mean 103 and variance (10 * 9 + 5 * 1 + 5 * 49) / 20 = 17
+    assert_eq!(std_dev.push(&ticks[5]), Ok(Some(17f64.sqrt())));
+    assert_eq!(std_dev.mean(), Some(103.));
+
+    assert_eq!(std_dev.push(&Tick {timestamp: 40, bid: 1, ask: 1}), Err(SmaError::EqualTimestamp));
+    assert_eq!(std_dev.push(&Tick {timestamp: 39, bid: 1, ask: 1}), Err(SmaError::OutOfOrder{last: 40, got: 39}));
+    assert_eq!(std_dev.value, Some(17f64.sqrt()));
+}
+
+/// Prices whose squared sums would overflow 64-bit integers, with jumps that move the mean far from where it
+/// started, match the standard deviation calculated by walking the window.
+#[test]
+fn std_dev_large_prices() {
+    let mut ticks = Vec::new();
+    for i in 0..2000u64 {
+        let base = if i < 1000 { 4_000_000_000 } else { 9_000_000_000 };
+        let price = base + (i as usize * 37 % 101) * 1000;
+        ticks.push(Tick {timestamp: i * 7 + (i % 5), bid: price, ask: price + (i as usize % 3)});
+    }
+
+    for &period in &[50, 1000, 100000] {
+        let mut std_dev = RollingStdDev::new(period, StdDevSource::Price);
+        for i in 0..ticks.len() {
+            let value = std_dev.push(&ticks[i]).unwrap();
+            if i == 0 {
+                continue;
+            }
+            let (mean, expected) = windowed_std_dev(&ticks[..i + 1], period, StdDevSource::Price).unwrap();
+            let value = value.unwrap();
+            assert!((value - expected).abs() <= expected * 1e-9 + 1e-6, "{} vs {} at {}", value, expected, i);
+            assert!((std_dev.mean().unwrap() - mean).abs() <= mean * 1e-12, "{} at {}", mean, i);
+        }
+    }
+}
+
+/// Log returns are weighted by the time between the ticks they're calculated from.
+#[test]
+fn std_dev_log_returns() {
+    let ticks = price_ticks(&[(0, 100), (10, 110), (20, 100), (40, 110), (50, 121), (60, 121)]);
+    let mut volatility = RollingStdDev::new(40, StdDevSource::LogReturns);
+    assert_eq!(volatility.params(), json!({"period_ms": 40, "log_returns": true}));
+    for i in 0..ticks.len() {
+        let value = volatility.push(&ticks[i]).unwrap();
+        if i == 0 {
+            continue;
+        }
+        let (_, expected) = windowed_std_dev(&ticks[..i + 1], 40, StdDevSource::LogReturns).unwrap();
+        assert!((value.unwrap() - expected).abs() < 1e-9, "{:?} vs {} at {}", value, expected, i);
+    }
+
+    // a flat price has no volatility once its returns fill the window
+    for timestamp in 61..101 {
+        volatility.push(&Tick {timestamp: timestamp, bid: 121, ask: 121}).unwrap();
+    }
+    assert_eq!(volatility.value, Some(0.));
+}
+
+#[bench]
+fn std_dev_calculation(b: &mut test::Bencher) {
+    let mut std_dev = RollingStdDev::new(60 * 60 * 1000, StdDevSource::Price);
+    let mut timestamp = 1;
+
+    b.iter(|| {
+        std_dev.push(&Tick {bid: 1239123 + (timestamp as usize % 13), ask: 1239125, timestamp: timestamp}).unwrap();
+        timestamp += 20;
+    });
+}
@@ -0,0 +1,279 @@
+//! Records the ticks that the Tick Processor receives to Postgres.  Ticks are collected into batches that are written
+//! with a single insert once they're large enough or have been waiting long enough, which is checked whenever a tick
+//! arrives and by `flush_if_due` while none are arriving.  Ticks that can't be written stay
+//! buffered and are retried, with the oldest being dropped once too many of them have built up.  Anything else that
+//! implements `BatchRow`, such as indicator values, can be written the same way.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use postgres::Connection;
+
+use tickgrinder_util::trading::tick::Tick;
+use tickgrinder_util::transport::postgres::{get_client, init_hist_data_table};
+use tickgrinder_util::conf::CONF;
+
+/// A row of a table that a `BatchWriter` inserts into
+pub trait BatchRow {
+    /// Creates the table if it doesn't exist.
+    fn init_table(table: &str, client: &Connection) -> Result<(), String>;
+
+    /// The columns that the values of each row are inserted into, separated by commas
+    fn columns() -> &'static str;
+
+    /// The row's values as a parenthesized, comma-separated SQL tuple
+    fn values(&self) -> String;
+
+    /// What to do about rows that conflict with existing ones, such as `ON CONFLICT DO NOTHING`
+    fn on_conflict() -> &'static str {
+        ""
+    }
+}
+
+impl BatchRow for Tick {
+    fn init_table(table: &str, client: &Connection) -> Result<(), String> {
+        init_hist_data_table(table, client, CONF.postgres_user)
+    }
+
+    fn columns() -> &'static str {
+        "tick_time, bid, ask"
+    }
+
+    fn values(&self) -> String {
+        format!("({}, {}, {})", self.timestamp, self.bid, self.ask)
+    }
+
+    // a duplicated tick would otherwise fail the whole batch every time it was retried
+    fn on_conflict() -> &'static str {
+        "ON CONFLICT (tick_time) DO NOTHING"
+    }
+}
+
+pub type TickSink = BatchWriter<Tick>;
+
+pub struct BatchWriter<R: BatchRow> {
+    pub table: String,
+    /// Rows waiting to be written, oldest first
+    batch: VecDeque<R>,
+    /// Number of buffered rows at which the batch is written
+    batch_size: usize,
+    /// How long rows can wait before the batch is written even if it isn't full
+    max_delay: Duration,
+    /// Most rows that can be buffered while writes are failing before the oldest are dropped
+    max_buffered: usize,
+    /// When the first row of the batch arrived or, after a failed write, when the write was attempted
+    batch_started: Option<Instant>,
+    /// True if the last write failed, in which case it isn't retried until `max_delay` has passed
+    failing: bool,
+    client: Option<Connection>,
+    written: u64,
+    dropped: u64,
+}
+
+impl<R: BatchRow> BatchWriter<R> {
+    /// Creates a writer that inserts rows into `table`, creating it if it doesn't exist.
+    pub fn new(
+        table: &str, batch_size: usize, max_delay_ms: u64, max_buffered: usize
+    ) -> Result<BatchWriter<R>, String> {
+        // base 36 digits are exactly the ASCII letters and numbers
+        if table.is_empty() || !table.chars().all(|c| c.is_digit(36) || c == '_') {
+            return Err(format!("Invalid table name: {}", table));
+        }
+        let client = get_client().map_err(|err| format!("Unable to connect to Postgres: {:?}", err))?;
+        R::init_table(table, &client)?;
+
+        Ok(BatchWriter {
+            table: String::from(table),
+            batch: VecDeque::with_capacity(batch_size),
+            batch_size: batch_size,
+            max_delay: Duration::from_millis(max_delay_ms),
+            max_buffered: max_buffered,
+            batch_started: None,
+            failing: false,
+            client: Some(client),
+            written: 0,
+            dropped: 0,
+        })
+    }
+
+    /// Creates a writer that inserts rows into `table` with the batching settings from the config.
+    pub fn from_conf(table: &str) -> Result<BatchWriter<R>, String> {
+        BatchWriter::new(table, CONF.tick_sink_batch_size, CONF.tick_sink_flush_ms as u64, CONF.tick_sink_max_buffered)
+    }
+
+    /// Adds a row to the batch, writing the batch if it's full or has been waiting for too long.
+    pub fn push(&mut self, row: R) {
+        if self.batch.len() >= self.max_buffered {
+            self.batch.pop_front();
+            self.dropped += 1;
+        }
+        self.batch.push_back(row);
+        if self.batch_started.is_none() {
+            self.batch_started = Some(Instant::now());
+        }
+
+        if (self.batch.len() >= self.batch_size && !self.failing) || self.waited_too_long() {
+            self.flush_buffered();
+        }
+    }
+
+    /// Writes the batch if it has been waiting for too long.  This is called periodically so that a partial batch
+    /// doesn't wait for more rows once they stop arriving.
+    pub fn flush_if_due(&mut self) {
+        if self.waited_too_long() {
+            self.flush_buffered();
+        }
+    }
+
+    fn waited_too_long(&self) -> bool {
+        self.batch_started.map(|started| started.elapsed() >= self.max_delay) == Some(true)
+    }
+
+    /// Writes the batch, logging the error and keeping the rows if the write fails.
+    fn flush_buffered(&mut self) {
+        if let Err(err) = self.flush() {
+            println!("{}; {} rows are buffered", err, self.batch.len());
+        }
+    }
+
+    /// Writes all buffered rows, returning how many there were.  If the write fails, they're kept to be retried.
+    pub fn flush(&mut self) -> Result<usize, String> {
+        if self.batch.is_empty() {
+            return Ok(0);
+        }
+
+        match self.write_batch() {
+            Ok(()) => {
+                let n = self.batch.len();
+                self.batch.clear();
+                self.batch_started = None;
+                self.failing = false;
+                self.written += n as u64;
+                Ok(n)
+            },
+            Err(err) => {
+                // reconnect on the next attempt in case the connection is what failed
+                self.client = None;
+                self.batch_started = Some(Instant::now());
+                self.failing = true;
+                Err(err)
+            },
+        }
+    }
+
+    fn write_batch(&mut self) -> Result<(), String> {
+        if self.client.is_none() {
+            let client = get_client().map_err(|err| format!("Unable to connect to Postgres: {:?}", err))?;
+            self.client = Some(client);
+        }
+
+        let values: Vec<String> = self.batch.iter().map(R::values).collect();
+        let query = format!(
+            "INSERT INTO {} ({}) VALUES {} {};",
+            self.table,
+            R::columns(),
+            values.join(", "),
+            R::on_conflict()
+        );
+        self.client.as_ref().unwrap().execute(&query, &[])
+            .map(|_| ())
+            .map_err(|err| format!("Unable to write to {}: {}", self.table, err))
+    }
+
+    /// Returns the number of rows waiting to be written.
+    pub fn buffered(&self) -> usize {
+        self.batch.len()
+    }
+
+    /// Returns the number of rows that have been written.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Returns the number of rows that were dropped because too many were buffered while writes were failing.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+fn count_rows(client: &Connection, table: &str) -> i64 {
+    let rows = client.query(&format!("SELECT COUNT(*) FROM {};", table), &[]).unwrap();
+    rows.get(0).get(0)
+}
+
+/// Ticks are only written once a full batch of them has arrived or they're flushed.
+#[test]
+fn tick_sink_batching() {
+    let table = format!("ticks_sink_test_{}", ::uuid::Uuid::new_v4().simple());
+    let client = get_client().unwrap();
+    // the flush interval is long enough that only full batches are written
+    let mut sink = TickSink::new(&table, 5, 3600 * 1000, 100).unwrap();
+
+    for i in 0..4 {
+        sink.push(Tick {timestamp: i, bid: 100, ask: 102});
+    }
+    assert_eq!(count_rows(&client, &table), 0);
+    sink.push(Tick {timestamp: 4, bid: 100, ask: 102});
+    assert_eq!(count_rows(&client, &table), 5);
+    assert_eq!(sink.buffered(), 0);
+
+    for i in 5..8 {
+        sink.push(Tick {timestamp: i, bid: 100, ask: 102});
+    }
+    assert_eq!(count_rows(&client, &table), 5);
+    assert_eq!(sink.flush(), Ok(3));
+    assert_eq!(count_rows(&client, &table), 8);
+    assert_eq!(sink.written(), 8);
+
+    client.execute(&format!("DROP TABLE {};", table), &[]).unwrap();
+}
+
+/// A partial batch is written once it has waited for the flush interval even if no more ticks arrive.
+#[test]
+fn tick_sink_flush_interval() {
+    use std::thread;
+
+    let table = format!("ticks_sink_test_{}", ::uuid::Uuid::new_v4().simple());
+    let client = get_client().unwrap();
+    let mut sink = TickSink::new(&table, 5, 50, 100).unwrap();
+
+    sink.push(Tick {timestamp: 0, bid: 100, ask: 102});
+    sink.push(Tick {timestamp: 1, bid: 100, ask: 102});
+    sink.flush_if_due();
+    assert_eq!(count_rows(&client, &table), 0);
+
+    thread::sleep(Duration::from_millis(60));
+    sink.flush_if_due();
+    assert_eq!(count_rows(&client, &table), 2);
+    assert_eq!(sink.buffered(), 0);
+    assert_eq!(sink.written(), 2);
+
+    client.execute(&format!("DROP TABLE {};", table), &[]).unwrap();
+}
+
+/// Ticks that can't be written are buffered up to a limit and written once the table is writable again.
+#[test]
+fn tick_sink_failed_writes() {
+    let table = format!("ticks_sink_test_{}", ::uuid::Uuid::new_v4().simple());
+    let client = get_client().unwrap();
+    let mut sink = TickSink::new(&table, 2, 3600 * 1000, 4).unwrap();
+    client.execute(&format!("DROP TABLE {};", table), &[]).unwrap();
+
+    for i in 0..6 {
+        sink.push(Tick {timestamp: i, bid: 100, ask: 102});
+    }
+    assert_eq!(sink.buffered(), 4);
+    assert_eq!(sink.dropped(), 2);
+    assert_eq!(sink.written(), 0);
+
+    init_hist_data_table(&table, &client, CONF.postgres_user).unwrap();
+    assert_eq!(sink.flush(), Ok(4));
+    let rows = client.query(&format!("SELECT MIN(tick_time) FROM {};", table), &[]).unwrap();
+    let oldest: i64 = rows.get(0).get(0);
+    assert_eq!(oldest, 2);
+    assert_eq!(count_rows(&client, &table), 4);
+
+    client.execute(&format!("DROP TABLE {};", table), &[]).unwrap();
+    assert!(TickSink::new("ticks; DROP TABLE ticks", 2, 1000, 4).is_err());
+}
@@ -13,8 +13,10 @@ use tickgrinder_util::transport::postgres;
 use tickgrinder_util::transport::query_server::QueryServer;
 use tickgrinder_util::transport::command_server::*;
 use tickgrinder_util::trading::tick::{Tick, SymbolTick};
+use tickgrinder_util::trading::indicators::CrossoverEvent;
 use tickgrinder_util::conf::CONF;
 use processor::Processor;
+use indicators::IndicatorValue;
 
 #[test]
 fn postgres_tick_insertion() {
@@ -60,6 +62,275 @@ fn tick_ingestion() {
     // TODO: Update to modern tick processing stuff
 }
 
+/// Recorded ticks that haven't filled a batch yet are written when the processor is flushed on shutdown.
+#[test]
+fn recorded_ticks_flushed_on_shutdown() {
+    let table = format!("ticks_record_test_{}", Uuid::new_v4().simple());
+    let mut processor = Processor::new("test9".to_string(), &Uuid::new_v4());
+    assert_eq!(processor.primary().record_ticks(true, Some(table.clone())), Response::Ok);
+    for timestamp in 1..11 {
+        processor.process(Tick {timestamp: timestamp, bid: 100, ask: 102});
+    }
+
+    let client = postgres::get_client().unwrap();
+    let count_query = format!("SELECT COUNT(*) FROM {};", table);
+    let count: i64 = client.query(&count_query, &[]).unwrap().get(0).get(0);
+    assert_eq!(count, 0);
+    processor.flush();
+    let count: i64 = client.query(&count_query, &[]).unwrap().get(0).get(0);
+    assert_eq!(count, 10);
+
+    // stopping recording writes whatever is still buffered as well
+    processor.process(Tick {timestamp: 11, bid: 100, ask: 102});
+    assert_eq!(processor.primary().record_ticks(false, None), Response::Ok);
+    assert!(processor.primary().tick_sink.is_none());
+    let count: i64 = client.query(&count_query, &[]).unwrap().get(0).get(0);
+    assert_eq!(count, 11);
+
+    client.execute(&format!("DROP TABLE {};", table), &[]).unwrap();
+}
+
+/// Interleaved ticks of two symbols only reach the indicators of their own symbol.
+#[test]
+fn multiple_symbols() {
+    let mut processor = Processor::new("test10a".to_string(), &Uuid::new_v4());
+    assert_eq!(processor.add_symbol("test10b".to_string()), Response::Ok);
+    assert!(processor.add_symbol("test10b".to_string()) != Response::Ok);
+
+    let (id_a, id_b) = {
+        let mut add_sma = |symbol: Option<&str>| {
+            let cmd = Command::AddIndicator{
+                kind: String::from("sma"),
+                params: json!({"period_ms": 2}),
+                throttle_ms: None,
+                hold_until_warm: false,
+                symbol: symbol.map(String::from),
+            };
+            match processor.execute_symbol_command(cmd) {
+                Response::Info{info} => Uuid::parse_str(&info).unwrap(),
+                res => panic!("Unexpected response to AddIndicator: {:?}", res),
+            }
+        };
+        // the primary symbol is used if the command doesn't name one
+        (add_sma(None), add_sma(Some("test10b")))
+    };
+
+    for timestamp in 1..6 {
+        processor.process(Tick {timestamp: timestamp, bid: 100, ask: 102});
+        processor.process_symbol("test10b", Tick {timestamp: timestamp, bid: 200, ask: 202});
+        // ticks of symbols that aren't followed are ignored
+        processor.process_symbol("test10c", Tick {timestamp: timestamp, bid: 300, ask: 302});
+    }
+
+    assert_eq!(processor.symbols["test10a"].indicators.value(id_a), Some(IndicatorValue::Value(101.)));
+    assert_eq!(processor.symbols["test10b"].indicators.value(id_b), Some(IndicatorValue::Value(201.)));
+    assert_eq!(processor.symbols["test10a"].indicators.value(id_b), None);
+    assert_eq!(processor.symbols["test10a"].ticks.len(), 5);
+    assert_eq!(processor.symbols["test10b"].ticks.len(), 5);
+    assert_eq!(processor.symbol_list(), vec![String::from("test10a"), String::from("test10b")]);
+
+    // the primary symbol stays and the others' indicators go with them
+    assert!(processor.remove_symbol("test10a") != Response::Ok);
+    assert_eq!(processor.remove_symbol("test10b"), Response::Ok);
+    let remove = Command::RemoveIndicator{id: id_b, symbol: Some(String::from("test10b"))};
+    assert!(processor.execute_symbol_command(remove) != Response::Ok);
+}
+
+/// Indicator snapshots hold the latest value of every indicator and whether it's still warming up, with null values
+/// for those that haven't seen any ticks.
+#[test]
+fn indicator_snapshot() {
+    let mut processor = Processor::new("test11a".to_string(), &Uuid::new_v4());
+    assert_eq!(processor.add_symbol("test11b".to_string()), Response::Ok);
+    let (fast_id, slow_id, other_id) = {
+        let mut add_sma = |period_ms: u64, symbol: Option<&str>| {
+            let cmd = Command::AddIndicator{
+                kind: String::from("sma"),
+                params: json!({"period_ms": period_ms}),
+                throttle_ms: None,
+                hold_until_warm: false,
+                symbol: symbol.map(String::from),
+            };
+            match processor.execute_symbol_command(cmd) {
+                Response::Info{info} => Uuid::parse_str(&info).unwrap().hyphenated().to_string(),
+                res => panic!("Unexpected response to AddIndicator: {:?}", res),
+            }
+        };
+        (add_sma(1, None), add_sma(3, None), add_sma(1, Some("test11b")))
+    };
+
+    processor.process(Tick {timestamp: 1, bid: 100, ask: 102});
+    processor.process(Tick {timestamp: 2, bid: 104, ask: 106});
+
+    let snapshot = processor.indicator_snapshot(None).unwrap();
+    assert_eq!(snapshot.as_object().unwrap().len(), 3);
+    let output = json!({"RedisChannel": {"host": CONF.redis_host, "channel": "indicators_test11a"}});
+    assert_eq!(snapshot[&fast_id], json!({
+        "symbol": "test11a", "kind": "sma", "params": {"period_ms": 1},
+        "value": 105., "timestamp": 2, "warm_up_complete": true, "output": output,
+    }));
+    assert_eq!(snapshot[&slow_id], json!({
+        "symbol": "test11a", "kind": "sma", "params": {"period_ms": 3},
+        "value": null, "timestamp": 2, "warm_up_complete": false, "output": output,
+    }));
+    assert_eq!(snapshot[&other_id]["value"], json!(null));
+
+    processor.process(Tick {timestamp: 3, bid: 108, ask: 110});
+    processor.process(Tick {timestamp: 4, bid: 112, ask: 114});
+    let snapshot = processor.indicator_snapshot(Some("test11a")).unwrap();
+    assert_eq!(snapshot.as_object().unwrap().len(), 2);
+    // (101 + 105 + 109) / 3, since each price is held for 1ms
+    assert_eq!(snapshot[&slow_id]["value"], json!(105.));
+    assert_eq!(snapshot[&slow_id]["timestamp"], json!(4));
+    assert_eq!(snapshot[&slow_id]["warm_up_complete"], json!(true));
+    assert!(processor.indicator_snapshot(Some("test11c")).is_err());
+}
+
+/// Switching the output of all indicators or of a single one while ticks are being processed sends the values
+/// produced from then on to the new output and none of them to the old one.
+#[test]
+fn indicator_output_switching() {
+    let mut processor = Processor::new("test12a".to_string(), &Uuid::new_v4());
+    let (fast, slow) = {
+        let mut add_sma = |period_ms: u64| {
+            let cmd = Command::AddIndicator{
+                kind: String::from("sma"),
+                params: json!({"period_ms": period_ms}),
+                throttle_ms: None,
+                hold_until_warm: false,
+                symbol: None,
+            };
+            match processor.execute_symbol_command(cmd) {
+                Response::Info{info} => Uuid::parse_str(&info).unwrap(),
+                res => panic!("Unexpected response to AddIndicator: {:?}", res),
+            }
+        };
+        (add_sma(1), add_sma(2))
+    };
+
+    let channel_a = format!("indicator_output_a_{}", Uuid::new_v4().simple());
+    let channel_b = format!("indicator_output_b_{}", Uuid::new_v4().simple());
+    let rx_a = sub_channel(CONF.redis_host, &channel_a);
+    let rx_b = sub_channel(CONF.redis_host, &channel_b);
+    let redis_dest = |channel: &str| IndicatorDest::RedisChannel{
+        host: String::from(CONF.redis_host), channel: String::from(channel),
+    };
+    let table = format!("indicator_output_test_{}", Uuid::new_v4().simple());
+    let client = postgres::get_client().unwrap();
+    let count_query = format!("SELECT COUNT(*) FROM {};", table);
+
+    assert_eq!(processor.set_indicator_output(redis_dest(&channel_a), None), Response::Ok);
+    for timestamp in 1..4 {
+        processor.process(Tick {timestamp: timestamp, bid: 100, ask: 102});
+    }
+    assert_eq!(processor.set_indicator_output(redis_dest(&channel_b), Some(slow)), Response::Ok);
+    let snapshot = processor.indicator_snapshot(None).unwrap();
+    assert_eq!(snapshot[&fast.hyphenated().to_string()]["output"], json!(redis_dest(&channel_a)));
+    assert_eq!(snapshot[&slow.hyphenated().to_string()]["output"], json!(redis_dest(&channel_b)));
+    for timestamp in 4..6 {
+        processor.process(Tick {timestamp: timestamp, bid: 100, ask: 102});
+    }
+
+    // the slow SMA's own output is dropped along with the Redis ones
+    assert_eq!(processor.set_indicator_output(IndicatorDest::Null, None), Response::Ok);
+    for timestamp in 6..8 {
+        processor.process(Tick {timestamp: timestamp, bid: 100, ask: 102});
+    }
+    assert_eq!(processor.set_indicator_output(IndicatorDest::Postgres{table: table.clone()}, None), Response::Ok);
+    for timestamp in 8..10 {
+        processor.process(Tick {timestamp: timestamp, bid: 100, ask: 102});
+    }
+    // the values held back by the Postgres output are written when it's replaced
+    let count: i64 = client.query(&count_query, &[]).unwrap().get(0).get(0);
+    assert_eq!(count, 0);
+    assert_eq!(processor.set_indicator_output(redis_dest(&channel_a), None), Response::Ok);
+    let count: i64 = client.query(&count_query, &[]).unwrap().get(0).get(0);
+    assert_eq!(count, 4);
+    processor.process(Tick {timestamp: 10, bid: 100, ask: 102});
+    assert_eq!(processor.set_indicator_output(redis_dest(&channel_b), Some(slow)), Response::Ok);
+    processor.process(Tick {timestamp: 11, bid: 100, ask: 102});
+
+    let parse = |msg: String| -> (Uuid, u64) {
+        let msg: ::serde_json::Value = ::serde_json::from_str(&msg).unwrap();
+        (Uuid::parse_str(msg["indicator_id"].as_str().unwrap()).unwrap(), msg["timestamp"].as_u64().unwrap())
+    };
+    let received: Vec<(Uuid, u64)> = rx_a.wait().take(11).map(|msg| parse(msg.unwrap())).collect();
+    assert_eq!(received, vec![
+        (fast, 1), (slow, 1), (fast, 2), (slow, 2), (fast, 3), (slow, 3),
+        (fast, 4), (fast, 5), (fast, 10), (slow, 10), (fast, 11),
+    ]);
+    let received: Vec<(Uuid, u64)> = rx_b.wait().take(3).map(|msg| parse(msg.unwrap())).collect();
+    assert_eq!(received, vec![(slow, 4), (slow, 5), (slow, 11)]);
+
+    let rows = client.query(&format!("SELECT indicator_id, value_time FROM {} ORDER BY id;", table), &[]).unwrap();
+    let rows: Vec<(String, i64)> = rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+    assert_eq!(rows, vec![
+        (fast.hyphenated().to_string(), 8), (slow.hyphenated().to_string(), 8),
+        (fast.hyphenated().to_string(), 9), (slow.hyphenated().to_string(), 9),
+    ]);
+
+    let res = processor.set_indicator_output(IndicatorDest::Null, Some(Uuid::new_v4()));
+    assert!(res != Response::Ok);
+    // an output that can't be opened leaves the current one in place
+    let res = processor.set_indicator_output(IndicatorDest::Postgres{table: String::from("values; DROP")}, None);
+    assert!(res != Response::Ok);
+    assert_eq!(processor.indicator_dest, Some(redis_dest(&channel_a)));
+
+    client.execute(&format!("DROP TABLE {};", table), &[]).unwrap();
+}
+
+/// The SMAs of a crossover registered with `RegisterCrossover` are added to the symbol's indicators like any others,
+/// and its crossings are published to the channel it was registered with.
+#[test]
+fn registered_crossovers() {
+    let mut processor = Processor::new("test13a".to_string(), &Uuid::new_v4());
+    let channel = format!("crossovers_{}", Uuid::new_v4().simple());
+    let rx = sub_channel(CONF.redis_host, &channel);
+    let register = |fast_period: u64| Command::RegisterCrossover{
+        fast_period: fast_period, slow_period: 4, channel: channel.clone(), symbol: None,
+    };
+    assert_eq!(processor.execute_symbol_command(register(2)), Response::Ok);
+    assert!(processor.execute_symbol_command(register(0)) != Response::Ok);
+
+    for (i, &price) in [10, 9, 8, 7, 6, 7, 8, 9, 10, 9, 8, 7].iter().enumerate() {
+        processor.process(Tick {timestamp: i as u64 + 1, bid: price, ask: price});
+    }
+    let events: Vec<CrossoverEvent> = rx.wait().take(2)
+        .map(|msg| ::serde_json::from_str(&msg.unwrap()).unwrap())
+        .collect();
+    assert_eq!(events, vec![
+        CrossoverEvent::BullishCross{timestamp: 8, fast_value: 7.5, slow_value: 7.},
+        CrossoverEvent::BearishCross{timestamp: 12, fast_value: 8.5, slow_value: 9.},
+    ]);
+
+    let list = processor.primary().indicators.list();
+    let kinds: Vec<&str> = list.as_array().unwrap().iter().map(|entry| entry["kind"].as_str().unwrap()).collect();
+    assert_eq!(kinds, vec!["sma", "sma", "crossover"]);
+}
+
+/// SMAs added with `AddSMAPeriod` are the same indicators as the ones used by crossovers, so removing one also stops
+/// the crossovers that depend on it.
+#[test]
+fn sma_period_commands() {
+    let mut processor = Processor::new("test14a".to_string(), &Uuid::new_v4());
+    let add = Command::AddSMAPeriod{period_ms: 2, symbol: None};
+    assert_eq!(processor.execute_symbol_command(add), Response::Ok);
+    let register = Command::RegisterCrossover{
+        fast_period: 2, slow_period: 4, channel: String::from("crossovers_test14a"), symbol: None,
+    };
+    assert_eq!(processor.execute_symbol_command(register), Response::Ok);
+    assert_eq!(processor.primary().indicators.list().as_array().unwrap().len(), 3);
+    assert!(processor.execute_symbol_command(Command::AddSMAPeriod{period_ms: 0, symbol: None}) != Response::Ok);
+
+    let remove = |period_ms: u64| Command::RemoveSMAPeriod{period_ms: period_ms, symbol: None};
+    assert_eq!(processor.execute_symbol_command(remove(2)), Response::Ok);
+    assert!(processor.execute_symbol_command(remove(2)) != Response::Ok);
+    let list = processor.primary().indicators.list();
+    assert_eq!(list[0]["params"], json!({"period_ms": 4}));
+    assert_eq!(list.as_array().unwrap().len(), 1);
+    assert!(processor.primary().crossing_channels.is_empty());
+}
+
 #[test]
 fn command_server_broadcast() {
     use std::str::FromStr;
@@ -11,6 +11,7 @@ const REDIS_CHANNEL = 2; // { host: String, channel: String },
 const REDIS_SET = 3; // { host: String, set_name: String },
 const CONSOLE = 4;
 const CSV = 5;
+const ARROW = 6; // { path: String }
 
 const POLONIEX_BOOK_MODIFY = 25;
 const POLONIEX_BOOK_REMOVE = 26;
@@ -80,6 +81,7 @@ module.exports = {
   REDIS_SET: REDIS_SET,
   CONSOLE: CONSOLE,
   CSV: CSV,
+  ARROW: ARROW,
 
   POLONIEX_BOOK_MODIFY: POLONIEX_BOOK_MODIFY,
   POLONIEX_BOOK_REMOVE: POLONIEX_BOOK_REMOVE,
@@ -23,9 +23,8 @@ pub trait PlatformInstance {
         let redis_client = get_client(CONF.redis_host);
 
         // Signal to the platform that we're ready to receive commands
-        let _ = send_command(&WrappedCommand::from_command(
-            Command::Ready{instance_type: "Backtester".to_string(), uuid: uuid}), &redis_client, "control"
-        );
+        let ready = Command::Ready{instance_type: "Backtester".to_string(), uuid: uuid, depends_on: Vec::new()};
+        let _ = send_command(&WrappedCommand::from_command(ready), &redis_client, "control");
 
         for res in rx.wait() {
             let (_, msg) = res.expect("Received err in the listen() event loop for the backtester!");
@@ -37,6 +36,7 @@ pub trait PlatformInstance {
                 }
             };
 
+            let _span = wr_cmd.span(&cs.instance_type());
             let res: Option<Response> = self.handle_command(wr_cmd.cmd);
             if res.is_some() {
                 redis::cmd("PUBLISH")
@@ -48,5 +48,6 @@ pub trait PlatformInstance {
     }
 
     /// Given a `Command` from the platform, process it and optionally return a `Response` to be sent as a reply.
+    /// Commands that are addressed to other instances, such as their `SetLogLevel`s, should return `None`.
     fn handle_command(&mut self, cmd: Command) -> Option<Response>;
 }
@@ -11,6 +11,7 @@ extern crate futures;
 extern crate uuid;
 extern crate serde;
 extern crate serde_json;
+extern crate serde_cbor;
 #[macro_use]
 extern crate serde_derive;
 extern crate postgres;
@@ -19,6 +20,9 @@ extern crate rand;
 extern crate time;
 extern crate test;
 extern crate libc;
+extern crate reqwest;
+#[macro_use]
+extern crate lazy_static;
 
 pub mod transport;
 pub mod strategies;
@@ -0,0 +1,194 @@
+//! Aggregates ticks into fixed-length OHLCV bars of the mid price.  Bars are aligned to multiples of their period
+//! since the epoch, so bars of the same period line up no matter when the ticks started.  Ticks don't carry any
+//! volume, so the number of ticks in each bar is used as its volume.
+
+use trading::tick::Tick;
+use trading::indicators::SmaError;
+
+/// The open, high, low, and close mid prices of the ticks that arrived during one period
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bar {
+    pub open: usize,
+    pub high: usize,
+    pub low: usize,
+    pub close: usize,
+    /// Number of ticks in the bar, which is 0 for bars carried forward over a gap
+    pub tick_count: u64,
+    /// Start of the bar, a multiple of its period
+    pub timestamp: u64,
+}
+
+impl Bar {
+    /// Creates an `OhlcvBuilder` of `period_ms` millisecond bars with `t` as the open of its first bar.
+    pub fn from_tick(t: Tick, period_ms: u64) -> OhlcvBuilder {
+        let mut builder = OhlcvBuilder::new(period_ms);
+        builder.push(t);
+        builder
+    }
+
+    fn open_at(timestamp: u64, price: usize) -> Bar {
+        Bar {
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            tick_count: 1,
+            timestamp: timestamp,
+        }
+    }
+
+    /// Returns the empty bar following this one, with all of its prices at this one's close.
+    fn carried_forward(&self, period_ms: u64) -> Bar {
+        Bar {
+            tick_count: 0,
+            ..Bar::open_at(self.timestamp + period_ms, self.close)
+        }
+    }
+}
+
+/// What to do about bar periods during which no ticks arrived
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapPolicy {
+    /// Emit an empty bar for each of them with all of its prices at the previous close
+    CarryForward,
+    /// Don't emit bars for them at all
+    Skip,
+}
+
+/// Builds bars of `period_ms` milliseconds.  A bar is completed once a tick arrives at or after its end, so the last
+/// bar stays open until the next tick or a `flush`.
+#[derive(Debug, Clone)]
+pub struct OhlcvBuilder {
+    pub period_ms: u64,
+    pub gap_policy: GapPolicy,
+    /// The bar that ticks are currently being added to
+    current: Option<Bar>,
+    last_timestamp: Option<u64>,
+}
+
+impl OhlcvBuilder {
+    /// Creates a builder that skips periods without any ticks.
+    pub fn new(period_ms: u64) -> OhlcvBuilder {
+        OhlcvBuilder::with_gap_policy(period_ms, GapPolicy::Skip)
+    }
+
+    pub fn with_gap_policy(period_ms: u64, gap_policy: GapPolicy) -> OhlcvBuilder {
+        assert!(period_ms > 0, "Bar period must be greater than zero!");
+        OhlcvBuilder {
+            period_ms: period_ms,
+            gap_policy: gap_policy,
+            current: None,
+            last_timestamp: None,
+        }
+    }
+
+    /// Adds a tick to the current bar.  If the tick is after the current bar's end, the tick becomes the open of a
+    /// new bar and the completed one is returned.  Ticks that aren't newer than the previous one are ignored.  Bars
+    /// carried forward over gaps aren't returned; use `push_tick` to get them as well.
+    pub fn push(&mut self, t: Tick) -> Option<Bar> {
+        self.push_tick(&t).ok().and_then(|bars| bars.into_iter().next())
+    }
+
+    /// Adds a tick to the current bar.  If the tick is after the current bar's end, returns that bar followed by an
+    /// empty bar for every period skipped over if gaps are carried forward.  Ticks that aren't newer than the
+    /// previous one are refused.
+    pub fn push_tick(&mut self, t: &Tick) -> Result<Vec<Bar>, SmaError> {
+        match self.last_timestamp {
+            Some(last) if t.timestamp < last => return Err(SmaError::OutOfOrder{last: last, got: t.timestamp}),
+            Some(last) if t.timestamp == last => return Err(SmaError::EqualTimestamp),
+            _ => (),
+        }
+        self.last_timestamp = Some(t.timestamp);
+
+        let price = t.mid();
+        let timestamp = t.timestamp - (t.timestamp % self.period_ms);
+        match self.current {
+            Some(ref mut bar) if bar.timestamp == timestamp => {
+                if price > bar.high {
+                    bar.high = price;
+                }
+                if price < bar.low {
+                    bar.low = price;
+                }
+                bar.close = price;
+                bar.tick_count += 1;
+                return Ok(Vec::new());
+            },
+            _ => (),
+        }
+
+        let mut completed = Vec::new();
+        if let Some(bar) = self.current {
+            completed.push(bar);
+            if self.gap_policy == GapPolicy::CarryForward {
+                let mut empty = bar.carried_forward(self.period_ms);
+                while empty.timestamp < timestamp {
+                    completed.push(empty);
+                    empty = empty.carried_forward(self.period_ms);
+                }
+            }
+        }
+        self.current = Some(Bar::open_at(timestamp, price));
+        Ok(completed)
+    }
+
+    /// Returns the bar that ticks are currently being added to, if any ticks have been pushed.
+    pub fn current(&self) -> Option<&Bar> {
+        self.current.as_ref()
+    }
+
+    /// Completes and returns the current bar even though its period hasn't ended, such as when shutting down.  A
+    /// later tick in the same period starts a new bar, and nothing is carried forward from the flushed one.
+    pub fn flush(&mut self) -> Option<Bar> {
+        self.current.take()
+    }
+}
+
+/// A sequence of ticks produces bars with the right prices, and the tick that completes a bar opens the next one.
+#[test]
+fn ohlcv_bars() {
+    let tick = |timestamp: u64, price: usize| Tick {timestamp: timestamp, bid: price, ask: price};
+    let mut builder = Bar::from_tick(tick(1005, 100), 1000);
+    assert_eq!(builder.push(tick(1200, 104)), None);
+    assert_eq!(builder.push(tick(1400, 98)), None);
+    assert_eq!(builder.push(tick(1999, 101)), None);
+    // ticks from before the last one don't change the bar
+    assert_eq!(builder.push(tick(500, 200)), None);
+    assert_eq!(builder.push(tick(1999, 200)), None);
+
+    let first = Bar {open: 100, high: 104, low: 98, close: 101, tick_count: 4, timestamp: 1000};
+    assert_eq!(builder.push(tick(2000, 102)), Some(first));
+    assert_eq!(builder.current().map(|bar| bar.open), Some(102));
+
+    // empty periods are skipped
+    let second = Bar {open: 102, high: 102, low: 102, close: 102, tick_count: 1, timestamp: 2000};
+    assert_eq!(builder.push(tick(5300, 99)), Some(second));
+    assert_eq!(builder.push(tick(5301, 97)), None);
+    let third = Bar {open: 99, high: 99, low: 97, close: 97, tick_count: 2, timestamp: 5000};
+    assert_eq!(builder.flush(), Some(third));
+    assert_eq!(builder.flush(), None);
+
+    // prices are the mid of the bid and ask
+    let mut builder = tick(0, 0).to_ohlcv_builder(1000);
+    assert_eq!(builder.push(Tick {timestamp: 10, bid: 100, ask: 102}), None);
+    assert_eq!(builder.current().map(|bar| bar.high), Some(101));
+}
+
+/// Out of order ticks are refused, and empty periods are filled with the previous close if gaps are carried forward.
+#[test]
+fn ohlcv_gaps_carried_forward() {
+    let tick = |timestamp: u64, price: usize| Tick {timestamp: timestamp, bid: price, ask: price};
+    let mut builder = OhlcvBuilder::with_gap_policy(1000, GapPolicy::CarryForward);
+    let completed: Vec<Bar> = [(500, 100), (900, 103), (3200, 90)].iter()
+        .flat_map(|&(timestamp, price)| builder.push_tick(&tick(timestamp, price)).unwrap())
+        .collect();
+
+    assert_eq!(completed, vec![
+        Bar {open: 100, high: 103, low: 100, close: 103, tick_count: 2, timestamp: 0},
+        Bar {open: 103, high: 103, low: 103, close: 103, tick_count: 0, timestamp: 1000},
+        Bar {open: 103, high: 103, low: 103, close: 103, tick_count: 0, timestamp: 2000},
+    ]);
+    assert_eq!(builder.current().map(|bar| bar.timestamp), Some(3000));
+    assert_eq!(builder.push_tick(&tick(3200, 1)), Err(SmaError::EqualTimestamp));
+    assert_eq!(builder.push_tick(&tick(10, 1)), Err(SmaError::OutOfOrder{last: 3200, got: 10}));
+}
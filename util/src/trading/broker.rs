@@ -5,8 +5,10 @@ use std::collections::HashMap;
 
 use futures::sync::oneshot::Receiver;
 use futures::stream::Stream;
+use uuid::Uuid;
 
 use trading::tick::Tick;
+use trading::trading_condition::{TradingAction, TimeInForce};
 pub use trading::objects::*;
 
 /// A broker is the endpoint for all trading actions taken by the platform.  It processes
@@ -27,6 +29,76 @@ pub trait Broker {
 
     /// Returns a stream of live ticks for a symbol.
     fn sub_ticks(&mut self, symbol: String) -> Result<Box<Stream<Item=Tick, Error=()> + Send>, BrokerError>;
+
+    // The following are convenience wrappers around `execute` that allow strategies to be written
+    // against any `Broker` without constructing `BrokerAction`s by hand.
+
+    /// Places a limit order that opens a position once the price reaches `entry_price`.
+    fn submit_order(
+        &mut self, account_uuid: Uuid, symbol: String, long: bool, size: usize, entry_price: usize,
+        stop: Option<usize>, take_profit: Option<usize>,
+    ) -> PendingResult {
+        self.execute(BrokerAction::TradingAction{
+            account_uuid: account_uuid,
+            action: TradingAction::LimitOrder{
+                symbol: symbol, long: long, size: size, stop: stop, take_profit: take_profit, entry_price: entry_price,
+                time_in_force: TimeInForce::GTC, tag: None,
+            },
+        })
+    }
+
+    /// Changes the parameters of a pending order.
+    fn modify_order(
+        &mut self, account_uuid: Uuid, order_uuid: Uuid, size: usize, entry_price: usize,
+        stop: Option<usize>, take_profit: Option<usize>,
+    ) -> PendingResult {
+        self.execute(BrokerAction::TradingAction{
+            account_uuid: account_uuid,
+            action: TradingAction::ModifyOrder{
+                uuid: order_uuid, size: size, entry_price: entry_price, stop: stop, take_profit: take_profit,
+            },
+        })
+    }
+
+    /// Cancels a pending order.
+    fn cancel_order(&mut self, account_uuid: Uuid, order_uuid: Uuid) -> PendingResult {
+        self.execute(BrokerAction::TradingAction{
+            account_uuid: account_uuid,
+            action: TradingAction::CancelOrder{uuid: order_uuid},
+        })
+    }
+
+    /// Opens a position at the current market price.
+    fn open_position(
+        &mut self, account_uuid: Uuid, symbol: String, long: bool, size: usize,
+        stop: Option<usize>, take_profit: Option<usize>,
+    ) -> PendingResult {
+        self.execute(BrokerAction::TradingAction{
+            account_uuid: account_uuid,
+            action: TradingAction::MarketOrder{
+                symbol: symbol, long: long, size: size, stop: stop, take_profit: take_profit, max_range: None, tag: None,
+            },
+        })
+    }
+
+    /// Closes `size` units of an open position at the current market price.
+    fn close_position(&mut self, account_uuid: Uuid, position_uuid: Uuid, size: usize) -> PendingResult {
+        self.execute(BrokerAction::TradingAction{
+            account_uuid: account_uuid,
+            action: TradingAction::MarketClose{uuid: position_uuid, size: size},
+        })
+    }
+
+    /// Returns the current state of an account as a `BrokerMessage::Ledger`.
+    fn account_state(&mut self, account_uuid: Uuid) -> PendingResult {
+        self.execute(BrokerAction::GetLedger{account_uuid: account_uuid})
+    }
+
+    /// Returns the broker's push stream converted into `BrokerEvent`s.
+    fn events(&mut self) -> Result<Box<Stream<Item=(u64, BrokerEvent), Error=()> + Send>, BrokerError> {
+        let stream = self.get_stream()?;
+        Ok(Box::new(stream.map(|(timestamp, res)| (timestamp, BrokerEvent::from_result(res)))))
+    }
 }
 
 /// Utility type for a broker response that may fail
@@ -34,3 +106,116 @@ pub type BrokerResult = Result<BrokerMessage, BrokerError>;
 
 /// Utility type for a currently pending broker action
 pub type PendingResult = Receiver<BrokerResult>;
+
+/// A broker that fills everything instantly at a fixed price of 100.  Used to make sure that strategies
+/// written against the `Broker` trait don't depend on any particular implementation.
+#[cfg(test)]
+pub struct DummyBroker {
+    pub ledger: Ledger,
+    pub account_uuid: Uuid,
+    pub executed: Vec<BrokerAction>,
+}
+
+#[cfg(test)]
+impl Broker for DummyBroker {
+    fn init(_: HashMap<String, String>) -> Receiver<Result<Self, BrokerError>> {
+        use futures::sync::oneshot::channel;
+
+        let (c, o) = channel();
+        c.complete(Ok(DummyBroker {
+            ledger: Ledger::new(10000),
+            account_uuid: Uuid::new_v4(),
+            executed: Vec::new(),
+        }));
+        o
+    }
+
+    fn execute(&mut self, action: BrokerAction) -> PendingResult {
+        use futures::sync::oneshot::channel;
+
+        let position = |long, size, price, stop, take_profit| Position {
+            creation_time: 0, symbol_id: 0, size: size, price: Some(price), long: long, stop: stop,
+            take_profit: take_profit, execution_time: None, execution_price: None, exit_price: None, exit_time: None,
+            tag: None,
+        };
+        let res = match action {
+            BrokerAction::TradingAction{action: TradingAction::LimitOrder{long, size, stop, take_profit, entry_price, ..}, ..} => {
+                self.ledger.place_order(position(long, size, entry_price, stop, take_profit), size, Uuid::new_v4())
+            },
+            BrokerAction::TradingAction{action: TradingAction::MarketOrder{long, size, stop, take_profit, ..}, ..} => {
+                let mut pos = position(long, size, 100, stop, take_profit);
+                pos.execution_time = Some(0);
+                pos.execution_price = Some(100);
+                self.ledger.buying_power -= size;
+                self.ledger.open_position(Uuid::new_v4(), pos)
+            },
+            BrokerAction::TradingAction{action: TradingAction::MarketClose{uuid, ..}, ..} => {
+                let value = self.ledger.open_positions.get(&uuid).map(|pos| pos.size).unwrap_or(0);
+                self.ledger.close_position(uuid, value, 0, PositionClosureReason::MarketClose)
+            },
+            BrokerAction::TradingAction{action: TradingAction::CancelOrder{uuid}, ..} => self.ledger.cancel_order(uuid, 0),
+            BrokerAction::GetLedger{..} => Ok(BrokerMessage::Ledger{ledger: self.ledger.clone()}),
+            BrokerAction::ListAccounts => Ok(BrokerMessage::AccountListing{accounts: vec![Account {
+                uuid: self.account_uuid, ledger: self.ledger.clone(), live: false,
+            }]}),
+            _ => Ok(BrokerMessage::Success),
+        };
+        self.executed.push(action);
+
+        let (c, o) = channel();
+        c.complete(res);
+        o
+    }
+
+    fn get_stream(&mut self) -> Result<Box<Stream<Item=(u64, BrokerResult), Error=()> + Send>, BrokerError> {
+        use futures::stream::iter;
+
+        let msgs: Vec<Result<(u64, BrokerResult), ()>> = vec![Ok((0, Err(BrokerError::NoSuchSymbol)))];
+        Ok(Box::new(iter(msgs)))
+    }
+
+    fn sub_ticks(&mut self, _: String) -> Result<Box<Stream<Item=Tick, Error=()> + Send>, BrokerError> {
+        Err(BrokerError::NoSuchSymbol)
+    }
+}
+
+/// A minimal strategy written only against the `Broker` trait: opens a position, places an order,
+/// cancels the order, closes the position, and returns the final ledger.  Broker implementations run
+/// it in their tests to make sure that strategies written against the trait work with them.
+pub fn round_trip_strategy<B: Broker>(broker: &mut B, account_uuid: Uuid, symbol: &str) -> Ledger {
+    use futures::Future;
+
+    let pos_uuid = match broker.open_position(account_uuid, String::from(symbol), true, 10, None, None).wait().unwrap() {
+        Ok(BrokerMessage::PositionOpened{position_id, ..}) => position_id,
+        res => panic!("Unexpected response to market order: {:?}", res),
+    };
+    let order_uuid = match broker.submit_order(account_uuid, String::from(symbol), true, 5, 1, None, None).wait().unwrap() {
+        Ok(BrokerMessage::OrderPlaced{order_id, ..}) => order_id,
+        res => panic!("Unexpected response to limit order: {:?}", res),
+    };
+    broker.cancel_order(account_uuid, order_uuid).wait().unwrap().unwrap();
+    broker.close_position(account_uuid, pos_uuid, 10).wait().unwrap().unwrap();
+
+    match broker.account_state(account_uuid).wait().unwrap() {
+        Ok(BrokerMessage::Ledger{ledger}) => ledger,
+        res => panic!("Unexpected response to ledger request: {:?}", res),
+    }
+}
+
+#[test]
+fn dummy_broker_strategy() {
+    use futures::{Future, Stream};
+
+    let mut broker = DummyBroker::init(HashMap::new()).wait().unwrap().unwrap();
+    let account_uuid = broker.account_uuid;
+    let ledger = round_trip_strategy(&mut broker, account_uuid, "TEST");
+    assert!(ledger.open_positions.is_empty());
+    assert!(ledger.pending_positions.is_empty());
+    assert_eq!(ledger.closed_positions.len(), 1);
+    assert_eq!(broker.executed.len(), 5);
+
+    // the trait should also be usable as a trait object
+    let mut boxed: Box<Broker + Send> = Box::new(broker);
+    let events: Vec<(u64, BrokerEvent)> = boxed.events().unwrap().wait().map(|e| e.unwrap()).collect();
+    assert_eq!(events, vec![(0, BrokerEvent::OrderRejected{reason: None, error: BrokerError::NoSuchSymbol})]);
+}
@@ -1,28 +1,102 @@
+use std::collections::VecDeque;
+use std::collections::vec_deque::Iter;
+use std::fmt;
 use std::ops::Index;
 
-#[derive(Debug)]
+#[allow(unused_imports)]
+use test;
+
+use trading::tick::Tick;
+
+/// Items that carry a timestamp, which lets a `DataField` of them drop the ones older than some age.
+pub trait Timestamped {
+    fn timestamp(&self) -> u64;
+}
+
+impl Timestamped for Tick {
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+/// Determines which items a `DataField` drops as new ones are pushed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetentionPolicy {
+    /// Every item is kept
+    Unbounded,
+    /// At most this many items are kept, dropping the oldest ones once it's full
+    DropOldest(usize),
+    /// Only items whose timestamps are within this many milliseconds of the newest item's are kept
+    MaxAge(u64),
+}
+
 pub struct DataField<T> {
-    pub data: Vec<T>
+    data: VecDeque<T>,
+    policy: RetentionPolicy,
+    /// Reads the timestamps of items for `MaxAge` retention
+    timestamp_of: Option<fn(&T) -> u64>,
+    /// Number of items that have been dropped by the retention policy
+    evicted: u64,
 }
 
 #[allow(dead_code)]
 impl<T> DataField<T> {
+    /// Creates a `DataField` that keeps every item pushed into it.
     pub fn new() -> DataField<T> {
         DataField {
-            data: Vec::new()
+            data: VecDeque::new(),
+            policy: RetentionPolicy::Unbounded,
+            timestamp_of: None,
+            evicted: 0,
+        }
+    }
+
+    /// Creates a `DataField` that holds at most `capacity` items, dropping the oldest ones once it's full.
+    pub fn with_capacity(capacity: usize) -> DataField<T> {
+        DataField {
+            data: VecDeque::with_capacity(capacity),
+            policy: RetentionPolicy::DropOldest(capacity),
+            timestamp_of: None,
+            evicted: 0,
         }
     }
 
     pub fn push(&mut self, d: T) {
-        self.data.push(d);
+        match self.policy {
+            RetentionPolicy::Unbounded => self.data.push_back(d),
+            RetentionPolicy::DropOldest(capacity) => {
+                if capacity == 0 {
+                    self.evicted += 1;
+                    return;
+                }
+                if self.data.len() == capacity {
+                    self.data.pop_front();
+                    self.evicted += 1;
+                }
+                self.data.push_back(d);
+            },
+            RetentionPolicy::MaxAge(max_age_ms) => {
+                let timestamp_of = self.timestamp_of.expect("`MaxAge` DataField created without a timestamp reader");
+                let newest = timestamp_of(&d);
+                self.data.push_back(d);
+                loop {
+                    match self.data.front() {
+                        Some(oldest) if newest.saturating_sub(timestamp_of(oldest)) > max_age_ms => (),
+                        _ => break,
+                    }
+                    self.data.pop_front();
+                    self.evicted += 1;
+                }
+            },
+        }
     }
 
     pub fn first(&mut self) -> Option<&T> {
-        self.data.first()
+        self.data.front()
     }
 
     pub fn last(&mut self) -> Option<&T> {
-        self.data.last()
+        self.data.back()
     }
 
     pub fn len(&self) -> usize {
@@ -32,8 +106,47 @@ impl<T> DataField<T> {
     pub fn is_empty(&self) -> bool {
         self.data.is_empty()
     }
+
+    /// Iterates over the retained items from oldest to newest.
+    pub fn iter(&self) -> Iter<T> {
+        self.data.iter()
+    }
+
+    pub fn policy(&self) -> RetentionPolicy {
+        self.policy
+    }
+
+    /// Returns the number of items that have been dropped by the retention policy.
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+}
+
+#[allow(dead_code)]
+impl<T: Timestamped> DataField<T> {
+    /// Creates a `DataField` that only keeps the items whose timestamps are within `max_age_ms` milliseconds of the
+    /// newest one's.
+    pub fn with_max_age(max_age_ms: u64) -> DataField<T> {
+        DataField {
+            data: VecDeque::new(),
+            policy: RetentionPolicy::MaxAge(max_age_ms),
+            timestamp_of: Some(T::timestamp),
+            evicted: 0,
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for DataField<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DataField")
+            .field("data", &self.data)
+            .field("policy", &self.policy)
+            .field("evicted", &self.evicted)
+            .finish()
+    }
 }
 
+/// Indexes the retained items, with 0 being the oldest.
 impl<T> Index<usize> for DataField<T> {
     type Output = T;
 
@@ -41,3 +154,64 @@ impl<T> Index<usize> for DataField<T> {
         &self.data[index]
     }
 }
+
+/// Items are still read back oldest first after the buffer has wrapped around several times.
+#[test]
+fn drop_oldest_wraparound() {
+    let mut df = DataField::with_capacity(4);
+    for i in 0..11 {
+        df.push(i);
+    }
+
+    assert_eq!(df.len(), 4);
+    assert_eq!(df.evicted(), 7);
+    assert_eq!(df.iter().cloned().collect::<Vec<_>>(), vec![7, 8, 9, 10]);
+    assert_eq!(df[0], 7);
+    assert_eq!(df[3], 10);
+    assert_eq!(df.first(), Some(&7));
+    assert_eq!(df.last(), Some(&10));
+}
+
+/// Only ticks within the maximum age of the newest one are kept.
+#[test]
+fn max_age_retention() {
+    let mut df = DataField::with_max_age(1000);
+    for i in 0..10 {
+        df.push(Tick {timestamp: i * 250, bid: 100, ask: 102});
+    }
+
+    let timestamps: Vec<u64> = df.iter().map(|t| t.timestamp).collect();
+    assert_eq!(timestamps, vec![1250, 1500, 1750, 2000, 2250]);
+    assert_eq!(df.evicted(), 5);
+
+    let mut unbounded = DataField::new();
+    for i in 0..1000 {
+        unbounded.push(i);
+    }
+    assert_eq!(unbounded.len(), 1000);
+    assert_eq!(unbounded.evicted(), 0);
+}
+
+// insert a tick into a DataField
+#[bench]
+fn tick_insertion(b: &mut test::Bencher) {
+    let t = Tick {bid: 1123128412, ask: 1123128402, timestamp: 1471291001837};
+    let mut df: DataField<Tick> = DataField::new();
+
+    b.iter(|| {
+        let mut df = &mut df;
+        df.push(t);
+    });
+}
+
+// insert a tick into a DataField that's already full and has to drop its oldest tick each time
+#[bench]
+fn tick_insertion_bounded(b: &mut test::Bencher) {
+    let t = Tick {bid: 1123128412, ask: 1123128402, timestamp: 1471291001837};
+    let mut df: DataField<Tick> = DataField::with_capacity(1000);
+    for _ in 0..1000 {
+        df.push(t);
+    }
+
+    b.iter(|| df.push(t));
+}
@@ -22,3 +22,22 @@ pub trait LiveQuery<T> {
     /// a new one is received.
     fn tick(t: Tick) -> Option<T>;
 }
+
+/// Why a moving average refused a tick.  Ticks have to be pushed in order of their timestamps; a tick that isn't
+/// newer than the previous one was duplicated or delayed by the feed, and averaging it in would corrupt the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmaError {
+    /// The tick is older than the last one that was pushed
+    OutOfOrder{last: u64, got: u64},
+    /// The tick has the same timestamp as the last one that was pushed
+    EqualTimestamp,
+}
+
+/// Signals that a fast moving average crossed a slow one.  Values are the averages after the cross.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CrossoverEvent {
+    /// The fast average crossed above the slow average
+    BullishCross{timestamp: u64, fast_value: f64, slow_value: f64},
+    /// The fast average crossed below the slow average
+    BearishCross{timestamp: u64, fast_value: f64, slow_value: f64},
+}
@@ -2,8 +2,10 @@
 //! used for interacting with brokers and backtesting.
 
 pub mod tick;
+pub mod bar;
 pub mod broker;
 pub mod indicators;
+pub mod sma;
 pub mod trading_condition;
 pub mod datafield;
 pub mod objects;
@@ -6,10 +6,11 @@ use std::collections::HashMap;
 use uuid::Uuid;
 
 use trading::trading_condition::{TradingAction};
+use trading::tick::Tick;
 use trading::broker::*;
 
 /// An account
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Account {
     pub uuid: Uuid,
     pub ledger: Ledger,
@@ -55,6 +56,12 @@ pub enum BrokerMessage {
         order: Position,
         timestamp: u64
     },
+    /// A pending order was cancelled by the broker because its time in force ran out
+    OrderExpired{
+        order_id: Uuid,
+        order: Position,
+        timestamp: u64,
+    },
     PositionOpened{
         position_id: Uuid,
         position: Position,
@@ -76,17 +83,93 @@ pub enum BrokerMessage {
     Ledger{ledger: Ledger},
 }
 
+/// A broker-independent view of something that happened on a broker, produced from the messages
+/// that it pushes.  Timestamps are delivered alongside the event rather than inside of it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BrokerEvent {
+    OrderAccepted{order_id: Uuid, order: Position},
+    OrderModified{order_id: Uuid, order: Position},
+    OrderCancelled{order_id: Uuid, order: Position},
+    /// An order was cancelled by the broker because its time in force ran out
+    OrderExpired{order_id: Uuid, order: Position},
+    /// A pending order or market order was filled and is now an open position
+    Filled{position_id: Uuid, position: Position},
+    PositionModified{position_id: Uuid, position: Position},
+    PositionClosed{position_id: Uuid, position: Position, reason: PositionClosureReason},
+    /// A position was closed because the account ran out of margin
+    MarginCall{position_id: Uuid, position: Position},
+    /// Every pending order on the broker was cancelled at once, such as to halt trading
+    AllOrdersCancelled{count: usize},
+    BalanceChange{account_uuid: Uuid, new_buying_power: usize},
+    /// The broker refused to carry out an action.  `reason` is set if an order was rejected for one of the
+    /// reasons in `RejectionReason`.
+    OrderRejected{reason: Option<RejectionReason>, error: BrokerError},
+    /// The broker discarded an invalid tick instead of updating its prices with it
+    TickRejected{symbol: String, tick: Tick, reason: String},
+    /// Any message that doesn't correspond to a trading event
+    Other{message: BrokerMessage},
+}
+
+impl BrokerEvent {
+    pub fn from_result(res: BrokerResult) -> BrokerEvent {
+        match res {
+            Ok(BrokerMessage::OrderPlaced{order_id, order, ..}) => BrokerEvent::OrderAccepted{order_id: order_id, order: order},
+            Ok(BrokerMessage::OrderModified{order_id, order, ..}) => BrokerEvent::OrderModified{order_id: order_id, order: order},
+            Ok(BrokerMessage::OrderCancelled{order_id, order, ..}) => BrokerEvent::OrderCancelled{order_id: order_id, order: order},
+            Ok(BrokerMessage::OrderExpired{order_id, order, ..}) => BrokerEvent::OrderExpired{order_id: order_id, order: order},
+            Ok(BrokerMessage::PositionOpened{position_id, position, ..}) => BrokerEvent::Filled{
+                position_id: position_id, position: position,
+            },
+            Ok(BrokerMessage::PositionModified{position_id, position, ..}) => BrokerEvent::PositionModified{
+                position_id: position_id, position: position,
+            },
+            Ok(BrokerMessage::PositionClosed{position_id, position, reason: PositionClosureReason::MarginCall, ..}) => {
+                BrokerEvent::MarginCall{position_id: position_id, position: position}
+            },
+            Ok(BrokerMessage::PositionClosed{position_id, position, reason, ..}) => BrokerEvent::PositionClosed{
+                position_id: position_id, position: position, reason: reason,
+            },
+            Ok(BrokerMessage::LedgerBalanceChange{account_uuid, new_buying_power}) => BrokerEvent::BalanceChange{
+                account_uuid: account_uuid, new_buying_power: new_buying_power,
+            },
+            Ok(msg) => BrokerEvent::Other{message: msg},
+            Err(err) => BrokerEvent::OrderRejected{reason: err.rejection_reason(), error: err},
+        }
+    }
+}
+
+/// Why a broker refused to accept an order or a modification of one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RejectionReason {
+    /// The account doesn't have enough buying power to cover the order
+    InsufficientMargin,
+    /// The order's size is zero or larger than the position it applies to
+    InvalidSize,
+    /// The broker doesn't have a symbol with the supplied name
+    UnknownSymbol,
+    /// The last price of the symbol is too old to trade against
+    StalePrice,
+    /// No prices are available for the symbol
+    MarketClosed,
+    /// The broker stopped before the order was processed
+    BrokerShuttingDown,
+    /// A price of the order (such as its stop or take profit) is on the wrong side of its entry price
+    PriceOutOfRange,
+    /// The price moved before the market order could be filled; the broker is willing to fill it at `new_price`
+    Requote{new_price: usize},
+    /// Opening the position would take the number of open positions above the broker's limit
+    MaxPositionsReached,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum BrokerError {
     Message{message: String},
     Unimplemented{message: String}, // the broker under the wrapper can't do what you asked it
-    InsufficientBuyingPower,
+    /// An order was refused for the given reason; `message` describes the specifics
+    Rejected{reason: RejectionReason, message: String},
     NoSuchPosition,
     NoSuchAccount,
     NoSuchSymbol,
-    InvalidModificationAmount,
-    InvalidStopValue,
-    InvalidTakeProfitValue,
     ExitWithoutEntry,
     MissingExecutionData,
     MissingExitData,
@@ -95,6 +178,42 @@ pub enum BrokerError {
     NoDataAvailable,
 }
 
+impl BrokerError {
+    pub fn rejected(reason: RejectionReason, message: &str) -> BrokerError {
+        BrokerError::Rejected{reason: reason, message: String::from(message)}
+    }
+
+    /// Returns the reason the order was rejected if this error is an order rejection.
+    pub fn rejection_reason(&self) -> Option<RejectionReason> {
+        match *self {
+            BrokerError::Rejected{reason, ..} => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+/// The result of modifying or cancelling a pending order.  Unlike a plain `BrokerResult`, this
+/// distinguishes between orders that never existed and orders that were filled before the
+/// operation could be applied.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderUpdateResult {
+    /// No order or position with the supplied UUID exists.
+    NotFound,
+    /// The order was filled before the operation was processed and is now a position.
+    AlreadyFilled,
+    /// The operation was rejected because the updated order would be invalid.
+    Rejected{reason: Option<RejectionReason>, message: String},
+    /// The operation succeeded; contains the order as it exists after the operation.
+    Ok{updated: Position},
+}
+
+impl OrderUpdateResult {
+    /// Creates a rejection from the error that caused it.
+    pub fn from_error(err: BrokerError) -> OrderUpdateResult {
+        OrderUpdateResult::Rejected{reason: err.rejection_reason(), message: format!("{:?}", err)}
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum PositionClosureReason {
     StopLoss,
@@ -107,7 +226,7 @@ pub enum PositionClosureReason {
 
 /// The platform's internal representation of the current state of an account.
 /// Contains information about past trades as well as current positions.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Ledger {
     pub buying_power: usize,
     pub pending_positions: HashMap<Uuid, Position>,
@@ -128,7 +247,7 @@ impl Ledger {
     /// Attempts to open a pending position in the ledger with the supplied position.
     pub fn place_order(&mut self, pos: Position, position_value: usize, uuid: Uuid) -> BrokerResult {
         if position_value > self.buying_power {
-            return Err(BrokerError::InsufficientBuyingPower)
+            return Err(BrokerError::rejected(RejectionReason::InsufficientMargin, "Not enough buying power to place the order."))
         }
         self.buying_power -= position_value;
         self.pending_positions.insert(uuid, pos.clone());
@@ -222,23 +341,29 @@ impl Ledger {
     /// if the account doesn't have enough buying power to execute the action or if a position with
     /// the specified UUID doesn't exist.
     pub fn resize_position(&mut self, uuid: Uuid, units: isize, modification_cost: usize, timestamp: u64) -> BrokerResult {
-        let mut pos = self.open_positions.remove(&uuid)
-            .expect("No position found with that UUID; should have caught this earlier.");
+        let mut pos = self.open_positions.get(&uuid)
+            .expect("No position found with that UUID; should have caught this earlier.")
+            .clone();
 
         let unit_diff = units + (pos.size as isize);
         if unit_diff < 0 {
-            return Err(BrokerError::InvalidModificationAmount);
+            return Err(BrokerError::rejected(RejectionReason::InvalidSize, "Can't reduce a position by more than its size."));
         } else if unit_diff == 0 {
             return self.close_position(uuid, modification_cost, timestamp, PositionClosureReason::MarketClose);
         }
 
-        if self.buying_power < modification_cost {
-            return Err(BrokerError::InsufficientBuyingPower);
+        if units > 0 && self.buying_power < modification_cost {
+            return Err(BrokerError::rejected(RejectionReason::InsufficientMargin, "Not enough buying power to enlarge the position."));
         }
 
-        // everything seems to be in order, so do the modification
+        // everything seems to be in order, so do the modification.  Reducing a position frees up the
+        // buying power held by the removed units.
         pos.size = ((pos.size as isize) + units) as usize;
-        self.buying_power -= modification_cost;
+        if units > 0 {
+            self.buying_power -= modification_cost;
+        } else {
+            self.buying_power += modification_cost;
+        }
         self.open_positions.insert(uuid, pos.clone());
 
         Ok(BrokerMessage::PositionModified{
@@ -274,7 +399,7 @@ impl Ledger {
 }
 
 /// Represents an opened, closed, or pending position on a broker.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Position {
     pub creation_time: u64,
     pub symbol_id: usize,
@@ -291,24 +416,24 @@ pub struct Position {
     pub exit_price: Option<usize>,
     /// the time the position was actually closed
     pub exit_time: Option<u64>,
+    /// The label supplied by the client when the order was submitted, if any.  Tags don't have to be unique.
+    #[serde(default)]
+    pub tag: Option<String>,
 }
 
 impl Position {
     /// Returns the price the position would execute at if the prices are at levels such that the position
-    /// can open, else returns None.
+    /// can open, else returns None.  Pending orders without a price are market orders and can always be opened.
     pub fn is_open_satisfied(&self, bid: usize, ask: usize) -> Option<usize> {
         // only meant to be used for pending positions
         assert_eq!(self.execution_price, None);
-        // only meant for limit/entry orders
-        assert!(self.price.is_some());
 
-        if self.long && ask <= self.price.unwrap() {
-            return Some(ask);
-        } else if bid >= self.price.unwrap() {
-            return Some(bid);
+        match self.price {
+            None => Some(if self.long { ask } else { bid }),
+            Some(price) if self.long && ask <= price => Some(ask),
+            Some(price) if !self.long && bid >= price => Some(bid),
+            Some(_) => None,
         }
-
-        None
     }
 
     /// Returns the price the position would execute at if the position meets
@@ -345,10 +470,10 @@ impl Position {
             let price = *self.price.as_ref().unwrap();
             match self.stop {
                 Some(stop) => {
-                    if self.long && price <= stop {
-                        return Err(BrokerError::InvalidStopValue);
-                    } else if !self.long && price >= stop {
-                        return Err(BrokerError::InvalidStopValue);
+                    if (self.long && price <= stop) || (!self.long && price >= stop) {
+                        return Err(BrokerError::rejected(
+                            RejectionReason::PriceOutOfRange, &format!("Stop {} is on the wrong side of price {}.", stop, price)
+                        ));
                     }
                 }
                 None => (),
@@ -356,10 +481,10 @@ impl Position {
 
             match self.take_profit {
                 Some(tp) => {
-                    if self.long && price >= tp {
-                        return Err(BrokerError::InvalidTakeProfitValue);
-                    } else if !self.long && price <= tp {
-                        return Err(BrokerError::InvalidTakeProfitValue);
+                    if (self.long && price >= tp) || (!self.long && price <= tp) {
+                        return Err(BrokerError::rejected(
+                            RejectionReason::PriceOutOfRange, &format!("Take profit {} is on the wrong side of price {}.", tp, price)
+                        ));
                     }
                 },
                 None => (),
@@ -0,0 +1,592 @@
+//! A time-weighted simple moving average of ticks, shared by the tick processor and the platform's strategies.
+
+use std::collections::{VecDeque, HashMap};
+use std::error::Error;
+
+#[allow(unused_imports)]
+use test;
+use postgres::rows::Row;
+use serde_json;
+
+use trading::indicators::*;
+use trading::tick::*;
+use transport::postgres::*;
+
+/// Alteration of a simple moving average using ticks as input where the prices in a time frame
+/// are weighted by the time the price stayed at that level before changing.
+pub struct Sma {
+    /// Length of the window in tick timestamp units (milliseconds)
+    pub period: u64,
+    /// Human-readable version of the period such as "15m" for display
+    pub period_label: String,
+    /// The ticks in the window.  Only ticks added with `push()` are included in the running sums.
+    pub ticks: VecDeque<Tick>,
+    // indicates if an out-of-range tick exists in the front element
+    ref_tick: Tick,
+    /// Running time-weighted sums over every pair of adjacent buffered ticks, updated as ticks are pushed and
+    /// trimmed so that averages don't have to walk the whole window.
+    sums: WeightedSums,
+}
+
+/// Sums of each buffered tick's prices multiplied by the time until the next tick, along with the sum of those
+/// times.  They're kept as integers so that adding and removing ticks never introduces rounding error, so they
+/// don't drift no matter how long they're kept running.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WeightedSums {
+    mid: u64,
+    bid: u64,
+    ask: u64,
+    time: u64,
+}
+
+impl WeightedSums {
+    /// Adds (or removes) the contribution of the time between `prev` and `next`, during which the price was that
+    /// of `prev`.
+    pub fn update(&mut self, prev: &Tick, next: &Tick, add: bool) {
+        let t_diff = next.timestamp - prev.timestamp;
+        if add {
+            self.mid += prev.mid() as u64 * t_diff;
+            self.bid += prev.bid as u64 * t_diff;
+            self.ask += prev.ask as u64 * t_diff;
+            self.time += t_diff;
+        } else {
+            self.mid -= prev.mid() as u64 * t_diff;
+            self.bid -= prev.bid as u64 * t_diff;
+            self.ask -= prev.ask as u64 * t_diff;
+            self.time -= t_diff;
+        }
+    }
+
+    /// Returns the sums with the time between the start of the period and the oldest buffered tick filled in
+    /// with the prices of `ref_tick`, the last tick trimmed from the window, if there is one.
+    pub fn with_ref_tick(&self, ref_tick: &Tick, period: u64) -> WeightedSums {
+        if ref_tick.bid == 0 {
+            return *self;
+        }
+
+        let old_time = period - self.time;
+        WeightedSums {
+            mid: self.mid + old_time * ref_tick.mid() as u64,
+            bid: self.bid + old_time * ref_tick.bid as u64,
+            ask: self.ask + old_time * ref_tick.ask as u64,
+            time: period,
+        }
+    }
+
+    /// Returns the time-weighted average of the unrounded mid prices, or `None` if the sums don't span any time.
+    pub fn average_f64(&self) -> Option<f64> {
+        if self.time == 0 {
+            return None;
+        }
+        // the unrounded mid price of each tick is half of its bid plus ask
+        Some((self.bid + self.ask) as f64 / 2. / self.time as f64)
+    }
+}
+
+impl Sma {
+    pub fn new(period: u64) -> Sma {
+        Sma {
+            period: period,
+            period_label: period_label(period),
+            ticks: VecDeque::new(),
+            ref_tick: Tick::null(),
+            sums: WeightedSums::default(),
+        }
+    }
+
+    /// Creates an SMA with a period of `period_secs` seconds.
+    pub fn new_seconds(period_secs: u64) -> Sma {
+        Sma::new(period_secs * TICK_TIMESTAMP_UNIT_MS)
+    }
+
+    /// Creates an SMA with a period of `period_mins` minutes.
+    pub fn new_minutes(period_mins: u64) -> Sma {
+        Sma::new_seconds(period_mins * 60)
+    }
+
+    /// Returns true once the ticks pushed have spanned at least a whole period, so the average covers all of it
+    /// rather than just the time since the first tick.
+    pub fn is_warmed_up(&self) -> bool {
+        // ticks are only trimmed once they span the period, and the window then always reaches back to the start
+        // of the period through the reference tick
+        self.ref_tick.bid != 0 || self.is_overflown()
+    }
+
+    /// Clears all of the ticks out of the SMA, leaving it as if it was just created.
+    pub fn reset(&mut self) {
+        self.ticks.clear();
+        self.ref_tick = Tick::null();
+        self.sums = WeightedSums::default();
+    }
+
+    /// Trims out of range ticks from the front of the queue.
+    /// Returns the last out-of-range tick removed.
+    fn trim(&mut self) -> Tick {
+        let mut t: Tick = Tick::null();
+        while self.is_overflown() {
+            t = self.ticks.pop_front().unwrap();
+            // the window is only overflown with at least two ticks, so there's always a new front
+            self.sums.update(&t, self.ticks.front().unwrap(), false);
+        }
+
+        t
+    }
+
+    /// Returns the average price for the SMA's period.
+    fn average(&self) -> usize {
+        if self.ticks.is_empty() {
+            return self.simple_mean().mid();
+        }
+
+        let sums = self.sums.with_ref_tick(&self.ref_tick, self.period);
+        if sums.time == 0 {
+            return self.simple_mean().mid();
+        }
+        (sums.mid / sums.time) as usize
+    }
+
+    /// Same as `average()` but uses unrounded mid prices and doesn't round the result.
+    fn average_f64(&self) -> f64 {
+        if self.ticks.is_empty() {
+            return self.simple_mean().mid_f64();
+        }
+
+        self.sums.with_ref_tick(&self.ref_tick, self.period).average_f64()
+            .unwrap_or_else(|| self.simple_mean().mid_f64())
+    }
+
+    /// Returns the unweighted mean bid and ask of the buffered ticks with the timestamp of the latest one.  Used
+    /// when the ticks don't span any time to weight them by, such as when they all arrived in the same millisecond.
+    /// If no ticks are buffered, the last tick trimmed from the window (or a null tick) is returned.
+    fn simple_mean(&self) -> Tick {
+        let count = self.ticks.len();
+        if count == 0 {
+            return self.ref_tick;
+        }
+
+        let bid_sum: usize = self.ticks.iter().map(|t| t.bid).sum();
+        let ask_sum: usize = self.ticks.iter().map(|t| t.ask).sum();
+        Tick {
+            bid: bid_sum / count,
+            ask: ask_sum / count,
+            timestamp: self.ticks.back().unwrap().timestamp,
+        }
+    }
+
+    fn is_overflown(&self) -> bool {
+        // a single tick doesn't span any time
+        if self.ticks.len() < 2 {
+            return false;
+        }
+        // time between newest tick and reference tick
+        let diff = self.ticks.back().unwrap().timestamp - self.ticks.front().unwrap().timestamp;
+        diff >= self.period
+    }
+
+    /// Add a new tick to be averaged.  Ticks that aren't newer than the last one are refused and leave the
+    /// average unchanged.
+    pub fn push(&mut self, t: Tick) -> Result<usize, SmaError> {
+        if let Some(last_tick) = self.ticks.back() {
+            if t.timestamp < last_tick.timestamp {
+                return Err(SmaError::OutOfOrder{last: last_tick.timestamp, got: t.timestamp});
+            } else if t.timestamp == last_tick.timestamp {
+                return Err(SmaError::EqualTimestamp);
+            }
+            self.sums.update(last_tick, &t, true);
+        }
+        self.ticks.push_back(t);
+
+        if self.is_overflown() {
+            self.ref_tick = self.trim();
+        }
+
+        if self.ticks.len() == 1 {
+            return Ok(self.ticks.front().unwrap().mid())
+        }
+
+        Ok(self.average())
+    }
+
+    /// Same as `push` but calculates the average with floating point precision.
+    pub fn push_f64(&mut self, t: Tick) -> Result<Option<f64>, SmaError> {
+        self.push(t)?;
+        if self.ticks.len() == 1 {
+            return Ok(Some(t.mid_f64()))
+        }
+
+        Ok(Some(self.average_f64()))
+    }
+
+    /// Same as push but returns a tick representing the average bid and ask instead a usize.
+    pub fn push_tick(&mut self, t: Tick) -> Result<Tick, SmaError> {
+        self.push(t)?;
+        Ok(self.average_tick())
+    }
+
+    pub fn average_tick(&self) -> Tick {
+        match self.ticks.len() {
+            0 => return self.simple_mean(),
+            1 => return *self.ticks.front().unwrap(),
+            _ => (),
+        }
+
+        let sums = self.sums.with_ref_tick(&self.ref_tick, self.period);
+        if sums.time == 0 {
+            return self.simple_mean();
+        }
+        Tick {
+            bid: (sums.bid / sums.time) as usize,
+            ask: (sums.ask / sums.time) as usize,
+            timestamp: (*self.ticks.back().unwrap()).timestamp,
+        }
+    }
+}
+
+impl HistQuery for Sma {
+    /// Queries the database for ticks in a range and returns the average bid and ask in that range.
+    fn get(start_time: u64, end_time: u64, period: u64, args: HashMap<String, String>) -> Result<String, String> {
+        let connection_opt = get_client();
+        if connection_opt.is_err() {
+            return Err(String::from("Unable to connect to PostgreSQL!"))
+        }
+        let conn = connection_opt.unwrap();
+
+        let table_name = try!( args.get("table_name").ok_or(no_arg_error("table_name")) );
+
+        let query = format!(
+            "SELECT (tick_time, bid, ask) FROM {} WHERE tick_time > {} AND tick_time < {};",
+            table_name,
+            start_time,
+            end_time
+        );
+        let rows = try!(
+            conn.query(&query, &[]).map_err( |err| format!("{}", err) )
+        );
+
+        let mut sma = Sma::new(period);
+        let mut last_time = 0;
+        let mut res = Vec::new();
+
+        for row in rows.iter() {
+            let t: Tick = tick_from_row(&row);
+            // the rows aren't sorted, so skip any that are older than the ones already averaged
+            let res_t = match sma.push_tick(t) {
+                Ok(res_t) => res_t,
+                Err(_) => continue,
+            };
+
+            if last_time == 0 || (t.timestamp - last_time) > period {
+                res.push(res_t);
+                last_time = t.timestamp;
+            }
+        }
+
+        serde_json::to_string(&res).map_err(|err| String::from(err.description()) )
+    }
+}
+
+/// Takes a row and returns a tick from the values of its columns.
+/// Panics if the row doesn't contain properly formatted `tick_time`, `bid`, and `ask` columns.
+fn tick_from_row(row: &Row) -> Tick {
+    let timestamp = row.get::<_, i64>("tick_time") as u64;
+    let bid = row.get::<_, i64>("bid") as usize;
+    let ask = row.get::<_, i64>("ask") as usize;
+
+    Tick {
+        timestamp: timestamp,
+        bid: bid,
+        ask: ask,
+    }
+}
+
+/// Formats a period in milliseconds using the largest of hours, minutes, or seconds that divides it evenly.
+fn period_label(period: u64) -> String {
+    let second = TICK_TIMESTAMP_UNIT_MS;
+    match period {
+        0 => String::from("0ms"),
+        p if p % (60 * 60 * second) == 0 => format!("{}h", p / (60 * 60 * second)),
+        p if p % (60 * second) == 0 => format!("{}m", p / (60 * second)),
+        p if p % second == 0 => format!("{}s", p / second),
+        p => format!("{}ms", p),
+    }
+}
+
+fn no_arg_error(name: &str) -> String {
+    format!("No argument \"{}\" provided in the arguments HashMap.", name)
+}
+
+#[test]
+fn hist_sma_accuracy() {
+    // TODO: Write this test >.>
+}
+
+#[test]
+fn sma_accuracy() {
+    let mut sma = Sma::new(15);
+    let mut t = Tick {bid: 101, ask: 107, timestamp: 1};
+    let mut avg = sma.push(t).unwrap();
+    assert_eq!(avg, t.mid());
+
+    t = Tick {bid: 103, ask: 108, timestamp: 5};
+    avg = sma.push(t).unwrap();
+    let man_avg = (101 + 107) / 2;
+    assert_eq!(avg, man_avg);
+
+    t = Tick {bid: 105, ask: 109, timestamp: 13};
+    avg = sma.push(t).unwrap();
+    let man_avg = ((((101 + 107) / 2) * 4) +
+                  (((103 + 108) / 2) * 8)) / 12;
+    assert_eq!(avg, man_avg);
+
+    t = Tick {bid: 104, ask: 1088, timestamp: 18};
+    avg = sma.push(t).unwrap();
+    let man_avg = ((((103 + 108) / 2) * 8) +
+                  (((105 + 109) / 2) * 5) +
+                  (((101 + 107) / 2) * 2)) / 15;
+    assert_eq!(avg, man_avg);
+}
+
+#[test]
+fn tick_sma_accuracy() {
+    let mut sma = Sma::new(15);
+    let mut t = Tick {bid: 101, ask: 107, timestamp: 1};
+    let mut avg_t = sma.push_tick(t).unwrap();
+    assert_eq!(avg_t.mid(), t.mid());
+
+    t = Tick {bid: 103, ask: 108, timestamp: 5};
+    avg_t = sma.push_tick(t).unwrap();
+    let man_avg = (101 + 107) / 2;
+    assert_eq!(avg_t.mid(), man_avg);
+
+    t = Tick {bid: 105, ask: 109, timestamp: 13};
+    avg_t = sma.push_tick(t).unwrap();
+    let man_avg = ((((101 + 107) / 2) * 4) +
+                  (((103 + 108) / 2) * 8)) / 12;
+    assert_eq!(avg_t.mid(), man_avg);
+
+    t = Tick {bid: 104, ask: 1088, timestamp: 18};
+    avg_t = sma.push_tick(t).unwrap();
+    let man_avg = ((((103 + 108) / 2) * 8) +
+                  (((105 + 109) / 2) * 5) +
+                  (((101 + 107) / 2) * 2)) / 15;
+    assert_eq!(avg_t.mid(), man_avg);
+}
+
+#[test]
+fn sma_f64_accuracy() {
+    let mut sma = Sma::new(15);
+    assert_eq!(sma.push_f64(Tick {bid: 101, ask: 106, timestamp: 1}), Ok(Some(103.5)));
+    assert_eq!(sma.push_f64(Tick {bid: 103, ask: 108, timestamp: 5}), Ok(Some(103.5)));
+    // (103.5 * 4 + 105.5 * 8) / 12
+    assert_eq!(sma.push_f64(Tick {bid: 105, ask: 109, timestamp: 13}), Ok(Some(104.83333333333333)));
+}
+
+/// Duplicated and late ticks are refused without changing the average.
+#[test]
+fn sma_out_of_order() {
+    let mut sma = Sma::new(15);
+    sma.push(Tick {bid: 101, ask: 107, timestamp: 1}).unwrap();
+    let avg = sma.push(Tick {bid: 103, ask: 108, timestamp: 5}).unwrap();
+
+    assert_eq!(sma.push(Tick {bid: 200, ask: 202, timestamp: 5}), Err(SmaError::EqualTimestamp));
+    assert_eq!(sma.push_tick(Tick {bid: 200, ask: 202, timestamp: 3}), Err(SmaError::OutOfOrder{last: 5, got: 3}));
+    assert_eq!(sma.ticks.len(), 2);
+    assert_eq!(sma.average(), avg);
+}
+
+/// A new SMA and one holding a single tick don't span any time and shouldn't panic.
+#[test]
+fn sma_empty_and_single_tick() {
+    let sma = Sma::new(15);
+    assert!(!sma.is_overflown());
+    assert_eq!(sma.average(), 0);
+    assert_eq!(sma.average_f64(), 0.);
+    assert_eq!(sma.average_tick(), Tick::null());
+
+    let mut sma = Sma::new(15);
+    let t = Tick {bid: 101, ask: 107, timestamp: 1};
+    assert_eq!(sma.push(t), Ok(104));
+    assert!(!sma.is_overflown());
+    assert_eq!(sma.average(), 104);
+    assert_eq!(sma.average_f64(), 104.);
+    assert_eq!(sma.average_tick(), t);
+
+    // a period of zero trims everything but the newest tick
+    let mut sma = Sma::new(0);
+    sma.push(t).unwrap();
+    assert_eq!(sma.push(Tick {bid: 103, ask: 109, timestamp: 2}), Ok(106));
+    assert_eq!(sma.ticks.len(), 1);
+}
+
+/// Ticks that all share a timestamp can't be weighted by time, so their plain mean is used.
+#[test]
+fn sma_identical_timestamps() {
+    let mut sma = Sma::new(15);
+    sma.ticks.push_back(Tick {bid: 100, ask: 102, timestamp: 7});
+    sma.ticks.push_back(Tick {bid: 104, ask: 106, timestamp: 7});
+    sma.ticks.push_back(Tick {bid: 108, ask: 113, timestamp: 7});
+    assert!(!sma.is_overflown());
+    // mean bid 104, mean ask 107
+    assert_eq!(sma.average(), 105);
+    assert_eq!(sma.average_f64(), 105.5);
+    assert_eq!(sma.average_tick(), Tick {bid: 104, ask: 107, timestamp: 7});
+}
+
+/// Ticks spanning exactly the period overflow it, so the oldest is trimmed and becomes the reference tick.
+#[test]
+fn sma_exact_period_span() {
+    let mut sma = Sma::new(10);
+    sma.push(Tick {bid: 100, ask: 102, timestamp: 0}).unwrap();
+    sma.push(Tick {bid: 110, ask: 112, timestamp: 4}).unwrap();
+    let avg = sma.push(Tick {bid: 120, ask: 122, timestamp: 10}).unwrap();
+    assert_eq!(sma.ticks.len(), 2);
+    assert_eq!(sma.ref_tick, Tick {bid: 100, ask: 102, timestamp: 0});
+    // 111 for the 6 units since the second tick and 101 for the remaining 4
+    assert_eq!(avg, (111 * 6 + 101 * 4) / 10);
+}
+
+/// The SMA is warmed up as soon as its ticks span the period, and resetting it starts the warm-up over.
+#[test]
+fn sma_warm_up() {
+    let mut sma = Sma::new(10);
+    for timestamp in 0..10 {
+        sma.push(Tick {bid: 100, ask: 102, timestamp: timestamp}).unwrap();
+        assert!(!sma.is_warmed_up());
+    }
+    sma.push(Tick {bid: 100, ask: 102, timestamp: 10}).unwrap();
+    assert!(sma.is_warmed_up());
+    sma.push(Tick {bid: 100, ask: 102, timestamp: 11}).unwrap();
+    assert!(sma.is_warmed_up());
+
+    sma.reset();
+    assert!(!sma.is_warmed_up());
+    assert!(sma.ticks.is_empty());
+    assert_eq!(sma.push(Tick {bid: 110, ask: 112, timestamp: 20}), Ok(111));
+    assert!(!sma.is_warmed_up());
+    assert_eq!(sma.push(Tick {bid: 120, ask: 122, timestamp: 25}), Ok(111));
+}
+
+/// The original implementation of the averages, which walks the whole window every time.  Used to check that the
+/// running sums give exactly the same results.
+#[cfg(test)]
+fn windowed_averages(sma: &Sma) -> (usize, f64, Tick) {
+    if sma.ticks.len() < 2 {
+        return (sma.average(), sma.average_f64(), sma.average_tick());
+    }
+
+    let (mut mid_sum, mut mid_sum_f64, mut bid_sum, mut ask_sum, mut t_sum) = (0, 0., 0, 0, 0);
+    let mut last_tick = sma.ticks.front().unwrap();
+    for t in sma.ticks.iter().skip(1) {
+        let t_diff = t.timestamp - last_tick.timestamp;
+        mid_sum += last_tick.mid() as u64 * t_diff;
+        mid_sum_f64 += last_tick.mid_f64() * t_diff as f64;
+        bid_sum += last_tick.bid as u64 * t_diff;
+        ask_sum += last_tick.ask as u64 * t_diff;
+        t_sum += t_diff;
+        last_tick = t;
+    }
+
+    if sma.ref_tick.bid != 0 {
+        let old_time = sma.period - t_sum;
+        mid_sum += old_time * sma.ref_tick.mid() as u64;
+        mid_sum_f64 += old_time as f64 * sma.ref_tick.mid_f64();
+        bid_sum += old_time * sma.ref_tick.bid as u64;
+        ask_sum += old_time * sma.ref_tick.ask as u64;
+        t_sum = sma.period;
+    }
+
+    let average_tick = Tick {
+        bid: (bid_sum / t_sum) as usize,
+        ask: (ask_sum / t_sum) as usize,
+        timestamp: sma.ticks.back().unwrap().timestamp,
+    };
+    ((mid_sum / t_sum) as usize, mid_sum_f64 / t_sum as f64, average_tick)
+}
+
+/// The running sums match re-summing the window over a long series of random ticks, including ones that are
+/// refused for being out of order.
+#[test]
+fn sma_running_sums_match_window() {
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    let mut rng = XorShiftRng::from_seed([0x193a6754, 0xa8a7d469, 0x97830e05, 0x113ba7bb]);
+    for &period in &[1u64, 15, 1000, 60000] {
+        let mut sma = Sma::new(period);
+        let mut timestamp = 1;
+        for i in 0..100000 {
+            // every so often, repeat or go back to an older timestamp
+            let t_timestamp = if i % 97 == 96 { timestamp - rng.gen_range(0, 2) } else { timestamp };
+            let bid = rng.gen_range(100000, 110000);
+            let t = Tick {bid: bid, ask: bid + rng.gen_range(0, 30), timestamp: t_timestamp};
+            let res = sma.push(t);
+            if res.is_ok() {
+                timestamp += rng.gen_range(1, 250);
+            }
+
+            let (avg, avg_f64, avg_tick) = windowed_averages(&sma);
+            assert_eq!(sma.average(), avg);
+            assert_eq!(sma.average_f64(), avg_f64);
+            assert_eq!(sma.average_tick(), avg_tick);
+            if let Ok(res_avg) = res {
+                if sma.ticks.len() > 1 {
+                    assert_eq!(res_avg, avg);
+                }
+            }
+        }
+    }
+}
+
+#[bench]
+fn sma_calculation(b: &mut test::Bencher) {
+    let mut sma = Sma::new(15);
+    let mut timestamp = 1;
+
+    b.iter(|| {
+        sma.push(Tick{bid: 1239123, ask: 112312, timestamp: timestamp}).unwrap();
+        timestamp += 1;
+    });
+}
+
+/// Pushes into an SMA with a window of one hour of ticks at 50 ticks per second, which took time proportional to
+/// the size of the window before the running sums.
+#[bench]
+fn sma_calculation_large_period(b: &mut test::Bencher) {
+    let mut sma = Sma::new(60 * 60 * 1000);
+    let mut timestamp = 1;
+    for _ in 0..(60 * 60 * 50) {
+        sma.push(Tick{bid: 1239123, ask: 1239125, timestamp: timestamp}).unwrap();
+        timestamp += 20;
+    }
+
+    b.iter(|| {
+        sma.push(Tick{bid: 1239123, ask: 1239125, timestamp: timestamp}).unwrap();
+        timestamp += 20;
+    });
+}
+
+/// The second and minute constructors only change how the period is given.
+#[test]
+fn sma_period_units() {
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    let mut from_secs = Sma::new_seconds(60);
+    let mut from_units = Sma::new(60000);
+    assert_eq!(from_secs.period, from_units.period);
+    assert_eq!(Sma::new_minutes(1).period, 60000);
+
+    let mut rng = XorShiftRng::from_seed([7, 25, 1912, 3]);
+    let mut timestamp = 0;
+    for _ in 0..1000 {
+        timestamp += rng.gen_range(1, 5000);
+        let bid = rng.gen_range(1000, 1100);
+        let t = Tick {bid: bid, ask: bid + rng.gen_range(0, 5), timestamp: timestamp};
+        assert_eq!(from_secs.push_f64(t), from_units.push_f64(t));
+    }
+    assert_eq!(from_secs.average_tick(), from_units.average_tick());
+
+    assert_eq!(from_secs.period_label, "1m");
+    assert_eq!(Sma::new_minutes(15).period_label, "15m");
+    assert_eq!(Sma::new_minutes(120).period_label, "2h");
+    assert_eq!(Sma::new_seconds(90).period_label, "90s");
+    assert_eq!(Sma::new(1500).period_label, "1500ms");
+}
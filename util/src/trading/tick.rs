@@ -1,7 +1,10 @@
 //! Structs and functions for creating and managing Ticks.  Ticks represent one
 //! data point in a timeseries.
 
+use std::cmp;
 use std::fmt::{self, Debug, Formatter};
+use std::num::ParseIntError;
+use std::str::FromStr;
 
 use serde_json;
 
@@ -9,6 +12,11 @@ use serde_json;
 use test;
 
 use transport::query_server::QueryServer;
+use trading::bar::{Bar, OhlcvBuilder};
+use conf::CONF;
+
+/// Number of tick timestamp units in one second.  Tick timestamps are in milliseconds.
+pub const TICK_TIMESTAMP_UNIT_MS: u64 = 1000;
 
 /// A generic tick.  The data it holds is defined by the user.
 pub struct GenTick<T> {
@@ -44,6 +52,284 @@ pub struct SymbolTick {
     pub symbol: String
 }
 
+/// One of the fields of a `Tick`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickColumn {
+    Timestamp,
+    Bid,
+    Ask,
+}
+
+/// Why a CSV row or header couldn't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TickParseError {
+    /// The row doesn't have a value for the column
+    MissingField(TickColumn),
+    /// The value of the column isn't a valid unsigned integer
+    ParseIntError(TickColumn, ParseIntError),
+    InvalidValue(String),
+}
+
+/// The positions of the tick's fields in the columns of a CSV row.  Other columns are ignored.  The default is
+/// `timestamp,bid,ask`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvHeader {
+    pub timestamp: usize,
+    pub bid: usize,
+    pub ask: usize,
+}
+
+impl Default for CsvHeader {
+    fn default() -> CsvHeader {
+        CsvHeader {timestamp: 0, bid: 1, ask: 2}
+    }
+}
+
+impl CsvHeader {
+    /// Returns the number of columns needed to hold all of the tick's fields.
+    pub fn width(&self) -> usize {
+        *[self.timestamp, self.bid, self.ask].iter().max().unwrap() + 1
+    }
+
+    /// Finds the tick's fields in a header row such as "bid,ask,timestamp".  Column names are case insensitive, and
+    /// `tick_time` and `time` are accepted as names for the timestamp.
+    pub fn parse(header: &str) -> Result<CsvHeader, TickParseError> {
+        let (mut timestamp, mut bid, mut ask) = (None, None, None);
+        for (i, name) in header.split(',').enumerate() {
+            let slot = match name.trim().to_lowercase().as_str() {
+                "timestamp" | "tick_time" | "time" => &mut timestamp,
+                "bid" => &mut bid,
+                "ask" => &mut ask,
+                _ => continue,
+            };
+            if slot.is_some() {
+                let message = format!("Column {} appears more than once in the header.", name.trim());
+                return Err(TickParseError::InvalidValue(message));
+            }
+            *slot = Some(i);
+        }
+
+        match (timestamp, bid, ask) {
+            (Some(timestamp), Some(bid), Some(ask)) => Ok(CsvHeader {timestamp: timestamp, bid: bid, ask: ask}),
+            (None, _, _) => Err(TickParseError::MissingField(TickColumn::Timestamp)),
+            (_, None, _) => Err(TickParseError::MissingField(TickColumn::Bid)),
+            (_, _, None) => Err(TickParseError::MissingField(TickColumn::Ask)),
+        }
+    }
+}
+
+/// Epoch timestamps at least this large are taken to be in microseconds.  In milliseconds, this is in the year 5138.
+const MIN_MICROSECONDS: u64 = 100_000_000_000_000;
+/// Epoch timestamps at least this large are taken to be in milliseconds.  In seconds, this is in the year 5138.
+const MIN_MILLISECONDS: u64 = 100_000_000_000;
+/// Epoch timestamps smaller than this are too small to tell the unit of.  In seconds, this is in March 1973, while in
+/// milliseconds it's only a day into 1970.
+const MIN_SECONDS: u64 = 100_000_000;
+
+/// The unit of the timestamps in a CSV file or some other source of ticks.  The timestamps of `Tick`s themselves are
+/// always in milliseconds; see `TimestampNormalizer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimestampUnit {
+    /// Seconds, with the milliseconds written as three decimal places
+    Seconds,
+    Milliseconds,
+    /// Microseconds, which are truncated to milliseconds when parsed
+    Microseconds,
+}
+
+impl TimestampUnit {
+    /// Guesses the unit of an epoch timestamp from its magnitude, assuming that it's from between 1973 and the year
+    /// 5138.  Returns `None` if the timestamp is too small to tell, as the made-up timestamps of generated ticks are.
+    pub fn detect(timestamp: u64) -> Option<TimestampUnit> {
+        if timestamp >= MIN_MICROSECONDS {
+            Some(TimestampUnit::Microseconds)
+        } else if timestamp >= MIN_MILLISECONDS {
+            Some(TimestampUnit::Milliseconds)
+        } else if timestamp >= MIN_SECONDS {
+            Some(TimestampUnit::Seconds)
+        } else {
+            None
+        }
+    }
+
+    /// Converts a timestamp in this unit to milliseconds, truncating microseconds.
+    pub fn to_ms(&self, timestamp: u64) -> u64 {
+        match *self {
+            TimestampUnit::Seconds => timestamp.saturating_mul(1000),
+            TimestampUnit::Milliseconds => timestamp,
+            TimestampUnit::Microseconds => timestamp / 1000,
+        }
+    }
+
+    fn format(&self, timestamp_ms: u64) -> String {
+        match *self {
+            TimestampUnit::Seconds => format_decimal(timestamp_ms, 3),
+            TimestampUnit::Milliseconds => timestamp_ms.to_string(),
+            TimestampUnit::Microseconds => (timestamp_ms * 1000).to_string(),
+        }
+    }
+
+    fn parse(&self, field: &str) -> Result<u64, TickParseError> {
+        match *self {
+            TimestampUnit::Seconds => parse_decimal(field, 3, TickColumn::Timestamp),
+            TimestampUnit::Milliseconds => parse_csv_field(field, TickColumn::Timestamp),
+            TimestampUnit::Microseconds => parse_csv_field(field, TickColumn::Timestamp).map(|us| us / 1000),
+        }
+    }
+}
+
+impl FromStr for TimestampUnit {
+    type Err = String;
+
+    fn from_str(unit: &str) -> Result<TimestampUnit, String> {
+        match unit.trim().to_lowercase().as_str() {
+            "seconds" | "s" => Ok(TimestampUnit::Seconds),
+            "milliseconds" | "ms" => Ok(TimestampUnit::Milliseconds),
+            "microseconds" | "us" => Ok(TimestampUnit::Microseconds),
+            _ => Err(format!("Unknown timestamp unit: {}", unit)),
+        }
+    }
+}
+
+/// A timestamp that was detected as being in a different unit than the earlier timestamps of its stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MixedTimestampUnits {
+    pub timestamp: u64,
+    /// The unit of the stream's earlier timestamps
+    pub expected: TimestampUnit,
+    pub found: TimestampUnit,
+}
+
+impl fmt::Display for MixedTimestampUnits {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f, "The timestamp {} looks like {:?} but the stream's earlier timestamps were in {:?}",
+            self.timestamp, self.found, self.expected
+        )
+    }
+}
+
+/// Converts the timestamps of a stream of ticks to milliseconds as they're ingested.  The stream's unit is either
+/// given explicitly, in which case it's used for every timestamp no matter how it looks, or detected from the first
+/// timestamp large enough to tell, after which timestamps that look like they're in a different unit are refused.
+/// Timestamps too small to tell are taken to be in the stream's unit, or in milliseconds until it has one.
+#[derive(Debug, Clone)]
+pub struct TimestampNormalizer {
+    /// The unit that the stream's timestamps were said to be in
+    pub explicit: Option<TimestampUnit>,
+    /// The unit detected from the stream's timestamps so far
+    detected: Option<TimestampUnit>,
+}
+
+impl TimestampNormalizer {
+    pub fn new(explicit: Option<TimestampUnit>) -> TimestampNormalizer {
+        TimestampNormalizer {
+            explicit: explicit,
+            detected: None,
+        }
+    }
+
+    /// Creates a normalizer that uses the unit in the `tick_timestamp_unit` setting, or detects it if that isn't set.
+    pub fn from_conf() -> TimestampNormalizer {
+        let explicit = CONF.tick_timestamp_unit.and_then(|unit| match TimestampUnit::from_str(unit) {
+            Ok(unit) => Some(unit),
+            Err(err) => {
+                println!("{}; detecting the units of timestamps instead", err);
+                None
+            },
+        });
+        TimestampNormalizer::new(explicit)
+    }
+
+    /// Returns the tick with its timestamp in milliseconds, or an error if the timestamp doesn't look like it's in
+    /// the same unit as the ones before it.
+    pub fn normalize(&mut self, t: Tick) -> Result<Tick, MixedTimestampUnits> {
+        let unit = match (self.explicit, self.detected, TimestampUnit::detect(t.timestamp)) {
+            (Some(unit), _, _) => unit,
+            (None, Some(expected), Some(found)) if expected != found => {
+                return Err(MixedTimestampUnits {timestamp: t.timestamp, expected: expected, found: found});
+            },
+            (None, Some(expected), _) => expected,
+            (None, None, Some(found)) => {
+                self.detected = Some(found);
+                found
+            },
+            (None, None, None) => TimestampUnit::Milliseconds,
+        };
+        Ok(Tick {timestamp: unit.to_ms(t.timestamp), bid: t.bid, ask: t.ask})
+    }
+
+    /// Returns the unit that the stream's timestamps are being converted from, if it's known yet.
+    pub fn unit(&self) -> Option<TimestampUnit> {
+        self.explicit.or(self.detected)
+    }
+}
+
+/// How the bid and ask are written in a CSV file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceFormat {
+    /// The integer representation that the platform uses internally
+    Integer,
+    /// Decimal prices such as "1.23134", which are multiplied by 10^`decimals` to get the integer representation.
+    /// Digits past `decimals` are truncated when parsing.
+    Decimal{decimals: u32},
+}
+
+impl PriceFormat {
+    /// Writes a price in this format.
+    pub fn format(&self, price: usize) -> String {
+        match *self {
+            PriceFormat::Integer => price.to_string(),
+            PriceFormat::Decimal{decimals} => format_decimal(price as u64, decimals),
+        }
+    }
+
+    fn parse(&self, field: &str, column: TickColumn) -> Result<usize, TickParseError> {
+        let price = match *self {
+            PriceFormat::Integer => parse_csv_field(field, column)?,
+            PriceFormat::Decimal{decimals} => parse_decimal(field, decimals, column)?,
+        };
+        Ok(price as usize)
+    }
+}
+
+/// Describes how ticks are laid out in a CSV file.  The default is `timestamp,bid,ask` without a header row, with
+/// timestamps in milliseconds and prices in the platform's integer representation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvLayout {
+    /// The positions of the tick's fields in each row
+    pub columns: CsvHeader,
+    /// Whether the first row of the file is a header naming the columns
+    pub has_header: bool,
+    pub timestamp_unit: TimestampUnit,
+    pub price_format: PriceFormat,
+}
+
+impl Default for CsvLayout {
+    fn default() -> CsvLayout {
+        CsvLayout {
+            columns: CsvHeader::default(),
+            has_header: false,
+            timestamp_unit: TimestampUnit::Milliseconds,
+            price_format: PriceFormat::Integer,
+        }
+    }
+}
+
+impl CsvLayout {
+    /// Returns a header row naming the columns, ending with a newline.  Columns that aren't part of the tick are
+    /// left unnamed.
+    pub fn header_row(&self) -> String {
+        let mut names = vec![""; self.columns.width()];
+        names[self.columns.timestamp] = "timestamp";
+        names[self.columns.bid] = "bid";
+        names[self.columns.ask] = "ask";
+        let mut row = names.join(",");
+        row.push('\n');
+        row
+    }
+}
+
 impl Tick {
     /// Returns a dummy placeholder tick
     pub fn null() -> Tick {
@@ -61,8 +347,21 @@ impl Tick {
             .expect("Couldn't convert tick to json string")
     }
 
-    pub fn to_csv_row(&self) -> String {
-        format!("{}, {}, {}\n", self.timestamp, self.bid, self.ask)
+    /// Formats the tick as a CSV row ending with a newline, laid out as described by `layout`.  Columns that aren't
+    /// part of the tick are left empty.
+    pub fn to_csv_row(&self, layout: &CsvLayout) -> String {
+        let mut fields = vec![String::new(); layout.columns.width()];
+        fields[layout.columns.timestamp] = layout.timestamp_unit.format(self.timestamp);
+        fields[layout.columns.bid] = layout.price_format.format(self.bid);
+        fields[layout.columns.ask] = layout.price_format.format(self.ask);
+        let mut row = fields.join(",");
+        row.push('\n');
+        row
+    }
+
+    /// Creates an `OhlcvBuilder` of `period_ms` millisecond bars with this tick as the open of its first bar.
+    pub fn to_ohlcv_builder(&self, period_ms: u64) -> OhlcvBuilder {
+        Bar::from_tick(*self, period_ms)
     }
 
     /// Returns the difference between the bid and the ask
@@ -75,6 +374,11 @@ impl Tick {
         (self.bid + self.ask) / 2usize
     }
 
+    /// Returns the average of the bid and ask price without rounding it to an integer
+    pub fn mid_f64(&self) -> f64 {
+        (self.bid as f64 + self.ask as f64) / 2.0
+    }
+
     /// Saves the tick in the database.  The table "ticks_SYMBOL" must exist.
     pub fn store(&self, symbol: &str, qs: &mut QueryServer) {
         let query = format!(
@@ -112,17 +416,80 @@ impl Tick {
         }
     }
 
-    /// Converts a String in the format "{timestamp}, {bid}, {ask}" into a Tick
+    /// Converts a String in the format "{timestamp}, {bid}, {ask}" into a Tick, panicking if it can't be parsed
     pub fn from_csv_string(s: &str) -> Tick {
-        let spl: Vec<&str> = s.split(", ").collect();
-        Tick {
-            timestamp: u64::from_str_radix(spl[0], 10).unwrap(),
-            bid: usize::from_str_radix(spl[1], 10).unwrap(),
-            ask: usize::from_str_radix(spl[2].split('\n').collect::<Vec<_>>()[0], 10).unwrap()
+        Tick::from_csv_row(s, &CsvLayout::default()).expect("Unable to parse tick from CSV row")
+    }
+
+    /// Parses a CSV row laid out as described by `layout`.  Whitespace around the values is ignored.
+    pub fn from_csv_row(row: &str, layout: &CsvLayout) -> Result<Tick, TickParseError> {
+        let (mut timestamp, mut bid, mut ask) = (None, None, None);
+        for (i, field) in row.split(',').enumerate() {
+            if i == layout.columns.timestamp {
+                timestamp = Some(layout.timestamp_unit.parse(field)?);
+            } else if i == layout.columns.bid {
+                bid = Some(layout.price_format.parse(field, TickColumn::Bid)?);
+            } else if i == layout.columns.ask {
+                ask = Some(layout.price_format.parse(field, TickColumn::Ask)?);
+            }
+        }
+
+        match (timestamp, bid, ask) {
+            (Some(timestamp), Some(bid), Some(ask)) => Ok(Tick {timestamp: timestamp, bid: bid, ask: ask}),
+            (None, _, _) => Err(TickParseError::MissingField(TickColumn::Timestamp)),
+            (_, None, _) => Err(TickParseError::MissingField(TickColumn::Bid)),
+            (_, _, None) => Err(TickParseError::MissingField(TickColumn::Ask)),
         }
     }
 }
 
+/// Parses the value of one column of a CSV row, treating an empty value as missing.
+fn parse_csv_field(field: &str, column: TickColumn) -> Result<u64, TickParseError> {
+    let field = field.trim();
+    if field.is_empty() {
+        return Err(TickParseError::MissingField(column));
+    }
+    field.parse().map_err(|err| TickParseError::ParseIntError(column, err))
+}
+
+/// Parses a decimal such as "1.23134" into an integer scaled by 10^`decimals`, truncating any extra digits.
+fn parse_decimal(field: &str, decimals: u32, column: TickColumn) -> Result<u64, TickParseError> {
+    let field = field.trim();
+    if field.is_empty() {
+        return Err(TickParseError::MissingField(column));
+    }
+    let mut parts = field.splitn(2, '.');
+    let whole = parts.next().unwrap();
+    let fraction = parts.next().unwrap_or("");
+    if !fraction.chars().all(|c| c.is_digit(10)) {
+        return Err(TickParseError::InvalidValue(format!("Invalid decimal for {:?}: {}", column, field)));
+    }
+
+    let whole: u64 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().map_err(|err| TickParseError::ParseIntError(column, err))?
+    };
+    let mut fraction: String = fraction.chars().take(decimals as usize).collect();
+    while fraction.len() < decimals as usize {
+        fraction.push('0');
+    }
+    let fraction: u64 = if fraction.is_empty() { 0 } else { fraction.parse().unwrap() };
+    10u64.checked_pow(decimals)
+        .and_then(|scale| whole.checked_mul(scale))
+        .and_then(|scaled| scaled.checked_add(fraction))
+        .ok_or_else(|| TickParseError::InvalidValue(format!("Decimal for {:?} is too large: {}", column, field)))
+}
+
+/// Formats an integer scaled by 10^`decimals` as a decimal with exactly that many decimal places.
+fn format_decimal(value: u64, decimals: u32) -> String {
+    if decimals == 0 {
+        return value.to_string();
+    }
+    let scale = 10u64.pow(decimals);
+    format!("{}.{:0width$}", value / scale, value % scale, width = decimals as usize)
+}
+
 impl SymbolTick {
     /// creates a SymbolTick given a Tick and a SymbolTick
     pub fn from_tick(tick: Tick, symbol: String) -> SymbolTick {
@@ -135,6 +502,416 @@ impl SymbolTick {
     }
 }
 
+/// The result of checking a tick with a `TickValidator`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TickVerdict {
+    Ok,
+    /// The timestamp is the same as the last accepted tick's and too many ticks have already shared it
+    Duplicate,
+    /// The timestamp is before the last accepted tick's
+    Regressed,
+    /// The bid is above the ask
+    Crossed,
+    /// The bid or ask is zero or outside of the validator's price bounds
+    OutOfRange,
+}
+
+/// The number of ticks that a `TickValidator` has given each verdict.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TickVerdictCounts {
+    pub ok: u64,
+    pub duplicate: u64,
+    pub regressed: u64,
+    pub crossed: u64,
+    pub out_of_range: u64,
+}
+
+impl TickVerdictCounts {
+    pub fn get(&self, verdict: TickVerdict) -> u64 {
+        match verdict {
+            TickVerdict::Ok => self.ok,
+            TickVerdict::Duplicate => self.duplicate,
+            TickVerdict::Regressed => self.regressed,
+            TickVerdict::Crossed => self.crossed,
+            TickVerdict::OutOfRange => self.out_of_range,
+        }
+    }
+
+    /// Returns the number of ticks that were given any verdict other than `Ok`.
+    pub fn invalid(&self) -> u64 {
+        self.duplicate + self.regressed + self.crossed + self.out_of_range
+    }
+}
+
+/// Checks a stream of ticks for crossed or implausible prices and for timestamps that don't increase.  Each tick's
+/// timestamp is compared against that of the last tick that was accepted, so a single bad tick doesn't cause the
+/// ones after it to be rejected as well.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TickValidator {
+    /// The lowest bid or ask that's accepted; at least 1 so that ticks with missing prices are rejected
+    pub min_price: usize,
+    /// The highest bid or ask that's accepted
+    pub max_price: usize,
+    /// How many ticks after the first can share a timestamp before the rest are rejected as duplicates
+    pub equal_timestamp_tolerance: usize,
+    last_timestamp: Option<u64>,
+    /// Number of accepted ticks after the first with the same timestamp as the last accepted tick
+    equal_timestamps: usize,
+    counts: TickVerdictCounts,
+}
+
+impl Default for TickValidator {
+    /// Accepts any nonzero prices and rejects every tick with the same timestamp as the previous one.
+    fn default() -> TickValidator {
+        TickValidator::new(1, ::std::usize::MAX, 0)
+    }
+}
+
+impl TickValidator {
+    pub fn new(min_price: usize, max_price: usize, equal_timestamp_tolerance: usize) -> TickValidator {
+        TickValidator {
+            min_price: cmp::max(min_price, 1),
+            max_price: max_price,
+            equal_timestamp_tolerance: equal_timestamp_tolerance,
+            last_timestamp: None,
+            equal_timestamps: 0,
+            counts: TickVerdictCounts::default(),
+        }
+    }
+
+    /// Creates a validator with the price bounds and timestamp tolerance from the config.
+    pub fn from_conf() -> TickValidator {
+        TickValidator::new(CONF.tick_min_price, CONF.tick_max_price, CONF.tick_equal_timestamp_tolerance)
+    }
+
+    /// Checks a tick, counts its verdict, and records its timestamp if it's accepted.
+    pub fn validate(&mut self, t: &Tick) -> TickVerdict {
+        let verdict = self.classify(t);
+        self.record(verdict);
+        if verdict == TickVerdict::Ok {
+            self.accept(t);
+        }
+        verdict
+    }
+
+    /// Returns the verdict for a tick without counting it or recording its timestamp.  Prices are checked before
+    /// the timestamp.
+    pub fn classify(&self, t: &Tick) -> TickVerdict {
+        match self.check_prices(t) {
+            TickVerdict::Ok => self.check_timestamp(t),
+            verdict => verdict,
+        }
+    }
+
+    /// Checks only the bid and ask of a tick, returning `OutOfRange`, `Crossed`, or `Ok`.
+    pub fn check_prices(&self, t: &Tick) -> TickVerdict {
+        let in_range = |price: usize| price >= self.min_price && price <= self.max_price;
+        if !in_range(t.bid) || !in_range(t.ask) {
+            TickVerdict::OutOfRange
+        } else if t.bid > t.ask {
+            TickVerdict::Crossed
+        } else {
+            TickVerdict::Ok
+        }
+    }
+
+    /// Checks only the timestamp of a tick against the last accepted one, returning `Regressed`, `Duplicate`, or
+    /// `Ok`.
+    pub fn check_timestamp(&self, t: &Tick) -> TickVerdict {
+        match self.last_timestamp {
+            Some(last) if t.timestamp < last => TickVerdict::Regressed,
+            Some(last) if t.timestamp == last && self.equal_timestamps >= self.equal_timestamp_tolerance => {
+                TickVerdict::Duplicate
+            },
+            _ => TickVerdict::Ok,
+        }
+    }
+
+    /// Counts a verdict that was reached without `validate`.
+    pub fn record(&mut self, verdict: TickVerdict) {
+        match verdict {
+            TickVerdict::Ok => self.counts.ok += 1,
+            TickVerdict::Duplicate => self.counts.duplicate += 1,
+            TickVerdict::Regressed => self.counts.regressed += 1,
+            TickVerdict::Crossed => self.counts.crossed += 1,
+            TickVerdict::OutOfRange => self.counts.out_of_range += 1,
+        }
+    }
+
+    /// Records a tick as accepted so that later ticks are checked against its timestamp.  Ticks with timestamps
+    /// before that of the last accepted tick are ignored.
+    pub fn accept(&mut self, t: &Tick) {
+        match self.last_timestamp {
+            Some(last) if t.timestamp < last => (),
+            Some(last) if t.timestamp == last => self.equal_timestamps = self.equal_timestamps.saturating_add(1),
+            _ => {
+                self.last_timestamp = Some(t.timestamp);
+                self.equal_timestamps = 0;
+            },
+        }
+    }
+
+    /// Returns the timestamp of the last accepted tick.
+    pub fn last_timestamp(&self) -> Option<u64> {
+        self.last_timestamp
+    }
+
+    /// Returns the number of ticks that have been given each verdict.
+    pub fn counts(&self) -> &TickVerdictCounts {
+        &self.counts
+    }
+
+    pub fn count(&self, verdict: TickVerdict) -> u64 {
+        self.counts.get(verdict)
+    }
+}
+
+/// Drops ticks that repeat the last accepted tick: exact duplicates, and optionally ticks with the same bid and ask
+/// that arrive less than `unchanged_window_ms` after it.  Each tick is only compared against the last accepted one.
+/// Ticks with earlier timestamps than that one are passed on without becoming the new reference, so that they can be
+/// handled as out of order by whatever comes after.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TickDeduplicator {
+    /// Ticks with unchanged prices are dropped if they're less than this many milliseconds after the last accepted
+    /// tick.  `None` only drops exact duplicates.
+    pub unchanged_window_ms: Option<u64>,
+    last: Option<Tick>,
+    duplicates: u64,
+    unchanged: u64,
+}
+
+impl TickDeduplicator {
+    pub fn new(unchanged_window_ms: Option<u64>) -> TickDeduplicator {
+        TickDeduplicator {
+            unchanged_window_ms: unchanged_window_ms,
+            last: None,
+            duplicates: 0,
+            unchanged: 0,
+        }
+    }
+
+    /// Creates a deduplicator with the unchanged price window from the config, where 0 disables it.
+    pub fn from_conf() -> TickDeduplicator {
+        match CONF.tick_dedup_unchanged_window_ms {
+            0 => TickDeduplicator::new(None),
+            window_ms => TickDeduplicator::new(Some(window_ms as u64)),
+        }
+    }
+
+    /// Returns the tick if it should be kept, counting it and returning `None` if it's dropped.
+    pub fn filter(&mut self, t: Tick) -> Option<Tick> {
+        let last = match self.last {
+            Some(last) if t.timestamp < last.timestamp => return Some(t),
+            Some(last) => last,
+            None => {
+                self.last = Some(t);
+                return Some(t);
+            },
+        };
+
+        if t == last {
+            self.duplicates += 1;
+            return None;
+        }
+        let unchanged = t.bid == last.bid && t.ask == last.ask;
+        match self.unchanged_window_ms {
+            Some(window_ms) if unchanged && t.timestamp - last.timestamp < window_ms => {
+                self.unchanged += 1;
+                None
+            },
+            _ => {
+                self.last = Some(t);
+                Some(t)
+            },
+        }
+    }
+
+    /// Returns the number of exact duplicates that were dropped.
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+
+    /// Returns the number of ticks that were dropped for having unchanged prices.
+    pub fn unchanged(&self) -> u64 {
+        self.unchanged
+    }
+}
+
+#[test]
+fn mid_precision() {
+    let t = Tick {bid: 1, ask: 2, timestamp: 1};
+    assert_eq!(t.mid(), 1);
+    assert_eq!(t.mid_f64(), 1.5);
+}
+
+#[test]
+fn csv_row_parsing() {
+    let layout = CsvLayout::default();
+    let expected = Tick {timestamp: 1476650327123, bid: 123134, ask: 123156};
+    assert_eq!(Tick::from_csv_row("1476650327123,123134,123156", &layout), Ok(expected));
+    assert_eq!(Tick::from_csv_row("1476650327123, 123134, 123156\r\n", &layout), Ok(expected));
+    assert_eq!(Tick::from_csv_string("1476650327123, 123134, 123156\n"), expected);
+
+    let header = CsvHeader::parse("Bid,Ask,symbol,tick_time").unwrap();
+    assert_eq!(header, CsvHeader {timestamp: 3, bid: 0, ask: 1});
+    let with_header = CsvLayout {columns: header, has_header: true, ..CsvLayout::default()};
+    assert_eq!(Tick::from_csv_row("123134,123156,EURUSD,1476650327123", &with_header), Ok(expected));
+
+    let decimal = CsvLayout {
+        timestamp_unit: TimestampUnit::Seconds,
+        price_format: PriceFormat::Decimal{decimals: 5},
+        ..CsvLayout::default()
+    };
+    assert_eq!(Tick::from_csv_row("1476650327.123,1.23134,1.23156", &decimal), Ok(expected));
+    assert_eq!(Tick::from_csv_row("1476650327.1234,1.231345,1.2315600", &decimal), Ok(expected));
+    assert_eq!(Tick::from_csv_row("1476650327,1.23134,1.23156", &decimal).map(|t| t.timestamp), Ok(1476650327000));
+    assert_eq!(expected.to_csv_row(&decimal), "1476650327.123,1.23134,1.23156\n");
+    match Tick::from_csv_row("1476650327.123,1.23a34,1.23156", &decimal) {
+        Err(TickParseError::InvalidValue(_)) => (),
+        res => panic!("Expected the bid to be rejected, got {:?}", res),
+    }
+    // too large to scale up to the integer representation
+    match Tick::from_csv_row("1476650327.123,1000000000000000.12345,1.23156", &decimal) {
+        Err(TickParseError::InvalidValue(_)) => (),
+        res => panic!("Expected the oversized bid to be rejected, got {:?}", res),
+    }
+
+    let missing = |column| -> Result<Tick, TickParseError> { Err(TickParseError::MissingField(column)) };
+    assert_eq!(Tick::from_csv_row("1476650327123,123134", &layout), missing(TickColumn::Ask));
+    assert_eq!(Tick::from_csv_row("1476650327123,,123156", &layout), missing(TickColumn::Bid));
+    match Tick::from_csv_row("1476650327123,1.23134,123156", &layout) {
+        Err(TickParseError::ParseIntError(TickColumn::Bid, _)) => (),
+        res => panic!("Expected the bid to be rejected, got {:?}", res),
+    }
+    assert_eq!(CsvHeader::parse("timestamp,bid"), Err(TickParseError::MissingField(TickColumn::Ask)));
+    match CsvHeader::parse("timestamp,bid,ask,bid") {
+        Err(TickParseError::InvalidValue(_)) => (),
+        res => panic!("Expected the duplicate column to be rejected, got {:?}", res),
+    }
+}
+
+/// Random ticks come back unchanged after being written and parsed with every combination of column order, header,
+/// timestamp unit, and price format.
+#[test]
+fn csv_layout_round_trip() {
+    use rand::{thread_rng, Rng};
+
+    let orders = [(0, 1, 2), (0, 2, 1), (1, 0, 2), (1, 2, 0), (2, 0, 1), (2, 1, 0), (3, 0, 1)];
+    let units = [TimestampUnit::Seconds, TimestampUnit::Milliseconds, TimestampUnit::Microseconds];
+    let price_formats = [PriceFormat::Integer, PriceFormat::Decimal{decimals: 0}, PriceFormat::Decimal{decimals: 5}];
+    let mut rng = thread_rng();
+
+    for &(timestamp, bid, ask) in orders.iter() {
+        for &has_header in [false, true].iter() {
+            for &timestamp_unit in units.iter() {
+                for &price_format in price_formats.iter() {
+                    let layout = CsvLayout {
+                        columns: CsvHeader {timestamp: timestamp, bid: bid, ask: ask},
+                        has_header: has_header,
+                        timestamp_unit: timestamp_unit,
+                        price_format: price_format,
+                    };
+                    assert_eq!(CsvHeader::parse(&layout.header_row()), Ok(layout.columns));
+
+                    for _ in 0..100 {
+                        let t = Tick {
+                            // small enough that the timestamp fits in microseconds
+                            timestamp: rng.gen_range(0, 1 << 50),
+                            bid: rng.gen_range(0, 1 << 40),
+                            ask: rng.gen_range(0, 1 << 40),
+                        };
+                        let row = t.to_csv_row(&layout);
+                        assert_eq!(Tick::from_csv_row(&row, &layout), Ok(t), "{:?} {}", layout, row);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Streams in each unit come out in milliseconds, with timestamps too small to tell taking on the stream's unit.
+#[test]
+fn timestamp_unit_detection() {
+    let cases = [
+        (TimestampUnit::Seconds, 1476650327, 1476650327000),
+        (TimestampUnit::Milliseconds, 1476650327123, 1476650327123),
+        (TimestampUnit::Microseconds, 1476650327123456, 1476650327123),
+    ];
+    for &(unit, timestamp, expected) in cases.iter() {
+        assert_eq!(TimestampUnit::detect(timestamp), Some(unit));
+        let mut normalizer = TimestampNormalizer::new(None);
+        let normalize = |normalizer: &mut TimestampNormalizer, timestamp: u64| {
+            normalizer.normalize(Tick {timestamp: timestamp, bid: 100, ask: 102}).map(|t| t.timestamp)
+        };
+        // generated ticks with made-up timestamps pass through until the unit is known
+        assert_eq!(normalize(&mut normalizer, 5), Ok(5));
+        assert_eq!(normalizer.unit(), None);
+        assert_eq!(normalize(&mut normalizer, timestamp), Ok(expected));
+        assert_eq!(normalizer.unit(), Some(unit));
+        assert_eq!(normalize(&mut normalizer, 5), Ok(unit.to_ms(5)));
+    }
+    assert_eq!(TimestampUnit::detect(99999999), None);
+    assert_eq!(TimestampUnit::from_str(" Microseconds"), Ok(TimestampUnit::Microseconds));
+    assert_eq!(TimestampUnit::from_str("ms"), Ok(TimestampUnit::Milliseconds));
+    assert!(TimestampUnit::from_str("minutes").is_err());
+}
+
+/// A timestamp in another unit than the rest of its stream is refused, while an explicit unit is used even for
+/// timestamps that look like they're in a different one.
+#[test]
+fn timestamp_unit_mixing_and_override() {
+    let tick = |timestamp: u64| Tick {timestamp: timestamp, bid: 100, ask: 102};
+    let mut detected = TimestampNormalizer::new(None);
+    assert_eq!(detected.normalize(tick(1476650327)).map(|t| t.timestamp), Ok(1476650327000));
+    assert_eq!(detected.normalize(tick(1476650328123)), Err(MixedTimestampUnits {
+        timestamp: 1476650328123,
+        expected: TimestampUnit::Seconds,
+        found: TimestampUnit::Milliseconds,
+    }));
+    // a refused timestamp doesn't change the stream's unit
+    assert_eq!(detected.normalize(tick(1476650329)).map(|t| t.timestamp), Ok(1476650329000));
+
+    // these look like milliseconds, but were said to be microseconds
+    let mut explicit = TimestampNormalizer::new(Some(TimestampUnit::Microseconds));
+    assert_eq!(TimestampUnit::detect(1476650327123), Some(TimestampUnit::Milliseconds));
+    assert_eq!(explicit.normalize(tick(1476650327123)).map(|t| t.timestamp), Ok(1476650327));
+    assert_eq!(explicit.normalize(tick(1476650327)).map(|t| t.timestamp), Ok(1476650));
+    assert_eq!(explicit.unit(), Some(TimestampUnit::Microseconds));
+}
+
+/// Every verdict is reached and counted, and rejected ticks don't become the timestamp later ticks are checked
+/// against.
+#[test]
+fn tick_validation() {
+    let mut validator = TickValidator::new(100, 200000, 1);
+    let tick = |timestamp: u64, bid: usize, ask: usize| Tick {timestamp: timestamp, bid: bid, ask: ask};
+
+    assert_eq!(validator.validate(&tick(10, 100100, 100102)), TickVerdict::Ok);
+    assert_eq!(validator.validate(&tick(11, 100104, 100102)), TickVerdict::Crossed);
+    assert_eq!(validator.validate(&tick(11, 0, 100102)), TickVerdict::OutOfRange);
+    assert_eq!(validator.validate(&tick(11, 100100, 300000)), TickVerdict::OutOfRange);
+    assert_eq!(validator.validate(&tick(11, 99, 100)), TickVerdict::OutOfRange);
+    assert_eq!(validator.validate(&tick(9, 100100, 100102)), TickVerdict::Regressed);
+    // one extra tick can share a timestamp with the tolerance of 1
+    assert_eq!(validator.validate(&tick(10, 100100, 100102)), TickVerdict::Ok);
+    assert_eq!(validator.validate(&tick(10, 100100, 100102)), TickVerdict::Duplicate);
+    assert_eq!(validator.validate(&tick(11, 100100, 100100)), TickVerdict::Ok);
+    assert_eq!(validator.last_timestamp(), Some(11));
+
+    let expected = TickVerdictCounts {ok: 3, duplicate: 1, regressed: 1, crossed: 1, out_of_range: 3};
+    assert_eq!(validator.counts(), &expected);
+    assert_eq!(validator.count(TickVerdict::OutOfRange), 3);
+    assert_eq!(expected.invalid(), 6);
+
+    // prices are checked first and equal timestamps are rejected by default
+    let mut strict = TickValidator::default();
+    assert_eq!(strict.validate(&tick(5, 100, 102)), TickVerdict::Ok);
+    assert_eq!(strict.classify(&tick(4, 104, 102)), TickVerdict::Crossed);
+    assert_eq!(strict.validate(&tick(5, 100, 102)), TickVerdict::Duplicate);
+    assert_eq!(strict.validate(&tick(4, 100, 102)), TickVerdict::Regressed);
+    assert_eq!(strict.count(TickVerdict::Ok), 1);
+}
+
 #[bench]
 fn from_csv_string(b: &mut test::Bencher) {
     let s = "1476650327123, 123134, 123156\n";
@@ -152,3 +929,48 @@ fn json_to_tick(b: &mut test::Bencher) {
         Tick::from_json_string(s);
     });
 }
+
+// parse a CSV row with the same values into a Tick, for comparison with `json_to_tick`
+#[bench]
+fn csv_to_tick(b: &mut test::Bencher) {
+    b.iter(|| {
+        let s: String = String::from("1471291001837,1123128,1123140");
+        Tick::from_csv_row(&s, &CsvLayout::default()).unwrap();
+    });
+}
+
+/// Exact duplicates are always dropped, unchanged prices only within the window, and out of order ticks are passed
+/// on without changing what later ticks are compared against.
+#[test]
+fn tick_deduplication() {
+    let tick = |timestamp: u64, bid: usize, ask: usize| Tick {timestamp: timestamp, bid: bid, ask: ask};
+
+    let mut dedup = TickDeduplicator::new(None);
+    assert_eq!(dedup.filter(tick(10, 100, 102)), Some(tick(10, 100, 102)));
+    assert_eq!(dedup.filter(tick(10, 100, 102)), None);
+    // same timestamp but different prices isn't a duplicate
+    assert_eq!(dedup.filter(tick(10, 101, 102)), Some(tick(10, 101, 102)));
+    assert_eq!(dedup.filter(tick(11, 101, 102)), Some(tick(11, 101, 102)));
+    assert_eq!((dedup.duplicates(), dedup.unchanged()), (1, 0));
+
+    let mut dedup = TickDeduplicator::new(Some(100));
+    assert!(dedup.filter(tick(10, 100, 102)).is_some());
+    assert_eq!(dedup.filter(tick(50, 100, 102)), None);
+    assert_eq!(dedup.filter(tick(109, 100, 102)), None);
+    // the window is measured from the last accepted tick rather than the last dropped one
+    assert!(dedup.filter(tick(110, 100, 102)).is_some());
+    assert!(dedup.filter(tick(120, 100, 103)).is_some());
+    assert_eq!(dedup.filter(tick(120, 100, 103)), None);
+    assert_eq!((dedup.duplicates(), dedup.unchanged()), (1, 2));
+
+    // an out of order tick is passed on even if it repeats an earlier one, and later ticks are still compared
+    // against the newest accepted tick
+    assert_eq!(dedup.filter(tick(10, 100, 102)), Some(tick(10, 100, 102)));
+    assert_eq!(dedup.filter(tick(130, 100, 103)), None);
+    let mut validator = TickValidator::default();
+    let verdicts: Vec<TickVerdict> = [tick(200, 100, 102), tick(150, 100, 102), tick(200, 100, 102)].iter()
+        .filter_map(|t| dedup.filter(*t))
+        .map(|t| validator.validate(&t))
+        .collect();
+    assert_eq!(verdicts, vec![TickVerdict::Ok, TickVerdict::Regressed]);
+}
@@ -12,15 +12,17 @@ pub trait TradingCondition {
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum TradingAction {
-    /// Opens an order at market price +-max_range pips.
+    /// Opens an order at market price +-max_range pips.  `tag` is an arbitrary label chosen by the client that's
+    /// attached to the resulting position.
     MarketOrder {
         symbol: String, long: bool, size: usize, stop: Option<usize>,
-        take_profit: Option<usize>, max_range: Option<usize>,
+        take_profit: Option<usize>, max_range: Option<usize>, tag: Option<String>,
     },
-    /// Opens an order at a price equal or better to `entry_price` as soon as possible.
+    /// Opens an order at a price equal or better to `entry_price` as soon as possible.  `tag` is an arbitrary
+    /// label chosen by the client that's attached to the order and the position it turns into.
     LimitOrder{
         symbol: String, long: bool, size: usize, stop: Option<usize>,
-        take_profit: Option<usize>, entry_price: usize,
+        take_profit: Option<usize>, entry_price: usize, time_in_force: TimeInForce, tag: Option<String>,
     },
     /// Closes `size` units of a position with the specified UUID at the current market rate.
     MarketClose{ uuid: Uuid, size: usize, },
@@ -33,3 +35,20 @@ pub enum TradingAction {
     /// Attempts to cancel an order
     CancelOrder{ uuid: Uuid },
 }
+
+/// How long a limit order stays on the books before the broker cancels it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeInForce {
+    /// Good 'til cancelled: the order stays pending until it's filled or cancelled.
+    GTC,
+    /// Good 'til date: the order is cancelled once the broker's time reaches `expires_at`.
+    GTD{expires_at: u64},
+    /// Immediate or cancel: as much of the order as can be filled right away is filled and the rest is cancelled.
+    IOC,
+}
+
+impl Default for TimeInForce {
+    fn default() -> TimeInForce {
+        TimeInForce::GTC
+    }
+}
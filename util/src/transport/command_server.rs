@@ -14,29 +14,39 @@
 
 extern crate test;
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::thread::{self, Thread};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::str::FromStr;
+use std::env;
 
 use futures::{Stream, Canceled};
 use futures::sync::mpsc::{unbounded, UnboundedSender, UnboundedReceiver};
 use futures::Future;
 use futures::sync::oneshot::{channel as oneshot, Sender, Receiver};
+use futures::stream::futures_unordered;
 use uuid::Uuid;
 use redis;
 
 use transport::redis::{get_client, sub_channel};
 use transport::commands::*;
+use transport::tracing::current_traceparent;
 use conf::CONF;
 
 /// A command waiting to be sent plus a Sender to send the Response/Error String
 /// through and the channel on which to broadcast the Command.
 struct CommandRequest {
     cmd: Command,
-    future: Sender<Result<Response, String>>,
+    /// Resolves to the response and the round trip time in milliseconds
+    future: Sender<Result<(Response, u64), String>>,
     channel: String,
+    /// `traceparent` of the span that was active when the command was queued
+    trace_context: Option<String>,
+    format: SerializationFormat,
+    latencies: LatencyHistory,
 }
 /// Contains a `CommandRequest` for a worker and a Sender that resolves when the worker
 /// becomes idle.
@@ -47,7 +57,17 @@ type UnboundedSenderQueue = Arc<Mutex<VecDeque<UnboundedSender<WorkerTask>>>>;
 type CommandQueue = Arc<Mutex<VecDeque<CommandRequest>>>;
 /// A `Vec` containing a `Uuid` of a `Response` that's expected and a `UnboundedSender` to send the
 /// response through once it arrives
-type RegisteredList = Vec<(Uuid, UnboundedSender<Result<Response, ()>>)>;
+type RegisteredList = Vec<(Uuid, UnboundedSender<Result<WrappedResponse, ()>>)>;
+/// Round trip times in milliseconds of the most recent commands sent with `execute`, oldest first
+type LatencyHistory = Arc<Mutex<VecDeque<u64>>>;
+
+/// Returned by `broadcast_with_individual_timeout` in place of the response of an instance that didn't respond
+/// before its timeout expired.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeout;
+
+/// How many of the most recent round trip times are kept for calculating latency percentiles
+pub const LATENCY_HISTORY_LEN: usize = 1000;
 /// A message to be sent to the timeout thread containing how long to time out for,
 /// a oneshot that resolves to a handle to the Timeout's thread as soon as the timeout begins,
 /// and a oneshot that resolves to `Err(())` if the timeout completes.
@@ -72,9 +92,9 @@ struct AlertList {
 /// Send out the Response to a worker that is registered interest to its Uuid
 fn send_messages(res: WrappedResponse, al: &Mutex<AlertList>) {
     let mut al_inner = al.lock().expect("Unable to unlock al n send_messages");
-    let pos_opt: Option<&mut (_, UnboundedSender<Result<Response, ()>>)> = al_inner.list.iter_mut().find(|x| x.0 == res.uuid );
+    let pos_opt: Option<&mut (_, UnboundedSender<Result<WrappedResponse, ()>>)> = al_inner.list.iter_mut().find(|x| x.0 == res.uuid );
     if pos_opt.is_some() {
-        pos_opt.unwrap().1.send( Ok(res.res) ).expect("Unable to send through subscribed future");
+        pos_opt.unwrap().1.send( Ok(res) ).expect("Unable to send through subscribed future");
     }
 }
 
@@ -89,7 +109,7 @@ impl AlertList {
 
     /// Register interest in Results with a specified Uuid and send
     /// the Result over the specified Oneshot when it's received
-    pub fn register(&mut self, response_uuid: &Uuid, c: UnboundedSender<Result<Response, ()>>) {
+    pub fn register(&mut self, response_uuid: &Uuid, c: UnboundedSender<Result<WrappedResponse, ()>>) {
         self.list.push((*response_uuid, c));
     }
 
@@ -106,10 +126,51 @@ impl AlertList {
 #[derive(Clone)]
 pub struct CommandServer {
     al: Arc<Mutex<AlertList>>,
+    latencies: LatencyHistory,
     command_queue: CommandQueue, // internal command queue
     conn_queue: UnboundedSenderQueue, // UnboundedSenders for idle command-UnboundedSender threadss
     client: redis::Client,
     instance: Instance, // The instance that owns this CommandServer
+    /// The format in which commands are sent
+    format: SerializationFormat,
+}
+
+lazy_static! {
+    /// The least severe level of log message that is sent to the logger, shared by every `CommandServer` in the
+    /// process so that it can be changed at runtime by `SetLogLevel`.  The platform's modules log by sending
+    /// messages to the logger through their `CommandServer`s rather than through a `tracing` subscriber, and
+    /// `tracing-subscriber` needs a much newer compiler than the platform is built with, so the level is kept here
+    /// instead of behind a reload layer.
+    static ref MIN_LOG_LEVEL: AtomicUsize = AtomicUsize::new(initial_log_level() as usize);
+}
+
+/// Reads the starting log level from the `RUST_LOG` environment variable, falling back to the `log_level` setting.
+pub fn initial_log_level() -> LogLevel {
+    env::var("RUST_LOG").ok()
+        .and_then(|level| LogLevel::from_str(&level).ok())
+        .or_else(|| LogLevel::from_str(CONF.log_level).ok())
+        .unwrap_or(LogLevel::Debug)
+}
+
+/// Sets the least severe level of log message that this process sends to the logger.
+pub fn set_log_level(level: LogLevel) {
+    MIN_LOG_LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+/// Returns `true` if messages of the given level are currently sent to the logger.
+pub fn log_level_enabled(level: &LogLevel) -> bool {
+    level.clone() as usize >= MIN_LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Applies the level of a `SetLogLevel` command, returning the `Response` to send back.
+pub fn set_log_level_response(level: &str) -> Response {
+    match LogLevel::from_str(level) {
+        Ok(level) => {
+            set_log_level(level);
+            Response::Ok
+        },
+        Err(err) => Response::Error{status: err},
+    }
 }
 
 /// Locks the `CommandQueue` and returns a queued command, if there are any.
@@ -121,11 +182,15 @@ fn try_get_new_command(command_queue: CommandQueue) -> Option<CommandRequest> {
 
 fn send_command_outer(
     al: &Mutex<AlertList>, command: &Command, client: &mut redis::Client,
-    mut sleeper_tx: &mut UnboundedSender<TimeoutRequest>, res_c: Sender<Result<Response, String>>,
-    command_queue: CommandQueue, mut attempts: usize, commands_channel: String
+    mut sleeper_tx: &mut UnboundedSender<TimeoutRequest>, res_c: Sender<Result<(Response, u64), String>>,
+    command_queue: CommandQueue, mut attempts: usize, commands_channel: String, trace_context: Option<String>,
+    format: SerializationFormat, latencies: LatencyHistory
 ) {
-    let wr_cmd = command.wrap();
-    let _ = send_command(&wr_cmd, client, commands_channel.as_str());
+    // commands are sent from worker threads, so the trace context of the thread that queued them is used
+    let mut wr_cmd = command.wrap();
+    wr_cmd.trace_context = trace_context;
+    let sent_at = Instant::now();
+    send_command_as(&wr_cmd, client, commands_channel.as_str(), format);
 
     let (sleepy_c, sleepy_o) = oneshot::<Thread>();
     let (awake_c, awake_o) = oneshot::<Result<Response, ()>>();
@@ -141,7 +206,7 @@ fn send_command_outer(
     // sleepy_o fulfills immediately to a handle to the sleeper thread
     let sleepy_handle = sleepy_o.wait();
     // UnboundedSender for giving to the AlertList and sending the response back
-    let (res_recvd_c, res_recvd_o) = unbounded::<Result<Response, ()>>();
+    let (res_recvd_c, res_recvd_o) = unbounded::<Result<WrappedResponse, ()>>();
     // register interest in new Responses coming in with our Command's Uuid
     {
         al.lock().expect("Unlock to lock al in send_command_outer #1")
@@ -149,10 +214,11 @@ fn send_command_outer(
     }
     res_recvd_o.into_future().map(|(item_opt, _)| {
         item_opt.expect("item_opt was None")
-    }).map_err(|_| Canceled ).select(awake_o).and_then(move |res| {
+            .map(|wrapped_res| (wrapped_res.elapsed_ms(sent_at), wrapped_res.res))
+    }).map_err(|_| Canceled ).select(awake_o.map(|timed_out| timed_out.map(|res| (0, res)))).and_then(move |res| {
         let (status, _) = res;
         match status {
-            Ok(wrapped_res) => { // command received
+            Ok((round_trip_ms, res)) => { // command received
                 {
                     // deregister since we're only waiting on one message
                     al.lock().expect("Unlock to lock al in send_command_outer #2")
@@ -160,8 +226,15 @@ fn send_command_outer(
                 }
                 // end the timeout now so that we can re-use sleeper thread
                 sleepy_handle.expect("Couldn't unwrap handle to sleeper thread").unpark();
+                {
+                    let mut latencies = latencies.lock().expect("Unable to lock latency history");
+                    if latencies.len() == LATENCY_HISTORY_LEN {
+                        latencies.pop_front();
+                    }
+                    latencies.push_back(round_trip_ms);
+                }
                 // resolve the Response future
-                res_c.complete(Ok(wrapped_res));
+                res_c.complete(Ok((res, round_trip_ms)));
                 return Ok(sleeper_tx)
             },
             Err(_) => { // timed out
@@ -179,7 +252,7 @@ fn send_command_outer(
                 } else { // re-send the command
                     // we can do this recursively since it's only a few retries
                     send_command_outer(al, &wr_cmd.cmd, client, sleeper_tx, res_c,
-                        command_queue, attempts, commands_channel)
+                        command_queue, attempts, commands_channel, wr_cmd.trace_context.clone(), format, latencies)
                 }
             }
         }
@@ -195,10 +268,16 @@ fn dispatch_worker(
     let (cr, idle_c) = work;
 
     // completes initial command and internally iterates until queue is empty
-    send_command_outer(al, &cr.cmd, &mut client, sleeper_tx, cr.future, command_queue.clone(), 0, cr.channel);
+    send_command_outer(
+        al, &cr.cmd, &mut client, sleeper_tx, cr.future, command_queue.clone(), 0, cr.channel, cr.trace_context, cr.format,
+        cr.latencies
+    );
     // keep trying to get queued commands to execute until the queue is empty;
     while let Some(cr) = try_get_new_command(command_queue.clone()) {
-        send_command_outer(al, &cr.cmd, client, &mut sleeper_tx, cr.future, command_queue.clone(), 0, cr.channel);
+        send_command_outer(
+            al, &cr.cmd, client, &mut sleeper_tx, cr.future, command_queue.clone(), 0, cr.channel, cr.trace_context,
+            cr.format, cr.latencies
+        );
     }
     idle_c.complete(());
 
@@ -254,7 +333,8 @@ impl CommandServer {
         thread::spawn(move || {
             for raw_res_res in rx.wait() {
                 let raw_res = raw_res_res.expect("Res was error in CommandServer response UnboundedReceiver thread.");
-                let parsed_res = parse_wrapped_response(raw_res);
+                let mut parsed_res = parse_wrapped_response(raw_res);
+                parsed_res.received_at_ms = Some(unix_time_ms());
                 send_messages(parsed_res, &*al_clone);
             }
         });
@@ -276,30 +356,49 @@ impl CommandServer {
 
         CommandServer {
             al: al,
+            latencies: Arc::new(Mutex::new(VecDeque::with_capacity(LATENCY_HISTORY_LEN))),
             command_queue: command_queue,
             conn_queue: Arc::new(Mutex::new(conn_queue)),
             client: client,
-            instance: Instance{ uuid: instance_uuid, instance_type: String::from(instance_type), },
+            instance: Instance::new(instance_type, instance_uuid),
+            format: SerializationFormat::default(),
         }
     }
 
+    /// Sets the format in which all commands sent from now on are encoded.  Responses are always JSON.
+    pub fn set_serialization_format(&mut self, format: SerializationFormat) {
+        self.format = format;
+    }
+
     /// Queues up a command to send to be sent.  Returns a future that resolves to
     /// the returned response.
     pub fn execute(
         &mut self, command: Command, commands_channel: String
-    ) -> Receiver<Result<Response, String>> {
+    ) -> impl Future<Item=Result<Response, String>, Error=Canceled> {
+        self.execute_timed(command, commands_channel)
+            .map(|res| res.map(|(res, _)| res))
+    }
+
+    /// Like `execute`, but the returned future resolves to the response along with the number of milliseconds between
+    /// when the command was sent and when the response was received.
+    pub fn execute_timed(
+        &mut self, command: Command, commands_channel: String
+    ) -> Receiver<Result<(Response, u64), String>> {
         let temp_lock_res = self.conn_queue.lock().unwrap().is_empty();
         // Force the guard locking conn_queue to go out of scope
         // this prevents the lock from being held through the entire if/else
         let copy_res = temp_lock_res;
         // future for handing back to the caller that resolves to Response/Error
-        let (res_c, res_o) = oneshot::<Result<Response, String>>();
+        let (res_c, res_o) = oneshot::<Result<(Response, u64), String>>();
         // future for notifying main thread when command is done and worker is idle
         let (idle_c, idle_o) = oneshot::<()>();
         let cr = CommandRequest {
             cmd: command,
             future: res_c,
             channel: commands_channel,
+            trace_context: current_traceparent(),
+            format: self.format,
+            latencies: self.latencies.clone(),
         };
 
         if copy_res {
@@ -324,6 +423,100 @@ impl CommandServer {
         res_o
     }
 
+    /// Sends a batch of commands, each paired with the channel to send it on, to Redis in a single pipeline.
+    /// Returns a future that resolves to the responses in the same order as the commands once all of them have
+    /// been received or the timeout expires.  The timeout applies to the batch as a whole and commands aren't
+    /// re-sent; commands that haven't received a response by the time it expires resolve to an error.
+    pub fn execute_many(
+        &mut self, cmds: Vec<(Command, String)>
+    ) -> impl Future<Item=Vec<Result<Response, String>>, Error=Canceled> {
+        let (all_responses_c, all_responses_o) = oneshot::<Vec<Result<Response, String>>>();
+        let wr_cmds: Vec<(WrappedCommand, String)> = cmds.into_iter()
+            .map(|(cmd, channel)| (cmd.wrap(), channel))
+            .collect();
+        let uuids: Vec<Uuid> = wr_cmds.iter().map(|&(ref wr_cmd, _)| wr_cmd.uuid).collect();
+        if uuids.is_empty() {
+            all_responses_c.complete(Vec::new());
+            return all_responses_o;
+        }
+
+        // register interest in all of the responses before sending anything so that fast responses aren't missed
+        let mut receivers = Vec::with_capacity(uuids.len());
+        {
+            let mut al_inner = self.al.lock().expect("Unable to lock al in execute_many");
+            for uuid in &uuids {
+                let (res_recvd_c, res_recvd_o) = unbounded::<Result<WrappedResponse, ()>>();
+                al_inner.register(uuid, res_recvd_c);
+                let uuid = *uuid;
+                receivers.push(res_recvd_o.into_future().map(move |(item_opt, _)| (uuid, item_opt)).map_err(|_| ()));
+            }
+        }
+
+        // responses can arrive in any order, so they're collected by `Uuid` and sorted out once they're all in
+        let received: Arc<Mutex<HashMap<Uuid, Result<Response, String>>>> = Arc::new(Mutex::new(HashMap::new()));
+        let received_clone = received.clone();
+        let all_received = futures_unordered(receivers).for_each(move |(uuid, item_opt)| {
+            if let Some(Ok(wrapped_res)) = item_opt {
+                received_clone.lock().unwrap().insert(uuid, Ok(wrapped_res.res));
+            }
+            Ok(())
+        });
+
+        // spawn a new timeout thread just for this batch
+        let (sleeper_tx, sleeper_rx) = unbounded::<TimeoutRequest>();
+        let (sleepy_c, sleepy_o) = oneshot::<Thread>();
+        // awake_o fulfills when the timeout expires
+        let (awake_c, awake_o) = oneshot::<Result<Response, ()>>();
+        thread::spawn(move || init_sleeper(sleeper_rx) );
+
+        let alc = self.al.clone();
+        thread::spawn(move || {
+            let timed_out = awake_o.map(|_| ()).map_err(|_| ());
+            // block until every response has been received or the timeout expires
+            let select_res = all_received.select(timed_out).wait();
+            // end the timeout early if it's still running so that the sleeper thread can exit
+            if let Ok(handle) = sleepy_o.wait() {
+                handle.unpark();
+            }
+
+            // deregister before dropping the listeners so that late responses have nowhere to go
+            {
+                let mut al_inner = alc.lock().expect("Unable to lock al in execute_many");
+                for uuid in &uuids {
+                    al_inner.deregister(uuid);
+                }
+            }
+            drop(select_res);
+
+            let mut received = received.lock().unwrap();
+            let results = uuids.iter().map(|uuid| {
+                received.remove(uuid).unwrap_or_else(|| {
+                    Err(format!("Timed out waiting for a response to command {}", uuid.hyphenated()))
+                })
+            }).collect();
+            all_responses_c.complete(results);
+        });
+
+        // actually send the commands
+        let mut pipe = redis::pipe();
+        for &(ref wr_cmd, ref channel) in &wr_cmds {
+            pipe.cmd("PUBLISH")
+                .arg(channel.as_str())
+                .arg(&wr_cmd.to_bytes(self.format)[..]);
+        }
+        pipe.execute(&self.client);
+
+        let timeout_msg = TimeoutRequest {
+            dur: Duration::from_millis(CONF.cs_timeout as u64),
+            thread_future: sleepy_c,
+            timeout_future: awake_c
+        };
+        // initiate timeout
+        sleeper_tx.send(timeout_msg).unwrap();
+
+        all_responses_o
+    }
+
     pub fn broadcast(
         &mut self, command: Command, commands_channel: String
     ) -> Receiver<Vec<Response>> {
@@ -340,7 +533,7 @@ impl CommandServer {
 
         let alc = self.al.clone();
 
-        let (res_recvd_c, res_recvd_o) = unbounded::<Result<Response, ()>>();
+        let (res_recvd_c, res_recvd_o) = unbounded::<Result<WrappedResponse, ()>>();
         {
             // oneshot triggered with matching message received
             let mut al_inner = alc.lock().expect("Unable to unlock to lock al in broadcast");
@@ -354,7 +547,7 @@ impl CommandServer {
                 match response {
                     Ok(res) => {
                         let mut responses = responses_container_clone.lock().unwrap();
-                        responses.push(res.expect("Inner error in responses iterator"))
+                        responses.push(res.expect("Inner error in responses iterator").res)
                     },
                     Err(err) => println!("Got error from response iterator: {:?}", err),
                 }
@@ -382,7 +575,7 @@ impl CommandServer {
         thread::spawn(move || init_sleeper(sleeper_rx) ); // timer thread
 
         // actually send the Command
-        let _ = send_command(&wr_cmd, &self.client, commands_channel.as_str());
+        send_command_as(&wr_cmd, &self.client, commands_channel.as_str(), self.format);
 
         let timeout_msg = TimeoutRequest {
             dur: dur,
@@ -395,13 +588,153 @@ impl CommandServer {
         all_responses_o
     }
 
+    /// Sends a command to each of the given instances over their own channels and returns a future that resolves to
+    /// their responses, sorted by instance `Uuid`, as soon as every instance has either responded or timed out.
+    /// Each instance has `per_instance_timeout_ms` from when its copy of the command was sent to respond, so unlike
+    /// `broadcast`, the result isn't held up past the slowest instance's timeout once the others have responded.
+    /// Commands aren't re-sent.
+    ///
+    /// The instances are passed in rather than counted from the spawner's list of living instances since the
+    /// `CommandServer` doesn't have that list.  Knowing who is expected to respond is also what lets each response
+    /// be paired with its instance's `Uuid`: responses on a shared channel only carry the command's `Uuid`, so each
+    /// instance is sent its own copy of the command on its own channel.  The responses are collected with
+    /// `futures_unordered` like the other broadcasts, but this version of `futures` has no timers, so they're
+    /// forwarded to a thread that waits for them against each instance's deadline with `recv_timeout`.
+    pub fn broadcast_with_individual_timeout(
+        &mut self, command: Command, instances: &[Uuid], per_instance_timeout_ms: u64
+    ) -> Receiver<Vec<(Uuid, Result<Response, Timeout>)>> {
+        let (all_responses_c, all_responses_o) = oneshot::<Vec<(Uuid, Result<Response, Timeout>)>>();
+        let mut instances = instances.to_vec();
+        instances.sort();
+        instances.dedup();
+        // each instance gets its own copy of the command so that responses can be told apart
+        let wr_cmds: Vec<WrappedCommand> = instances.iter().map(|_| command.wrap()).collect();
+        let cmd_uuids: Vec<Uuid> = wr_cmds.iter().map(|wr_cmd| wr_cmd.uuid).collect();
+
+        // register interest in all of the responses before sending anything so that fast responses aren't missed
+        let mut receivers = Vec::with_capacity(wr_cmds.len());
+        {
+            let mut al_inner = self.al.lock().expect("Unable to lock al in broadcast_with_individual_timeout");
+            for (i, uuid) in cmd_uuids.iter().enumerate() {
+                let (res_recvd_c, res_recvd_o) = unbounded::<Result<WrappedResponse, ()>>();
+                al_inner.register(uuid, res_recvd_c);
+                receivers.push(res_recvd_o.into_future().map(move |(item_opt, _)| (i, item_opt)).map_err(|_| ()));
+            }
+        }
+
+        // forward responses as they arrive so that they can be waited on with a deadline
+        let (received_tx, received_rx) = mpsc::channel::<(usize, Response)>();
+        thread::spawn(move || {
+            for item in futures_unordered(receivers).wait() {
+                if let Ok((i, Some(Ok(wrapped_res)))) = item {
+                    if received_tx.send((i, wrapped_res.res)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        let timeout = Duration::from_millis(per_instance_timeout_ms);
+        let mut deadlines = Vec::with_capacity(wr_cmds.len());
+        for (wr_cmd, uuid) in wr_cmds.iter().zip(instances.iter()) {
+            send_command_as(wr_cmd, &self.client, &uuid.hyphenated().to_string(), self.format);
+            deadlines.push(Instant::now() + timeout);
+        }
+
+        let alc = self.al.clone();
+        thread::spawn(move || {
+            let mut results: Vec<Option<Result<Response, Timeout>>> = vec![None; deadlines.len()];
+            loop {
+                let now = Instant::now();
+                for (slot, deadline) in results.iter_mut().zip(deadlines.iter()) {
+                    if slot.is_none() && *deadline <= now {
+                        *slot = Some(Err(Timeout));
+                    }
+                }
+                // wait until the next response or the soonest deadline of the instances that are still pending
+                let next_deadline = results.iter().zip(deadlines.iter())
+                    .filter(|&(slot, _)| slot.is_none())
+                    .map(|(_, deadline)| *deadline)
+                    .min();
+                let next_deadline = match next_deadline {
+                    Some(deadline) => deadline,
+                    None => break,
+                };
+                if let Ok((i, res)) = received_rx.recv_timeout(next_deadline - now) {
+                    if results[i].is_none() {
+                        results[i] = Some(Ok(res));
+                    }
+                }
+            }
+
+            // deregister so that late responses have nowhere to go, which also ends the forwarding thread
+            {
+                let mut al_inner = alc.lock().expect("Unable to lock al in broadcast_with_individual_timeout");
+                for uuid in &cmd_uuids {
+                    al_inner.deregister(uuid);
+                }
+            }
+
+            let results = instances.into_iter()
+                .zip(results.into_iter().map(|slot| slot.unwrap()))
+                .collect();
+            all_responses_c.complete(results);
+        });
+
+        all_responses_o
+    }
+
+    /// Returns the round trip times in milliseconds of the most recent commands sent with `execute`, oldest first.
+    pub fn recent_latencies(&self) -> Vec<u64> {
+        self.latencies.lock().expect("Unable to lock latency history").iter().cloned().collect()
+    }
+
+    /// Returns the 99th percentile round trip time in milliseconds of the most recent commands sent with `execute`,
+    /// or 0 if none have received responses yet.
+    pub fn p99_latency_ms(&self) -> u64 {
+        let mut latencies = self.recent_latencies();
+        if latencies.is_empty() {
+            return 0;
+        }
+        latencies.sort();
+        let rank = (latencies.len() * 99 + 99) / 100;
+        latencies[rank - 1]
+    }
+
+    /// Returns the type of the instance that owns this `CommandServer`.
+    pub fn instance_type(&self) -> String {
+        self.instance.instance_type.clone()
+    }
+
     /// Sends a command asynchronously without bothering to wait for responses.
     pub fn send_forget(&self, cmd: &Command, channel: &str) {
-        let _ = send_command(&cmd.wrap(), &self.client, channel);
+        send_command_as(&cmd.wrap(), &self.client, channel, self.format);
     }
 
-    /// Sends a message to the logger with the specified severity
+    /// Publishes an event to the responses channel, where it's picked up by every `subscribe_to_events()` stream.
+    pub fn send_event(&self, event: PlatformEvent) {
+        let wr = Response::Event{event: event}.wrap(Uuid::new_v4());
+        let _ = send_response(&wr, &self.client, CONF.redis_responses_channel);
+    }
+
+    /// Returns a stream of every `Event` response sent over the responses channel from now on.  Each stream has its
+    /// own subscription, which is open by the time this returns.  Other responses are skipped.
+    pub fn subscribe_to_events(&self) -> impl Stream<Item=WrappedResponse, Error=()> {
+        sub_channel(CONF.redis_host, CONF.redis_responses_channel)
+            .filter_map(|raw_res| match WrappedResponse::from_str(&raw_res) {
+                Ok(wr) => match wr.res {
+                    Response::Event{..} => Some(wr),
+                    _ => None,
+                },
+                Err(()) => None,
+            })
+    }
+
+    /// Sends a message to the logger with the specified severity unless it's less severe than the current log level
     pub fn log(&mut self, message_type_opt: Option<&str>, message: &str, level: LogLevel) {
+        if !log_level_enabled(&level) {
+            return;
+        }
         let message_type = match message_type_opt {
             Some(t) => t,
             None => "General",
@@ -441,6 +774,116 @@ impl CommandServer {
     }
 }
 
+/// Every command in a batch should be matched up with its own response even if the responses arrive out of order,
+/// and commands that never get a response should resolve to an error once the batch times out.
+#[test]
+fn execute_many_batch() {
+    let channel = format!("execute_many_test_{}", Uuid::new_v4().simple());
+    let rx = sub_channel(CONF.redis_host, &channel);
+    thread::spawn(move || {
+        let client = get_client(CONF.redis_host);
+        let wr_cmds: Vec<WrappedCommand> = rx.wait().take(10)
+            .map(|raw_cmd| WrappedCommand::from_str(&raw_cmd.unwrap()).unwrap())
+            .collect();
+        // reply in the reverse of the order the commands were sent in and ignore the sixth command entirely
+        for wr_cmd in wr_cmds.into_iter().rev() {
+            if let Command::Register{channel} = wr_cmd.cmd {
+                if channel != "5" {
+                    let wr_res = Response::Info{info: channel}.wrap(wr_cmd.uuid);
+                    send_response(&wr_res, &client, CONF.redis_responses_channel).unwrap();
+                }
+            }
+        }
+    });
+
+    let mut cs = CommandServer::new(Uuid::new_v4(), "`execute_many` Test");
+    let cmds = (0..10).map(|i| (Command::Register{channel: i.to_string()}, channel.clone())).collect();
+    let results = cs.execute_many(cmds).wait().unwrap();
+    assert_eq!(results.len(), 10);
+    for (i, res) in results.into_iter().enumerate() {
+        if i == 5 {
+            assert!(res.is_err());
+        } else {
+            assert_eq!(res, Ok(Response::Info{info: i.to_string()}));
+        }
+    }
+}
+
+/// Responses from each instance are returned as soon as they've all arrived or timed out, without waiting on the
+/// timeouts of the instances that did respond.
+#[test]
+fn broadcast_individual_timeouts() {
+    let mut instances: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+    instances.sort();
+    let slow = instances[1];
+    for &uuid in &instances {
+        let rx = sub_channel(CONF.redis_host, &uuid.hyphenated().to_string());
+        thread::spawn(move || {
+            let client = get_client(CONF.redis_host);
+            let wr_cmd = WrappedCommand::from_str(&rx.wait().next().unwrap().unwrap()).unwrap();
+            if uuid == slow {
+                thread::sleep(Duration::from_millis(1500));
+            }
+            let wr_res = Response::Info{info: uuid.hyphenated().to_string()}.wrap(wr_cmd.uuid);
+            send_response(&wr_res, &client, CONF.redis_responses_channel).unwrap();
+        });
+    }
+
+    let mut cs = CommandServer::new(Uuid::new_v4(), "Individual Timeout Test");
+    let start = Instant::now();
+    // the order in which the instances are given doesn't matter
+    let reversed: Vec<Uuid> = instances.iter().rev().cloned().collect();
+    let results = cs.broadcast_with_individual_timeout(Command::Ping, &reversed, 300).wait().unwrap();
+    assert!(start.elapsed() < Duration::from_millis(1500));
+    assert_eq!(results.len(), 3);
+    for (&(uuid, ref res), expected_uuid) in results.iter().zip(instances.iter()) {
+        assert_eq!(uuid, *expected_uuid);
+        if uuid == slow {
+            assert_eq!(*res, Err(Timeout));
+        } else {
+            assert_eq!(*res, Ok(Response::Info{info: uuid.hyphenated().to_string()}));
+        }
+    }
+
+
+    // there's nothing to wait for without any instances
+    let start = Instant::now();
+    assert!(cs.broadcast_with_individual_timeout(Command::Ping, &[], 300).wait().unwrap().is_empty());
+    assert!(start.elapsed() < Duration::from_millis(300));
+}
+
+/// Round trip times are recorded for every command sent with `execute` and can be summarized as percentiles.
+#[test]
+fn command_latency_tracking() {
+    let channel = format!("latency_test_{}", Uuid::new_v4().simple());
+    let rx = sub_channel(CONF.redis_host, &channel);
+    thread::spawn(move || {
+        let client = get_client(CONF.redis_host);
+        for raw_cmd in rx.wait().take(100) {
+            let wr_cmd = WrappedCommand::from_str(&raw_cmd.unwrap()).unwrap();
+            // make sure that every round trip takes long enough to be measured in milliseconds
+            thread::sleep(Duration::from_millis(5));
+            send_response(&Response::Pong{args: Vec::new()}.wrap(wr_cmd.uuid), &client, CONF.redis_responses_channel)
+                .unwrap();
+        }
+    });
+
+    let mut cs = CommandServer::new(Uuid::new_v4(), "Latency Test");
+    assert_eq!(cs.p99_latency_ms(), 0);
+    for _ in 0..100 {
+        let (res, round_trip_ms) = cs.execute_timed(Command::Ping, channel.clone()).wait().unwrap().unwrap();
+        assert_eq!(res, Response::Pong{args: Vec::new()});
+        // the send time is reconstructed from an `Instant`, so it can be off by a millisecond
+        assert!(round_trip_ms >= 4);
+    }
+
+    let mut latencies = cs.recent_latencies();
+    assert_eq!(latencies.len(), 100);
+    latencies.sort();
+    assert_eq!(cs.p99_latency_ms(), latencies[98]);
+    assert!(cs.p99_latency_ms() >= 4);
+}
+
 #[bench]
 fn thread_spawn(b: &mut test::Bencher) {
     b.iter(|| thread::spawn(|| {}))
@@ -2,8 +2,11 @@
 //! system as well as helper functions for Serialization/Deserialization and unwrapping.
 
 use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use serde::{Deserialize, Serializer, Deserializer};
 use serde_json;
+use serde_cbor;
 use redis;
 use uuid::Uuid;
 #[allow(unused_imports)]
@@ -11,6 +14,8 @@ use test;
 
 use std::collections::HashMap;
 
+use transport::tracing::{Span, TraceContext, current_traceparent};
+
 /// Represents a Command that can be serde'd and sent over Redis.
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub enum Command {
@@ -20,24 +25,265 @@ pub enum Command {
     Kill,
     Register {channel: String},
     Type, // returns what kind of instance this is
-    Ready {instance_type: String, uuid: Uuid}, /* signals that a newly spawned instance is ready to receive commands */
+    /// Changes the least severe level of log message that the instance with the given `uuid` sends to the logger.
+    /// `level` is the name of a `LogLevel`, case insensitive.
+    SetLogLevel {uuid: Uuid, level: String},
+    /// Signals that a newly spawned instance is ready to receive commands.  `depends_on` lists the Uuids of the
+    /// instances it needs in order to work.
+    Ready {
+        instance_type: String,
+        uuid: Uuid,
+        #[serde(default)]
+        depends_on: Vec<Uuid>,
+    },
     // Tick Processor Commands
+    // The commands below that take an optional `symbol` apply to the Tick Processor's primary symbol, the one it was
+    // spawned with, if it isn't set.
     AddCondition {condition_string: String},
     RemoveCondition {condition_string: String},
     ListConditions,
     SubTicks {broker_def: String},
+    /// Starts following another symbol, whose ticks are read from `ticks_<symbol>`.  Each symbol has its own
+    /// indicators, candles, and recorded ticks.
+    AddSymbol {symbol: String},
+    /// Stops following a symbol that was added with `AddSymbol`, flushing its candles and recorded ticks.
+    RemoveSymbol {symbol: String},
+    /// Sent by a Tick Processor to the control channel whenever the set of symbols it follows changes
+    TickProcessorSymbols {uuid: Uuid, symbols: Vec<String>},
+    /// Publishes a `CrossoverEvent` to `channel` whenever the time-weighted SMAs with the given periods in
+    /// milliseconds cross.  The SMAs and the crossover between them are added to the symbol's indicators, and
+    /// registering the same crossover again sends its events to the new channel instead.
+    RegisterCrossover {
+        fast_period: u64,
+        slow_period: u64,
+        channel: String,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// Starts calculating a time-weighted simple moving average with a period in milliseconds
+    AddSMAPeriod {
+        period_ms: u64,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    RemoveSMAPeriod {
+        period_ms: u64,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// Starts calculating an exponential moving average with a period in milliseconds
+    AddEma {
+        period_ms: u64,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    RemoveEma {
+        period_ms: u64,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// Starts calculating a relative strength index over `period` samples of the mid price taken every `interval_ms`
+    AddRsi {
+        period: usize,
+        interval_ms: u64,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    RemoveRsi {
+        period: usize,
+        interval_ms: u64,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// Starts calculating a MACD with EMA periods in milliseconds, publishing a `CrossoverEvent` to `channel` whenever
+    /// the MACD line crosses its signal line.  Periods that aren't supplied are set to the tick processor's defaults.
+    AddMacd {
+        fast_period_ms: Option<u64>,
+        slow_period_ms: Option<u64>,
+        signal_period_ms: Option<u64>,
+        channel: String,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    RemoveMacd {
+        fast_period_ms: Option<u64>,
+        slow_period_ms: Option<u64>,
+        signal_period_ms: Option<u64>,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// Starts calculating an average true range over `period` bars of `bar_ms` milliseconds
+    AddAtr {
+        bar_ms: u64,
+        period: usize,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    RemoveAtr {
+        bar_ms: u64,
+        period: usize,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// Starts aggregating ticks into OHLC candles of `duration_ms` milliseconds, publishing each completed one to
+    /// `candles_<symbol>_<duration_ms>` and also writing it to a Postgres table of that name if `store` is set.
+    /// Periods without any ticks produce empty candles at the previous close if `carry_forward` is set.
+    AddCandles {
+        duration_ms: u64,
+        carry_forward: bool,
+        store: bool,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    RemoveCandles {
+        duration_ms: u64,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// Starts building Renko bricks of `brick_size` price units from the mid price, publishing each completed one to
+    /// `renko_<symbol>_<brick_size>`.
+    AddRenko {
+        brick_size: usize,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    RemoveRenko {
+        brick_size: usize,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// Starts building Heikin-Ashi candles out of OHLC candles of `duration_ms` milliseconds, publishing each
+    /// completed one to `heikin_ashi_<symbol>_<duration_ms>`.  Gaps are handled as they are by `AddCandles`.
+    AddHeikinAshi {
+        duration_ms: u64,
+        carry_forward: bool,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    RemoveHeikinAshi {
+        duration_ms: u64,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// Starts, adjusts, or stops republishing a symbol's ticks at a lower rate to `sampled_ticks_<symbol>`.  Either
+    /// every `every_n`th tick or the last tick of every `bucket_ms` milliseconds is relayed; if neither is set, the
+    /// relay is stopped.  The latest tick that hasn't been relayed is sent once no ticks have arrived for `quiet_ms`,
+    /// which defaults to `bucket_ms` or to one second when sampling every Nth tick.
+    SetTickSampling {
+        #[serde(default)]
+        every_n: Option<usize>,
+        #[serde(default)]
+        bucket_ms: Option<u64>,
+        #[serde(default)]
+        quiet_ms: Option<u64>,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// Starts or stops recording every tick the Tick Processor receives to a Postgres table, which is created if it
+    /// doesn't exist.  Ticks are recorded to `ticks_<symbol>` unless `table` is set.
+    RecordTicks {
+        enabled: bool,
+        #[serde(default)]
+        table: Option<String>,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// Starts calculating an indicator of the given kind, such as "sma" or "rsi", with its parameters given as a JSON
+    /// object.  Its values are published to `indicators_<symbol>` at most once every `throttle_ms` milliseconds, or
+    /// after every tick if that isn't set.  If `hold_until_warm` is set, nothing is published until the indicator's
+    /// warm-up is complete.  A "crossover" compares two existing indicators whose ids are given as `a` and `b` and
+    /// publishes an event whenever `a` crosses above or below `b`.  The "tick_rate" and "spread" feed health indicators
+    /// take a `min_rate` or `max_spread` threshold, and publish an alert to the notifications channel when it's
+    /// crossed.  Responds with the id of the indicator.
+    AddIndicator {
+        kind: String,
+        params: serde_json::Value,
+        #[serde(default)]
+        throttle_ms: Option<u64>,
+        #[serde(default)]
+        hold_until_warm: bool,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    RemoveIndicator {
+        id: Uuid,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// Changes where indicator values are sent without resetting the indicators.  If `indicator` is set, only the
+    /// values of the indicator with that id are redirected; otherwise the values of every indicator of every symbol
+    /// are, including those of symbols followed later, and destinations set for single indicators are dropped.
+    /// Alerts are still sent to the notifications channel.
+    SetIndicatorOutput {
+        dest: IndicatorDest,
+        #[serde(default)]
+        indicator: Option<Uuid>,
+    },
+    /// Publishes a `HookEvent` to `channel` whenever `condition` starts being met by the symbol's indicators.  If it's
+    /// already met, the hook is triggered by the next tick.
+    AddHook {
+        condition: HookCondition,
+        channel: String,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    RemoveHook {
+        condition: HookCondition,
+        channel: String,
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// Responds with a JSON array of the symbol, id, kind, parameters, and latest value of every indicator
+    ListIndicators,
+    /// Responds with a JSON object keyed by indicator id holding the symbol, kind, parameters, latest value,
+    /// timestamp of the latest value, and `IndicatorDest` of every indicator, or only those of `symbol` if it's
+    /// supplied.  Indicators that are still warming up have a null value and `warm_up_complete` set to false.
+    IndicatorSnapshot {
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+    /// Responds with a JSON object of the number of ticks of each symbol that the Tick Processor has given each
+    /// `TickVerdict`, keyed by symbol
+    GetTickValidationCounts,
+    /// Changes what the Tick Processor does with incoming ticks once `capacity` of them are waiting to be processed.
+    /// `policy` is `DropOldest`, `DropNewest`, or `CoalesceToLatest`.
+    SetTickIntakePolicy {policy: String, capacity: usize},
+    /// Responds with a JSON object of the Tick Processor's intake policy and capacity, the number of ticks waiting
+    /// to be processed, and the number that have been dropped
+    GetTickIntakeStats,
     // Spawner Commands
     Census,
+    /// Returns a DOT-format graph of which instances depend on which, as declared in their `Ready` messages
+    DependencyGraph,
     SpawnOptimizer{strategy: String},
-    SpawnTickParser{symbol: String},
+    SpawnTickParser{
+        symbol: String,
+        #[serde(default)]
+        metadata: HashMap<String, String>,
+    },
+    /// Spawns a Tick Processor for each of the symbols, responding with a JSON array of their Uuids in the same
+    /// order.  Slots for Tick Processors that couldn't be spawned are `null`.
+    SpawnTickParserGroup{symbols: Vec<String>},
     SpawnBacktester,
     SpawnLogger,
     SpawnFxcmNativeDataDownloader,
     SpawnFxcmFlatfileDataDownloader,
     SpawnIexDataDownloader,
     SpawnPoloniexDataDownloader,
+    /// Spawns the data downloader configured for `source` and has it download the ticks of `symbol` between the
+    /// `start` and `end` timestamps to `dst` once it's ready
+    SpawnDataDownloader{source: HistTickSrc, dst: HistTickDst, symbol: String, start: String, end: String},
     KillInstance{uuid: Uuid},
     KillAllInstances,
+    /// Kills all instances of the given type (or all types if `None`) whose metadata matches the filter
+    KillGroup{instance_type: Option<String>, metadata_filter: Option<HashMap<String, String>>},
+    /// Replaces all instances of the given type one at a time, waiting for each replacement to become ready
+    /// before killing the instance it replaces and then waiting `delay_ms` before moving on to the next
+    RollingRestart{instance_type: String, delay_ms: u64},
+    /// Spawns every instance listed in the `[[spawn]]` entries of a TOML file on the spawner's filesystem
+    SpawnFromConfig{config_path: String},
+    /// Changes how long the spawner waits between pinging all instances to check that they're alive
+    SetHeartbeatInterval{ms: u64},
     // Commands for interfacing with the document store
     QueryDocumentStore{query: String},
     InsertIntoDocumentStore{doc: String},
@@ -46,10 +292,46 @@ pub enum Command {
     StartBacktest{definition: String},
     PauseBacktest{uuid: Uuid},
     ResumeBacktest{uuid: Uuid},
+    /// Pauses every backtest running on the Backtester
+    PauseAllBacktests,
+    /// Resumes every backtest running on the Backtester
+    ResumeAllBacktests,
     StopBacktest{uuid: Uuid},
     ListBacktests,
+    /// Pauses every backtest, saves their definitions and positions to the restart state file, and exits so that
+    /// an updated Backtester can resume them where they left off
+    PrepareForRestart,
+    /// Compares the results of two backtests, returning a `BacktestDiff` with B's stats relative to A's
+    DiffBacktests{uuid_a: Uuid, uuid_b: Uuid},
+    /// Returns the tick at position `tick_index` (counting from 0) of a backtest's recent tick history
+    InspectTick{backtest_uuid: Uuid, tick_index: u64},
+    /// Sends the ticks of a running backtest to a SimBroker from now on instead of its current data destination
+    AttachSimbroker{backtest_uuid: Uuid, simbroker_uuid: Uuid},
     ListSimbrokers,
     SpawnSimbroker{settings: HashMap<String, String>},
+    ModifySimbrokerOrder{
+        uuid: Uuid,
+        order_uuid: Uuid,
+        new_price: Option<usize>,
+        new_size: Option<usize>,
+        new_sl_tp: Option<(Option<usize>, Option<usize>)>,
+    },
+    CancelSimbrokerOrder{uuid: Uuid, order_uuid: Uuid},
+    /// Cancels every pending order of every symbol on a SimBroker at once, such as during a risk incident.  Responds
+    /// with the number of orders that were cancelled.
+    CancelAllOrders{simbroker_uuid: Uuid},
+    ListSimbrokerOrders{uuid: Uuid},
+    /// Lists the open positions of a SimBroker as blotter rows
+    SimbrokerPositions{uuid: Uuid},
+    /// Lists the pending orders of a SimBroker as blotter rows
+    SimbrokerOrders{uuid: Uuid},
+    EquityCurve{uuid: Uuid},
+    /// Returns aggregate performance statistics of a SimBroker
+    GetSimbrokerStats{uuid: Uuid},
+    /// Returns a SimBroker to its initial state so it can be reused, optionally replacing its settings
+    ResetSimbroker{uuid: Uuid, settings: Option<HashMap<String, String>>},
+    /// Measures the throughput of a SimBroker with synthetic ticks and orders and then resets it
+    BenchSimbroker{uuid: Uuid, n_ticks: usize, orders_per_tick: usize},
     // Data Downloader Commands
     // TODO: Create a `DataDownload` struct and replace these with that
     DownloadTicks {
@@ -81,6 +363,15 @@ pub enum Response {
     Document{doc: SrcDocument},
     DownloadProgress{download: RunningDownload},
     RunningDownloads{downloads: Vec<RunningDownload>},
+    /// Sent spontaneously rather than in reply to a command; see `CommandServer::subscribe_to_events()`
+    Event{event: PlatformEvent},
+}
+
+/// Something that happened on the platform that monitoring clients may want to know about
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub enum PlatformEvent {
+    /// An instance was registered with the spawner, either when it was spawned or once it sent `Ready`
+    InstanceSpawned{instance: Instance},
 }
 
 impl Command {
@@ -88,11 +379,21 @@ impl Command {
         serde_json::to_string(self).map_err(|_| ())
     }
 
-    /// Generates a new Uuid and creates a new WrappedCommand
+    /// Generates a new Uuid and creates a new WrappedCommand carrying the context of the current trace span
     pub fn wrap(&self) -> WrappedCommand {
         WrappedCommand {
             uuid: Uuid::new_v4(),
             cmd: self.clone(),
+            trace_context: current_traceparent(),
+        }
+    }
+
+    /// Returns the name of the command's variant
+    pub fn name(&self) -> String {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(name)) => name,
+            Ok(serde_json::Value::Object(map)) => map.keys().next().cloned().unwrap_or_default(),
+            _ => String::new(),
         }
     }
 }
@@ -105,6 +406,16 @@ impl FromStr for Command {
     }
 }
 
+/// Where to download historical ticks from.  Each source is handled by its own data downloader.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum HistTickSrc {
+    FxcmNative,
+    FxcmFlatfile,
+    Iex,
+    Poloniex,
+    Postgres,
+}
+
 /// Where to save the recorded ticks to.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum HistTickDst {
@@ -112,7 +423,60 @@ pub enum HistTickDst {
     Postgres { table: String },
     RedisChannel { host: String, channel: String },
     RedisSet { host: String, set_name: String },
+    Console {
+        #[serde(default)]
+        format: ConsoleTickFormat,
+    },
+    /// An Apache Arrow IPC file with `timestamp`, `bid`, and `ask` columns
+    Arrow { path: String },
+}
+
+/// Where the Tick Processor sends the values of its indicators.  By default, each symbol's are published to its
+/// `indicators_<symbol>` channel.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum IndicatorDest {
+    RedisChannel { host: String, channel: String },
+    /// Values are written to the table in batches, along with their symbol and indicator.  The table is created if
+    /// it doesn't exist.
+    Postgres { table: String },
     Console,
+    Null,
+}
+
+/// A condition on the indicators of one of a Tick Processor's symbols that triggers the hooks added with `AddHook`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum HookCondition {
+    /// Every indicator of the symbol other than the crossovers has completed its warm-up.  Adding an indicator that
+    /// has to warm up lets the hook trigger again once it has.
+    AllIndicatorsWarmedUp,
+}
+
+/// Published to the channel of a hook when its condition starts being met
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HookEvent {
+    pub condition: HookCondition,
+    pub symbol: String,
+    /// Timestamp of the tick that triggered the hook
+    pub timestamp: u64,
+}
+
+/// How ticks sent to `HistTickDst::Console` are printed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ConsoleTickFormat {
+    /// The tick's debug representation
+    Raw,
+    /// `timestamp,bid,ask` rows
+    Csv,
+    /// A JSON object for each tick
+    Json,
+    /// `[timestamp] bid ask` with the prices written as decimals with `console_tick_decimals` decimal places
+    HumanReadable,
+}
+
+impl Default for ConsoleTickFormat {
+    fn default() -> ConsoleTickFormat {
+        ConsoleTickFormat::Raw
+    }
 }
 
 /// A log message from some part of the platform
@@ -141,10 +505,56 @@ pub struct RunningDownload {
 pub struct Instance {
     pub instance_type: String,
     pub uuid: Uuid,
+    /// Arbitrary labels attached to the instance when it was spawned (`env=prod`, `region=us-east`, etc.)
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    /// When the instance was spawned, or when it was first seen if it wasn't spawned by the Spawner.  Serialized as
+    /// milliseconds since the epoch.
+    #[serde(rename = "spawn_time_unix_ms", default = "spawn_time_now",
+        serialize_with = "serialize_unix_ms", deserialize_with = "deserialize_unix_ms")]
+    pub spawn_time: SystemTime,
+}
+
+impl Instance {
+    /// Creates a new `Instance` without any metadata, spawned at the current time.
+    pub fn new(instance_type: &str, uuid: Uuid) -> Instance {
+        Instance {
+            instance_type: String::from(instance_type),
+            uuid: uuid,
+            metadata: HashMap::new(),
+            spawn_time: spawn_time_now(),
+        }
+    }
+
+    /// Returns how long ago the instance was spawned.
+    pub fn uptime(&self) -> Duration {
+        // the clock may have been set back since the instance was spawned
+        self.spawn_time.elapsed().unwrap_or(Duration::from_millis(0))
+    }
+
+    /// Returns `true` if the instance's metadata contains all of the key:value pairs in the filter.
+    pub fn matches_metadata(&self, filter: &HashMap<String, String>) -> bool {
+        filter.iter().all(|(k, v)| self.metadata.get(k) == Some(v))
+    }
+}
+
+/// The current time truncated to the millisecond so that it survives being serialized as an `Instance`'s spawn time.
+fn spawn_time_now() -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(unix_time_ms())
+}
+
+fn serialize_unix_ms<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::from_millis(0));
+    serializer.serialize_u64(since_epoch.as_secs() * 1000 + (since_epoch.subsec_nanos() / 1_000_000) as u64)
+}
+
+fn deserialize_unix_ms<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+    let ms = u64::deserialize(deserializer)?;
+    Ok(UNIX_EPOCH + Duration::from_millis(ms))
 }
 
-/// Severity of a log message, Notice through Critical
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+/// Severity of a log message, Notice through Critical.  Levels are ordered from least to most severe.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub enum LogLevel {
     Debug,
     Notice,
@@ -153,6 +563,21 @@ pub enum LogLevel {
     Critical,
 }
 
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(level: &str) -> Result<LogLevel, String> {
+        match level.trim().to_lowercase().as_str() {
+            "debug" | "trace" => Ok(LogLevel::Debug),
+            "notice" | "info" => Ok(LogLevel::Notice),
+            "warning" | "warn" => Ok(LogLevel::Warning),
+            "error" | "err" => Ok(LogLevel::Error),
+            "critical" => Ok(LogLevel::Critical),
+            _ => Err(format!("Unknown log level: {}", level)),
+        }
+    }
+}
+
 /// C-format `LogLevel` enum for use in FFI
 #[repr(C)]
 #[allow(dead_code)]
@@ -188,12 +613,58 @@ pub struct SrcDocument {
     pub id: Uuid,
 }
 
+/// How commands are encoded when they're sent over Redis.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SerializationFormat {
+    Json,
+    /// Binary CBOR, which is more compact and faster to encode than JSON.  Messages are sent as binary
+    /// Redis strings.  They're encoded with `serde_cbor` since `ciborium` needs a much newer compiler than the
+    /// platform is built with; both write standard CBOR, so messages can be decoded by either.
+    Cbor,
+}
+
+impl Default for SerializationFormat {
+    fn default() -> SerializationFormat {
+        SerializationFormat::Json
+    }
+}
+
+/// Returns `true` if the message is JSON rather than CBOR.  Messages are always JSON objects or strings, and
+/// neither `{` nor `"` can begin a CBOR-encoded map or string.
+fn is_json(bytes: &[u8]) -> bool {
+    match bytes.iter().find(|b| !(**b as char).is_whitespace()) {
+        Some(&b'{') | Some(&b'"') => true,
+        _ => false,
+    }
+}
+
+/// Decodes a message that was encoded in either of the `SerializationFormat`s.
+fn decode_message<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> Result<T, ()> {
+    if is_json(bytes) {
+        serde_json::from_slice(bytes).map_err(|_| ())
+    } else {
+        serde_cbor::from_slice(bytes).map_err(|_| ())
+    }
+}
+
+/// Converts a CBOR-encoded message into the equivalent JSON so that it can be read by code that only
+/// understands JSON.  Returns `None` if the message isn't valid CBOR.
+pub fn cbor_to_json(bytes: &[u8]) -> Option<String> {
+    match serde_cbor::from_slice::<serde_cbor::Value>(bytes) {
+        Ok(value) => serde_json::to_string(&value).ok(),
+        Err(_) => None,
+    }
+}
+
 /// Represents a command bound to a unique identifier that can be
 /// used to link it with a Response
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct WrappedCommand {
     pub uuid: Uuid,
     pub cmd: Command,
+    /// `traceparent` of the span the command was sent from, if it was sent while tracing
+    #[serde(default)]
+    pub trace_context: Option<String>,
 }
 
 impl WrappedCommand {
@@ -201,13 +672,46 @@ impl WrappedCommand {
         serde_json::to_string(self).map_err(|_| ())
     }
 
+    pub fn to_cbor_bytes(&self) -> Vec<u8> {
+        serde_cbor::to_vec(self).expect("Unable to serialize WrappedCommand to CBOR")
+    }
+
+    pub fn from_cbor_bytes(bytes: &[u8]) -> Result<WrappedCommand, serde_cbor::Error> {
+        serde_cbor::from_slice(bytes)
+    }
+
+    /// Encodes the command in the given format.
+    pub fn to_bytes(&self, format: SerializationFormat) -> Vec<u8> {
+        match format {
+            SerializationFormat::Json => serde_json::to_vec(self).expect("Unable to serialize WrappedCommand to JSON"),
+            SerializationFormat::Cbor => self.to_cbor_bytes(),
+        }
+    }
+
+    /// Decodes a command that was encoded in either format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<WrappedCommand, ()> {
+        decode_message(bytes)
+    }
+
     /// Creates a new WrappedCommand with the given command as an inner
     pub fn from_command(cmd: Command) -> WrappedCommand {
         WrappedCommand {
             uuid: Uuid::new_v4(),
             cmd: cmd.clone(),
+            trace_context: current_traceparent(),
         }
     }
+
+    /// Starts a span for handling the command as a child of the span that sent it.  Invalid trace contexts
+    /// are ignored.
+    pub fn span(&self, module: &str) -> Span {
+        let parent = self.trace_context.as_ref().and_then(|tp| TraceContext::from_traceparent(tp));
+        let mut span = Span::start(&format!("{} {}", module, self.cmd.name()), parent);
+        span.set_attribute("command", &self.cmd.name());
+        span.set_attribute("uuid", &self.uuid.hyphenated().to_string());
+        span.set_attribute("module", module);
+        span
+    }
 }
 
 impl FromStr for WrappedCommand {
@@ -234,12 +738,9 @@ impl Response {
         serde_json::to_string(self).map_err(|_| ())
     }
 
-    /// Creates a new WrappedResponse from a Command and a Uuid
+    /// Creates a new WrappedResponse from a Command and a Uuid, marking it as processed now
     pub fn wrap(&self, uuid: Uuid) -> WrappedResponse {
-        WrappedResponse {
-            uuid: uuid,
-            res: self.clone(),
-        }
+        WrappedResponse::from_response(self.clone(), uuid)
     }
 }
 
@@ -256,6 +757,12 @@ impl FromStr for Response {
 pub struct WrappedResponse {
     pub uuid: Uuid,
     pub res: Response,
+    /// When the command was handled, in milliseconds since the epoch
+    #[serde(default)]
+    pub processed_at_ms: u64,
+    /// When the response arrived at the `CommandServer` that sent the command, in milliseconds since the epoch
+    #[serde(default)]
+    pub received_at_ms: Option<u64>,
 }
 
 impl WrappedResponse {
@@ -263,13 +770,24 @@ impl WrappedResponse {
         serde_json::to_string(self).map_err(|_| ())
     }
 
-    /// Creates a new WrappedResponse from a Response and a Uuid
+    /// Creates a new WrappedResponse from a Response and a Uuid, marking it as processed now
     pub fn from_response(res: Response, uuid: Uuid) -> WrappedResponse {
         WrappedResponse {
             uuid: uuid,
             res: res,
+            processed_at_ms: unix_time_ms(),
+            received_at_ms: None,
         }
     }
+
+    /// Returns the number of milliseconds between `cmd_time`, when the command was sent, and when this response was
+    /// received, or now if it hasn't been received yet.
+    pub fn elapsed_ms(&self, cmd_time: Instant) -> u64 {
+        let now = unix_time_ms();
+        let elapsed = cmd_time.elapsed();
+        let sent_at_ms = now.saturating_sub(elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1_000_000) as u64);
+        self.received_at_ms.unwrap_or(now).saturating_sub(sent_at_ms)
+    }
 }
 
 impl FromStr for WrappedResponse {
@@ -280,6 +798,12 @@ impl FromStr for WrappedResponse {
     }
 }
 
+/// Returns the current time in milliseconds since the epoch.
+pub fn unix_time_ms() -> u64 {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    since_epoch.as_secs() * 1000 + (since_epoch.subsec_nanos() / 1_000_000) as u64
+}
+
 /// Utility function to asynchronously sends off a command
 pub fn send_command(cmd: &WrappedCommand, client: &redis::Client, commands_channel: &str) -> Result<(), serde_json::Error> {
     let command_string = try!(serde_json::to_string(cmd));
@@ -290,6 +814,16 @@ pub fn send_command(cmd: &WrappedCommand, client: &redis::Client, commands_chann
     Ok(())
 }
 
+/// Sends off a command encoded in the given format.
+pub fn send_command_as(
+    cmd: &WrappedCommand, client: &redis::Client, commands_channel: &str, format: SerializationFormat
+) {
+    redis::cmd("PUBLISH")
+        .arg(commands_channel)
+        .arg(&cmd.to_bytes(format)[..])
+        .execute(client);
+}
+
 /// Utility function to asynchronously send off a response
 pub fn send_response(res: &WrappedResponse, client: &redis::Client, channel: &str) -> Result<(), serde_json::Error> {
     let ser = try!(serde_json::to_string(res));
@@ -314,6 +848,10 @@ fn command_serialization() {
     let cmd_str = "{\"Register\":{\"channel\":\"channel\"}}";
     let cmd: Command = serde_json::from_str(cmd_str).unwrap();
     assert_eq!(cmd, Command::Register { channel: String::from("channel") });
+
+    // Tick Processor commands sent before they took a symbol still apply to the primary one
+    let cmd: Command = serde_json::from_str("{\"AddEma\":{\"period_ms\":60000}}").unwrap();
+    assert_eq!(cmd, Command::AddEma { period_ms: 60000, symbol: None });
 }
 
 #[test]
@@ -323,6 +861,18 @@ fn command_deserialization() {
     assert_eq!("{\"Register\":{\"channel\":\"channel\"}}", &cmd_string);
 }
 
+/// Log levels are parsed case-insensitively and ordered by severity.
+#[test]
+fn log_level_parsing() {
+    assert_eq!(LogLevel::from_str("DEBUG"), Ok(LogLevel::Debug));
+    assert_eq!(LogLevel::from_str("info"), Ok(LogLevel::Notice));
+    assert_eq!(LogLevel::from_str("Warn"), Ok(LogLevel::Warning));
+    assert_eq!(LogLevel::from_str(" error "), Ok(LogLevel::Error));
+    assert!(LogLevel::from_str("loud").is_err());
+    assert!(LogLevel::Debug < LogLevel::Notice);
+    assert!(LogLevel::Critical > LogLevel::Error);
+}
+
 #[test]
 fn response_serialization() {
     let res_str = "\"Ok\"";
@@ -335,6 +885,191 @@ fn response_deserialization() {
     let res = Response::Ok;
     let res_string = serde_json::to_string(&res).unwrap();
     assert_eq!("\"Ok\"", &res_string);
+
+    let instance = Instance::new("Tick Processor", Uuid::new_v4());
+    let res = Response::Event{event: PlatformEvent::InstanceSpawned{instance: instance}};
+    let res_string = serde_json::to_string(&res).unwrap();
+    assert_eq!(Response::from_str(&res_string), Ok(res));
+}
+
+/// Returns an instance of every `Command` variant.
+#[cfg(test)]
+fn all_commands() -> Vec<Command> {
+    let uuid = Uuid::new_v4();
+    let instance = Instance::new("Test Instance", uuid);
+    let download = RunningDownload {
+        id: uuid,
+        symbol: String::from("EURUSD"),
+        downloader: instance.clone(),
+        start_time: 1,
+        cur_time: 2,
+        end_time: 3,
+        dst: HistTickDst::Postgres{table: String::from("ticks")},
+    };
+    let mut hm = HashMap::new();
+    hm.insert(String::from("env"), String::from("prod"));
+
+    vec![
+        Command::Ping,
+        Command::Shutdown,
+        Command::Kill,
+        Command::Register{channel: String::from("channel")},
+        Command::Type,
+        Command::SetLogLevel{uuid: uuid, level: String::from("warning")},
+        Command::Ready{instance_type: String::from("Backtester"), uuid: uuid, depends_on: vec![Uuid::new_v4()]},
+        Command::AddCondition{condition_string: String::from("condition")},
+        Command::RemoveCondition{condition_string: String::from("condition")},
+        Command::ListConditions,
+        Command::SubTicks{broker_def: String::from("{}")},
+        Command::AddSymbol{symbol: String::from("USDJPY")},
+        Command::RemoveSymbol{symbol: String::from("USDJPY")},
+        Command::TickProcessorSymbols{uuid: uuid, symbols: vec![String::from("EURUSD"), String::from("USDJPY")]},
+        Command::RegisterCrossover{fast_period: 5, slow_period: 20, channel: String::from("crossovers"), symbol: None},
+        Command::AddSMAPeriod{period_ms: 900000, symbol: None},
+        Command::RemoveSMAPeriod{period_ms: 900000, symbol: Some(String::from("USDJPY"))},
+        Command::AddEma{period_ms: 60000, symbol: Some(String::from("USDJPY"))},
+        Command::RemoveEma{period_ms: 60000, symbol: None},
+        Command::AddRsi{period: 14, interval_ms: 60000, symbol: None},
+        Command::RemoveRsi{period: 14, interval_ms: 60000, symbol: None},
+        Command::AddMacd{
+            fast_period_ms: Some(12000), slow_period_ms: None, signal_period_ms: None, channel: String::from("macd"),
+            symbol: None,
+        },
+        Command::RemoveMacd{fast_period_ms: Some(12000), slow_period_ms: None, signal_period_ms: None, symbol: None},
+        Command::AddAtr{bar_ms: 60000, period: 14, symbol: None},
+        Command::RemoveAtr{bar_ms: 60000, period: 14, symbol: None},
+        Command::AddCandles{duration_ms: 60000, carry_forward: true, store: false, symbol: None},
+        Command::RemoveCandles{duration_ms: 60000, symbol: None},
+        Command::AddRenko{brick_size: 10, symbol: Some(String::from("USDJPY"))},
+        Command::RemoveRenko{brick_size: 10, symbol: None},
+        Command::AddHeikinAshi{duration_ms: 60000, carry_forward: false, symbol: None},
+        Command::RemoveHeikinAshi{duration_ms: 60000, symbol: None},
+        Command::RecordTicks{enabled: true, table: Some(String::from("ticks_eurusd_live")), symbol: None},
+        Command::SetTickSampling{every_n: None, bucket_ms: Some(250), quiet_ms: None, symbol: None},
+        Command::AddIndicator{
+            kind: String::from("ema"), params: serde_json::from_str("{\"period_ms\": 60000}").unwrap(),
+            throttle_ms: Some(1000), hold_until_warm: true, symbol: Some(String::from("USDJPY")),
+        },
+        Command::RemoveIndicator{id: Uuid::new_v4(), symbol: None},
+        Command::SetIndicatorOutput{
+            dest: IndicatorDest::RedisChannel{host: String::from("redis://localhost"), channel: String::from("values")},
+            indicator: None,
+        },
+        Command::SetIndicatorOutput{
+            dest: IndicatorDest::Postgres{table: String::from("indicator_values")}, indicator: Some(Uuid::new_v4()),
+        },
+        Command::SetIndicatorOutput{dest: IndicatorDest::Null, indicator: None},
+        Command::AddHook{
+            condition: HookCondition::AllIndicatorsWarmedUp, channel: String::from("warm"), symbol: None,
+        },
+        Command::RemoveHook{
+            condition: HookCondition::AllIndicatorsWarmedUp, channel: String::from("warm"),
+            symbol: Some(String::from("USDJPY")),
+        },
+        Command::ListIndicators,
+        Command::IndicatorSnapshot{symbol: Some(String::from("USDJPY"))},
+        Command::IndicatorSnapshot{symbol: None},
+        Command::GetTickValidationCounts,
+        Command::SetTickIntakePolicy{policy: String::from("CoalesceToLatest"), capacity: 1000},
+        Command::GetTickIntakeStats,
+        Command::Census,
+        Command::DependencyGraph,
+        Command::SpawnOptimizer{strategy: String::from("sma_cross")},
+        Command::SpawnTickParser{symbol: String::from("EURUSD"), metadata: hm.clone()},
+        Command::SpawnTickParserGroup{symbols: vec![String::from("EURUSD"), String::from("USDJPY")]},
+        Command::SpawnBacktester,
+        Command::SpawnLogger,
+        Command::SpawnFxcmNativeDataDownloader,
+        Command::SpawnFxcmFlatfileDataDownloader,
+        Command::SpawnIexDataDownloader,
+        Command::SpawnPoloniexDataDownloader,
+        Command::SpawnDataDownloader{
+            source: HistTickSrc::Postgres, dst: HistTickDst::Flatfile{filename: String::from("ticks.csv")},
+            symbol: String::from("EURUSD"), start: String::from("1"), end: String::from("2"),
+        },
+        Command::KillInstance{uuid: uuid},
+        Command::KillAllInstances,
+        Command::KillGroup{instance_type: Some(String::from("Tick Processor")), metadata_filter: Some(hm.clone())},
+        Command::KillGroup{instance_type: None, metadata_filter: None},
+        Command::RollingRestart{instance_type: String::from("Tick Processor"), delay_ms: 500},
+        Command::SpawnFromConfig{config_path: String::from("spawn.toml")},
+        Command::SetHeartbeatInterval{ms: 1000},
+        Command::QueryDocumentStore{query: String::from("query")},
+        Command::InsertIntoDocumentStore{doc: String::from("doc")},
+        Command::GetDocument{title: String::from("title")},
+        Command::StartBacktest{definition: String::from("{}")},
+        Command::PauseBacktest{uuid: uuid},
+        Command::ResumeBacktest{uuid: uuid},
+        Command::PauseAllBacktests,
+        Command::ResumeAllBacktests,
+        Command::StopBacktest{uuid: uuid},
+        Command::ListBacktests,
+        Command::PrepareForRestart,
+        Command::DiffBacktests{uuid_a: uuid, uuid_b: Uuid::new_v4()},
+        Command::InspectTick{backtest_uuid: uuid, tick_index: 25},
+        Command::AttachSimbroker{backtest_uuid: uuid, simbroker_uuid: Uuid::new_v4()},
+        Command::ListSimbrokers,
+        Command::SpawnSimbroker{settings: hm.clone()},
+        Command::ModifySimbrokerOrder{
+            uuid: uuid, order_uuid: uuid, new_price: Some(1001), new_size: None, new_sl_tp: Some((None, Some(1010))),
+        },
+        Command::CancelSimbrokerOrder{uuid: uuid, order_uuid: uuid},
+        Command::CancelAllOrders{simbroker_uuid: uuid},
+        Command::ListSimbrokerOrders{uuid: uuid},
+        Command::SimbrokerPositions{uuid: uuid},
+        Command::SimbrokerOrders{uuid: uuid},
+        Command::EquityCurve{uuid: uuid},
+        Command::GetSimbrokerStats{uuid: uuid},
+        Command::ResetSimbroker{uuid: uuid, settings: Some(hm.clone())},
+        Command::BenchSimbroker{uuid: uuid, n_ticks: 1000, orders_per_tick: 10},
+        Command::DownloadTicks{
+            start_time: 1, end_time: 2, symbol: String::from("EURUSD"), dst: HistTickDst::Flatfile{filename: String::from("ticks.csv")},
+        },
+        Command::ListRunningDownloads,
+        Command::DownloadComplete{download: download.clone()},
+        Command::DownloadStarted{download: download},
+        Command::GetDownloadProgress{id: uuid},
+        Command::CancelDataDownload{download_id: uuid},
+        Command::TransferHistData{
+            src: HistTickDst::RedisChannel{host: String::from("redis://localhost/"), channel: String::from("ticks")},
+            dst: HistTickDst::RedisSet{host: String::from("redis://localhost/"), set_name: String::from("ticks")},
+        },
+        Command::TransferHistData{
+            src: HistTickDst::Console{format: ConsoleTickFormat::Raw},
+            dst: HistTickDst::Console{format: ConsoleTickFormat::HumanReadable},
+        },
+        Command::TransferHistData{
+            src: HistTickDst::Postgres{table: String::from("ticks")}, dst: HistTickDst::Arrow{path: String::from("ticks.arrow")},
+        },
+        Command::Log{msg: LogMessage {
+            sender: instance,
+            message_type: String::from("General"),
+            message: String::from("message"),
+            level: LogLevel::Critical,
+        }},
+    ]
+}
+
+#[test]
+fn cbor_command_round_trip() {
+    for cmd in all_commands() {
+        let wr_cmd = WrappedCommand {
+            uuid: Uuid::new_v4(),
+            cmd: cmd,
+            trace_context: Some(String::from("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")),
+        };
+        let bytes = wr_cmd.to_cbor_bytes();
+        assert_eq!(WrappedCommand::from_cbor_bytes(&bytes).unwrap(), wr_cmd);
+        assert_eq!(WrappedCommand::from_bytes(&bytes), Ok(wr_cmd.clone()));
+        assert_eq!(WrappedCommand::from_bytes(&wr_cmd.to_bytes(SerializationFormat::Json)), Ok(wr_cmd.clone()));
+
+        // CBOR messages can be read by subscribers that only understand JSON
+        let json = cbor_to_json(&bytes).unwrap();
+        assert_eq!(WrappedCommand::from_str(&json), Ok(wr_cmd));
+    }
+
+    assert!(WrappedCommand::from_cbor_bytes(b"garbage").is_err());
+    assert_eq!(cbor_to_json(&[0xff, 0x00]), None);
 }
 
 #[bench]
@@ -343,6 +1078,7 @@ fn wrappedcmd_to_string(b: &mut test::Bencher) {
     let wr_cmd = WrappedCommand {
         uuid: Uuid::new_v4(),
         cmd: cmd,
+        trace_context: None,
     };
 
     b.iter(|| {
@@ -351,6 +1087,20 @@ fn wrappedcmd_to_string(b: &mut test::Bencher) {
     })
 }
 
+#[bench]
+fn cbor_wrappedcmd_to_bytes(b: &mut test::Bencher) {
+    let wr_cmd = WrappedCommand {
+        uuid: Uuid::new_v4(),
+        cmd: Command::Ping,
+        trace_context: None,
+    };
+
+    b.iter(|| {
+        let wr_cmd = &wr_cmd;
+        let _ = wr_cmd.to_cbor_bytes();
+    })
+}
+
 #[bench]
 fn string_to_wrappedcmd(b: &mut test::Bencher) {
     let raw = "{\"uuid\":\"2f663301-5b73-4fa0-b201-09ab196ec5fd\",\"cmd\":{\"Register\":\
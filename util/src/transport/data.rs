@@ -13,13 +13,14 @@ use redis;
 use libc::{uint64_t, c_double};
 use postgres::Connection;
 
-use transport::commands::HistTickDst;
+use transport::commands::{HistTickDst, ConsoleTickFormat};
 use transport::redis::get_client as get_redis_client;
 use transport::postgres::get_client as get_postgres_client;
 use transport::postgres::init_hist_data_table;
 use transport::query_server::QueryServer;
 use transport::command_server::CommandServer;
-use trading::tick::Tick;
+use transport::tickstream::{TickSink, ArrowSink};
+use trading::tick::{Tick, CsvLayout, PriceFormat, TimestampNormalizer};
 use conf::CONF;
 
 // TODO: Some kind of drop implementation that automatically clears the buffers when they're dropped
@@ -37,12 +38,13 @@ pub fn transfer_data(src: HistTickDst, dst: HistTickDst, cs: CommandServer) {
     });
 }
 
-/// Given a `HistTickDst`, returns a closure that can be used as a receiver callback.
+/// Given a `HistTickDst`, returns a closure that can be used as a receiver callback.  Timestamps are converted to
+/// milliseconds before the ticks are written, since each downloader passes them on in the unit its source uses.
 pub fn get_rx_closure(dst: HistTickDst) -> Result<RxCallback, String> {
     let cb = match dst.clone() {
-        HistTickDst::Console => {
-            let inner = |t: Tick| {
-                println!("{:?}", t);
+        HistTickDst::Console{format} => {
+            let inner = move |t: Tick| {
+                print!("{}", format_console_tick(&t, format));
             };
 
             RxCallback{
@@ -122,7 +124,7 @@ pub fn get_rx_closure(dst: HistTickDst) -> Result<RxCallback, String> {
             let mut file = file_opt.unwrap();
 
             let inner = move |t: Tick| {
-                let tick_string = t.to_csv_row();
+                let tick_string = t.to_csv_row(&CsvLayout::default());
                 file.write_all(tick_string.as_str().as_bytes())
                     .expect(format!("couldn't write to output file: {}, {}", filename, tick_string).as_str());
             };
@@ -132,6 +134,16 @@ pub fn get_rx_closure(dst: HistTickDst) -> Result<RxCallback, String> {
                 inner: Box::new(inner),
             }
         },
+        HistTickDst::Arrow{path} => {
+            // the file is finished once the callback is dropped
+            let mut sink = ArrowSink::new(&path)?;
+            let inner = move |t: Tick| sink.tick(t);
+
+            RxCallback {
+                dst: dst,
+                inner: Box::new(inner),
+            }
+        },
         HistTickDst::Postgres{table} => {
             let connection_opt = get_postgres_client();
             if connection_opt.is_err() {
@@ -164,7 +176,28 @@ pub fn get_rx_closure(dst: HistTickDst) -> Result<RxCallback, String> {
         },
     };
 
-    Ok(cb)
+    let mut normalizer = TimestampNormalizer::from_conf();
+    let mut inner = cb.inner;
+    Ok(RxCallback {
+        dst: cb.dst,
+        inner: Box::new(move |t: Tick| match normalizer.normalize(t) {
+            Ok(t) => inner(t),
+            Err(err) => println!("Dropping tick {:?}: {}", t, err),
+        }),
+    })
+}
+
+/// Formats a tick for printing to the console, ending with a newline.
+pub fn format_console_tick(t: &Tick, format: ConsoleTickFormat) -> String {
+    match format {
+        ConsoleTickFormat::Raw => format!("{:?}\n", t),
+        ConsoleTickFormat::Csv => t.to_csv_row(&CsvLayout::default()),
+        ConsoleTickFormat::Json => format!("{}\n", serde_json::to_string(t).unwrap()),
+        ConsoleTickFormat::HumanReadable => {
+            let prices = PriceFormat::Decimal{decimals: CONF.console_tick_decimals as u32};
+            format!("[{}] {} {}\n", t.timestamp, prices.format(t.bid), prices.format(t.ask))
+        },
+    }
 }
 
 /// A struct that functions as a callback for ticks in a generator.
@@ -350,3 +383,52 @@ impl PostgresReader {
         }
     }
 }
+
+/// Each format writes a tick the way it's documented to.
+#[test]
+fn console_tick_formats() {
+    let t = Tick {timestamp: 1000, bid: 106143, ask: 106147};
+    assert_eq!(format_console_tick(&t, ConsoleTickFormat::Csv), "1000,106143,106147\n");
+    let json = format_console_tick(&t, ConsoleTickFormat::Json);
+    assert!(json.ends_with('\n'));
+    assert_eq!(serde_json::from_str::<Tick>(&json).unwrap(), t);
+    let human = PriceFormat::Decimal{decimals: CONF.console_tick_decimals as u32};
+    assert_eq!(
+        format_console_tick(&t, ConsoleTickFormat::HumanReadable),
+        format!("[1000] {} {}\n", human.format(t.bid), human.format(t.ask))
+    );
+    assert_eq!(format_console_tick(&t, ConsoleTickFormat::Raw), format!("{:?}\n", t));
+}
+
+/// Ticks sent to a console destination in the CSV format come out of stdout as one row each.  The test binary
+/// re-runs this test in a child process that prints the ticks so that its stdout can be read.
+#[test]
+fn console_dst_csv() {
+    use std::env;
+    use std::process;
+
+    if env::var("CONSOLE_DST_CSV_CHILD").is_ok() {
+        let mut rx_closure = get_rx_closure(HistTickDst::Console{format: ConsoleTickFormat::Csv}).unwrap();
+        for i in 0..10 {
+            rx_closure(Tick {timestamp: 1000 + i as u64, bid: 106140 + i, ask: 106145 + i});
+        }
+        return;
+    }
+
+    let output = process::Command::new(env::current_exe().unwrap())
+        .args(&["console_dst_csv", "--nocapture", "--test-threads=1"])
+        .env("CONSOLE_DST_CSV_CHILD", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // the test harness prints its own lines around the ticks
+    let rows: Vec<&str> = stdout.lines().filter(|line| line.starts_with(|c: char| c.is_digit(10))).collect();
+    assert_eq!(rows.len(), 10);
+    for (i, row) in rows.iter().enumerate() {
+        let fields: Vec<&str> = row.split(',').collect();
+        assert_eq!(fields.len(), 3, "{}", row);
+        assert!(fields.iter().all(|field| !field.is_empty() && field.chars().all(|c| c.is_digit(10))), "{}", row);
+        assert_eq!(*row, format!("{},{},{}", 1000 + i, 106140 + i, 106145 + i));
+    }
+}
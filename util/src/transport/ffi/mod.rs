@@ -25,7 +25,7 @@ pub unsafe fn ptr_to_cstring(ptr: *mut c_char) -> CString {
 }
 
 use transport::command_server::CommandServer;
-use transport::commands::HistTickDst;
+use transport::commands::{HistTickDst, ConsoleTickFormat};
 use transport::data::transfer_data as rust_transfer_data;
 
 const FLATFILE: c_int = 0; // { filename: String }
@@ -34,6 +34,7 @@ const REDIS_CHANNEL: c_int = 2; // { host: String, channel: String },
 const REDIS_SET: c_int = 3; // { host: String, set_name: String },
 const CONSOLE: c_int = 4;
 const CSV: c_int = 5;
+const ARROW: c_int = 6; // { path: String }
 
 // TODO: Convert the old `RxCallback`-based sinks into real `TickSink`s.
 
@@ -132,7 +133,12 @@ pub unsafe fn build_htd(id: c_int, arg1: *mut c_void, arg2: *mut c_void) -> Hist
                 set_name: set_string,
             }
         },
-        CONSOLE => HistTickDst::Console,
+        CONSOLE => HistTickDst::Console{format: ConsoleTickFormat::Raw},
+        ARROW => {
+            let path_cstring = ptr_to_cstring(arg1 as *mut c_char);
+            let path_string = String::from(path_cstring.to_str().expect(CSTRING_CONV_ERR));
+            HistTickDst::Arrow{path: path_string}
+        },
         _ => panic!("Invalid ID given for `HistTickDst` conversion function"),
     }
 }
@@ -5,6 +5,7 @@ pub mod postgres;
 pub mod commands;
 pub mod query_server;
 pub mod command_server;
+pub mod tracing;
 pub mod tickstream;
 pub mod textlog;
 pub mod data;
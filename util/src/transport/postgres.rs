@@ -6,17 +6,20 @@ use postgres::{Connection, Error, TlsMode};
 
 use conf::CONF;
 
-pub fn get_client() -> Result<Connection, Error> {
-    let conn_string = format!("postgres://{}:{}@{}:{}/{}",
+/// Returns the connection string of the platform's database
+pub fn conn_string() -> String {
+    format!("postgres://{}:{}@{}:{}/{}",
         CONF.postgres_user,
         CONF.postgres_password,
         CONF.postgres_host,
         CONF.postgres_port,
         CONF.postgres_db
-    );
+    )
+}
 
+pub fn get_client() -> Result<Connection, Error> {
     // TODO: Look into setting up TLS
-    Connection::connect(conn_string.as_str(), TlsMode::None)
+    Connection::connect(conn_string().as_str(), TlsMode::None)
 }
 
 /**************************\
@@ -42,6 +45,61 @@ pub fn init_hist_data_table(table_name: &str, client: &Connection, pg_user: &str
     tick_table_inner(table_name, client, pg_user)
 }
 
+/// Initializes a table in which OHLC candles can be stored if such a table doesn't already exist.
+pub fn init_candle_table(table_name: &str, client: &Connection, pg_user: &str) -> Result<(), String> {
+    let query1 = format!(
+    "CREATE TABLE IF NOT EXISTS {}
+    (
+      open_ts BIGINT NOT NULL PRIMARY KEY UNIQUE,
+      close_ts BIGINT NOT NULL,
+      open DOUBLE PRECISION NOT NULL,
+      high DOUBLE PRECISION NOT NULL,
+      low DOUBLE PRECISION NOT NULL,
+      close DOUBLE PRECISION NOT NULL,
+      tick_count BIGINT NOT NULL
+    )
+    WITH (
+      OIDS=FALSE
+    );", table_name);
+    let query2 = format!(
+    "ALTER TABLE {}
+      OWNER TO {};", table_name, pg_user);
+    client.execute(&query1, &[])
+        .map_err(|err| format!("Error while querying postgres to set up candle table: {}", err))?;
+    client.execute(&query2, &[])
+        .map_err(|err| format!("Error while querying postgres to set up candle table: {}", err))?;
+
+    Ok(())
+}
+
+/// Initializes a table in which the values of the Tick Processor's indicators can be stored if such a table doesn't
+/// already exist.  Values are stored as JSON since some indicators have more than one of them.
+pub fn init_indicator_table(table_name: &str, client: &Connection, pg_user: &str) -> Result<(), String> {
+    let query1 = format!(
+    "CREATE TABLE IF NOT EXISTS {}
+    (
+      id BIGSERIAL PRIMARY KEY,
+      symbol TEXT NOT NULL,
+      indicator_id TEXT NOT NULL,
+      kind TEXT NOT NULL,
+      value TEXT NOT NULL,
+      value_time BIGINT NOT NULL,
+      warm_up_complete BOOLEAN NOT NULL
+    )
+    WITH (
+      OIDS=FALSE
+    );", table_name);
+    let query2 = format!(
+    "ALTER TABLE {}
+      OWNER TO {};", table_name, pg_user);
+    client.execute(&query1, &[])
+        .map_err(|err| format!("Error while querying postgres to set up indicator table: {}", err))?;
+    client.execute(&query2, &[])
+        .map_err(|err| format!("Error while querying postgres to set up indicator table: {}", err))?;
+
+    Ok(())
+}
+
 fn tick_table_inner(table_name: &str, client: &Connection, pg_user: &str) -> Result<(), String> {
     let query1 = format!(
     "CREATE TABLE IF NOT EXISTS {}
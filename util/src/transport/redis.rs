@@ -1,26 +1,42 @@
 //! Functions for interfacing with Redis
 
 use std::thread;
+use std::str;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
 
 use redis;
 use futures::sync::mpsc::{unbounded, UnboundedSender, UnboundedReceiver};
 
+use transport::commands::cbor_to_json;
+
 pub fn get_client(host: &str) -> redis::Client {
     redis::Client::open(host).expect("Could not connect to redis")
 }
 
+/// Returns the payload of a message as a String.  Binary payloads are CBOR-encoded commands (see
+/// `SerializationFormat`), which are converted to JSON so that all subscribers can read them.
+fn get_payload_string(msg: &redis::Msg) -> String {
+    let payload = msg.get_payload_bytes();
+    match str::from_utf8(payload) {
+        Ok(s) => String::from(s),
+        Err(_) => cbor_to_json(payload).expect("Could not convert redis message to string!"),
+    }
+}
+
 /// Blocks until a message is received on a pubsub then returns it
 /// Returns the message as a String
 fn get_message(ps: &redis::PubSub) -> String {
     let msg = ps.get_message().expect("Could not get message from pubsub!");
-    msg.get_payload::<String>().expect("Could not convert redis message to string!")
+    get_payload_string(&msg)
 }
 
 /// Blocks until a message is received on a pubsub then returns (channel, message)
 fn get_chan_message(ps: &redis::PubSub) -> (String, String) {
     let msg = ps.get_message().expect("Could not get message from pubsub!");
     let channel = msg.get_channel_name().to_string();
-    let message = msg.get_payload::<String>().expect("Could not convert redis message to string!");
+    let message = get_payload_string(&msg);
 
     (channel, message)
 }
@@ -65,9 +81,44 @@ pub fn sub_channel(host: &str, ps_channel: &str) -> UnboundedReceiver<String> {
     rx
 }
 
+/// Subscribes to a pubsub channel and returns a Receiver that buffers up to `capacity` `(channel, message)` items.
+/// Messages received while the buffer is full are dropped and counted in `dropped` rather than queued, so a slow
+/// consumer can't make the backlog grow without bound.
+pub fn sub_channel_bounded(
+    host: &str, ps_channel: &str, capacity: usize, dropped: Arc<AtomicUsize>
+) -> Receiver<(String, String)> {
+    let (tx, rx) = sync_channel(capacity);
+    let ps = get_pubsub(host, ps_channel);
+    thread::spawn(move || {
+        let mut full = false;
+        loop {
+            match tx.try_send(get_chan_message(&ps)) {
+                Ok(()) => full = false,
+                Err(TrySendError::Full((channel, _))) => {
+                    if !full {
+                        println!("Buffer for messages on {} is full; dropping them until it drains.", channel);
+                        full = true;
+                    }
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                },
+                // the receiver was dropped, so nobody wants the messages anymore
+                Err(TrySendError::Disconnected(_)) => break,
+            }
+        }
+    });
+
+    rx
+}
+
 /// Subscribes to many Redis channels and returns a `Stream` that yeilds
 /// `(channel, message)` items every time a message is received on one of them.
 pub fn sub_multiple(host: &str, channels: &[&str]) -> UnboundedReceiver<(String, String)> {
+    sub_multiple_patterns(host, channels, &[])
+}
+
+/// Like `sub_multiple`, but also subscribes to every channel matching one of the glob-style `patterns`.  Messages
+/// received through a pattern are yielded with the name of the channel they were published to.
+pub fn sub_multiple_patterns(host: &str, channels: &[&str], patterns: &[&str]) -> UnboundedReceiver<(String, String)> {
     let (mut tx, rx) = unbounded::<(String, String)>();
     let client = get_client(host);
     let mut pubsub = client.get_pubsub()
@@ -77,6 +128,10 @@ pub fn sub_multiple(host: &str, channels: &[&str]) -> UnboundedReceiver<(String,
         pubsub.subscribe(*channel)
             .expect("Could not subscribe to pubsub channel");
     }
+    for pattern in patterns {
+        pubsub.psubscribe(*pattern)
+            .expect("Could not subscribe to pubsub pattern");
+    }
 
     thread::spawn(move || {
         loop {
@@ -114,3 +169,45 @@ pub fn publish(client: &redis::Client, channel: &str, msg: &str) {
         .arg(msg)
         .execute(client);
 }
+
+/// Publishes messages from a background thread that buffers up to `capacity` of them, so a slow Redis server can't
+/// hold up the code publishing them.  Messages published while the buffer is full are dropped and counted instead.
+/// Clones share the buffer, the thread, and the count of dropped messages.
+#[derive(Clone)]
+pub struct BoundedPublisher {
+    tx: SyncSender<(String, String)>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl BoundedPublisher {
+    pub fn new(host: &str, capacity: usize) -> BoundedPublisher {
+        let (tx, rx) = sync_channel::<(String, String)>(capacity);
+        let client = get_client(host);
+        thread::spawn(move || {
+            for (channel, msg) in rx.iter() {
+                publish(&client, &channel, &msg);
+            }
+        });
+
+        BoundedPublisher {
+            tx: tx,
+            dropped: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Queues a message to be published.  Returns `false` if it was dropped because the buffer is full.
+    pub fn publish(&self, channel: String, msg: String) -> bool {
+        match self.tx.try_send((channel, msg)) {
+            Ok(()) => true,
+            Err(_) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            },
+        }
+    }
+
+    /// Returns the number of messages that were dropped because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed) as u64
+    }
+}
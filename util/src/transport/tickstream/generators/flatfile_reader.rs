@@ -1,14 +1,17 @@
 //! A `TickGenerator` that reads historical ticks out of CSV files.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::thread;
 
+#[allow(unused_imports)]
+use test;
+
 use futures::sync::mpsc::channel;
 use futures::{Future, Stream, Sink};
 use futures::stream::BoxStream;
-use trading::tick::Tick;
+use trading::tick::{Tick, CsvLayout, TickValidator, TickVerdict};
 use conf::CONF;
 
 use super::super::*;
@@ -16,6 +19,18 @@ use super::super::*;
 pub struct FlatfileReader {
     pub symbol: String,
     pub start_time: Option<u64>,
+    /// The file to read, or `None` to read the symbol's file in the data directory; see `symbol_path`
+    pub path: Option<PathBuf>,
+}
+
+impl FlatfileReader {
+    /// Returns the path of the file that the reader reads its ticks from.
+    pub fn path(&self) -> PathBuf {
+        match self.path {
+            Some(ref path) => path.clone(),
+            None => symbol_path(&self.symbol),
+        }
+    }
 }
 
 impl TickGenerator for FlatfileReader {
@@ -30,11 +45,11 @@ impl TickGenerator for FlatfileReader {
         // spawn the worker thread that does the blocking
         let mut _got_mail = got_mail.clone();
         let _internal_message = internal_message.clone();
-        let symbol = self.symbol.clone();
+        let path = self.path();
         let start_time = self.start_time;
         let reader_handle = thread::spawn(move || {
             // open the file and get an iterator over its lines set to the starting point
-            let iter_ = init_reader(&symbol);
+            let iter_ = read_ticks(&path, CONF.flatfile_buffer_bytes);
             if iter_.is_err() {
                 println!("Unable to open the file!");
             }
@@ -66,9 +81,9 @@ impl TickGenerator for FlatfileReader {
         let (mut tx, rx) = channel(1);
 
         let start_time = self.start_time;
-        let symbol = self.symbol.clone();
+        let path = self.path();
         thread::spawn(move || {
-            let iter_ = init_reader(&symbol);
+            let iter_ = read_ticks(&path, CONF.flatfile_buffer_bytes);
             if iter_.is_err() {
                 println!("Unable to open the file!");
             }
@@ -85,16 +100,184 @@ impl TickGenerator for FlatfileReader {
     }
 }
 
-/// Trys to open the file containing the historical ticks for the supplied symbol.
-pub fn init_reader(symbol: &str) -> Result<impl Iterator<Item=Tick>, String> {
+/// Returns the path of the file containing the historical ticks for the supplied symbol in the data directory.
+pub fn symbol_path(symbol: &str) -> PathBuf {
     let mut path = PathBuf::from(CONF.data_dir);
     path.push("historical_ticks");
     let filename = format!("{}.csv", symbol.to_uppercase());
     path.push(filename.as_str());
+    path
+}
+
+/// Trys to open the file containing the historical ticks for the supplied symbol.
+pub fn init_reader(symbol: &str) -> Result<impl Iterator<Item=Tick>, String> {
+    read_ticks(&symbol_path(symbol), CONF.flatfile_buffer_bytes)
+}
+
+/// Opens a CSV file of ticks in the format "{timestamp}, {bid}, {ask}" for reading through a buffer of
+/// `buffer_bytes` bytes.
+pub fn read_ticks(path: &Path, buffer_bytes: usize) -> Result<FlatfileTicks<BufReader<File>>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    Ok(FlatfileTicks::new(BufReader::with_capacity(buffer_bytes, file)))
+}
+
+/// Iterates over the ticks in a CSV file one line at a time, reusing the same line buffer, so that only the
+/// reader's buffer and a single line are ever held in memory no matter how large the file is.  Blank lines, rows
+/// that can't be parsed, and ticks rejected by the `TickValidator` are skipped and iteration stops at the end of the
+/// file or the first read error.
+pub struct FlatfileTicks<R> {
+    reader: R,
+    line: String,
+    layout: CsvLayout,
+    /// True until the header row has been skipped, if the layout has one
+    before_header: bool,
+    validator: TickValidator,
+}
+
+impl<R: BufRead> FlatfileTicks<R> {
+    /// Reads ticks in the default `CsvLayout`.
+    pub fn new(reader: R) -> FlatfileTicks<R> {
+        FlatfileTicks::with_layout(reader, CsvLayout::default())
+    }
+
+    pub fn with_layout(reader: R, layout: CsvLayout) -> FlatfileTicks<R> {
+        FlatfileTicks {
+            reader: reader,
+            line: String::new(),
+            layout: layout,
+            before_header: layout.has_header,
+            validator: TickValidator::from_conf(),
+        }
+    }
+
+    /// Returns the validator that the ticks read so far have been checked with, which counts the ones skipped.
+    pub fn validator(&self) -> &TickValidator {
+        &self.validator
+    }
+}
+
+impl<R: BufRead> Iterator for FlatfileTicks<R> {
+    type Item = Tick;
+
+    fn next(&mut self) -> Option<Tick> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    let line = self.line.trim_right_matches(|c| c == '\n' || c == '\r');
+                    if line.is_empty() {
+                        continue;
+                    }
+                    if self.before_header {
+                        self.before_header = false;
+                        continue;
+                    }
+                    match Tick::from_csv_row(line, &self.layout) {
+                        Ok(t) => match self.validator.validate(&t) {
+                            TickVerdict::Ok => return Some(t),
+                            verdict => println!("Skipping invalid tick in CSV row {:?}: {:?}", line, verdict),
+                        },
+                        Err(err) => println!("Skipping CSV row {:?} that couldn't be parsed: {:?}", line, err),
+                    }
+                },
+                Err(err) => {
+                    println!("Error while reading ticks from file: {}", err);
+                    return None;
+                },
+            }
+        }
+    }
+}
+
+/// Writes `n` ticks with increasing timestamps to a temporary CSV file and returns its path.
+#[cfg(test)]
+fn write_test_file(n: usize) -> PathBuf {
+    use std::env;
+    use std::io::{BufWriter, Write};
+
+    let path = env::temp_dir().join(format!("flatfile_reader_{}.csv", ::uuid::Uuid::new_v4().simple()));
+    let mut writer = BufWriter::new(File::create(&path).unwrap());
+    for i in 0..n {
+        writeln!(writer, "{}, {}, {}", i + 1, 100000 + i % 1000, 100002 + i % 1000).unwrap();
+    }
+    path
+}
+
+/// Returns the peak resident set size of the process in kB as reported by `/proc/self/status`.
+#[cfg(test)]
+fn peak_rss_kb() -> Option<u64> {
+    use std::io::Read;
+
+    let mut status = String::new();
+    if File::open("/proc/self/status").and_then(|mut file| file.read_to_string(&mut status)).is_err() {
+        return None;
+    }
+    status.lines()
+        .find(|line| line.starts_with("VmHWM:"))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse().ok())
+}
+
+#[test]
+fn flatfile_reads_every_tick() {
+    use std::fs;
+    use trading::tick::{CsvHeader, PriceFormat, TimestampUnit};
+
+    let n = 1000000;
+    let path = write_test_file(n);
+    let mut count = 0;
+    let mut last_timestamp = 0;
+    for t in read_ticks(&path, 8192).unwrap() {
+        assert_eq!(t.timestamp, last_timestamp + 1);
+        assert_eq!(t.bid, 100000 + count % 1000);
+        last_timestamp = t.timestamp;
+        count += 1;
+    }
+    assert_eq!(count, n);
+    fs::remove_file(&path).unwrap();
+
+    // blank lines and Windows line endings don't produce ticks of their own
+    let ticks: Vec<Tick> = FlatfileTicks::new("1, 100, 102\r\n\n2, 101, 103".as_bytes()).collect();
+    assert_eq!(ticks, vec![Tick {timestamp: 1, bid: 100, ask: 102}, Tick {timestamp: 2, bid: 101, ask: 103}]);
+
+    // crossed and out-of-order ticks are skipped and counted
+    let mut reader = FlatfileTicks::new("5, 100, 102\n6, 104, 102\n4, 100, 102\n7, 101, 103".as_bytes());
+    let ticks: Vec<Tick> = reader.by_ref().collect();
+    assert_eq!(ticks, vec![Tick {timestamp: 5, bid: 100, ask: 102}, Tick {timestamp: 7, bid: 101, ask: 103}]);
+    assert_eq!(reader.validator().count(TickVerdict::Crossed), 1);
+    assert_eq!(reader.validator().count(TickVerdict::Regressed), 1);
+
+    // files in other layouts are read with the shared CSV helpers
+    let layout = CsvLayout {
+        columns: CsvHeader {timestamp: 2, bid: 0, ask: 1},
+        has_header: true,
+        timestamp_unit: TimestampUnit::Seconds,
+        price_format: PriceFormat::Decimal{decimals: 5},
+    };
+    let file = format!("{}{}", layout.header_row(), Tick {timestamp: 1500, bid: 100, ask: 102}.to_csv_row(&layout));
+    let ticks: Vec<Tick> = FlatfileTicks::with_layout(file.as_bytes(), layout).collect();
+    assert_eq!(ticks, vec![Tick {timestamp: 1500, bid: 100, ask: 102}]);
+}
+
+/// Reads a file line by line with a reused buffer.  Compare against `flatfile_lines_read`, which allocates a new
+/// String for every line like the reader used to.
+#[bench]
+fn flatfile_chunk_read(b: &mut test::Bencher) {
+    let path = write_test_file(100000);
+    b.iter(|| read_ticks(&path, CONF.flatfile_buffer_bytes).unwrap().count());
+    println!("Peak RSS: {:?} kB", peak_rss_kb());
+    let _ = ::std::fs::remove_file(&path);
+}
 
-    let file = try!(File::open(path).map_err( |e| e.to_string() ));
-    Ok(BufReader::new(file).lines().map( |line| {
-        Tick::from_csv_string(line.unwrap().as_str())
-    }))
+#[bench]
+fn flatfile_lines_read(b: &mut test::Bencher) {
+    let path = write_test_file(100000);
+    b.iter(|| {
+        let file = File::open(&path).unwrap();
+        BufReader::new(file).lines().map(|line| Tick::from_csv_string(line.unwrap().as_str())).count()
+    });
+    println!("Peak RSS: {:?} kB", peak_rss_kb());
+    let _ = ::std::fs::remove_file(&path);
 }
 
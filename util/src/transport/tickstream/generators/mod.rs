@@ -5,3 +5,4 @@ pub mod flatfile_reader;
 pub mod postgres_reader;
 pub mod random_reader;
 pub mod redis_reader;
+pub mod timescale_reader;
@@ -1,24 +1,60 @@
-//! Reads ticks out of a Postgres database
+//! Reads ticks out of a Postgres database.  Ticks are streamed through a server-side cursor so that tables far
+//! too large to fit in memory can be read; only one batch of rows is held at a time.
 
 use std::thread;
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use futures::{Future, Stream, Sink};
 use futures::stream::BoxStream;
-use futures::sync::mpsc::channel;
+use futures::sync::mpsc::{channel, Sender};
 use postgres::Connection;
-use postgres::rows::Rows;
-use postgres::error::Error;
+use postgres::types::ToSql;
 
 use trading::tick::*;
 use transport::postgres::*;
 
 use super::super::*;
 
+/// How many rows are fetched from the cursor at a time by default
+pub const DEFAULT_BATCH_SIZE: usize = 10000;
+
 pub struct PostgresReader {
     pub symbol: String,
+    /// Only ticks with timestamps at or after this are read
     pub start_time: Option<u64>,
+    /// How many rows are fetched from the cursor at a time
+    pub batch_size: usize,
+    /// Set when the reader is dropped so that the worker threads close their cursors
+    closed: Arc<AtomicBool>,
+}
+
+impl PostgresReader {
+    pub fn new(symbol: String, start_time: Option<u64>) -> PostgresReader {
+        PostgresReader {
+            symbol: symbol,
+            start_time: start_time,
+            batch_size: DEFAULT_BATCH_SIZE,
+            closed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Drop for PostgresReader {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Sends a tick through the channel, returning `false` if the receiving end has been dropped.
+pub fn send_tick(tx: &mut Option<Sender<Tick>>, tick: Tick) -> bool {
+    match tx.take().unwrap().send(tick).wait() {
+        Ok(new_tx) => {
+            *tx = Some(new_tx);
+            true
+        },
+        Err(_) => false,
+    }
 }
 
 impl TickGenerator for PostgresReader {
@@ -28,45 +64,43 @@ impl TickGenerator for PostgresReader {
         // small atomic communication bus between the handle listener and worker threads
         let internal_message: Arc<Mutex<TickstreamCommand>> = Arc::new(Mutex::new(TickstreamCommand::Stop));
         let got_mail = Arc::new(AtomicBool::new(false));
-        let (mut tx, rx) = channel::<Tick>(1);
+        let (tx, rx) = channel::<Tick>(1);
 
         let _got_mail = got_mail.clone();
-        let symbol = self.symbol.clone();
-        let start_time = self.start_time;
+        let _internal_message = internal_message.clone();
+        let table = format!("hist_{}", self.symbol);
+        let start_time = self.start_time.unwrap_or(0);
+        let batch_size = self.batch_size;
+        let closed = self.closed.clone();
         let reader_handle = thread::spawn(move || {
-            let conn_opt = get_client();
-            if conn_opt.is_err() {
-                return Err("Unable to create Postgres client".to_string())
-            }
-            let conn = conn_opt.unwrap();
-
-            let mut cur_time = start_time.or(Some(0)).unwrap();
-            loop {
-                let rows_opt = get_ticks(&symbol, cur_time, &conn);
-                if rows_opt.is_err() {
-                    println!("Got an error back from Postgres when trying to get ticks");
-                    break;
-                }
-                let rows = rows_opt.unwrap();
-                for row in rows.iter() {
-                    let tick = Tick {
-                        timestamp: row.get::<_, i64>(0) as u64,
-                        bid: row.get::<_, i64>(1) as usize,
-                        ask: row.get::<_, i64>(2) as usize,
-                    };
+            let conn = match get_client() {
+                Ok(conn) => conn,
+                Err(err) => {
+                    println!("Unable to create Postgres client: {:?}", err);
+                    return;
+                },
+            };
+
+            let mut tx = Some(tx);
+            let res = read_cursor(&conn, &table, start_time, batch_size, &closed, |ticks| {
+                for tick in ticks {
+                    if check_mail(&*got_mail, &*_internal_message) {
+                        println!("Stop command received; killing reader");
+                        return false;
+                    }
 
                     // apply the map
-                    let t_mod = map.map(tick);
-                    if t_mod.is_some() {
-                        tx = tx.send(tick).wait().expect("Unable to send through tx in `get` in postgres_reader!");
+                    if let Some(t_mod) = map.map(*tick) {
+                        if !send_tick(&mut tx, t_mod) {
+                            return false;
+                        }
                     }
-
-                    // this should end up being the highest seen timestamp after the inner loop
-                    cur_time = tick.timestamp;
                 }
+                true
+            });
+            if let Err(err) = res {
+                println!("Error while reading ticks from Postgres: {}", err);
             }
-
-            Ok(()) // ???
         }).thread().clone();
 
         // spawn the handle listener thread that awaits commands
@@ -76,36 +110,20 @@ impl TickGenerator for PostgresReader {
     }
 
     fn get_raw(&mut self) -> Result<BoxStream<Tick, ()>, String> {
-        let (mut tx, rx) = channel(1);
-
-        let conn_opt = get_client();
-        if conn_opt.is_err() {
-            return Err("Unable to create Postgres client".to_string())
-        }
-        let conn = conn_opt.unwrap();
-
-        let mut cur_time = self.start_time.or(Some(0)).unwrap();
-        let symbol = self.symbol.clone();
-        thread::spawn(move ||{
-            loop {
-                let rows_opt = get_ticks(&symbol, cur_time, &conn);
-                if rows_opt.is_err() {
-                    println!("Got an error back from Postgres when trying to get ticks");
-                    break;
-                }
-                let rows = rows_opt.unwrap();
-                for row in rows.iter() {
-                    let tick = Tick {
-                        timestamp: row.get::<_, i64>(0) as u64,
-                        bid: row.get::<_, i64>(1) as usize,
-                        ask: row.get::<_, i64>(2) as usize,
-                    };
-
-                    tx = tx.send(tick).wait().expect("Unable to send through tx in `get_raw` in potgres_reader!");
-
-                    // this should end up being the highest seen timestamp after the inner loop
-                    cur_time = tick.timestamp;
-                }
+        let (tx, rx) = channel(1);
+
+        let conn = get_client().map_err(|_| String::from("Unable to create Postgres client"))?;
+        let table = format!("hist_{}", self.symbol);
+        let start_time = self.start_time.unwrap_or(0);
+        let batch_size = self.batch_size;
+        let closed = self.closed.clone();
+        thread::spawn(move || {
+            let mut tx = Some(tx);
+            let res = read_cursor(&conn, &table, start_time, batch_size, &closed, |ticks| {
+                ticks.iter().all(|tick| send_tick(&mut tx, *tick))
+            });
+            if let Err(err) = res {
+                println!("Error while reading ticks from Postgres: {}", err);
             }
         });
 
@@ -113,11 +131,88 @@ impl TickGenerator for PostgresReader {
     }
 }
 
-fn get_ticks<'a>(symbol: &str, start_time: u64, conn: &'a Connection) -> Result<Rows, Error> {
-    let query = format!(
-        "SELECT (tick_time, bid, ask) FROM hist_{} WHERE tick_time > {} LIMIT 500 ORDER BY tick_time;",
-        symbol,
-        start_time
-    );
-    conn.query(&query, &[])
+/// Reads all ticks in `table` with timestamps at or after `start_time` in order through a server-side cursor,
+/// handing them to `handle_batch` `batch_size` rows at a time.  Stops early if `handle_batch` returns `false`
+/// or `closed` is set.  The cursor is closed once reading stops.
+pub fn read_cursor<F>(
+    conn: &Connection, table: &str, start_time: u64, batch_size: usize, closed: &AtomicBool, handle_batch: F
+) -> Result<(), String> where F: FnMut(&[Tick]) -> bool {
+    let query = format!("SELECT tick_time, bid, ask FROM {} WHERE tick_time >= $1 ORDER BY tick_time", table);
+    stream_cursor(conn, &query, &[&(start_time as i64)], batch_size, closed, handle_batch)
+}
+
+/// Runs `query`, which must select the timestamp, bid, and ask of ticks as BIGINTs, through a server-side cursor
+/// and hands the resulting ticks to `handle_batch` `batch_size` rows at a time.  Stops early if `handle_batch`
+/// returns `false` or `closed` is set.
+pub fn stream_cursor<F>(
+    conn: &Connection, query: &str, params: &[&ToSql], batch_size: usize, closed: &AtomicBool, mut handle_batch: F
+) -> Result<(), String> where F: FnMut(&[Tick]) -> bool {
+    // cursors only exist within a transaction
+    let trans = conn.transaction().map_err(|err| format!("Unable to start transaction: {:?}", err))?;
+    trans.execute(&format!("DECLARE tick_cursor NO SCROLL CURSOR FOR {};", query), params)
+        .map_err(|err| format!("Unable to declare cursor: {:?}", err))?;
+
+    let fetch_query = format!("FETCH {} FROM tick_cursor;", batch_size);
+    let mut batch = Vec::with_capacity(batch_size);
+    while !closed.load(Ordering::Relaxed) {
+        let rows = trans.query(&fetch_query, &[]).map_err(|err| format!("Unable to fetch ticks: {:?}", err))?;
+        if rows.is_empty() {
+            break;
+        }
+
+        batch.clear();
+        for row in rows.iter() {
+            batch.push(Tick {
+                timestamp: row.get::<_, i64>(0) as u64,
+                bid: row.get::<_, i64>(1) as usize,
+                ask: row.get::<_, i64>(2) as usize,
+            });
+        }
+        if !handle_batch(&batch) {
+            break;
+        }
+    }
+
+    trans.batch_execute("CLOSE tick_cursor;").map_err(|err| format!("Unable to close cursor: {:?}", err))?;
+    trans.commit().map_err(|err| format!("Unable to commit transaction: {:?}", err))
+}
+
+/// A 100-row table is read in full and in order, ten rows at a time.
+#[test]
+fn cursor_batched_read() {
+    let conn = get_client().unwrap();
+    let table = format!("hist_cursor_test_{}", ::uuid::Uuid::new_v4().simple());
+    conn.batch_execute(&format!("CREATE TABLE {} (tick_time BIGINT NOT NULL PRIMARY KEY, bid BIGINT NOT NULL, \
+        ask BIGINT NOT NULL);", table)).unwrap();
+    let values: Vec<String> = (0..100).map(|i| format!("({}, {}, {})", i, 1000 + i, 1002 + i)).collect();
+    conn.batch_execute(&format!("INSERT INTO {} (tick_time, bid, ask) VALUES {};", table, values.join(", "))).unwrap();
+
+    let mut batch_sizes = Vec::new();
+    let mut timestamps = Vec::new();
+    let closed = AtomicBool::new(false);
+    read_cursor(&conn, &table, 0, 10, &closed, |ticks| {
+        batch_sizes.push(ticks.len());
+        timestamps.extend(ticks.iter().map(|t| t.timestamp));
+        true
+    }).unwrap();
+    assert_eq!(batch_sizes, vec![10; 10]);
+    assert_eq!(timestamps, (0..100).collect::<Vec<u64>>());
+
+    // the start time is inclusive
+    let mut count = 0;
+    read_cursor(&conn, &table, 95, 10, &closed, |ticks| {
+        count += ticks.len();
+        true
+    }).unwrap();
+    assert_eq!(count, 5);
+
+    // the whole table comes through the stream as well
+    let symbol = table.trim_left_matches("hist_").to_string();
+    let mut reader = PostgresReader::new(symbol, None);
+    reader.batch_size = 7;
+    let ticks: Vec<Tick> = reader.get_raw().unwrap().wait().map(|t| t.unwrap()).collect();
+    assert_eq!(ticks.len(), 100);
+    assert_eq!(ticks[99], Tick {timestamp: 99, bid: 1099, ask: 1101});
+
+    conn.batch_execute(&format!("DROP TABLE {};", table)).unwrap();
 }
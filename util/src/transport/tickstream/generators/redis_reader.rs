@@ -1,28 +1,35 @@
 //! A `TickGenerator` that reads ticks out of a Redis channel.
 
 use std::thread;
+use std::sync::atomic::AtomicUsize;
 
 use futures::sync::mpsc::channel;
 use futures::{Future, Stream, Sink};
 use futures::stream::BoxStream;
 
 use trading::tick::Tick;
-use transport::redis::sub_channel;
+use transport::redis::sub_channel_bounded;
 
 use super::super::*;
 
 pub struct RedisReader {
     pub symbol: String,
     pub redis_host: String,
-    pub channel: String
+    pub channel: String,
+    /// How many ticks can be waiting to be processed before new ones are dropped
+    pub buffer_size: usize,
+    /// Number of ticks dropped because the buffer was full
+    dropped_ticks: Arc<AtomicUsize>,
 }
 
 impl TickGenerator for RedisReader {
     fn get(
         &mut self, mut map: Box<TickMap + Send>, cmd_handle: CommandStream
     ) -> Result<BoxStream<Tick, ()>, String> {
-        let host = self.redis_host.clone();
-        let input_channel = self.channel.clone();
+        // subscribe before returning so that no ticks published after this are missed
+        let in_rx = sub_channel_bounded(
+            self.redis_host.as_str(), self.channel.as_str(), self.buffer_size, self.dropped_ticks.clone()
+        );
 
         // small atomic communication bus between the handle listener and worker threads
         let internal_message: Arc<Mutex<TickstreamCommand>> = Arc::new(Mutex::new(TickstreamCommand::Stop));
@@ -33,14 +40,12 @@ impl TickGenerator for RedisReader {
         let (mut tx, rx) = channel::<Tick>(1);
 
         let reader_handle = thread::spawn(move || {
-            let in_rx = sub_channel(host.as_str(), input_channel.as_str());
-
-            for t_string in in_rx.wait() {
+            for (_, t_string) in in_rx.iter() {
                 if check_mail(&*got_mail, &*_internal_message) {
                     println!("Stop command received; killing reader");
                     break;
                 }
-                let t = Tick::from_json_string(t_string.unwrap());
+                let t = Tick::from_json_string(t_string);
 
                 // apply map
                 let t_mod = map.map(t);
@@ -59,12 +64,12 @@ impl TickGenerator for RedisReader {
     fn get_raw(&mut self) -> Result<BoxStream<Tick, ()>, String> {
         let (mut tx, rx) = channel(1);
 
-        let input_channel = self.channel.clone();
+        let in_rx = sub_channel_bounded(
+            CONF.redis_host, self.channel.as_str(), self.buffer_size, self.dropped_ticks.clone()
+        );
         thread::spawn(move || {
-            let in_rx = sub_channel(CONF.redis_host, input_channel.as_str());
-
-            for t_string in in_rx.wait() {
-                let t = Tick::from_json_string(t_string.unwrap());
+            for (_, t_string) in in_rx.iter() {
+                let t = Tick::from_json_string(t_string);
                 tx = tx.send(t).wait().expect("Unable to send through tx in `get_raw` in redis_reader!");
             }
         });
@@ -78,7 +83,39 @@ impl RedisReader {
         RedisReader {
             symbol: symbol,
             redis_host: host,
-            channel: channel
+            channel: channel,
+            buffer_size: CONF.reader_buffer_size,
+            dropped_ticks: Arc::new(AtomicUsize::new(0)),
         }
     }
+
+    /// Returns the number of ticks that were dropped because they arrived faster than they could be processed.
+    pub fn dropped_ticks(&self) -> u64 {
+        self.dropped_ticks.load(Ordering::Relaxed) as u64
+    }
+}
+
+/// Ticks published while nothing is reading them fill the buffer and the rest are dropped.
+#[test]
+fn redis_reader_drops_ticks_when_full() {
+    use std::time::Duration;
+    use transport::redis::{get_client, publish};
+
+    let channel = format!("redis_reader_backpressure_{}", ::uuid::Uuid::new_v4().simple());
+    let mut reader = RedisReader::new(String::from("TEST"), String::from(CONF.redis_host), channel.clone());
+    reader.buffer_size = 2;
+    // the stream is never polled, so at most a few ticks can be taken off of the buffer
+    let _stream = reader.get_raw().unwrap();
+
+    let client = get_client(CONF.redis_host);
+    for i in 0..100 {
+        let t = Tick {timestamp: i, bid: 100, ask: 102};
+        publish(&client, &channel, &t.to_json_string(String::from("TEST")));
+    }
+    thread::sleep(Duration::from_millis(500));
+
+    // everything past the buffer and the couple of ticks in flight to it must have been dropped
+    let dropped = reader.dropped_ticks();
+    assert!(dropped >= 100 - reader.buffer_size as u64 - 2);
+    assert!(dropped <= 100);
 }
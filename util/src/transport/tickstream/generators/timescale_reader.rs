@@ -0,0 +1,205 @@
+//! Reads ticks out of a TimescaleDB hypertable.  Unlike the `hist_` tables read by `PostgresReader`, hypertables
+//! usually hold the ticks of many symbols in one table with a `TIMESTAMPTZ` time column, so rows are filtered by
+//! symbol and the time range is converted on the server.  TimescaleDB excludes the chunks that fall outside of the
+//! range from the scan, and the rows are streamed in order through a server-side cursor.
+
+use std::f64;
+use std::thread;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use futures::Stream;
+use futures::stream::BoxStream;
+use futures::sync::mpsc::channel;
+use postgres::{Connection, TlsMode};
+
+use trading::tick::*;
+
+use super::postgres_reader::{send_tick, stream_cursor, DEFAULT_BATCH_SIZE};
+use super::super::*;
+
+/// Streams the ticks of one symbol out of a hypertable with the columns `time TIMESTAMPTZ`, `symbol TEXT`, and
+/// integer `bid` and `ask` columns holding prices in pips.
+pub struct TimescaleReader {
+    pub connection_str: String,
+    pub hypertable: String,
+    pub symbol: String,
+    /// Only ticks with timestamps at or after this are read
+    pub start_time: Option<u64>,
+    /// Only ticks with timestamps at or before this are read
+    pub end_time: Option<u64>,
+    /// How many rows are fetched from the cursor at a time
+    pub batch_size: usize,
+    /// Set when the reader is dropped so that the worker threads close their cursors
+    closed: Arc<AtomicBool>,
+}
+
+impl TimescaleReader {
+    pub fn new(
+        connection_str: String, hypertable: String, symbol: String, start_time: Option<u64>, end_time: Option<u64>
+    ) -> TimescaleReader {
+        TimescaleReader {
+            connection_str: connection_str,
+            hypertable: hypertable,
+            symbol: symbol,
+            start_time: start_time,
+            end_time: end_time,
+            batch_size: DEFAULT_BATCH_SIZE,
+            closed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Returns a copy of the settings needed by a worker thread to read the ticks.
+    fn query(&self) -> TimescaleQuery {
+        TimescaleQuery {
+            connection_str: self.connection_str.clone(),
+            hypertable: self.hypertable.clone(),
+            symbol: self.symbol.clone(),
+            start_time: self.start_time,
+            end_time: self.end_time,
+            batch_size: self.batch_size,
+            closed: self.closed.clone(),
+        }
+    }
+}
+
+impl Drop for TimescaleReader {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+struct TimescaleQuery {
+    connection_str: String,
+    hypertable: String,
+    symbol: String,
+    start_time: Option<u64>,
+    end_time: Option<u64>,
+    batch_size: usize,
+    closed: Arc<AtomicBool>,
+}
+
+impl TimescaleQuery {
+    fn run<F>(&self, handle_batch: F) -> Result<(), String> where F: FnMut(&[Tick]) -> bool {
+        let conn = Connection::connect(self.connection_str.as_str(), TlsMode::None)
+            .map_err(|err| format!("Unable to connect to TimescaleDB: {:?}", err))?;
+        read_hypertable(
+            &conn, &self.hypertable, &self.symbol, self.start_time, self.end_time, self.batch_size, &self.closed,
+            handle_batch
+        )
+    }
+}
+
+impl TickGenerator for TimescaleReader {
+    fn get(
+        &mut self, mut map: Box<TickMap + Send>, cmd_handle: CommandStream
+    ) -> Result<BoxStream<Tick, ()>, String> {
+        // small atomic communication bus between the handle listener and worker threads
+        let internal_message: Arc<Mutex<TickstreamCommand>> = Arc::new(Mutex::new(TickstreamCommand::Stop));
+        let got_mail = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel::<Tick>(1);
+
+        let _got_mail = got_mail.clone();
+        let _internal_message = internal_message.clone();
+        let query = self.query();
+        let reader_handle = thread::spawn(move || {
+            let mut tx = Some(tx);
+            let res = query.run(|ticks| {
+                for tick in ticks {
+                    if check_mail(&*got_mail, &*_internal_message) {
+                        println!("Stop command received; killing reader");
+                        return false;
+                    }
+
+                    // apply the map
+                    if let Some(t_mod) = map.map(*tick) {
+                        if !send_tick(&mut tx, t_mod) {
+                            return false;
+                        }
+                    }
+                }
+                true
+            });
+            if let Err(err) = res {
+                println!("Error while reading ticks from TimescaleDB: {}", err);
+            }
+        }).thread().clone();
+
+        // spawn the handle listener thread that awaits commands
+        spawn_listener_thread(_got_mail, cmd_handle, internal_message, reader_handle);
+
+        Ok(rx.boxed())
+    }
+
+    fn get_raw(&mut self) -> Result<BoxStream<Tick, ()>, String> {
+        let (tx, rx) = channel(1);
+
+        let query = self.query();
+        thread::spawn(move || {
+            let mut tx = Some(tx);
+            let res = query.run(|ticks| ticks.iter().all(|tick| send_tick(&mut tx, *tick)));
+            if let Err(err) = res {
+                println!("Error while reading ticks from TimescaleDB: {}", err);
+            }
+        });
+
+        Ok(rx.boxed())
+    }
+}
+
+/// Reads the ticks of `symbol` in `hypertable` with timestamps between `start_time` and `end_time` (inclusive) in
+/// order, handing them to `handle_batch` `batch_size` rows at a time.  Timestamps are converted to and from
+/// milliseconds since the epoch.
+pub fn read_hypertable<F>(
+    conn: &Connection, hypertable: &str, symbol: &str, start_time: Option<u64>, end_time: Option<u64>,
+    batch_size: usize, closed: &AtomicBool, handle_batch: F
+) -> Result<(), String> where F: FnMut(&[Tick]) -> bool {
+    let query = format!("SELECT (EXTRACT(EPOCH FROM time) * 1000)::BIGINT, bid::BIGINT, ask::BIGINT FROM {} \
+        WHERE symbol = $1 AND time BETWEEN to_timestamp($2) AND to_timestamp($3) ORDER BY time", hypertable);
+    // `to_timestamp()` takes seconds, and infinity gives a timestamp after every other one
+    let start_secs = start_time.unwrap_or(0) as f64 / 1000.;
+    let end_secs = end_time.map(|end_time| end_time as f64 / 1000.).unwrap_or(f64::INFINITY);
+    stream_cursor(conn, &query, &[&symbol, &start_secs, &end_secs], batch_size, closed, handle_batch)
+}
+
+/// A hypertable's ticks are read back in order, filtered by symbol and time range.
+#[test]
+fn hypertable_read() {
+    use futures::Future;
+    use transport::postgres::{conn_string, get_client};
+
+    let conn = get_client().unwrap();
+    let table = format!("ticks_timescale_test_{}", ::uuid::Uuid::new_v4().simple());
+    conn.batch_execute(&format!("CREATE EXTENSION IF NOT EXISTS timescaledb; \
+        CREATE TABLE {0} (time TIMESTAMPTZ NOT NULL, symbol TEXT NOT NULL, bid BIGINT NOT NULL, ask BIGINT NOT NULL); \
+        SELECT create_hypertable('{0}', 'time', chunk_time_interval => INTERVAL '1 second');", table)).unwrap();
+    // insert the ticks out of order and interleaved with those of another symbol, spread over several chunks
+    let values: Vec<String> = (0..1000).rev().flat_map(|i| {
+        let time = format!("TIMESTAMPTZ 'epoch' + {} * INTERVAL '1 millisecond'", 1500000000000u64 + i * 10);
+        vec![
+            format!("({}, 'EURUSD', {}, {})", time, 100000 + i, 100002 + i),
+            format!("({}, 'USDJPY', 1, 2)", time),
+        ]
+    }).collect();
+    conn.batch_execute(&format!("INSERT INTO {} (time, symbol, bid, ask) VALUES {};", table, values.join(", ")))
+        .unwrap();
+
+    let mut reader = TimescaleReader::new(conn_string(), table.clone(), String::from("EURUSD"), None, None);
+    reader.batch_size = 64;
+    let ticks: Vec<Tick> = reader.get_raw().unwrap().wait().map(|t| t.unwrap()).collect();
+    let expected: Vec<Tick> = (0..1000).map(|i| {
+        Tick {timestamp: 1500000000000 + i * 10, bid: 100000 + i as usize, ask: 100002 + i as usize}
+    }).collect();
+    assert_eq!(ticks, expected);
+
+    // both ends of the range are inclusive
+    let mut timestamps = Vec::new();
+    let closed = AtomicBool::new(false);
+    read_hypertable(&conn, &table, "EURUSD", Some(1500000000100), Some(1500000000150), 2, &closed, |ticks| {
+        timestamps.extend(ticks.iter().map(|t| t.timestamp));
+        true
+    }).unwrap();
+    assert_eq!(timestamps, (10..16).map(|i| 1500000000000 + i * 10).collect::<Vec<u64>>());
+
+    conn.batch_execute(&format!("DROP TABLE {};", table)).unwrap();
+}
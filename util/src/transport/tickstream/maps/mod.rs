@@ -1,6 +1,7 @@
 //! Contains all the `TickMap`s for the platform.
 
 use super::*;
+use trading::tick::TickDeduplicator;
 
 pub mod poloniex;
 
@@ -19,6 +20,26 @@ impl TickMap for FastMap {
     }
 }
 
+/// Outputs ticks at a constant rate of `ticks_per_second`.
+pub struct TickRateMap {
+    pub delay_us: u64,
+}
+
+impl TickRateMap {
+    pub fn new(ticks_per_second: f64) -> TickRateMap {
+        TickRateMap {
+            delay_us: (1_000_000. / ticks_per_second) as u64,
+        }
+    }
+}
+
+impl TickMap for TickRateMap {
+    fn map(&mut self, t: Tick) -> Option<Tick> {
+        thread::sleep(Duration::new(self.delay_us / 1_000_000, ((self.delay_us % 1_000_000) * 1000) as u32));
+        Some(t)
+    }
+}
+
 /// Plays ticks back at the rate that they were recorded.
 pub struct LiveMap {
     pub last_tick_timestamp: u64
@@ -50,3 +71,25 @@ pub struct NullMap {}
 impl TickMap for NullMap {
     fn map(&mut self, t: Tick) -> Option<Tick> { Some(t) }
 }
+
+/// Drops repeated ticks; see `TickDeduplicator`.
+impl TickMap for TickDeduplicator {
+    fn map(&mut self, t: Tick) -> Option<Tick> {
+        self.filter(t)
+    }
+}
+
+#[test]
+fn tick_rate_map_timing() {
+    use std::time::Instant;
+
+    let mut map = TickRateMap::new(100.);
+    assert_eq!(map.delay_us, 10_000);
+    let start = Instant::now();
+    for i in 0..100 {
+        assert!(map.map(Tick {bid: 1, ask: 2, timestamp: i}).is_some());
+    }
+    let elapsed = start.elapsed();
+    let elapsed_ms = elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1_000_000) as u64;
+    assert!(elapsed_ms >= 900 && elapsed_ms <= 1100, "100 ticks at 100 ticks/second took {}ms", elapsed_ms);
+}
@@ -23,6 +23,8 @@ pub use self::generators::flatfile_reader::*;
 pub use self::generators::postgres_reader::*;
 pub use self::generators::random_reader::*;
 pub use self::generators::redis_reader::*;
+pub use self::generators::timescale_reader::*;
+pub use self::sinks::arrow_sink::*;
 pub use self::sinks::console_sink::*;
 pub use self::sinks::null_sink::*;
 pub use self::sinks::redis_sink::*;
@@ -43,13 +45,15 @@ pub enum TickGenerators {
 
 impl TickGenerators {
     /// Depending on variant, returns a `TickGenerator` based on the supplied params.
-    pub fn get(&self) -> Box<TickGenerator> {
+    pub fn get(&self) -> Box<TickGenerator + Send> {
         match self {
-            &TickGenerators::FlatfileReader{ref symbol, start_time} => Box::new(FlatfileReader{symbol: symbol.clone(), start_time: start_time}),
-            &TickGenerators::PostgresReader{ref symbol, start_time} => Box::new(PostgresReader{symbol: symbol.clone(), start_time: start_time}),
+            &TickGenerators::FlatfileReader{ref symbol, start_time} => {
+                Box::new(FlatfileReader{symbol: symbol.clone(), start_time: start_time, path: None})
+            },
+            &TickGenerators::PostgresReader{ref symbol, start_time} => Box::new(PostgresReader::new(symbol.clone(), start_time)),
             &TickGenerators::RandomReader => Box::new(RandomReader {}),
             &TickGenerators::RedisReader{ref symbol, ref redis_host, ref channel} => {
-                Box::new(RedisReader{symbol: symbol.clone(), redis_host: redis_host.clone(), channel: channel.clone()})
+                Box::new(RedisReader::new(symbol.clone(), redis_host.clone(), channel.clone()))
             },
         }
     }
@@ -60,6 +64,7 @@ impl TickGenerators {
 pub enum TickMaps {
     FastMap{delay_ms: usize},
     LiveMap{last_tick_timestamp: u64},
+    TickRateMap{ticks_per_second: f64},
     NullMap,
 }
 
@@ -69,6 +74,7 @@ impl TickMaps {
         match self {
             &TickMaps::FastMap{delay_ms} => Box::new(FastMap{delay_ms: delay_ms}),
             &TickMaps::LiveMap{last_tick_timestamp} => Box::new(LiveMap{last_tick_timestamp: last_tick_timestamp}),
+            &TickMaps::TickRateMap{ticks_per_second} => Box::new(TickRateMap::new(ticks_per_second)),
             &TickMaps::NullMap => Box::new(NullMap {}),
         }
     }
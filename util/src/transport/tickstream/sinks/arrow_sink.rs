@@ -0,0 +1,509 @@
+//! Saves ticks to a file in the Apache Arrow IPC streaming format so that they can be loaded directly by analytics
+//! tools such as pyarrow or the R `arrow` package.  The schema is written at the start of the file, so the file
+//! describes itself.
+//!
+//! The Arrow crates need a much newer compiler than the rest of the platform, so the format is written by hand.  A
+//! stream is a series of messages, each made up of a continuation marker, the length of its metadata, the metadata
+//! as a flatbuffer (see `Message.fbs` and `Schema.fbs` in the Arrow repository), and a body holding the message's
+//! buffers.  The first message holds the schema, each following one holds a record batch, and the stream ends with
+//! a continuation marker followed by a length of 0.
+//!
+//! The flatbuffers are laid out the same way as the `flatbuffers` crate lays out those written by arrow-rs, so the
+//! files match the ones it writes byte for byte.
+
+use std::cmp::max;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use trading::tick::Tick;
+use transport::tickstream::TickSink;
+
+/// Number of rows in each record batch written to the file
+pub const ARROW_BATCH_SIZE: usize = 65536;
+/// Names and bit widths of the columns of tick files.  All of them are non-nullable signed integers.
+pub const TICK_COLUMNS: [(&'static str, usize); 3] = [("timestamp", 64), ("bid", 32), ("ask", 32)];
+
+/// Marks the start of every message in the stream
+const CONTINUATION_MARKER: u32 = 0xFFFFFFFF;
+/// `MetadataVersion::V5`
+const METADATA_VERSION: i16 = 4;
+/// Values of the `MessageHeader` union
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_RECORD_BATCH: u8 = 3;
+/// The value of the `Type` union for integers
+const TYPE_INT: u8 = 2;
+
+/// Offsets within the vtables of the fields that are written, which follow from the order of the fields in the
+/// `.fbs` files
+const MESSAGE_VERSION: usize = 4;
+const MESSAGE_HEADER_TYPE: usize = 6;
+const MESSAGE_HEADER: usize = 8;
+const MESSAGE_BODY_LENGTH: usize = 10;
+const SCHEMA_FIELDS: usize = 6;
+const FIELD_NAME: usize = 4;
+const FIELD_TYPE_TYPE: usize = 8;
+const FIELD_TYPE: usize = 10;
+const FIELD_CHILDREN: usize = 14;
+const INT_BIT_WIDTH: usize = 4;
+const INT_IS_SIGNED: usize = 6;
+const RECORD_BATCH_LENGTH: usize = 4;
+const RECORD_BATCH_NODES: usize = 6;
+const RECORD_BATCH_BUFFERS: usize = 8;
+
+/// Appends the lowest `size` bytes of `value` in little endian order.
+fn push_le(buf: &mut Vec<u8>, value: u64, size: usize) {
+    for i in 0..size {
+        buf.push((value >> (8 * i)) as u8);
+    }
+}
+
+/// Pads the buffer with zeros until its length is a multiple of `alignment`.
+fn pad(buf: &mut Vec<u8>, alignment: usize) {
+    while buf.len() % alignment != 0 {
+        buf.push(0);
+    }
+}
+
+/// Builds a flatbuffer from back to front like the `flatbuffers` crate does.  Everything in the buffer is referred
+/// to by its position, which is its distance from the end of the buffer so that it doesn't change as more is added
+/// to the front.
+struct FbBuilder {
+    buf: VecDeque<u8>,
+    /// The largest alignment of anything pushed so far, which the offset to the root table is aligned to
+    min_align: usize,
+    /// Vtable offsets and positions of the fields of the table being built
+    fields: Vec<(usize, usize)>,
+    /// Positions of the vtables written so far, which are shared by every table with the same layout
+    vtables: Vec<usize>,
+}
+
+impl FbBuilder {
+    fn new() -> FbBuilder {
+        FbBuilder {
+            buf: VecDeque::new(),
+            min_align: 1,
+            fields: Vec::new(),
+            vtables: Vec::new(),
+        }
+    }
+
+    /// Returns the position of the front of the buffer, which is where tables start being built.
+    fn pos(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Pads the front of the buffer with zeros so that `len` bytes pushed afterwards are aligned to `alignment`.
+    fn align(&mut self, len: usize, alignment: usize) {
+        self.min_align = max(self.min_align, alignment);
+        while (self.buf.len() + len) % alignment != 0 {
+            self.buf.push_front(0);
+        }
+    }
+
+    /// Pushes bytes to the front of the buffer as they are.
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter().rev() {
+            self.buf.push_front(*byte);
+        }
+    }
+
+    /// Pushes the lowest `size` bytes of `value` in little endian order and returns their position.
+    fn push_scalar(&mut self, value: u64, size: usize) -> usize {
+        let mut bytes = Vec::with_capacity(size);
+        push_le(&mut bytes, value, size);
+        self.align(size, size);
+        self.push_bytes(&bytes);
+        self.pos()
+    }
+
+    /// Pushes an offset to the string, vector, or table at `target` and returns its position.
+    fn push_offset(&mut self, target: usize) -> usize {
+        self.align(4, 4);
+        let offset = self.pos() + 4 - target;
+        self.push_scalar(offset as u64, 4)
+    }
+
+    /// Overwrites the four bytes at `pos` with `value` in little endian order.
+    fn patch_u32(&mut self, pos: usize, value: u32) {
+        let start = self.buf.len() - pos;
+        for i in 0..4 {
+            self.buf[start + i] = (value >> (8 * i)) as u8;
+        }
+    }
+
+    /// Pushes a string, which is stored after its length and followed by a zero, and returns its position.
+    fn push_string(&mut self, s: &str) -> usize {
+        self.align(s.len() + 1, 4);
+        self.push_scalar(0, 1);
+        self.push_bytes(s.as_bytes());
+        self.push_scalar(s.len() as u64, 4)
+    }
+
+    /// Pushes a vector of offsets to the tables at the given positions and returns its position.
+    fn push_tables(&mut self, tables: &[usize]) -> usize {
+        self.align(4 * tables.len(), 4);
+        for table in tables.iter().rev() {
+            self.push_offset(*table);
+        }
+        self.push_scalar(tables.len() as u64, 4)
+    }
+
+    /// Pushes a vector of structs made up of two longs, such as `FieldNode` and `Buffer`, and returns its position.
+    fn push_long_pairs(&mut self, pairs: &[(i64, i64)]) -> usize {
+        self.align(16 * pairs.len(), 8);
+        for &(first, second) in pairs.iter().rev() {
+            let mut bytes = Vec::with_capacity(16);
+            push_le(&mut bytes, first as u64, 8);
+            push_le(&mut bytes, second as u64, 8);
+            self.push_bytes(&bytes);
+        }
+        self.push_scalar(pairs.len() as u64, 4)
+    }
+
+    /// Adds a scalar field to the table being built.  Like in the builders generated by `flatc`, fields with their
+    /// default value are left out.
+    fn add_scalar(&mut self, field: usize, value: u64, size: usize, default: u64) {
+        if value != default {
+            let pos = self.push_scalar(value, size);
+            self.fields.push((field, pos));
+        }
+    }
+
+    /// Adds a field referring to the string, vector, or table at `target` to the table being built.
+    fn add_offset(&mut self, field: usize, target: usize) {
+        let pos = self.push_offset(target);
+        self.fields.push((field, pos));
+    }
+
+    /// Finishes the table whose fields were added since the front of the buffer was at `start` and returns its
+    /// position.  The table is preceded by its vtable unless an identical one has already been written.
+    fn end_table(&mut self, start: usize) -> usize {
+        let table_pos = self.push_scalar(0, 4);
+        let vtable_len = self.fields.iter().map(|&(field, _)| field + 2).max().unwrap_or(4);
+        let mut vtable = Vec::with_capacity(vtable_len);
+        push_le(&mut vtable, vtable_len as u64, 2);
+        push_le(&mut vtable, (table_pos - start) as u64, 2);
+        vtable.resize(vtable_len, 0);
+        for &(field, pos) in &self.fields {
+            vtable[field] = (table_pos - pos) as u8;
+            vtable[field + 1] = ((table_pos - pos) >> 8) as u8;
+        }
+        self.fields.clear();
+
+        let existing = {
+            let buf = &self.buf;
+            self.vtables.iter().cloned().find(|&pos| {
+                buf.iter().skip(buf.len() - pos).take(vtable_len).eq(vtable.iter())
+            })
+        };
+        let vtable_pos = match existing {
+            Some(pos) => pos,
+            None => {
+                self.push_bytes(&vtable);
+                let pos = self.pos();
+                self.vtables.push(pos);
+                pos
+            },
+        };
+        // the table starts with the signed distance back to its vtable
+        self.patch_u32(table_pos, (vtable_pos as i64 - table_pos as i64) as u32);
+        table_pos
+    }
+
+    /// Pushes the offset to the root table and returns the finished flatbuffer.
+    fn finish(mut self, root: usize) -> Vec<u8> {
+        let min_align = self.min_align;
+        self.align(4, min_align);
+        self.push_offset(root);
+        self.buf.into_iter().collect()
+    }
+}
+
+/// Adds a `Message` table with the given header to the flatbuffer and returns the finished flatbuffer.
+fn finish_message(mut fbb: FbBuilder, header_type: u8, header: usize, body_len: usize) -> Vec<u8> {
+    let start = fbb.pos();
+    fbb.add_scalar(MESSAGE_VERSION, METADATA_VERSION as u64, 2, 0);
+    fbb.add_scalar(MESSAGE_HEADER_TYPE, header_type as u64, 1, 0);
+    fbb.add_scalar(MESSAGE_BODY_LENGTH, body_len as u64, 8, 0);
+    fbb.add_offset(MESSAGE_HEADER, header);
+    let message = fbb.end_table(start);
+    fbb.finish(message)
+}
+
+/// Returns the metadata of the message holding the schema of tick files.
+fn schema_metadata() -> Vec<u8> {
+    let mut fbb = FbBuilder::new();
+    let fields: Vec<usize> = TICK_COLUMNS.iter().map(|&(name, bit_width)| {
+        let name = fbb.push_string(name);
+        // arrow-rs gives every field a list of children, even if its type can't have any
+        let children = fbb.push_tables(&[]);
+        let start = fbb.pos();
+        fbb.add_scalar(INT_IS_SIGNED, 1, 1, 0);
+        fbb.add_scalar(INT_BIT_WIDTH, bit_width as u64, 4, 0);
+        let int_type = fbb.end_table(start);
+
+        // not being nullable is the default
+        let start = fbb.pos();
+        fbb.add_offset(FIELD_NAME, name);
+        fbb.add_scalar(FIELD_TYPE_TYPE, TYPE_INT as u64, 1, 0);
+        fbb.add_offset(FIELD_CHILDREN, children);
+        fbb.add_offset(FIELD_TYPE, int_type);
+        fbb.end_table(start)
+    }).collect();
+    let fields = fbb.push_tables(&fields);
+
+    // little endian is the default
+    let start = fbb.pos();
+    fbb.add_offset(SCHEMA_FIELDS, fields);
+    let schema = fbb.end_table(start);
+    finish_message(fbb, HEADER_SCHEMA, schema, 0)
+}
+
+/// Returns the metadata of the message holding a record batch with the given number of rows, whose body is made up
+/// of the given buffers.
+fn record_batch_metadata(rows: usize, buffers: &[(i64, i64)], body_len: usize) -> Vec<u8> {
+    let mut fbb = FbBuilder::new();
+    let buffers = fbb.push_long_pairs(buffers);
+    let nodes = fbb.push_long_pairs(&vec![(rows as i64, 0); TICK_COLUMNS.len()]);
+    let start = fbb.pos();
+    fbb.add_scalar(RECORD_BATCH_LENGTH, rows as u64, 8, 0);
+    fbb.add_offset(RECORD_BATCH_NODES, nodes);
+    fbb.add_offset(RECORD_BATCH_BUFFERS, buffers);
+    let record_batch = fbb.end_table(start);
+    finish_message(fbb, HEADER_RECORD_BATCH, record_batch, body_len)
+}
+
+/// Writes a message with the given metadata and body to the stream.  The metadata is padded so that the body
+/// starts on an 8 byte boundary.
+fn write_message<W: Write>(out: &mut W, mut metadata: Vec<u8>, body: &[u8]) -> Result<(), String> {
+    pad(&mut metadata, 8);
+    let mut prefix = Vec::with_capacity(8);
+    push_le(&mut prefix, CONTINUATION_MARKER as u64, 4);
+    push_le(&mut prefix, metadata.len() as u64, 4);
+    out.write_all(&prefix)
+        .and_then(|_| out.write_all(&metadata))
+        .and_then(|_| out.write_all(body))
+        .map_err(|err| format!("Unable to write Arrow message: {:?}", err))
+}
+
+/// A tick sink that buffers ticks into columns and writes them to an Arrow IPC file one record batch at a time.
+/// The end of the stream is written when the sink is finished or dropped.
+pub struct ArrowSink {
+    writer: Option<BufWriter<File>>,
+    timestamps: Vec<i64>,
+    bids: Vec<i32>,
+    asks: Vec<i32>,
+}
+
+impl ArrowSink {
+    /// Creates the file at `path`, replacing it if it exists, and writes the schema to it.
+    pub fn new(path: &str) -> Result<ArrowSink, String> {
+        let file = File::create(path).map_err(|err| format!("Unable to create file {}: {:?}", path, err))?;
+        let mut writer = BufWriter::new(file);
+        write_message(&mut writer, schema_metadata(), &[])
+            .map_err(|err| format!("Unable to write Arrow schema: {}", err))?;
+
+        Ok(ArrowSink {
+            writer: Some(writer),
+            timestamps: Vec::with_capacity(ARROW_BATCH_SIZE),
+            bids: Vec::with_capacity(ARROW_BATCH_SIZE),
+            asks: Vec::with_capacity(ARROW_BATCH_SIZE),
+        })
+    }
+
+    /// Writes the buffered ticks to the file as a record batch.  Each column gets a validity buffer with every bit
+    /// set, which is what arrow-rs writes for columns without nulls, followed by a buffer of its values.  Both are
+    /// padded to 8 bytes.
+    fn write_batch(&mut self) -> Result<(), String> {
+        if self.timestamps.is_empty() {
+            return Ok(());
+        }
+
+        let rows = self.timestamps.len();
+        let mut columns: Vec<Vec<u8>> = TICK_COLUMNS.iter()
+            .map(|&(_, bit_width)| Vec::with_capacity(rows * bit_width / 8))
+            .collect();
+        for i in 0..rows {
+            push_le(&mut columns[0], self.timestamps[i] as u64, 8);
+            push_le(&mut columns[1], self.bids[i] as u64, 4);
+            push_le(&mut columns[2], self.asks[i] as u64, 4);
+        }
+        self.timestamps.clear();
+        self.bids.clear();
+        self.asks.clear();
+
+        let mut body = Vec::new();
+        let mut buffers = Vec::with_capacity(2 * columns.len());
+        for column in &columns {
+            let validity_len = (rows + 7) / 8;
+            buffers.push((body.len() as i64, validity_len as i64));
+            let validity_end = body.len() + validity_len;
+            body.resize(validity_end, 0xFF);
+            pad(&mut body, 8);
+            buffers.push((body.len() as i64, column.len() as i64));
+            body.extend_from_slice(column);
+            pad(&mut body, 8);
+        }
+
+        let metadata = record_batch_metadata(rows, &buffers, body.len());
+        match self.writer {
+            Some(ref mut writer) => write_message(writer, metadata, &body)
+                .map_err(|err| format!("Unable to write record batch: {}", err)),
+            None => Err(String::from("The Arrow file has already been finished.")),
+        }
+    }
+
+    /// Writes any buffered ticks and the end of the stream.  Nothing can be written to the sink afterwards.
+    pub fn finish(&mut self) -> Result<(), String> {
+        self.write_batch()?;
+        match self.writer.take() {
+            Some(mut writer) => {
+                let mut end = Vec::with_capacity(8);
+                push_le(&mut end, CONTINUATION_MARKER as u64, 4);
+                push_le(&mut end, 0, 4);
+                writer.write_all(&end)
+                    .and_then(|_| writer.flush())
+                    .map_err(|err| format!("Unable to finish Arrow file: {:?}", err))
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+impl TickSink for ArrowSink {
+    fn tick(&mut self, t: Tick) {
+        // the columns are narrower than the fields of ticks, so ticks that don't fit are left out instead of being
+        // truncated
+        let max_price = i32::max_value() as usize;
+        if t.timestamp > i64::max_value() as u64 || t.bid > max_price || t.ask > max_price {
+            println!("Unable to write tick to Arrow file since it doesn't fit in the columns: {:?}", t);
+            return;
+        }
+
+        self.timestamps.push(t.timestamp as i64);
+        self.bids.push(t.bid as i32);
+        self.asks.push(t.ask as i32);
+
+        if self.timestamps.len() >= ARROW_BATCH_SIZE {
+            if let Err(err) = self.write_batch() {
+                println!("Error while writing ticks to Arrow file: {}", err);
+            }
+        }
+    }
+}
+
+impl Drop for ArrowSink {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            println!("Error while finishing Arrow file: {}", err);
+        }
+    }
+}
+
+/// An Arrow stream written by the `StreamWriter` of arrow-rs 54 with metadata version 5 and 8 byte alignment.  It
+/// holds the ticks returned by `golden_ticks`, with the first three in one record batch and the rest in another.
+/// It's printed by `scripts/arrow_golden`, which has to be rerun if the ticks or the layout of the file change.
+#[cfg(test)]
+const GOLDEN_STREAM: &'static [u8] = &[
+    0xff, 0xff, 0xff, 0xff, 0xe0, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x00,
+    0x0c, 0x00, 0x0a, 0x00, 0x09, 0x00, 0x04, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00,
+    0x00, 0x01, 0x04, 0x00, 0x08, 0x00, 0x08, 0x00, 0x00, 0x00, 0x04, 0x00, 0x08, 0x00, 0x00, 0x00,
+    0x04, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x74, 0x00, 0x00, 0x00, 0x34, 0x00, 0x00, 0x00,
+    0x04, 0x00, 0x00, 0x00, 0xa8, 0xff, 0xff, 0xff, 0x10, 0x00, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x02, 0x14, 0x00, 0x00, 0x00, 0x98, 0xff, 0xff, 0xff, 0x20, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x61, 0x73, 0x6b, 0x00,
+    0xd4, 0xff, 0xff, 0xff, 0x10, 0x00, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    0x14, 0x00, 0x00, 0x00, 0xc4, 0xff, 0xff, 0xff, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x62, 0x69, 0x64, 0x00, 0x10, 0x00, 0x14, 0x00,
+    0x10, 0x00, 0x00, 0x00, 0x0f, 0x00, 0x04, 0x00, 0x00, 0x00, 0x08, 0x00, 0x10, 0x00, 0x00, 0x00,
+    0x18, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x1c, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x0c, 0x00, 0x04, 0x00, 0x0b, 0x00, 0x08, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x74, 0x69, 0x6d, 0x65,
+    0x73, 0x74, 0x61, 0x6d, 0x70, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xe8, 0x00, 0x00, 0x00,
+    0x10, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x1a, 0x00, 0x18, 0x00, 0x17, 0x00, 0x04, 0x00, 0x08, 0x00,
+    0x0c, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x50, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x04, 0x00, 0x0a, 0x00, 0x18, 0x00, 0x0c, 0x00,
+    0x08, 0x00, 0x04, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x4c, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00,
+    0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00,
+    0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x38, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x98, 0xf7, 0x3e, 0x5d, 0x01, 0x00, 0x00, 0x01, 0x98, 0xf7, 0x3e, 0x5d, 0x01, 0x00, 0x00,
+    0x02, 0x98, 0xf7, 0x3e, 0x5d, 0x01, 0x00, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xe8, 0x03, 0x00, 0x00, 0xe9, 0x03, 0x00, 0x00, 0xea, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xea, 0x03, 0x00, 0x00, 0xeb, 0x03, 0x00, 0x00,
+    0xec, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xe8, 0x00, 0x00, 0x00,
+    0x10, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x1a, 0x00, 0x18, 0x00, 0x17, 0x00, 0x04, 0x00, 0x08, 0x00,
+    0x0c, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x38, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x04, 0x00, 0x0a, 0x00, 0x18, 0x00, 0x0c, 0x00,
+    0x08, 0x00, 0x04, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x4c, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00,
+    0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00,
+    0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x03, 0x98, 0xf7, 0x3e, 0x5d, 0x01, 0x00, 0x00, 0x04, 0x98, 0xf7, 0x3e, 0x5d, 0x01, 0x00, 0x00,
+    0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xeb, 0x03, 0x00, 0x00, 0xec, 0x03, 0x00, 0x00,
+    0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xed, 0x03, 0x00, 0x00, 0xee, 0x03, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00,
+];
+
+#[cfg(test)]
+fn golden_ticks() -> Vec<Tick> {
+    (0..5).map(|i| Tick {timestamp: 1500000000000 + i, bid: 1000 + i as usize, ask: 1002 + i as usize}).collect()
+}
+
+/// Writes the ticks to a new file with an `ArrowSink`, starting a new record batch after each of the given numbers
+/// of ticks, and returns the contents of the file.
+#[cfg(test)]
+fn write_arrow_file(ticks: &[Tick], batch_ends: &[usize]) -> Vec<u8> {
+    use std::fs;
+    use std::io::Read;
+
+    use uuid::Uuid;
+
+    let path = format!("/tmp/tickgrinder_arrow_test_{}.arrow", Uuid::new_v4().simple());
+    {
+        let mut sink = ArrowSink::new(&path).unwrap();
+        for (i, t) in ticks.iter().enumerate() {
+            sink.tick(*t);
+            if batch_ends.contains(&(i + 1)) {
+                sink.write_batch().unwrap();
+            }
+        }
+    }
+
+    let mut file = Vec::new();
+    File::open(&path).unwrap().read_to_end(&mut file).unwrap();
+    fs::remove_file(&path).unwrap();
+    file
+}
+
+/// The sink should write exactly the same bytes as arrow-rs does for the same ticks.
+#[test]
+fn arrow_stream_matches_arrow_rs() {
+    assert_eq!(write_arrow_file(&golden_ticks(), &[3]), GOLDEN_STREAM);
+}
+
+/// Ticks with prices too large for the columns should be left out rather than truncated.
+#[test]
+fn arrow_sink_skips_overflowing_ticks() {
+    let mut ticks = golden_ticks();
+    let overflowing = Tick {timestamp: 1500000000000, bid: i32::max_value() as usize + 1, ask: 1002};
+    ticks.insert(1, overflowing);
+    assert_eq!(write_arrow_file(&ticks, &[4]), GOLDEN_STREAM);
+}
@@ -1,6 +1,7 @@
 //! Tick sinks server as the consumers of time series data streams within the platform.  They can be things such as databases where the data
 //! is stored, backtests, or strategy executors.
 
+pub mod arrow_sink;
 pub mod console_sink;
 pub mod csv_sink;
 pub mod null_sink;
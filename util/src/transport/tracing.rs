@@ -0,0 +1,480 @@
+//! Traces commands as they are passed between the platform's modules.  The context of the span that sends a
+//! command is carried along with it in a `WrappedCommand` in the W3C `traceparent` format
+//! (`00-<32 hex trace id>-<16 hex span id>-<2 hex flags>`), and the instance that receives it starts a child span
+//! for handling it.  Finished spans are handed to the `SpanExporter` registered with `set_exporter()`, if any, which
+//! is normally a `JaegerExporter` set up by `init_from_conf()`.  Tests can collect the spans finished on their own
+//! thread with `with_exporter()` instead.
+//!
+//! The `opentelemetry` and `opentelemetry-jaeger` crates need a much newer compiler than the rest of the platform, so
+//! the tracer is written by hand.  It only covers what the platform uses: propagating `traceparent` contexts, a
+//! stack of open spans per thread, and exporting finished spans to Jaeger's OTLP/HTTP endpoint as JSON, which is the
+//! same data that the OpenTelemetry exporters send.  `MemoryExporter` takes the place of their test exporter.
+
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use rand::{thread_rng, Rng};
+use reqwest;
+use reqwest::header::ContentType;
+use serde_json;
+
+use conf::CONF;
+
+/// Identifies a span within a trace.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceContext {
+    pub trace_id: String,
+    pub span_id: String,
+    pub sampled: bool,
+}
+
+/// Returns `len` random lowercase hex digits, which are never all zero since an id of zeros is invalid.
+fn random_hex(len: usize) -> String {
+    let mut rng = thread_rng();
+    loop {
+        let hex: String = (0..len).map(|_| format!("{:x}", rng.gen_range(0u8, 16))).collect();
+        if hex.chars().any(|c| c != '0') {
+            return hex;
+        }
+    }
+}
+
+fn is_hex_id(s: &str, len: usize) -> bool {
+    s.len() == len && s.chars().all(|c| c.is_digit(16) && !c.is_uppercase()) && s.chars().any(|c| c != '0')
+}
+
+impl TraceContext {
+    /// Creates the context of the first span of a new trace.
+    pub fn new_root() -> TraceContext {
+        TraceContext {
+            trace_id: random_hex(32),
+            span_id: random_hex(16),
+            sampled: true,
+        }
+    }
+
+    /// Creates the context of a new span that's part of the same trace as this one.
+    pub fn child(&self) -> TraceContext {
+        TraceContext {
+            trace_id: self.trace_id.clone(),
+            span_id: random_hex(16),
+            sampled: self.sampled,
+        }
+    }
+
+    /// Parses a `traceparent` header, returning `None` if it's malformed.
+    pub fn from_traceparent(traceparent: &str) -> Option<TraceContext> {
+        let parts: Vec<&str> = traceparent.trim().split('-').collect();
+        if parts.len() != 4 || parts[0] != "00" || !is_hex_id(parts[1], 32) || !is_hex_id(parts[2], 16) {
+            return None;
+        }
+
+        let flags = match u8::from_str_radix(parts[3], 16) {
+            Ok(flags) if parts[3].len() == 2 => flags,
+            _ => return None,
+        };
+
+        Some(TraceContext {
+            trace_id: String::from(parts[1]),
+            span_id: String::from(parts[2]),
+            sampled: flags & 1 == 1,
+        })
+    }
+
+    pub fn to_traceparent(&self) -> String {
+        format!("00-{}-{}-{}", self.trace_id, self.span_id, if self.sampled { "01" } else { "00" })
+    }
+}
+
+/// A span that has finished.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpanRecord {
+    pub name: String,
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub attributes: Vec<(String, String)>,
+    /// Milliseconds since the epoch when the span was started
+    pub start_time_ms: u64,
+    pub duration_ns: u64,
+}
+
+/// Receives spans as they finish.
+pub trait SpanExporter {
+    fn export(&mut self, span: SpanRecord);
+}
+
+/// Keeps all exported spans in memory so that they can be inspected.
+#[derive(Clone)]
+pub struct MemoryExporter {
+    pub spans: Arc<Mutex<Vec<SpanRecord>>>,
+}
+
+impl MemoryExporter {
+    pub fn new() -> MemoryExporter {
+        MemoryExporter { spans: Arc::new(Mutex::new(Vec::new())) }
+    }
+}
+
+impl SpanExporter for MemoryExporter {
+    fn export(&mut self, span: SpanRecord) {
+        self.spans.lock().unwrap().push(span);
+    }
+}
+
+/// The most spans that a `JaegerExporter` sends in one request
+pub const MAX_EXPORT_BATCH: usize = 512;
+
+/// Sends spans to a Jaeger collector as OTLP/HTTP JSON, which Jaeger accepts at `/v1/traces` on port 4318.  Spans
+/// are sent from a background thread in batches of whatever has finished since the last request, so exporting them
+/// never blocks the thread that finished them.  Batches that can't be sent are dropped.
+pub struct JaegerExporter {
+    tx: Sender<SpanRecord>,
+}
+
+impl JaegerExporter {
+    /// Starts sending spans to `endpoint`, such as `http://localhost:4318/v1/traces`, as those of the service
+    /// `service_name`.
+    pub fn new(endpoint: &str, service_name: &str) -> JaegerExporter {
+        let (tx, rx) = mpsc::channel();
+        let endpoint = String::from(endpoint);
+        let service_name = String::from(service_name);
+        thread::spawn(move || {
+            let client = reqwest::Client::new();
+            // the thread exits once the exporter has been dropped
+            while let Ok(span) = rx.recv() {
+                let mut batch = vec![span];
+                batch.extend(rx.try_iter().take(MAX_EXPORT_BATCH - 1));
+                let res = otlp_json(&service_name, &batch)
+                    .map_err(|err| format!("{:?}", err))
+                    .and_then(|body| {
+                        client.post(endpoint.as_str()).header(ContentType::json()).body(body).send()
+                            .map_err(|err| format!("{:?}", err))
+                    });
+                match res {
+                    Ok(ref res) if res.status().is_success() => (),
+                    Ok(res) => println!("Jaeger refused {} spans: {}", batch.len(), res.status()),
+                    Err(err) => println!("Unable to send {} spans to Jaeger: {}", batch.len(), err),
+                }
+            }
+        });
+
+        JaegerExporter { tx: tx }
+    }
+}
+
+impl SpanExporter for JaegerExporter {
+    fn export(&mut self, span: SpanRecord) {
+        let _ = self.tx.send(span);
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OtlpExport {
+    resource_spans: Vec<OtlpResourceSpans>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OtlpResourceSpans {
+    resource: OtlpResource,
+    scope_spans: Vec<OtlpScopeSpans>,
+}
+
+#[derive(Serialize)]
+struct OtlpResource {
+    attributes: Vec<OtlpAttribute>,
+}
+
+#[derive(Serialize)]
+struct OtlpScopeSpans {
+    scope: OtlpScope,
+    spans: Vec<OtlpSpan>,
+}
+
+#[derive(Serialize)]
+struct OtlpScope {
+    name: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OtlpSpan {
+    trace_id: String,
+    span_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_span_id: Option<String>,
+    name: String,
+    /// `SPAN_KIND_INTERNAL`
+    kind: u8,
+    /// 64-bit integers are written as strings in OTLP JSON
+    start_time_unix_nano: String,
+    end_time_unix_nano: String,
+    attributes: Vec<OtlpAttribute>,
+}
+
+#[derive(Serialize)]
+struct OtlpAttribute {
+    key: String,
+    value: OtlpValue,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OtlpValue {
+    string_value: String,
+}
+
+fn otlp_attribute(key: &str, value: &str) -> OtlpAttribute {
+    OtlpAttribute {
+        key: String::from(key),
+        value: OtlpValue { string_value: String::from(value) },
+    }
+}
+
+/// Encodes spans as the body of an OTLP/HTTP JSON export request from the service `service_name`.
+pub fn otlp_json(service_name: &str, spans: &[SpanRecord]) -> Result<String, serde_json::Error> {
+    let spans = spans.iter()
+        .map(|span| {
+            let start_ns = span.start_time_ms * 1_000_000;
+            OtlpSpan {
+                trace_id: span.trace_id.clone(),
+                span_id: span.span_id.clone(),
+                parent_span_id: span.parent_span_id.clone(),
+                name: span.name.clone(),
+                kind: 1,
+                start_time_unix_nano: start_ns.to_string(),
+                end_time_unix_nano: (start_ns + span.duration_ns).to_string(),
+                attributes: span.attributes.iter().map(|&(ref key, ref value)| otlp_attribute(key, value)).collect(),
+            }
+        })
+        .collect();
+
+    serde_json::to_string(&OtlpExport {
+        resource_spans: vec![OtlpResourceSpans {
+            resource: OtlpResource { attributes: vec![otlp_attribute("service.name", service_name)] },
+            scope_spans: vec![OtlpScopeSpans {
+                scope: OtlpScope { name: "tickgrinder" },
+                spans: spans,
+            }],
+        }],
+    })
+}
+
+lazy_static! {
+    static ref EXPORTER: Mutex<Option<Box<SpanExporter + Send>>> = Mutex::new(None);
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<TraceContext>> = RefCell::new(None);
+    /// Receives the spans finished on this thread instead of `EXPORTER` while `with_exporter()` is running
+    static SCOPED_EXPORTER: RefCell<Option<Box<SpanExporter>>> = RefCell::new(None);
+}
+
+/// Sets the exporter that all finished spans of this process are sent to.
+pub fn set_exporter(exporter: Box<SpanExporter + Send>) {
+    *EXPORTER.lock().unwrap() = Some(exporter);
+}
+
+/// Sends this process's spans to the Jaeger collector in the `jaeger_endpoint` setting as those of `service_name`.
+/// Nothing is exported if the setting is empty.
+pub fn init_from_conf(service_name: &str) {
+    if let Some(endpoint) = CONF.jaeger_endpoint {
+        set_exporter(Box::new(JaegerExporter::new(endpoint, service_name)));
+    }
+}
+
+/// Sends the spans finished on this thread while `f` runs to `exporter` instead of the one set with
+/// `set_exporter()`.  Tests use this to see only their own spans, even while other tests are finishing theirs.
+pub fn with_exporter<T, F: FnOnce() -> T>(exporter: Box<SpanExporter>, f: F) -> T {
+    let previous = SCOPED_EXPORTER.with(|scoped| scoped.borrow_mut().take());
+    SCOPED_EXPORTER.with(|scoped| *scoped.borrow_mut() = Some(exporter));
+    let res = f();
+    SCOPED_EXPORTER.with(|scoped| *scoped.borrow_mut() = previous);
+    res
+}
+
+/// Returns the context of the span that's active on this thread, if any.
+pub fn current_context() -> Option<TraceContext> {
+    CURRENT.with(|cur| cur.borrow().clone())
+}
+
+/// Returns the `traceparent` of the span that's active on this thread, if any.
+pub fn current_traceparent() -> Option<String> {
+    current_context().map(|ctx| ctx.to_traceparent())
+}
+
+/// A unit of work being traced.  The span is the active span of its thread until it's dropped, at which point
+/// the previously active span is restored and the span is exported.
+pub struct Span {
+    name: String,
+    context: TraceContext,
+    parent_span_id: Option<String>,
+    attributes: Vec<(String, String)>,
+    previous: Option<TraceContext>,
+    start_time_ms: u64,
+    started: Instant,
+}
+
+impl Span {
+    /// Starts a span as a child of `parent`.  If no parent is supplied, the span that's active on this thread
+    /// is used as the parent and a new trace is started if there isn't one.
+    pub fn start(name: &str, parent: Option<TraceContext>) -> Span {
+        let previous = current_context();
+        let parent = parent.or_else(|| previous.clone());
+        let context = match parent {
+            Some(ref parent) => parent.child(),
+            None => TraceContext::new_root(),
+        };
+        CURRENT.with(|cur| *cur.borrow_mut() = Some(context.clone()));
+
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        Span {
+            name: String::from(name),
+            context: context,
+            parent_span_id: parent.map(|parent| parent.span_id),
+            attributes: Vec::new(),
+            previous: previous,
+            start_time_ms: since_epoch.as_secs() * 1000 + (since_epoch.subsec_nanos() / 1_000_000) as u64,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn set_attribute(&mut self, key: &str, value: &str) {
+        self.attributes.push((String::from(key), String::from(value)));
+    }
+
+    pub fn context(&self) -> &TraceContext {
+        &self.context
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|cur| *cur.borrow_mut() = previous);
+
+        if !self.context.sampled {
+            return;
+        }
+        let elapsed = self.started.elapsed();
+        let mut record = Some(SpanRecord {
+            name: self.name.clone(),
+            trace_id: self.context.trace_id.clone(),
+            span_id: self.context.span_id.clone(),
+            parent_span_id: self.parent_span_id.clone(),
+            attributes: self.attributes.clone(),
+            start_time_ms: self.start_time_ms,
+            duration_ns: elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64,
+        });
+
+        SCOPED_EXPORTER.with(|scoped| {
+            if let Some(ref mut exporter) = *scoped.borrow_mut() {
+                exporter.export(record.take().unwrap());
+            }
+        });
+        if let Some(record) = record {
+            if let Some(ref mut exporter) = *EXPORTER.lock().unwrap() {
+                exporter.export(record);
+            }
+        }
+    }
+}
+
+#[test]
+fn traceparent_parsing() {
+    let ctx = TraceContext::from_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+    assert_eq!(ctx.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+    assert_eq!(ctx.span_id, "00f067aa0ba902b7");
+    assert!(ctx.sampled);
+    assert_eq!(ctx.to_traceparent(), "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01");
+
+    assert_eq!(TraceContext::from_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01"), None);
+    assert_eq!(TraceContext::from_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7"), None);
+    assert_eq!(TraceContext::from_traceparent("garbage"), None);
+
+    // every digit of generated ids is random, including the ones that are fixed in v4 UUIDs
+    let ids: Vec<TraceContext> = (0..64).map(|_| TraceContext::new_root()).collect();
+    for ctx in ids.iter() {
+        assert!(TraceContext::from_traceparent(&ctx.to_traceparent()).is_some());
+    }
+    assert!(ids.iter().any(|ctx| &ctx.trace_id[12..13] != "4"));
+    assert!(ids.iter().any(|ctx| &ctx.span_id[12..13] != "4"));
+}
+
+/// A command sent from within a span carries its context, and handling it on the other end creates a child span.
+#[test]
+fn command_trace_propagation() {
+    use std::str::FromStr;
+    use transport::commands::{Command, WrappedCommand};
+
+    let exporter = MemoryExporter::new();
+    let parent = TraceContext::from_traceparent("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01").unwrap();
+    let (wr_cmd, sent_from) = with_exporter(Box::new(exporter.clone()), || {
+        let raw_cmd = {
+            let _sending_span = Span::start("send", Some(parent.clone()));
+            Command::Ping.wrap().to_string().unwrap()
+        };
+
+        let wr_cmd = WrappedCommand::from_str(&raw_cmd).unwrap();
+        let sent_from = TraceContext::from_traceparent(wr_cmd.trace_context.as_ref().unwrap()).unwrap();
+        assert_eq!(sent_from.trace_id, parent.trace_id);
+        {
+            let _handling_span = wr_cmd.span("Test Instance");
+            assert_eq!(current_context().unwrap().trace_id, parent.trace_id);
+        }
+        assert_eq!(current_context(), None);
+        (wr_cmd, sent_from)
+    });
+
+    let spans = exporter.spans.lock().unwrap();
+    assert_eq!(spans.len(), 2);
+    let handling = spans.iter().find(|span| span.parent_span_id == Some(sent_from.span_id.clone()))
+        .expect("No child span was exported for the handled command");
+    assert_eq!(handling.trace_id, parent.trace_id);
+    let uuid_string = wr_cmd.uuid.hyphenated().to_string();
+    assert!(handling.attributes.contains(&(String::from("command"), String::from("Ping"))));
+    assert!(handling.attributes.contains(&(String::from("uuid"), uuid_string)));
+    assert!(handling.attributes.contains(&(String::from("module"), String::from("Test Instance"))));
+
+    // spans finished after the scoped exporter is gone aren't sent to it
+    drop(spans);
+    drop(Span::start("after", None));
+    assert_eq!(exporter.spans.lock().unwrap().len(), 2);
+}
+
+/// Spans are sent to Jaeger in the OTLP JSON format, with the ids in hex and the times in nanoseconds.
+#[test]
+fn otlp_encoding() {
+    use serde_json::Value;
+
+    let span = SpanRecord {
+        name: String::from("Backtester"),
+        trace_id: String::from("0af7651916cd43dd8448eb211c80319c"),
+        span_id: String::from("00f067aa0ba902b7"),
+        parent_span_id: Some(String::from("b7ad6b7169203331")),
+        attributes: vec![(String::from("command"), String::from("StartBacktest"))],
+        start_time_ms: 1500000000000,
+        duration_ns: 2500,
+    };
+    let root = SpanRecord { parent_span_id: None, ..span.clone() };
+    let export: Value = serde_json::from_str(&otlp_json("backtester", &[span, root]).unwrap()).unwrap();
+
+    let resource_spans = &export["resourceSpans"][0];
+    assert_eq!(resource_spans["resource"]["attributes"][0]["key"], Value::from("service.name"));
+    assert_eq!(resource_spans["resource"]["attributes"][0]["value"]["stringValue"], Value::from("backtester"));
+    let spans = &resource_spans["scopeSpans"][0]["spans"];
+    assert_eq!(spans[0]["traceId"], Value::from("0af7651916cd43dd8448eb211c80319c"));
+    assert_eq!(spans[0]["spanId"], Value::from("00f067aa0ba902b7"));
+    assert_eq!(spans[0]["parentSpanId"], Value::from("b7ad6b7169203331"));
+    assert_eq!(spans[0]["name"], Value::from("Backtester"));
+    assert_eq!(spans[0]["startTimeUnixNano"], Value::from("1500000000000000000"));
+    assert_eq!(spans[0]["endTimeUnixNano"], Value::from("1500000000000002500"));
+    assert_eq!(spans[0]["attributes"][0]["key"], Value::from("command"));
+    assert_eq!(spans[0]["attributes"][0]["value"]["stringValue"], Value::from("StartBacktest"));
+    assert!(spans[1].get("parentSpanId").is_none());
+}